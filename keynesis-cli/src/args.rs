@@ -0,0 +1,126 @@
+use anyhow::{bail, Context as _, Result};
+use std::str::FromStr;
+
+/// the command line arguments of a command
+///
+/// the options are all of the form `--name value` (or `--name=value`),
+/// everything else is a positional argument. The command takes the
+/// arguments it knows about and [`Args::finish`] fails if some are left.
+#[derive(Debug, Default)]
+pub struct Args {
+    options: Vec<(String, String)>,
+    positionals: Vec<String>,
+}
+
+impl Args {
+    pub fn parse<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.positionals.extend(args.by_ref());
+            } else if let Some(option) = arg.strip_prefix("--") {
+                let (name, value) = match option.split_once('=') {
+                    Some((name, value)) => (name.to_owned(), value.to_owned()),
+                    None => {
+                        let value = args
+                            .next()
+                            .with_context(|| format!("Missing value of --{}", option))?;
+                        (option.to_owned(), value)
+                    }
+                };
+                parsed.options.push((name, value));
+            } else {
+                parsed.positionals.push(arg);
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// take all the values of the option, in the order of the command line
+    pub fn values(&mut self, name: &str) -> Vec<String> {
+        let (values, others) = std::mem::take(&mut self.options)
+            .into_iter()
+            .partition(|(option, _)| option == name);
+        self.options = others;
+        values.into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// take the value of the option, fails if it is given more than once
+    pub fn value(&mut self, name: &str) -> Result<Option<String>> {
+        let mut values = self.values(name);
+        if values.len() > 1 {
+            bail!("--{} can only be given once", name);
+        }
+        Ok(values.pop())
+    }
+
+    /// take and parse the value of the option
+    pub fn parse_value<T>(&mut self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.value(name)?
+            .map(|value| {
+                value
+                    .parse()
+                    .with_context(|| format!("Invalid value of --{}", name))
+            })
+            .transpose()
+    }
+
+    /// take the next positional argument
+    pub fn positional(&mut self) -> Option<String> {
+        if self.positionals.is_empty() {
+            None
+        } else {
+            Some(self.positionals.remove(0))
+        }
+    }
+
+    /// fail if some of the arguments were not used by the command
+    pub fn finish(self) -> Result<()> {
+        if let Some((name, _)) = self.options.first() {
+            bail!("Unexpected option --{}", name);
+        }
+        if let Some(arg) = self.positionals.first() {
+            bail!("Unexpected argument {:?}", arg);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn options() {
+        let mut args = args(&["--path", "a", "file", "--path=b", "--key", "k", "--", "--x"]);
+
+        assert_eq!(args.values("path"), vec!["a", "b"]);
+        assert_eq!(args.value("key").unwrap().as_deref(), Some("k"));
+        assert_eq!(args.value("key").unwrap(), None);
+        assert_eq!(args.positional().as_deref(), Some("file"));
+        assert_eq!(args.positional().as_deref(), Some("--x"));
+        args.finish().unwrap();
+    }
+
+    #[test]
+    fn unexpected() {
+        assert!(Args::parse(vec!["--path".to_owned()]).is_err());
+        assert!(args(&["--key", "a", "--key", "b"]).value("key").is_err());
+        assert!(args(&["--unknown", "a"]).finish().is_err());
+        assert!(args(&["file"]).finish().is_err());
+    }
+}
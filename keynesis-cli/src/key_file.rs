@@ -0,0 +1,152 @@
+/*!
+# Encrypted key file
+
+The key file contains the [`Seed`] of the root key, encrypted with a key
+derived from a password with [Argon2id]. It is a bech32 string (with the
+[`KeyFile::HRP`] human readable part) of:
+
+* the version of the format (1 byte);
+* the Argon2id parameters: the memory cost, the time cost and the
+  parallelism (4 bytes each, big endian);
+* the salt of the password derivation ([`SALT_SIZE`] bytes);
+* the encrypted seed and its authentication tag ([`TAG_SIZE`] bytes).
+
+The header (everything but the encrypted seed and the tag) is
+authenticated too. A new salt is generated every time a key file is
+written so the derived key is only used once and the nonce is always 0.
+
+[Argon2id]: keynesis_core::kdf::argon2id
+*/
+
+use anyhow::{bail, ensure, Context as _, Result};
+use cryptoxide::chacha20poly1305::ChaCha20Poly1305;
+use keynesis_core::{
+    bech32,
+    kdf::{argon2id, Argon2Params},
+    memsec::Scrubbed as _,
+    Seed,
+};
+use rand_core::{CryptoRng, RngCore};
+
+const VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+const TAG_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+const HEADER_SIZE: usize = 1 + 3 * 4 + SALT_SIZE;
+const SIZE: usize = HEADER_SIZE + Seed::SIZE + TAG_SIZE;
+
+/// encode and decode the encrypted key files
+pub struct KeyFile {
+    params: Argon2Params,
+}
+
+impl KeyFile {
+    pub const HRP: &'static str = "keynesis_key";
+
+    pub fn new(params: Argon2Params) -> Self {
+        Self { params }
+    }
+
+    /// encrypt the seed with the password
+    pub fn encrypt<Rng>(&self, rng: &mut Rng, seed: &Seed, password: &[u8]) -> Result<String>
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        ensure!(
+            !seed.is_extended(),
+            "The key file cannot hold an extended seed"
+        );
+
+        let mut bytes = [0; SIZE];
+        bytes[0] = VERSION;
+        bytes[1..5].copy_from_slice(&self.params.m_cost().to_be_bytes());
+        bytes[5..9].copy_from_slice(&self.params.t_cost().to_be_bytes());
+        bytes[9..13].copy_from_slice(&self.params.p_cost().to_be_bytes());
+        rng.fill_bytes(&mut bytes[13..HEADER_SIZE]);
+
+        let (header, payload) = bytes.split_at_mut(HEADER_SIZE);
+        let (encrypted, tag) = payload.split_at_mut(Seed::SIZE);
+
+        let mut key = derive_key(&self.params, password, &header[13..])?;
+        let mut context = ChaCha20Poly1305::new(&key, &[0; 12], header);
+        context.encrypt(seed.as_ref(), encrypted, tag);
+        key.scrub();
+
+        Ok(bech32::encode(Self::HRP, bytes))
+    }
+
+    /// decrypt the seed of the key file with the password
+    ///
+    /// the parameters of the key derivation are the ones of the file,
+    /// not the ones of `self`.
+    pub fn decrypt(key_file: &str, password: &[u8]) -> Result<Seed> {
+        let bytes = bech32::decode_with_hrp(Self::HRP, key_file.trim())
+            .context("Invalid key file encoding")?;
+        ensure!(bytes.len() == SIZE, "Invalid key file size");
+        if bytes[0] != VERSION {
+            bail!("Unsupported key file version {}", bytes[0]);
+        }
+
+        let (header, payload) = bytes.split_at(HEADER_SIZE);
+        let (encrypted, tag) = payload.split_at(Seed::SIZE);
+
+        let param = |index: usize| {
+            let mut param = [0; 4];
+            param.copy_from_slice(&header[index..index + 4]);
+            u32::from_be_bytes(param)
+        };
+        let params = Argon2Params::new(param(1), param(5), param(9))
+            .context("Invalid key file parameters")?;
+
+        let mut key = derive_key(&params, password, &header[13..])?;
+        let mut seed = [0; Seed::SIZE];
+        let mut context = ChaCha20Poly1305::new(&key, &[0; 12], header);
+        let valid = context.decrypt(encrypted, &mut seed, tag);
+        key.scrub();
+
+        ensure!(valid, "Invalid password or corrupted key file");
+
+        let result = Seed::from(seed);
+        seed.scrub();
+        Ok(result)
+    }
+}
+
+fn derive_key(params: &Argon2Params, password: &[u8], salt: &[u8]) -> Result<[u8; KEY_SIZE]> {
+    let mut key = [0; KEY_SIZE];
+    argon2id(params, password, salt, &mut key).context("Cannot derive the key file's key")?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::OsRng;
+
+    fn key_file() -> KeyFile {
+        KeyFile::new(Argon2Params::new(64, 1, 1).unwrap())
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        let seed = Seed::generate(&mut OsRng);
+        let encrypted = key_file().encrypt(&mut OsRng, &seed, b"password").unwrap();
+
+        let decrypted = KeyFile::decrypt(&encrypted, b"password").unwrap();
+        assert_eq!(decrypted.as_ref(), seed.as_ref());
+
+        assert!(KeyFile::decrypt(&encrypted, b"passw0rd").is_err());
+    }
+
+    #[test]
+    fn tampered_header() {
+        let seed = Seed::generate(&mut OsRng);
+        let encrypted = key_file().encrypt(&mut OsRng, &seed, b"password").unwrap();
+
+        let mut bytes = bech32::decode_with_hrp(KeyFile::HRP, &encrypted).unwrap();
+        bytes[HEADER_SIZE - 1] ^= 1;
+        let tampered = bech32::encode(KeyFile::HRP, bytes);
+
+        assert!(KeyFile::decrypt(&tampered, b"password").is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{args::Args, key_file::KeyFile};
+use anyhow::{bail, ensure, Context as _, Result};
+use keynesis_core::{
+    hash::{Blake2b, Digest as _},
+    kdf::Argon2Params,
+    key::{ed25519, ed25519_hd},
+    Seed, Signature,
+};
+use rand_core::OsRng;
+use std::{
+    fs,
+    io::{self, Read as _, Write as _},
+};
+
+/// human readable part of the exported seeds
+const SEED_HRP: &str = "keynesis_seed";
+
+/// size of the fingerprints of the public keys
+const FINGERPRINT_SIZE: usize = 20;
+
+pub fn keygen(mut args: Args) -> Result<()> {
+    let output = args.value("output")?;
+    let password = password(&mut args)?;
+    args.finish()?;
+
+    let seed = Seed::generate(&mut OsRng);
+    write_key_file(output, &seed, &password)
+}
+
+pub fn import(mut args: Args) -> Result<()> {
+    let output = args.value("output")?;
+    let password = password(&mut args)?;
+    args.finish()?;
+
+    let mut line = String::new();
+    io::stdin()
+        .read_line(&mut line)
+        .context("Cannot read the seed from the standard input")?;
+    let seed = Seed::from_bech32_str(SEED_HRP, line.trim()).context("Invalid seed")?;
+
+    write_key_file(output, &seed, &password)
+}
+
+pub fn export(mut args: Args) -> Result<()> {
+    let seed = seed(&mut args)?;
+    args.finish()?;
+
+    println!("{}", seed.to_bech32_str(SEED_HRP));
+    Ok(())
+}
+
+pub fn derive(mut args: Args) -> Result<()> {
+    let public_key = public_key(&mut args)?;
+    args.finish()?;
+
+    println!("{}", public_key);
+    Ok(())
+}
+
+pub fn sign(mut args: Args) -> Result<()> {
+    let key = secret_key(&mut args)?;
+    let message = args.positional();
+    args.finish()?;
+
+    let message = read_input(message)?;
+    println!("{}", key.sign(message));
+    Ok(())
+}
+
+pub fn verify(mut args: Args) -> Result<()> {
+    let public_key = args.value("public-key")?.context("Missing --public-key")?;
+    let public_key = parse_public_key(&public_key)?;
+    let signature = args
+        .parse_value::<Signature>("signature")?
+        .context("Missing --signature")?;
+    let message = args.positional();
+    args.finish()?;
+
+    let message = read_input(message)?;
+    if public_key.verify(message, &signature) {
+        println!("valid");
+        Ok(())
+    } else {
+        bail!("Invalid signature")
+    }
+}
+
+pub fn fingerprint(mut args: Args) -> Result<()> {
+    let public_key = public_key(&mut args)?;
+    args.finish()?;
+
+    println!("{}", fingerprint_of(public_key.key()));
+    Ok(())
+}
+
+/// the ed25519 public key, or the key of an HD public key
+pub fn parse_public_key(s: &str) -> Result<ed25519::PublicKey> {
+    if s.len() == 2 * ed25519_hd::PublicKey::SIZE {
+        let public_key: ed25519_hd::PublicKey = s.parse().context("Invalid public key")?;
+        Ok(*public_key.key())
+    } else {
+        ensure!(
+            s.len() == 2 * ed25519::PublicKey::SIZE,
+            "Invalid public key size"
+        );
+        s.parse().context("Invalid public key")
+    }
+}
+
+/// the first bytes of the BLAKE2b of the public key, in groups of
+/// 2 bytes of hexadecimal
+fn fingerprint_of(public_key: &ed25519::PublicKey) -> String {
+    let mut hash = [0; FINGERPRINT_SIZE];
+    let mut hasher = Blake2b::new(FINGERPRINT_SIZE);
+    hasher.input(public_key.as_ref());
+    hasher.result(&mut hash);
+
+    hash.chunks(2)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// the public key of the `--key` or the `--public-key`, derived with
+/// the `--path`s
+fn public_key(args: &mut Args) -> Result<ed25519_hd::PublicKey> {
+    if let Some(public_key) = args.value("public-key")? {
+        let mut public_key: ed25519_hd::PublicKey =
+            public_key.parse().context("Invalid HD public key")?;
+        for path in args.values("path") {
+            public_key = public_key
+                .derive(path.as_bytes())
+                .with_context(|| format!("Cannot derive the path {:?}", path))?;
+        }
+        Ok(public_key)
+    } else {
+        secret_key(args).map(|key| key.public_key())
+    }
+}
+
+/// the secret key of the `--key` file, derived with the `--path`s
+pub fn secret_key(args: &mut Args) -> Result<ed25519_hd::SecretKey> {
+    let seed = seed(args)?;
+
+    let mut key = ed25519_hd::SecretKey::from_seed(&seed);
+    for path in args.values("path") {
+        key = key.derive(path.as_bytes());
+    }
+    Ok(key)
+}
+
+fn seed(args: &mut Args) -> Result<Seed> {
+    let key_file = args.value("key")?.context("Missing --key")?;
+    let password = password(args)?;
+
+    let content = fs::read_to_string(&key_file)
+        .with_context(|| format!("Cannot read the key file {}", key_file))?;
+    KeyFile::decrypt(&content, &password)
+}
+
+fn password(args: &mut Args) -> Result<Vec<u8>> {
+    if let Some(file) = args.value("password-file")? {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Cannot read the password file {}", file))?;
+        let password = content.lines().next().unwrap_or_default();
+        Ok(password.as_bytes().to_vec())
+    } else if let Ok(password) = std::env::var("KEYNESIS_PASSWORD") {
+        Ok(password.into_bytes())
+    } else {
+        bail!("Missing password, use --password-file or set KEYNESIS_PASSWORD")
+    }
+}
+
+fn read_input(file: Option<String>) -> Result<Vec<u8>> {
+    match file {
+        Some(file) => fs::read(&file).with_context(|| format!("Cannot read {}", file)),
+        None => {
+            let mut input = Vec::new();
+            io::stdin()
+                .read_to_end(&mut input)
+                .context("Cannot read the standard input")?;
+            Ok(input)
+        }
+    }
+}
+
+/// write the key file, the file needs to not exist already
+fn write_key_file(output: Option<String>, seed: &Seed, password: &[u8]) -> Result<()> {
+    let key_file = KeyFile::new(Argon2Params::MODERATE).encrypt(&mut OsRng, seed, password)?;
+
+    match output {
+        None => println!("{}", key_file),
+        Some(output) => {
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+            let mut file = options
+                .open(&output)
+                .with_context(|| format!("Cannot create the key file {}", output))?;
+            writeln!(file, "{}", key_file)
+                .with_context(|| format!("Cannot write the key file {}", output))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_keys() {
+        let key = ed25519_hd::SecretKey::new(OsRng).public_key();
+
+        let hd = parse_public_key(&key.to_string()).unwrap();
+        let ed25519 = parse_public_key(&key.key().to_string()).unwrap();
+
+        assert_eq!(hd, *key.key());
+        assert_eq!(ed25519, *key.key());
+        assert!(parse_public_key("00").is_err());
+    }
+
+    #[test]
+    fn fingerprint() {
+        let key = ed25519_hd::SecretKey::new(OsRng).public_key();
+        let fingerprint = fingerprint_of(key.key());
+
+        assert_eq!(
+            fingerprint.len(),
+            FINGERPRINT_SIZE * 2 + FINGERPRINT_SIZE / 2 - 1
+        );
+        assert_eq!(fingerprint, fingerprint_of(key.key()));
+    }
+}
@@ -0,0 +1,67 @@
+/*!
+# keynesis
+
+command line tool to manage the keynesis identities and to debug the
+derivations of the HD keys. Run `keynesis help` for the list of the
+commands.
+*/
+
+mod args;
+mod key_file;
+mod keys;
+mod net;
+
+use self::args::Args;
+use anyhow::{bail, Result};
+
+const USAGE: &str = "\
+keynesis <COMMAND> [OPTIONS]
+
+Commands:
+    keygen      [--output FILE]
+                generate a new root key and write its encrypted key file
+    import      [--output FILE]
+                read a seed (bech32) from stdin and write its encrypted key file
+    export      --key FILE
+                print the seed (bech32) of the key file, in clear
+    derive      (--key FILE | --public-key HEX) [--path PATH]...
+                print the HD public key derived with the given paths
+    sign        --key FILE [--path PATH]... [MESSAGE_FILE]
+                sign the message (stdin by default) with the (derived) key
+    verify      --public-key HEX --signature HEX [MESSAGE_FILE]
+                verify the signature of the message (stdin by default)
+    fingerprint (--key FILE | --public-key HEX) [--path PATH]...
+                print the fingerprint of the (derived) public key
+    connect     --key FILE [--path PATH]... --remote-key HEX ADDRESS
+                open a session with the peer and pipe stdin/stdout through it
+    listen      --key FILE [--path PATH]... [--allow HEX]... ADDRESS
+                accept a session (from the allowed peers) and pipe
+                stdin/stdout through it
+
+The password of the key files is read from the first line of the
+--password-file or from the KEYNESIS_PASSWORD environment variable.
+The paths are applied one after the other, from the root key.
+";
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let args = Args::parse(args)?;
+
+    match command.as_deref() {
+        Some("keygen") => keys::keygen(args),
+        Some("import") => keys::import(args),
+        Some("export") => keys::export(args),
+        Some("derive") => keys::derive(args),
+        Some("sign") => keys::sign(args),
+        Some("verify") => keys::verify(args),
+        Some("fingerprint") => keys::fingerprint(args),
+        Some("connect") => net::connect(args),
+        Some("listen") => net::listen(args),
+        None | Some("help") | Some("--help") | Some("-h") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => bail!("Unknown command {:?}, see `keynesis help`", command),
+    }
+}
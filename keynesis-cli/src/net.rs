@@ -0,0 +1,136 @@
+use crate::{args::Args, keys};
+use anyhow::{Context as _, Result};
+use bytes::Bytes;
+use futures::prelude::*;
+use keynesis_core::key::{ed25519::PublicKey, ed25519_hd::SecretKey, Dh as _};
+use keynesis_network::net::{Connection, Listener};
+use rand_core::OsRng;
+use tokio::io::{self, AsyncReadExt as _, AsyncWriteExt as _};
+
+/// size of the chunks of the standard input sent to the peer
+const CHUNK_SIZE: usize = 4096;
+
+pub fn connect(mut args: Args) -> Result<()> {
+    let key = keys::secret_key(&mut args)?;
+    let remote = args.value("remote-key")?.context("Missing --remote-key")?;
+    let remote = keys::parse_public_key(&remote)?;
+    let address = args.positional().context("Missing the ADDRESS")?;
+    args.finish()?;
+
+    eprintln!("identity: {}", key.public());
+
+    run(async move {
+        let connection = Connection::connect(OsRng, &key, address, remote).await?;
+        eprintln!("session: {}", connection.session_id());
+
+        pipe(connection).await
+    })
+}
+
+pub fn listen(mut args: Args) -> Result<()> {
+    let key = keys::secret_key(&mut args)?;
+    let allowed = args
+        .values("allow")
+        .iter()
+        .map(|key| keys::parse_public_key(key))
+        .collect::<Result<Vec<PublicKey>>>()?;
+    let address = args.positional().context("Missing the ADDRESS")?;
+    args.finish()?;
+
+    eprintln!("identity: {}", key.public());
+
+    run(async move {
+        let listener = Listener::new(address).await?;
+
+        // only one peer is served, the ones failing the handshake are
+        // ignored so a port scan does not stop the listener
+        let connection = loop {
+            let accepting = listener.accept::<_, SecretKey>(OsRng).await?;
+            let peer_addr = accepting.remote_address();
+
+            let check_id = |id: &PublicKey| allowed.is_empty() || allowed.contains(id);
+            match accepting.handshake(&key, check_id).await {
+                Ok(connection) => break connection,
+                Err(error) => eprintln!("{}: {:#}", peer_addr, error),
+            }
+        };
+
+        eprintln!(
+            "peer: {} ({})",
+            connection
+                .remote_public_identity()
+                .map_or_else(|| "anonymous".to_owned(), ToString::to_string),
+            connection.remote_address()
+        );
+        eprintln!("session: {}", connection.session_id());
+
+        pipe(connection).await
+    })
+}
+
+fn run<F>(future: F) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .context("Cannot start the runtime")?;
+
+    let result = runtime.block_on(future);
+
+    // the read of the standard input may still be blocking
+    runtime.shutdown_background();
+
+    result
+}
+
+/// send the standard input to the peer and write what is received
+/// in the standard output
+async fn pipe(connection: Connection) -> Result<()> {
+    let (mut reader, mut writer) = connection.into_parts();
+
+    let send = async {
+        let mut stdin = io::stdin();
+        let mut buffer = vec![0; CHUNK_SIZE];
+
+        loop {
+            let len = stdin
+                .read(&mut buffer)
+                .await
+                .context("Cannot read the standard input")?;
+            if len == 0 {
+                break;
+            }
+            writer.send(Bytes::copy_from_slice(&buffer[..len])).await?;
+        }
+
+        writer.close().await
+    };
+
+    let receive = async {
+        let mut stdout = io::stdout();
+
+        while let Some(bytes) = reader.next().await {
+            let bytes = bytes?;
+            stdout
+                .write_all(&bytes)
+                .await
+                .context("Cannot write the standard output")?;
+            stdout.flush().await?;
+        }
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    // the peer closing the connection ends the session, even if
+    // the standard input is still open
+    futures::pin_mut!(send, receive);
+    match future::select(send, receive).await {
+        future::Either::Left((sent, receive)) => {
+            sent?;
+            receive.await
+        }
+        future::Either::Right((received, _)) => received,
+    }
+}
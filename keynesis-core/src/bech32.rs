@@ -0,0 +1,286 @@
+/*!
+# Bech32 encoding
+
+Checksummed, human readable encoding of binary data as defined in
+[BIP173]. The human readable part (the _hrp_) is a prefix that can be
+used to tell what kind of data is encoded (a seed, a public key...).
+
+Unlike [BIP173] we do not limit the length of the encoded string to
+90 characters, so larger objects (like the HD public keys) can be
+encoded too.
+
+```
+use keynesis_core::bech32;
+
+let encoded = bech32::encode("data", b"some bytes");
+let (hrp, decoded) = bech32::decode(&encoded).unwrap();
+
+assert_eq!(hrp, "data");
+assert_eq!(decoded, b"some bytes");
+```
+
+[BIP173]: https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki
+*/
+
+use crate::memsec::Scrubbed as _;
+use thiserror::Error;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const SEPARATOR: char = '1';
+const CHECKSUM_LEN: usize = 6;
+const GENERATORS: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Bech32Error {
+    #[error("Missing human readable part separator")]
+    MissingSeparator,
+
+    #[error("Invalid human readable part")]
+    InvalidHrp,
+
+    #[error("Unexpected human readable part, expecting {expected}")]
+    UnexpectedHrp { expected: String },
+
+    #[error("Invalid character {0:?}")]
+    InvalidChar(char),
+
+    #[error("Mixed case string")]
+    MixedCase,
+
+    #[error("Not enough data to contain the checksum")]
+    TooShort,
+
+    #[error("Invalid checksum")]
+    InvalidChecksum,
+
+    #[error("Invalid padding")]
+    InvalidPadding,
+}
+
+fn polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    let mut chk: u32 = 1;
+    for v in values {
+        let b = chk >> 25;
+        chk = (chk & 0x1ff_ffff) << 5 ^ u32::from(v);
+        for (i, generator) in GENERATORS.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes()
+        .map(|b| b >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|b| b & 0x1f))
+}
+
+fn create_checksum(hrp: &str, data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let values = hrp_expand(hrp)
+        .chain(data.iter().copied())
+        .chain([0; CHECKSUM_LEN]);
+    let m = polymod(values) ^ 1;
+
+    let mut checksum = [0; CHECKSUM_LEN];
+    for (i, c) in checksum.iter_mut().enumerate() {
+        *c = ((m >> (5 * (5 - i))) & 0x1f) as u8;
+    }
+    checksum
+}
+
+fn verify_checksum(hrp: &str, data: &[u8]) -> bool {
+    polymod(hrp_expand(hrp).chain(data.iter().copied())) == 1
+}
+
+/// regroup the bits of `data` from groups of `from` bits to groups of `to` bits
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Bech32Error> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max_v: u32 = (1 << to) - 1;
+    let mut ret = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+
+    for value in data {
+        acc = (acc << from) | u32::from(*value);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            ret.push(((acc >> bits) & max_v) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            ret.push(((acc << (to - bits)) & max_v) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max_v) != 0 {
+        return Err(Bech32Error::InvalidPadding);
+    }
+
+    Ok(ret)
+}
+
+fn check_hrp(hrp: &str) -> Result<(), Bech32Error> {
+    if hrp.is_empty() || !hrp.bytes().all(|b| (33..=126).contains(&b)) {
+        Err(Bech32Error::InvalidHrp)
+    } else {
+        Ok(())
+    }
+}
+
+/// encode the given bytes with the given human readable part
+///
+/// # Panics
+///
+/// the function will panic if the `hrp` is empty or contains characters
+/// outside of the printable US-ASCII range. The hrp is expected to be a
+/// constant defined by the application.
+pub fn encode(hrp: &str, data: impl AsRef<[u8]>) -> String {
+    check_hrp(hrp).expect("the human readable part should be valid");
+
+    let hrp = hrp.to_lowercase();
+    let mut data = convert_bits(data.as_ref(), 8, 5, true)
+        .expect("converting with padding enabled cannot fail");
+    let checksum = create_checksum(&hrp, &data);
+
+    let mut s = String::with_capacity(hrp.len() + 1 + data.len() + CHECKSUM_LEN);
+    s.push_str(&hrp);
+    s.push(SEPARATOR);
+    for c in data.iter().chain(checksum.iter()) {
+        s.push(CHARSET[*c as usize] as char);
+    }
+
+    // the data may be a secret (a seed for example)
+    data.scrub();
+    s
+}
+
+/// decode the given bech32 string into its human readable part
+/// and its data
+pub fn decode(s: &str) -> Result<(String, Vec<u8>), Bech32Error> {
+    let has_lower = s.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = s.bytes().any(|b| b.is_ascii_uppercase());
+    if has_lower && has_upper {
+        return Err(Bech32Error::MixedCase);
+    }
+    let s = s.to_lowercase();
+
+    let position = s.rfind(SEPARATOR).ok_or(Bech32Error::MissingSeparator)?;
+    let (hrp, data) = s.split_at(position);
+    let data = &data[1..];
+    check_hrp(hrp)?;
+
+    if data.len() < CHECKSUM_LEN {
+        return Err(Bech32Error::TooShort);
+    }
+
+    let data = data
+        .chars()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|v| *v as char == c)
+                .map(|v| v as u8)
+                .ok_or(Bech32Error::InvalidChar(c))
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    if !verify_checksum(hrp, &data) {
+        return Err(Bech32Error::InvalidChecksum);
+    }
+
+    let data = convert_bits(&data[..data.len() - CHECKSUM_LEN], 5, 8, false)?;
+
+    Ok((hrp.to_owned(), data))
+}
+
+/// decode the given bech32 string, checking the human readable part
+/// is the `expected` one
+pub fn decode_with_hrp(expected: &str, s: &str) -> Result<Vec<u8>, Bech32Error> {
+    let (hrp, data) = decode(s)?;
+
+    if hrp != expected.to_lowercase() {
+        Err(Bech32Error::UnexpectedHrp {
+            expected: expected.to_owned(),
+        })
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum_only(s: &str) -> bool {
+        let s = s.to_lowercase();
+        let position = s.rfind(SEPARATOR).unwrap();
+        let (hrp, data) = s.split_at(position);
+        let data = data[1..]
+            .chars()
+            .map(|c| CHARSET.iter().position(|v| *v as char == c).unwrap() as u8)
+            .collect::<Vec<_>>();
+        verify_checksum(hrp, &data)
+    }
+
+    #[test]
+    fn bip173_valid_checksums() {
+        const VALID: &[&str] = &[
+            "A12UEL5L",
+            "a12uel5l",
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1tt5tgs",
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+        ];
+
+        for valid in VALID {
+            assert!(checksum_only(valid), "{} should be valid", valid);
+        }
+    }
+
+    #[test]
+    fn invalid_strings() {
+        assert_eq!(decode("pzry9x0s0muk"), Err(Bech32Error::MissingSeparator));
+        assert_eq!(decode("1pzry9x0s0muk"), Err(Bech32Error::InvalidHrp));
+        assert_eq!(decode("A1G7SGD8"), Err(Bech32Error::InvalidChecksum));
+        assert_eq!(decode("li1dgmt3"), Err(Bech32Error::TooShort));
+        assert_eq!(decode("A12uEL5L"), Err(Bech32Error::MixedCase));
+        assert_eq!(decode("x1b4n0q5v"), Err(Bech32Error::InvalidChar('b')));
+    }
+
+    #[test]
+    fn unexpected_hrp() {
+        let encoded = encode("seed", [0; 32]);
+        assert!(decode_with_hrp("seed", &encoded).is_ok());
+        assert_eq!(
+            decode_with_hrp("pk", &encoded),
+            Err(Bech32Error::UnexpectedHrp {
+                expected: "pk".to_owned()
+            })
+        );
+    }
+
+    #[quickcheck]
+    fn encode_decode(data: Vec<u8>) -> bool {
+        let encoded = encode("test", &data);
+        let (hrp, decoded) = decode(&encoded).unwrap();
+
+        hrp == "test" && decoded == data
+    }
+
+    #[quickcheck]
+    fn decode_uppercase(data: Vec<u8>) -> bool {
+        let encoded = encode("test", &data).to_uppercase();
+        let (hrp, decoded) = decode(&encoded).unwrap();
+
+        hrp == "test" && decoded == data
+    }
+}
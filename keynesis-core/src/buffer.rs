@@ -39,3 +39,83 @@ impl AsRef<[u8]> for BufRead<'_> {
         self.bytes
     }
 }
+
+/// Output buffer of the encryption and decryption functions
+///
+/// The fixed size buffers (`[u8]` and `[u8; N]`) are written from their
+/// first byte and need to be large enough for the output. The growable
+/// buffers (`Vec<u8>` and, with the `bytes` feature, `BytesMut`) get the
+/// output appended to their current content.
+///
+/// ```
+/// # use keynesis_core::{OutBuffer as _};
+/// let mut fixed = [0u8; 4];
+/// fixed.prepare(3).unwrap().copy_from_slice(b"abc");
+/// assert_eq!(&fixed, b"abc\0");
+///
+/// let mut growable = b"abc".to_vec();
+/// growable.prepare(3).unwrap().copy_from_slice(b"def");
+/// assert_eq!(growable, b"abcdef");
+/// ```
+pub trait OutBuffer {
+    /// get `len` writable bytes from the buffer
+    ///
+    /// returns `None` if the buffer cannot hold `len` bytes.
+    fn prepare(&mut self, len: usize) -> Option<&mut [u8]>;
+
+    /// release the `len` bytes obtained with the last call to
+    /// [`prepare`](OutBuffer::prepare). This is used when the
+    /// operation failed and the prepared bytes are not valid.
+    fn discard(&mut self, len: usize);
+}
+
+impl OutBuffer for [u8] {
+    fn prepare(&mut self, len: usize) -> Option<&mut [u8]> {
+        self.get_mut(..len)
+    }
+
+    fn discard(&mut self, _len: usize) {}
+}
+
+impl<const N: usize> OutBuffer for [u8; N] {
+    fn prepare(&mut self, len: usize) -> Option<&mut [u8]> {
+        self.get_mut(..len)
+    }
+
+    fn discard(&mut self, _len: usize) {}
+}
+
+impl OutBuffer for Vec<u8> {
+    fn prepare(&mut self, len: usize) -> Option<&mut [u8]> {
+        let start = self.len();
+        self.resize(start + len, 0);
+        Some(&mut self[start..])
+    }
+
+    fn discard(&mut self, len: usize) {
+        self.truncate(self.len().saturating_sub(len));
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl OutBuffer for bytes::BytesMut {
+    fn prepare(&mut self, len: usize) -> Option<&mut [u8]> {
+        let start = self.len();
+        self.resize(start + len, 0);
+        Some(&mut self[start..])
+    }
+
+    fn discard(&mut self, len: usize) {
+        self.truncate(self.len().saturating_sub(len));
+    }
+}
+
+impl<T: OutBuffer + ?Sized> OutBuffer for &mut T {
+    fn prepare(&mut self, len: usize) -> Option<&mut [u8]> {
+        (**self).prepare(len)
+    }
+
+    fn discard(&mut self, len: usize) {
+        (**self).discard(len)
+    }
+}
@@ -0,0 +1,152 @@
+/*!
+# Canonical encoding
+
+Every value has a single valid byte encoding: [`to_canonical_bytes`]
+produces it and [`from_bytes_strict`] rejects any other encoding of the
+same value. Without it, an attacker could re-encode a public key or a
+signature (for example with the non reduced coordinate of a point or the
+non reduced scalar of a signature) and the hash or the signature of the
+structure containing it would change while the value remains the same.
+
+The `TryFrom<&[u8]>` implementations remain lenient: they only check the
+sizes of the inputs.
+
+```
+use keynesis_core::{canonical::Canonical as _, key::ed25519::{PublicKey, SecretKey}};
+# use rand::thread_rng;
+
+let public_key = SecretKey::new(thread_rng()).public_key();
+let bytes = public_key.to_canonical_bytes();
+
+assert_eq!(PublicKey::from_bytes_strict(&bytes).unwrap(), public_key);
+```
+
+[`to_canonical_bytes`]: Canonical::to_canonical_bytes
+[`from_bytes_strict`]: Canonical::from_bytes_strict
+*/
+
+use curve25519_dalek::{edwards::CompressedEdwardsY, scalar::Scalar};
+use thiserror::Error;
+
+/// types with a single valid byte encoding
+pub trait Canonical: Sized {
+    /// the canonical encoding of the value
+    fn to_canonical_bytes(&self) -> Vec<u8>;
+
+    /// decode the value, rejecting the non canonical encodings
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError>;
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CanonicalError {
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+
+    #[error("The encoding is not canonical")]
+    NonCanonical,
+}
+
+/// error of the decoding of a packed array of values
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseManyError {
+    #[error("Invalid size, expecting a multiple of {expected} bytes")]
+    InvalidSize { expected: usize },
+
+    #[error("Invalid entries at indices {0:?}")]
+    InvalidEntries(Vec<usize>),
+}
+
+/// strictly decode the concatenated values of `size` bytes each
+///
+/// all the entries are checked so the error reports the indices of all
+/// the invalid ones, not only the first one.
+pub(crate) fn parse_many<T: Canonical>(
+    bytes: &[u8],
+    size: usize,
+) -> Result<Vec<T>, ParseManyError> {
+    if !bytes.len().is_multiple_of(size) {
+        return Err(ParseManyError::InvalidSize { expected: size });
+    }
+
+    let mut values = Vec::with_capacity(bytes.len() / size);
+    let mut invalid = Vec::new();
+
+    for (index, entry) in bytes.chunks_exact(size).enumerate() {
+        match T::from_bytes_strict(entry) {
+            Ok(value) => values.push(value),
+            Err(_) => invalid.push(index),
+        }
+    }
+
+    if invalid.is_empty() {
+        Ok(values)
+    } else {
+        Err(ParseManyError::InvalidEntries(invalid))
+    }
+}
+
+/// check the bytes are the canonical encoding of an edwards25519 point
+///
+/// the `y` coordinate needs to be reduced and the sign of `x` cannot be
+/// set if `x` is `0`.
+pub(crate) fn check_point(bytes: &[u8]) -> Result<(), CanonicalError> {
+    let bytes = <[u8; 32]>::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)?;
+    let point = CompressedEdwardsY(bytes)
+        .decompress()
+        .ok_or(CanonicalError::InvalidEncoding)?;
+
+    if point.compress().to_bytes() == bytes {
+        Ok(())
+    } else {
+        Err(CanonicalError::NonCanonical)
+    }
+}
+
+/// check the bytes are the canonical (reduced) encoding of a scalar
+pub(crate) fn check_scalar(bytes: &[u8]) -> Result<(), CanonicalError> {
+    let bytes = <[u8; 32]>::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)?;
+
+    Scalar::from_canonical_bytes(bytes)
+        .map(|_| ())
+        .ok_or(CanonicalError::NonCanonical)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+
+    /// the identity point with its `y` coordinate not reduced (`p + 1`)
+    pub(crate) const NON_CANONICAL_POINT: [u8; 32] = [
+        0xee, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ];
+
+    #[test]
+    fn points() {
+        check_point(ED25519_BASEPOINT_POINT.compress().as_bytes()).unwrap();
+
+        let mut identity = [0; 32];
+        identity[0] = 1;
+        check_point(&identity).unwrap();
+
+        assert_eq!(
+            check_point(&NON_CANONICAL_POINT),
+            Err(CanonicalError::NonCanonical)
+        );
+
+        // x is 0 for the identity, the sign bit cannot be set
+        identity[31] |= 0x80;
+        assert_eq!(check_point(&identity), Err(CanonicalError::NonCanonical));
+    }
+
+    #[test]
+    fn scalars() {
+        check_scalar(Scalar::one().as_bytes()).unwrap();
+        assert_eq!(check_scalar(&[0xff; 32]), Err(CanonicalError::NonCanonical));
+        assert_eq!(check_scalar(&[0; 31]), Err(CanonicalError::InvalidEncoding));
+    }
+}
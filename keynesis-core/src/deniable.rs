@@ -0,0 +1,235 @@
+/*!
+# Deniable authentication
+
+Authenticate messages with a MAC keyed from the Diffie-Hellman shared
+secret of the two peers instead of signing them (like in [OTR]). The
+receiver knows the message comes from the sender since only the two of
+them can compute the tag. But since the receiver could have computed
+the very same tag (see [`DeniableAuth::forge`]) the transcript cannot
+be used to prove to a third party who authored the messages.
+
+The Noise handshakes of the [`noise`](crate::noise) module already
+authenticate the peers this way. This is for the messages that would
+otherwise be signed with the [`ed25519`](crate::key::ed25519) keys.
+
+```
+use keynesis_core::{deniable::DeniableAuth, key::ed25519::SecretKey};
+# use rand::thread_rng;
+
+let alice = SecretKey::new(thread_rng());
+let bob = SecretKey::new(thread_rng());
+
+let alice_auth = DeniableAuth::new(&alice, &bob.public_key(), b"chat session 42");
+let bob_auth = DeniableAuth::new(&bob, &alice.public_key(), b"chat session 42");
+
+let tag = alice_auth.authenticate(b"meet at noon");
+assert!(bob_auth.verify(b"meet at noon", &tag));
+
+// bob could have produced the same tag on his own
+assert_eq!(bob_auth.forge(b"meet at noon"), tag);
+```
+
+The `context` should identify the conversation (for example the Noise
+handshake hash of the session) so tags cannot be replayed in another
+conversation.
+
+[OTR]: https://otr.cypherpunks.ca/otr-wpes.pdf
+*/
+
+use crate::{
+    key::{ed25519::PublicKey, Dh},
+    memsec::Scrubbed as _,
+};
+use cryptoxide::{
+    hkdf::{hkdf_expand, hkdf_extract},
+    hmac::Hmac,
+    mac::Mac as _,
+    sha2::Sha512,
+};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug, Formatter},
+};
+use subtle::{Choice, ConstantTimeEq};
+
+const INFO: &[u8] = b"keynesis:deniable";
+
+/// authenticate the messages exchanged between a local and a remote peer
+///
+/// The key is scrubbed (zeroed) when dropped.
+pub struct DeniableAuth {
+    key: [u8; 64],
+    local: PublicKey,
+    remote: PublicKey,
+}
+
+/// authentication tag of a message
+#[derive(Clone, Copy)]
+pub struct Tag([u8; Self::SIZE]);
+
+impl Tag {
+    pub const SIZE: usize = 64;
+}
+
+impl DeniableAuth {
+    /// create the authenticator of the messages exchanged with `remote`
+    /// in the given `context`
+    pub fn new<K>(local: &K, remote: &PublicKey, context: &[u8]) -> Self
+    where
+        K: Dh,
+    {
+        let shared_secret = local.dh(remote);
+
+        let mut info = Vec::with_capacity(INFO.len() + context.len());
+        info.extend_from_slice(INFO);
+        info.extend_from_slice(context);
+
+        let mut prk = [0; 64];
+        let mut key = [0; 64];
+        hkdf_extract(Sha512::new(), &[], shared_secret.as_ref(), &mut prk);
+        hkdf_expand(Sha512::new(), &prk, &info, &mut key);
+        prk.scrub();
+
+        Self {
+            key,
+            local: local.public(),
+            remote: *remote,
+        }
+    }
+
+    fn tag(&self, sender: &PublicKey, message: &[u8]) -> Tag {
+        let mut mac = Hmac::new(Sha512::new(), &self.key);
+        mac.input(sender.as_ref());
+        mac.input(message);
+
+        let mut tag = [0; Tag::SIZE];
+        mac.raw_result(&mut tag);
+        Tag(tag)
+    }
+
+    /// authenticate a message we are sending to the remote peer
+    pub fn authenticate(&self, message: &[u8]) -> Tag {
+        self.tag(&self.local, message)
+    }
+
+    /// verify a message has been authenticated by the remote peer
+    pub fn verify(&self, message: &[u8], tag: &Tag) -> bool {
+        self.tag(&self.remote, message) == *tag
+    }
+
+    /// compute the tag the remote peer would have computed for the message
+    ///
+    /// this is what makes the authentication deniable: anyone with the
+    /// local key can produce the tags of the remote peer.
+    pub fn forge(&self, message: &[u8]) -> Tag {
+        self.tag(&self.remote, message)
+    }
+}
+
+impl Drop for DeniableAuth {
+    fn drop(&mut self) {
+        self.key.scrub();
+    }
+}
+
+/* Format ****************************************************************** */
+
+impl Debug for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Tag").field(&hex::encode(self.0)).finish()
+    }
+}
+
+/* Eq ********************************************************************** */
+
+impl ConstantTimeEq for Tag {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0[..].ct_eq(&other.0[..])
+    }
+}
+
+impl PartialEq for Tag {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Tag {}
+
+/* Conversion ************************************************************** */
+
+impl AsRef<[u8]> for Tag {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; Self::SIZE]> for Tag {
+    fn from(tag: [u8; Self::SIZE]) -> Self {
+        Self(tag)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Tag {
+    type Error = std::array::TryFromSliceError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        <[u8; Self::SIZE]>::try_from(value).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::{curve25519, ed25519_extended};
+
+    #[quickcheck]
+    fn authenticate_verify(
+        alice: ed25519_extended::SecretKey,
+        bob: ed25519_extended::SecretKey,
+        message: Vec<u8>,
+    ) -> bool {
+        let alice_auth = DeniableAuth::new(&alice, &bob.public_key(), b"");
+        let bob_auth = DeniableAuth::new(&bob, &alice.public_key(), b"");
+
+        let tag = alice_auth.authenticate(&message);
+
+        bob_auth.verify(&message, &tag) && bob_auth.forge(&message) == tag
+    }
+
+    #[quickcheck]
+    fn cannot_reflect(alice: curve25519::SecretKey, bob: curve25519::SecretKey) -> bool {
+        let alice_auth = DeniableAuth::new(&alice, &bob.public_key(), b"");
+
+        let tag = alice_auth.authenticate(b"message");
+
+        !alice_auth.verify(b"message", &tag)
+    }
+
+    #[quickcheck]
+    fn different_context(
+        alice: ed25519_extended::SecretKey,
+        bob: ed25519_extended::SecretKey,
+    ) -> bool {
+        let alice_auth = DeniableAuth::new(&alice, &bob.public_key(), b"context 1");
+        let bob_auth = DeniableAuth::new(&bob, &alice.public_key(), b"context 2");
+
+        let tag = alice_auth.authenticate(b"message");
+
+        !bob_auth.verify(b"message", &tag)
+    }
+
+    #[quickcheck]
+    fn third_party(
+        alice: ed25519_extended::SecretKey,
+        bob: ed25519_extended::SecretKey,
+        mallory: ed25519_extended::SecretKey,
+    ) -> bool {
+        let alice_auth = DeniableAuth::new(&alice, &bob.public_key(), b"");
+        let mallory_auth = DeniableAuth::new(&mallory, &alice.public_key(), b"");
+
+        let tag = alice_auth.authenticate(b"message");
+
+        !mallory_auth.verify(b"message", &tag)
+    }
+}
@@ -0,0 +1,814 @@
+/*!
+# Distributed key generation
+
+Several participants jointly generate an Ed25519 identity key without
+any of them ever knowing the secret key: every participant ends up with
+a [`KeyShare`] and any `threshold` of them are needed to use the key.
+This is the Pedersen DKG with the proofs of knowledge of the [FROST]
+paper (its `KeyGen` protocol) so the shares can be used for FROST
+threshold signatures (see [`frost`](crate::key::frost)).
+
+The protocol has two rounds:
+
+1. every participant broadcasts a [`Round1Message`] (the commitments to
+   its secret polynomial and a proof it knows the secret);
+2. every participant sends a [`SecretShare`] to each of the other
+   participants. **The secret shares need to be sent through a
+   confidential and authenticated channel** (a Noise session for
+   example).
+
+```
+use keynesis_core::dkg::{Parameters, Round1};
+# use rand::thread_rng;
+
+let params = Parameters::new(2, 3).unwrap();
+
+let (round1, messages): (Vec<_>, Vec<_>) = (1..=3)
+    .map(|index| Round1::new(thread_rng(), params, index, b"org root").unwrap())
+    .unzip();
+
+let (round2, shares): (Vec<_>, Vec<_>) = round1
+    .into_iter()
+    .map(|round1| round1.receive(&messages).unwrap())
+    .unzip();
+let shares: Vec<_> = shares.into_iter().flatten().collect();
+
+let key_shares: Vec<_> = round2
+    .into_iter()
+    .map(|round2| {
+        let index = round2.index();
+        let received: Vec<_> = shares.iter().filter(|s| s.receiver() == index).cloned().collect();
+        round2.finish(&received).unwrap()
+    })
+    .collect();
+
+// everyone agrees on the identity
+assert_eq!(key_shares[0].group_public_key(), key_shares[2].group_public_key());
+```
+
+The `context` given to [`Round1::new`] binds the proofs to this run of
+the protocol, it needs to be the same for all the participants and
+unique (the name of the organization and a date for example).
+
+[FROST]: https://eprint.iacr.org/2020/852.pdf
+*/
+
+use crate::key::ed25519::PublicKey;
+use cryptoxide::{digest::Digest as _, sha2::Sha512};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_TABLE,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::Identity as _,
+};
+use rand_core::{CryptoRng, RngCore};
+use std::{collections::BTreeMap, convert::TryFrom};
+use thiserror::Error;
+
+const CHALLENGE_CONTEXT: &[u8] = b"keynesis:dkg:proof";
+
+/// index of a participant, between `1` and the number of participants
+pub type ParticipantIndex = u16;
+
+/// the number of participants and the threshold of the key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Parameters {
+    threshold: u16,
+    participants: u16,
+}
+
+/// the first round of the protocol
+///
+/// created with [`Round1::new`] alongside the [`Round1Message`] to
+/// broadcast to the other participants.
+pub struct Round1 {
+    params: Parameters,
+    index: ParticipantIndex,
+    coefficients: Vec<Scalar>,
+    message: Round1Message,
+    context: Vec<u8>,
+}
+
+/// the message every participant broadcasts in the first round
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Round1Message {
+    sender: ParticipantIndex,
+    commitments: Vec<[u8; 32]>,
+    proof_r: [u8; 32],
+    proof_mu: [u8; 32],
+}
+
+/// the second round of the protocol
+pub struct Round2 {
+    params: Parameters,
+    index: ParticipantIndex,
+    own_share: Scalar,
+    commitments: BTreeMap<ParticipantIndex, Vec<EdwardsPoint>>,
+}
+
+/// the share of a participant's secret for another participant
+///
+/// needs to be sent through a confidential and authenticated channel.
+#[derive(Clone)]
+pub struct SecretShare {
+    sender: ParticipantIndex,
+    receiver: ParticipantIndex,
+    value: Scalar,
+}
+
+/// the result of the protocol: the share of the secret key of a
+/// participant and the public keys
+#[derive(Clone)]
+pub struct KeyShare {
+    params: Parameters,
+    index: ParticipantIndex,
+    secret: Scalar,
+    group_public_key: PublicKey,
+    verification_shares: Vec<PublicKey>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DkgError {
+    #[error(
+        "Invalid parameters, the threshold needs to be between 1 and the number of participants"
+    )]
+    InvalidParameters,
+
+    #[error("Invalid participant index {0}")]
+    InvalidIndex(ParticipantIndex),
+
+    #[error("Unexpected message from or for participant {0}")]
+    UnexpectedParticipant(ParticipantIndex),
+
+    #[error("Duplicated message from participant {0}")]
+    DuplicatedParticipant(ParticipantIndex),
+
+    #[error("Missing message from participant {0}")]
+    MissingParticipant(ParticipantIndex),
+
+    #[error("Invalid commitments from participant {0}")]
+    InvalidCommitments(ParticipantIndex),
+
+    #[error("Invalid proof of knowledge from participant {0}")]
+    InvalidProof(ParticipantIndex),
+
+    #[error("Invalid secret share from participant {0}")]
+    InvalidShare(ParticipantIndex),
+
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+}
+
+/* Parameters ************************************************************** */
+
+impl Parameters {
+    pub fn new(threshold: u16, participants: u16) -> Result<Self, DkgError> {
+        if threshold == 0 || threshold > participants {
+            return Err(DkgError::InvalidParameters);
+        }
+
+        Ok(Self {
+            threshold,
+            participants,
+        })
+    }
+
+    /// the number of participants needed to use the key
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+
+    pub fn participants(&self) -> u16 {
+        self.participants
+    }
+
+    pub(crate) fn check_index(&self, index: ParticipantIndex) -> Result<(), DkgError> {
+        if index == 0 || index > self.participants {
+            Err(DkgError::InvalidIndex(index))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn others(&self, index: ParticipantIndex) -> impl Iterator<Item = ParticipantIndex> {
+        (1..=self.participants).filter(move |other| *other != index)
+    }
+}
+
+/* Round 1 ***************************************************************** */
+
+impl Round1 {
+    /// start the protocol as the participant `index`
+    pub fn new<Rng>(
+        mut rng: Rng,
+        params: Parameters,
+        index: ParticipantIndex,
+        context: &[u8],
+    ) -> Result<(Self, Round1Message), DkgError>
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        params.check_index(index)?;
+
+        let coefficients: Vec<Scalar> = (0..params.threshold)
+            .map(|_| random_scalar(&mut rng))
+            .collect();
+        let commitments: Vec<[u8; 32]> = coefficients
+            .iter()
+            .map(|a| (a * &ED25519_BASEPOINT_TABLE).compress().to_bytes())
+            .collect();
+
+        // proof of knowledge of the secret (the first coefficient) so a
+        // participant cannot cancel the contributions of the others
+        let k = random_scalar(&mut rng);
+        let proof_r = (&k * &ED25519_BASEPOINT_TABLE).compress().to_bytes();
+        let c = challenge(context, index, &commitments[0], &proof_r);
+        let proof_mu = (k + coefficients[0] * c).to_bytes();
+
+        let message = Round1Message {
+            sender: index,
+            commitments,
+            proof_r,
+            proof_mu,
+        };
+
+        let round1 = Self {
+            params,
+            index,
+            coefficients,
+            message: message.clone(),
+            context: context.to_owned(),
+        };
+
+        Ok((round1, message))
+    }
+
+    pub fn index(&self) -> ParticipantIndex {
+        self.index
+    }
+
+    /// verify the messages of the other participants and compute the
+    /// secret shares to send to each one of them
+    ///
+    /// our own message can be part of `messages`, it is ignored.
+    pub fn receive(
+        self,
+        messages: &[Round1Message],
+    ) -> Result<(Round2, Vec<SecretShare>), DkgError> {
+        let mut commitments = BTreeMap::new();
+
+        for message in messages {
+            if message.sender == self.index {
+                if message != &self.message {
+                    return Err(DkgError::UnexpectedParticipant(message.sender));
+                }
+                continue;
+            }
+            self.params
+                .check_index(message.sender)
+                .map_err(|_| DkgError::UnexpectedParticipant(message.sender))?;
+            if commitments.contains_key(&message.sender) {
+                return Err(DkgError::DuplicatedParticipant(message.sender));
+            }
+
+            let points = message.verify(&self.params, &self.context)?;
+            commitments.insert(message.sender, points);
+        }
+
+        if let Some(missing) = self
+            .params
+            .others(self.index)
+            .find(|other| !commitments.contains_key(other))
+        {
+            return Err(DkgError::MissingParticipant(missing));
+        }
+
+        let shares = self
+            .params
+            .others(self.index)
+            .map(|receiver| SecretShare {
+                sender: self.index,
+                receiver,
+                value: evaluate(&self.coefficients, receiver),
+            })
+            .collect();
+
+        let own_commitments = self
+            .coefficients
+            .iter()
+            .map(|a| a * &ED25519_BASEPOINT_TABLE)
+            .collect();
+        commitments.insert(self.index, own_commitments);
+
+        let round2 = Round2 {
+            params: self.params,
+            index: self.index,
+            own_share: evaluate(&self.coefficients, self.index),
+            commitments,
+        };
+
+        Ok((round2, shares))
+    }
+}
+
+impl Round1Message {
+    pub fn sender(&self) -> ParticipantIndex {
+        self.sender
+    }
+
+    /// check the proof of knowledge and decode the commitments
+    fn verify(&self, params: &Parameters, context: &[u8]) -> Result<Vec<EdwardsPoint>, DkgError> {
+        if self.commitments.len() != params.threshold as usize {
+            return Err(DkgError::InvalidCommitments(self.sender));
+        }
+
+        let points = self
+            .commitments
+            .iter()
+            .map(|bytes| CompressedEdwardsY(*bytes).decompress())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(DkgError::InvalidCommitments(self.sender))?;
+
+        let mu = Scalar::from_canonical_bytes(self.proof_mu)
+            .ok_or(DkgError::InvalidProof(self.sender))?;
+        let c = challenge(context, self.sender, &self.commitments[0], &self.proof_r);
+        let r = &mu * &ED25519_BASEPOINT_TABLE - points[0] * c;
+
+        if r.compress().to_bytes() != self.proof_r {
+            return Err(DkgError::InvalidProof(self.sender));
+        }
+
+        Ok(points)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + 32 * (self.commitments.len() + 2));
+        bytes.extend_from_slice(&self.sender.to_be_bytes());
+        for commitment in &self.commitments {
+            bytes.extend_from_slice(commitment);
+        }
+        bytes.extend_from_slice(&self.proof_r);
+        bytes.extend_from_slice(&self.proof_mu);
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Round1Message {
+    type Error = DkgError;
+
+    /// the number of commitments is the threshold, it is checked when
+    /// the message is received
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < 2 + 3 * 32 || !(bytes.len() - 2).is_multiple_of(32) {
+            return Err(DkgError::InvalidEncoding);
+        }
+
+        let (sender, bytes) = bytes.split_at(2);
+        let mut chunks: Vec<[u8; 32]> = bytes
+            .chunks_exact(32)
+            .map(|chunk| <[u8; 32]>::try_from(chunk).unwrap())
+            .collect();
+        let proof_mu = chunks.pop().unwrap();
+        let proof_r = chunks.pop().unwrap();
+
+        Ok(Self {
+            sender: u16::from_be_bytes([sender[0], sender[1]]),
+            commitments: chunks,
+            proof_r,
+            proof_mu,
+        })
+    }
+}
+
+/* Round 2 ***************************************************************** */
+
+impl Round2 {
+    pub fn index(&self) -> ParticipantIndex {
+        self.index
+    }
+
+    /// verify the secret shares received from the other participants
+    /// and compute our [`KeyShare`]
+    pub fn finish(self, shares: &[SecretShare]) -> Result<KeyShare, DkgError> {
+        let mut secret = self.own_share;
+        let mut received = Vec::with_capacity(shares.len());
+
+        for share in shares {
+            if share.receiver != self.index || share.sender == self.index {
+                return Err(DkgError::UnexpectedParticipant(share.sender));
+            }
+            let commitments = self
+                .commitments
+                .get(&share.sender)
+                .ok_or(DkgError::UnexpectedParticipant(share.sender))?;
+            if received.contains(&share.sender) {
+                return Err(DkgError::DuplicatedParticipant(share.sender));
+            }
+
+            let expected = evaluate_commitments(commitments, self.index);
+            if &share.value * &ED25519_BASEPOINT_TABLE != expected {
+                return Err(DkgError::InvalidShare(share.sender));
+            }
+
+            secret += share.value;
+            received.push(share.sender);
+        }
+
+        if let Some(missing) = self
+            .params
+            .others(self.index)
+            .find(|other| !received.contains(other))
+        {
+            return Err(DkgError::MissingParticipant(missing));
+        }
+
+        let group_public_key: EdwardsPoint = self
+            .commitments
+            .values()
+            .map(|commitments| commitments[0])
+            .sum();
+        let verification_shares = (1..=self.params.participants)
+            .map(|index| {
+                let share: EdwardsPoint = self
+                    .commitments
+                    .values()
+                    .map(|commitments| evaluate_commitments(commitments, index))
+                    .sum();
+                PublicKey::from(share.compress().to_bytes())
+            })
+            .collect();
+
+        Ok(KeyShare {
+            params: self.params,
+            index: self.index,
+            secret,
+            group_public_key: PublicKey::from(group_public_key.compress().to_bytes()),
+            verification_shares,
+        })
+    }
+}
+
+impl SecretShare {
+    pub const SIZE: usize = 2 + 2 + 32;
+
+    pub fn sender(&self) -> ParticipantIndex {
+        self.sender
+    }
+
+    pub fn receiver(&self) -> ParticipantIndex {
+        self.receiver
+    }
+
+    /// encode the share, the result needs to be kept confidential
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..2].copy_from_slice(&self.sender.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.receiver.to_be_bytes());
+        bytes[4..].copy_from_slice(self.value.as_bytes());
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SecretShare {
+    type Error = DkgError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != Self::SIZE {
+            return Err(DkgError::InvalidEncoding);
+        }
+
+        let value = <[u8; 32]>::try_from(&bytes[4..]).unwrap();
+        let value = Scalar::from_canonical_bytes(value).ok_or(DkgError::InvalidEncoding)?;
+
+        Ok(Self {
+            sender: u16::from_be_bytes([bytes[0], bytes[1]]),
+            receiver: u16::from_be_bytes([bytes[2], bytes[3]]),
+            value,
+        })
+    }
+}
+
+/* Key share *************************************************************** */
+
+impl KeyShare {
+    pub fn params(&self) -> Parameters {
+        self.params
+    }
+
+    pub fn index(&self) -> ParticipantIndex {
+        self.index
+    }
+
+    /// the public key of the jointly generated identity
+    pub fn group_public_key(&self) -> &PublicKey {
+        &self.group_public_key
+    }
+
+    /// the public key of the share of the given participant, used to
+    /// verify its contributions to the threshold signatures
+    pub fn verification_share(&self, index: ParticipantIndex) -> Option<&PublicKey> {
+        self.params.check_index(index).ok()?;
+        self.verification_shares.get(index as usize - 1)
+    }
+
+    pub(crate) fn secret(&self) -> &Scalar {
+        &self.secret
+    }
+
+    pub(crate) fn from_parts(
+        params: Parameters,
+        index: ParticipantIndex,
+        secret: Scalar,
+        group_public_key: PublicKey,
+        verification_shares: Vec<PublicKey>,
+    ) -> Self {
+        Self {
+            params,
+            index,
+            secret,
+            group_public_key,
+            verification_shares,
+        }
+    }
+
+    /// encode the key share so it can be stored, the result needs to
+    /// be kept secret
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(6 + 32 * (2 + self.verification_shares.len()));
+        bytes.extend_from_slice(&self.params.threshold.to_be_bytes());
+        bytes.extend_from_slice(&self.params.participants.to_be_bytes());
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(self.secret.as_bytes());
+        bytes.extend_from_slice(self.group_public_key.as_ref());
+        for share in &self.verification_shares {
+            bytes.extend_from_slice(share.as_ref());
+        }
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for KeyShare {
+    type Error = DkgError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < 6 + 64 {
+            return Err(DkgError::InvalidEncoding);
+        }
+
+        let params = Parameters::new(
+            u16::from_be_bytes([bytes[0], bytes[1]]),
+            u16::from_be_bytes([bytes[2], bytes[3]]),
+        )
+        .map_err(|_| DkgError::InvalidEncoding)?;
+        let index = u16::from_be_bytes([bytes[4], bytes[5]]);
+        params
+            .check_index(index)
+            .map_err(|_| DkgError::InvalidEncoding)?;
+
+        if bytes.len() != 6 + 32 * (2 + params.participants as usize) {
+            return Err(DkgError::InvalidEncoding);
+        }
+
+        let secret = <[u8; 32]>::try_from(&bytes[6..38]).unwrap();
+        let secret = Scalar::from_canonical_bytes(secret).ok_or(DkgError::InvalidEncoding)?;
+        let mut keys = bytes[38..]
+            .chunks_exact(PublicKey::SIZE)
+            .map(|chunk| PublicKey::try_from(chunk).unwrap());
+        let group_public_key = keys.next().unwrap();
+        let verification_shares = keys.collect();
+
+        Ok(Self {
+            params,
+            index,
+            secret,
+            group_public_key,
+            verification_shares,
+        })
+    }
+}
+
+/* Drop ******************************************************************** */
+
+impl Drop for Round1 {
+    fn drop(&mut self) {
+        self.coefficients
+            .iter_mut()
+            .for_each(|a| *a = Scalar::zero());
+    }
+}
+
+impl Drop for Round2 {
+    fn drop(&mut self) {
+        self.own_share = Scalar::zero();
+    }
+}
+
+impl Drop for SecretShare {
+    fn drop(&mut self) {
+        self.value = Scalar::zero();
+    }
+}
+
+impl Drop for KeyShare {
+    fn drop(&mut self) {
+        self.secret = Scalar::zero();
+    }
+}
+
+/* Helpers ***************************************************************** */
+
+pub(crate) fn random_scalar<Rng>(rng: &mut Rng) -> Scalar
+where
+    Rng: RngCore + CryptoRng,
+{
+    let mut bytes = [0; 64];
+    rng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+fn challenge(
+    context: &[u8],
+    index: ParticipantIndex,
+    commitment: &[u8; 32],
+    r: &[u8; 32],
+) -> Scalar {
+    let mut hasher = Sha512::new();
+    hasher.input(CHALLENGE_CONTEXT);
+    hasher.input(&(context.len() as u64).to_be_bytes());
+    hasher.input(context);
+    hasher.input(&index.to_be_bytes());
+    hasher.input(commitment);
+    hasher.input(r);
+
+    let mut hash = [0; 64];
+    hasher.result(&mut hash);
+    Scalar::from_bytes_mod_order_wide(&hash)
+}
+
+/// evaluate the polynomial at `x`
+pub(crate) fn evaluate(coefficients: &[Scalar], x: ParticipantIndex) -> Scalar {
+    let x = Scalar::from(x as u64);
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::zero(), |acc, a| acc * x + a)
+}
+
+/// evaluate the commitments of the polynomial at `x`
+fn evaluate_commitments(commitments: &[EdwardsPoint], x: ParticipantIndex) -> EdwardsPoint {
+    let x = Scalar::from(x as u64);
+    commitments
+        .iter()
+        .rev()
+        .fold(EdwardsPoint::identity(), |acc, c| acc * x + c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn run(params: Parameters) -> Vec<KeyShare> {
+        let (round1, messages): (Vec<_>, Vec<_>) = (1..=params.participants())
+            .map(|index| Round1::new(thread_rng(), params, index, b"test").unwrap())
+            .unzip();
+
+        let (round2, shares): (Vec<_>, Vec<_>) = round1
+            .into_iter()
+            .map(|round1| round1.receive(&messages).unwrap())
+            .unzip();
+        let shares: Vec<_> = shares.into_iter().flatten().collect();
+
+        round2
+            .into_iter()
+            .map(|round2| {
+                let received: Vec<_> = shares
+                    .iter()
+                    .filter(|share| share.receiver == round2.index())
+                    .cloned()
+                    .collect();
+                round2.finish(&received).unwrap()
+            })
+            .collect()
+    }
+
+    /// recombine the secret with the lagrange coefficients at 0
+    fn recombine(shares: &[&KeyShare]) -> Scalar {
+        shares
+            .iter()
+            .map(|share| {
+                let i = Scalar::from(share.index as u64);
+                let lambda = shares
+                    .iter()
+                    .filter(|other| other.index != share.index)
+                    .fold(Scalar::one(), |acc, other| {
+                        let j = Scalar::from(other.index as u64);
+                        acc * j * (j - i).invert()
+                    });
+                lambda * share.secret
+            })
+            .sum()
+    }
+
+    #[test]
+    fn threshold() {
+        let params = Parameters::new(3, 5).unwrap();
+        let key_shares = run(params);
+
+        let group_public_key = *key_shares[0].group_public_key();
+        for share in &key_shares {
+            assert_eq!(share.group_public_key(), &group_public_key);
+            let verification_share = (&share.secret * &ED25519_BASEPOINT_TABLE)
+                .compress()
+                .to_bytes();
+            assert_eq!(
+                key_shares[0].verification_share(share.index()),
+                Some(&PublicKey::from(verification_share))
+            );
+        }
+
+        for subset in [[0, 1, 2], [1, 3, 4], [4, 2, 0]] {
+            let shares: Vec<_> = subset.iter().map(|i| &key_shares[*i]).collect();
+            let secret = recombine(&shares);
+            let public_key = (&secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes();
+            assert_eq!(PublicKey::from(public_key), group_public_key);
+        }
+
+        // below the threshold the secret cannot be recombined
+        let secret = recombine(&[&key_shares[0], &key_shares[1]]);
+        let public_key = (&secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes();
+        assert_ne!(PublicKey::from(public_key), group_public_key);
+    }
+
+    #[test]
+    fn invalid_proof() {
+        let params = Parameters::new(2, 2).unwrap();
+        let (alice, _) = Round1::new(thread_rng(), params, 1, b"test").unwrap();
+        let (_, mut message) = Round1::new(thread_rng(), params, 2, b"test").unwrap();
+        message.commitments[0] = message.commitments[1];
+
+        assert!(matches!(
+            alice.receive(&[message]),
+            Err(DkgError::InvalidProof(2))
+        ));
+
+        // the proofs are bound to the context
+        let (alice, _) = Round1::new(thread_rng(), params, 1, b"test").unwrap();
+        let (_, message) = Round1::new(thread_rng(), params, 2, b"other").unwrap();
+        assert!(matches!(
+            alice.receive(&[message]),
+            Err(DkgError::InvalidProof(2))
+        ));
+    }
+
+    #[test]
+    fn invalid_share() {
+        let params = Parameters::new(2, 2).unwrap();
+        let (alice, alice_message) = Round1::new(thread_rng(), params, 1, b"test").unwrap();
+        let (bob, bob_message) = Round1::new(thread_rng(), params, 2, b"test").unwrap();
+
+        let (alice, _) = alice.receive(&[bob_message]).unwrap();
+        let (_, mut shares) = bob.receive(&[alice_message]).unwrap();
+        shares[0].value += Scalar::one();
+
+        assert!(matches!(
+            alice.finish(&shares),
+            Err(DkgError::InvalidShare(2))
+        ));
+    }
+
+    #[test]
+    fn missing_participants() {
+        let params = Parameters::new(2, 3).unwrap();
+        let (alice, _) = Round1::new(thread_rng(), params, 1, b"test").unwrap();
+        let (_, bob_message) = Round1::new(thread_rng(), params, 2, b"test").unwrap();
+
+        assert!(matches!(
+            alice.receive(&[bob_message.clone(), bob_message]),
+            Err(DkgError::DuplicatedParticipant(2))
+        ));
+        assert_eq!(Parameters::new(0, 3), Err(DkgError::InvalidParameters));
+        assert_eq!(Parameters::new(4, 3), Err(DkgError::InvalidParameters));
+        assert!(Round1::new(thread_rng(), params, 4, b"test").is_err());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let params = Parameters::new(2, 3).unwrap();
+        let (alice, alice_message) = Round1::new(thread_rng(), params, 1, b"test").unwrap();
+        let decoded = Round1Message::try_from(alice_message.to_bytes().as_slice()).unwrap();
+        assert_eq!(decoded, alice_message);
+
+        let (_, bob_message) = Round1::new(thread_rng(), params, 2, b"test").unwrap();
+        let (_, carol_message) = Round1::new(thread_rng(), params, 3, b"test").unwrap();
+        let (_, shares) = alice.receive(&[bob_message, carol_message]).unwrap();
+        let decoded = SecretShare::try_from(shares[0].to_bytes().as_ref()).unwrap();
+        assert_eq!(decoded.to_bytes(), shares[0].to_bytes());
+
+        let key_share = run(params).remove(1);
+        let decoded = KeyShare::try_from(key_share.to_bytes().as_slice()).unwrap();
+        assert_eq!(decoded.to_bytes(), key_share.to_bytes());
+        assert_eq!(decoded.index(), 2);
+    }
+}
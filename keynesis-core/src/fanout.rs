@@ -0,0 +1,358 @@
+/*!
+# Encryption of a message for all the devices of a user
+
+A user has one key per device (see the `Device` purpose of
+`keynesis-derive`). Instead of encrypting the message for each of the
+devices, [`Devices::seal`] encrypts the message once with a random
+content key and seals the content key to every device with a one-way
+[Noise **N**](crate::noise::N) handshake: each device gets a small
+fixed size [`Envelope`].
+
+```
+use keynesis_core::{fanout::Devices, key::ed25519::SecretKey};
+# use rand::thread_rng;
+
+let laptop = SecretKey::new(thread_rng());
+let phone = SecretKey::new(thread_rng());
+
+let mut devices = Devices::new();
+devices.add(laptop.public_key());
+devices.add(phone.public_key());
+
+let sealed = devices.seal::<SecretKey, _>(thread_rng(), b"hello from the other side");
+assert_eq!(sealed.open(&phone).unwrap(), b"hello from the other side");
+
+// the revoked devices do not receive the next messages
+devices.revoke(&phone.public_key());
+let sealed = devices.seal::<SecretKey, _>(thread_rng(), b"bye");
+assert!(sealed.open(&phone).is_err());
+assert_eq!(sealed.open(&laptop).unwrap(), b"bye");
+```
+
+Each message is sealed to the devices of the set at the time of the
+call: the devices added or revoked in between receive (or stop
+receiving) the following messages only. It is up to the application to
+keep the set up to date with the devices of the user.
+
+The devices' keys are expected to be the same [`Dh`] type (`K`) on the
+sending and the receiving sides.
+*/
+
+use crate::{
+    hash::Blake2b,
+    key::{ed25519::PublicKey, Dh},
+    memsec::Scrubbed as _,
+    noise::{HandshakeStateError, N},
+};
+use cryptoxide::{blake2b, chacha20poly1305::ChaCha20Poly1305};
+use rand_core::{CryptoRng, RngCore};
+use std::{collections::BTreeSet, convert::TryFrom};
+use thiserror::Error;
+
+const CONTENT_KEY_SIZE: usize = 32;
+const TAG_SIZE: usize = 16;
+/// the content key is used only once
+const NONCE: [u8; 12] = [0; 12];
+const CONTEXT: &[u8] = b"keynesis:fanout";
+
+/// the set of the active devices of a user
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Devices {
+    keys: BTreeSet<PublicKey>,
+}
+
+/// a message encrypted once with the content key sealed for each of the
+/// recipients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sealed {
+    ciphertext: Vec<u8>,
+    envelopes: Vec<Envelope>,
+}
+
+/// the content key of a [`Sealed`] message sealed for one of the devices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    device: PublicKey,
+    sealed_key: [u8; Self::SEALED_KEY_SIZE],
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum FanOutError {
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+
+    #[error("The message was not sealed for this device")]
+    NotARecipient,
+
+    #[error("Cannot open the envelope of the device")]
+    InvalidEnvelope(#[source] HandshakeStateError),
+
+    #[error("Cannot decrypt the message")]
+    InvalidCiphertext,
+}
+
+impl Devices {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add a device, returns `false` if it was already active
+    pub fn add(&mut self, device: PublicKey) -> bool {
+        self.keys.insert(device)
+    }
+
+    /// revoke a device, returns `false` if it was not active
+    pub fn revoke(&mut self, device: &PublicKey) -> bool {
+        self.keys.remove(device)
+    }
+
+    pub fn contains(&self, device: &PublicKey) -> bool {
+        self.keys.contains(device)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PublicKey> {
+        self.keys.iter()
+    }
+
+    /// encrypt the `message` once and seal the content key for each of
+    /// the active devices
+    ///
+    /// the envelopes are sealed with ephemeral keys of type `K`, the
+    /// type of the devices' keys.
+    pub fn seal<K, RNG>(&self, mut rng: RNG, message: &[u8]) -> Sealed
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        let mut content_key = [0; CONTENT_KEY_SIZE];
+        rng.fill_bytes(&mut content_key);
+
+        let mut ciphertext = vec![0; message.len() + TAG_SIZE];
+        let (encrypted, tag) = ciphertext.split_at_mut(message.len());
+        ChaCha20Poly1305::new(&content_key, &NONCE, CONTEXT).encrypt(message, encrypted, tag);
+
+        let prologue = prologue(&ciphertext);
+        let envelopes = self
+            .keys
+            .iter()
+            .map(|device| {
+                let mut sealed_key = [0; Envelope::SEALED_KEY_SIZE];
+                N::<K, Blake2b, _>::new(&mut rng, &None, &prologue)
+                    .send(device, content_key, sealed_key.as_mut())
+                    .expect("the envelope has the size of the sealed key");
+                Envelope {
+                    device: *device,
+                    sealed_key,
+                }
+            })
+            .collect();
+
+        content_key.scrub();
+
+        Sealed {
+            ciphertext,
+            envelopes,
+        }
+    }
+}
+
+/// bind the envelopes to the ciphertext so they cannot be moved to
+/// another message
+fn prologue(ciphertext: &[u8]) -> [u8; 32] {
+    let mut prologue = [0; 32];
+    blake2b::Blake2b::blake2b(&mut prologue, ciphertext, CONTEXT);
+    prologue
+}
+
+impl Sealed {
+    pub fn envelopes(&self) -> &[Envelope] {
+        &self.envelopes
+    }
+
+    /// the recipients of the message
+    pub fn devices(&self) -> impl Iterator<Item = &PublicKey> {
+        self.envelopes.iter().map(|envelope| &envelope.device)
+    }
+
+    /// open the envelope of the device `k` and decrypt the message
+    pub fn open<K>(&self, k: &K) -> Result<Vec<u8>, FanOutError>
+    where
+        K: Dh,
+    {
+        let device = k.public();
+        let envelope = self
+            .envelopes
+            .iter()
+            .find(|envelope| envelope.device == device)
+            .ok_or(FanOutError::NotARecipient)?;
+
+        // the receiver does not generate any key, it has no RNG
+        let mut content_key = N::<K, Blake2b, ()>::new((), &None, &prologue(&self.ciphertext))
+            .receive(k, &envelope.sealed_key)
+            .map_err(FanOutError::InvalidEnvelope)?;
+        if content_key.len() != CONTENT_KEY_SIZE {
+            content_key.scrub();
+            return Err(FanOutError::InvalidCiphertext);
+        }
+
+        let (ciphertext, tag) = self.ciphertext.split_at(self.ciphertext.len() - TAG_SIZE);
+        let mut message = vec![0; ciphertext.len()];
+        let valid = ChaCha20Poly1305::new(&content_key, &NONCE, CONTEXT).decrypt(
+            ciphertext,
+            &mut message,
+            tag,
+        );
+        content_key.scrub();
+
+        if valid {
+            Ok(message)
+        } else {
+            Err(FanOutError::InvalidCiphertext)
+        }
+    }
+
+    /// encode the message: the size of the ciphertext (u32, big endian),
+    /// the ciphertext, and the envelopes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(4 + self.ciphertext.len() + self.envelopes.len() * Envelope::SIZE);
+        bytes.extend_from_slice(&(self.ciphertext.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.ciphertext);
+        for envelope in &self.envelopes {
+            bytes.extend_from_slice(envelope.device.as_ref());
+            bytes.extend_from_slice(&envelope.sealed_key);
+        }
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Sealed {
+    type Error = FanOutError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < 4 {
+            return Err(FanOutError::InvalidEncoding);
+        }
+        let (len, bytes) = bytes.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if len < TAG_SIZE
+            || bytes.len() < len
+            || !(bytes.len() - len).is_multiple_of(Envelope::SIZE)
+        {
+            return Err(FanOutError::InvalidEncoding);
+        }
+
+        let (ciphertext, bytes) = bytes.split_at(len);
+        let envelopes = bytes
+            .chunks(Envelope::SIZE)
+            .map(|envelope| {
+                let (device, sealed_key) = envelope.split_at(PublicKey::SIZE);
+                Ok(Envelope {
+                    device: PublicKey::try_from(device)
+                        .map_err(|_| FanOutError::InvalidEncoding)?,
+                    sealed_key: <[u8; Envelope::SEALED_KEY_SIZE]>::try_from(sealed_key)
+                        .map_err(|_| FanOutError::InvalidEncoding)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            ciphertext: ciphertext.to_vec(),
+            envelopes,
+        })
+    }
+}
+
+impl Envelope {
+    /// the ephemeral key, the encrypted content key and its tag
+    const SEALED_KEY_SIZE: usize = PublicKey::SIZE + CONTENT_KEY_SIZE + TAG_SIZE;
+    /// size of an encoded envelope: the device and the sealed key
+    pub const SIZE: usize = PublicKey::SIZE + Self::SEALED_KEY_SIZE;
+
+    pub fn device(&self) -> &PublicKey {
+        &self.device
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::{curve25519, ed25519};
+    use rand::thread_rng;
+
+    #[test]
+    fn devices_added_and_revoked() {
+        let keys = (0..3)
+            .map(|_| curve25519::SecretKey::new(thread_rng()))
+            .collect::<Vec<_>>();
+
+        let mut devices = Devices::new();
+        assert!(devices.add(keys[0].public_key()));
+        assert!(devices.add(keys[1].public_key()));
+        assert!(!devices.add(keys[1].public_key()));
+
+        let first = devices.seal::<curve25519::SecretKey, _>(thread_rng(), b"first");
+
+        assert!(devices.revoke(&keys[0].public_key()));
+        assert!(!devices.revoke(&keys[0].public_key()));
+        devices.add(keys[2].public_key());
+        let second = devices.seal::<curve25519::SecretKey, _>(thread_rng(), b"second");
+
+        assert_eq!(first.open(&keys[0]).unwrap(), b"first");
+        assert_eq!(first.open(&keys[1]).unwrap(), b"first");
+        assert!(matches!(
+            first.open(&keys[2]),
+            Err(FanOutError::NotARecipient)
+        ));
+
+        assert!(matches!(
+            second.open(&keys[0]),
+            Err(FanOutError::NotARecipient)
+        ));
+        assert_eq!(second.open(&keys[1]).unwrap(), b"second");
+        assert_eq!(second.open(&keys[2]).unwrap(), b"second");
+    }
+
+    #[test]
+    fn encoding() {
+        let key = ed25519::SecretKey::new(thread_rng());
+        let mut devices = Devices::new();
+        devices.add(key.public_key());
+        devices.add(ed25519::SecretKey::new(thread_rng()).public_key());
+
+        let sealed = devices.seal::<ed25519::SecretKey, _>(thread_rng(), b"message");
+        let bytes = sealed.to_bytes();
+        assert_eq!(bytes.len(), 4 + 7 + TAG_SIZE + 2 * Envelope::SIZE);
+
+        let decoded = Sealed::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(decoded, sealed);
+        assert_eq!(decoded.open(&key).unwrap(), b"message");
+
+        assert!(Sealed::try_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn envelopes_are_bound_to_the_message() {
+        let key = ed25519::SecretKey::new(thread_rng());
+        let mut devices = Devices::new();
+        devices.add(key.public_key());
+
+        let first = devices.seal::<ed25519::SecretKey, _>(thread_rng(), b"first");
+        let mut second = devices.seal::<ed25519::SecretKey, _>(thread_rng(), b"second");
+        second.envelopes = first.envelopes.clone();
+
+        assert!(matches!(
+            second.open(&key),
+            Err(FanOutError::InvalidEnvelope(_))
+        ));
+    }
+}
@@ -0,0 +1,433 @@
+/*!
+# BLAKE3
+
+Portable implementation of the [BLAKE3] hash function with the default
+32 bytes output. It processes the input in chunks of 1KiB organised in a
+binary tree and uses 7 rounds of the compression function (instead of
+the 10 rounds of BLAKE2s) so it is faster than the BLAKE2 hashes for the
+high message rate transports.
+
+```
+use keynesis_core::hash::{Blake3, Digest as _};
+
+let mut hasher = Blake3::new();
+hasher.input(b"abc");
+
+let mut hash = [0; 32];
+hasher.result(&mut hash);
+assert_eq!(
+    hex::encode(hash),
+    "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+);
+```
+
+[BLAKE3]: https://github.com/BLAKE3-team/BLAKE3-specs
+*/
+
+use crate::memsec::Scrubbed;
+use cryptoxide::digest::Digest;
+
+const OUT_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+/// enough for the 2^64 bytes of input
+const MAX_DEPTH: usize = 54;
+
+const CHUNK_START: u32 = 1 << 0;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+
+const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+/// the BLAKE3 hasher, see the [module](self) documentation
+#[derive(Clone)]
+pub struct Blake3 {
+    chunk: ChunkState,
+    cv_stack: [[u32; 8]; MAX_DEPTH],
+    cv_stack_len: usize,
+}
+
+#[derive(Clone)]
+struct ChunkState {
+    cv: [u32; 8],
+    chunk_counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    blocks_compressed: usize,
+}
+
+/// the last compression of a chunk or of a parent node, kept until we
+/// know if it is the root node
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Blake3 {
+    pub fn new() -> Self {
+        Self {
+            chunk: ChunkState::new(0),
+            cv_stack: [[0; 8]; MAX_DEPTH],
+            cv_stack_len: 0,
+        }
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            if self.chunk.len() == CHUNK_LEN {
+                let cv = self.chunk.output().chaining_value();
+                let total_chunks = self.chunk.chunk_counter + 1;
+                self.push_chunk(cv, total_chunks);
+                self.chunk = ChunkState::new(total_chunks);
+            }
+
+            let take = usize::min(CHUNK_LEN - self.chunk.len(), input.len());
+            self.chunk.update(&input[..take]);
+            input = &input[take..];
+        }
+    }
+
+    /// merge the completed subtrees: there is one for every bit set in
+    /// the number of chunks
+    fn push_chunk(&mut self, mut cv: [u32; 8], mut total_chunks: u64) {
+        while total_chunks & 1 == 0 {
+            self.cv_stack_len -= 1;
+            cv = parent(&self.cv_stack[self.cv_stack_len], &cv).chaining_value();
+            total_chunks >>= 1;
+        }
+        self.cv_stack[self.cv_stack_len] = cv;
+        self.cv_stack_len += 1;
+    }
+
+    fn finalize(&self, out: &mut [u8]) {
+        let output = self.cv_stack[..self.cv_stack_len]
+            .iter()
+            .rev()
+            .fold(self.chunk.output(), |output, left| {
+                parent(left, &output.chaining_value())
+            });
+        output.root_bytes(out);
+    }
+}
+
+impl ChunkState {
+    fn new(chunk_counter: u64) -> Self {
+        Self {
+            cv: IV,
+            chunk_counter,
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            blocks_compressed: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        BLOCK_LEN * self.blocks_compressed + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks_compressed == 0 {
+            CHUNK_START
+        } else {
+            0
+        }
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // the last block is only compressed once we know it is not
+            // the last one of the chunk
+            if self.block_len == BLOCK_LEN {
+                let block = words(&self.block);
+                let state = compress(
+                    &self.cv,
+                    &block,
+                    self.chunk_counter,
+                    BLOCK_LEN as u32,
+                    self.start_flag(),
+                );
+                self.cv.copy_from_slice(&state[..8]);
+                self.blocks_compressed += 1;
+                self.block = [0; BLOCK_LEN];
+                self.block_len = 0;
+            }
+
+            let take = usize::min(BLOCK_LEN - self.block_len, input.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&input[..take]);
+            self.block_len += take;
+            input = &input[take..];
+        }
+    }
+
+    fn output(&self) -> Output {
+        Output {
+            cv: self.cv,
+            block: words(&self.block),
+            counter: self.chunk_counter,
+            block_len: self.block_len as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+
+    fn scrub(&mut self) {
+        self.cv.iter_mut().for_each(Scrubbed::scrub);
+        self.block.scrub();
+    }
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        let state = compress(
+            &self.cv,
+            &self.block,
+            self.counter,
+            self.block_len,
+            self.flags,
+        );
+        let mut cv = [0; 8];
+        cv.copy_from_slice(&state[..8]);
+        cv
+    }
+
+    fn root_bytes(&self, out: &mut [u8]) {
+        for (counter, out) in out.chunks_mut(2 * OUT_LEN).enumerate() {
+            let state = compress(
+                &self.cv,
+                &self.block,
+                counter as u64,
+                self.block_len,
+                self.flags | ROOT,
+            );
+            for (word, out) in state.iter().zip(out.chunks_mut(4)) {
+                out.copy_from_slice(&word.to_le_bytes()[..out.len()]);
+            }
+        }
+    }
+}
+
+fn parent(left: &[u32; 8], right: &[u32; 8]) -> Output {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(left);
+    block[8..].copy_from_slice(right);
+    Output {
+        cv: IV,
+        block,
+        counter: 0,
+        block_len: BLOCK_LEN as u32,
+        flags: PARENT,
+    }
+}
+
+fn words(block: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    words
+}
+
+#[inline(always)]
+fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+    // the columns
+    g(state, 0, 4, 8, 12, m[0], m[1]);
+    g(state, 1, 5, 9, 13, m[2], m[3]);
+    g(state, 2, 6, 10, 14, m[4], m[5]);
+    g(state, 3, 7, 11, 15, m[6], m[7]);
+    // the diagonals
+    g(state, 0, 5, 10, 15, m[8], m[9]);
+    g(state, 1, 6, 11, 12, m[10], m[11]);
+    g(state, 2, 7, 8, 13, m[12], m[13]);
+    g(state, 3, 4, 9, 14, m[14], m[15]);
+}
+
+fn compress(
+    cv: &[u32; 8],
+    block: &[u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+) -> [u32; 16] {
+    let mut state = [
+        cv[0],
+        cv[1],
+        cv[2],
+        cv[3],
+        cv[4],
+        cv[5],
+        cv[6],
+        cv[7],
+        IV[0],
+        IV[1],
+        IV[2],
+        IV[3],
+        counter as u32,
+        (counter >> 32) as u32,
+        block_len,
+        flags,
+    ];
+
+    let mut m = *block;
+    for i in 0..7 {
+        round(&mut state, &m);
+        if i < 6 {
+            let mut permuted = [0; 16];
+            for (permuted, index) in permuted.iter_mut().zip(MSG_PERMUTATION.iter()) {
+                *permuted = m[*index];
+            }
+            m = permuted;
+        }
+    }
+    m.iter_mut().for_each(Scrubbed::scrub);
+
+    for i in 0..8 {
+        state[i] ^= state[i + 8];
+        state[i + 8] ^= cv[i];
+    }
+    state
+}
+
+/* Digest ****************************************************************** */
+
+impl Digest for Blake3 {
+    fn input(&mut self, input: &[u8]) {
+        self.update(input)
+    }
+
+    fn result(&mut self, out: &mut [u8]) {
+        self.finalize(&mut out[..OUT_LEN])
+    }
+
+    fn reset(&mut self) {
+        self.scrub();
+    }
+
+    fn output_bits(&self) -> usize {
+        OUT_LEN * 8
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_LEN
+    }
+}
+
+impl Default for Blake3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/* Drop ******************************************************************** */
+
+impl Scrubbed for Blake3 {
+    fn scrub(&mut self) {
+        self.chunk.scrub();
+        self.cv_stack.iter_mut().flatten().for_each(Scrubbed::scrub);
+        self.chunk = ChunkState::new(0);
+        self.cv_stack_len = 0;
+    }
+}
+
+/// the intermediate chaining values are scrubbed (zeroed), they are
+/// derived from the secrets of the noise handshakes
+impl Drop for Blake3 {
+    fn drop(&mut self) {
+        self.scrub()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(input: &[u8]) -> String {
+        let mut hasher = Blake3::new();
+        hasher.input(input);
+        let mut hash = [0; OUT_LEN];
+        hasher.result(&mut hash);
+        hex::encode(hash)
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!(
+            hash(b""),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            hash(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    /// the official test vectors (one, several and incomplete chunks),
+    /// the input is the repeated sequence of bytes from 0 to 250
+    #[test]
+    fn official_vectors() {
+        let vectors = [
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+            (
+                2049,
+                "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030",
+            ),
+            (
+                3072,
+                "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2",
+            ),
+        ];
+
+        for (len, expected) in vectors {
+            let input: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(hash(&input), expected, "input of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut hasher = Blake3::new();
+        hasher.input(b"some data");
+        hasher.reset();
+        hasher.input(b"abc");
+        let mut result = [0; OUT_LEN];
+        hasher.result(&mut result);
+
+        assert_eq!(hex::encode(result), hash(b"abc"));
+    }
+
+    #[quickcheck]
+    fn incremental(input: Vec<u8>, split: usize) -> bool {
+        let split = split.checked_rem(input.len()).unwrap_or_default();
+        let mut hasher = Blake3::new();
+        hasher.input(&input[..split]);
+        hasher.input(&input[split..]);
+        let mut incremental = [0; OUT_LEN];
+        hasher.result(&mut incremental);
+
+        hex::encode(incremental) == hash(&input)
+    }
+}
@@ -1,5 +1,12 @@
+mod blake3;
+
+pub use self::blake3::Blake3;
 pub use cryptoxide::digest::Digest;
-pub use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
+pub use cryptoxide::{
+    blake2b::Blake2b,
+    blake2s::Blake2s,
+    sha2::{Sha256, Sha512},
+};
 
 pub trait Hash {
     const HASH_LEN: usize;
@@ -94,3 +101,111 @@ impl Hash for Blake2s {
         Digest::result(self, output.as_mut());
     }
 }
+
+impl Hash for Sha256 {
+    const HASH_LEN: usize = 32;
+    const BLOCK_LEN: usize = 64;
+
+    type HASH = [u8; 32];
+    type BLOCK = [u8; 64];
+
+    fn name() -> &'static str {
+        "SHA256"
+    }
+
+    fn zero_hash() -> Self::HASH {
+        [0; Self::HASH_LEN]
+    }
+
+    fn zero_block() -> Self::BLOCK {
+        [0; Self::BLOCK_LEN]
+    }
+
+    fn hasher() -> Self {
+        Sha256::new()
+    }
+
+    fn reset(&mut self) {
+        Digest::reset(self)
+    }
+
+    fn input(&mut self, data: impl AsRef<[u8]>) {
+        Digest::input(self, data.as_ref())
+    }
+
+    fn result(&mut self, output: &mut Self::HASH) {
+        Digest::result(self, output.as_mut());
+    }
+}
+
+impl Hash for Sha512 {
+    const HASH_LEN: usize = 64;
+    const BLOCK_LEN: usize = 128;
+
+    type HASH = [u8; 64];
+    type BLOCK = [u8; 128];
+
+    fn name() -> &'static str {
+        "SHA512"
+    }
+
+    fn zero_hash() -> Self::HASH {
+        [0; Self::HASH_LEN]
+    }
+
+    fn zero_block() -> Self::BLOCK {
+        [0; Self::BLOCK_LEN]
+    }
+
+    fn hasher() -> Self {
+        Sha512::new()
+    }
+
+    fn reset(&mut self) {
+        Digest::reset(self)
+    }
+
+    fn input(&mut self, data: impl AsRef<[u8]>) {
+        Digest::input(self, data.as_ref())
+    }
+
+    fn result(&mut self, output: &mut Self::HASH) {
+        Digest::result(self, output.as_mut());
+    }
+}
+
+impl Hash for Blake3 {
+    const HASH_LEN: usize = 32;
+    const BLOCK_LEN: usize = 64;
+
+    type HASH = [u8; 32];
+    type BLOCK = [u8; 64];
+
+    fn name() -> &'static str {
+        "BLAKE3"
+    }
+
+    fn zero_hash() -> Self::HASH {
+        [0; Self::HASH_LEN]
+    }
+
+    fn zero_block() -> Self::BLOCK {
+        [0; Self::BLOCK_LEN]
+    }
+
+    fn hasher() -> Self {
+        Blake3::new()
+    }
+
+    fn reset(&mut self) {
+        Digest::reset(self)
+    }
+
+    fn input(&mut self, data: impl AsRef<[u8]>) {
+        Digest::input(self, data.as_ref())
+    }
+
+    fn result(&mut self, output: &mut Self::HASH) {
+        Digest::result(self, output.as_mut());
+    }
+}
@@ -0,0 +1,527 @@
+/*!
+Argon2id memory hard password hashing as described in [RFC9106]
+
+Only the `id` variant and the version `0x13` are supported.
+
+[RFC9106]: https://www.rfc-editor.org/rfc/rfc9106.html
+*/
+
+use crate::{
+    kdf::KdfError,
+    memsec::{self, Scrubbed},
+};
+use cryptoxide::{blake2b::Blake2b, digest::Digest};
+
+const VERSION: u32 = 0x13;
+const ARGON2ID: u32 = 2;
+const SYNC_POINTS: u32 = 4;
+const BLOCK_WORDS: usize = 128;
+const BLOCK_SIZE: usize = BLOCK_WORDS * 8;
+const ADDRESSES_IN_BLOCK: u32 = BLOCK_WORDS as u32;
+
+/// parameters of the Argon2id function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Argon2Params {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Block([u64; BLOCK_WORDS]);
+
+/* Parameters ************************************************************** */
+
+impl Argon2Params {
+    /// recommended parameters for interactive logins, 19MiB of memory,
+    /// 2 passes and a single lane
+    pub const INTERACTIVE: Self = Self {
+        m_cost: 19 * 1024,
+        t_cost: 2,
+        p_cost: 1,
+    };
+
+    /// second recommended option of the [RFC9106] for when the memory is
+    /// constrained: 64MiB of memory, 3 passes and 4 lanes. This is a
+    /// good default to encrypt key files.
+    ///
+    /// [RFC9106]: https://www.rfc-editor.org/rfc/rfc9106.html#section-4
+    pub const MODERATE: Self = Self {
+        m_cost: 64 * 1024,
+        t_cost: 3,
+        p_cost: 4,
+    };
+
+    /// first recommended option of the [RFC9106]: 2GiB of memory,
+    /// a single pass and 4 lanes
+    ///
+    /// [RFC9106]: https://www.rfc-editor.org/rfc/rfc9106.html#section-4
+    pub const SENSITIVE: Self = Self {
+        m_cost: 2 * 1024 * 1024,
+        t_cost: 1,
+        p_cost: 4,
+    };
+
+    /// create new parameters
+    ///
+    /// * `m_cost` is the memory size in KiB, it needs to be at least
+    ///   8 times the number of lanes;
+    /// * `t_cost` is the number of passes, at least 1;
+    /// * `p_cost` is the number of lanes, between 1 and 2^24-1.
+    pub fn new(m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Self, KdfError> {
+        if p_cost == 0 || p_cost > 0x00FF_FFFF {
+            return Err(KdfError::InvalidParameters("invalid number of lanes"));
+        }
+        if t_cost == 0 {
+            return Err(KdfError::InvalidParameters("invalid number of passes"));
+        }
+        if u64::from(m_cost) < 8 * u64::from(p_cost) {
+            return Err(KdfError::InvalidParameters("not enough memory"));
+        }
+
+        Ok(Self {
+            m_cost,
+            t_cost,
+            p_cost,
+        })
+    }
+
+    /// memory size in KiB
+    pub fn m_cost(&self) -> u32 {
+        self.m_cost
+    }
+
+    /// number of passes
+    pub fn t_cost(&self) -> u32 {
+        self.t_cost
+    }
+
+    /// number of lanes
+    pub fn p_cost(&self) -> u32 {
+        self.p_cost
+    }
+}
+
+/// compute the Argon2id of the given `password` and `salt` and write
+/// the result in `output`
+///
+/// the output needs to be at least 4 bytes long and the salt should be
+/// at least 16 bytes of random data.
+pub fn argon2id(
+    params: &Argon2Params,
+    password: &[u8],
+    salt: &[u8],
+    output: &mut [u8],
+) -> Result<(), KdfError> {
+    argon2id_with(params, password, salt, &[], &[], output)
+}
+
+/// full function with the optional secret and associated data
+pub(crate) fn argon2id_with(
+    params: &Argon2Params,
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    ad: &[u8],
+    output: &mut [u8],
+) -> Result<(), KdfError> {
+    if output.len() < 4 {
+        return Err(KdfError::InvalidParameters("output too short"));
+    }
+    if salt.len() < 8 {
+        return Err(KdfError::InvalidParameters("salt too short"));
+    }
+
+    let lanes = params.p_cost;
+    let segment_length = params.m_cost / (lanes * SYNC_POINTS);
+    let lane_length = segment_length * SYNC_POINTS;
+
+    let mut h0 = [0; 64 + 8];
+    let mut hasher = Blake2b::new(64);
+    for v in [
+        lanes,
+        output.len() as u32,
+        params.m_cost,
+        params.t_cost,
+        VERSION,
+        ARGON2ID,
+    ] {
+        hasher.input(&v.to_le_bytes());
+    }
+    for data in [password, salt, secret, ad] {
+        hasher.input(&(data.len() as u32).to_le_bytes());
+        hasher.input(data);
+    }
+    hasher.result(&mut h0[..64]);
+
+    let mut memory = vec![Block::zero(); (lane_length * lanes) as usize];
+    let mut bytes = [0; BLOCK_SIZE];
+    for lane in 0..lanes {
+        for i in 0..2u32 {
+            h0[64..68].copy_from_slice(&i.to_le_bytes());
+            h0[68..72].copy_from_slice(&lane.to_le_bytes());
+            h_prime(&mut bytes, &h0);
+            memory[(lane * lane_length + i) as usize] = Block::from_bytes(&bytes);
+        }
+    }
+    h0.scrub();
+
+    let filler = Filler {
+        lanes,
+        lane_length,
+        segment_length,
+        passes: params.t_cost,
+        total_blocks: u64::from(lane_length * lanes),
+    };
+    for pass in 0..params.t_cost {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                filler.fill_segment(&mut memory, pass, lane, slice);
+            }
+        }
+    }
+
+    let mut last = memory[(lane_length - 1) as usize];
+    for lane in 1..lanes {
+        last ^= &memory[(lane * lane_length + lane_length - 1) as usize];
+    }
+    last.to_bytes(&mut bytes);
+    h_prime(output, &bytes);
+
+    bytes.scrub();
+    memory.iter_mut().for_each(Block::scrub);
+    last.scrub();
+
+    Ok(())
+}
+
+/// variable length hash function
+fn h_prime(output: &mut [u8], input: &[u8]) {
+    let len = (output.len() as u32).to_le_bytes();
+
+    if output.len() <= 64 {
+        let mut hasher = Blake2b::new(output.len());
+        hasher.input(&len);
+        hasher.input(input);
+        hasher.result(output);
+        return;
+    }
+
+    let mut v = [0; 64];
+    let mut hasher = Blake2b::new(64);
+    hasher.input(&len);
+    hasher.input(input);
+    hasher.result(&mut v);
+    output[..32].copy_from_slice(&v[..32]);
+
+    let mut position = 32;
+    while output.len() - position > 64 {
+        let mut hasher = Blake2b::new(64);
+        hasher.input(&v);
+        hasher.result(&mut v);
+        output[position..position + 32].copy_from_slice(&v[..32]);
+        position += 32;
+    }
+
+    let remaining = output.len() - position;
+    let mut hasher = Blake2b::new(remaining);
+    hasher.input(&v);
+    hasher.result(&mut output[position..]);
+
+    v.scrub();
+}
+
+/* Memory filling ********************************************************** */
+
+struct Filler {
+    lanes: u32,
+    lane_length: u32,
+    segment_length: u32,
+    passes: u32,
+    total_blocks: u64,
+}
+
+impl Filler {
+    fn fill_segment(&self, memory: &mut [Block], pass: u32, lane: u32, slice: u32) {
+        let data_independent = pass == 0 && slice < SYNC_POINTS / 2;
+
+        let mut input_block = Block::zero();
+        let mut address_block = Block::zero();
+        if data_independent {
+            input_block.0[0] = u64::from(pass);
+            input_block.0[1] = u64::from(lane);
+            input_block.0[2] = u64::from(slice);
+            input_block.0[3] = self.total_blocks;
+            input_block.0[4] = u64::from(self.passes);
+            input_block.0[5] = u64::from(ARGON2ID);
+        }
+
+        let starting_index = if pass == 0 && slice == 0 {
+            if data_independent {
+                next_addresses(&mut address_block, &mut input_block);
+            }
+            2
+        } else {
+            0
+        };
+
+        let lane_start = lane * self.lane_length;
+        for index in starting_index..self.segment_length {
+            let current = lane_start + slice * self.segment_length + index;
+            // the first block of the lane follows the last one of the lane
+            let previous = if current == lane_start {
+                lane_start + self.lane_length - 1
+            } else {
+                current - 1
+            };
+
+            let pseudo_rand = if data_independent {
+                if index % ADDRESSES_IN_BLOCK == 0 {
+                    next_addresses(&mut address_block, &mut input_block);
+                }
+                address_block.0[(index % ADDRESSES_IN_BLOCK) as usize]
+            } else {
+                memory[previous as usize].0[0]
+            };
+
+            let ref_lane = if pass == 0 && slice == 0 {
+                lane
+            } else {
+                ((pseudo_rand >> 32) % u64::from(self.lanes)) as u32
+            };
+            let ref_index =
+                self.index_alpha(pass, slice, index, pseudo_rand as u32, ref_lane == lane);
+
+            let reference = memory[(ref_lane * self.lane_length + ref_index) as usize];
+            let mut block = memory[previous as usize];
+            block.compress(&reference);
+            if pass == 0 {
+                memory[current as usize] = block;
+            } else {
+                memory[current as usize] ^= &block;
+            }
+        }
+    }
+
+    fn index_alpha(
+        &self,
+        pass: u32,
+        slice: u32,
+        index: u32,
+        pseudo_rand: u32,
+        same_lane: bool,
+    ) -> u32 {
+        let reference_area_size = if pass == 0 {
+            if slice == 0 {
+                index - 1
+            } else if same_lane {
+                slice * self.segment_length + index - 1
+            } else if index == 0 {
+                slice * self.segment_length - 1
+            } else {
+                slice * self.segment_length
+            }
+        } else if same_lane {
+            self.lane_length - self.segment_length + index - 1
+        } else if index == 0 {
+            self.lane_length - self.segment_length - 1
+        } else {
+            self.lane_length - self.segment_length
+        };
+        let reference_area_size = u64::from(reference_area_size);
+
+        let relative_position = u64::from(pseudo_rand);
+        let relative_position = (relative_position * relative_position) >> 32;
+        let relative_position =
+            reference_area_size - 1 - ((reference_area_size * relative_position) >> 32);
+
+        let start_position = if pass != 0 && slice != SYNC_POINTS - 1 {
+            (slice + 1) * self.segment_length
+        } else {
+            0
+        };
+
+        ((u64::from(start_position) + relative_position) % u64::from(self.lane_length)) as u32
+    }
+}
+
+fn next_addresses(address_block: &mut Block, input_block: &mut Block) {
+    input_block.0[6] += 1;
+    *address_block = Block::zero();
+    address_block.compress(input_block);
+    let first = *address_block;
+    *address_block = Block::zero();
+    address_block.compress(&first);
+}
+
+/* Block ******************************************************************* */
+
+impl Block {
+    const fn zero() -> Self {
+        Self([0; BLOCK_WORDS])
+    }
+
+    fn from_bytes(bytes: &[u8; BLOCK_SIZE]) -> Self {
+        let mut block = Self::zero();
+        for (word, chunk) in block.0.iter_mut().zip(bytes.chunks_exact(8)) {
+            let mut b = [0; 8];
+            b.copy_from_slice(chunk);
+            *word = u64::from_le_bytes(b);
+        }
+        block
+    }
+
+    fn to_bytes(self, bytes: &mut [u8; BLOCK_SIZE]) {
+        for (word, chunk) in self.0.iter().zip(bytes.chunks_exact_mut(8)) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    /// the compression function `G`, `self` becomes `G(self, other)`
+    fn compress(&mut self, other: &Self) {
+        let mut r = *self;
+        r ^= other;
+        let mut q = r;
+
+        for row in 0..8 {
+            let i = row * 16;
+            permute(
+                &mut q.0,
+                [
+                    i,
+                    i + 1,
+                    i + 2,
+                    i + 3,
+                    i + 4,
+                    i + 5,
+                    i + 6,
+                    i + 7,
+                    i + 8,
+                    i + 9,
+                    i + 10,
+                    i + 11,
+                    i + 12,
+                    i + 13,
+                    i + 14,
+                    i + 15,
+                ],
+            );
+        }
+        for column in 0..8 {
+            let i = column * 2;
+            permute(
+                &mut q.0,
+                [
+                    i,
+                    i + 1,
+                    i + 16,
+                    i + 17,
+                    i + 32,
+                    i + 33,
+                    i + 48,
+                    i + 49,
+                    i + 64,
+                    i + 65,
+                    i + 80,
+                    i + 81,
+                    i + 96,
+                    i + 97,
+                    i + 112,
+                    i + 113,
+                ],
+            );
+        }
+
+        q ^= &r;
+        *self = q;
+    }
+}
+
+impl Scrubbed for Block {
+    fn scrub(&mut self) {
+        unsafe { memsec::memset(self.0.as_mut_ptr() as *mut u8, 0, BLOCK_SIZE) }
+    }
+}
+
+impl std::ops::BitXorAssign<&Block> for Block {
+    fn bitxor_assign(&mut self, rhs: &Block) {
+        for (a, b) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *a ^= b;
+        }
+    }
+}
+
+/// the permutation `P` applied on the 16 words at the given indices
+fn permute(v: &mut [u64; BLOCK_WORDS], i: [usize; 16]) {
+    gb(v, i[0], i[4], i[8], i[12]);
+    gb(v, i[1], i[5], i[9], i[13]);
+    gb(v, i[2], i[6], i[10], i[14]);
+    gb(v, i[3], i[7], i[11], i[15]);
+    gb(v, i[0], i[5], i[10], i[15]);
+    gb(v, i[1], i[6], i[11], i[12]);
+    gb(v, i[2], i[7], i[8], i[13]);
+    gb(v, i[3], i[4], i[9], i[14]);
+}
+
+#[inline(always)]
+fn fblamka(x: u64, y: u64) -> u64 {
+    let m = (x & 0xFFFF_FFFF).wrapping_mul(y & 0xFFFF_FFFF);
+    x.wrapping_add(y).wrapping_add(m.wrapping_mul(2))
+}
+
+#[inline(always)]
+fn gb(v: &mut [u64; BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = fblamka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = fblamka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = fblamka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = fblamka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test vector from the RFC9106 section 5.3
+    #[test]
+    fn rfc9106_argon2id() {
+        let params = Argon2Params::new(32, 3, 4).unwrap();
+        let mut output = [0; 32];
+        argon2id_with(
+            &params,
+            &[0x01; 32],
+            &[0x02; 16],
+            &[0x03; 8],
+            &[0x04; 12],
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            hex::encode(output),
+            "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"
+        );
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(Argon2Params::new(8, 1, 0).is_err());
+        assert!(Argon2Params::new(8, 0, 1).is_err());
+        assert!(Argon2Params::new(15, 1, 2).is_err());
+        assert!(Argon2Params::new(16, 1, 2).is_ok());
+    }
+
+    #[quickcheck]
+    fn long_output_is_deterministic(password: Vec<u8>) -> bool {
+        let params = Argon2Params::new(16, 1, 2).unwrap();
+        let mut output1 = [0; 100];
+        let mut output2 = [0; 100];
+        argon2id(&params, &password, b"some salt", &mut output1).unwrap();
+        argon2id(&params, &password, b"some salt", &mut output2).unwrap();
+
+        output1 == output2
+    }
+}
@@ -0,0 +1,425 @@
+/*!
+# Password hashing and key derivation
+
+Memory hard functions to derive keys from passwords or to store
+password verifiers: [Argon2id] and [scrypt].
+
+The password hashes can be encoded in the [PHC string format] so
+they can be stored and verified later on without having to remember
+the parameters or the salt:
+
+```
+use keynesis_core::kdf::{self, Argon2Params, Kdf};
+# use rand::thread_rng;
+let kdf = Kdf::Argon2id(Argon2Params::INTERACTIVE);
+# // keep the doc test fast
+# let kdf = Kdf::Argon2id(Argon2Params::new(64, 1, 1).unwrap());
+let hash = kdf::hash_password(&mut thread_rng(), &kdf, b"password").unwrap();
+
+assert!(kdf::verify_password(&hash, b"password").unwrap());
+assert!(!kdf::verify_password(&hash, b"not the password").unwrap());
+```
+
+The parameters of a PHC string are picked by whoever created it: the
+verification refuses the parameters more expensive than the
+[`VerifyLimits`] so a crafted string cannot exhaust the memory or the
+CPU of the verifier.
+
+[Argon2id]: https://www.rfc-editor.org/rfc/rfc9106.html
+[scrypt]: https://www.rfc-editor.org/rfc/rfc7914.html
+[PHC string format]: https://github.com/P-H-C/phc-string-format/blob/master/phc-sf-spec.md
+*/
+
+mod argon2;
+pub(crate) mod phc;
+
+pub use self::argon2::{argon2id, Argon2Params};
+use self::phc::PhcString;
+use crate::memsec::{self, Scrubbed as _};
+use rand_core::{CryptoRng, RngCore};
+use thiserror::Error;
+
+/// length of the salt generated by [`hash_password`]
+pub const SALT_LEN: usize = 16;
+
+/// length of the hash generated by [`hash_password`]
+pub const HASH_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum KdfError {
+    #[error("Invalid parameters: {0}")]
+    InvalidParameters(&'static str),
+
+    #[error("Invalid PHC string")]
+    InvalidPhcString,
+
+    #[error("Unsupported algorithm {0:?}")]
+    UnsupportedAlgorithm(String),
+
+    #[error("Unsupported version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("The parameters exceed the verification limits")]
+    LimitsExceeded,
+}
+
+/// the most expensive parameters [`verify_password_with_limits`] accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VerifyLimits {
+    /// maximum memory used by the function, in bytes
+    pub max_memory: u64,
+    /// maximum number of passes over the memory: the `t` of Argon2id and
+    /// the `p` of scrypt
+    pub max_passes: u32,
+}
+
+/// parameters of the scrypt function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScryptParams {
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+/// the password hashing function and its parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kdf {
+    Argon2id(Argon2Params),
+    Scrypt(ScryptParams),
+}
+
+/* Scrypt ****************************************************************** */
+
+impl ScryptParams {
+    /// recommended parameters for interactive logins (`N = 2^15`)
+    pub const INTERACTIVE: Self = Self {
+        log_n: 15,
+        r: 8,
+        p: 1,
+    };
+
+    /// recommended parameters for sensitive data like the encrypted
+    /// key files (`N = 2^20`)
+    pub const SENSITIVE: Self = Self {
+        log_n: 20,
+        r: 8,
+        p: 1,
+    };
+
+    /// create new parameters, `N` is `2^log_n`
+    pub fn new(log_n: u8, r: u32, p: u32) -> Result<Self, KdfError> {
+        if r == 0 || p == 0 {
+            return Err(KdfError::InvalidParameters("r and p cannot be 0"));
+        }
+        if log_n == 0 || u64::from(log_n) >= u64::from(r) * 16 || log_n >= 64 {
+            return Err(KdfError::InvalidParameters("invalid cost parameter"));
+        }
+        let r128 = u64::from(r) * 128;
+        let memory = r128
+            .checked_mul(1 << log_n)
+            .and_then(|memory| usize::try_from(memory).ok());
+        if memory.is_none()
+            || usize::try_from(r128 * u64::from(p)).is_err()
+            || u64::from(r) * u64::from(p) >= 0x4000_0000
+        {
+            return Err(KdfError::InvalidParameters("parameters too large"));
+        }
+
+        Ok(Self { log_n, r, p })
+    }
+
+    pub fn log_n(&self) -> u8 {
+        self.log_n
+    }
+
+    pub fn r(&self) -> u32 {
+        self.r
+    }
+
+    pub fn p(&self) -> u32 {
+        self.p
+    }
+}
+
+/// compute the scrypt of the given `password` and `salt` and write
+/// the result in `output`
+pub fn scrypt(
+    params: &ScryptParams,
+    password: &[u8],
+    salt: &[u8],
+    output: &mut [u8],
+) -> Result<(), KdfError> {
+    if output.is_empty() {
+        return Err(KdfError::InvalidParameters("output too short"));
+    }
+
+    let params = cryptoxide::scrypt::ScryptParams::new(params.log_n, params.r, params.p);
+    cryptoxide::scrypt::scrypt(password, salt, &params, output);
+    Ok(())
+}
+
+/* Limits ****************************************************************** */
+
+impl VerifyLimits {
+    /// the limits of [`verify_password`]: 64MiB of memory and 4 passes,
+    /// enough for the `INTERACTIVE` and [`Argon2Params::MODERATE`]
+    /// parameters
+    pub const DEFAULT: Self = Self {
+        max_memory: 64 * 1024 * 1024,
+        max_passes: 4,
+    };
+
+    /// 2GiB of memory and 4 passes, enough for the `SENSITIVE`
+    /// parameters
+    pub const SENSITIVE: Self = Self {
+        max_memory: 2 * 1024 * 1024 * 1024,
+        max_passes: 4,
+    };
+
+    fn allow(&self, kdf: &Kdf) -> bool {
+        let (memory, passes) = match kdf {
+            Kdf::Argon2id(params) => (u64::from(params.m_cost()) * 1024, params.t_cost()),
+            Kdf::Scrypt(params) => (128 * u64::from(params.r) * (1 << params.log_n), params.p),
+        };
+
+        memory <= self.max_memory && passes <= self.max_passes
+    }
+}
+
+impl Default for VerifyLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/* Kdf ********************************************************************* */
+
+impl Kdf {
+    /// derive `output.len()` bytes from the password and the salt
+    pub fn derive(&self, password: &[u8], salt: &[u8], output: &mut [u8]) -> Result<(), KdfError> {
+        match self {
+            Self::Argon2id(params) => argon2id(params, password, salt, output),
+            Self::Scrypt(params) => scrypt(params, password, salt, output),
+        }
+    }
+
+    fn to_phc_string(self, salt: &[u8], hash: &[u8]) -> String {
+        let params = match self {
+            Self::Argon2id(params) => format!(
+                "argon2id$v=19$m={},t={},p={}",
+                params.m_cost(),
+                params.t_cost(),
+                params.p_cost()
+            ),
+            Self::Scrypt(params) => {
+                format!("scrypt$ln={},r={},p={}", params.log_n, params.r, params.p)
+            }
+        };
+
+        format!("${}${}${}", params, phc::encode(salt), phc::encode(hash))
+    }
+
+    fn from_phc_string(phc: &PhcString<'_>) -> Result<Self, KdfError> {
+        match phc.id {
+            "argon2id" => {
+                match phc.version {
+                    Some(19) => (),
+                    Some(version) => return Err(KdfError::UnsupportedVersion(version)),
+                    None => return Err(KdfError::UnsupportedVersion(16)),
+                }
+                let params = Argon2Params::new(phc.param("m")?, phc.param("t")?, phc.param("p")?)?;
+                Ok(Self::Argon2id(params))
+            }
+            "scrypt" => {
+                let log_n = u8::try_from(phc.param("ln")?)
+                    .map_err(|_| KdfError::InvalidParameters("invalid cost parameter"))?;
+                let params = ScryptParams::new(log_n, phc.param("r")?, phc.param("p")?)?;
+                Ok(Self::Scrypt(params))
+            }
+            id => Err(KdfError::UnsupportedAlgorithm(id.to_owned())),
+        }
+    }
+}
+
+/// hash the password with a random salt and encode the result
+/// in a PHC string
+pub fn hash_password<RNG>(rng: &mut RNG, kdf: &Kdf, password: &[u8]) -> Result<String, KdfError>
+where
+    RNG: RngCore + CryptoRng,
+{
+    let mut salt = [0; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+
+    let mut hash = [0; HASH_LEN];
+    kdf.derive(password, &salt, &mut hash)?;
+    let phc = kdf.to_phc_string(&salt, &hash);
+
+    hash.scrub();
+
+    Ok(phc)
+}
+
+/// verify the password matches the given PHC string
+///
+/// the comparison of the hashes is done in constant time. The PHC
+/// strings with parameters beyond the [`VerifyLimits::DEFAULT`] are
+/// refused, see [`verify_password_with_limits`] for more expensive
+/// parameters.
+pub fn verify_password(phc: &str, password: &[u8]) -> Result<bool, KdfError> {
+    verify_password_with_limits(phc, password, &VerifyLimits::DEFAULT)
+}
+
+/// same as [`verify_password`] but fails with
+/// [`KdfError::LimitsExceeded`], before computing anything, if the
+/// parameters of the PHC string are beyond the `limits`
+pub fn verify_password_with_limits(
+    phc: &str,
+    password: &[u8],
+    limits: &VerifyLimits,
+) -> Result<bool, KdfError> {
+    let phc = PhcString::parse(phc)?;
+    let kdf = Kdf::from_phc_string(&phc)?;
+    if !limits.allow(&kdf) {
+        return Err(KdfError::LimitsExceeded);
+    }
+
+    let mut hash = vec![0; phc.hash.len()];
+    kdf.derive(password, &phc.salt, &mut hash)?;
+
+    let valid = unsafe { memsec::memeq(hash.as_ptr(), phc.hash.as_ptr(), hash.len()) };
+
+    hash.scrub();
+
+    Ok(valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn rfc7914_scrypt() {
+        let params = ScryptParams::new(10, 8, 16).unwrap();
+        let mut output = [0; 64];
+        scrypt(&params, b"password", b"NaCl", &mut output).unwrap();
+
+        assert_eq!(
+            hex::encode(output),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+             2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+    }
+
+    #[test]
+    fn scrypt_invalid_parameters() {
+        assert!(ScryptParams::new(0, 8, 1).is_err());
+        assert!(ScryptParams::new(16, 1, 1).is_err());
+        assert!(ScryptParams::new(10, 0, 1).is_err());
+        assert!(ScryptParams::new(10, 8, 0).is_err());
+    }
+
+    #[test]
+    fn hash_and_verify() {
+        let scrypt = Kdf::Scrypt(ScryptParams::new(4, 8, 1).unwrap());
+        let argon2 = Kdf::Argon2id(Argon2Params::new(64, 1, 2).unwrap());
+
+        for kdf in [scrypt, argon2] {
+            let hash = hash_password(&mut thread_rng(), &kdf, b"password").unwrap();
+
+            assert!(verify_password(&hash, b"password").unwrap());
+            assert!(!verify_password(&hash, b"passw0rd").unwrap());
+        }
+    }
+
+    #[test]
+    fn phc_string_format() {
+        let salt = [0x02; SALT_LEN];
+        let hash = [0x03; 4];
+
+        assert_eq!(
+            Kdf::Argon2id(Argon2Params::INTERACTIVE).to_phc_string(&salt, &hash),
+            "$argon2id$v=19$m=19456,t=2,p=1$AgICAgICAgICAgICAgICAg$AwMDAw"
+        );
+        assert_eq!(
+            Kdf::Scrypt(ScryptParams::INTERACTIVE).to_phc_string(&salt, &hash),
+            "$scrypt$ln=15,r=8,p=1$AgICAgICAgICAgICAgICAg$AwMDAw"
+        );
+    }
+
+    #[test]
+    fn verify_limits() {
+        let kdf = Kdf::Argon2id(Argon2Params::new(64, 2, 1).unwrap());
+        let hash = hash_password(&mut thread_rng(), &kdf, b"password").unwrap();
+        let limits = VerifyLimits {
+            max_memory: 64 * 1024,
+            max_passes: 2,
+        };
+
+        assert!(verify_password_with_limits(&hash, b"password", &limits).unwrap());
+        for limits in [
+            VerifyLimits {
+                max_memory: 63 * 1024,
+                ..limits
+            },
+            VerifyLimits {
+                max_passes: 1,
+                ..limits
+            },
+        ] {
+            assert!(matches!(
+                verify_password_with_limits(&hash, b"password", &limits),
+                Err(KdfError::LimitsExceeded)
+            ));
+        }
+
+        // 4TiB of memory, refused before allocating anything
+        assert!(matches!(
+            verify_password(
+                "$argon2id$v=19$m=4294967295,t=1,p=1$c2FsdHNhbHQ$aGFzaA",
+                b""
+            ),
+            Err(KdfError::LimitsExceeded)
+        ));
+        assert!(matches!(
+            verify_password("$scrypt$ln=20,r=8,p=1$c2FsdHNhbHQ$aGFzaA", b""),
+            Err(KdfError::LimitsExceeded)
+        ));
+        assert!(matches!(
+            verify_password("$scrypt$ln=4,r=8,p=1000$c2FsdHNhbHQ$aGFzaA", b""),
+            Err(KdfError::LimitsExceeded)
+        ));
+
+        for kdf in [
+            Kdf::Argon2id(Argon2Params::INTERACTIVE),
+            Kdf::Argon2id(Argon2Params::MODERATE),
+            Kdf::Scrypt(ScryptParams::INTERACTIVE),
+        ] {
+            assert!(VerifyLimits::DEFAULT.allow(&kdf));
+        }
+        for kdf in [
+            Kdf::Argon2id(Argon2Params::SENSITIVE),
+            Kdf::Scrypt(ScryptParams::SENSITIVE),
+        ] {
+            assert!(!VerifyLimits::DEFAULT.allow(&kdf));
+            assert!(VerifyLimits::SENSITIVE.allow(&kdf));
+        }
+    }
+
+    #[test]
+    fn verify_unsupported() {
+        assert!(matches!(
+            verify_password("$argon2i$v=19$m=64,t=1,p=1$c2FsdHNhbHQ$aGFzaA", b""),
+            Err(KdfError::UnsupportedAlgorithm(_))
+        ));
+        assert!(matches!(
+            verify_password("$argon2id$v=16$m=64,t=1,p=1$c2FsdHNhbHQ$aGFzaA", b""),
+            Err(KdfError::UnsupportedVersion(16))
+        ));
+        assert!(matches!(
+            verify_password("$argon2id$v=19$m=64,t=0,p=1$c2FsdHNhbHQ$aGFzaA", b""),
+            Err(KdfError::InvalidParameters(_))
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+//! minimal support for the [PHC string format]
+//!
+//! `$<id>[$v=<version>][$<param>=<value>(,<param>=<value>)*][$<salt>[$<hash>]]`
+//!
+//! the salt and the hash are encoded in base64 without padding.
+//!
+//! [PHC string format]: https://github.com/P-H-C/phc-string-format/blob/master/phc-sf-spec.md
+
+use crate::kdf::KdfError;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) struct PhcString<'a> {
+    pub(crate) id: &'a str,
+    pub(crate) version: Option<u32>,
+    pub(crate) params: Vec<(&'a str, u32)>,
+    pub(crate) salt: Vec<u8>,
+    pub(crate) hash: Vec<u8>,
+}
+
+impl<'a> PhcString<'a> {
+    pub(crate) fn parse(s: &'a str) -> Result<Self, KdfError> {
+        let mut fields = s
+            .strip_prefix('$')
+            .ok_or(KdfError::InvalidPhcString)?
+            .split('$')
+            .peekable();
+
+        let id = fields.next().ok_or(KdfError::InvalidPhcString)?;
+
+        let version = match fields.peek() {
+            Some(field) if field.starts_with("v=") => {
+                let version = parse_u32(&field[2..])?;
+                fields.next();
+                Some(version)
+            }
+            _ => None,
+        };
+
+        let params = fields
+            .next()
+            .ok_or(KdfError::InvalidPhcString)?
+            .split(',')
+            .map(|param| {
+                let (name, value) = param.split_once('=').ok_or(KdfError::InvalidPhcString)?;
+                Ok((name, parse_u32(value)?))
+            })
+            .collect::<Result<Vec<_>, KdfError>>()?;
+
+        let salt = decode(fields.next().ok_or(KdfError::InvalidPhcString)?)?;
+        let hash = decode(fields.next().ok_or(KdfError::InvalidPhcString)?)?;
+
+        if fields.next().is_some() {
+            return Err(KdfError::InvalidPhcString);
+        }
+
+        Ok(Self {
+            id,
+            version,
+            params,
+            salt,
+            hash,
+        })
+    }
+
+    pub(crate) fn param(&self, name: &str) -> Result<u32, KdfError> {
+        self.params
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| *v)
+            .ok_or(KdfError::InvalidPhcString)
+    }
+}
+
+fn parse_u32(s: &str) -> Result<u32, KdfError> {
+    // the format does not allow signs or leading zeros
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) || (s.len() > 1 && s.starts_with('0'))
+    {
+        return Err(KdfError::InvalidPhcString);
+    }
+    s.parse().map_err(|_| KdfError::InvalidPhcString)
+}
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity((bytes.len() * 4).div_ceil(3));
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..=chunk.len() {
+            s.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+
+    s
+}
+
+pub(crate) fn decode(s: &str) -> Result<Vec<u8>, KdfError> {
+    if s.len() % 4 == 1 {
+        return Err(KdfError::InvalidPhcString);
+    }
+
+    let mut bytes = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.as_bytes().chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = ALPHABET
+                .iter()
+                .position(|a| a == c)
+                .ok_or(KdfError::InvalidPhcString)?;
+            n |= (v as u32) << (18 - 6 * i);
+        }
+        let decoded = n.to_be_bytes();
+        let len = chunk.len() - 1;
+        // non canonical encodings are refused
+        if decoded[1 + len..].iter().any(|b| *b != 0) {
+            return Err(KdfError::InvalidPhcString);
+        }
+        bytes.extend_from_slice(&decoded[1..1 + len]);
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_vectors() {
+        const VECTORS: &[(&[u8], &str)] = &[
+            (b"", ""),
+            (b"f", "Zg"),
+            (b"fo", "Zm8"),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg"),
+            (b"fooba", "Zm9vYmE"),
+            (b"foobar", "Zm9vYmFy"),
+        ];
+
+        for (bytes, encoded) in VECTORS {
+            assert_eq!(&encode(bytes), encoded);
+            assert_eq!(&decode(encoded).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn base64_non_canonical() {
+        assert!(decode("Zh").is_err());
+        assert!(decode("Z").is_err());
+        assert!(decode("Zg=").is_err());
+    }
+
+    #[quickcheck]
+    fn base64_encode_decode(bytes: Vec<u8>) -> bool {
+        decode(&encode(&bytes)).unwrap() == bytes
+    }
+
+    #[test]
+    fn parse() {
+        let phc = PhcString::parse("$argon2id$v=19$m=65536,t=3,p=4$c2FsdA$aGFzaA").unwrap();
+        assert_eq!(phc.id, "argon2id");
+        assert_eq!(phc.version, Some(19));
+        assert_eq!(phc.param("m").unwrap(), 65536);
+        assert_eq!(phc.param("t").unwrap(), 3);
+        assert_eq!(phc.param("p").unwrap(), 4);
+        assert_eq!(phc.salt, b"salt");
+        assert_eq!(phc.hash, b"hash");
+
+        assert!(PhcString::parse("argon2id$v=19$m=65536,t=3,p=4$c2FsdA$aGFzaA").is_err());
+        assert!(PhcString::parse("$argon2id$v=19$m=065536,t=3,p=4$c2FsdA$aGFzaA").is_err());
+        assert!(PhcString::parse("$argon2id$v=19$m=65536,t=3,p=4$c2FsdA").is_err());
+        assert!(PhcString::parse("$argon2id$v=19$m=65536,t=3,p=4$c2FsdA$aGFzaA$").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+/*!
+# Audit of the HD keys derivations
+
+Wrap an HD secret key in an [`Audited`] key to record every derivation
+(the path, the derived public key and the time) in an [`AuditSink`]
+provided by the application. The keys derived from an audited key are
+audited too, so the record shows every key the application materialized
+from the root.
+
+```
+use keynesis_core::key::{audit::{Audited, Derivation}, ed25519_hd::SecretKey};
+use std::sync::{Arc, Mutex};
+
+let log = Arc::new(Mutex::new(Vec::new()));
+let sink = {
+    let log = Arc::clone(&log);
+    move |derivation: Derivation| log.lock().unwrap().push(derivation)
+};
+
+let root = Audited::new(SecretKey::generate(), sink);
+let key = root.derive(b"encryption").derive(b"bob");
+
+let log = log.lock().unwrap();
+assert_eq!(log.len(), 2);
+assert_eq!(log[1].path, b"bob");
+assert_eq!(log[1].public_key, key.public_key());
+```
+
+Only the derived public keys are recorded, the sink never sees any
+secret. The paths are recorded as given: the paths that are secret (see
+[`SecretKey::derive_slow`]) should not be derived through an audited key.
+*/
+
+use crate::key::ed25519_hd::{PublicKey, SecretKey};
+use std::{ops::Deref, sync::Arc, time::SystemTime};
+
+/// a derivation recorded by an [`Audited`] key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Derivation {
+    /// the public key of the key the derivation is from
+    pub parent: PublicKey,
+    pub path: Vec<u8>,
+    /// the public key of the derived key
+    pub public_key: PublicKey,
+    pub time: SystemTime,
+}
+
+/// record the derivations of the [`Audited`] keys
+///
+/// implemented for the closures taking a [`Derivation`]. The sink is
+/// cloned in every derived key, so it is usually a handle to a shared
+/// log (an `Arc<Mutex<_>>`, a channel...).
+pub trait AuditSink {
+    fn record(&self, derivation: Derivation);
+}
+
+/// an HD secret key recording its derivations in the sink `S`
+///
+/// the audited key dereferences to the [`SecretKey`] for the signing and
+/// the key exchanges.
+#[derive(Clone)]
+pub struct Audited<S> {
+    key: SecretKey,
+    sink: S,
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(Derivation),
+{
+    fn record(&self, derivation: Derivation) {
+        self(derivation)
+    }
+}
+
+impl<S: AuditSink + ?Sized> AuditSink for Arc<S> {
+    fn record(&self, derivation: Derivation) {
+        self.as_ref().record(derivation)
+    }
+}
+
+impl<S: AuditSink + Clone> Audited<S> {
+    pub fn new(key: SecretKey, sink: S) -> Self {
+        Self { key, sink }
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// derive the key with [`SecretKey::derive`] and record the
+    /// derivation, the derived key is audited with the same sink
+    pub fn derive<P>(&self, path: P) -> Self
+    where
+        P: AsRef<[u8]>,
+    {
+        let key = self.key.derive(&path);
+
+        self.sink.record(Derivation {
+            parent: self.key.public_key(),
+            path: path.as_ref().to_vec(),
+            public_key: key.public_key(),
+            time: SystemTime::now(),
+        });
+
+        Self {
+            key,
+            sink: self.sink.clone(),
+        }
+    }
+
+    /// stop the auditing of the key, the derivations of the returned key
+    /// are not recorded
+    pub fn into_inner(self) -> SecretKey {
+        self.key
+    }
+}
+
+impl<S> Deref for Audited<S> {
+    type Target = SecretKey;
+
+    fn deref(&self) -> &Self::Target {
+        &self.key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Mutex};
+
+    #[test]
+    fn derivations_are_recorded() {
+        let (sender, receiver) = mpsc::channel();
+        let sender = Arc::new(Mutex::new(sender));
+        let sink = move |derivation| sender.lock().unwrap().send(derivation).unwrap();
+
+        let root = SecretKey::generate();
+        let audited = Audited::new(root.clone(), sink);
+        let derived = audited.derive(b"a").derive(b"b");
+
+        // the derivations are the same as without the audit
+        assert_eq!(
+            derived.public_key(),
+            root.derive(b"a").derive(b"b").public_key()
+        );
+
+        let first = receiver.try_recv().unwrap();
+        let second = receiver.try_recv().unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        assert_eq!(first.parent, root.public_key());
+        assert_eq!(first.path, b"a");
+        assert_eq!(second.parent, first.public_key);
+        assert_eq!(second.public_key, derived.public_key());
+        assert!(first.time <= second.time);
+
+        // the public derivation gives the recorded keys
+        assert_eq!(root.public_key().derive(b"a").unwrap(), first.public_key);
+
+        derived.into_inner().derive(b"c");
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -1,7 +1,4 @@
-use crate::{
-    key::SharedSecret,
-    memsec::{self, Scrubbed as _},
-};
+use crate::{key::SharedSecret, memsec::Scrubbed as _, Seed};
 use cryptoxide::curve25519::curve25519;
 use rand_core::{CryptoRng, RngCore};
 use std::{
@@ -10,6 +7,7 @@ use std::{
     hash::{Hash, Hasher},
     str::FromStr,
 };
+use subtle::{Choice, ConstantTimeEq};
 use thiserror::Error;
 
 #[derive(Clone)]
@@ -32,6 +30,16 @@ impl SecretKey {
         }
     }
 
+    /// generate a new `SecretKey` with the operating system's
+    /// random number generator
+    ///
+    /// use [`SecretKey::new`] to provide a different random number
+    /// generator.
+    #[cfg(feature = "getrandom")]
+    pub fn generate() -> Self {
+        Self::new(rand_core::OsRng)
+    }
+
     /// generate a new `SecretKey` with the given random number generator
     ///
     pub fn new<Rng>(mut rng: Rng) -> Self
@@ -47,6 +55,27 @@ impl SecretKey {
         s
     }
 
+    /// deterministically generate a new `SecretKey` from the given [`Seed`]
+    ///
+    /// This is the same as calling [`SecretKey::new`] with the RNG returned
+    /// by [`Seed::into_rand_chacha`]: the same `Seed` always generates the
+    /// same `SecretKey`.
+    pub fn from_seed(seed: &Seed) -> Self {
+        Self::new(seed.clone().into_rand_chacha())
+    }
+
+    /// deterministically generate a `SecretKey` from an arbitrary byte
+    /// string, see [`Seed::from_phrase`]
+    pub fn from_seed_phrase<P: AsRef<[u8]>>(phrase: P) -> Self {
+        Self::from_seed(&Seed::from_phrase(phrase))
+    }
+
+    /// the key with a public key that is not `secret * base`, for the
+    /// Elligator keys (see [`elligator::generate`](super::elligator::generate))
+    pub(crate) fn with_public(secret: [u8; Self::SIZE], public: [u8; 32]) -> Self {
+        Self { secret, public }
+    }
+
     /// get the `PublicKey` associated to this key
     ///
     /// Unlike the `SecretKey`, the `PublicKey` can be safely
@@ -102,9 +131,16 @@ impl Debug for SecretKey {
 /* Conversion ************************************************************** */
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum SecretKeyError {
     #[error("Invalid size, expecting {}", SecretKey::SIZE)]
     InvalidSize,
+    #[error("Invalid hexadecimal string")]
+    InvalidHexadecimal(
+        #[source]
+        #[from]
+        hex::FromHexError,
+    ),
 }
 
 impl From<[u8; Self::SIZE]> for SecretKey {
@@ -131,7 +167,7 @@ impl<'a> TryFrom<&'a [u8]> for SecretKey {
 }
 
 impl FromStr for SecretKey {
-    type Err = hex::FromHexError;
+    type Err = SecretKeyError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut r = Self::zero();
         hex::decode_to_slice(s, &mut r.secret)?;
@@ -143,9 +179,15 @@ impl FromStr for SecretKey {
 
 /* Eq ********************************************************************** */
 
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.secret[..].ct_eq(&other.secret[..])
+    }
+}
+
 impl PartialEq<Self> for SecretKey {
     fn eq(&self, other: &Self) -> bool {
-        unsafe { memsec::memeq(self.secret.as_ptr(), other.secret.as_ptr(), Self::SIZE) }
+        self.ct_eq(other).into()
     }
 }
 
@@ -203,6 +245,9 @@ mod tests {
             Err(SecretKeyError::InvalidSize) => {
                 TestResult::error("was expecting the test to pass, not an invalid size")
             }
+            Err(SecretKeyError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 
@@ -216,6 +261,9 @@ mod tests {
                 "Expecting to fail with invalid size instead of having a valid value",
             ),
             Err(SecretKeyError::InvalidSize) => TestResult::passed(),
+            Err(SecretKeyError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 }
@@ -0,0 +1,254 @@
+/*!
+# Derivation paths
+
+An [`ed25519_hd`](super::ed25519_hd) key is derived with a slice of
+bytes. A [`DerivationPath`] is a list of such slices (the segments), the
+key is derived with every segment one after the other. This gives a
+structure to the derivation schemes of the applications so they can be
+written down, compared and audited:
+
+```
+use keynesis_core::key::{derivation_path::DerivationPath, ed25519_hd::SecretKey};
+# use rand::thread_rng;
+
+let root = SecretKey::new(&mut thread_rng());
+
+let path = DerivationPath::new().child("wallet").child("account").child(1u32.to_be_bytes());
+assert_eq!(path.to_string(), "m/wallet/account/%00%00%00%01");
+assert_eq!(path, "m/wallet/account/%00%00%00%01".parse().unwrap());
+
+// this is the same as deriving every segment with the slice of bytes
+assert_eq!(
+    root.derive(&path),
+    root.derive(b"wallet").derive(b"account").derive(1u32.to_be_bytes()),
+);
+```
+
+The string representation starts with `m` (the root key) followed by
+the segments separated by `/`. The bytes of the segments that are not
+printable ASCII characters, the `/` and the `%` are percent encoded.
+*/
+
+use std::{
+    fmt::{self, Display, Formatter},
+    iter::FromIterator,
+    str::FromStr,
+};
+use thiserror::Error;
+
+const ROOT: &str = "m";
+const SEPARATOR: char = '/';
+const ESCAPE: u8 = b'%';
+
+/// the segments to derive a key with, see the [module](self)
+/// documentation
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DerivationPath {
+    segments: Vec<Vec<u8>>,
+}
+
+/// the paths the hierarchical deterministic keys can be derived with
+///
+/// a slice of bytes is a path of one segment, this is the derivation
+/// of [`ed25519_hd::SecretKey::derive`](super::ed25519_hd::SecretKey::derive)
+/// with the raw bytes.
+pub trait AsDerivationPath {
+    /// the segments to derive, in order
+    fn segments(&self) -> Vec<&[u8]>;
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DerivationPathError {
+    #[error("The derivation path needs to start with \"m\"")]
+    MissingRoot,
+
+    #[error("Invalid character {0:?}, it needs to be percent encoded")]
+    InvalidCharacter(char),
+
+    #[error("Invalid percent encoding")]
+    InvalidEscape,
+}
+
+impl DerivationPath {
+    /// the empty path, the root key itself
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// append a segment at the end of the path
+    pub fn push<S: AsRef<[u8]>>(&mut self, segment: S) {
+        self.segments.push(segment.as_ref().to_vec())
+    }
+
+    /// the path with the `segment` appended
+    pub fn child<S: AsRef<[u8]>>(mut self, segment: S) -> Self {
+        self.push(segment);
+        self
+    }
+
+    /// the path without its last segment, `None` for the empty path
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.segments.split_last()?;
+        Some(Self {
+            segments: parent.to_vec(),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.segments.iter().map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+/* AsDerivationPath ******************************************************** */
+
+impl<T: AsRef<[u8]>> AsDerivationPath for T {
+    fn segments(&self) -> Vec<&[u8]> {
+        vec![self.as_ref()]
+    }
+}
+
+impl AsDerivationPath for DerivationPath {
+    fn segments(&self) -> Vec<&[u8]> {
+        self.iter().collect()
+    }
+}
+
+impl AsDerivationPath for &DerivationPath {
+    fn segments(&self) -> Vec<&[u8]> {
+        self.iter().collect()
+    }
+}
+
+/* Format ****************************************************************** */
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(ROOT)?;
+        for segment in &self.segments {
+            write!(f, "{}", SEPARATOR)?;
+            for byte in segment {
+                if byte.is_ascii_graphic() && *byte != SEPARATOR as u8 && *byte != ESCAPE {
+                    write!(f, "{}", *byte as char)?;
+                } else {
+                    write!(f, "%{:02X}", byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = DerivationPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(SEPARATOR);
+        if parts.next() != Some(ROOT) {
+            return Err(DerivationPathError::MissingRoot);
+        }
+
+        parts.map(decode_segment).collect()
+    }
+}
+
+fn decode_segment(s: &str) -> Result<Vec<u8>, DerivationPathError> {
+    let mut segment = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte == ESCAPE {
+            let escaped = [
+                bytes.next().ok_or(DerivationPathError::InvalidEscape)?,
+                bytes.next().ok_or(DerivationPathError::InvalidEscape)?,
+            ];
+            let mut decoded = [0];
+            hex::decode_to_slice(escaped, &mut decoded)
+                .map_err(|_| DerivationPathError::InvalidEscape)?;
+            segment.push(decoded[0]);
+        } else if byte.is_ascii_graphic() {
+            segment.push(byte);
+        } else {
+            let c = s[s.len() - bytes.len() - 1..].chars().next().unwrap_or('?');
+            return Err(DerivationPathError::InvalidCharacter(c));
+        }
+    }
+
+    Ok(segment)
+}
+
+/* Conversion ************************************************************** */
+
+impl<S: AsRef<[u8]>> FromIterator<S> for DerivationPath {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self {
+            segments: iter.into_iter().map(|s| s.as_ref().to_vec()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen};
+
+    impl Arbitrary for DerivationPath {
+        fn arbitrary(g: &mut Gen) -> Self {
+            Vec::<Vec<u8>>::arbitrary(g).into_iter().collect()
+        }
+    }
+
+    #[quickcheck]
+    fn display_parse(path: DerivationPath) -> bool {
+        path.to_string().parse::<DerivationPath>() == Ok(path)
+    }
+
+    #[test]
+    fn parse() {
+        let path: DerivationPath = "m/purpose/account/0".parse().unwrap();
+        assert_eq!(path.segments(), vec![&b"purpose"[..], b"account", b"0"]);
+
+        assert_eq!("m".parse(), Ok(DerivationPath::new()));
+        assert_eq!("m/".parse(), Ok(DerivationPath::new().child("")));
+        assert_eq!(
+            "m/a%2Fb/%25".parse(),
+            Ok(DerivationPath::new().child("a/b").child("%"))
+        );
+
+        assert_eq!(
+            "purpose/account".parse::<DerivationPath>(),
+            Err(DerivationPathError::MissingRoot)
+        );
+        assert_eq!(
+            "m/a b".parse::<DerivationPath>(),
+            Err(DerivationPathError::InvalidCharacter(' '))
+        );
+        assert_eq!(
+            "m/é".parse::<DerivationPath>(),
+            Err(DerivationPathError::InvalidCharacter('é'))
+        );
+        assert_eq!(
+            "m/%4".parse::<DerivationPath>(),
+            Err(DerivationPathError::InvalidEscape)
+        );
+        assert_eq!(
+            "m/%zz".parse::<DerivationPath>(),
+            Err(DerivationPathError::InvalidEscape)
+        );
+    }
+
+    #[test]
+    fn parent() {
+        let path = DerivationPath::new().child("a").child("b");
+        assert_eq!(path.parent(), Some(DerivationPath::new().child("a")));
+        assert_eq!(DerivationPath::new().parent(), None);
+    }
+}
@@ -1,8 +1,21 @@
+#[cfg(feature = "openssh")]
+use crate::openssh::{self, OpenSshError};
+#[cfg(feature = "pkcs8")]
+use crate::pkcs8::{self, Pkcs8Error};
 use crate::{
-    key::SharedSecret,
+    bech32::{self, Bech32Error},
+    canonical::{self, Canonical, CanonicalError, ParseManyError},
+    key::{vrf, Fingerprint, SharedSecret},
     memsec::{self, Scrubbed as _},
+    Seed,
+};
+use cryptoxide::{digest::Digest as _, ed25519, sha2::Sha512};
+use curve25519_dalek::{
+    constants::{ED25519_BASEPOINT_POINT, ED25519_BASEPOINT_TABLE},
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::{IsIdentity as _, VartimeMultiscalarMul as _},
 };
-use cryptoxide::ed25519;
 use packtool::Packed;
 use rand_core::{CryptoRng, RngCore};
 use std::{
@@ -12,8 +25,22 @@ use std::{
     hash::{Hash, Hasher},
     str::FromStr,
 };
+use subtle::{Choice, ConstantTimeEq};
 use thiserror::Error;
 
+/// size of the prehashed messages of the Ed25519ph signatures (see
+/// [`SecretKey::sign_prehashed`])
+pub const PREHASH_SIZE: usize = 64;
+
+/// `dom2` prefix of the Ed25519ph signatures ([RFC 8032]), with an empty
+/// context
+///
+/// [RFC 8032]: https://www.rfc-editor.org/rfc/rfc8032#section-5.1
+const PREHASH_DOM: &[u8] = b"SigEd25519 no Ed25519 collisions\x01\x00";
+
+/// domain separation of the coefficients of [`PublicKey::verify_batch`]
+const BATCH_CONTEXT: &[u8] = b"keynesis:ed25519:batch";
+
 /// Ed25519 Secret Key
 ///
 /// Mind it though, losing this key means losing control over your identity
@@ -44,6 +71,16 @@ impl SecretKey {
         Self([0; Self::SIZE])
     }
 
+    /// generate a new `SecretKey` with the operating system's
+    /// random number generator
+    ///
+    /// use [`SecretKey::new`] to provide a different random number
+    /// generator.
+    #[cfg(feature = "getrandom")]
+    pub fn generate() -> Self {
+        Self::new(rand_core::OsRng)
+    }
+
     /// generate a new `SecretKey` with the given random number generator
     ///
     pub fn new<Rng>(mut rng: Rng) -> Self
@@ -55,6 +92,20 @@ impl SecretKey {
         s
     }
 
+    /// deterministically generate a `SecretKey` from the given [`Seed`]
+    ///
+    /// the seed is expanded with [`Seed::into_rand_chacha`], it is
+    /// not used as the raw Ed25519 secret.
+    pub fn from_seed(seed: &Seed) -> Self {
+        Self::new(seed.clone().into_rand_chacha())
+    }
+
+    /// deterministically generate a `SecretKey` from an arbitrary byte
+    /// string, see [`Seed::from_phrase`]
+    pub fn from_seed_phrase<P: AsRef<[u8]>>(phrase: P) -> Self {
+        Self::from_seed(&Seed::from_phrase(phrase))
+    }
+
     /// generate a shared secret between the owner of the given public key and
     /// ourselves.
     ///
@@ -95,6 +146,100 @@ impl SecretKey {
         Signature(signature)
     }
 
+    /// create an Ed25519ph `Signature` of the SHA512 `prehash` of the
+    /// message
+    ///
+    /// the message does not need to be held in memory: the hash can be
+    /// computed as the message is read (with [`Sha512`]). Use
+    /// [`PublicKey::verify_prehashed`] to verify the signature, it is
+    /// not valid for [`PublicKey::verify`].
+    ///
+    /// [`Sha512`]: cryptoxide::sha2::Sha512
+    pub fn sign_prehashed(&self, prehash: &[u8; PREHASH_SIZE]) -> Signature {
+        let mut expanded = [0; 64];
+        let mut hasher = Sha512::new();
+        hasher.input(&self.0);
+        hasher.result(&mut expanded);
+        expanded[0] &= 0b1111_1000;
+        expanded[31] &= 0b0111_1111;
+        expanded[31] |= 0b0100_0000;
+
+        let signature = sign_prehashed_extended(&expanded, prehash);
+
+        expanded.scrub();
+
+        signature
+    }
+
+    /// compute the VRF output of `alpha` and the proof for the
+    /// public key (see [`vrf`](crate::key::vrf))
+    pub fn vrf_prove<T: AsRef<[u8]>>(&self, alpha: T) -> (vrf::Output, vrf::Proof) {
+        let mut expanded = [0; 64];
+        let mut hasher = Sha512::new();
+        hasher.input(&self.0);
+        hasher.result(&mut expanded);
+        expanded[0] &= 0b1111_1000;
+        expanded[31] &= 0b0111_1111;
+        expanded[31] |= 0b0100_0000;
+
+        let result = vrf::prove(&expanded, alpha.as_ref());
+
+        expanded.scrub();
+
+        result
+    }
+
+    /// encode the secret key in a PKCS#8 DER document (see
+    /// [`pkcs8`](crate::pkcs8))
+    #[cfg(feature = "pkcs8")]
+    pub fn to_pkcs8_der(&self) -> Vec<u8> {
+        pkcs8::encode_private_key(&self.0)
+    }
+
+    /// decode the secret key of a PKCS#8 DER document
+    #[cfg(feature = "pkcs8")]
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, Pkcs8Error> {
+        let (key, public_key) = pkcs8::decode_private_key(der)?;
+        let secret_key = Self(key);
+        match public_key {
+            Some(public_key) if PublicKey(public_key) != secret_key.public_key() => {
+                Err(Pkcs8Error::MismatchedPublicKey)
+            }
+            _ => Ok(secret_key),
+        }
+    }
+
+    /// encode the secret key in a `PRIVATE KEY` PEM document
+    #[cfg(feature = "pkcs8")]
+    pub fn to_pkcs8_pem(&self) -> String {
+        let mut der = self.to_pkcs8_der();
+        let pem = pkcs8::to_pem(pkcs8::PRIVATE_KEY_LABEL, &der);
+        der.scrub();
+        pem
+    }
+
+    /// decode the secret key of a `PRIVATE KEY` PEM document
+    #[cfg(feature = "pkcs8")]
+    pub fn from_pkcs8_pem(pem: &str) -> Result<Self, Pkcs8Error> {
+        let mut der = pkcs8::from_pem(pkcs8::PRIVATE_KEY_LABEL, pem)?;
+        let secret_key = Self::from_pkcs8_der(&der);
+        der.scrub();
+        secret_key
+    }
+
+    /// encode the secret key in an unencrypted OpenSSH private key file
+    /// (see [`openssh`](crate::openssh))
+    #[cfg(feature = "openssh")]
+    pub fn to_openssh(&self, comment: &str) -> String {
+        openssh::encode_private_key(&self.0, &self.public_key().0, comment)
+    }
+
+    /// decode the secret key of an unencrypted OpenSSH private key file
+    #[cfg(feature = "openssh")]
+    pub fn from_openssh(file: &str) -> Result<Self, OpenSshError> {
+        openssh::decode_private_key(file, |seed| Self(*seed).public_key().0).map(Self)
+    }
+
     /// get a reference to the inner Seed bytes
     ///
     /// # Security Consideration
@@ -121,6 +266,188 @@ impl PublicKey {
     pub fn verify<T: AsRef<[u8]>>(&self, msg: T, signature: &Signature) -> bool {
         ed25519::verify(msg.as_ref(), &self.0, &signature.0)
     }
+
+    /// the short identifier of the key, see [`Fingerprint`]
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&self.0)
+    }
+
+    /// check the public key has the given `fingerprint`
+    pub fn matches_fingerprint(&self, fingerprint: &Fingerprint) -> bool {
+        &self.fingerprint() == fingerprint
+    }
+
+    /// verify the VRF `proof` of `alpha`, returns the VRF output
+    /// (see [`vrf`](crate::key::vrf))
+    pub fn vrf_verify<T: AsRef<[u8]>>(&self, alpha: T, proof: &vrf::Proof) -> Option<vrf::Output> {
+        vrf::verify(&self.0, alpha.as_ref(), proof)
+    }
+
+    /// verify the Ed25519ph `Signature` of the SHA512 `prehash` of the
+    /// message (see [`SecretKey::sign_prehashed`])
+    pub fn verify_prehashed(&self, prehash: &[u8; PREHASH_SIZE], signature: &Signature) -> bool {
+        let (r, s) = signature.0.split_at(32);
+        let mut s_bytes = [0; 32];
+        s_bytes.copy_from_slice(s);
+
+        let (a, s) = match (
+            CompressedEdwardsY(self.0).decompress(),
+            Scalar::from_canonical_bytes(s_bytes),
+        ) {
+            (Some(a), Some(s)) => (a, s),
+            _ => return false,
+        };
+
+        let k = prehash_challenge(r, &self.0, prehash);
+        let expected = EdwardsPoint::vartime_double_scalar_mul_basepoint(&k, &-a, &s);
+
+        expected.compress().as_bytes() == r
+    }
+
+    /// verify all the `signatures` of the `messages` with their
+    /// associated `keys` at once
+    ///
+    /// returns `true` only if all the signatures are valid (and the 3
+    /// slices have the same length). This is faster than verifying the
+    /// signatures one at a time but it does not tell which signature is
+    /// invalid: verify them one by one to find out.
+    ///
+    /// the signatures are checked with the cofactored equation so a
+    /// signature with a small order component may be accepted here and
+    /// rejected by [`verify`](Self::verify). The random coefficients of
+    /// the batch are derived from all the signatures, keys and messages.
+    pub fn verify_batch<T: AsRef<[u8]>>(
+        messages: &[T],
+        signatures: &[Signature],
+        keys: &[PublicKey],
+    ) -> bool {
+        if messages.len() != signatures.len() || messages.len() != keys.len() {
+            return false;
+        }
+
+        let mut transcript = Sha512::new();
+        transcript.input(BATCH_CONTEXT);
+        for ((message, signature), key) in messages.iter().zip(signatures).zip(keys) {
+            let mut hash = [0; 64];
+            let mut hasher = Sha512::new();
+            hasher.input(message.as_ref());
+            hasher.result(&mut hash);
+
+            transcript.input(&signature.0);
+            transcript.input(&key.0);
+            transcript.input(&hash);
+        }
+        let mut seed = [0; 64];
+        transcript.result(&mut seed);
+
+        let mut b = Scalar::zero();
+        let mut scalars = Vec::with_capacity(2 * keys.len() + 1);
+        let mut points = Vec::with_capacity(2 * keys.len() + 1);
+        for (i, ((message, signature), key)) in
+            messages.iter().zip(signatures).zip(keys).enumerate()
+        {
+            let (r, s) = signature.0.split_at(32);
+            let mut s_bytes = [0; 32];
+            s_bytes.copy_from_slice(s);
+
+            let r = CompressedEdwardsY::from_slice(r).decompress();
+            let a = CompressedEdwardsY(key.0).decompress();
+            let s = Scalar::from_canonical_bytes(s_bytes);
+            let (r, a, s) = match (r, a, s) {
+                (Some(r), Some(a), Some(s)) => (r, a, s),
+                _ => return false,
+            };
+
+            let mut hash = [0; 64];
+            let mut hasher = Sha512::new();
+            hasher.input(&signature.0[..32]);
+            hasher.input(&key.0);
+            hasher.input(message.as_ref());
+            hasher.result(&mut hash);
+            let k = Scalar::from_bytes_mod_order_wide(&hash);
+
+            // 128 bits coefficient, unique to the batch and the index
+            let mut hasher = Sha512::new();
+            hasher.input(&seed);
+            hasher.input(&(i as u64).to_be_bytes());
+            hasher.result(&mut hash);
+            let mut z = [0; 32];
+            z[..16].copy_from_slice(&hash[..16]);
+            let z = Scalar::from_bits(z);
+
+            b -= z * s;
+            scalars.push(z);
+            points.push(r);
+            scalars.push(z * k);
+            points.push(a);
+        }
+        scalars.push(b);
+        points.push(ED25519_BASEPOINT_POINT);
+
+        EdwardsPoint::vartime_multiscalar_mul(scalars, points)
+            .mul_by_cofactor()
+            .is_identity()
+    }
+
+    /// strictly decode a packed array of public keys (a list of peers...)
+    ///
+    /// every key needs to be canonical (see [`Canonical`]), the error
+    /// lists the indices of all the invalid keys.
+    pub fn parse_many(bytes: &[u8]) -> Result<Vec<Self>, ParseManyError> {
+        canonical::parse_many(bytes, Self::SIZE)
+    }
+
+    /// encode the public key in a `SubjectPublicKeyInfo` DER document
+    /// (see [`pkcs8`](crate::pkcs8))
+    #[cfg(feature = "pkcs8")]
+    pub fn to_public_key_der(&self) -> Vec<u8> {
+        pkcs8::encode_public_key(&self.0)
+    }
+
+    /// decode the public key of a `SubjectPublicKeyInfo` DER document
+    #[cfg(feature = "pkcs8")]
+    pub fn from_public_key_der(der: &[u8]) -> Result<Self, Pkcs8Error> {
+        pkcs8::decode_public_key(der).map(Self)
+    }
+
+    /// encode the public key in a `PUBLIC KEY` PEM document
+    #[cfg(feature = "pkcs8")]
+    pub fn to_public_key_pem(&self) -> String {
+        pkcs8::to_pem(pkcs8::PUBLIC_KEY_LABEL, &self.to_public_key_der())
+    }
+
+    /// decode the public key of a `PUBLIC KEY` PEM document
+    #[cfg(feature = "pkcs8")]
+    pub fn from_public_key_pem(pem: &str) -> Result<Self, Pkcs8Error> {
+        Self::from_public_key_der(&pkcs8::from_pem(pkcs8::PUBLIC_KEY_LABEL, pem)?)
+    }
+
+    /// encode the public key in an OpenSSH `ssh-ed25519` line (see
+    /// [`openssh`](crate::openssh)), the comment may be empty
+    #[cfg(feature = "openssh")]
+    pub fn to_openssh(&self, comment: &str) -> String {
+        openssh::encode_public_key(&self.0, comment)
+    }
+
+    /// decode the public key and the comment of an OpenSSH
+    /// `ssh-ed25519` line
+    #[cfg(feature = "openssh")]
+    pub fn from_openssh(line: &str) -> Result<(Self, &str), OpenSshError> {
+        openssh::decode_public_key(line).map(|(key, comment)| (Self(key), comment))
+    }
+
+    /// encode the public key in a bech32 string with the given human
+    /// readable part
+    pub fn to_bech32_str(&self, hrp: &str) -> String {
+        bech32::encode(hrp, self.0)
+    }
+
+    /// decode a public key from a bech32 string, the human readable part
+    /// needs to be the given `hrp`.
+    pub fn from_bech32_str(hrp: &str, s: &str) -> Result<Self, PublicKeyError> {
+        let bytes = bech32::decode_with_hrp(hrp, s)?;
+        Self::try_from(bytes.as_slice())
+    }
 }
 
 impl Signature {
@@ -130,6 +457,63 @@ impl Signature {
     const fn zero() -> Self {
         Self([0; Self::SIZE])
     }
+
+    /// strictly decode a packed array of signatures
+    ///
+    /// see [`PublicKey::parse_many`]
+    pub fn parse_many(bytes: &[u8]) -> Result<Vec<Self>, ParseManyError> {
+        canonical::parse_many(bytes, Self::SIZE)
+    }
+
+    /// encode the signature in a bech32 string with the given human
+    /// readable part
+    pub fn to_bech32_str(&self, hrp: &str) -> String {
+        bech32::encode(hrp, self.0)
+    }
+
+    /// decode a signature from a bech32 string, the human readable part
+    /// needs to be the given `hrp`.
+    pub fn from_bech32_str(hrp: &str, s: &str) -> Result<Self, SignatureError> {
+        let bytes = bech32::decode_with_hrp(hrp, s)?;
+        Self::try_from(bytes.as_slice())
+    }
+}
+
+/// Ed25519ph signature with the expanded (and clamped) secret key, see
+/// [`SecretKey::sign_prehashed`]
+pub(crate) fn sign_prehashed_extended(key: &[u8; 64], prehash: &[u8; PREHASH_SIZE]) -> Signature {
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(&key[..32]);
+    let a = Scalar::from_bytes_mod_order(bytes);
+    bytes.scrub();
+    let public = (&a * &ED25519_BASEPOINT_TABLE).compress();
+
+    let mut hash = [0; 64];
+    let mut hasher = Sha512::new();
+    hasher.input(PREHASH_DOM);
+    hasher.input(&key[32..]);
+    hasher.input(prehash);
+    hasher.result(&mut hash);
+    let r = Scalar::from_bytes_mod_order_wide(&hash);
+    hash.scrub();
+
+    let mut signature = [0; Signature::SIZE];
+    signature[..32].copy_from_slice((&r * &ED25519_BASEPOINT_TABLE).compress().as_bytes());
+    let k = prehash_challenge(&signature[..32], public.as_bytes(), prehash);
+    signature[32..].copy_from_slice((k * a + r).as_bytes());
+
+    Signature(signature)
+}
+
+fn prehash_challenge(r: &[u8], public_key: &[u8; 32], prehash: &[u8; PREHASH_SIZE]) -> Scalar {
+    let mut hash = [0; 64];
+    let mut hasher = Sha512::new();
+    hasher.input(PREHASH_DOM);
+    hasher.input(r);
+    hasher.input(public_key);
+    hasher.input(prehash);
+    hasher.result(&mut hash);
+    Scalar::from_bytes_mod_order_wide(&hash)
 }
 
 /* Format ****************************************************************** */
@@ -168,7 +552,7 @@ impl Debug for PublicKey {
 impl Debug for SecretKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_tuple("SecretKey<Ed25519>")
-            .field(&hex::encode(&self.0))
+            .field(&hex::encode(self.0))
             .finish()
     }
 }
@@ -221,21 +605,54 @@ impl From<[u8; Self::SIZE]> for Signature {
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum SecretKeyError {
     #[error("Invalid size, expecting {}", SecretKey::SIZE)]
     InvalidSize,
+    #[error("Invalid hexadecimal string")]
+    InvalidHexadecimal(
+        #[source]
+        #[from]
+        hex::FromHexError,
+    ),
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum PublicKeyError {
     #[error("Invalid size, expecting {}", PublicKey::SIZE)]
     InvalidSize,
+    #[error("Invalid hexadecimal string")]
+    InvalidHexadecimal(
+        #[source]
+        #[from]
+        hex::FromHexError,
+    ),
+    #[error("Invalid bech32 string")]
+    InvalidBech32(
+        #[source]
+        #[from]
+        Bech32Error,
+    ),
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum SignatureError {
-    #[error("Invalid size, expecting {}", PublicKey::SIZE)]
+    #[error("Invalid size, expecting {}", Signature::SIZE)]
     InvalidSize,
+    #[error("Invalid hexadecimal string")]
+    InvalidHexadecimal(
+        #[source]
+        #[from]
+        hex::FromHexError,
+    ),
+    #[error("Invalid bech32 string")]
+    InvalidBech32(
+        #[source]
+        #[from]
+        Bech32Error,
+    ),
 }
 
 impl<'a> TryFrom<&'a [u8]> for SecretKey {
@@ -278,7 +695,7 @@ impl<'a> TryFrom<&'a [u8]> for Signature {
 }
 
 impl FromStr for SecretKey {
-    type Err = hex::FromHexError;
+    type Err = SecretKeyError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut r = Self::zero();
         hex::decode_to_slice(s, &mut r.0)?;
@@ -287,7 +704,7 @@ impl FromStr for SecretKey {
 }
 
 impl FromStr for PublicKey {
-    type Err = hex::FromHexError;
+    type Err = PublicKeyError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut r = Self::zero();
         hex::decode_to_slice(s, &mut r.0)?;
@@ -296,7 +713,7 @@ impl FromStr for PublicKey {
 }
 
 impl FromStr for Signature {
-    type Err = hex::FromHexError;
+    type Err = SignatureError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut r = Self::zero();
         hex::decode_to_slice(s, &mut r.0)?;
@@ -311,6 +728,36 @@ impl<'a> TryFrom<&'a str> for Signature {
     }
 }
 
+/* Canonical *************************************************************** */
+
+impl Canonical for PublicKey {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    /// the public key needs to be the canonical encoding of a point
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        canonical::check_point(bytes)?;
+        Self::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)
+    }
+}
+
+impl Canonical for Signature {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    /// the `R` of the signature needs to be the canonical encoding of a
+    /// point and the `S` needs to be reduced
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        let signature = Self::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)?;
+        let (r, s) = signature.0.split_at(32);
+        canonical::check_point(r)?;
+        canonical::check_scalar(s)?;
+        Ok(signature)
+    }
+}
+
 /* Eq ********************************************************************** */
 
 impl PartialEq<Self> for Signature {
@@ -325,9 +772,15 @@ impl PartialEq<Self> for PublicKey {
     }
 }
 
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0[..].ct_eq(&other.0[..])
+    }
+}
+
 impl PartialEq<Self> for SecretKey {
     fn eq(&self, other: &Self) -> bool {
-        unsafe { memsec::memeq(self.0.as_ptr(), other.0.as_ptr(), Self::SIZE) }
+        self.ct_eq(other).into()
     }
 }
 
@@ -440,6 +893,137 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn generate_with_os_rng() {
+        let key1 = SecretKey::generate();
+        let key2 = SecretKey::generate();
+        assert_ne!(key1, key2);
+
+        let signature = key1.sign(b"message");
+        assert!(key1.public_key().verify(b"message", &signature));
+    }
+
+    #[quickcheck]
+    fn bech32_encode_decode(signing_key: SecretKey, message: Vec<u8>) -> bool {
+        let public_key = signing_key.public_key();
+        let signature = signing_key.sign(&message);
+
+        let pk = public_key.to_bech32_str("pk");
+        let sig = signature.to_bech32_str("sig");
+
+        PublicKey::from_bech32_str("pk", &pk).unwrap() == public_key
+            && Signature::from_bech32_str("sig", &sig).unwrap() == signature
+    }
+
+    #[quickcheck]
+    fn bech32_unexpected_hrp(public_key: PublicKey, signature: Signature) -> bool {
+        let pk = public_key.to_bech32_str("pk");
+        let sig = signature.to_bech32_str("sig");
+
+        matches!(
+            PublicKey::from_bech32_str("sig", &pk),
+            Err(PublicKeyError::InvalidBech32(
+                Bech32Error::UnexpectedHrp { .. }
+            ))
+        ) && matches!(
+            Signature::from_bech32_str("pk", &sig),
+            Err(SignatureError::InvalidBech32(
+                Bech32Error::UnexpectedHrp { .. }
+            ))
+        )
+    }
+
+    #[test]
+    fn bech32_invalid_size() {
+        let s = bech32::encode("pk", [0; 31]);
+        assert!(matches!(
+            PublicKey::from_bech32_str("pk", &s),
+            Err(PublicKeyError::InvalidSize)
+        ));
+    }
+
+    #[quickcheck]
+    fn canonical_encoding(signing_key: SecretKey, message: Vec<u8>) -> bool {
+        let public_key = signing_key.public_key();
+        let signature = signing_key.sign(&message);
+
+        PublicKey::from_bytes_strict(&public_key.to_canonical_bytes()).unwrap() == public_key
+            && Signature::from_bytes_strict(&signature.to_canonical_bytes()).unwrap() == signature
+    }
+
+    #[test]
+    fn non_canonical_encoding() {
+        use crate::canonical::tests::NON_CANONICAL_POINT;
+
+        assert_eq!(
+            PublicKey::from_bytes_strict(&NON_CANONICAL_POINT),
+            Err(CanonicalError::NonCanonical)
+        );
+
+        let signing_key = SecretKey::new(rand::thread_rng());
+        let signature = signing_key.sign(b"message");
+
+        // adding the order of the group to S is accepted by some verifiers
+        let mut bytes = signature.0;
+        let mut carry = 0u16;
+        for (byte, l) in bytes[32..]
+            .iter_mut()
+            .zip(curve25519_dalek::constants::BASEPOINT_ORDER.as_bytes())
+        {
+            let sum = *byte as u16 + *l as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+        assert_eq!(
+            Signature::from_bytes_strict(&bytes),
+            Err(CanonicalError::NonCanonical)
+        );
+    }
+
+    #[test]
+    fn parse_many() {
+        use crate::canonical::tests::NON_CANONICAL_POINT;
+
+        let keys: Vec<_> = (0..4)
+            .map(|_| SecretKey::new(rand::thread_rng()).public_key())
+            .collect();
+        let mut bytes: Vec<u8> = keys.iter().flat_map(|key| key.0).collect();
+
+        assert_eq!(PublicKey::parse_many(&bytes).unwrap(), keys);
+        assert_eq!(PublicKey::parse_many(&[]).unwrap(), Vec::new());
+        assert_eq!(
+            PublicKey::parse_many(&bytes[1..]),
+            Err(ParseManyError::InvalidSize {
+                expected: PublicKey::SIZE
+            })
+        );
+
+        bytes[PublicKey::SIZE..2 * PublicKey::SIZE].copy_from_slice(&NON_CANONICAL_POINT);
+        bytes[3 * PublicKey::SIZE..].copy_from_slice(&NON_CANONICAL_POINT);
+        assert_eq!(
+            PublicKey::parse_many(&bytes),
+            Err(ParseManyError::InvalidEntries(vec![1, 3]))
+        );
+
+        let signing_key = SecretKey::new(rand::thread_rng());
+        let signatures = vec![signing_key.sign(b"1"), signing_key.sign(b"2")];
+        let bytes: Vec<u8> = signatures
+            .iter()
+            .flat_map(|signature| signature.0)
+            .collect();
+        assert_eq!(Signature::parse_many(&bytes).unwrap(), signatures);
+    }
+
+    #[quickcheck]
+    fn constant_time_eq(key: SecretKey, other: SecretKey) -> bool {
+        let equal = key.leak_as_ref() == other.leak_as_ref();
+
+        bool::from(key.ct_eq(&key.clone()))
+            && bool::from(key.ct_eq(&other)) == equal
+            && (key == other) == equal
+    }
+
     #[quickcheck]
     fn verify_exchange_works(alice: SecretKey, bob: SecretKey) -> bool {
         let alice_pk = alice.public_key();
@@ -456,6 +1040,98 @@ mod tests {
         public_key.verify(message, &signature)
     }
 
+    #[quickcheck]
+    fn verify_batch_works(signing_keys: Vec<SecretKey>, messages: Vec<Vec<u8>>) -> TestResult {
+        let count = std::cmp::min(signing_keys.len(), messages.len());
+        if count == 0 {
+            return TestResult::discard();
+        }
+        let messages = &messages[..count];
+        let keys: Vec<_> = signing_keys[..count]
+            .iter()
+            .map(SecretKey::public_key)
+            .collect();
+        let mut signatures: Vec<_> = signing_keys[..count]
+            .iter()
+            .zip(messages)
+            .map(|(key, message)| key.sign(message))
+            .collect();
+
+        if !PublicKey::verify_batch(messages, &signatures, &keys) {
+            return TestResult::error("valid signatures rejected");
+        }
+        if PublicKey::verify_batch(&messages[1..], &signatures, &keys) {
+            return TestResult::error("batch of different lengths accepted");
+        }
+
+        signatures[count - 1].0[0] ^= 1;
+        TestResult::from_bool(!PublicKey::verify_batch(messages, &signatures, &keys))
+    }
+
+    #[test]
+    fn verify_batch_swapped() {
+        let alice = SecretKey::new(rand::thread_rng());
+        let bob = SecretKey::new(rand::thread_rng());
+        let keys = [alice.public_key(), bob.public_key()];
+        let signatures = [alice.sign(b"alice"), bob.sign(b"bob")];
+
+        assert!(PublicKey::verify_batch(
+            &["alice", "bob"],
+            &signatures,
+            &keys
+        ));
+        assert!(!PublicKey::verify_batch(
+            &["bob", "alice"],
+            &signatures,
+            &keys
+        ));
+        assert!(PublicKey::verify_batch::<&[u8]>(&[], &[], &[]));
+    }
+
+    fn prehash(message: &[u8]) -> [u8; PREHASH_SIZE] {
+        let mut prehash = [0; PREHASH_SIZE];
+        let mut hasher = Sha512::new();
+        hasher.input(message);
+        hasher.result(&mut prehash);
+        prehash
+    }
+
+    /// test vector `TEST abc` of the Ed25519ph section of RFC 8032
+    #[test]
+    fn sign_prehashed_test_vector() {
+        let mut seed = [0; SecretKey::SIZE];
+        hex::decode_to_slice(
+            "833fe62409237b9d62ec77587520911e9a759cec1d19755b7da901b96dca3d42",
+            &mut seed,
+        )
+        .unwrap();
+        let signing_key = SecretKey::try_from(seed.as_ref()).unwrap();
+        let public_key = signing_key.public_key();
+        assert_eq!(
+            public_key.to_string(),
+            "ec172b93ad5e563bf4932c70e1245034c35467ef2efd4d64ebf819683467e2bf"
+        );
+
+        let signature = signing_key.sign_prehashed(&prehash(b"abc"));
+        assert_eq!(
+            signature.to_string(),
+            "98a70222f0b8121aa9d30f813d683f809e462b469c7ff87639499bb94e6dae41\
+             31f85042463c2a355a2003d062adf5aaa10b8c61e636062aaad11c2a26083406"
+        );
+        assert!(public_key.verify_prehashed(&prehash(b"abc"), &signature));
+    }
+
+    #[quickcheck]
+    fn sign_prehashed_verify_works(signing_key: SecretKey, message: Vec<u8>) -> bool {
+        let public_key = signing_key.public_key();
+        let prehash = prehash(&message);
+        let signature = signing_key.sign_prehashed(&prehash);
+
+        public_key.verify_prehashed(&prehash, &signature)
+            && !public_key.verify(prehash, &signature)
+            && !public_key.verify_prehashed(&self::prehash(b"other"), &signature)
+    }
+
     #[quickcheck]
     fn verify_random_signature_does_not_work(
         public_key: PublicKey,
@@ -476,6 +1152,9 @@ mod tests {
         match SecretKey::try_from(signing_key.leak_as_ref().as_ref()) {
             Ok(_) => TestResult::passed(),
             Err(SecretKeyError::InvalidSize) => TestResult::error("was expecting the test to pass"),
+            Err(SecretKeyError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 
@@ -489,6 +1168,9 @@ mod tests {
                 "Expecting to fail with invalid size instead of having a valid value",
             ),
             Err(SecretKeyError::InvalidSize) => TestResult::passed(),
+            Err(SecretKeyError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 
@@ -497,6 +1179,9 @@ mod tests {
         match PublicKey::try_from(public_key.as_ref()) {
             Ok(_) => TestResult::passed(),
             Err(PublicKeyError::InvalidSize) => TestResult::error("was expecting the test to pass"),
+            Err(PublicKeyError::InvalidHexadecimal(_)) | Err(PublicKeyError::InvalidBech32(_)) => {
+                unreachable!("We should not see an encoding error at all in this test")
+            }
         }
     }
 
@@ -510,6 +1195,9 @@ mod tests {
                 "Expecting to fail with invalid size instead of having a valid value",
             ),
             Err(PublicKeyError::InvalidSize) => TestResult::passed(),
+            Err(PublicKeyError::InvalidHexadecimal(_)) | Err(PublicKeyError::InvalidBech32(_)) => {
+                unreachable!("We should not see an encoding error at all in this test")
+            }
         }
     }
 
@@ -518,6 +1206,9 @@ mod tests {
         match Signature::try_from(signature.as_ref()) {
             Ok(_) => TestResult::passed(),
             Err(SignatureError::InvalidSize) => TestResult::error("was expecting the test to pass"),
+            Err(SignatureError::InvalidHexadecimal(_)) | Err(SignatureError::InvalidBech32(_)) => {
+                unreachable!("We should not see an encoding error at all in this test")
+            }
         }
     }
 
@@ -531,6 +1222,9 @@ mod tests {
                 "Expecting to fail with invalid size instead of having a valid value",
             ),
             Err(SignatureError::InvalidSize) => TestResult::passed(),
+            Err(SignatureError::InvalidHexadecimal(_)) | Err(SignatureError::InvalidBech32(_)) => {
+                unreachable!("We should not see an encoding error at all in this test")
+            }
         }
     }
 
@@ -1,6 +1,7 @@
 use crate::{
-    key::SharedSecret,
-    memsec::{self, Scrubbed as _},
+    key::{ed25519::sign_prehashed_extended, vrf, SharedSecret},
+    memsec::Scrubbed as _,
+    Seed,
 };
 use cryptoxide::{
     curve25519::{curve25519, Fe},
@@ -13,12 +14,13 @@ use std::{
     hash::{Hash, Hasher},
     str::FromStr,
 };
+use subtle::{Choice, ConstantTimeEq};
 use thiserror::Error;
 
 #[derive(Clone)]
 pub struct SecretKey([u8; Self::SIZE]);
 
-pub use crate::key::ed25519::{PublicKey, PublicKeyError, Signature};
+pub use crate::key::ed25519::{PublicKey, PublicKeyError, Signature, PREHASH_SIZE};
 
 impl SecretKey {
     pub const SIZE: usize = ed25519::PRIVATE_KEY_LENGTH;
@@ -29,6 +31,16 @@ impl SecretKey {
         Self([0; Self::SIZE])
     }
 
+    /// generate a new `SecretKey` with the operating system's
+    /// random number generator
+    ///
+    /// use [`SecretKey::new`] to provide a different random number
+    /// generator.
+    #[cfg(feature = "getrandom")]
+    pub fn generate() -> Self {
+        Self::new(rand_core::OsRng)
+    }
+
     /// generate a new `SecretKey` with the given random number generator
     ///
     pub fn new<Rng>(mut rng: Rng) -> Self
@@ -50,6 +62,20 @@ impl SecretKey {
         s
     }
 
+    /// deterministically generate a `SecretKey` from the given [`Seed`]
+    ///
+    /// the bit tweaks of the extended key are applied just like with
+    /// [`SecretKey::new`].
+    pub fn from_seed(seed: &Seed) -> Self {
+        Self::new(seed.clone().into_rand_chacha())
+    }
+
+    /// deterministically generate a `SecretKey` from an arbitrary byte
+    /// string, see [`Seed::from_phrase`]
+    pub fn from_seed_phrase<P: AsRef<[u8]>>(phrase: P) -> Self {
+        Self::from_seed(&Seed::from_phrase(phrase))
+    }
+
     pub(crate) fn clear_3rd_highest_bit(&mut self) {
         self.0[31] &= 0b1101_1111;
     }
@@ -97,6 +123,22 @@ impl SecretKey {
         Signature::from(signature)
     }
 
+    /// create an Ed25519ph `Signature` of the SHA512 `prehash` of the
+    /// message, see [`ed25519::SecretKey::sign_prehashed`]
+    ///
+    /// [`ed25519::SecretKey::sign_prehashed`]: crate::key::ed25519::SecretKey::sign_prehashed
+    pub fn sign_prehashed(&self, prehash: &[u8; PREHASH_SIZE]) -> Signature {
+        sign_prehashed_extended(&self.0, prehash)
+    }
+
+    /// compute the VRF output of `alpha` and the proof, see
+    /// [`ed25519::SecretKey::vrf_prove`]
+    ///
+    /// [`ed25519::SecretKey::vrf_prove`]: crate::key::ed25519::SecretKey::vrf_prove
+    pub fn vrf_prove<T: AsRef<[u8]>>(&self, alpha: T) -> (vrf::Output, vrf::Proof) {
+        vrf::prove(&self.0, alpha.as_ref())
+    }
+
     /// get a reference to the inner Seed bytes
     ///
     /// # Security Consideration
@@ -145,11 +187,18 @@ impl Debug for SecretKey {
 /* Conversion ************************************************************** */
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum SecretKeyError {
     #[error("Invalid size, expecting {}", SecretKey::SIZE)]
     InvalidSize,
     #[error("Invalid structure")]
     InvalidStructure,
+    #[error("Invalid hexadecimal string")]
+    InvalidHexadecimal(
+        #[source]
+        #[from]
+        hex::FromHexError,
+    ),
 }
 
 impl TryFrom<[u8; Self::SIZE]> for SecretKey {
@@ -179,19 +228,30 @@ impl<'a> TryFrom<&'a [u8]> for SecretKey {
 }
 
 impl FromStr for SecretKey {
-    type Err = hex::FromHexError;
+    type Err = SecretKeyError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut r = Self::zero();
-        hex::decode_to_slice(s, &mut r.0)?;
-        Ok(r)
+        let mut r = [0; Self::SIZE];
+        hex::decode_to_slice(s, &mut r)?;
+
+        let sk = Self::try_from(r);
+
+        r.scrub();
+
+        sk
     }
 }
 
 /* Eq ********************************************************************** */
 
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0[..].ct_eq(&other.0[..])
+    }
+}
+
 impl PartialEq<Self> for SecretKey {
     fn eq(&self, other: &Self) -> bool {
-        unsafe { memsec::memeq(self.0.as_ptr(), other.0.as_ptr(), Self::SIZE) }
+        self.ct_eq(other).into()
     }
 }
 
@@ -237,6 +297,17 @@ mod tests {
         }
     }
 
+    #[quickcheck]
+    fn sign_prehashed_verify_works(signing_key: SecretKey, prehash: Vec<u8>) -> bool {
+        let mut bytes = [0; PREHASH_SIZE];
+        bytes.iter_mut().zip(prehash).for_each(|(b, p)| *b = p);
+        let signature = signing_key.sign_prehashed(&bytes);
+
+        signing_key
+            .public_key()
+            .verify_prehashed(&bytes, &signature)
+    }
+
     #[quickcheck]
     fn verify_exchange_works(alice: SecretKey, bob: SecretKey) -> bool {
         let alice_pk = alice.public_key();
@@ -263,6 +334,9 @@ mod tests {
             Err(SecretKeyError::InvalidStructure) => {
                 TestResult::error("was expecting the test to pass, not an invalid structure")
             }
+            Err(SecretKeyError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 
@@ -279,6 +353,9 @@ mod tests {
             Err(SecretKeyError::InvalidStructure) => {
                 TestResult::error("was expecting an invalid size error, not an invalid structure")
             }
+            Err(SecretKeyError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 
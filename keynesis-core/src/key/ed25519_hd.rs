@@ -1,11 +1,20 @@
+#[cfg(feature = "mnemonic")]
+use crate::mnemonic::Mnemonic;
 use crate::{
-    key::{ed25519_extended, SharedSecret},
+    bech32::{self, Bech32Error},
+    canonical::{Canonical, CanonicalError},
+    kdf::{Kdf, KdfError},
+    key::{derivation_path::AsDerivationPath, ed25519_extended, vrf, Fingerprint, SharedSecret},
     memsec::Scrubbed as _,
+    Seed,
 };
+#[cfg(feature = "mnemonic")]
+use cryptoxide::sha2::Sha256;
 use cryptoxide::{
     curve25519::{ge_scalarmult_base, GeP3},
     hmac::Hmac,
     mac::Mac,
+    pbkdf2::pbkdf2,
     sha2::Sha512,
 };
 use packtool::Packed;
@@ -13,9 +22,11 @@ use rand_core::{CryptoRng, RngCore};
 use std::{
     convert::TryFrom,
     fmt::{self, Debug, Display, Formatter},
+    hash::{Hash, Hasher},
     ops::Deref,
     str::FromStr,
 };
+use subtle::{Choice, ConstantTimeEq};
 use thiserror::Error;
 
 #[derive(Packed, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash)]
@@ -24,7 +35,7 @@ pub struct ChainCode(
     #[packed(accessor = false)] [u8; Self::SIZE],
 );
 
-#[derive(Clone, Eq, PartialEq, Hash)]
+#[derive(Clone)]
 pub struct SecretKey {
     key: ed25519_extended::SecretKey,
     chain_code: ChainCode,
@@ -39,7 +50,11 @@ pub struct PublicKey {
     chain_code: ChainCode,
 }
 
-pub use crate::key::ed25519::Signature;
+pub use crate::key::ed25519::{Signature, PREHASH_SIZE};
+
+/// the indices from this one are hardened derivations, see
+/// [`SecretKey::derive_index`]
+pub const HARDENED_INDEX: u32 = 0x8000_0000;
 
 impl ChainCode {
     pub const SIZE: usize = 32;
@@ -65,6 +80,19 @@ impl ChainCode {
 impl SecretKey {
     pub const SIZE: usize = ed25519_extended::SecretKey::SIZE + ChainCode::SIZE;
 
+    /// size of the path once stretched by [`derive_slow`](Self::derive_slow)
+    const SLOW_PATH_SIZE: usize = 32;
+
+    /// generate a new root `SecretKey` with the operating system's
+    /// random number generator
+    ///
+    /// use [`SecretKey::new`] to provide a different random number
+    /// generator.
+    #[cfg(feature = "getrandom")]
+    pub fn generate() -> Self {
+        Self::new(rand_core::OsRng)
+    }
+
     /// generate a new `SecretKey` with the given random number generator
     ///
     pub fn new<Rng>(mut rng: Rng) -> Self
@@ -86,6 +114,111 @@ impl SecretKey {
         s
     }
 
+    /// deterministically generate a root `SecretKey` (key and chain code)
+    /// from the given [`Seed`]
+    pub fn from_seed(seed: &Seed) -> Self {
+        Self::new(seed.clone().into_rand_chacha())
+    }
+
+    /// deterministically generate a `SecretKey` from an arbitrary byte
+    /// string, see [`Seed::from_phrase`]
+    pub fn from_seed_phrase<P: AsRef<[u8]>>(phrase: P) -> Self {
+        Self::from_seed(&Seed::from_phrase(phrase))
+    }
+
+    /// generate a new root `SecretKey` and the [`Mnemonic`] to restore
+    /// it with [`from_mnemonic`](Self::from_mnemonic) and the same
+    /// `passphrase`
+    #[cfg(feature = "mnemonic")]
+    pub fn new_with_mnemonic<Rng>(rng: Rng, passphrase: &str) -> (Self, Mnemonic)
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let mnemonic = Mnemonic::new(rng);
+        let key = Self::from_mnemonic(&mnemonic, passphrase);
+        (key, mnemonic)
+    }
+
+    /// restore the root `SecretKey` from the BIP39 [`Mnemonic`] and the
+    /// `passphrase` (that may be empty)
+    ///
+    /// the root key is generated from the BIP39 seed as described in
+    /// [SLIP-0023].
+    ///
+    /// [SLIP-0023]: https://github.com/satoshilabs/slips/blob/master/slip-0023.md
+    #[cfg(feature = "mnemonic")]
+    pub fn from_mnemonic(mnemonic: &Mnemonic, passphrase: &str) -> Self {
+        const KEY: &[u8] = b"ed25519 cardano seed";
+
+        let mut seed = mnemonic.to_seed(passphrase);
+
+        let mut bytes = [0; ed25519_extended::SecretKey::SIZE];
+        let mut mac = Hmac::new(Sha512::new(), KEY);
+        mac.input(&seed);
+        mac.raw_result(&mut bytes);
+        // the root key needs the 3rd highest bit cleared, try again until it is
+        while bytes[31] & 0b0010_0000 != 0 {
+            let mut mac = Hmac::new(Sha512::new(), KEY);
+            mac.input(&bytes);
+            mac.raw_result(&mut bytes);
+        }
+        bytes[0] &= 0b1111_1000;
+        bytes[31] &= 0b0111_1111;
+        bytes[31] |= 0b0100_0000;
+
+        let mut chain_code = [0; ChainCode::SIZE];
+        let mut mac = Hmac::new(Sha256::new(), KEY);
+        mac.input(&[1]);
+        mac.input(&seed);
+        mac.raw_result(&mut chain_code);
+
+        let key = ed25519_extended::SecretKey::try_from(bytes.as_ref())
+            .expect("the key is clamped so it has a valid structure");
+        let s = Self {
+            key,
+            chain_code: ChainCode(chain_code),
+        };
+
+        seed.scrub();
+        bytes.scrub();
+        chain_code.scrub();
+
+        s
+    }
+
+    /// restore the root `SecretKey` from the BIP39 [`Mnemonic`] and the
+    /// `passphrase` the way the Cardano Icarus wallets do, see
+    /// [`from_icarus_entropy`](Self::from_icarus_entropy)
+    #[cfg(feature = "mnemonic")]
+    pub fn from_mnemonic_icarus(mnemonic: &Mnemonic, passphrase: &str) -> Self {
+        Self::from_icarus_entropy(mnemonic.entropy(), passphrase.as_bytes())
+    }
+
+    /// generate the root `SecretKey` from the entropy of the mnemonic
+    /// and the `passphrase` as described in [CIP-3] (the Icarus master
+    /// key generation)
+    ///
+    /// with [`derive_index`](Self::derive_index) the derived keys are
+    /// the keys of the Cardano wallets.
+    ///
+    /// [CIP-3]: https://cips.cardano.org/cip/CIP-0003
+    pub fn from_icarus_entropy(entropy: &[u8], passphrase: &[u8]) -> Self {
+        const ITERATIONS: u32 = 4096;
+
+        let mut bytes = [0; Self::SIZE];
+        let mut mac = Hmac::new(Sha512::new(), passphrase);
+        pbkdf2(&mut mac, entropy, ITERATIONS, &mut bytes);
+        bytes[0] &= 0b1111_1000;
+        bytes[31] &= 0b0001_1111;
+        bytes[31] |= 0b0100_0000;
+
+        let s = Self::try_from(bytes).expect("the key is clamped so it has a valid structure");
+
+        bytes.scrub();
+
+        s
+    }
+
     #[inline]
     pub fn is_3rd_highest_bit_clear(&self) -> bool {
         self.key.is_3rd_highest_bit_clear()
@@ -118,7 +251,7 @@ impl SecretKey {
     }
 
     pub fn into_key(self) -> ed25519_extended::SecretKey {
-        self.key
+        self.key.clone()
     }
 
     /// generate a shared secret between the owner of the given public key and
@@ -139,10 +272,83 @@ impl SecretKey {
         self.key.sign(msg)
     }
 
+    /// create an Ed25519ph `Signature` of the SHA512 `prehash` of the
+    /// message, see [`ed25519::SecretKey::sign_prehashed`]
+    ///
+    /// [`ed25519::SecretKey::sign_prehashed`]: crate::key::ed25519::SecretKey::sign_prehashed
+    pub fn sign_prehashed(&self, prehash: &[u8; PREHASH_SIZE]) -> Signature {
+        self.key.sign_prehashed(prehash)
+    }
+
+    /// compute the VRF output of `alpha` and the proof, see
+    /// [`ed25519::SecretKey::vrf_prove`]
+    ///
+    /// [`ed25519::SecretKey::vrf_prove`]: crate::key::ed25519::SecretKey::vrf_prove
+    pub fn vrf_prove<T: AsRef<[u8]>>(&self, alpha: T) -> (vrf::Output, vrf::Proof) {
+        self.key.vrf_prove(alpha)
+    }
+
+    /// the extended secret key and the chain code, see
+    /// `TryFrom<[u8; Self::SIZE]>`
+    pub(crate) fn leak_to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..ed25519_extended::SecretKey::SIZE].copy_from_slice(self.key.leak_as_ref());
+        bytes[ed25519_extended::SecretKey::SIZE..].copy_from_slice(self.chain_code.as_ref());
+        bytes
+    }
+
+    /// derive a new secret key for the given path
+    ///
+    /// the path is a slice of bytes or a [`DerivationPath`], the key is
+    /// then derived with every segment one after the other.
+    ///
+    /// [`DerivationPath`]: super::derivation_path::DerivationPath
     pub fn derive<P>(&self, path: P) -> Self
     where
-        P: AsRef<[u8]>,
+        P: AsDerivationPath,
     {
+        self.derive_segments(path, false)
+    }
+
+    /// derive a new secret key for the given path with the hardened
+    /// derivation
+    ///
+    /// unlike [`derive`](Self::derive) the derived key uses the secret
+    /// key instead of the public key, so the derived public key cannot be
+    /// computed from the public key of `self`. It also means that
+    /// leaking the derived secret key and the public key of `self` does
+    /// not leak the secret key of `self`, use it to protect the root
+    /// keys.
+    pub fn derive_hardened<P>(&self, path: P) -> Self
+    where
+        P: AsDerivationPath,
+    {
+        self.derive_segments(path, true)
+    }
+
+    /// derive a new secret key with the integer `index` of BIP32-Ed25519
+    ///
+    /// the indices from [`HARDENED_INDEX`] are hardened derivations (see
+    /// [`derive_hardened`](Self::derive_hardened)). The index is encoded
+    /// in 4 bytes little endian so the derivation is compatible with the
+    /// Cardano wallets (see [`from_icarus_entropy`](Self::from_icarus_entropy)).
+    pub fn derive_index(&self, index: u32) -> Self {
+        self.derive_segment(&index.to_le_bytes(), index >= HARDENED_INDEX)
+    }
+
+    fn derive_segments<P>(&self, path: P, hardened: bool) -> Self
+    where
+        P: AsDerivationPath,
+    {
+        let mut segments = path.segments().into_iter();
+        let first = match segments.next() {
+            None => return self.clone(),
+            Some(segment) => self.derive_segment(segment, hardened),
+        };
+        segments.fold(first, |key, segment| key.derive_segment(segment, hardened))
+    }
+
+    fn derive_segment(&self, path: &[u8], hardened: bool) -> Self {
         let e_key = &self.key.leak_as_ref()[0..64];
         let kl = &e_key[0..32];
         let kr = &e_key[32..64];
@@ -150,14 +356,21 @@ impl SecretKey {
 
         let mut z_mac = Hmac::new(Sha512::new(), chaincode);
         let mut i_mac = Hmac::new(Sha512::new(), chaincode);
-        let pk = self.public_key();
-        let pk = pk.key().as_ref();
-        z_mac.input(&[0x2]);
-        z_mac.input(pk);
-        z_mac.input(path.as_ref());
-        i_mac.input(&[0x3]);
-        i_mac.input(pk);
-        i_mac.input(path.as_ref());
+        if hardened {
+            z_mac.input(&[0x0]);
+            z_mac.input(e_key);
+            i_mac.input(&[0x1]);
+            i_mac.input(e_key);
+        } else {
+            let pk = self.public_key();
+            let pk = pk.key().as_ref();
+            z_mac.input(&[0x2]);
+            z_mac.input(pk);
+            i_mac.input(&[0x3]);
+            i_mac.input(pk);
+        }
+        z_mac.input(path);
+        i_mac.input(path);
 
         let mut z_out = [0u8; 64];
         z_mac.raw_result(&mut z_out);
@@ -187,6 +400,27 @@ impl SecretKey {
 
         Self::try_from(out).unwrap()
     }
+
+    /// derive a new secret key for a low entropy path (a PIN, the answer
+    /// to a question...)
+    ///
+    /// the path is first stretched with the memory hard `kdf`, salted
+    /// with the chain code, so every guess of an offline attacker costs
+    /// as much as a run of the `kdf`. The stretched path is then used
+    /// with [`derive`](Self::derive).
+    pub fn derive_slow<P>(&self, path: P, kdf: &Kdf) -> Result<Self, KdfError>
+    where
+        P: AsRef<[u8]>,
+    {
+        let mut stretched = [0; Self::SLOW_PATH_SIZE];
+        kdf.derive(path.as_ref(), self.chain_code.as_ref(), &mut stretched)?;
+
+        let derived = self.derive(&stretched[..]);
+
+        stretched.scrub();
+
+        Ok(derived)
+    }
 }
 
 impl PublicKey {
@@ -200,6 +434,18 @@ impl PublicKey {
         &self.key
     }
 
+    /// the fingerprint of the key, the chain code is not part of it so
+    /// it is the same as the [`fingerprint`](ed25519_extended::PublicKey::fingerprint)
+    /// of the [`key`](Self::key)
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.key.fingerprint()
+    }
+
+    /// check the key has the given `fingerprint`
+    pub fn matches_fingerprint(&self, fingerprint: &Fingerprint) -> bool {
+        self.key.matches_fingerprint(fingerprint)
+    }
+
     pub fn into_key(self) -> ed25519_extended::PublicKey {
         self.key
     }
@@ -208,10 +454,48 @@ impl PublicKey {
         &self.chain_code
     }
 
-    pub fn derive<P>(&self, path: P) -> Option<Self>
+    /// encode the public key and its chain code in a bech32 string with
+    /// the given human readable part
+    ///
+    /// the chain code is included so the receiver can derive the child
+    /// public keys too.
+    pub fn to_bech32_str(&self, hrp: &str) -> String {
+        bech32::encode(hrp, self.to_canonical_bytes())
+    }
+
+    /// decode a public key from a bech32 string, the human readable part
+    /// needs to be the given `hrp`.
+    pub fn from_bech32_str(hrp: &str, s: &str) -> Result<Self, PublicKeyError> {
+        let bytes = bech32::decode_with_hrp(hrp, s)?;
+        Self::try_from(bytes.as_slice())
+    }
+
+    /// derive a new public key for the given path, see
+    /// [`SecretKey::derive`]
+    ///
+    /// this will fail if the public key or the derived point are not
+    /// valid points of the curve.
+    pub fn derive<P>(&self, path: P) -> Result<Self, DerivationError>
     where
-        P: AsRef<[u8]>,
+        P: AsDerivationPath,
     {
+        path.segments()
+            .into_iter()
+            .try_fold(self.clone(), |key, segment| key.derive_segment(segment))
+    }
+
+    /// derive a new public key with the integer `index`, see
+    /// [`SecretKey::derive_index`]
+    ///
+    /// the hardened indices cannot be derived from the public key.
+    pub fn derive_index(&self, index: u32) -> Result<Self, DerivationError> {
+        if index >= HARDENED_INDEX {
+            return Err(DerivationError::HardenedIndex(index));
+        }
+        self.derive_segment(&index.to_le_bytes())
+    }
+
+    fn derive_segment(&self, path: &[u8]) -> Result<Self, DerivationError> {
         let pk = self.key().as_ref();
         let chaincode = self.chain_code().as_ref();
 
@@ -219,10 +503,10 @@ impl PublicKey {
         let mut i_mac = Hmac::new(Sha512::new(), chaincode);
         z_mac.input(&[0x2]);
         z_mac.input(pk);
-        z_mac.input(path.as_ref());
+        z_mac.input(path);
         i_mac.input(&[0x3]);
         i_mac.input(pk);
-        i_mac.input(path.as_ref());
+        i_mac.input(path);
 
         let mut z_out = [0u8; 64];
         z_mac.raw_result(&mut z_out);
@@ -230,7 +514,8 @@ impl PublicKey {
         let _zr = &z_out[32..64];
 
         // left = kl + 8 * trunc28(zl)
-        let left = point_plus(pk, &point_of_trunc28_mul8(zl))?;
+        let left =
+            point_plus(pk, &point_of_trunc28_mul8(zl)).ok_or(DerivationError::InvalidPoint)?;
 
         let mut i_out = [0u8; 64];
         i_mac.raw_result(&mut i_out);
@@ -243,7 +528,7 @@ impl PublicKey {
         i_mac.reset();
         z_mac.reset();
 
-        Some(Self::from(out))
+        Ok(Self::from(out))
     }
 }
 
@@ -299,6 +584,37 @@ fn add_256bits(x: &[u8], y: &[u8]) -> [u8; 32] {
     out
 }
 
+/* Eq ********************************************************************** */
+
+impl ConstantTimeEq for ChainCode {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0[..].ct_eq(&other.0[..])
+    }
+}
+
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.key.ct_eq(&other.key) & self.chain_code.ct_eq(&other.chain_code)
+    }
+}
+
+impl PartialEq<Self> for SecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for SecretKey {}
+
+/* Hash ******************************************************************** */
+
+impl Hash for SecretKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+        self.chain_code.hash(state);
+    }
+}
+
 /* Deref ******************************************************************* */
 
 impl Deref for PublicKey {
@@ -313,7 +629,7 @@ impl Deref for PublicKey {
 impl Debug for ChainCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_tuple("ChainCode")
-            .field(&hex::encode(&self.0))
+            .field(&hex::encode(self.0))
             .finish()
     }
 }
@@ -338,7 +654,7 @@ impl Debug for PublicKey {
 
 impl Display for ChainCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Display::fmt(&hex::encode(&self.0), f)
+        Display::fmt(&hex::encode(self.0), f)
     }
 }
 
@@ -371,9 +687,29 @@ impl From<[u8; Self::SIZE]> for PublicKey {
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum DerivationError {
+    #[error("Cannot derive from an invalid curve point")]
+    InvalidPoint,
+
+    #[error("Cannot derive the hardened index {0} from a public key")]
+    HardenedIndex(u32),
+
+    #[error("Cannot derive beyond the maximum depth")]
+    MaximumDepth,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum ChainCodeError {
     #[error("Invalid size, expecting {}", ChainCode::SIZE)]
     InvalidSize,
+    #[error("Invalid hexadecimal string")]
+    InvalidHexadecimal(
+        #[source]
+        #[from]
+        hex::FromHexError,
+    ),
 }
 
 impl<'a> TryFrom<&'a [u8]> for ChainCode {
@@ -390,6 +726,7 @@ impl<'a> TryFrom<&'a [u8]> for ChainCode {
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum PublicKeyError {
     #[error("Invalid size, expecting {}", PublicKey::SIZE)]
     InvalidSize,
@@ -405,6 +742,18 @@ pub enum PublicKeyError {
         #[source]
         ChainCodeError,
     ),
+    #[error("Invalid hexadecimal string")]
+    InvalidHexadecimal(
+        #[source]
+        #[from]
+        hex::FromHexError,
+    ),
+    #[error("Invalid bech32 string")]
+    InvalidBech32(
+        #[source]
+        #[from]
+        Bech32Error,
+    ),
 }
 
 impl<'a> TryFrom<&'a [u8]> for PublicKey {
@@ -424,6 +773,7 @@ impl<'a> TryFrom<&'a [u8]> for PublicKey {
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum SecretKeyError {
     #[error("Invalid size, expecting {}", SecretKey::SIZE)]
     InvalidSize,
@@ -473,6 +823,12 @@ impl<'a> TryFrom<&'a [u8]> for SecretKey {
             Err(ed25519_extended::SecretKeyError::InvalidStructure) => {
                 Err(Self::Error::InvalidStructure)
             }
+            Err(error) => {
+                unreachable!(
+                    "Reading the extended key from bytes cannot fail with: {}",
+                    error
+                )
+            }
         }
     }
 }
@@ -492,16 +848,16 @@ impl FromStr for SecretKey {
 }
 
 impl FromStr for PublicKey {
-    type Err = hex::FromHexError;
+    type Err = PublicKeyError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut r = [0; Self::SIZE];
         hex::decode_to_slice(s, &mut r)?;
-        Ok(Self::from(r))
+        Self::try_from(&r[..])
     }
 }
 
 impl FromStr for ChainCode {
-    type Err = hex::FromHexError;
+    type Err = ChainCodeError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut r = [0; Self::SIZE];
         hex::decode_to_slice(s, &mut r)?;
@@ -509,6 +865,30 @@ impl FromStr for ChainCode {
     }
 }
 
+/* Canonical *************************************************************** */
+
+impl Canonical for PublicKey {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.key.to_canonical_bytes();
+        bytes.extend_from_slice(self.chain_code.as_ref());
+        bytes
+    }
+
+    /// the key needs to be canonical, any chain code is valid
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        if bytes.len() != Self::SIZE {
+            return Err(CanonicalError::InvalidEncoding);
+        }
+
+        let (key, chain_code) = bytes.split_at(ed25519_extended::PublicKey::SIZE);
+        let key = ed25519_extended::PublicKey::from_bytes_strict(key)?;
+        let chain_code =
+            ChainCode::try_from(chain_code).map_err(|_| CanonicalError::InvalidEncoding)?;
+
+        Ok(Self { key, chain_code })
+    }
+}
+
 /* AsRef ******************************************************************* */
 
 impl AsRef<[u8]> for ChainCode {
@@ -523,9 +903,20 @@ impl AsRef<ed25519_extended::PublicKey> for PublicKey {
     }
 }
 
+/* Drop ******************************************************************** */
+
+/// the key is scrubbed by [`ed25519_extended::SecretKey`], the chain code
+/// is scrubbed (zeroed) here as it is needed to derive the child keys
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.chain_code.0.scrub()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::key::derivation_path::DerivationPath;
     use quickcheck::{Arbitrary, Gen, TestResult};
 
     impl Arbitrary for ChainCode {
@@ -576,6 +967,33 @@ mod tests {
         public_key.verify(message, &signature)
     }
 
+    #[quickcheck]
+    fn bech32_encode_decode(signing_key: SecretKey) -> bool {
+        let public_key = signing_key.public_key();
+        let s = public_key.to_bech32_str("xpub");
+
+        PublicKey::from_bech32_str("xpub", &s).unwrap() == public_key
+            && matches!(
+                PublicKey::from_bech32_str("pk", &s),
+                Err(PublicKeyError::InvalidBech32(
+                    Bech32Error::UnexpectedHrp { .. }
+                ))
+            )
+    }
+
+    #[quickcheck]
+    fn canonical_encoding(signing_key: SecretKey) -> bool {
+        let public_key = signing_key.public_key();
+        let mut bytes = public_key.to_canonical_bytes();
+        let decoded = PublicKey::from_bytes_strict(&bytes).unwrap();
+
+        bytes[..ed25519_extended::PublicKey::SIZE]
+            .copy_from_slice(&crate::canonical::tests::NON_CANONICAL_POINT);
+
+        decoded == public_key
+            && PublicKey::from_bytes_strict(&bytes) == Err(CanonicalError::NonCanonical)
+    }
+
     #[quickcheck]
     fn signing_key_try_from_correct_size(signing_key: SecretKey) -> TestResult {
         let mut bytes = signing_key.key.leak_as_ref().to_vec();
@@ -585,7 +1003,7 @@ mod tests {
             Err(SecretKeyError::InvalidSize) => {
                 TestResult::error("was expecting the test to pass, not an invalid size")
             }
-            Err(SecretKeyError::InvalidChainCode(ChainCodeError::InvalidSize)) => {
+            Err(SecretKeyError::InvalidChainCode(_)) => {
                 unreachable!("The total size of the key is already being checked")
             }
             Err(SecretKeyError::InvalidStructure) => {
@@ -607,7 +1025,7 @@ mod tests {
                 "Expecting to fail with invalid size instead of having a valid value",
             ),
             Err(SecretKeyError::InvalidSize) => TestResult::passed(),
-            Err(SecretKeyError::InvalidChainCode(ChainCodeError::InvalidSize)) => {
+            Err(SecretKeyError::InvalidChainCode(_)) => {
                 unreachable!("The total size of the key is already being checked")
             }
             Err(SecretKeyError::InvalidStructure) => {
@@ -628,12 +1046,12 @@ mod tests {
             Err(PublicKeyError::InvalidSize) => {
                 TestResult::error("was expecting the test to pass, not an invalid size")
             }
-            Err(PublicKeyError::InvalidPublicKey(
-                ed25519_extended::PublicKeyError::InvalidSize,
-            )) => unreachable!("The total size of the key is already being checked"),
-            Err(PublicKeyError::InvalidChainCode(ChainCodeError::InvalidSize)) => {
+            Err(PublicKeyError::InvalidPublicKey(_)) | Err(PublicKeyError::InvalidChainCode(_)) => {
                 unreachable!("The total size of the key is already being checked")
             }
+            Err(PublicKeyError::InvalidHexadecimal(_)) | Err(PublicKeyError::InvalidBech32(_)) => {
+                unreachable!("We should not see an encoding error at all in this test")
+            }
         }
     }
 
@@ -647,12 +1065,12 @@ mod tests {
                 "Expecting to fail with invalid size instead of having a valid value",
             ),
             Err(PublicKeyError::InvalidSize) => TestResult::passed(),
-            Err(PublicKeyError::InvalidPublicKey(
-                ed25519_extended::PublicKeyError::InvalidSize,
-            )) => unreachable!("The total size of the key is already being checked"),
-            Err(PublicKeyError::InvalidChainCode(ChainCodeError::InvalidSize)) => {
+            Err(PublicKeyError::InvalidPublicKey(_)) | Err(PublicKeyError::InvalidChainCode(_)) => {
                 unreachable!("The total size of the key is already being checked")
             }
+            Err(PublicKeyError::InvalidHexadecimal(_)) | Err(PublicKeyError::InvalidBech32(_)) => {
+                unreachable!("We should not see an encoding error at all in this test")
+            }
         }
     }
 
@@ -663,6 +1081,9 @@ mod tests {
             Err(ChainCodeError::InvalidSize) => {
                 TestResult::error("was expecting the test to pass, not an invalid size")
             }
+            Err(ChainCodeError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 
@@ -676,6 +1097,9 @@ mod tests {
                 "Expecting to fail with invalid size instead of having a valid value",
             ),
             Err(ChainCodeError::InvalidSize) => TestResult::passed(),
+            Err(ChainCodeError::InvalidHexadecimal(_)) => {
+                unreachable!("We should not see an hexadecimal error at all in this test")
+            }
         }
     }
 
@@ -697,9 +1121,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn public_key_from_str_invalid_hexadecimal() {
+        use std::error::Error as _;
+
+        let error = "not an hexadecimal string"
+            .parse::<PublicKey>()
+            .unwrap_err();
+
+        assert!(matches!(error, PublicKeyError::InvalidHexadecimal(_)));
+        assert!(error
+            .source()
+            .unwrap()
+            .downcast_ref::<hex::FromHexError>()
+            .is_some());
+    }
+
+    #[test]
+    fn derive_slow() {
+        use crate::kdf::ScryptParams;
+
+        let root = SecretKey::new(rand::thread_rng());
+        let kdf = Kdf::Scrypt(ScryptParams::new(4, 8, 1).unwrap());
+
+        let derived = root.derive_slow(b"1234", &kdf).unwrap();
+
+        // deterministic but different from the fast derivation
+        assert_eq!(
+            derived.public_key(),
+            root.derive_slow(b"1234", &kdf).unwrap().public_key()
+        );
+        assert_ne!(derived.public_key(), root.derive(b"1234").public_key());
+        assert_ne!(
+            derived.public_key(),
+            root.derive_slow(b"1235", &kdf).unwrap().public_key()
+        );
+    }
+
+    #[test]
+    fn derive_invalid_point() {
+        // the y coordinate 2 has no valid x coordinate on the curve
+        let mut bytes = [0; PublicKey::SIZE];
+        bytes[0] = 2;
+        let public_key = PublicKey::from(bytes);
+
+        assert!(matches!(
+            public_key.derive(b"path"),
+            Err(DerivationError::InvalidPoint)
+        ));
+    }
+
     #[quickcheck]
     fn chain_code_from_str(chain_code: ChainCode) -> TestResult {
-        let s = hex::encode(&chain_code);
+        let s = hex::encode(chain_code);
 
         match s.parse::<ChainCode>() {
             Ok(decoded) => {
@@ -713,6 +1187,21 @@ mod tests {
         }
     }
 
+    #[quickcheck]
+    fn from_seed_is_deterministic(seed: Seed) -> bool {
+        let key = SecretKey::from_seed(&seed);
+
+        key.is_3rd_highest_bit_clear() && key == SecretKey::from_seed(&seed)
+    }
+
+    #[quickcheck]
+    fn from_seed_phrase_is_deterministic(phrase: String) -> bool {
+        let key = SecretKey::from_seed_phrase(&phrase);
+
+        key == SecretKey::from_seed_phrase(&phrase)
+            && key == SecretKey::from_seed(&Seed::from_phrase(&phrase))
+    }
+
     #[quickcheck]
     fn derivation_from_signing_and_public_key(root_key: SecretKey, path: Vec<u8>) -> TestResult {
         let root_public_key = root_key.public_key();
@@ -723,6 +1212,68 @@ mod tests {
         TestResult::from_bool(Some(d1.public_key()) == Some(d2))
     }
 
+    /// test vector of the CIP-3 (Icarus) and the account key
+    /// m/1852'/1815'/0' of the Cardano wallets
+    #[test]
+    fn icarus_vector() {
+        let entropy = hex::decode("46e62370a138a182a498b8e2885bc032379ddf38").unwrap();
+        let root = SecretKey::from_icarus_entropy(&entropy, b"");
+        assert_eq!(
+            hex::encode(root.leak_to_bytes()),
+            "c065afd2832cd8b087c4d9ab7011f481ee1e0721e78ea5dd609f3ab3f156d245\
+             d176bd8fd4ec60b4731c3918a2a72a0226c0cd119ec35b47e4d55884667f552a\
+             23f7fdcd4a10c6cd2c7393ac61d877873e248f417634aa3d812af327ffe9d620"
+        );
+
+        let account = root
+            .derive_index(1852 | HARDENED_INDEX)
+            .derive_index(1815 | HARDENED_INDEX)
+            .derive_index(HARDENED_INDEX);
+        assert_eq!(
+            hex::encode(account.leak_to_bytes()),
+            "f80081fa05eece83236e612463aafad20d6b92eee67479a1977959540057d245\
+             2173fe9a0fccf61cf2cc7c52638f2ded6c08002a71424ca5b93681ee7a385828\
+             332b13689518700be3c6d330d72490c42e8a98b7495889a27851e543319fb095"
+        );
+    }
+
+    #[quickcheck]
+    fn derive_index(root_key: SecretKey, index: u32) -> bool {
+        let derived = root_key.derive_index(index);
+        let public = root_key.public_key().derive_index(index);
+
+        if index >= HARDENED_INDEX {
+            derived == root_key.derive_hardened(index.to_le_bytes())
+                && matches!(public, Err(DerivationError::HardenedIndex(_)))
+        } else {
+            derived == root_key.derive(index.to_le_bytes())
+                && public.unwrap() == derived.public_key()
+        }
+    }
+
+    #[quickcheck]
+    fn hardened_derivation(root_key: SecretKey, path: Vec<u8>) -> bool {
+        let hardened = root_key.derive_hardened(&path);
+
+        hardened == root_key.derive_hardened(&path)
+            && hardened.public_key() != root_key.derive(&path).public_key()
+            && hardened.public_key() != root_key.public_key().derive(&path).unwrap()
+    }
+
+    #[quickcheck]
+    fn derivation_path_is_derivation_of_every_segment(
+        root_key: SecretKey,
+        segments: Vec<Vec<u8>>,
+    ) -> bool {
+        let path: DerivationPath = segments.iter().collect();
+        let expected = segments
+            .iter()
+            .fold(root_key.clone(), |key, segment| key.derive(segment));
+
+        root_key.derive(&path) == expected
+            && root_key.public_key().derive(&path).unwrap() == expected.public_key()
+    }
+
     #[quickcheck]
     fn different_derivation_from_signing_key(
         root_key: SecretKey,
@@ -758,4 +1309,44 @@ mod tests {
         dbg!(&dp2);
         TestResult::from_bool(dp1 != dp2)
     }
+
+    #[quickcheck]
+    fn constant_time_eq(key: SecretKey, other: SecretKey) -> bool {
+        let mut bytes = key.leak_to_bytes();
+        bytes[SecretKey::SIZE - 1] ^= 1;
+        let other_chain_code = SecretKey::try_from(bytes).unwrap();
+        let equal = key.leak_to_bytes() == other.leak_to_bytes();
+
+        bool::from(key.ct_eq(&key.clone()))
+            && !bool::from(key.ct_eq(&other_chain_code))
+            && key != other_chain_code
+            && bool::from(key.ct_eq(&other)) == equal
+            && (key == other) == equal
+    }
+
+    #[cfg(feature = "mnemonic")]
+    #[test]
+    fn mnemonic_backup() {
+        let (key, mnemonic) = SecretKey::new_with_mnemonic(rand::thread_rng(), "passphrase");
+        assert!(key.is_3rd_highest_bit_clear());
+
+        let restored: Mnemonic = mnemonic.to_string().parse().unwrap();
+        assert_eq!(SecretKey::from_mnemonic(&restored, "passphrase"), key);
+        assert_ne!(SecretKey::from_mnemonic(&restored, ""), key);
+    }
+
+    #[cfg(feature = "mnemonic")]
+    #[test]
+    fn mnemonic_icarus() {
+        let mnemonic: Mnemonic =
+            "eight country switch draw meat scout mystery blade tip drift useless good keep usage title"
+                .parse()
+                .unwrap();
+        let entropy = hex::decode("46e62370a138a182a498b8e2885bc032379ddf38").unwrap();
+
+        assert_eq!(
+            SecretKey::from_mnemonic_icarus(&mnemonic, "foo"),
+            SecretKey::from_icarus_entropy(&entropy, b"foo")
+        );
+    }
 }
@@ -0,0 +1,314 @@
+/*!
+# Elligator2 representatives of Curve25519 keys
+
+An X25519 public key is not a random string: only about half of the
+32 bytes strings are valid keys and some bits are always 0. Elligator2
+maps (about half of) the public keys to a *representative*
+indistinguishable from 32 random bytes and back.
+
+[`generate`] creates a [`curve25519::SecretKey`] with a public key that
+has a representative. The public key is "dirty": a random low order
+point is added to it so the keys are not only in the prime order
+subgroup (which would be visible once the representative is decoded).
+The low order point does not change the Diffie-Hellman: the X25519
+secret keys are multiples of the cofactor. Each public key has 2
+representatives (one per sign of the `v` coordinate), one of them is
+picked at random.
+
+```
+use keynesis_core::key::elligator;
+# use rand::thread_rng;
+
+let (key, representative) = elligator::generate(thread_rng());
+assert_eq!(elligator::decode(&representative), key.public_key());
+```
+
+See the [Elligator paper] for the details.
+
+[Elligator paper]: https://elligator.cr.yp.to/elligator-20130828.pdf
+*/
+
+use crate::key::curve25519::{self, PublicKey};
+use cryptoxide::curve25519::Fe;
+use curve25519_dalek::{constants, scalar::Scalar};
+use rand_core::{CryptoRng, RngCore};
+
+/// size of a representative
+pub const REPRESENTATIVE_SIZE: usize = 32;
+
+/// the `A` coefficient of Curve25519, `486662`
+const A: [u8; 32] = {
+    let mut a = [0; 32];
+    a[0] = 0x06;
+    a[1] = 0x6d;
+    a[2] = 0x07;
+    a
+};
+
+/// the 2 highest bits of the representatives are always 0, they are
+/// filled with random bits
+const PADDING_MASK: u8 = 0b1100_0000;
+
+/// the bit of the random byte selecting which of the 2 representatives
+/// of the public key is used
+const BRANCH_MASK: u8 = 0b0000_1000;
+
+/// generate a new secret key whose public key has a representative,
+/// returns the key and the representative
+pub fn generate<Rng>(mut rng: Rng) -> (curve25519::SecretKey, [u8; REPRESENTATIVE_SIZE])
+where
+    Rng: RngCore + CryptoRng,
+{
+    loop {
+        let mut secret = [0; curve25519::SecretKey::SIZE];
+        rng.fill_bytes(&mut secret);
+
+        let mut random = [0; 1];
+        rng.fill_bytes(&mut random);
+
+        if let Some((public, representative)) = dirty_representative(&secret, random[0]) {
+            let key = curve25519::SecretKey::with_public(secret, public);
+            return (key, representative);
+        }
+    }
+}
+
+/// decode the representative into the public key it represents
+///
+/// every 32 bytes string is a valid representative.
+pub fn decode(representative: &[u8; REPRESENTATIVE_SIZE]) -> PublicKey {
+    let mut r = *representative;
+    r[31] &= !PADDING_MASK;
+    let r = Fe::from_bytes(&r);
+
+    let one = one();
+    let a = Fe::from_bytes(&A);
+
+    // w = -A / (1 + 2 r^2)
+    let w = &(&zero() - &a) * &(&one + &(&two() * &(&r * &r))).invert();
+    // if w^3 + A w^2 + w is not a square, the point is -w - A
+    let u = if is_square(&curve(&w)) {
+        w
+    } else {
+        &(&zero() - &w) - &a
+    };
+
+    PublicKey::from(u.to_bytes())
+}
+
+/// the public key of the secret with the low order point selected by
+/// `random` added to it and its representative, if it exists
+fn dirty_representative(
+    secret: &[u8; 32],
+    random: u8,
+) -> Option<([u8; 32], [u8; REPRESENTATIVE_SIZE])> {
+    let mut scalar = *secret;
+    clamp(&mut scalar);
+
+    let point = &constants::ED25519_BASEPOINT_TABLE * &Scalar::from_bits(scalar)
+        + constants::EIGHT_TORSION[(random & 0b111) as usize];
+    let u = point.to_montgomery().to_bytes();
+
+    let mut representative = representative(&u, random & BRANCH_MASK != 0)?;
+    representative[31] |= random & PADDING_MASK;
+    Some((u, representative))
+}
+
+/// the representative of the Montgomery `u` coordinate, if it exists
+///
+/// `u` has 2 representatives, one per sign of `v` (which X25519
+/// ignores): `sqrt(-(u + A) / (2 u))` decodes through `w = u` and
+/// `sqrt(-u / (2 (u + A)))` through `w = -u - A`. Always picking the
+/// same one would be visible: `w^3 + A w^2 + w` would always be a
+/// square, so `other` selects between them and must be random.
+fn representative(u: &[u8; 32], other: bool) -> Option<[u8; REPRESENTATIVE_SIZE]> {
+    let u = Fe::from_bytes(u);
+    let a = Fe::from_bytes(&A);
+
+    let u_plus_a = &u + &a;
+    if equal(&u, &zero()) || equal(&u_plus_a, &zero()) {
+        return None;
+    }
+
+    let (numerator, denominator) = if other { (u, u_plus_a) } else { (u_plus_a, u) };
+    let r = sqrt(&(&(&zero() - &numerator) * &(&two() * &denominator).invert()))?;
+
+    // of the 2 roots, take the one lower than (p - 1) / 2
+    let r = r.to_bytes();
+    let minus_r = (&zero() - &Fe::from_bytes(&r)).to_bytes();
+    let r = if less_than(&r, &minus_r) { r } else { minus_r };
+
+    debug_assert_eq!(r[31] & PADDING_MASK, 0);
+    Some(r)
+}
+
+/// clamp the scalar like X25519 does
+fn clamp(scalar: &mut [u8; 32]) {
+    scalar[0] &= 0b1111_1000;
+    scalar[31] &= 0b0111_1111;
+    scalar[31] |= 0b0100_0000;
+}
+
+fn zero() -> Fe {
+    Fe::from_bytes(&[0; 32])
+}
+
+fn one() -> Fe {
+    let mut one = [0; 32];
+    one[0] = 1;
+    Fe::from_bytes(&one)
+}
+
+fn two() -> Fe {
+    &one() + &one()
+}
+
+/// `w^3 + A w^2 + w`
+fn curve(w: &Fe) -> Fe {
+    let a = Fe::from_bytes(&A);
+    let w2 = w * w;
+    &(&(&w2 * w) + &(&a * &w2)) + w
+}
+
+/// raise to the power given as little endian bytes
+fn pow(x: &Fe, exponent: &[u8; 32]) -> Fe {
+    let mut result = one();
+    for byte in exponent.iter().rev() {
+        for bit in (0..8).rev() {
+            result = &result * &result;
+            if (byte >> bit) & 1 == 1 {
+                result = &result * x;
+            }
+        }
+    }
+    result
+}
+
+/// `(p + 3) / 8`
+fn sqrt_exponent() -> [u8; 32] {
+    let mut e = [0xff; 32];
+    e[0] = 0xfe;
+    e[31] = 0x0f;
+    e
+}
+
+/// `sqrt(-1)`, `2^((p - 1) / 4)`
+fn sqrt_minus_one() -> Fe {
+    let mut e = [0xff; 32];
+    e[0] = 0xfb;
+    e[31] = 0x1f;
+    pow(&two(), &e)
+}
+
+fn sqrt(x: &Fe) -> Option<Fe> {
+    let candidate = pow(x, &sqrt_exponent());
+    let square = &candidate * &candidate;
+
+    if equal(&square, x) {
+        Some(candidate)
+    } else if equal(&square, &(&zero() - x)) {
+        Some(&candidate * &sqrt_minus_one())
+    } else {
+        None
+    }
+}
+
+fn is_square(x: &Fe) -> bool {
+    sqrt(x).is_some()
+}
+
+/// compare the canonical encodings, the limbs of the field elements
+/// are not normalized
+fn equal(a: &Fe, b: &Fe) -> bool {
+    a.to_bytes() == b.to_bytes()
+}
+
+/// compare 2 little endian integers
+fn less_than(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().rev().lt(b.iter().rev())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::Dh as _;
+    use rand::thread_rng;
+
+    #[test]
+    fn roundtrip() {
+        for _ in 0..32 {
+            let (key, representative) = generate(thread_rng());
+            assert_eq!(decode(&representative), key.public_key());
+        }
+    }
+
+    #[test]
+    fn dirty_keys_exchange() {
+        let (alice, _) = generate(thread_rng());
+        let (bob, _) = generate(thread_rng());
+
+        // the low order points do not change the shared secrets
+        assert_eq!(alice.dh(&bob.public_key()), bob.dh(&alice.public_key()));
+
+        let clean = curve25519::SecretKey::from(*bob.leak_as_ref());
+        assert_eq!(alice.dh(&clean.public_key()), alice.dh(&bob.public_key()));
+    }
+
+    #[test]
+    fn padding_is_random() {
+        let mut padding = 0;
+        for _ in 0..64 {
+            let (_, representative) = generate(thread_rng());
+            padding |= representative[31] & PADDING_MASK;
+        }
+        assert_eq!(padding, PADDING_MASK);
+    }
+
+    #[test]
+    fn both_representatives_decode() {
+        for _ in 0..32 {
+            let secret = curve25519::SecretKey::new(thread_rng());
+            let mut u = [0; 32];
+            u.copy_from_slice(secret.public_key().as_ref());
+            if let Some(r) = representative(&u, false) {
+                let other = representative(&u, true).unwrap();
+                assert_ne!(r, other);
+                assert_eq!(decode(&r), secret.public_key());
+                assert_eq!(decode(&other), secret.public_key());
+            }
+        }
+    }
+
+    /// a random string decodes through a `w` on the curve half of the
+    /// time, the representatives must not do better
+    #[test]
+    fn curve_is_square_half_of_the_time() {
+        const SAMPLES: usize = 256;
+
+        let a = Fe::from_bytes(&A);
+        let mut squares = 0;
+        for _ in 0..SAMPLES {
+            let (_, mut representative) = generate(thread_rng());
+            representative[31] &= !PADDING_MASK;
+            let r = Fe::from_bytes(&representative);
+            let w = &(&zero() - &a) * &(&one() + &(&two() * &(&r * &r))).invert();
+            if is_square(&curve(&w)) {
+                squares += 1;
+            }
+        }
+
+        // 8 standard deviations away from SAMPLES / 2
+        assert!(
+            (64..=192).contains(&squares),
+            "{} squares out of {}",
+            squares,
+            SAMPLES
+        );
+    }
+
+    #[test]
+    fn sqrt_minus_one_squared() {
+        let i = sqrt_minus_one();
+        assert!(equal(&(&i * &i), &(&zero() - &one())));
+    }
+}
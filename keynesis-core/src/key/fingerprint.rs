@@ -0,0 +1,94 @@
+use cryptoxide::{blake2b::Blake2b, digest::Digest as _};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
+};
+
+/// short identifier of a public key: the first bytes of its BLAKE2b
+///
+/// it is for the logs, the user interfaces or the peer databases to
+/// display compact identities. **It does not replace the public key**:
+/// finding a key with a given fingerprint is cheap enough, the full
+/// public key needs to be checked before trusting it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fingerprint([u8; Self::SIZE]);
+
+impl Fingerprint {
+    pub const SIZE: usize = 8;
+
+    pub(crate) fn of(public_key: &[u8]) -> Self {
+        let mut fingerprint = [0; Self::SIZE];
+        let mut hasher = Blake2b::new(Self::SIZE);
+        hasher.input(public_key);
+        hasher.result(&mut fingerprint);
+        Self(fingerprint)
+    }
+
+    /// the zero fingerprint, for the keys without a parent (see
+    /// [`ExtendedPublicKey::root`](super::xpub::ExtendedPublicKey::root))
+    pub const fn zero() -> Self {
+        Self([0; Self::SIZE])
+    }
+}
+
+/* Format ****************************************************************** */
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&hex::encode(self.0), f)
+    }
+}
+
+impl Debug for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Fingerprint")
+            .field(&hex::encode(self.0))
+            .finish()
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = hex::FromHexError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0; Self::SIZE];
+        hex::decode_to_slice(s, &mut bytes)?;
+        Ok(Self(bytes))
+    }
+}
+
+/* Conversion ************************************************************** */
+
+impl From<[u8; Self::SIZE]> for Fingerprint {
+    fn from(bytes: [u8; Self::SIZE]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Fingerprint {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::ed25519::SecretKey;
+
+    #[quickcheck]
+    fn display_parse(key: SecretKey) -> bool {
+        let fingerprint = key.public_key().fingerprint();
+        let s = fingerprint.to_string();
+
+        s.len() == Fingerprint::SIZE * 2 && s.parse::<Fingerprint>() == Ok(fingerprint)
+    }
+
+    #[quickcheck]
+    fn matches_fingerprint(key: SecretKey, other: SecretKey) -> bool {
+        let public_key = key.public_key();
+
+        public_key.matches_fingerprint(&public_key.fingerprint())
+            && (public_key == other.public_key()
+                || !public_key.matches_fingerprint(&other.public_key().fingerprint()))
+    }
+}
@@ -0,0 +1,666 @@
+/*!
+# FROST threshold signatures
+
+A quorum of `threshold` holders of a [`KeyShare`] jointly produce an
+Ed25519 [`Signature`] of the group public key. The signature is a
+standard Ed25519 signature: it is verified with [`PublicKey::verify`]
+and nothing tells it apart from a signature of a single key.
+
+This is the two rounds signing protocol of [RFC9591], modelled on its
+`FROST(Ed25519, SHA-512)` ciphersuite. It is not checked against the
+test vectors of the RFC and the encodings of the commitments and of the
+shares are specific to this crate: do not expect it to interoperate with
+other FROST implementations.
+
+The signature takes two rounds:
+
+1. every signer calls [`commit`] and sends the [`SigningCommitments`]
+   to the coordinator while keeping the [`SigningNonces`] secret;
+2. the coordinator sends the message and the list of the commitments
+   to the signers, every signer calls [`sign`] and sends back its
+   [`SignatureShare`]. The coordinator calls [`aggregate`] to verify the
+   shares and compute the signature.
+
+The key shares are created with the distributed key generation of the
+[`dkg`](crate::dkg) module or, when a trusted party is acceptable, with
+[`trusted_dealer`].
+
+```
+use keynesis_core::{dkg::Parameters, key::frost};
+# use rand::thread_rng;
+
+let params = Parameters::new(2, 3).unwrap();
+let key_shares = frost::trusted_dealer(thread_rng(), params);
+const MESSAGE: &[u8] = b"signed by 2 out of 3";
+
+// round 1: the first and the last participants sign
+let (alice_nonces, alice_commitments) = frost::commit(thread_rng(), &key_shares[0]);
+let (carol_nonces, carol_commitments) = frost::commit(thread_rng(), &key_shares[2]);
+let commitments = [alice_commitments, carol_commitments];
+
+// round 2
+let shares = [
+    frost::sign(alice_nonces, &key_shares[0], MESSAGE, &commitments).unwrap(),
+    frost::sign(carol_nonces, &key_shares[2], MESSAGE, &commitments).unwrap(),
+];
+
+let signature = frost::aggregate(&key_shares[1], MESSAGE, &commitments, &shares).unwrap();
+assert!(key_shares[1].group_public_key().verify(MESSAGE, &signature));
+```
+
+The [`SigningNonces`] are consumed by [`sign`]: **using the same nonces
+for two signatures leaks the key share**.
+
+[RFC9591]: https://www.rfc-editor.org/rfc/rfc9591.html
+*/
+
+use crate::{
+    dkg::{self, KeyShare, Parameters, ParticipantIndex},
+    key::ed25519::{PublicKey, Signature},
+    memsec::Scrubbed as _,
+};
+use cryptoxide::{digest::Digest as _, sha2::Sha512};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_TABLE,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::IsIdentity as _,
+};
+use rand_core::{CryptoRng, RngCore};
+use std::{collections::BTreeMap, convert::TryFrom};
+use thiserror::Error;
+
+const CONTEXT: &[u8] = b"FROST-ED25519-SHA512-v1";
+
+/// the secret nonces of a signer for one signature
+///
+/// created with [`commit`] and consumed by [`sign`], they must never be
+/// used twice.
+pub struct SigningNonces {
+    hiding: Scalar,
+    binding: Scalar,
+    commitments: SigningCommitments,
+}
+
+/// the public commitments to the [`SigningNonces`] of a signer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SigningCommitments {
+    index: ParticipantIndex,
+    hiding: [u8; 32],
+    binding: [u8; 32],
+}
+
+/// the contribution of a signer to the signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignatureShare {
+    index: ParticipantIndex,
+    value: [u8; 32],
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrostError {
+    #[error("Not enough signers, at least {0} are needed")]
+    NotEnoughSigners(u16),
+
+    #[error("Invalid participant index {0}")]
+    InvalidIndex(ParticipantIndex),
+
+    #[error("Unexpected signature share from participant {0}")]
+    UnexpectedParticipant(ParticipantIndex),
+
+    #[error("Duplicated message from participant {0}")]
+    DuplicatedParticipant(ParticipantIndex),
+
+    #[error("Missing message from participant {0}")]
+    MissingParticipant(ParticipantIndex),
+
+    #[error("Invalid commitments from participant {0}")]
+    InvalidCommitments(ParticipantIndex),
+
+    #[error("Invalid signature share from participant {0}")]
+    InvalidShare(ParticipantIndex),
+
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+}
+
+/// the values every party computes from the message and the commitments
+struct SigningSession {
+    signers: BTreeMap<ParticipantIndex, Signer>,
+    group_commitment: EdwardsPoint,
+    challenge: Scalar,
+}
+
+struct Signer {
+    binding_factor: Scalar,
+    commitment: EdwardsPoint,
+    lambda: Scalar,
+}
+
+/* Key generation ********************************************************** */
+
+/// generate the key shares from a secret key known to the caller only
+/// for the time of the call
+///
+/// the returned key shares needs to be handed to the participants
+/// through confidential and authenticated channels, use the
+/// [`dkg`](crate::dkg) to not rely on a trusted party.
+pub fn trusted_dealer<Rng>(mut rng: Rng, params: Parameters) -> Vec<KeyShare>
+where
+    Rng: RngCore + CryptoRng,
+{
+    let mut coefficients: Vec<Scalar> = (0..params.threshold())
+        .map(|_| dkg::random_scalar(&mut rng))
+        .collect();
+
+    let group_public_key = (&coefficients[0] * &ED25519_BASEPOINT_TABLE)
+        .compress()
+        .to_bytes();
+    let mut secrets: Vec<Scalar> = (1..=params.participants())
+        .map(|index| dkg::evaluate(&coefficients, index))
+        .collect();
+    let verification_shares: Vec<PublicKey> = secrets
+        .iter()
+        .map(|secret| PublicKey::from((secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes()))
+        .collect();
+
+    let key_shares = secrets
+        .iter()
+        .zip(1..)
+        .map(|(secret, index)| {
+            KeyShare::from_parts(
+                params,
+                index,
+                *secret,
+                PublicKey::from(group_public_key),
+                verification_shares.clone(),
+            )
+        })
+        .collect();
+
+    coefficients.iter_mut().for_each(|a| *a = Scalar::zero());
+    secrets.iter_mut().for_each(|s| *s = Scalar::zero());
+
+    key_shares
+}
+
+/* Signing ***************************************************************** */
+
+/// first round: generate the nonces of the signer and the commitments
+/// to send to the coordinator
+pub fn commit<Rng>(mut rng: Rng, key_share: &KeyShare) -> (SigningNonces, SigningCommitments)
+where
+    Rng: RngCore + CryptoRng,
+{
+    let hiding = generate_nonce(&mut rng, key_share.secret());
+    let binding = generate_nonce(&mut rng, key_share.secret());
+
+    let commitments = SigningCommitments {
+        index: key_share.index(),
+        hiding: (&hiding * &ED25519_BASEPOINT_TABLE).compress().to_bytes(),
+        binding: (&binding * &ED25519_BASEPOINT_TABLE).compress().to_bytes(),
+    };
+
+    let nonces = SigningNonces {
+        hiding,
+        binding,
+        commitments,
+    };
+
+    (nonces, commitments)
+}
+
+/// second round: compute the signature share of `message`
+///
+/// `commitments` are the commitments of all the signers (ours included)
+/// as selected by the coordinator.
+pub fn sign(
+    nonces: SigningNonces,
+    key_share: &KeyShare,
+    message: &[u8],
+    commitments: &[SigningCommitments],
+) -> Result<SignatureShare, FrostError> {
+    let index = key_share.index();
+    match commitments.iter().find(|c| c.index == index) {
+        None => return Err(FrostError::MissingParticipant(index)),
+        Some(own) if own != &nonces.commitments => {
+            return Err(FrostError::InvalidCommitments(index))
+        }
+        Some(_) => {}
+    }
+
+    let session = SigningSession::new(key_share, message, commitments)?;
+    let signer = &session.signers[&index];
+
+    let value = nonces.hiding
+        + nonces.binding * signer.binding_factor
+        + signer.lambda * key_share.secret() * session.challenge;
+
+    Ok(SignatureShare {
+        index,
+        value: value.to_bytes(),
+    })
+}
+
+/// verify the signature shares and compute the signature
+///
+/// any of the key shares can be used, only its public keys are used.
+pub fn aggregate(
+    key_share: &KeyShare,
+    message: &[u8],
+    commitments: &[SigningCommitments],
+    shares: &[SignatureShare],
+) -> Result<Signature, FrostError> {
+    let session = SigningSession::new(key_share, message, commitments)?;
+
+    let mut values = BTreeMap::new();
+    for share in shares {
+        let signer = session
+            .signers
+            .get(&share.index)
+            .ok_or(FrostError::UnexpectedParticipant(share.index))?;
+        if values.contains_key(&share.index) {
+            return Err(FrostError::DuplicatedParticipant(share.index));
+        }
+
+        let value = Scalar::from_canonical_bytes(share.value)
+            .ok_or(FrostError::InvalidShare(share.index))?;
+        let verification_share = key_share
+            .verification_share(share.index)
+            .and_then(|key| CompressedEdwardsY::from_slice(key.as_ref()).decompress())
+            .ok_or(FrostError::InvalidShare(share.index))?;
+
+        let expected = signer.commitment + verification_share * (session.challenge * signer.lambda);
+        if &value * &ED25519_BASEPOINT_TABLE != expected {
+            return Err(FrostError::InvalidShare(share.index));
+        }
+
+        values.insert(share.index, value);
+    }
+
+    if let Some(missing) = session.signers.keys().find(|i| !values.contains_key(i)) {
+        return Err(FrostError::MissingParticipant(*missing));
+    }
+
+    let z: Scalar = values.values().sum();
+
+    let mut signature = [0; Signature::SIZE];
+    signature[..32].copy_from_slice(session.group_commitment.compress().as_bytes());
+    signature[32..].copy_from_slice(z.as_bytes());
+    Ok(Signature::from(signature))
+}
+
+/* Signing session ********************************************************* */
+
+impl SigningSession {
+    fn new(
+        key_share: &KeyShare,
+        message: &[u8],
+        commitments: &[SigningCommitments],
+    ) -> Result<Self, FrostError> {
+        let params = key_share.params();
+
+        let mut sorted = BTreeMap::new();
+        for commitment in commitments {
+            params
+                .check_index(commitment.index)
+                .map_err(|_| FrostError::InvalidIndex(commitment.index))?;
+            if sorted.contains_key(&commitment.index) {
+                return Err(FrostError::DuplicatedParticipant(commitment.index));
+            }
+            let points = commitment
+                .decompress()
+                .ok_or(FrostError::InvalidCommitments(commitment.index))?;
+            sorted.insert(commitment.index, (commitment, points));
+        }
+
+        if sorted.len() < params.threshold() as usize {
+            return Err(FrostError::NotEnoughSigners(params.threshold()));
+        }
+
+        // the binding factors bind every signer to the message and to
+        // the full list of commitments
+        let mut encoded = Vec::with_capacity(sorted.len() * 96);
+        for (index, (commitment, _)) in &sorted {
+            encoded.extend_from_slice(Scalar::from(*index as u64).as_bytes());
+            encoded.extend_from_slice(&commitment.hiding);
+            encoded.extend_from_slice(&commitment.binding);
+        }
+        let mut prefix = Vec::with_capacity(32 + 64 + 64);
+        prefix.extend_from_slice(key_share.group_public_key().as_ref());
+        prefix.extend_from_slice(&hash(b"msg", &[message]));
+        prefix.extend_from_slice(&hash(b"com", &[&encoded]));
+
+        let signers: BTreeMap<_, _> = sorted
+            .iter()
+            .map(|(index, (_, (hiding, binding)))| {
+                let binding_factor = Scalar::from_bytes_mod_order_wide(&hash(
+                    b"rho",
+                    &[&prefix, Scalar::from(*index as u64).as_bytes()],
+                ));
+                let lambda = lagrange(*index, sorted.keys().copied());
+                let signer = Signer {
+                    binding_factor,
+                    commitment: hiding + binding * binding_factor,
+                    lambda,
+                };
+                (*index, signer)
+            })
+            .collect();
+
+        let group_commitment: EdwardsPoint = signers.values().map(|s| s.commitment).sum();
+
+        // the challenge of an Ed25519 signature
+        let mut hasher = Sha512::new();
+        hasher.input(group_commitment.compress().as_bytes());
+        hasher.input(key_share.group_public_key().as_ref());
+        hasher.input(message);
+        let mut challenge = [0; 64];
+        hasher.result(&mut challenge);
+        let challenge = Scalar::from_bytes_mod_order_wide(&challenge);
+
+        Ok(Self {
+            signers,
+            group_commitment,
+            challenge,
+        })
+    }
+}
+
+/* Commitments ************************************************************* */
+
+impl SigningCommitments {
+    pub const SIZE: usize = 2 + 32 + 32;
+
+    pub fn index(&self) -> ParticipantIndex {
+        self.index
+    }
+
+    fn decompress(&self) -> Option<(EdwardsPoint, EdwardsPoint)> {
+        let hiding = CompressedEdwardsY(self.hiding).decompress()?;
+        let binding = CompressedEdwardsY(self.binding).decompress()?;
+        if hiding.is_identity() || binding.is_identity() {
+            None
+        } else {
+            Some((hiding, binding))
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..2].copy_from_slice(&self.index.to_be_bytes());
+        bytes[2..34].copy_from_slice(&self.hiding);
+        bytes[34..].copy_from_slice(&self.binding);
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SigningCommitments {
+    type Error = FrostError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != Self::SIZE {
+            return Err(FrostError::InvalidEncoding);
+        }
+
+        let commitments = Self {
+            index: u16::from_be_bytes([bytes[0], bytes[1]]),
+            hiding: <[u8; 32]>::try_from(&bytes[2..34]).unwrap(),
+            binding: <[u8; 32]>::try_from(&bytes[34..]).unwrap(),
+        };
+        commitments
+            .decompress()
+            .ok_or(FrostError::InvalidEncoding)?;
+        Ok(commitments)
+    }
+}
+
+/* Signature share ********************************************************* */
+
+impl SignatureShare {
+    pub const SIZE: usize = 2 + 32;
+
+    pub fn index(&self) -> ParticipantIndex {
+        self.index
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..2].copy_from_slice(&self.index.to_be_bytes());
+        bytes[2..].copy_from_slice(&self.value);
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SignatureShare {
+    type Error = FrostError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != Self::SIZE {
+            return Err(FrostError::InvalidEncoding);
+        }
+
+        let value = <[u8; 32]>::try_from(&bytes[2..]).unwrap();
+        Scalar::from_canonical_bytes(value).ok_or(FrostError::InvalidEncoding)?;
+
+        Ok(Self {
+            index: u16::from_be_bytes([bytes[0], bytes[1]]),
+            value,
+        })
+    }
+}
+
+/* Drop ******************************************************************** */
+
+impl Drop for SigningNonces {
+    fn drop(&mut self) {
+        self.hiding = Scalar::zero();
+        self.binding = Scalar::zero();
+    }
+}
+
+/* Helpers ***************************************************************** */
+
+fn hash(tag: &[u8], inputs: &[&[u8]]) -> [u8; 64] {
+    let mut hasher = Sha512::new();
+    hasher.input(CONTEXT);
+    hasher.input(tag);
+    for input in inputs {
+        hasher.input(input);
+    }
+    let mut hash = [0; 64];
+    hasher.result(&mut hash);
+    hash
+}
+
+/// the nonces are derived from fresh randomness and the key share so a
+/// weak random generator alone does not leak the key share
+fn generate_nonce<Rng>(rng: &mut Rng, secret: &Scalar) -> Scalar
+where
+    Rng: RngCore + CryptoRng,
+{
+    let mut random = [0; 32];
+    rng.fill_bytes(&mut random);
+    let nonce = Scalar::from_bytes_mod_order_wide(&hash(b"nonce", &[&random, secret.as_bytes()]));
+    random.scrub();
+    nonce
+}
+
+/// the lagrange coefficient of `index` at 0 for the given signers
+fn lagrange(index: ParticipantIndex, signers: impl Iterator<Item = ParticipantIndex>) -> Scalar {
+    let i = Scalar::from(index as u64);
+    let (numerator, denominator) = signers.filter(|j| *j != index).fold(
+        (Scalar::one(), Scalar::one()),
+        |(numerator, denominator), j| {
+            let j = Scalar::from(j as u64);
+            (numerator * j, denominator * (j - i))
+        },
+    );
+    numerator * denominator.invert()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dkg::Round1;
+    use rand::thread_rng;
+
+    fn dkg(params: Parameters) -> Vec<KeyShare> {
+        let (round1, messages): (Vec<_>, Vec<_>) = (1..=params.participants())
+            .map(|index| Round1::new(thread_rng(), params, index, b"test").unwrap())
+            .unzip();
+
+        let (round2, shares): (Vec<_>, Vec<_>) = round1
+            .into_iter()
+            .map(|round1| round1.receive(&messages).unwrap())
+            .unzip();
+        let shares: Vec<_> = shares.into_iter().flatten().collect();
+
+        round2
+            .into_iter()
+            .map(|round2| {
+                let received: Vec<_> = shares
+                    .iter()
+                    .filter(|share| share.receiver() == round2.index())
+                    .cloned()
+                    .collect();
+                round2.finish(&received).unwrap()
+            })
+            .collect()
+    }
+
+    fn round1(key_shares: &[&KeyShare]) -> (Vec<SigningNonces>, Vec<SigningCommitments>) {
+        key_shares
+            .iter()
+            .map(|key_share| commit(thread_rng(), key_share))
+            .unzip()
+    }
+
+    fn threshold_sign(key_shares: &[&KeyShare], message: &[u8]) -> Result<Signature, FrostError> {
+        let (nonces, commitments) = round1(key_shares);
+        let shares = nonces
+            .into_iter()
+            .zip(key_shares)
+            .map(|(nonces, key_share)| sign(nonces, key_share, message, &commitments))
+            .collect::<Result<Vec<_>, _>>()?;
+        aggregate(key_shares[0], message, &commitments, &shares)
+    }
+
+    #[test]
+    fn sign_with_dkg_shares() {
+        const MESSAGE: &[u8] = b"message";
+        let key_shares = dkg(Parameters::new(3, 5).unwrap());
+        let public_key = key_shares[0].group_public_key();
+
+        for subset in [vec![0, 1, 2], vec![4, 2, 0], vec![1, 2, 3, 4]] {
+            let signers: Vec<_> = subset.iter().map(|i| &key_shares[*i]).collect();
+            let signature = threshold_sign(&signers, MESSAGE).unwrap();
+            assert!(public_key.verify(MESSAGE, &signature));
+            assert!(!public_key.verify(b"other message", &signature));
+        }
+
+        let signers = [&key_shares[0], &key_shares[1]];
+        assert_eq!(
+            threshold_sign(&signers, MESSAGE),
+            Err(FrostError::NotEnoughSigners(3))
+        );
+    }
+
+    #[test]
+    fn sign_with_trusted_dealer() {
+        const MESSAGE: &[u8] = b"message";
+        for (threshold, participants) in [(1, 1), (1, 3), (2, 3), (4, 4)] {
+            let key_shares = trusted_dealer(
+                thread_rng(),
+                Parameters::new(threshold, participants).unwrap(),
+            );
+            let signers: Vec<_> = key_shares.iter().take(threshold as usize).collect();
+            let signature = threshold_sign(&signers, MESSAGE).unwrap();
+            assert!(key_shares[0].group_public_key().verify(MESSAGE, &signature));
+        }
+    }
+
+    #[test]
+    fn invalid_share() {
+        const MESSAGE: &[u8] = b"message";
+        let key_shares = trusted_dealer(thread_rng(), Parameters::new(2, 3).unwrap());
+        let signers = [&key_shares[0], &key_shares[1]];
+        let (mut nonces, commitments) = round1(&signers);
+
+        let bob = sign(nonces.pop().unwrap(), signers[1], MESSAGE, &commitments).unwrap();
+        let mut alice = sign(nonces.pop().unwrap(), signers[0], MESSAGE, &commitments).unwrap();
+        alice.value =
+            (Scalar::from_canonical_bytes(alice.value).unwrap() + Scalar::one()).to_bytes();
+
+        assert_eq!(
+            aggregate(&key_shares[2], MESSAGE, &commitments, &[alice, bob]),
+            Err(FrostError::InvalidShare(1))
+        );
+        assert_eq!(
+            aggregate(&key_shares[2], MESSAGE, &commitments, &[bob]),
+            Err(FrostError::MissingParticipant(1))
+        );
+        assert_eq!(
+            aggregate(&key_shares[2], MESSAGE, &commitments, &[bob, bob]),
+            Err(FrostError::DuplicatedParticipant(2))
+        );
+    }
+
+    #[test]
+    fn invalid_commitments() {
+        const MESSAGE: &[u8] = b"message";
+        let key_shares = trusted_dealer(thread_rng(), Parameters::new(2, 3).unwrap());
+        let signers = [&key_shares[0], &key_shares[1]];
+
+        let (mut nonces, commitments) = round1(&signers);
+        assert_eq!(
+            sign(
+                nonces.pop().unwrap(),
+                signers[1],
+                MESSAGE,
+                &commitments[..1]
+            )
+            .map(|_| ()),
+            Err(FrostError::MissingParticipant(2))
+        );
+
+        // the coordinator cannot replace the commitments of a signer
+        let (mut nonces, mut commitments) = round1(&signers);
+        commitments[1].binding = commitments[1].hiding;
+        assert_eq!(
+            sign(nonces.pop().unwrap(), signers[1], MESSAGE, &commitments).map(|_| ()),
+            Err(FrostError::InvalidCommitments(2))
+        );
+
+        let (mut nonces, commitments) = round1(&signers);
+        let duplicated = [commitments[0], commitments[0], commitments[1]];
+        assert_eq!(
+            sign(nonces.pop().unwrap(), signers[1], MESSAGE, &duplicated).map(|_| ()),
+            Err(FrostError::DuplicatedParticipant(1))
+        );
+    }
+
+    #[test]
+    fn encode_decode() {
+        let key_shares = trusted_dealer(thread_rng(), Parameters::new(2, 3).unwrap());
+        let signers = [&key_shares[0], &key_shares[1]];
+        let (mut nonces, commitments) = round1(&signers);
+
+        let decoded = SigningCommitments::try_from(commitments[0].to_bytes().as_ref()).unwrap();
+        assert_eq!(decoded, commitments[0]);
+
+        let share = sign(nonces.pop().unwrap(), signers[1], b"message", &commitments).unwrap();
+        let decoded = SignatureShare::try_from(share.to_bytes().as_ref()).unwrap();
+        assert_eq!(decoded, share);
+
+        let mut bytes = commitments[0].to_bytes();
+        // the identity point
+        bytes[2..34].copy_from_slice(&[0; 32]);
+        bytes[2] = 1;
+        assert_eq!(
+            SigningCommitments::try_from(bytes.as_ref()),
+            Err(FrostError::InvalidEncoding)
+        );
+    }
+}
@@ -72,15 +72,49 @@ encryption_context.process_mut(&mut encrypted);
 # assert_eq!(message, encrypted);
 ```
 
+# Comparing secrets
+
+The secret keys, the [`SharedSecret`] and the other secret material of
+the crate (the [`Seed`], the [shares](sharding::Share), the cipher
+states of the noise sessions...) implement [`ConstantTimeEq`]: the time it takes to
+compare them does not depend on their content (only on their length
+for the variable sized ones). Their `PartialEq` is the constant time
+comparison so using `==` does not leak the secret either.
+
+```
+use keynesis_core::key::ed25519::SecretKey;
+use subtle::ConstantTimeEq as _;
+# use rand::thread_rng;
+
+let key = SecretKey::new(&mut thread_rng());
+let other = SecretKey::new(&mut thread_rng());
+
+assert!(bool::from(key.ct_eq(&key.clone())));
+assert!(!bool::from(key.ct_eq(&other)));
+assert!(key != other);
+```
+
+[`Seed`]: crate::Seed
+[`ConstantTimeEq`]: subtle::ConstantTimeEq
 */
 
+pub mod audit;
 pub mod curve25519;
+pub mod derivation_path;
 pub mod ed25519;
 pub mod ed25519_extended;
 pub mod ed25519_hd;
+pub mod elligator;
+mod fingerprint;
+pub mod frost;
+pub mod protected;
+pub mod sharding;
 mod shared_secret;
+pub mod slip10;
+pub mod vrf;
+pub mod xpub;
 
-pub use self::shared_secret::SharedSecret;
+pub use self::{fingerprint::Fingerprint, shared_secret::SharedSecret};
 use rand_core::{CryptoRng, RngCore};
 
 pub trait Dh {
@@ -0,0 +1,418 @@
+/*!
+# Password protected secret keys
+
+A [`ProtectedSecretKey`] is a secret key encrypted with a key derived
+from a password, to store the keys at rest (in a file, a database...).
+
+```
+use keynesis_core::{
+    kdf::{Argon2Params, Kdf},
+    key::{ed25519::SecretKey, protected::ProtectedSecretKey},
+};
+# use rand::thread_rng;
+# use std::convert::TryFrom as _;
+
+let secret_key = SecretKey::new(thread_rng());
+let kdf = Kdf::Argon2id(Argon2Params::SENSITIVE);
+# // keep the doc test fast
+# let kdf = Kdf::Argon2id(Argon2Params::new(64, 1, 1).unwrap());
+
+let protected =
+    ProtectedSecretKey::seal(&mut thread_rng(), &kdf, b"password", &secret_key).unwrap();
+let bytes = protected.to_bytes();
+
+// the parameters of the kdf and the salt are stored with the key
+let protected = ProtectedSecretKey::try_from(bytes.as_slice()).unwrap();
+let decoded: SecretKey = protected.open(b"password").unwrap();
+assert_eq!(decoded.public_key(), secret_key.public_key());
+```
+
+The encoding is self describing, all the integers are big endian:
+
+* the magic `KNSK` (4 bytes) and the version (1 byte, `1`);
+* the [kind](Protectable::KIND) of the secret key (1 byte);
+* the [`Kdf`]: `1` and the Argon2id `m_cost`, `t_cost`, `p_cost` (u32)
+  or `2` and the scrypt `log_n` (1 byte), `r` and `p` (u32);
+* the salt (16 bytes);
+* the secret key encrypted with ChaCha20Poly1305, with the key derived
+  from the password and the salt, and the previous fields as the
+  additional data.
+
+The parameters of the kdf are read from the encoding: check them with
+[`ProtectedSecretKey::kdf`] before opening a key from an untrusted
+source, large parameters take a lot of memory and time to compute.
+*/
+
+use crate::{
+    kdf::{Argon2Params, Kdf, KdfError, ScryptParams, SALT_LEN},
+    key::{curve25519, ed25519, ed25519_extended, ed25519_hd},
+    memsec::Scrubbed as _,
+};
+use cryptoxide::chacha20poly1305::ChaCha20Poly1305;
+use rand_core::{CryptoRng, RngCore};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"KNSK";
+const VERSION: u8 = 1;
+const ARGON2ID: u8 = 1;
+const SCRYPT: u8 = 2;
+const KEY_SIZE: usize = 32;
+const TAG_SIZE: usize = 16;
+/// the encryption key is derived with a new random salt for every key
+const NONCE: [u8; 12] = [0; 12];
+
+/// a secret key encrypted with a password
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectedSecretKey {
+    kind: u8,
+    kdf: Kdf,
+    salt: [u8; SALT_LEN],
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ProtectedError {
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+
+    #[error("Unsupported version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Unexpected kind of secret key {found}, expecting {expected}")]
+    UnexpectedKind { expected: u8, found: u8 },
+
+    #[error("Invalid password or corrupted key")]
+    InvalidPassword,
+
+    #[error("Invalid key derivation")]
+    Kdf(
+        #[source]
+        #[from]
+        KdfError,
+    ),
+
+    #[error("Invalid secret key")]
+    InvalidKey,
+}
+
+/// the secret keys that can be protected with a [`ProtectedSecretKey`]
+pub trait Protectable: Sized {
+    /// identifier of the type of the key in the encoding
+    const KIND: u8;
+
+    /// the bytes of the key, they are scrubbed once encrypted
+    fn to_secret_bytes(&self) -> Vec<u8>;
+
+    fn from_secret_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl ProtectedSecretKey {
+    /// encrypt the `key` with a key derived from the `password` with the
+    /// `kdf` and a random salt
+    pub fn seal<K, RNG>(
+        rng: &mut RNG,
+        kdf: &Kdf,
+        password: &[u8],
+        key: &K,
+    ) -> Result<Self, ProtectedError>
+    where
+        K: Protectable,
+        RNG: RngCore + CryptoRng,
+    {
+        let mut salt = [0; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+
+        let mut protected = Self {
+            kind: K::KIND,
+            kdf: *kdf,
+            salt,
+            ciphertext: Vec::new(),
+        };
+
+        let mut encryption_key = [0; KEY_SIZE];
+        kdf.derive(password, &salt, &mut encryption_key)?;
+
+        let mut secret = key.to_secret_bytes();
+        let mut ciphertext = vec![0; secret.len() + TAG_SIZE];
+        let (encrypted, tag) = ciphertext.split_at_mut(secret.len());
+        ChaCha20Poly1305::new(&encryption_key, &NONCE, &protected.header())
+            .encrypt(&secret, encrypted, tag);
+
+        secret.scrub();
+        encryption_key.scrub();
+
+        protected.ciphertext = ciphertext;
+        Ok(protected)
+    }
+
+    /// decrypt the secret key with the `password`
+    ///
+    /// fails with [`ProtectedError::UnexpectedKind`] if the key was not
+    /// sealed as a `K`, and with [`ProtectedError::InvalidPassword`] if
+    /// the password is not valid or the encoding has been altered.
+    pub fn open<K>(&self, password: &[u8]) -> Result<K, ProtectedError>
+    where
+        K: Protectable,
+    {
+        if self.kind != K::KIND {
+            return Err(ProtectedError::UnexpectedKind {
+                expected: K::KIND,
+                found: self.kind,
+            });
+        }
+
+        let mut encryption_key = [0; KEY_SIZE];
+        self.kdf.derive(password, &self.salt, &mut encryption_key)?;
+
+        let (encrypted, tag) = self.ciphertext.split_at(self.ciphertext.len() - TAG_SIZE);
+        let mut secret = vec![0; encrypted.len()];
+        let valid = ChaCha20Poly1305::new(&encryption_key, &NONCE, &self.header()).decrypt(
+            encrypted,
+            &mut secret,
+            tag,
+        );
+        encryption_key.scrub();
+
+        let key = if valid {
+            K::from_secret_bytes(&secret).ok_or(ProtectedError::InvalidKey)
+        } else {
+            Err(ProtectedError::InvalidPassword)
+        };
+        secret.scrub();
+
+        key
+    }
+
+    /// the kind of the secret key, see [`Protectable::KIND`]
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// the password hashing function and the parameters used to derive
+    /// the encryption key
+    pub fn kdf(&self) -> &Kdf {
+        &self.kdf
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(4 + 3 + 12 + SALT_LEN);
+        header.extend_from_slice(MAGIC);
+        header.push(VERSION);
+        header.push(self.kind);
+        match &self.kdf {
+            Kdf::Argon2id(params) => {
+                header.push(ARGON2ID);
+                header.extend_from_slice(&params.m_cost().to_be_bytes());
+                header.extend_from_slice(&params.t_cost().to_be_bytes());
+                header.extend_from_slice(&params.p_cost().to_be_bytes());
+            }
+            Kdf::Scrypt(params) => {
+                header.push(SCRYPT);
+                header.push(params.log_n());
+                header.extend_from_slice(&params.r().to_be_bytes());
+                header.extend_from_slice(&params.p().to_be_bytes());
+            }
+        }
+        header.extend_from_slice(&self.salt);
+        header
+    }
+
+    /// encode the protected key, see the [module documentation](self)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+}
+
+fn read<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], ProtectedError> {
+    if bytes.len() < len {
+        return Err(ProtectedError::InvalidEncoding);
+    }
+    let (value, remaining) = bytes.split_at(len);
+    *bytes = remaining;
+    Ok(value)
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Result<u32, ProtectedError> {
+    let value = read(bytes, 4)?;
+    Ok(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+impl<'a> TryFrom<&'a [u8]> for ProtectedSecretKey {
+    type Error = ProtectedError;
+
+    fn try_from(mut bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if read(&mut bytes, MAGIC.len())? != MAGIC {
+            return Err(ProtectedError::InvalidEncoding);
+        }
+        match read(&mut bytes, 1)?[0] {
+            VERSION => (),
+            version => return Err(ProtectedError::UnsupportedVersion(version)),
+        }
+        let kind = read(&mut bytes, 1)?[0];
+        let kdf = match read(&mut bytes, 1)?[0] {
+            ARGON2ID => Kdf::Argon2id(Argon2Params::new(
+                read_u32(&mut bytes)?,
+                read_u32(&mut bytes)?,
+                read_u32(&mut bytes)?,
+            )?),
+            SCRYPT => Kdf::Scrypt(ScryptParams::new(
+                read(&mut bytes, 1)?[0],
+                read_u32(&mut bytes)?,
+                read_u32(&mut bytes)?,
+            )?),
+            _ => return Err(ProtectedError::InvalidEncoding),
+        };
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(read(&mut bytes, SALT_LEN)?);
+
+        if bytes.len() <= TAG_SIZE {
+            return Err(ProtectedError::InvalidEncoding);
+        }
+
+        Ok(Self {
+            kind,
+            kdf,
+            salt,
+            ciphertext: bytes.to_vec(),
+        })
+    }
+}
+
+/* Protectable ************************************************************* */
+
+impl Protectable for ed25519::SecretKey {
+    const KIND: u8 = 1;
+
+    fn to_secret_bytes(&self) -> Vec<u8> {
+        self.leak_as_ref().to_vec()
+    }
+
+    fn from_secret_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::try_from(bytes).ok()
+    }
+}
+
+impl Protectable for ed25519_extended::SecretKey {
+    const KIND: u8 = 2;
+
+    fn to_secret_bytes(&self) -> Vec<u8> {
+        self.leak_as_ref().to_vec()
+    }
+
+    fn from_secret_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::try_from(bytes).ok()
+    }
+}
+
+impl Protectable for ed25519_hd::SecretKey {
+    const KIND: u8 = 3;
+
+    fn to_secret_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.leak_to_bytes();
+        let secret = bytes.to_vec();
+        bytes.scrub();
+        secret
+    }
+
+    fn from_secret_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::try_from(bytes).ok()
+    }
+}
+
+impl Protectable for curve25519::SecretKey {
+    const KIND: u8 = 4;
+
+    fn to_secret_bytes(&self) -> Vec<u8> {
+        self.leak_as_ref().to_vec()
+    }
+
+    fn from_secret_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::try_from(bytes).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn kdfs() -> [Kdf; 2] {
+        [
+            Kdf::Argon2id(Argon2Params::new(64, 1, 1).unwrap()),
+            Kdf::Scrypt(ScryptParams::new(4, 8, 1).unwrap()),
+        ]
+    }
+
+    fn seal_open<K: Protectable>(key: &K) -> K {
+        let mut key = K::from_secret_bytes(&key.to_secret_bytes()).unwrap();
+        for kdf in kdfs().iter() {
+            let protected =
+                ProtectedSecretKey::seal(&mut thread_rng(), kdf, b"password", &key).unwrap();
+            let decoded = ProtectedSecretKey::try_from(protected.to_bytes().as_slice()).unwrap();
+            assert_eq!(decoded, protected);
+            assert_eq!(decoded.kdf(), kdf);
+            key = decoded.open(b"password").unwrap();
+        }
+        key
+    }
+
+    #[test]
+    fn seal_open_keys() {
+        let key = ed25519::SecretKey::new(thread_rng());
+        assert_eq!(seal_open(&key).leak_as_ref(), key.leak_as_ref());
+
+        let key = ed25519_extended::SecretKey::new(thread_rng());
+        assert_eq!(seal_open(&key).leak_as_ref(), key.leak_as_ref());
+
+        let key = ed25519_hd::SecretKey::new(thread_rng());
+        assert_eq!(seal_open(&key).leak_to_bytes(), key.leak_to_bytes());
+
+        let key = curve25519::SecretKey::new(thread_rng());
+        assert_eq!(seal_open(&key).leak_as_ref(), key.leak_as_ref());
+    }
+
+    #[test]
+    fn invalid() {
+        let key = ed25519::SecretKey::new(thread_rng());
+        let kdf = kdfs()[0];
+        let protected =
+            ProtectedSecretKey::seal(&mut thread_rng(), &kdf, b"password", &key).unwrap();
+
+        assert!(matches!(
+            protected.open::<ed25519::SecretKey>(b"not the password"),
+            Err(ProtectedError::InvalidPassword)
+        ));
+        assert!(matches!(
+            protected.open::<curve25519::SecretKey>(b"password"),
+            Err(ProtectedError::UnexpectedKind {
+                expected: 4,
+                found: 1
+            })
+        ));
+
+        // the parameters of the kdf are authenticated
+        let mut bytes = protected.to_bytes();
+        bytes[10] = 128;
+        let altered = ProtectedSecretKey::try_from(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            altered.open::<ed25519::SecretKey>(b"password"),
+            Err(ProtectedError::InvalidPassword)
+        ));
+
+        let mut bytes = protected.to_bytes();
+        bytes[4] = 2;
+        assert!(matches!(
+            ProtectedSecretKey::try_from(bytes.as_slice()),
+            Err(ProtectedError::UnsupportedVersion(2))
+        ));
+        let bytes = protected.to_bytes();
+        assert!(matches!(
+            ProtectedSecretKey::try_from(&bytes[..bytes.len() - key.leak_as_ref().len()]),
+            Err(ProtectedError::InvalidEncoding)
+        ));
+    }
+}
@@ -0,0 +1,414 @@
+/*!
+# Shamir secret sharing of the root keys
+
+[`split`] a root [`SecretKey`] (the extended key and the chain code)
+into `count` [`Share`]s so that any `threshold` of them [`combine`]
+back into the key, while fewer shares reveal nothing about it.
+
+```
+use keynesis_core::key::{ed25519_hd::SecretKey, sharding};
+# use rand::thread_rng;
+
+let root = SecretKey::new(thread_rng());
+let shares = sharding::split(thread_rng(), &root, 3, 5).unwrap();
+
+// any 3 of the 5 shares are enough
+let restored = sharding::combine(&shares[1..4]).unwrap();
+assert_eq!(restored.public_key(), root.public_key());
+
+assert!(sharding::combine(&shares[..2]).is_err());
+```
+
+The bytes of the key are shared one by one over GF(2^8) (with the
+polynomial `x^8 + x^4 + x^3 + x + 1`). Every share carries the
+threshold, an identifier of the key (a hash of its public key and chain code) and a
+checksum: the corrupted shares and the shares of different keys are
+rejected and the combined key is checked against the identifier.
+*/
+
+use crate::{canonical::Canonical as _, key::ed25519_hd::SecretKey, memsec::Scrubbed as _};
+use cryptoxide::blake2b::Blake2b;
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug, Formatter},
+};
+use subtle::{Choice, ConstantTimeEq};
+use thiserror::Error;
+
+const VERSION: u8 = 1;
+const KEY_ID_SIZE: usize = 8;
+const CHECKSUM_SIZE: usize = 4;
+const KEY_ID_CONTEXT: &[u8] = b"keynesis:sharding:id";
+const CHECKSUM_CONTEXT: &[u8] = b"keynesis:sharding:checksum";
+
+/// one of the shares of a [`SecretKey`]
+///
+/// the value of the share is scrubbed (zeroed) when the share is
+/// dropped, the encoding needs to be kept confidential.
+#[derive(Clone)]
+pub struct Share {
+    threshold: u8,
+    index: u8,
+    key_id: [u8; KEY_ID_SIZE],
+    value: [u8; SecretKey::SIZE],
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShardingError {
+    #[error("Invalid threshold, expecting 2 <= threshold ({threshold}) <= count ({count})")]
+    InvalidThreshold { threshold: u8, count: u8 },
+
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+
+    #[error("Invalid checksum, the share is corrupted")]
+    InvalidChecksum,
+
+    #[error("Not enough shares ({found}), expecting {threshold}")]
+    NotEnoughShares { threshold: u8, found: usize },
+
+    #[error("Share {0} provided more than once")]
+    DuplicateShare(u8),
+
+    #[error("The shares are not from the same key")]
+    MismatchedShares,
+
+    #[error("The combined key does not match the identifier of the shares")]
+    InvalidKey,
+}
+
+/// split the `key` in `count` shares, `threshold` of them are needed to
+/// combine the key
+pub fn split<Rng>(
+    mut rng: Rng,
+    key: &SecretKey,
+    threshold: u8,
+    count: u8,
+) -> Result<Vec<Share>, ShardingError>
+where
+    Rng: RngCore + CryptoRng,
+{
+    if threshold < 2 || count < threshold {
+        return Err(ShardingError::InvalidThreshold { threshold, count });
+    }
+
+    let key_id = key_id(key);
+    let mut secret = key.leak_to_bytes();
+
+    // the coefficients of the polynomial of every byte, the constant
+    // term is the byte of the secret
+    let mut coefficients = vec![0; SecretKey::SIZE * (threshold as usize - 1)];
+    rng.fill_bytes(&mut coefficients);
+
+    let shares = (1..=count)
+        .map(|index| {
+            let mut value = [0; SecretKey::SIZE];
+            for (i, byte) in value.iter_mut().enumerate() {
+                let coefficients = &coefficients[i * (threshold as usize - 1)..];
+                // Horner's method, from the highest degree
+                *byte = coefficients[..threshold as usize - 1]
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, c| gf_mul(acc, index) ^ c);
+                *byte = gf_mul(*byte, index) ^ secret[i];
+            }
+            Share {
+                threshold,
+                index,
+                key_id,
+                value,
+            }
+        })
+        .collect();
+
+    coefficients.scrub();
+    secret.scrub();
+
+    Ok(shares)
+}
+
+/// combine the key from (at least) `threshold` of its shares
+///
+/// only the first `threshold` shares are used, the other ones still
+/// need to be valid shares of the same key.
+pub fn combine(shares: &[Share]) -> Result<SecretKey, ShardingError> {
+    let first = shares.first().ok_or(ShardingError::NotEnoughShares {
+        threshold: 2,
+        found: 0,
+    })?;
+    let threshold = first.threshold;
+    for (i, share) in shares.iter().enumerate() {
+        if share.threshold != threshold || share.key_id != first.key_id {
+            return Err(ShardingError::MismatchedShares);
+        }
+        if shares[..i].iter().any(|s| s.index == share.index) {
+            return Err(ShardingError::DuplicateShare(share.index));
+        }
+    }
+    if shares.len() < threshold as usize {
+        return Err(ShardingError::NotEnoughShares {
+            threshold,
+            found: shares.len(),
+        });
+    }
+    let shares = &shares[..threshold as usize];
+
+    // the Lagrange coefficients of the shares at 0
+    let lagrange: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            let (numerator, denominator) = shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold((1, 1), |(n, d), other| {
+                    (gf_mul(n, other.index), gf_mul(d, other.index ^ share.index))
+                });
+            gf_mul(numerator, gf_inv(denominator))
+        })
+        .collect();
+
+    let mut secret = [0; SecretKey::SIZE];
+    for (i, byte) in secret.iter_mut().enumerate() {
+        *byte = shares
+            .iter()
+            .zip(lagrange.iter())
+            .fold(0, |acc, (share, l)| acc ^ gf_mul(share.value[i], *l));
+    }
+
+    let key = SecretKey::try_from(secret);
+    secret.scrub();
+
+    match key {
+        Ok(key) if key_id(&key) == first.key_id => Ok(key),
+        _ => Err(ShardingError::InvalidKey),
+    }
+}
+
+fn key_id(key: &SecretKey) -> [u8; KEY_ID_SIZE] {
+    let mut id = [0; KEY_ID_SIZE];
+    Blake2b::blake2b(
+        &mut id,
+        &key.public_key().to_canonical_bytes(),
+        KEY_ID_CONTEXT,
+    );
+    id
+}
+
+/// multiplication in GF(2^8), in constant time
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0;
+    for _ in 0..8 {
+        result ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    result
+}
+
+/// inverse in GF(2^8) (`a^254`), `0` has no inverse and gives `0`
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut square = a;
+    for bit in 0..8 {
+        if (254 >> bit) & 1 == 1 {
+            result = gf_mul(result, square);
+        }
+        square = gf_mul(square, square);
+    }
+    result
+}
+
+impl Share {
+    /// the size of the encoded share
+    pub const SIZE: usize = 3 + KEY_ID_SIZE + SecretKey::SIZE + CHECKSUM_SIZE;
+
+    /// the number of shares needed to combine the key
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// the index of the share, between `1` and the number of shares
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// identifier of the key, the same for all of its shares
+    pub fn key_id(&self) -> &[u8; KEY_ID_SIZE] {
+        &self.key_id
+    }
+
+    /// encode the share: the version, the threshold, the index, the key
+    /// identifier, the value and a checksum of the previous fields
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = VERSION;
+        bytes[1] = self.threshold;
+        bytes[2] = self.index;
+        bytes[3..3 + KEY_ID_SIZE].copy_from_slice(&self.key_id);
+        bytes[3 + KEY_ID_SIZE..Self::SIZE - CHECKSUM_SIZE].copy_from_slice(&self.value);
+        let checksum = checksum(&bytes[..Self::SIZE - CHECKSUM_SIZE]);
+        bytes[Self::SIZE - CHECKSUM_SIZE..].copy_from_slice(&checksum);
+        bytes
+    }
+}
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let mut checksum = [0; CHECKSUM_SIZE];
+    Blake2b::blake2b(&mut checksum, bytes, CHECKSUM_CONTEXT);
+    checksum
+}
+
+impl<'a> TryFrom<&'a [u8]> for Share {
+    type Error = ShardingError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != Self::SIZE || bytes[0] != VERSION {
+            return Err(ShardingError::InvalidEncoding);
+        }
+        let (content, expected) = bytes.split_at(Self::SIZE - CHECKSUM_SIZE);
+        if checksum(content) != expected {
+            return Err(ShardingError::InvalidChecksum);
+        }
+        if bytes[1] < 2 || bytes[2] == 0 {
+            return Err(ShardingError::InvalidEncoding);
+        }
+
+        let mut share = Self {
+            threshold: bytes[1],
+            index: bytes[2],
+            key_id: [0; KEY_ID_SIZE],
+            value: [0; SecretKey::SIZE],
+        };
+        share.key_id.copy_from_slice(&content[3..3 + KEY_ID_SIZE]);
+        share.value.copy_from_slice(&content[3 + KEY_ID_SIZE..]);
+        Ok(share)
+    }
+}
+
+impl Debug for Share {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share")
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .field("key_id", &hex::encode(self.key_id))
+            .finish_non_exhaustive()
+    }
+}
+
+/* Eq ********************************************************************** */
+
+impl ConstantTimeEq for Share {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.threshold.ct_eq(&other.threshold)
+            & self.index.ct_eq(&other.index)
+            & self.key_id[..].ct_eq(&other.key_id[..])
+            & self.value[..].ct_eq(&other.value[..])
+    }
+}
+
+impl PartialEq for Share {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Share {}
+
+/* Drop ******************************************************************** */
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.value.scrub();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen, TestResult};
+    use rand::thread_rng;
+
+    #[test]
+    fn gf_arithmetic() {
+        // the example of FIPS 197 (section 4.2)
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[quickcheck]
+    fn split_combine(key: SecretKey, threshold: u8, count: u8) -> TestResult {
+        let count = count % 16 + 2;
+        let threshold = threshold % (count - 1) + 2;
+        let shares = split(thread_rng(), &key, threshold, count).unwrap();
+
+        // any subset of `threshold` shares
+        let mut g = Gen::new(count as usize);
+        let mut subset: Vec<_> = shares.clone();
+        for i in (1..subset.len()).rev() {
+            subset.swap(i, usize::arbitrary(&mut g) % (i + 1));
+        }
+        subset.truncate(threshold as usize);
+
+        let decoded: Vec<_> = subset
+            .iter()
+            .map(|share| Share::try_from(share.to_bytes().as_ref()).unwrap())
+            .collect();
+        let combined = combine(&decoded).unwrap();
+        if combined.leak_to_bytes() != key.leak_to_bytes() {
+            return TestResult::error("the combined key is not the original key");
+        }
+
+        TestResult::from_bool(
+            combine(&subset[..threshold as usize - 1]).err()
+                == Some(ShardingError::NotEnoughShares {
+                    threshold,
+                    found: threshold as usize - 1,
+                }),
+        )
+    }
+
+    #[test]
+    fn invalid() {
+        let key = SecretKey::new(thread_rng());
+        let other = SecretKey::new(thread_rng());
+        let shares = split(thread_rng(), &key, 2, 3).unwrap();
+        let others = split(thread_rng(), &other, 2, 3).unwrap();
+
+        assert_eq!(
+            split(thread_rng(), &key, 1, 3).err(),
+            Some(ShardingError::InvalidThreshold {
+                threshold: 1,
+                count: 3
+            })
+        );
+        assert_eq!(
+            combine(&[shares[0].clone(), shares[0].clone()]).err(),
+            Some(ShardingError::DuplicateShare(1))
+        );
+        assert_eq!(
+            combine(&[shares[0].clone(), others[1].clone()]).err(),
+            Some(ShardingError::MismatchedShares)
+        );
+
+        let mut bytes = shares[1].to_bytes();
+        bytes[20] ^= 1;
+        assert_eq!(
+            Share::try_from(bytes.as_ref()).err(),
+            Some(ShardingError::InvalidChecksum)
+        );
+
+        // a corrupted share with a valid checksum
+        let mut corrupted = shares[1].clone();
+        corrupted.value[0] ^= 1;
+        assert_eq!(
+            combine(&[shares[0].clone(), corrupted]).err(),
+            Some(ShardingError::InvalidKey)
+        );
+    }
+}
@@ -4,6 +4,7 @@ use std::{
     fmt::{self, Debug, Formatter},
     hash::{Hash, Hasher},
 };
+use subtle::{Choice, ConstantTimeEq};
 
 /// A Shared Secret that can be used to generate a symmetric key
 #[derive(Clone)]
@@ -28,7 +29,7 @@ impl SharedSecret {
 impl Debug for SharedSecret {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_tuple("SharedSecret<Ed25519>")
-            .field(&hex::encode(&self.0))
+            .field(&hex::encode(self.0))
             .finish()
     }
 }
@@ -44,9 +45,15 @@ impl Debug for SharedSecret {
 
 /* Eq ********************************************************************** */
 
+impl ConstantTimeEq for SharedSecret {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0[..].ct_eq(&other.0[..])
+    }
+}
+
 impl PartialEq<Self> for SharedSecret {
     fn eq(&self, other: &Self) -> bool {
-        unsafe { memsec::memeq(self.0.as_ptr(), other.0.as_ptr(), Self::SIZE) }
+        self.ct_eq(other).into()
     }
 }
 
@@ -0,0 +1,163 @@
+/*!
+# SLIP-0010 Ed25519 keys
+
+[SLIP-0010] is the derivation of the Ed25519 keys of the hardware
+wallets (and of the wallets of Solana, Stellar...). Unlike the
+[`ed25519_hd`](super::ed25519_hd) keys the derived secret keys are plain
+[`ed25519::SecretKey`]s and **only the hardened derivation exists**:
+nothing can be derived from a public key.
+
+```
+use keynesis_core::key::slip10::SecretKey;
+
+let root = SecretKey::from_master_seed(b"the BIP39 seed of the wallet");
+
+// m/44'/501'/0'
+let key = root.derive(44).derive(501).derive(0);
+let public_key = key.public_key();
+```
+
+Use it to migrate the keys of the existing wallets, the
+[`ed25519_hd`](super::ed25519_hd) keys are preferred otherwise.
+
+[SLIP-0010]: https://github.com/satoshilabs/slips/blob/master/slip-0010.md
+*/
+
+use crate::{
+    key::{
+        ed25519,
+        ed25519_hd::{ChainCode, HARDENED_INDEX},
+    },
+    memsec::Scrubbed as _,
+};
+use cryptoxide::{hmac::Hmac, mac::Mac, sha2::Sha512};
+use std::{
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+};
+use subtle::{Choice, ConstantTimeEq};
+
+const MASTER_KEY: &[u8] = b"ed25519 seed";
+
+/// a SLIP-0010 secret key and its chain code
+#[derive(Debug, Clone)]
+pub struct SecretKey {
+    key: ed25519::SecretKey,
+    chain_code: ChainCode,
+}
+
+impl SecretKey {
+    /// generate the master key from the `seed` (the BIP39 seed
+    /// of the mnemonic for example)
+    pub fn from_master_seed(seed: &[u8]) -> Self {
+        let mut mac = Hmac::new(Sha512::new(), MASTER_KEY);
+        mac.input(seed);
+        Self::from_mac(mac)
+    }
+
+    /// derive the child key of hardened index `index`
+    ///
+    /// all the derivations are hardened: `derive(0)` is `m/0'` and is the
+    /// same as `derive(0 | HARDENED_INDEX)`.
+    pub fn derive(&self, index: u32) -> Self {
+        let mut mac = Hmac::new(Sha512::new(), self.chain_code.as_ref());
+        mac.input(&[0]);
+        mac.input(self.key.leak_as_ref());
+        mac.input(&(index | HARDENED_INDEX).to_be_bytes());
+        Self::from_mac(mac)
+    }
+
+    pub fn key(&self) -> &ed25519::SecretKey {
+        &self.key
+    }
+
+    pub fn chain_code(&self) -> &ChainCode {
+        &self.chain_code
+    }
+
+    pub fn public_key(&self) -> ed25519::PublicKey {
+        self.key.public_key()
+    }
+
+    pub fn into_key(self) -> ed25519::SecretKey {
+        self.key
+    }
+
+    fn from_mac(mut mac: Hmac<Sha512>) -> Self {
+        let mut bytes = [0; 64];
+        mac.raw_result(&mut bytes);
+        mac.reset();
+
+        let key = ed25519::SecretKey::try_from(&bytes[..32]).unwrap();
+        let chain_code = ChainCode::try_from(&bytes[32..]).unwrap();
+
+        bytes.scrub();
+
+        Self { key, chain_code }
+    }
+}
+
+/* Eq ********************************************************************** */
+
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.key.ct_eq(&other.key) & self.chain_code.ct_eq(&other.chain_code)
+    }
+}
+
+impl PartialEq<Self> for SecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for SecretKey {}
+
+/* Hash ******************************************************************** */
+
+impl Hash for SecretKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+        self.chain_code.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the ed25519 test vectors of SLIP-0010
+    #[test]
+    fn slip10_vectors() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let root = SecretKey::from_master_seed(&seed);
+
+        assert_eq!(
+            hex::encode(root.key().leak_as_ref()),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            root.chain_code().to_string(),
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb"
+        );
+        assert_eq!(
+            root.public_key().to_string(),
+            "a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed"
+        );
+
+        let child = root.derive(0);
+        assert_eq!(child, root.derive(HARDENED_INDEX));
+        assert_eq!(
+            hex::encode(child.key().leak_as_ref()),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+        assert_eq!(
+            child.chain_code().to_string(),
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69"
+        );
+        assert_eq!(
+            child.public_key().to_string(),
+            "8c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+/*!
+# Verifiable random function
+
+The holder of an Ed25519 [`SecretKey`] computes a pseudo random
+[`Output`] of an input (`alpha`) alongside a [`Proof`]. Anyone with the
+[`PublicKey`] verifies the proof and obtains the same output, while
+nobody can predict the output without the secret key. The output is
+unique: there is only one output for a given key and input.
+
+This is `ECVRF-EDWARDS25519-SHA512-TAI` of [RFC9381], it is useful for
+leader elections and lotteries between keynesis identities.
+
+```
+use keynesis_core::key::ed25519::SecretKey;
+# use rand::thread_rng;
+
+let secret_key = SecretKey::new(&mut thread_rng());
+let public_key = secret_key.public_key();
+
+let (output, proof) = secret_key.vrf_prove(b"epoch 42");
+
+assert_eq!(public_key.vrf_verify(b"epoch 42", &proof), Some(output));
+assert_eq!(public_key.vrf_verify(b"epoch 43", &proof), None);
+```
+
+[RFC9381]: https://www.rfc-editor.org/rfc/rfc9381.html
+[`SecretKey`]: crate::key::ed25519::SecretKey
+[`PublicKey`]: crate::key::ed25519::PublicKey
+*/
+
+use crate::memsec::Scrubbed as _;
+use cryptoxide::{digest::Digest as _, sha2::Sha512};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_TABLE,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::IsIdentity as _,
+};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug, Display, Formatter},
+};
+use thiserror::Error;
+
+/// `ECVRF-EDWARDS25519-SHA512-TAI`
+const SUITE: u8 = 0x03;
+
+const CHALLENGE_SIZE: usize = 16;
+
+/// the proof that an [`Output`] was computed with the secret key of a
+/// public key
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Proof([u8; Self::SIZE]);
+
+/// the pseudo random output of the VRF
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Output([u8; Self::SIZE]);
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ProofError {
+    #[error("Invalid size, expecting {}", Proof::SIZE)]
+    InvalidSize,
+}
+
+/* Prove and verify ******************************************************** */
+
+/// compute the output and the proof with the expanded (and clamped)
+/// secret key
+pub(crate) fn prove(key: &[u8; 64], alpha: &[u8]) -> (Output, Proof) {
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(&key[..32]);
+    let x = Scalar::from_bytes_mod_order(bytes);
+    bytes.scrub();
+    let public = (&x * &ED25519_BASEPOINT_TABLE).compress();
+
+    let h = encode_to_curve(public.as_bytes(), alpha)
+        .expect("a point is found with an overwhelming probability");
+    let h_string = h.compress();
+    let gamma = x * h;
+
+    let mut hash = [0; 64];
+    let mut hasher = Sha512::new();
+    hasher.input(&key[32..]);
+    hasher.input(h_string.as_bytes());
+    hasher.result(&mut hash);
+    let k = Scalar::from_bytes_mod_order_wide(&hash);
+    hash.scrub();
+
+    let c = challenge(
+        public.as_bytes(),
+        &h_string,
+        &gamma,
+        &(&k * &ED25519_BASEPOINT_TABLE),
+        &(k * h),
+    );
+    let s = k + c * x;
+
+    let mut proof = [0; Proof::SIZE];
+    proof[..32].copy_from_slice(gamma.compress().as_bytes());
+    proof[32..48].copy_from_slice(&c.as_bytes()[..CHALLENGE_SIZE]);
+    proof[48..].copy_from_slice(s.as_bytes());
+
+    (proof_to_hash(&gamma), Proof(proof))
+}
+
+/// verify the proof and return the output
+pub(crate) fn verify(public_key: &[u8; 32], alpha: &[u8], proof: &Proof) -> Option<Output> {
+    let y = CompressedEdwardsY(*public_key).decompress()?;
+    if y.is_small_order() {
+        return None;
+    }
+
+    let (gamma, c, s) = proof.decode()?;
+
+    let h = encode_to_curve(public_key, alpha)?;
+    let h_string = h.compress();
+    let u = &s * &ED25519_BASEPOINT_TABLE - c * y;
+    let v = s * h - c * gamma;
+
+    if challenge(public_key, &h_string, &gamma, &u, &v) == c {
+        Some(proof_to_hash(&gamma))
+    } else {
+        None
+    }
+}
+
+/* Proof ******************************************************************* */
+
+impl Proof {
+    pub const SIZE: usize = 32 + CHALLENGE_SIZE + 32;
+
+    fn decode(&self) -> Option<(EdwardsPoint, Scalar, Scalar)> {
+        let mut gamma = [0; 32];
+        gamma.copy_from_slice(&self.0[..32]);
+        let gamma = CompressedEdwardsY(gamma).decompress()?;
+
+        let mut c = [0; 32];
+        c[..CHALLENGE_SIZE].copy_from_slice(&self.0[32..48]);
+        let c = Scalar::from_canonical_bytes(c)?;
+
+        let mut s = [0; 32];
+        s.copy_from_slice(&self.0[48..]);
+        let s = Scalar::from_canonical_bytes(s)?;
+
+        Some((gamma, c, s))
+    }
+
+    /// the output of the VRF, **only to use once the proof has been
+    /// verified** (see [`PublicKey::vrf_verify`])
+    ///
+    /// [`PublicKey::vrf_verify`]: crate::key::ed25519::PublicKey::vrf_verify
+    pub fn output_unverified(&self) -> Option<Output> {
+        self.decode().map(|(gamma, _, _)| proof_to_hash(&gamma))
+    }
+}
+
+impl Output {
+    pub const SIZE: usize = 64;
+}
+
+/* Format ****************************************************************** */
+
+impl Display for Proof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&hex::encode(self.as_ref()), f)
+    }
+}
+
+impl Debug for Proof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Proof")
+            .field(&hex::encode(self.as_ref()))
+            .finish()
+    }
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&hex::encode(self.as_ref()), f)
+    }
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Output")
+            .field(&hex::encode(self.as_ref()))
+            .finish()
+    }
+}
+
+/* Conversion ************************************************************** */
+
+impl From<[u8; Self::SIZE]> for Proof {
+    fn from(bytes: [u8; Self::SIZE]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Proof {
+    type Error = ProofError;
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let bytes = <[u8; Self::SIZE]>::try_from(value).map_err(|_| ProofError::InvalidSize)?;
+        Ok(Self(bytes))
+    }
+}
+
+impl AsRef<[u8]> for Proof {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Output {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/* Helpers ***************************************************************** */
+
+/// `ECVRF_encode_to_curve_try_and_increment`
+fn encode_to_curve(public_key: &[u8; 32], alpha: &[u8]) -> Option<EdwardsPoint> {
+    (0..=u8::MAX).find_map(|ctr| {
+        let mut hash = [0; 64];
+        let mut hasher = Sha512::new();
+        hasher.input(&[SUITE, 0x01]);
+        hasher.input(public_key);
+        hasher.input(alpha);
+        hasher.input(&[ctr, 0x00]);
+        hasher.result(&mut hash);
+
+        let mut point = [0; 32];
+        point.copy_from_slice(&hash[..32]);
+        CompressedEdwardsY(point)
+            .decompress()
+            .map(|point| point.mul_by_cofactor())
+            .filter(|point| !point.is_identity())
+    })
+}
+
+/// `ECVRF_challenge_generation`
+fn challenge(
+    public_key: &[u8; 32],
+    h: &CompressedEdwardsY,
+    gamma: &EdwardsPoint,
+    u: &EdwardsPoint,
+    v: &EdwardsPoint,
+) -> Scalar {
+    let mut hash = [0; 64];
+    let mut hasher = Sha512::new();
+    hasher.input(&[SUITE, 0x02]);
+    hasher.input(public_key);
+    hasher.input(h.as_bytes());
+    hasher.input(gamma.compress().as_bytes());
+    hasher.input(u.compress().as_bytes());
+    hasher.input(v.compress().as_bytes());
+    hasher.input(&[0x00]);
+    hasher.result(&mut hash);
+
+    let mut c = [0; 32];
+    c[..CHALLENGE_SIZE].copy_from_slice(&hash[..CHALLENGE_SIZE]);
+    Scalar::from_bits(c)
+}
+
+/// `ECVRF_proof_to_hash`
+fn proof_to_hash(gamma: &EdwardsPoint) -> Output {
+    let mut output = [0; Output::SIZE];
+    let mut hasher = Sha512::new();
+    hasher.input(&[SUITE, 0x03]);
+    hasher.input(gamma.mul_by_cofactor().compress().as_bytes());
+    hasher.input(&[0x00]);
+    hasher.result(&mut output);
+    Output(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::key::ed25519::{PublicKey, SecretKey};
+    use std::str::FromStr as _;
+
+    /// test vectors of the RFC9381 (section B.3)
+    #[test]
+    fn rfc9381_vectors() {
+        let vectors = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "",
+                "8657106690b5526245a92b003bb079ccd1a92130477671f6fc01ad16f26f723f26f8a57ccaed74ee1b190bed1f479d9727d2d0f9b005a6e456a35d4fb0daab1268a1b0db10836d9826a528ca76567805",
+                "90cf1df3b703cce59e2a35b925d411164068269d7b2d29f3301c03dd757876ff66b71dda49d2de59d03450451af026798e8f81cd2e333de5cdf4f3e140fdd8ae",
+            ),
+        ];
+
+        for (secret_key, alpha, proof, output) in vectors {
+            let secret_key = SecretKey::from_str(secret_key).unwrap();
+            let alpha = hex::decode(alpha).unwrap();
+            let (computed_output, computed_proof) = secret_key.vrf_prove(&alpha);
+
+            assert_eq!(hex::encode(computed_proof), proof);
+            assert_eq!(hex::encode(computed_output), output);
+            assert_eq!(
+                secret_key.public_key().vrf_verify(&alpha, &computed_proof),
+                Some(computed_output)
+            );
+        }
+    }
+
+    #[quickcheck]
+    fn prove_verify(secret_key: SecretKey, alpha: Vec<u8>) -> bool {
+        let public_key = secret_key.public_key();
+        let (output, proof) = secret_key.vrf_prove(&alpha);
+
+        public_key.vrf_verify(&alpha, &proof) == Some(output)
+            && proof.output_unverified() == Some(output)
+    }
+
+    #[quickcheck]
+    fn wrong_key_or_input(secret_key: SecretKey, other: SecretKey, alpha: Vec<u8>) -> bool {
+        let (_, proof) = secret_key.vrf_prove(&alpha);
+        let mut other_alpha = alpha.clone();
+        other_alpha.push(0);
+
+        secret_key
+            .public_key()
+            .vrf_verify(&other_alpha, &proof)
+            .is_none()
+            && (secret_key.public_key() == other.public_key()
+                || other.public_key().vrf_verify(&alpha, &proof).is_none())
+    }
+
+    #[test]
+    fn small_order_key() {
+        let secret_key =
+            SecretKey::from_str("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+                .unwrap();
+        let (_, proof) = secret_key.vrf_prove(b"");
+        let mut identity = [0; PublicKey::SIZE];
+        identity[0] = 1;
+
+        assert!(PublicKey::from(identity).vrf_verify(b"", &proof).is_none());
+    }
+}
@@ -0,0 +1,274 @@
+/*!
+# Extended public keys
+
+An [`ExtendedPublicKey`] is an [`ed25519_hd::PublicKey`] (the key and
+its chain code) with its depth in the hierarchy and the fingerprint of
+its parent key. It is encoded in a single, checksummed, string so it can
+be handed to a watch-only service that derives the child public keys:
+
+```
+use keynesis_core::key::{ed25519_hd::SecretKey, xpub::ExtendedPublicKey};
+# use rand::thread_rng;
+
+let root = SecretKey::new(&mut thread_rng());
+let account = root.derive_hardened(b"account");
+
+// hand the string to the watch-only service
+let xpub = ExtendedPublicKey::new(account.public_key(), 1, root.public_key().fingerprint());
+let s = xpub.to_string();
+
+let xpub: ExtendedPublicKey = s.parse().unwrap();
+let invoice = xpub.derive(b"invoice 42").unwrap();
+assert_eq!(invoice.public_key(), &account.derive(b"invoice 42").public_key());
+assert_eq!(invoice.depth(), 2);
+```
+
+The string is the [`bech32`] encoding (with the `xpub` human readable
+part) of a version byte, the depth, the parent's fingerprint, the public
+key and the chain code.
+
+[`ed25519_hd::PublicKey`]: crate::key::ed25519_hd::PublicKey
+[`bech32`]: crate::bech32
+*/
+
+use crate::{
+    bech32::{self, Bech32Error},
+    canonical::Canonical as _,
+    key::{
+        derivation_path::AsDerivationPath,
+        ed25519_hd::{DerivationError, PublicKey},
+        Fingerprint,
+    },
+};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use thiserror::Error;
+
+const HRP: &str = "xpub";
+
+const VERSION: u8 = 1;
+
+const PUBLIC_KEY_OFFSET: usize = 2 + Fingerprint::SIZE;
+
+/// a [`PublicKey`] with its depth and the fingerprint of its parent
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtendedPublicKey {
+    depth: u8,
+    parent_fingerprint: Fingerprint,
+    public_key: PublicKey,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ExtendedPublicKeyError {
+    #[error("Invalid bech32 string")]
+    InvalidBech32(
+        #[source]
+        #[from]
+        Bech32Error,
+    ),
+
+    #[error("Unsupported version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Invalid size, expecting {}", ExtendedPublicKey::SIZE)]
+    InvalidSize,
+
+    #[error("Invalid public key")]
+    InvalidPublicKey,
+}
+
+impl ExtendedPublicKey {
+    pub const SIZE: usize = 1 + 1 + Fingerprint::SIZE + PublicKey::SIZE;
+
+    pub fn new(public_key: PublicKey, depth: u8, parent_fingerprint: Fingerprint) -> Self {
+        Self {
+            depth,
+            parent_fingerprint,
+            public_key,
+        }
+    }
+
+    /// a root key: at depth 0 and without parent (its parent fingerprint
+    /// is zero)
+    pub fn root(public_key: PublicKey) -> Self {
+        Self::new(public_key, 0, Fingerprint::zero())
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn parent_fingerprint(&self) -> &Fingerprint {
+        &self.parent_fingerprint
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn into_public_key(self) -> PublicKey {
+        self.public_key
+    }
+
+    /// derive the child public key for the given path (see
+    /// [`PublicKey::derive`]), the depth is increased by the number of
+    /// segments of the path
+    pub fn derive<P>(&self, path: P) -> Result<Self, DerivationError>
+    where
+        P: AsDerivationPath,
+    {
+        path.segments()
+            .into_iter()
+            .try_fold(self.clone(), |key, segment| key.derive_child(segment))
+    }
+
+    /// derive the child public key with the integer `index` (see
+    /// [`PublicKey::derive_index`])
+    pub fn derive_index(&self, index: u32) -> Result<Self, DerivationError> {
+        let public_key = self.public_key.derive_index(index)?;
+        self.child(public_key)
+    }
+
+    fn derive_child(&self, segment: &[u8]) -> Result<Self, DerivationError> {
+        let public_key = self.public_key.derive(segment)?;
+        self.child(public_key)
+    }
+
+    fn child(&self, public_key: PublicKey) -> Result<Self, DerivationError> {
+        let depth = self
+            .depth
+            .checked_add(1)
+            .ok_or(DerivationError::MaximumDepth)?;
+
+        Ok(Self {
+            depth,
+            parent_fingerprint: self.public_key.fingerprint(),
+            public_key,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = VERSION;
+        bytes[1] = self.depth;
+        bytes[2..PUBLIC_KEY_OFFSET].copy_from_slice(self.parent_fingerprint.as_ref());
+        bytes[PUBLIC_KEY_OFFSET..].copy_from_slice(&self.public_key.to_canonical_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExtendedPublicKeyError> {
+        match bytes.first() {
+            None => return Err(ExtendedPublicKeyError::InvalidSize),
+            Some(&VERSION) => {}
+            Some(version) => return Err(ExtendedPublicKeyError::UnsupportedVersion(*version)),
+        }
+        if bytes.len() != Self::SIZE {
+            return Err(ExtendedPublicKeyError::InvalidSize);
+        }
+
+        let mut parent_fingerprint = [0; Fingerprint::SIZE];
+        parent_fingerprint.copy_from_slice(&bytes[2..PUBLIC_KEY_OFFSET]);
+        let public_key = PublicKey::from_bytes_strict(&bytes[PUBLIC_KEY_OFFSET..])
+            .map_err(|_| ExtendedPublicKeyError::InvalidPublicKey)?;
+
+        Ok(Self {
+            depth: bytes[1],
+            parent_fingerprint: Fingerprint::from(parent_fingerprint),
+            public_key,
+        })
+    }
+}
+
+/* Format ****************************************************************** */
+
+impl Display for ExtendedPublicKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&bech32::encode(HRP, self.to_bytes()))
+    }
+}
+
+impl FromStr for ExtendedPublicKey {
+    type Err = ExtendedPublicKeyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bech32::decode_with_hrp(HRP, s)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::ed25519_hd::{SecretKey, HARDENED_INDEX};
+
+    #[quickcheck]
+    fn display_parse(root: SecretKey, path: Vec<u8>) -> bool {
+        let xpub = ExtendedPublicKey::root(root.public_key())
+            .derive(&path)
+            .unwrap();
+        let decoded: ExtendedPublicKey = xpub.to_string().parse().unwrap();
+
+        decoded == xpub
+    }
+
+    #[quickcheck]
+    fn derive(root: SecretKey, path: Vec<u8>, index: u32) -> bool {
+        let xpub = ExtendedPublicKey::root(root.public_key());
+        let child = xpub.derive(&path).unwrap();
+        let grandchild = child.derive_index(index % HARDENED_INDEX).unwrap();
+
+        child.public_key() == &root.derive(&path).public_key()
+            && child.depth() == 1
+            && child.parent_fingerprint() == &root.public_key().fingerprint()
+            && grandchild.depth() == 2
+            && grandchild.parent_fingerprint() == &child.public_key().fingerprint()
+            && xpub.derive_index(index | HARDENED_INDEX).is_err()
+    }
+
+    #[test]
+    fn maximum_depth() {
+        let root = SecretKey::new(rand::thread_rng());
+        let xpub =
+            ExtendedPublicKey::new(root.public_key(), u8::MAX, root.public_key().fingerprint());
+
+        assert!(matches!(
+            xpub.derive(b"child"),
+            Err(DerivationError::MaximumDepth)
+        ));
+    }
+
+    #[test]
+    fn invalid_strings() {
+        let root = SecretKey::new(rand::thread_rng());
+        let xpub = ExtendedPublicKey::root(root.public_key());
+
+        let mut s = xpub.to_string();
+        let last = s.pop().unwrap();
+        s.push(if last == 'q' { 'p' } else { 'q' });
+        assert!(matches!(
+            s.parse::<ExtendedPublicKey>(),
+            Err(ExtendedPublicKeyError::InvalidBech32(
+                Bech32Error::InvalidChecksum
+            ))
+        ));
+
+        let mut bytes = xpub.to_bytes();
+        bytes[0] = 2;
+        assert!(matches!(
+            bech32::encode(HRP, bytes).parse::<ExtendedPublicKey>(),
+            Err(ExtendedPublicKeyError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            bech32::encode(HRP, &xpub.to_bytes()[..40]).parse::<ExtendedPublicKey>(),
+            Err(ExtendedPublicKeyError::InvalidSize)
+        ));
+        assert!(matches!(
+            bech32::encode("pk", xpub.to_bytes()).parse::<ExtendedPublicKey>(),
+            Err(ExtendedPublicKeyError::InvalidBech32(
+                Bech32Error::UnexpectedHrp { .. }
+            ))
+        ));
+    }
+}
@@ -11,14 +11,31 @@ and APIs to use for different purpose
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
 
+pub mod bech32;
 mod buffer;
+pub mod canonical;
+pub mod deniable;
+pub mod dkg;
+pub mod fanout;
 pub mod hash;
+pub mod kdf;
 pub mod key;
 pub mod memsec;
+#[cfg(feature = "mnemonic")]
+pub mod mnemonic;
 pub mod noise;
+pub mod opaque;
+#[cfg(feature = "openssh")]
+pub mod openssh;
+pub mod pake;
+#[cfg(feature = "pkcs8")]
+pub mod pkcs8;
+pub mod prekey;
+pub mod schedule;
 mod seed;
 
 pub use self::{
+    buffer::OutBuffer,
     key::{ed25519::Signature, SharedSecret},
-    seed::Seed,
+    seed::{Seed, SeedError},
 };
@@ -0,0 +1,336 @@
+/*!
+# BIP39 mnemonic phrases
+
+A [`Mnemonic`] encodes 128 to 256 bits of entropy (and a checksum) in
+12 to 24 words of the [BIP39] English word list, so a root key can be
+written down on paper and restored later.
+
+```
+use keynesis_core::mnemonic::Mnemonic;
+# use rand::thread_rng;
+
+let mnemonic = Mnemonic::new(thread_rng());
+assert_eq!(mnemonic.word_count(), 24);
+
+// the words are all that need to be kept to restore the mnemonic
+let restored: Mnemonic = mnemonic.to_string().parse().unwrap();
+assert_eq!(restored.to_seed("passphrase"), mnemonic.to_seed("passphrase"));
+```
+
+The [BIP39] seed is stretched from the words and an optional passphrase so
+the same words with a different passphrase give a different seed. See
+[`ed25519_hd::SecretKey::from_mnemonic`] to create a root key from it.
+
+The passphrase is used as given: the [BIP39] specification expects it
+to be normalized (NFKD) first, the application is responsible for that
+if the passphrase is not ASCII.
+
+[BIP39]: https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki
+[`ed25519_hd::SecretKey::from_mnemonic`]: crate::key::ed25519_hd::SecretKey::from_mnemonic
+*/
+
+use crate::memsec::Scrubbed as _;
+use cryptoxide::{
+    digest::Digest as _,
+    hmac::Hmac,
+    pbkdf2::pbkdf2,
+    sha2::{Sha256, Sha512},
+};
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
+    sync::OnceLock,
+};
+use subtle::{Choice, ConstantTimeEq};
+use thiserror::Error;
+
+/// the 2048 words of the BIP39 English word list, one per line, sorted
+const ENGLISH: &str = include_str!("english.txt");
+const WORD_BITS: usize = 11;
+const SEED_ITERATIONS: u32 = 2048;
+
+/// the phrase of words encoding the entropy
+///
+/// the entropy is scrubbed (zeroed) when the mnemonic is dropped, the
+/// mnemonics are compared in constant time (only the number of words
+/// may leak).
+#[derive(Clone)]
+pub struct Mnemonic {
+    entropy: Vec<u8>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MnemonicError {
+    #[error("Invalid entropy size ({0} bytes), expecting 16, 20, 24, 28 or 32 bytes")]
+    InvalidEntropySize(usize),
+
+    #[error("Invalid number of words ({0}), expecting 12, 15, 18, 21 or 24 words")]
+    InvalidWordCount(usize),
+
+    #[error("Unknown word {0:?}")]
+    UnknownWord(String),
+
+    #[error("Invalid checksum")]
+    InvalidChecksum,
+}
+
+fn words() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| ENGLISH.lines().collect())
+}
+
+impl Mnemonic {
+    /// size of the entropy of the [`Mnemonic::new`] mnemonics
+    pub const ENTROPY_SIZE: usize = 32;
+    /// size of the seed generated by [`Mnemonic::to_seed`]
+    pub const SEED_SIZE: usize = 64;
+
+    /// generate a new mnemonic of 24 words with the given random number
+    /// generator
+    pub fn new<Rng>(mut rng: Rng) -> Self
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let mut entropy = vec![0; Self::ENTROPY_SIZE];
+        rng.fill_bytes(&mut entropy);
+        Self { entropy }
+    }
+
+    /// create the mnemonic of the given entropy
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, MnemonicError> {
+        if !(16..=32).contains(&entropy.len()) || !entropy.len().is_multiple_of(4) {
+            return Err(MnemonicError::InvalidEntropySize(entropy.len()));
+        }
+
+        Ok(Self {
+            entropy: entropy.to_vec(),
+        })
+    }
+
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+
+    pub fn word_count(&self) -> usize {
+        (self.entropy.len() * 8 + self.checksum_bits()) / WORD_BITS
+    }
+
+    /// the words of the mnemonic
+    pub fn words(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let checksum = checksum(&self.entropy);
+        let bit = move |i: usize| {
+            let byte = if i < self.entropy.len() * 8 {
+                self.entropy[i / 8]
+            } else {
+                checksum
+            };
+            (byte >> (7 - i % 8)) & 1
+        };
+
+        (0..self.word_count()).map(move |word| {
+            let index = (0..WORD_BITS).fold(0, |index, i| {
+                (index << 1) | bit(word * WORD_BITS + i) as usize
+            });
+            words()[index]
+        })
+    }
+
+    /// generate the BIP39 seed of the mnemonic and the `passphrase`
+    ///
+    /// the passphrase may be empty.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; Self::SEED_SIZE] {
+        let mut phrase = self.to_string();
+        let mut salt = format!("mnemonic{}", passphrase);
+
+        let mut seed = [0; Self::SEED_SIZE];
+        let mut mac = Hmac::new(Sha512::new(), phrase.as_bytes());
+        pbkdf2(&mut mac, salt.as_bytes(), SEED_ITERATIONS, &mut seed);
+
+        // SAFETY: the strings are scrubbed with zeroes which are valid utf8
+        unsafe {
+            phrase.as_bytes_mut().scrub();
+            salt.as_bytes_mut().scrub();
+        }
+
+        seed
+    }
+
+    /// the size of the checksum is 1 bit per 32 bits of entropy
+    fn checksum_bits(&self) -> usize {
+        self.entropy.len() * 8 / 32
+    }
+}
+
+/// the first byte of the SHA256 of the entropy, only the highest
+/// [`Mnemonic::checksum_bits`] are used
+fn checksum(entropy: &[u8]) -> u8 {
+    let mut hash = [0; 32];
+    let mut sha256 = Sha256::new();
+    sha256.input(entropy);
+    sha256.result(&mut hash);
+    hash[0]
+}
+
+impl ConstantTimeEq for Mnemonic {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.entropy[..].ct_eq(&other.entropy[..])
+    }
+}
+
+impl PartialEq for Mnemonic {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Mnemonic {}
+
+impl Drop for Mnemonic {
+    fn drop(&mut self) {
+        self.entropy.scrub();
+    }
+}
+
+impl Display for Mnemonic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, word) in self.words().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(word)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Mnemonic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mnemonic")
+            .field("word_count", &self.word_count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = MnemonicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let indices = s
+            .split_whitespace()
+            .map(|word| {
+                words()
+                    .binary_search(&word)
+                    .map_err(|_| MnemonicError::UnknownWord(word.to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !(12..=24).contains(&indices.len()) || !indices.len().is_multiple_of(3) {
+            return Err(MnemonicError::InvalidWordCount(indices.len()));
+        }
+
+        let total_bits = indices.len() * WORD_BITS;
+        let checksum_bits = total_bits / 33;
+        let mut bytes = vec![0u8; total_bits.div_ceil(8)];
+        for (i, index) in indices.iter().enumerate() {
+            for b in 0..WORD_BITS {
+                if (index >> (WORD_BITS - 1 - b)) & 1 == 1 {
+                    let bit = i * WORD_BITS + b;
+                    bytes[bit / 8] |= 1 << (7 - bit % 8);
+                }
+            }
+        }
+
+        let entropy_size = (total_bits - checksum_bits) / 8;
+        let mask = 0xff_u8 << (8 - checksum_bits);
+        let valid = checksum(&bytes[..entropy_size]) & mask == bytes[entropy_size] & mask;
+
+        let mnemonic = Self::from_entropy(&bytes[..entropy_size]);
+        bytes.scrub();
+
+        if valid {
+            mnemonic
+        } else {
+            Err(MnemonicError::InvalidChecksum)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    /// test vectors of the BIP39 specification (with the `TREZOR`
+    /// passphrase)
+    const VECTORS: &[(&str, &str, &str)] = &[
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+            "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+        ),
+    ];
+
+    #[test]
+    fn word_list() {
+        let words = words();
+        assert_eq!(words.len(), 1 << WORD_BITS);
+        assert!(words.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_vectors() {
+        for (entropy, phrase, seed) in VECTORS {
+            let mnemonic = Mnemonic::from_entropy(&hex::decode(entropy).unwrap()).unwrap();
+            assert_eq!(&mnemonic.to_string(), phrase);
+            assert_eq!(hex::encode(mnemonic.to_seed("TREZOR")), *seed);
+
+            let decoded: Mnemonic = phrase.parse().unwrap();
+            assert_eq!(decoded, mnemonic);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        for _ in 0..16 {
+            let mnemonic = Mnemonic::new(thread_rng());
+            assert_eq!(mnemonic.word_count(), 24);
+            let decoded: Mnemonic = mnemonic.to_string().parse().unwrap();
+            assert_eq!(decoded, mnemonic);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+                .parse::<Mnemonic>(),
+            Err(MnemonicError::InvalidChecksum)
+        );
+        assert_eq!(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"
+                .parse::<Mnemonic>(),
+            Err(MnemonicError::InvalidWordCount(11))
+        );
+        assert_eq!(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon keynesis"
+                .parse::<Mnemonic>(),
+            Err(MnemonicError::UnknownWord("keynesis".to_owned()))
+        );
+        assert_eq!(
+            Mnemonic::from_entropy(&[0; 15]),
+            Err(MnemonicError::InvalidEntropySize(15))
+        );
+    }
+}
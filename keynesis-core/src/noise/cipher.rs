@@ -0,0 +1,175 @@
+use aes_gcm::{
+    aead::{AeadInPlace as _, NewAead as _},
+    Aes256Gcm,
+};
+use cryptoxide::chacha20poly1305::{Context, DecryptionResult, Tag};
+use std::fmt::Debug;
+
+/// size of the keys of the ciphers
+pub const KEY_LEN: usize = 32;
+
+/// size of the authentication tags of the ciphers
+pub const TAG_LEN: usize = 16;
+
+/// the AEAD of the Noise handshakes and transport sessions
+///
+/// The cipher is a type parameter of the handshakes, the default is
+/// [`ChaChaPoly`]; both peers need to use the same cipher.
+///
+/// ```
+/// use keynesis_core::{hash::Blake2b, key::ed25519::SecretKey, noise::{AesGcm, IK}};
+/// # use rand::thread_rng;
+///
+/// # let responder_key = SecretKey::new(&mut thread_rng());
+/// let initiator = IK::<SecretKey, Blake2b, _, _, AesGcm>::with_cipher(thread_rng(), &None, &[]);
+/// # let initiator_key = SecretKey::new(&mut thread_rng());
+/// # let mut output = Vec::new();
+/// let initiator = initiator.initiate(&initiator_key, responder_key.public_key(), &mut output);
+/// ```
+pub trait Cipher: Debug + Clone + Copy + Default {
+    /// the name of the cipher in the Noise protocol name
+    fn name() -> &'static str;
+
+    /// encrypt the `buffer` in place with the key `k` and the nonce `n`,
+    /// returns the authentication tag
+    fn encrypt(k: &[u8; KEY_LEN], n: u64, ad: &[u8], buffer: &mut [u8]) -> [u8; TAG_LEN];
+
+    /// decrypt the `buffer` in place with the key `k` and the nonce `n`
+    ///
+    /// returns `false` if the `tag` does not authenticate the `buffer`,
+    /// the `buffer` is left unchanged then.
+    fn decrypt(
+        k: &[u8; KEY_LEN],
+        n: u64,
+        ad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8; TAG_LEN],
+    ) -> bool;
+}
+
+/// ChaCha20-Poly1305, the nonce is encoded in little endian
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChaChaPoly;
+
+/// AES-256-GCM, the nonce is encoded in big endian
+///
+/// the AES instructions of the CPU are used when they are available,
+/// prefer [`ChaChaPoly`] otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AesGcm;
+
+impl Cipher for ChaChaPoly {
+    fn name() -> &'static str {
+        "ChaChaPoly"
+    }
+
+    fn encrypt(k: &[u8; KEY_LEN], n: u64, ad: &[u8], buffer: &mut [u8]) -> [u8; TAG_LEN] {
+        let mut ctx = Context::new(k, &Self::nonce(n));
+        ctx.add_data(ad);
+        let mut ctx = ctx.to_encryption();
+        ctx.encrypt_mut(buffer);
+        ctx.finalize().0
+    }
+
+    fn decrypt(
+        k: &[u8; KEY_LEN],
+        n: u64,
+        ad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8; TAG_LEN],
+    ) -> bool {
+        let nonce = Self::nonce(n);
+        let mut ctx = Context::new(k, &nonce);
+        ctx.add_data(ad);
+        let mut ctx = ctx.to_decryption();
+        ctx.decrypt_mut(buffer);
+
+        match ctx.finalize(&Tag(*tag)) {
+            DecryptionResult::Match => true,
+            DecryptionResult::MisMatch => {
+                // applying the key stream again restores the cipher text
+                let mut ctx = Context::new(k, &nonce).to_encryption();
+                ctx.encrypt_mut(buffer);
+                false
+            }
+        }
+    }
+}
+
+impl ChaChaPoly {
+    fn nonce(n: u64) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&n.to_le_bytes());
+        nonce
+    }
+}
+
+impl Cipher for AesGcm {
+    fn name() -> &'static str {
+        "AESGCM"
+    }
+
+    fn encrypt(k: &[u8; KEY_LEN], n: u64, ad: &[u8], buffer: &mut [u8]) -> [u8; TAG_LEN] {
+        let tag = Aes256Gcm::new(k.into())
+            .encrypt_in_place_detached(&Self::nonce(n).into(), ad, buffer)
+            .expect("the noise messages are shorter than the limit of AES-GCM");
+        tag.into()
+    }
+
+    fn decrypt(
+        k: &[u8; KEY_LEN],
+        n: u64,
+        ad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8; TAG_LEN],
+    ) -> bool {
+        Aes256Gcm::new(k.into())
+            .decrypt_in_place_detached(&Self::nonce(n).into(), ad, buffer, tag.into())
+            .is_ok()
+    }
+}
+
+impl AesGcm {
+    fn nonce(n: u64) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&n.to_be_bytes());
+        nonce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypt_decrypt<C: Cipher>(k: [u8; KEY_LEN], n: u64, ad: Vec<u8>, message: Vec<u8>) -> bool {
+        let mut buffer = message.clone();
+        let tag = C::encrypt(&k, n, &ad, &mut buffer);
+        let cipher_text = buffer.clone();
+
+        let mut wrong_tag = tag;
+        wrong_tag[0] ^= 1;
+        // a wrong tag or nonce is rejected and the cipher text kept
+        if C::decrypt(&k, n, &ad, &mut buffer, &wrong_tag) || buffer != cipher_text {
+            return false;
+        }
+        if C::decrypt(&k, n.wrapping_add(1), &ad, &mut buffer, &tag) || buffer != cipher_text {
+            return false;
+        }
+
+        C::decrypt(&k, n, &ad, &mut buffer, &tag) && buffer == message
+    }
+
+    #[quickcheck]
+    fn chacha_poly(k: u64, n: u64, ad: Vec<u8>, message: Vec<u8>) -> bool {
+        let mut key = [0; KEY_LEN];
+        key[..8].copy_from_slice(&k.to_le_bytes());
+        encrypt_decrypt::<ChaChaPoly>(key, n, ad, message)
+    }
+
+    #[quickcheck]
+    fn aes_gcm(k: u64, n: u64, ad: Vec<u8>, message: Vec<u8>) -> bool {
+        let mut key = [0; KEY_LEN];
+        key[..8].copy_from_slice(&k.to_le_bytes());
+        encrypt_decrypt::<AesGcm>(key, n, ad, message)
+    }
+}
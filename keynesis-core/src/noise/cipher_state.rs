@@ -1,21 +1,34 @@
-use cryptoxide::chacha20poly1305::{ChaCha20Poly1305, Context};
-use std::fmt;
+use crate::{
+    memsec::Scrubbed as _,
+    noise::cipher::{ChaChaPoly, Cipher, KEY_LEN, TAG_LEN},
+    OutBuffer,
+};
+use std::{fmt, marker::PhantomData};
+use subtle::{Choice, ConstantTimeEq};
 use thiserror::Error;
 
 #[derive(Debug, Default, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Nonce(u64);
 
+/// the state of the [`Cipher`] `C` of one direction of a session
 #[derive(Clone)]
-pub struct CipherState {
-    k: [u8; Self::KEY_LEN],
+pub struct CipherState<C = ChaChaPoly> {
+    k: [u8; KEY_LEN],
     n: Nonce,
     has_key: bool,
+    cipher: PhantomData<C>,
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum CipherStateError {
-    #[error("The nonce has reached 2^64-1 operations already")]
-    Nonce,
+    /// the 2^64-1 nonces of the cipher have been used, the cipher state
+    /// cannot encrypt or decrypt anymore: a new handshake is needed
+    ///
+    /// the output is left unchanged. See [`CipherState::remaining`] to
+    /// start the new handshake before it happens.
+    #[error("The nonce has reached 2^64-1 operations already, a new handshake is needed")]
+    NonceExhausted,
 
     #[error("Not enough bytes allocated in the output's")]
     NotEnoughOutput,
@@ -25,6 +38,15 @@ pub enum CipherStateError {
 
     #[error("Authenticated decryption failed, invalid final tag")]
     InvalidTag,
+
+    #[error("Invalid chunk, expecting a message sent with send_chunked")]
+    InvalidChunk,
+
+    #[error("The chunked message is larger than the maximum message size")]
+    MessageTooLarge,
+
+    #[error("The message has already been received or is too old")]
+    Replayed,
 }
 
 impl Nonce {
@@ -40,6 +62,10 @@ impl Nonce {
         self.0
     }
 
+    pub(crate) const fn from_u64(n: u64) -> Self {
+        Self(n)
+    }
+
     #[inline(always)]
     fn to_bytes(self) -> [u8; 12] {
         let mut nonce_bytes = [0u8; 12];
@@ -52,123 +78,258 @@ impl Nonce {
     }
 }
 
-impl fmt::Debug for CipherState {
+impl<C: Cipher> fmt::Debug for CipherState<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("CipherState")
-            .field("k", &hex::encode(&self.k))
-            .field("n", &hex::encode(&self.n.to_bytes()))
+        f.debug_struct(&format!("CipherState<{}>", C::name()))
+            .field("k", &hex::encode(self.k))
+            .field("n", &hex::encode(self.n.to_bytes()))
             .finish()
     }
 }
 
-impl CipherState {
-    pub const KEY_LEN: usize = 32;
-    pub const TAG_LEN: usize = 16;
-
+impl<C: Cipher> CipherState<C> {
     pub fn new() -> Self {
         Self {
-            k: [0; Self::KEY_LEN],
+            k: [0; KEY_LEN],
             n: Nonce::zero(),
             has_key: false,
+            cipher: PhantomData,
         }
     }
 
-    pub fn initialize_key(k: [u8; Self::KEY_LEN]) -> Self {
+    pub fn initialize_key(k: [u8; KEY_LEN]) -> Self {
         Self {
             k,
             n: Nonce::zero(),
             has_key: true,
+            cipher: PhantomData,
+        }
+    }
+
+    /// restore a cipher state with the key `k` and the next nonce `n`
+    pub(crate) fn restore(k: [u8; KEY_LEN], n: Nonce) -> Self {
+        Self {
+            k,
+            n,
+            has_key: true,
+            cipher: PhantomData,
         }
     }
 
+    pub(crate) fn key(&self) -> &[u8; KEY_LEN] {
+        &self.k
+    }
+
     #[inline(always)]
     pub fn has_key(&self) -> bool {
         self.has_key
     }
 
+    /// number of bytes the encryption of `len` bytes of plaintext
+    /// will output
+    #[inline(always)]
+    pub fn encrypted_len(&self, len: usize) -> usize {
+        if self.has_key() {
+            len + TAG_LEN
+        } else {
+            len
+        }
+    }
+
     #[inline(always)]
     pub(crate) fn nonce(&self) -> &Nonce {
         &self.n
     }
 
+    /// number of encryption or decryption operations left before the
+    /// nonce reaches its limit and the cipher cannot be used anymore
+    #[inline(always)]
+    pub fn remaining(&self) -> u64 {
+        Nonce::max().0 - self.n.0
+    }
+
+    /// encrypt the `plaintext` into the `output`, returns the number of
+    /// bytes written in the output (the `plaintext` length plus the
+    /// `TAG_LEN` if the cipher has a key)
     pub fn encrypt_with_ad(
         &mut self,
         ad: impl AsRef<[u8]>,
         plaintext: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<usize, CipherStateError> {
         let tag_index = plaintext.as_ref().len();
         let len = if self.has_key() {
-            if tag_index + Self::TAG_LEN > output.len() {
-                return Err(CipherStateError::NotEnoughOutput);
-            }
-
-            let mut ctx = Context::new(&self.k, &self.n.to_bytes());
-            ctx.add_data(ad.as_ref());
-            let mut ctx = ctx.to_encryption();
+            let len = tag_index + TAG_LEN;
+            let n = self.n.increment().ok_or(CipherStateError::NonceExhausted)?;
+            let output = output
+                .prepare(len)
+                .ok_or(CipherStateError::NotEnoughOutput)?;
 
             let (output, tag) = output.split_at_mut(tag_index);
-            ctx.encrypt(plaintext.as_ref(), output);
-            tag[..Self::TAG_LEN].copy_from_slice(&ctx.finalize().0);
-            self.n = self.n.increment().ok_or(CipherStateError::Nonce)?;
-            tag_index + Self::TAG_LEN
+            output.copy_from_slice(plaintext.as_ref());
+            tag.copy_from_slice(&C::encrypt(&self.k, self.n.0, ad.as_ref(), output));
+            self.n = n;
+            len
         } else {
-            output[..tag_index].copy_from_slice(plaintext.as_ref());
+            output
+                .prepare(tag_index)
+                .ok_or(CipherStateError::NotEnoughOutput)?
+                .copy_from_slice(plaintext.as_ref());
             tag_index
         };
 
         Ok(len)
     }
 
+    /// decrypt the `cipher_text` into the `output`. If the cipher has
+    /// a key the output will be `TAG_LEN` bytes shorter
+    /// than the `cipher_text`.
     pub fn decrypt_with_ad(
         &mut self,
         ad: impl AsRef<[u8]>,
         cipher_text: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<(), CipherStateError> {
+        let cipher_text = cipher_text.as_ref();
         if self.has_key() {
-            let cipher_text = cipher_text.as_ref();
-            if cipher_text.len() < Self::TAG_LEN {
+            if cipher_text.len() < TAG_LEN {
                 return Err(CipherStateError::NotEnoughInput);
             }
 
-            let tag_index = cipher_text.len() - Self::TAG_LEN;
-            if tag_index > output.len() {
-                return Err(CipherStateError::NotEnoughOutput);
-            }
+            let n = self.n.increment().ok_or(CipherStateError::NonceExhausted)?;
+            self.decrypt_with_nonce(self.n, ad, cipher_text, output)?;
+            self.n = n;
+        } else {
+            output
+                .prepare(cipher_text.len())
+                .ok_or(CipherStateError::NotEnoughOutput)?
+                .copy_from_slice(cipher_text);
+        }
 
-            let (cipher_text, tag) = cipher_text.split_at(tag_index);
+        Ok(())
+    }
 
-            let mut ctx = ChaCha20Poly1305::new(&self.k, &self.n.to_bytes(), ad.as_ref());
+    /// decrypt the `cipher_text` with the explicit nonce `n`, the nonce
+    /// of the cipher state is not used nor updated
+    ///
+    /// the cipher is expected to have a key.
+    pub(crate) fn decrypt_with_nonce(
+        &self,
+        n: Nonce,
+        ad: impl AsRef<[u8]>,
+        cipher_text: &[u8],
+        output: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<(), CipherStateError> {
+        debug_assert!(self.has_key());
+        if cipher_text.len() < TAG_LEN {
+            return Err(CipherStateError::NotEnoughInput);
+        }
 
-            if !ctx.decrypt(cipher_text.as_ref(), output, &tag[..Self::TAG_LEN]) {
-                return Err(CipherStateError::InvalidTag);
-            }
+        let tag_index = cipher_text.len() - TAG_LEN;
+        let (cipher_text, tag) = cipher_text.split_at(tag_index);
+        let mut expected = [0; TAG_LEN];
+        expected.copy_from_slice(tag);
+
+        let decrypted = output
+            .prepare(tag_index)
+            .ok_or(CipherStateError::NotEnoughOutput)?;
+        decrypted.copy_from_slice(cipher_text);
+        if !C::decrypt(&self.k, n.0, ad.as_ref(), decrypted, &expected) {
+            output.discard(tag_index);
+            return Err(CipherStateError::InvalidTag);
+        }
 
-            self.n = self.n.increment().ok_or(CipherStateError::Nonce)?;
-        } else {
-            output.copy_from_slice(cipher_text.as_ref());
+        Ok(())
+    }
+
+    /// same as [`encrypt_with_ad`](Self::encrypt_with_ad) but encrypt
+    /// the `buffer` in place, the `TAG_LEN` bytes of the
+    /// tag are appended if the cipher has a key
+    pub fn encrypt_in_place(
+        &mut self,
+        ad: impl AsRef<[u8]>,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CipherStateError> {
+        if !self.has_key() {
+            return Ok(());
         }
 
+        let n = self.n.increment().ok_or(CipherStateError::NonceExhausted)?;
+
+        let tag = C::encrypt(&self.k, self.n.0, ad.as_ref(), buffer);
+        buffer.extend_from_slice(&tag);
+
+        self.n = n;
         Ok(())
     }
 
+    /// same as [`decrypt_with_ad`](Self::decrypt_with_ad) but decrypt
+    /// the `buffer` in place, the tag is removed if the cipher has a key
+    ///
+    /// if the decryption fails the `buffer` is left unchanged.
+    pub fn decrypt_in_place(
+        &mut self,
+        ad: impl AsRef<[u8]>,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CipherStateError> {
+        if !self.has_key() {
+            return Ok(());
+        }
+        if buffer.len() < TAG_LEN {
+            return Err(CipherStateError::NotEnoughInput);
+        }
+
+        let n = self.n.increment().ok_or(CipherStateError::NonceExhausted)?;
+        let tag_index = buffer.len() - TAG_LEN;
+        let mut tag = [0; TAG_LEN];
+        tag.copy_from_slice(&buffer[tag_index..]);
+
+        if C::decrypt(
+            &self.k,
+            self.n.0,
+            ad.as_ref(),
+            &mut buffer[..tag_index],
+            &tag,
+        ) {
+            buffer.truncate(tag_index);
+            self.n = n;
+            Ok(())
+        } else {
+            Err(CipherStateError::InvalidTag)
+        }
+    }
+
     /// one way function to derive a new cipher key from the previous key
     ///
     /// this prevents compromised keys to decrypt older messages. Periodically
     /// or continuous rekey is recommended
     pub fn rekey(&mut self) {
-        let mut new_key = [0; Self::KEY_LEN];
-        let mut _tag = [0; Self::TAG_LEN];
-
-        let mut ctx = ChaCha20Poly1305::new(&self.k, &Nonce::max().to_bytes(), &[]);
-        ctx.encrypt(&[0; Self::KEY_LEN], &mut new_key, &mut _tag);
+        let mut new_key = [0; KEY_LEN];
+        let _tag = C::encrypt(&self.k, Nonce::max().0, &[], &mut new_key);
 
         self.k = new_key;
+        new_key.scrub();
+    }
+}
+
+/// the cipher key is compared in constant time, the states are equal
+/// if they have the same key and nonce
+impl<C> ConstantTimeEq for CipherState<C> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.k[..].ct_eq(&other.k[..])
+            & self.n.0.ct_eq(&other.n.0)
+            & (self.has_key as u8).ct_eq(&(other.has_key as u8))
+    }
+}
+
+/// the cipher key is scrubbed (zeroed) before releasing the memory
+impl<C> Drop for CipherState<C> {
+    fn drop(&mut self) {
+        self.k.scrub()
     }
 }
 
-impl Default for CipherState {
+impl<C: Cipher> Default for CipherState<C> {
     fn default() -> Self {
         Self::new()
     }
@@ -181,7 +342,7 @@ mod tests {
 
     #[test]
     fn ref_empty() {
-        let mut ours = CipherState::new();
+        let mut ours = CipherState::<ChaChaPoly>::new();
         let mut theirs = CipherStateRef::new();
 
         assert_eq!(
@@ -197,7 +358,7 @@ mod tests {
 
         const PLAINTEXT: &[u8] = b"plain text";
         let mut our_output = [0u8; 1024];
-        ours.encrypt_with_ad(&[], PLAINTEXT, &mut our_output)
+        ours.encrypt_with_ad([], PLAINTEXT, &mut our_output)
             .unwrap();
 
         let mut their_output = [0; 10];
@@ -214,7 +375,7 @@ mod tests {
             "encrypted data should be the same"
         );
         assert_eq!(
-            &our_output[PLAINTEXT.len()..PLAINTEXT.len() + CipherState::TAG_LEN],
+            &our_output[PLAINTEXT.len()..PLAINTEXT.len() + TAG_LEN],
             &their_mac,
             "encrypted data MAC should be the same"
         );
@@ -227,9 +388,9 @@ mod tests {
 
     #[test]
     fn ref_something() {
-        const KEY: [u8; CipherState::KEY_LEN] = [0x1b; CipherState::KEY_LEN];
+        const KEY: [u8; KEY_LEN] = [0x1b; KEY_LEN];
 
-        let mut ours = CipherState::initialize_key(KEY);
+        let mut ours = CipherState::<ChaChaPoly>::initialize_key(KEY);
         let mut theirs = CipherStateRef::from_key(noiseexplorer_ik::types::Key::from_bytes(KEY));
 
         let mut decrypt_ours = ours.clone();
@@ -249,7 +410,7 @@ mod tests {
 
         const PLAINTEXT: &[u8] = b"plain text";
         let mut our_output = [0u8; 1024];
-        ours.encrypt_with_ad(&[], PLAINTEXT, &mut our_output)
+        ours.encrypt_with_ad([], PLAINTEXT, &mut our_output)
             .unwrap();
 
         let mut their_output = [0; 10];
@@ -265,7 +426,7 @@ mod tests {
             "encrypted data should be the same"
         );
         assert_eq!(
-            &our_output[PLAINTEXT.len()..PLAINTEXT.len() + CipherState::TAG_LEN],
+            &our_output[PLAINTEXT.len()..PLAINTEXT.len() + TAG_LEN],
             &their_mac,
             "encrypted data MAC should be the same"
         );
@@ -278,8 +439,8 @@ mod tests {
         let mut our_decrypted = [0u8; 10];
         decrypt_ours
             .decrypt_with_ad(
-                &[],
-                &our_output[..PLAINTEXT.len() + CipherState::TAG_LEN],
+                [],
+                &our_output[..PLAINTEXT.len() + TAG_LEN],
                 &mut our_decrypted,
             )
             .unwrap();
@@ -290,6 +451,7 @@ mod tests {
             theirs.n.get_value().unwrap(),
             "nonce should be incremented to 1"
         );
+        assert_eq!(ours.remaining(), u64::MAX - 1);
         assert_eq!(
             ours.k,
             theirs.k.as_bytes(),
@@ -297,4 +459,127 @@ mod tests {
         );
         assert_eq!(ours.n.0, 1, "nonce should be incremented to 1");
     }
+
+    #[test]
+    fn growable_output() {
+        const KEY: [u8; KEY_LEN] = [0x1b; KEY_LEN];
+        const PLAINTEXT: &[u8] = b"plain text";
+
+        let mut ours = CipherState::<ChaChaPoly>::initialize_key(KEY);
+        let mut decrypt_ours = ours.clone();
+
+        let mut encrypted = b"prefix".to_vec();
+        let len = ours.encrypt_with_ad([], PLAINTEXT, &mut encrypted).unwrap();
+        assert_eq!(len, ours.encrypted_len(PLAINTEXT.len()));
+        assert_eq!(&encrypted[..6], b"prefix", "output should be appended");
+        assert_eq!(encrypted.len(), 6 + len);
+
+        let mut decrypted = Vec::new();
+        let mut tempered = encrypted[6..].to_vec();
+        tempered[0] ^= 1;
+        assert!(matches!(
+            decrypt_ours
+                .clone()
+                .decrypt_with_ad([], &tempered, &mut decrypted),
+            Err(CipherStateError::InvalidTag)
+        ));
+        assert!(
+            decrypted.is_empty(),
+            "failed decryption should be discarded"
+        );
+
+        decrypt_ours
+            .decrypt_with_ad([], &encrypted[6..], &mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, PLAINTEXT);
+
+        let mut too_small = [0; 4];
+        assert!(matches!(
+            ours.encrypt_with_ad([], PLAINTEXT, &mut too_small),
+            Err(CipherStateError::NotEnoughOutput)
+        ));
+    }
+
+    #[test]
+    fn nonce_exhausted() {
+        const KEY: [u8; KEY_LEN] = [0x1b; KEY_LEN];
+
+        let mut ours = CipherState::<ChaChaPoly>::restore(KEY, Nonce::from_u64(u64::MAX - 1));
+        let mut theirs = ours.clone();
+        assert_eq!(ours.remaining(), 1);
+
+        let mut message = Vec::new();
+        ours.encrypt_with_ad([], b"last", &mut message).unwrap();
+        theirs
+            .decrypt_with_ad([], &message, &mut Vec::new())
+            .unwrap();
+        assert_eq!(ours.remaining(), 0);
+
+        let mut output = Vec::new();
+        assert!(matches!(
+            ours.encrypt_with_ad([], b"one more", &mut output),
+            Err(CipherStateError::NonceExhausted)
+        ));
+        assert!(output.is_empty());
+        assert!(matches!(
+            ours.encrypt_in_place([], &mut b"one more".to_vec()),
+            Err(CipherStateError::NonceExhausted)
+        ));
+        assert!(matches!(
+            theirs.decrypt_with_ad([], &message, &mut Vec::new()),
+            Err(CipherStateError::NonceExhausted)
+        ));
+    }
+
+    #[test]
+    fn in_place() {
+        const KEY: [u8; KEY_LEN] = [0x1b; KEY_LEN];
+        const PLAINTEXT: &[u8] = b"plain text";
+
+        let mut ours = CipherState::<ChaChaPoly>::initialize_key(KEY);
+        let mut expected = Vec::new();
+        ours.clone()
+            .encrypt_with_ad(b"ad", PLAINTEXT, &mut expected)
+            .unwrap();
+
+        let mut buffer = PLAINTEXT.to_vec();
+        ours.encrypt_in_place(b"ad", &mut buffer).unwrap();
+        assert_eq!(buffer, expected);
+        assert_eq!(ours.n.0, 1, "nonce should be incremented to 1");
+
+        let mut decrypt_ours = CipherState::<ChaChaPoly>::initialize_key(KEY);
+        let mut tempered = buffer.clone();
+        tempered[0] ^= 1;
+        let copy = tempered.clone();
+        assert!(matches!(
+            decrypt_ours.decrypt_in_place(b"ad", &mut tempered),
+            Err(CipherStateError::InvalidTag)
+        ));
+        assert_eq!(tempered, copy, "failed decryption should be reverted");
+        assert_eq!(decrypt_ours.n.0, 0, "nonce should not be incremented");
+
+        decrypt_ours.decrypt_in_place(b"ad", &mut buffer).unwrap();
+        assert_eq!(buffer, PLAINTEXT);
+
+        assert!(matches!(
+            decrypt_ours.decrypt_in_place([], &mut vec![0; 4]),
+            Err(CipherStateError::NotEnoughInput)
+        ));
+    }
+
+    #[test]
+    fn constant_time_eq() {
+        const KEY: [u8; KEY_LEN] = [0x1b; KEY_LEN];
+
+        let mut ours = CipherState::<ChaChaPoly>::initialize_key(KEY);
+        let theirs = CipherState::<ChaChaPoly>::initialize_key(KEY);
+        assert!(bool::from(ours.ct_eq(&theirs)));
+        assert!(!bool::from(ours.ct_eq(&CipherState::<ChaChaPoly>::new())));
+        assert!(!bool::from(ours.ct_eq(
+            &CipherState::<ChaChaPoly>::initialize_key([0x1c; KEY_LEN])
+        )));
+
+        ours.encrypt_in_place(b"ad", &mut vec![0; 4]).unwrap();
+        assert!(!bool::from(ours.ct_eq(&theirs)), "the nonces are different");
+    }
 }
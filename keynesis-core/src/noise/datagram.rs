@@ -0,0 +1,320 @@
+use crate::{
+    hash::Hash,
+    key::ed25519::PublicKey,
+    noise::{
+        cipher::{ChaChaPoly, Cipher},
+        cipher_state::Nonce,
+        CipherState, CipherStateError,
+    },
+    OutBuffer,
+};
+
+/// size of the explicit nonce prepended to every datagram
+pub const DATAGRAM_NONCE_LEN: usize = std::mem::size_of::<u64>();
+
+const WORD_BITS: u64 = u64::BITS as u64;
+const WORDS: usize = 32;
+const WINDOW: u64 = (WORDS as u64 - 1) * WORD_BITS;
+
+/// sending half of a datagram transport, see
+/// [`TransportState::into_datagram`]
+///
+/// every message carries its nonce (8 bytes, big endian) before the
+/// encrypted message so the messages can be lost, duplicated or
+/// reordered by the transport. The keys are not rotated after every
+/// message: the session needs to be renewed with a new handshake.
+///
+/// [`TransportState::into_datagram`]: crate::noise::TransportState::into_datagram
+pub struct DatagramSendHalf<H: Hash, C = ChaChaPoly> {
+    handshake_hash: H::HASH,
+    local: CipherState<C>,
+    remote_id: Option<PublicKey>,
+}
+
+/// receiving half of a datagram transport, see
+/// [`TransportState::into_datagram`]
+///
+/// the messages are accepted in any order as long as they are within
+/// the [`REPLAY_WINDOW`](Self::REPLAY_WINDOW) of the most recent
+/// message. A message is accepted only once.
+///
+/// [`TransportState::into_datagram`]: crate::noise::TransportState::into_datagram
+pub struct DatagramReceiveHalf<H: Hash, C = ChaChaPoly> {
+    handshake_hash: H::HASH,
+    remote: CipherState<C>,
+    remote_id: Option<PublicKey>,
+    window: ReplayWindow,
+}
+
+/// sliding window of the received nonces (see [RFC 6479])
+///
+/// [RFC 6479]: https://www.rfc-editor.org/rfc/rfc6479
+#[derive(Debug, Clone)]
+struct ReplayWindow {
+    /// the greatest nonce received so far
+    last: u64,
+    bitmap: [u64; WORDS],
+}
+
+impl<H: Hash, C: Cipher> DatagramSendHalf<H, C> {
+    pub(crate) fn new(
+        handshake_hash: H::HASH,
+        local: CipherState<C>,
+        remote_id: Option<PublicKey>,
+    ) -> Self {
+        Self {
+            handshake_hash,
+            local,
+            remote_id,
+        }
+    }
+
+    /// unique identifier of the noise session
+    pub fn noise_session(&self) -> &H::HASH {
+        &self.handshake_hash
+    }
+
+    /// get the remote's public identity
+    ///
+    /// `None` if the remote peer did not authenticate itself (the
+    /// initiator of an [`NK`](crate::noise::NK) handshake)
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.remote_id.as_ref()
+    }
+
+    /// get the number of messages that can still be sent
+    pub fn remaining_sends(&self) -> u64 {
+        self.local.remaining()
+    }
+
+    /// encrypt the message and append the datagram to the `output`
+    ///
+    /// the datagram is [`DATAGRAM_NONCE_LEN`] + 16 bytes larger than the input.
+    pub fn send(
+        &mut self,
+        input: impl AsRef<[u8]>,
+        output: &mut Vec<u8>,
+    ) -> Result<(), CipherStateError> {
+        if self.local.remaining() == 0 {
+            return Err(CipherStateError::NonceExhausted);
+        }
+
+        let start = output.len();
+        output.extend_from_slice(&self.local.nonce().into_u64().to_be_bytes());
+        if let Err(error) = self.local.encrypt_with_ad([], input, output) {
+            output.truncate(start);
+            return Err(error);
+        }
+
+        Ok(())
+    }
+}
+
+impl<H: Hash, C: Cipher> DatagramReceiveHalf<H, C> {
+    /// number of messages before the most recent one that can still be
+    /// received
+    pub const REPLAY_WINDOW: u64 = WINDOW;
+
+    pub(crate) fn new(
+        handshake_hash: H::HASH,
+        remote: CipherState<C>,
+        remote_id: Option<PublicKey>,
+    ) -> Self {
+        Self {
+            handshake_hash,
+            remote,
+            remote_id,
+            window: ReplayWindow::new(),
+        }
+    }
+
+    /// unique identifier of the noise session
+    pub fn noise_session(&self) -> &H::HASH {
+        &self.handshake_hash
+    }
+
+    /// get the remote's public identity
+    ///
+    /// `None` if the remote peer did not authenticate itself (the
+    /// initiator of an [`NK`](crate::noise::NK) handshake)
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.remote_id.as_ref()
+    }
+
+    /// decrypt the datagram into the `output`
+    ///
+    /// fails with [`CipherStateError::Replayed`] if the message has
+    /// already been received or is older than the
+    /// [`REPLAY_WINDOW`](Self::REPLAY_WINDOW). The window is only
+    /// updated with the authenticated messages.
+    pub fn receive(
+        &mut self,
+        input: impl AsRef<[u8]>,
+        output: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<(), CipherStateError> {
+        let input = input.as_ref();
+        if input.len() < DATAGRAM_NONCE_LEN {
+            return Err(CipherStateError::NotEnoughInput);
+        }
+        let (nonce, cipher_text) = input.split_at(DATAGRAM_NONCE_LEN);
+        let mut bytes = [0; DATAGRAM_NONCE_LEN];
+        bytes.copy_from_slice(nonce);
+        let n = u64::from_be_bytes(bytes);
+
+        if n == u64::MAX {
+            return Err(CipherStateError::NonceExhausted);
+        }
+        if !self.window.check(n) {
+            return Err(CipherStateError::Replayed);
+        }
+
+        self.remote
+            .decrypt_with_nonce(Nonce::from_u64(n), [], cipher_text, output)?;
+        self.window.update(n);
+
+        Ok(())
+    }
+}
+
+impl ReplayWindow {
+    fn new() -> Self {
+        Self {
+            last: 0,
+            bitmap: [0; WORDS],
+        }
+    }
+
+    fn position(n: u64) -> (usize, u64) {
+        (
+            ((n / WORD_BITS) % WORDS as u64) as usize,
+            1 << (n % WORD_BITS),
+        )
+    }
+
+    /// `true` if the nonce `n` has not been received and is within the
+    /// window
+    fn check(&self, n: u64) -> bool {
+        if n > self.last {
+            return true;
+        }
+        if self.last - n > WINDOW {
+            return false;
+        }
+
+        let (index, bit) = Self::position(n);
+        self.bitmap[index] & bit == 0
+    }
+
+    /// mark the nonce `n` as received, slides the window if `n` is the
+    /// most recent nonce
+    fn update(&mut self, n: u64) {
+        if n > self.last {
+            let current = self.last / WORD_BITS;
+            let diff = std::cmp::min(n / WORD_BITS - current, WORDS as u64);
+            for i in 1..=diff {
+                self.bitmap[((current + i) % WORDS as u64) as usize] = 0;
+            }
+            self.last = n;
+        }
+
+        let (index, bit) = Self::position(n);
+        self.bitmap[index] |= bit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::cipher::KEY_LEN;
+    use crate::noise::TransportState;
+    use crate::seed::Seed;
+    use cryptoxide::blake2b::Blake2b;
+
+    type Window = DatagramReceiveHalf<Blake2b>;
+
+    fn datagram_pair() -> (DatagramSendHalf<Blake2b>, DatagramReceiveHalf<Blake2b>) {
+        let (send, _) = TransportState::<Blake2b>::new(
+            Blake2b::zero_hash(),
+            CipherState::initialize_key([1; KEY_LEN]),
+            CipherState::initialize_key([2; KEY_LEN]),
+            None,
+            Seed::from([3; Seed::SIZE]),
+            Seed::from([4; Seed::SIZE]),
+        )
+        .into_datagram();
+        let (_, receive) = TransportState::<Blake2b>::new(
+            Blake2b::zero_hash(),
+            CipherState::initialize_key([2; KEY_LEN]),
+            CipherState::initialize_key([1; KEY_LEN]),
+            None,
+            Seed::from([3; Seed::SIZE]),
+            Seed::from([4; Seed::SIZE]),
+        )
+        .into_datagram();
+        (send, receive)
+    }
+
+    #[quickcheck]
+    fn out_of_order(messages: Vec<Vec<u8>>) -> bool {
+        let (mut send, mut receive) = datagram_pair();
+
+        let datagrams: Vec<_> = messages
+            .iter()
+            .map(|message| {
+                let mut datagram = Vec::new();
+                send.send(message, &mut datagram).unwrap();
+                assert_eq!(datagram.len(), DATAGRAM_NONCE_LEN + message.len() + 16);
+                datagram
+            })
+            .collect();
+
+        datagrams.iter().zip(messages.iter()).rev().all(|(d, m)| {
+            let mut output = Vec::new();
+            receive.receive(d, &mut output).unwrap();
+            output == *m
+        })
+    }
+
+    #[test]
+    fn replayed() {
+        let (mut send, mut receive) = datagram_pair();
+
+        let mut datagram = Vec::new();
+        send.send(b"message", &mut datagram).unwrap();
+        receive.receive(&datagram, &mut Vec::new()).unwrap();
+        assert!(matches!(
+            receive.receive(&datagram, &mut Vec::new()),
+            Err(CipherStateError::Replayed)
+        ));
+
+        // a tampered message does not update the window
+        let mut datagram = Vec::new();
+        send.send(b"message", &mut datagram).unwrap();
+        let mut tampered = datagram.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            receive.receive(&tampered, &mut Vec::new()),
+            Err(CipherStateError::InvalidTag)
+        ));
+        receive.receive(&datagram, &mut Vec::new()).unwrap();
+    }
+
+    #[test]
+    fn window() {
+        let mut window = ReplayWindow::new();
+        assert!(window.check(0));
+        window.update(0);
+        assert!(!window.check(0));
+
+        window.update(Window::REPLAY_WINDOW + 10);
+        assert!(!window.check(9));
+        assert!(window.check(10));
+        assert!(window.check(Window::REPLAY_WINDOW + 9));
+        assert!(!window.check(Window::REPLAY_WINDOW + 10));
+
+        // a large jump clears the whole window
+        window.update(10 * Window::REPLAY_WINDOW);
+        assert!(window.check(9 * Window::REPLAY_WINDOW + 1));
+        assert!(!window.check(Window::REPLAY_WINDOW + 10));
+    }
+}
@@ -1,25 +1,71 @@
 use crate::{
-    buffer::BufRead,
+    buffer::{BufRead, OutBuffer},
     hash::Hash,
-    key::{ed25519_extended::PublicKey, Dh},
-    noise::{CipherState, CipherStateError, SymmetricState},
+    key::{curve25519, ed25519_extended::PublicKey, elligator, Dh},
+    noise::{
+        cipher::{ChaChaPoly, Cipher, TAG_LEN},
+        CipherStateError, ProtocolName, SymmetricState,
+    },
+    seed::Seed,
 };
 use rand_core::{CryptoRng, RngCore};
 use std::io::Write;
 use thiserror::Error;
 
-pub(crate) struct HandshakeState<RNG, DH, H>
+pub(crate) struct HandshakeState<RNG, DH, H, C = ChaChaPoly>
 where
     H: Hash,
+    C: Cipher,
 {
-    symmetric_state: SymmetricState<H>,
+    symmetric_state: SymmetricState<H, C>,
+    protocol_name: ProtocolName,
 
     rng: RNG,
     is_psk: bool,
+    /// the pre-shared key not yet mixed, see [`HandshakeState::mix_psk`]
+    psk: Option<Seed>,
     e: Option<DH>,
+    elligator: Option<Elligator<RNG, DH>>,
+}
+
+/// encode the ephemeral keys with their Elligator representatives
+/// instead of the public keys (see [`elligator`])
+struct Elligator<RNG, DH> {
+    generate: fn(&mut RNG) -> (DH, [u8; elligator::REPRESENTATIVE_SIZE]),
+    decode: fn(&[u8; elligator::REPRESENTATIVE_SIZE]) -> PublicKey,
+}
+
+impl<RNG, DH, H, C> Clone for HandshakeState<RNG, DH, H, C>
+where
+    RNG: Clone,
+    DH: Clone,
+    H: Hash + Clone,
+    C: Cipher + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            symmetric_state: self.symmetric_state.clone(),
+            protocol_name: self.protocol_name.clone(),
+            rng: self.rng.clone(),
+            is_psk: self.is_psk,
+            psk: self.psk.clone(),
+            e: self.e.clone(),
+            elligator: self.elligator.clone(),
+        }
+    }
+}
+
+impl<RNG, DH> Clone for Elligator<RNG, DH> {
+    fn clone(&self) -> Self {
+        Self {
+            generate: self.generate,
+            decode: self.decode,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum HandshakeStateError {
     #[error("Not enough bytes, expecting to read a public key")]
     ExpectingPublicKey,
@@ -35,15 +81,30 @@ pub enum HandshakeStateError {
 
     #[error("Cannot write message")]
     Write(#[from] std::io::Error),
+
+    #[error("The remote identity {0} was rejected")]
+    RejectedIdentity(PublicKey),
 }
 
-impl<RNG, DH, H> HandshakeState<RNG, DH, H>
+impl<RNG, DH, H, C> HandshakeState<RNG, DH, H, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub(crate) fn write_e(&mut self, mut output: impl Write) -> Result<(), HandshakeStateError> {
+        if let Some(elligator) = &self.elligator {
+            let (e, representative) = (elligator.generate)(&mut self.rng);
+            output.write_all(&representative)?;
+            self.symmetric_state.mix_hash(representative);
+            if self.is_psk {
+                self.symmetric_state.mix_key(representative);
+            }
+            self.e = Some(e);
+            return Ok(());
+        }
+
         if self.e.is_none() {
             self.e = Some(DH::generate(&mut self.rng));
         }
@@ -61,20 +122,68 @@ where
     }
 }
 
-impl<RNG, DH, H> HandshakeState<RNG, DH, H>
+impl<RNG, H, C> HandshakeState<RNG, curve25519::SecretKey, H, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// write and read the ephemeral keys as Elligator representatives,
+    /// the hash is mixed with the representatives (the bytes on the wire)
+    pub(crate) fn elligator(&mut self) {
+        self.elligator = Some(Elligator {
+            generate: |rng| elligator::generate(rng),
+            decode: elligator::decode,
+        });
+    }
+}
+
+impl<RNG, DH, H, C> HandshakeState<RNG, DH, H, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
-    pub(crate) fn new(rng: RNG, prologue: &[u8], protocol_name: &str) -> Self {
-        let mut symmetric_state = SymmetricState::initialize_symmetric(protocol_name);
+    pub(crate) fn new(rng: RNG, prologue: &[u8], protocol_name: ProtocolName) -> Self {
+        let mut symmetric_state = SymmetricState::initialize_symmetric(protocol_name.to_string());
         symmetric_state.mix_hash(prologue);
 
         Self {
             symmetric_state,
+            protocol_name,
             rng,
             is_psk: false,
+            psk: None,
             e: None,
+            elligator: None,
+        }
+    }
+
+    pub(crate) fn protocol_name(&self) -> &ProtocolName {
+        &self.protocol_name
+    }
+
+    /// restart the handshake as `protocol_name`, keeping the ephemeral
+    /// key already sent: for the patterns with the `fallback` modifier
+    ///
+    /// the ephemeral key is a pre-message of the new handshake (see
+    /// [`mix_e`](Self::mix_e)), the Elligator encoding is not kept.
+    pub(crate) fn fallback(self, prologue: &[u8], protocol_name: ProtocolName) -> Self {
+        let e = self.e;
+        let mut state = Self::new(self.rng, prologue, protocol_name);
+        state.e = e;
+        state
+    }
+
+    /// mix the public key of our ephemeral key, sent as a pre-message
+    pub(crate) fn mix_e(&mut self) {
+        if let Some(e) = &self.e {
+            let public = e.public();
+            self.symmetric_state.mix_hash(public.as_ref());
+        } else {
+            // this error can only happen if we fall back from a
+            // handshake before doing an `e`
+            panic!("we are trying to mix our ephemeral key but it has not been generated")
         }
     }
 
@@ -83,17 +192,40 @@ where
         self.symmetric_state.mix_key_and_hash(psk);
     }
 
+    /// use the pre-shared key in the handshake, it is mixed later at the
+    /// position of the `psk` token of the pattern (see [`mix_psk`])
+    ///
+    /// the ephemeral keys are mixed in the key from the first message on.
+    ///
+    /// [`mix_psk`]: Self::mix_psk
+    pub(crate) fn set_psk(&mut self, psk: &Option<Seed>) {
+        if let Some(psk) = psk {
+            self.is_psk = true;
+            self.psk = Some(psk.clone());
+        }
+    }
+
+    /// the `psk` token: mix the pre-shared key given to [`set_psk`], if any
+    ///
+    /// [`set_psk`]: Self::set_psk
+    pub(crate) fn mix_psk(&mut self) {
+        if let Some(psk) = self.psk.take() {
+            self.symmetric_state
+                .mix_key_and_hash(psk.compact().as_ref());
+        }
+    }
+
     pub(crate) fn mix_hash(&mut self, pk: &PublicKey) {
         self.symmetric_state.mix_hash(pk.as_ref());
     }
 
-    pub(crate) fn symmetric_state(&mut self) -> &mut SymmetricState<H> {
+    pub(crate) fn symmetric_state(&mut self) -> &mut SymmetricState<H, C> {
         &mut self.symmetric_state
     }
 
     fn encrypted_len(&self, len: usize) -> usize {
         if self.symmetric_state.has_key() {
-            len + CipherState::TAG_LEN
+            len + TAG_LEN
         } else {
             len
         }
@@ -108,11 +240,15 @@ where
         } else {
             let mut pk = [0; PublicKey::SIZE];
             input.read(&mut pk);
-            self.symmetric_state.mix_hash(&pk);
+            self.symmetric_state.mix_hash(pk);
             if self.is_psk {
-                self.symmetric_state.mix_key(&pk);
+                self.symmetric_state.mix_key(pk);
+            }
+            if let Some(elligator) = &self.elligator {
+                Ok((elligator.decode)(&pk))
+            } else {
+                Ok(PublicKey::from(pk))
             }
-            Ok(PublicKey::from(pk))
         }
     }
 
@@ -122,7 +258,7 @@ where
         mut output: impl Write,
     ) -> Result<(), HandshakeStateError> {
         let len = self.encrypted_len(PublicKey::SIZE);
-        let mut pk = [0; PublicKey::SIZE + CipherState::TAG_LEN];
+        let mut pk = [0; PublicKey::SIZE + TAG_LEN];
         self.symmetric_state
             .encrypt_and_hash(s.as_ref(), &mut pk[..len])?;
         output.write_all(&pk[..len])?;
@@ -139,7 +275,7 @@ where
         } else {
             let mut pk = [0; PublicKey::SIZE];
             self.symmetric_state
-                .decrypt_and_hash(&input.slice(len), &mut pk)?;
+                .decrypt_and_hash(input.slice(len), &mut pk)?;
             input.advance(len);
             Ok(PublicKey::from(pk))
         }
@@ -152,8 +288,8 @@ where
     ) -> Result<(), HandshakeStateError> {
         let mut buffer = [0; u16::MAX as usize];
         let len = self.encrypted_len(plaintext.len());
-        let mut tag = &mut buffer[..len];
-        let len = self.symmetric_state.encrypt_and_hash(plaintext, &mut tag)?;
+        let tag = &mut buffer[..len];
+        let len = self.symmetric_state.encrypt_and_hash(plaintext, tag)?;
         output.write_all(&buffer[..len])?;
         Ok(())
     }
@@ -161,7 +297,7 @@ where
     pub(crate) fn decrypt_and_hash(
         &mut self,
         input: &mut BufRead,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<(), HandshakeStateError> {
         let len = input.remaining();
         self.symmetric_state
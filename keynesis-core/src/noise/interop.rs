@@ -0,0 +1,290 @@
+/*!
+# Interoperability with other Noise stacks
+
+The handshakes of this crate are typestates: each step consumes the
+previous one. The other Noise libraries ([snow], the reference
+implementations...) drive the handshakes with a single state and
+`write_message`/`read_message` calls. The [`Handshake`] here does the
+same on top of the [`IK`] and [`XX`] patterns so the raw handshake
+messages can be exchanged with another stack, in both directions, by a
+test harness.
+
+Only the `25519` DH (the [`curve25519::SecretKey`]) is wire compatible
+with the other stacks: the Ed25519 keys use a different encoding of the
+public keys.
+
+**The transport messages are rekeyed after each message** (see
+[`TransportState`]), the other stack needs to rekey its sending cipher
+after writing a message and its receiving cipher after reading one (with
+snow: `rekey_outgoing` and `rekey_incoming`).
+
+[snow]: https://docs.rs/snow
+[`curve25519::SecretKey`]: crate::key::curve25519::SecretKey
+*/
+
+use crate::{
+    buffer::OutBuffer,
+    hash::Hash,
+    key::{ed25519::PublicKey, Dh},
+    noise::{ik, xx, ChaChaPoly, Cipher, HandshakeStateError, TransportState, IK, XX},
+};
+use rand_core::{CryptoRng, RngCore};
+use std::io::Write;
+use thiserror::Error;
+
+/// the patterns supported by the [`Handshake`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    IK,
+    XX,
+}
+
+/// a handshake driven one message at a time
+pub struct Handshake<DH, H, RNG, C = ChaChaPoly>
+where
+    H: Hash,
+    C: Cipher,
+{
+    s: DH,
+    state: State<DH, H, RNG, C>,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum InteropError {
+    #[error("Not our turn to {0} a message")]
+    UnexpectedMessage(&'static str),
+
+    #[error("The XX handshake messages do not carry payloads")]
+    PayloadNotSupported,
+
+    #[error("The handshake failed previously")]
+    Failed,
+
+    #[error("Handshake error")]
+    Handshake(#[from] HandshakeStateError),
+}
+
+#[allow(clippy::upper_case_acronyms)]
+enum State<DH, H, RNG, C>
+where
+    H: Hash,
+    C: Cipher,
+{
+    IkInitiator(IK<DH, H, RNG, ik::A, C>, PublicKey),
+    IkWaitB(IK<DH, H, RNG, ik::WaitB, C>),
+    IkResponder(IK<DH, H, RNG, ik::A, C>),
+    IkSendB(IK<DH, H, RNG, ik::SendB, C>),
+    XxInitiator(XX<DH, H, RNG, xx::A, C>),
+    XxWaitB(XX<DH, H, RNG, xx::WaitB, C>),
+    XxSendC(XX<DH, H, RNG, xx::SendC, C>),
+    XxResponder(XX<DH, H, RNG, xx::A, C>),
+    XxSendB(XX<DH, H, RNG, xx::SendB, C>),
+    XxWaitC(XX<DH, H, RNG, xx::WaitC, C>),
+    Transport(TransportState<H, C>),
+    Failed,
+}
+
+impl<DH, H, RNG, C> Handshake<DH, H, RNG, C>
+where
+    DH: Dh,
+    H: Hash,
+    RNG: RngCore + CryptoRng,
+    C: Cipher,
+{
+    /// start the handshake as the initiator, the remote static key `rs`
+    /// is required for the [`IK`](Pattern::IK) pattern
+    ///
+    /// # panics
+    ///
+    /// if `rs` is missing with the [`IK`](Pattern::IK) pattern
+    pub fn initiator(
+        pattern: Pattern,
+        rng: RNG,
+        prologue: &[u8],
+        s: DH,
+        rs: Option<PublicKey>,
+    ) -> Self {
+        let state = match pattern {
+            Pattern::IK => State::IkInitiator(
+                IK::with_cipher(rng, &None, prologue),
+                rs.expect("the IK pattern needs the responder's static key"),
+            ),
+            Pattern::XX => State::XxInitiator(XX::with_cipher(rng, &None, prologue)),
+        };
+
+        Self { s, state }
+    }
+
+    /// start the handshake as the responder
+    pub fn responder(pattern: Pattern, rng: RNG, prologue: &[u8], s: DH) -> Self {
+        let state = match pattern {
+            Pattern::IK => State::IkResponder(IK::with_cipher(rng, &None, prologue)),
+            Pattern::XX => State::XxResponder(XX::with_cipher(rng, &None, prologue)),
+        };
+
+        Self { s, state }
+    }
+
+    /// `true` once all the handshake messages have been written and read
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Transport(_))
+    }
+
+    /// `true` if the next step is to write a message
+    pub fn is_my_turn(&self) -> bool {
+        matches!(
+            self.state,
+            State::IkInitiator(..)
+                | State::IkSendB(_)
+                | State::XxInitiator(_)
+                | State::XxSendB(_)
+                | State::XxSendC(_)
+        )
+    }
+
+    /// the transport state, once the handshake is finished
+    pub fn into_transport(self) -> Option<TransportState<H, C>> {
+        match self.state {
+            State::Transport(transport) => Some(transport),
+            _ => None,
+        }
+    }
+
+    /// write the next handshake message in `output`
+    pub fn write_message(
+        &mut self,
+        payload: &[u8],
+        output: impl Write,
+    ) -> Result<(), InteropError> {
+        let state = std::mem::replace(&mut self.state, State::Failed);
+        if !payload.is_empty()
+            && matches!(
+                state,
+                State::XxInitiator(_) | State::XxSendB(_) | State::XxSendC(_)
+            )
+        {
+            self.state = state;
+            return Err(InteropError::PayloadNotSupported);
+        }
+
+        self.state = match state {
+            State::IkInitiator(ik, rs) => {
+                State::IkWaitB(ik.initiate_with_payload(&self.s, rs, payload, output)?)
+            }
+            State::IkSendB(ik) => State::Transport(ik.reply_with_payload(payload, output)?),
+            State::XxInitiator(xx) => State::XxWaitB(xx.initiate(output)?),
+            State::XxSendB(xx) => State::XxWaitC(xx.reply(&self.s, output)?),
+            State::XxSendC(xx) => State::Transport(xx.reply(&self.s, output)?),
+            State::Failed => return Err(InteropError::Failed),
+            state => {
+                self.state = state;
+                return Err(InteropError::UnexpectedMessage("write"));
+            }
+        };
+
+        Ok(())
+    }
+
+    /// read the next handshake message, its payload is written in
+    /// `payload`
+    pub fn read_message(
+        &mut self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<(), InteropError> {
+        let state = std::mem::replace(&mut self.state, State::Failed);
+
+        self.state = match state {
+            State::IkResponder(ik) => {
+                State::IkSendB(ik.receive_with_payload(&self.s, input, payload)?)
+            }
+            State::IkWaitB(ik) => {
+                State::Transport(ik.receive_with_payload(&self.s, input, payload)?)
+            }
+            State::XxResponder(xx) => State::XxSendB(xx.receive(input)?),
+            State::XxWaitB(xx) => State::XxSendC(xx.receive(input)?),
+            State::XxWaitC(xx) => State::Transport(xx.receive(input)?),
+            State::Failed => return Err(InteropError::Failed),
+            state => {
+                self.state = state;
+                return Err(InteropError::UnexpectedMessage("read"));
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::curve25519::SecretKey;
+    use cryptoxide::blake2s::Blake2s;
+    use rand::thread_rng;
+
+    fn run(pattern: Pattern) -> (TransportState<Blake2s>, TransportState<Blake2s>) {
+        let initiator_s = SecretKey::new(thread_rng());
+        let responder_s = SecretKey::new(thread_rng());
+        let rs = responder_s.public_key();
+
+        let mut initiator = Handshake::<_, Blake2s, _>::initiator(
+            pattern,
+            thread_rng(),
+            b"prologue",
+            initiator_s,
+            Some(rs),
+        );
+        let mut responder =
+            Handshake::<_, Blake2s, _>::responder(pattern, thread_rng(), b"prologue", responder_s);
+
+        let (mut writer, mut reader) = (&mut initiator, &mut responder);
+        while !writer.is_finished() || !reader.is_finished() {
+            assert!(writer.is_my_turn());
+            assert!(matches!(
+                reader.write_message(&[], &mut Vec::new()),
+                Err(InteropError::UnexpectedMessage(_))
+            ));
+
+            let mut message = Vec::new();
+            writer.write_message(&[], &mut message).unwrap();
+            reader.read_message(&message, &mut []).unwrap();
+
+            std::mem::swap(&mut writer, &mut reader);
+        }
+
+        (
+            initiator.into_transport().unwrap(),
+            responder.into_transport().unwrap(),
+        )
+    }
+
+    #[test]
+    fn ik() {
+        let (initiator, responder) = run(Pattern::IK);
+        assert_eq!(initiator.noise_session(), responder.noise_session());
+    }
+
+    #[test]
+    fn xx() {
+        let (initiator, responder) = run(Pattern::XX);
+        assert_eq!(initiator.noise_session(), responder.noise_session());
+    }
+
+    #[test]
+    fn xx_payloads() {
+        let mut initiator = Handshake::<_, Blake2s, _>::initiator(
+            Pattern::XX,
+            thread_rng(),
+            &[],
+            SecretKey::new(thread_rng()),
+            None,
+        );
+
+        assert!(matches!(
+            initiator.write_message(b"payload", &mut Vec::new()),
+            Err(InteropError::PayloadNotSupported)
+        ));
+        assert!(initiator.write_message(&[], &mut Vec::new()).is_ok());
+    }
+}
@@ -3,10 +3,14 @@
 
 This module provides some of the noise's patterns and configuration.
 Currently we only support `Ed25519` for the key exchange, ChaChaPoly
-for the cipher and BLAKE2b for the hash function.
+(the default) or AES-256-GCM for the cipher (see [`Cipher`]) and the
+functions of [`hash`](crate::hash) for the hash function. The choice is
+recorded in the [`ProtocolName`] of the handshakes.
 
-We also limit to a few patterns so far (X, IX, XX, IK). There are pros and
-cons to use one over the other.
+We also limit to a few patterns so far (N, X, IX, XX, XK, IK, KK, NK, NN and
+XXfallback for the Noise Pipes). There are pros and
+cons to use one over the other. A session can be resumed later without a
+full handshake with [`Resume`] and the [`ResumptionSecret`] of the session.
 
 See [Noise Specification] for more details about the noise protocol. And have
 a look at [Noise Explorer] for the details regarding the different patterns
@@ -15,18 +19,33 @@ available here.
 [Noise Specification]: http://noiseprotocol.org/noise.html
 [Noise Explorer]: https://noiseexplorer.com/patterns/
 */
+mod cipher;
 mod cipher_state;
+mod datagram;
 mod handshake_state;
+#[cfg(feature = "interop")]
+pub mod interop;
 mod pattern;
+mod protocol_name;
+mod resumption;
 mod symmetric_state;
+mod transcript;
 mod transport_state;
 
-pub(crate) use self::{
-    cipher_state::CipherState, handshake_state::HandshakeState, symmetric_state::SymmetricState,
-};
 pub use self::{
+    cipher::{AesGcm, ChaChaPoly, Cipher},
     cipher_state::CipherStateError,
+    datagram::{DatagramReceiveHalf, DatagramSendHalf, DATAGRAM_NONCE_LEN},
     handshake_state::HandshakeStateError,
     pattern::*,
-    transport_state::{TransportReceiveHalf, TransportSendHalf, TransportState},
+    protocol_name::{ProtocolName, ProtocolNameError},
+    resumption::{ResumptionSecret, ResumptionSecretError},
+    transcript::{SignedTranscript, Transcript, TranscriptError, TranscriptSummary},
+    transport_state::{
+        RekeyPolicy, RekeyPolicyError, SnapshotError, TransportReceiveHalf, TransportSendHalf,
+        TransportState, MAX_MESSAGE_LEN,
+    },
+};
+pub(crate) use self::{
+    cipher_state::CipherState, handshake_state::HandshakeState, symmetric_state::SymmetricState,
 };
@@ -1,8 +1,12 @@
 use crate::{
-    buffer::BufRead,
+    buffer::{BufRead, OutBuffer},
     hash::Hash,
-    key::{ed25519::PublicKey, Dh},
-    noise::{HandshakeState, HandshakeStateError, TransportState},
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{
+        xxfallback, ChaChaPoly, Cipher, CipherStateError, HandshakeState, HandshakeStateError,
+        ProtocolName, TransportState, XXfallback,
+    },
+    seed::Seed,
 };
 use rand_core::{CryptoRng, RngCore};
 use std::io::Write;
@@ -11,55 +15,153 @@ use std::io::Write;
 ///
 /// [**Noise IK**]: https://noiseexplorer.com/patterns/IK/
 #[allow(clippy::upper_case_acronyms)]
-pub struct IK<DH, H, RNG, S>
+pub struct IK<DH, H, RNG, S, C = ChaChaPoly>
 where
     H: Hash,
+    C: Cipher,
 {
-    inner: HandshakeState<RNG, DH, H>,
+    inner: HandshakeState<RNG, DH, H, C>,
     state: S,
 }
 
+#[derive(Clone)]
 pub struct A;
+#[derive(Clone)]
 pub struct WaitB {
     rs: PublicKey,
 }
+#[derive(Clone)]
 pub struct SendB {
     re: PublicKey,
     rs: PublicKey,
 }
 
-impl<DH, H, RNG> IK<DH, H, RNG, A>
+/// the initiator waiting for the reply can be cloned to try more than
+/// one reply, when they may be forged (on top of UDP for example)
+impl<DH, H, RNG, S, C> Clone for IK<DH, H, RNG, S, C>
 where
-    DH: Dh,
-    H: Hash,
+    DH: Clone,
+    H: Hash + Clone,
+    RNG: Clone,
+    S: Clone,
+    C: Cipher + Clone,
 {
-    pub fn new(rng: RNG, prologue: &[u8]) -> Self {
-        let protocol_name = format!(
-            "Noise_{pattern}_{dh}_{cipher}_{hash}",
-            pattern = "IK",
-            dh = DH::name(),
-            cipher = "ChaChaPoly",
-            hash = H::name(),
-        );
+    fn clone(&self) -> Self {
         Self {
-            inner: HandshakeState::new(rng, prologue, &protocol_name),
-            state: A,
+            inner: self.inner.clone(),
+            state: self.state.clone(),
         }
     }
 }
 
+/// the reply of the responder of a [Noise Pipes] handshake, see
+/// [`IK::receive_with_fallback`]
+///
+/// [Noise Pipes]: http://noiseprotocol.org/noise.html#noise-pipes
+pub enum Pipe<DH, H, RNG, C = ChaChaPoly>
+where
+    H: Hash,
+    C: Cipher,
+{
+    /// the responder replied to the `IK` handshake
+    Established(TransportState<H, C>),
+    /// the responder could not decrypt the initial message and fell
+    /// back to the [`XXfallback`] handshake: check its new static key
+    /// and send ours with [`XXfallback::reply`]
+    Fallback(XXfallback<DH, H, RNG, xxfallback::SendC, C>),
+}
+
+impl<DH, H, RNG, S, C> IK<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
 impl<DH, H, RNG> IK<DH, H, RNG, A>
+where
+    DH: Dh,
+    H: Hash,
+{
+    /// start the handshake, with the optional pre-shared key `psk` the
+    /// handshake is [**Noise IKpsk2**]
+    ///
+    /// both peers need to use the same pre-shared key.
+    ///
+    /// [**Noise IKpsk2**]: https://noiseexplorer.com/patterns/IKpsk2/
+    pub fn new(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, psk, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> IK<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
+        let pattern = if psk.is_some() { "IKpsk2" } else { "IK" };
+
+        let protocol_name = ProtocolName::new::<DH, C, H>(pattern);
+        let mut inner = HandshakeState::new(rng, prologue, protocol_name);
+        inner.set_psk(psk);
+
+        Self { inner, state: A }
+    }
+}
+
+impl<H, RNG, C> IK<curve25519::SecretKey, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> IK<DH, H, RNG, A, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub fn initiate<K>(
         self,
         s: &K,
         rs: PublicKey,
+        output: impl Write,
+    ) -> Result<IK<DH, H, RNG, WaitB, C>, HandshakeStateError>
+    where
+        K: Dh,
+    {
+        self.initiate_with_payload(s, rs, b"", output)
+    }
+
+    /// same as [`initiate`](Self::initiate) but send the given payload
+    /// too. The payload is encrypted and authenticated.
+    pub fn initiate_with_payload<K>(
+        self,
+        s: &K,
+        rs: PublicKey,
+        payload: impl AsRef<[u8]>,
         mut output: impl Write,
-    ) -> Result<IK<DH, H, RNG, WaitB>, HandshakeStateError>
+    ) -> Result<IK<DH, H, RNG, WaitB, C>, HandshakeStateError>
     where
         K: Dh,
     {
@@ -75,7 +177,7 @@ where
         inner.write_s(&s.public(), &mut output)?;
         inner.dh_sx(s, &rs);
 
-        inner.encrypt_and_hash(&[], &mut output)?;
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
 
         Ok(IK {
             inner,
@@ -83,16 +185,28 @@ where
         })
     }
 }
-impl<DH, H, RNG> IK<DH, H, RNG, A>
+impl<DH, H, RNG, C> IK<DH, H, RNG, A, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub fn receive(
         self,
         s: &DH,
         input: &[u8],
-    ) -> Result<IK<DH, H, RNG, SendB>, HandshakeStateError> {
+    ) -> Result<IK<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        self.receive_with_payload(s, input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// initiator in `payload`
+    pub fn receive_with_payload(
+        self,
+        s: &DH,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<IK<DH, H, RNG, SendB, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: A,
@@ -107,25 +221,61 @@ where
         let rs = inner.read_s(&mut input)?;
         inner.dh_sx(s, &rs);
 
-        inner.decrypt_and_hash(&mut input, &mut [])?;
+        inner.decrypt_and_hash(&mut input, payload)?;
 
         Ok(IK {
             inner,
             state: SendB { re, rs },
         })
     }
+
+    /// same as [`receive_with_payload`](Self::receive_with_payload) but
+    /// the `policy` decides whether the initiator's identity is accepted
+    /// before replying
+    ///
+    /// the `policy` is called once the message is authenticated, the
+    /// handshake fails with [`HandshakeStateError::RejectedIdentity`] if
+    /// it returns `false`.
+    pub fn receive_with_policy<F>(
+        self,
+        s: &DH,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+        policy: F,
+    ) -> Result<IK<DH, H, RNG, SendB, C>, HandshakeStateError>
+    where
+        F: Fn(&PublicKey) -> bool,
+    {
+        let ik = self.receive_with_payload(s, input, payload)?;
+        if policy(&ik.state.rs) {
+            Ok(ik)
+        } else {
+            Err(HandshakeStateError::RejectedIdentity(ik.state.rs))
+        }
+    }
 }
-impl<DH, H, RNG> IK<DH, H, RNG, SendB>
+impl<DH, H, RNG, C> IK<DH, H, RNG, SendB, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub fn remote_public_identity(&self) -> &PublicKey {
         &self.state.rs
     }
 
-    pub fn reply(self, mut output: impl Write) -> Result<TransportState<H>, HandshakeStateError> {
+    pub fn reply(self, output: impl Write) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.reply_with_payload(b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted and authenticated.
+    pub fn reply_with_payload(
+        self,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: SendB { re, rs },
@@ -134,8 +284,9 @@ where
         inner.write_e(&mut output)?;
         inner.dh_ex(&re);
         inner.dh_ex(&rs);
+        inner.mix_psk();
 
-        inner.encrypt_and_hash(&[], &mut output)?;
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
 
         let (remote, local) = inner.symmetric_state().split();
 
@@ -143,21 +294,104 @@ where
             inner.symmetric_state().get_handshake_hash().clone(),
             local,
             remote,
-            rs,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
         ))
     }
 }
-impl<DH, H, RNG> IK<DH, H, RNG, WaitB>
+impl<DH, H, RNG, C> IK<DH, H, RNG, WaitB, C>
 where
     DH: Dh,
 
     H: Hash,
+    C: Cipher,
 {
     pub fn remote_public_identity(&self) -> &PublicKey {
         &self.state.rs
     }
 
-    pub fn receive(self, s: &DH, input: &[u8]) -> Result<TransportState<H>, HandshakeStateError> {
+    pub fn receive(
+        self,
+        s: &DH,
+        input: &[u8],
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.receive_with_payload(s, input, &mut [])
+    }
+
+    /// give up on the `IK` handshake and switch to the [`XXfallback`]
+    /// handshake with the same ephemeral key, to use when the responder
+    /// fell back (see [`receive_with_fallback`](Self::receive_with_fallback))
+    pub fn fallback(self, prologue: &[u8]) -> XXfallback<DH, H, RNG, xxfallback::WaitB, C> {
+        XXfallback::fall_back(self.inner, prologue)
+    }
+
+    /// same as [`receive_with_payload`](Self::receive_with_payload) but
+    /// the responder may have fallen back to the [`XXfallback`]
+    /// handshake: this is the [Noise Pipes] compound protocol
+    ///
+    /// If the reply cannot be decrypted as the reply of the `IK`
+    /// handshake it is read as the first message of the [`XXfallback`]
+    /// handshake started with `prologue`. The `payload` is the payload of
+    /// the message in either case.
+    ///
+    /// [Noise Pipes]: http://noiseprotocol.org/noise.html#noise-pipes
+    pub fn receive_with_fallback(
+        self,
+        s: &DH,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+        prologue: &[u8],
+    ) -> Result<Pipe<DH, H, RNG, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitB { rs },
+        } = self;
+
+        let mut reply = BufRead::new(input);
+
+        let re = inner.read_e(&mut reply)?;
+        inner.dh_ex(&re);
+        inner.dh_sx(s, &re);
+        inner.mix_psk();
+
+        // the decryption is tried regardless of the size of `payload`
+        let mut plaintext = Vec::with_capacity(reply.remaining());
+        match inner.decrypt_and_hash(&mut reply, &mut plaintext) {
+            Ok(()) => {
+                payload
+                    .prepare(plaintext.len())
+                    .ok_or(CipherStateError::NotEnoughOutput)?
+                    .copy_from_slice(&plaintext);
+
+                let (local, remote) = inner.symmetric_state().split();
+
+                Ok(Pipe::Established(TransportState::new(
+                    inner.symmetric_state().get_handshake_hash().clone(),
+                    local,
+                    remote,
+                    Some(rs),
+                    inner.symmetric_state().resumption_secret(),
+                    inner.symmetric_state().exporter_secret(),
+                )))
+            }
+            Err(HandshakeStateError::Cipher(CipherStateError::InvalidTag)) => {
+                XXfallback::fall_back(inner, prologue)
+                    .receive_with_payload(input, payload)
+                    .map(Pipe::Fallback)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// responder in `payload`
+    pub fn receive_with_payload(
+        self,
+        s: &DH,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: WaitB { rs },
@@ -168,8 +402,9 @@ where
         let re = inner.read_e(&mut input)?;
         inner.dh_ex(&re);
         inner.dh_sx(s, &re);
+        inner.mix_psk();
 
-        inner.decrypt_and_hash(&mut input, &mut [])?;
+        inner.decrypt_and_hash(&mut input, payload)?;
 
         let (local, remote) = inner.symmetric_state().split();
 
@@ -177,7 +412,9 @@ where
             inner.symmetric_state().get_handshake_hash().clone(),
             local,
             remote,
-            rs,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
         ))
     }
 }
@@ -185,26 +422,27 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::{Blake3, Sha256, Sha512};
     use crate::{
         key::{curve25519, ed25519, ed25519_extended, ed25519_hd},
-        noise::transport_state::tests::test_transport,
+        noise::{transport_state::tests::test_transport, AesGcm, Transcript},
     };
     use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
 
-    fn establish_handshake<H: Hash, K1: Dh, K2: Dh>(
+    fn establish_handshake<H: Hash, C: Cipher, K1: Dh, K2: Dh>(
         rng1: crate::Seed,
         rng2: crate::Seed,
         initiator_s: K1,
         responder_s: K2,
-    ) -> (TransportState<H>, TransportState<H>) {
+    ) -> (TransportState<H, C>, TransportState<H, C>) {
         let initiator_key = initiator_s.public();
         let responder_key = responder_s.public();
 
         let mut rng1 = rng1.into_rand_chacha();
         let mut rng2 = rng2.into_rand_chacha();
 
-        let initiator = IK::new(&mut rng1, &[]);
-        let responder = IK::new(&mut rng2, &[]);
+        let initiator = IK::with_cipher(&mut rng1, &None, &[]);
+        let responder = IK::with_cipher(&mut rng2, &None, &[]);
 
         let mut output = Vec::with_capacity(1024);
         let initiator = initiator
@@ -224,14 +462,181 @@ mod tests {
             .receive(&initiator_s, input.as_slice())
             .expect("initiator receives message B");
 
-        assert_eq!(&initiator_key, responder.remote_public_identity());
-        assert_eq!(&responder_key, initiator.remote_public_identity());
+        assert_eq!(Some(&initiator_key), responder.remote_public_identity());
+        assert_eq!(Some(&responder_key), initiator.remote_public_identity());
 
         (initiator, responder)
     }
 
+    #[quickcheck]
+    fn payloads(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        payload_a: Vec<u8>,
+        payload_b: Vec<u8>,
+    ) -> bool {
+        let initiator = IK::<_, Blake2b, _, _>::new(rng1.into_rand_chacha(), &None, &[]);
+        let responder = IK::<_, Blake2b, _, _>::new(rng2.into_rand_chacha(), &None, &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate_with_payload(&initiator_s, responder_s.public(), &payload_a, &mut output)
+            .expect("initiator sends message A");
+        let mut received_a = Vec::new();
+        let responder = responder
+            .receive_with_payload(&responder_s, output.as_slice(), &mut received_a)
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        responder
+            .reply_with_payload(&payload_b, &mut output)
+            .expect("responder sends message B");
+        let mut received_b = Vec::new();
+        initiator
+            .receive_with_payload(&initiator_s, output.as_slice(), &mut received_b)
+            .expect("initiator receives message B");
+
+        payload_a == received_a && payload_b == received_b
+    }
+
+    #[quickcheck]
+    fn policy(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+    ) -> bool {
+        let mut output = Vec::with_capacity(1024);
+        IK::<ed25519::SecretKey, Blake2b, _, _>::new(rng1.into_rand_chacha(), &None, &[])
+            .initiate(&initiator_s, responder_s.public(), &mut output)
+            .expect("initiator sends message A");
+
+        let receive = |policy: &dyn Fn(&PublicKey) -> bool| {
+            IK::<_, Blake2b, _, _>::new(rng2.clone().into_rand_chacha(), &None, &[])
+                .receive_with_policy(&responder_s, &output, &mut [], policy)
+        };
+
+        let accepted = match receive(&|id| id == &initiator_s.public_key()) {
+            Ok(responder) => responder.remote_public_identity() == &initiator_s.public_key(),
+            Err(_) => false,
+        };
+        let rejected = matches!(
+            receive(&|_| false),
+            Err(HandshakeStateError::RejectedIdentity(id)) if id == initiator_s.public_key()
+        );
+
+        accepted && rejected
+    }
+
+    #[test]
+    fn protocol_name() {
+        let rng = rand::thread_rng();
+        let psk = Some(Seed::from([1; Seed::SIZE]));
+
+        let ik = IK::<curve25519::SecretKey, Blake2s, _, _>::new(rng.clone(), &None, &[]);
+        assert_eq!(
+            ik.protocol_name().to_string(),
+            "Noise_IK_25519_ChaChaPoly_BLAKE2s"
+        );
+
+        let ik = IK::<curve25519::SecretKey, Sha256, _, _, AesGcm>::with_cipher(rng, &psk, &[]);
+        let name = ik.protocol_name();
+        assert_eq!(name.to_string(), "Noise_IKpsk2_25519_AESGCM_SHA256");
+        assert!(name
+            .validate::<curve25519::SecretKey, AesGcm, Sha256>()
+            .is_ok());
+    }
+
+    #[quickcheck]
+    fn psk2(
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        psk: crate::Seed,
+        other: crate::Seed,
+        payload: Vec<u8>,
+    ) -> bool {
+        use rand::thread_rng;
+
+        let handshake = |initiator_psk: &Option<crate::Seed>,
+                         responder_psk: &Option<crate::Seed>| {
+            let initiator = IK::<_, Blake2b, _, _>::new(thread_rng(), initiator_psk, &[]);
+            let responder = IK::<_, Blake2b, _, _>::new(thread_rng(), responder_psk, &[]);
+
+            let mut a = Vec::new();
+            let initiator = initiator.initiate_with_payload(
+                &initiator_s,
+                responder_s.public(),
+                &payload,
+                &mut a,
+            )?;
+            let mut received = Vec::new();
+            let responder = responder.receive_with_payload(&responder_s, &a, &mut received)?;
+
+            let mut b = Vec::new();
+            let responder = responder.reply(&mut b)?;
+            let initiator = initiator.receive(&initiator_s, &b)?;
+
+            Ok::<_, HandshakeStateError>((initiator, responder, received))
+        };
+
+        let psk = Some(psk);
+        let established = match handshake(&psk, &psk) {
+            Ok((initiator, responder, received)) => {
+                initiator.noise_session() == responder.noise_session() && received == payload
+            }
+            Err(_) => false,
+        };
+
+        // the peers need to share the same pre-shared key
+        established && handshake(&psk, &Some(other)).is_err() && handshake(&psk, &None).is_err()
+    }
+
+    #[quickcheck]
+    fn transcripts(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519_extended::SecretKey,
+        responder_s: ed25519_extended::SecretKey,
+        messages: Vec<Vec<u8>>,
+    ) -> bool {
+        let (mut initiator, mut responder) = establish_handshake::<Blake2b, ChaChaPoly, _, _>(
+            rng1,
+            rng2,
+            initiator_s.clone(),
+            responder_s,
+        );
+        initiator.enable_transcript();
+        responder.enable_transcript();
+
+        for message in &messages {
+            let mut output = Vec::new();
+            initiator.send(message, &mut output).unwrap();
+            let mut received = Vec::new();
+            responder.receive(&output, &mut received).unwrap();
+        }
+        let mut output = Vec::new();
+        responder.send(b"ack", &mut output).unwrap();
+        initiator.receive(&output, &mut [0; 3]).unwrap();
+
+        let initiator_summary = initiator.transcript().unwrap();
+        let responder_summary = responder.transcript().unwrap();
+        let replayed = Transcript::<Blake2b>::replay(initiator.noise_session(), &messages);
+
+        let signed = initiator_summary.clone().sign(&initiator_s);
+
+        initiator_summary.matches(&responder_summary)
+            && replayed.chain() == initiator_summary.sent()
+            && signed.verify()
+            && Some(signed.signer()) == responder.remote_public_identity()
+    }
+
     macro_rules! mk_test {
         ($name:ident, $sk1:ty, $sk2:ty, $hash:ty) => {
+            mk_test!($name, $sk1, $sk2, $hash, ChaChaPoly);
+        };
+        ($name:ident, $sk1:ty, $sk2:ty, $hash:ty, $cipher:ty) => {
             #[quickcheck]
             fn $name(
                 rng1: crate::Seed,
@@ -244,7 +649,7 @@ mod tests {
                 let (initiator, responder) =
                     establish_handshake(rng1, rng2, initiator_s, responder_s);
 
-                test_transport::<$hash>(
+                test_transport::<$hash, $cipher>(
                     initiator,
                     responder,
                     messages_init_to_responder,
@@ -266,6 +671,38 @@ mod tests {
         curve25519::SecretKey,
         Blake2s
     );
+    mk_test!(
+        curve25519_to_curve25519_blake3,
+        curve25519::SecretKey,
+        curve25519::SecretKey,
+        Blake3
+    );
+    mk_test!(
+        curve25519_to_curve25519_sha256,
+        curve25519::SecretKey,
+        curve25519::SecretKey,
+        Sha256
+    );
+    mk_test!(
+        curve25519_to_curve25519_sha512,
+        curve25519::SecretKey,
+        curve25519::SecretKey,
+        Sha512
+    );
+    mk_test!(
+        curve25519_to_curve25519_blake2s_aes_gcm,
+        curve25519::SecretKey,
+        curve25519::SecretKey,
+        Blake2s,
+        AesGcm
+    );
+    mk_test!(
+        curve25519_to_curve25519_sha256_aes_gcm,
+        curve25519::SecretKey,
+        curve25519::SecretKey,
+        Sha256,
+        AesGcm
+    );
 
     mk_test!(
         ed25519_to_ed25519_blake2b,
@@ -279,6 +716,12 @@ mod tests {
         ed25519::SecretKey,
         Blake2s
     );
+    mk_test!(
+        ed25519_to_ed25519_blake3,
+        ed25519::SecretKey,
+        ed25519::SecretKey,
+        Blake3
+    );
     mk_test!(
         ed25519_to_ed25519_extended_blake2b,
         ed25519::SecretKey,
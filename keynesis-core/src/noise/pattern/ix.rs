@@ -1,8 +1,10 @@
 use crate::{
     buffer::BufRead,
     hash::Hash,
-    key::{ed25519::PublicKey, Dh},
-    noise::{HandshakeState, HandshakeStateError, TransportState},
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{
+        ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName, TransportState,
+    },
 };
 use rand_core::{CryptoRng, RngCore};
 use std::io::Write;
@@ -11,11 +13,12 @@ use std::io::Write;
 ///
 /// [**Noise IX**]: https://noiseexplorer.com/patterns/IX/
 #[allow(clippy::upper_case_acronyms)]
-pub struct IX<DH, H, RNG, S>
+pub struct IX<DH, H, RNG, S, C = ChaChaPoly>
 where
     H: Hash,
+    C: Cipher,
 {
-    inner: HandshakeState<RNG, DH, H>,
+    inner: HandshakeState<RNG, DH, H, C>,
     state: S,
 }
 
@@ -26,38 +29,74 @@ pub struct SendB {
     rs: PublicKey,
 }
 
+impl<DH, H, RNG, S, C> IX<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
 impl<DH, H, RNG> IX<DH, H, RNG, A>
 where
     DH: Dh,
     H: Hash,
 {
     pub fn new(rng: RNG, prologue: &[u8]) -> Self {
-        let protocol_name = format!(
-            "Noise_{pattern}_{dh}_{cipher}_{hash}",
-            pattern = "IX",
-            dh = DH::name(),
-            cipher = "ChaChaPoly",
-            hash = H::name(),
-        );
+        Self::with_cipher(rng, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> IX<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, prologue: &[u8]) -> Self {
+        let protocol_name = ProtocolName::new::<DH, C, H>("IX");
 
         Self {
-            inner: HandshakeState::new(rng, prologue, &protocol_name),
+            inner: HandshakeState::new(rng, prologue, protocol_name),
             state: A,
         }
     }
 }
 
-impl<DH, H, RNG> IX<DH, H, RNG, A>
+impl<H, RNG, C> IX<curve25519::SecretKey, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> IX<DH, H, RNG, A, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub fn initiate(
         self,
         s: &PublicKey,
         mut output: impl Write,
-    ) -> Result<IX<DH, H, RNG, WaitB>, HandshakeStateError> {
+    ) -> Result<IX<DH, H, RNG, WaitB, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: A,
@@ -74,12 +113,13 @@ where
         })
     }
 }
-impl<DH, H, RNG> IX<DH, H, RNG, A>
+impl<DH, H, RNG, C> IX<DH, H, RNG, A, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
-    pub fn receive(self, input: &[u8]) -> Result<IX<DH, H, RNG, SendB>, HandshakeStateError> {
+    pub fn receive(self, input: &[u8]) -> Result<IX<DH, H, RNG, SendB, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: A,
@@ -98,17 +138,18 @@ where
         })
     }
 }
-impl<DH, H, RNG> IX<DH, H, RNG, SendB>
+impl<DH, H, RNG, C> IX<DH, H, RNG, SendB, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub fn reply(
         self,
         s: &DH,
         mut output: impl Write,
-    ) -> Result<TransportState<H>, HandshakeStateError> {
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: SendB { re, rs },
@@ -128,16 +169,23 @@ where
             inner.symmetric_state().get_handshake_hash().clone(),
             local,
             remote,
-            rs,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
         ))
     }
 }
-impl<DH, H, RNG> IX<DH, H, RNG, WaitB>
+impl<DH, H, RNG, C> IX<DH, H, RNG, WaitB, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
-    pub fn receive(self, s: &DH, input: &[u8]) -> Result<TransportState<H>, HandshakeStateError> {
+    pub fn receive(
+        self,
+        s: &DH,
+        input: &[u8],
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: WaitB,
@@ -159,7 +207,9 @@ where
             inner.symmetric_state().get_handshake_hash().clone(),
             local,
             remote,
-            rs,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
         ))
     }
 }
@@ -206,8 +256,8 @@ mod tests {
             .receive(&initiator_s, input.as_slice())
             .expect("initiator receives message B");
 
-        assert_eq!(&initiator_key, responder.remote_public_identity());
-        assert_eq!(&responder_key, initiator.remote_public_identity());
+        assert_eq!(Some(&initiator_key), responder.remote_public_identity());
+        assert_eq!(Some(&responder_key), initiator.remote_public_identity());
 
         (initiator, responder)
     }
@@ -226,7 +276,7 @@ mod tests {
                 let (initiator, responder) =
                     establish_handshake(rng1, rng2, initiator_s, responder_s);
 
-                test_transport::<$hash>(
+                test_transport::<$hash, _>(
                     initiator,
                     responder,
                     messages_init_to_responder,
@@ -0,0 +1,452 @@
+use crate::{
+    buffer::{BufRead, OutBuffer},
+    hash::Hash,
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{
+        ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName, TransportState,
+    },
+};
+use rand_core::{CryptoRng, RngCore};
+use std::io::Write;
+
+/// Interactive Handshake [**Noise KK**]
+///
+/// both peers already know the static public key of the other one (from
+/// a peer database for example): the static keys are not sent at all. The
+/// messages are smaller than with [`IK`](crate::noise::IK) and neither
+/// identity appears on the wire.
+///
+/// [**Noise KK**]: https://noiseexplorer.com/patterns/KK/
+#[allow(clippy::upper_case_acronyms)]
+pub struct KK<DH, H, RNG, S, C = ChaChaPoly>
+where
+    H: Hash,
+    C: Cipher,
+{
+    inner: HandshakeState<RNG, DH, H, C>,
+    state: S,
+}
+
+pub struct A;
+pub struct WaitB {
+    rs: PublicKey,
+}
+pub struct SendB {
+    re: PublicKey,
+    rs: PublicKey,
+}
+
+impl<DH, H, RNG, S, C> KK<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
+impl<DH, H, RNG> KK<DH, H, RNG, A>
+where
+    DH: Dh,
+    H: Hash,
+{
+    pub fn new(rng: RNG, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> KK<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, prologue: &[u8]) -> Self {
+        let protocol_name = ProtocolName::new::<DH, C, H>("KK");
+        Self {
+            inner: HandshakeState::new(rng, prologue, protocol_name),
+            state: A,
+        }
+    }
+}
+
+impl<H, RNG, C> KK<curve25519::SecretKey, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> KK<DH, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn initiate<K>(
+        self,
+        s: &K,
+        rs: PublicKey,
+        output: impl Write,
+    ) -> Result<KK<DH, H, RNG, WaitB, C>, HandshakeStateError>
+    where
+        K: Dh,
+    {
+        self.initiate_with_payload(s, rs, b"", output)
+    }
+
+    /// same as [`initiate`](Self::initiate) but send the given payload
+    /// too. The payload is encrypted and authenticated.
+    pub fn initiate_with_payload<K>(
+        self,
+        s: &K,
+        rs: PublicKey,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<KK<DH, H, RNG, WaitB, C>, HandshakeStateError>
+    where
+        K: Dh,
+    {
+        let Self {
+            mut inner,
+            state: A,
+        } = self;
+
+        inner.mix_hash(&s.public());
+        inner.mix_hash(&rs);
+
+        inner.write_e(&mut output)?;
+        inner.dh_ex(&rs);
+        inner.dh_sx(s, &rs);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        Ok(KK {
+            inner,
+            state: WaitB { rs },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> KK<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// receive the first message of the initiator `rs`
+    pub fn receive(
+        self,
+        s: &DH,
+        rs: PublicKey,
+        input: &[u8],
+    ) -> Result<KK<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        self.receive_with_payload(s, rs, input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// initiator in `payload`
+    pub fn receive_with_payload(
+        self,
+        s: &DH,
+        rs: PublicKey,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<KK<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A,
+        } = self;
+
+        inner.mix_hash(&rs);
+        inner.mix_hash(&s.public());
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+        inner.dh_sx(s, &re);
+        inner.dh_sx(s, &rs);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        Ok(KK {
+            inner,
+            state: SendB { re, rs },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> KK<DH, H, RNG, SendB, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn remote_public_identity(&self) -> &PublicKey {
+        &self.state.rs
+    }
+
+    pub fn reply(self, output: impl Write) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.reply_with_payload(b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted and authenticated.
+    pub fn reply_with_payload(
+        self,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: SendB { re, rs },
+        } = self;
+
+        inner.write_e(&mut output)?;
+        inner.dh_ex(&re);
+        inner.dh_ex(&rs);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        let (remote, local) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+impl<DH, H, RNG, C> KK<DH, H, RNG, WaitB, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn remote_public_identity(&self) -> &PublicKey {
+        &self.state.rs
+    }
+
+    pub fn receive(
+        self,
+        s: &DH,
+        input: &[u8],
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.receive_with_payload(s, input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// responder in `payload`
+    pub fn receive_with_payload(
+        self,
+        s: &DH,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitB { rs },
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+        inner.dh_ex(&re);
+        inner.dh_sx(s, &re);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        let (local, remote) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::{curve25519, ed25519, ed25519_extended, ed25519_hd},
+        noise::{cipher::TAG_LEN, transport_state::tests::test_transport, IK},
+    };
+    use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
+
+    fn establish_handshake<H: Hash, K: Dh>(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: K,
+        responder_s: K,
+    ) -> (TransportState<H>, TransportState<H>) {
+        let initiator_key = initiator_s.public();
+        let responder_key = responder_s.public();
+
+        let initiator = KK::<K, H, _, _>::new(rng1.into_rand_chacha(), &[]);
+        let responder = KK::<K, H, _, _>::new(rng2.into_rand_chacha(), &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate(&initiator_s, responder_key, &mut output)
+            .expect("initiator sends message A");
+        let input = output;
+        let responder = responder
+            .receive(&responder_s, initiator_key, input.as_slice())
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = responder
+            .reply(&mut output)
+            .expect("responder sends message B");
+        let input = output;
+        let initiator = initiator
+            .receive(&initiator_s, input.as_slice())
+            .expect("initiator receives message B");
+
+        assert_eq!(Some(&initiator_key), responder.remote_public_identity());
+        assert_eq!(Some(&responder_key), initiator.remote_public_identity());
+
+        (initiator, responder)
+    }
+
+    #[quickcheck]
+    fn payloads(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        payload_a: Vec<u8>,
+        payload_b: Vec<u8>,
+    ) -> bool {
+        let initiator = KK::<_, Blake2b, _, _>::new(rng1.into_rand_chacha(), &[]);
+        let responder = KK::<_, Blake2b, _, _>::new(rng2.into_rand_chacha(), &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate_with_payload(&initiator_s, responder_s.public(), &payload_a, &mut output)
+            .expect("initiator sends message A");
+        let mut received_a = Vec::new();
+        let responder = responder
+            .receive_with_payload(
+                &responder_s,
+                initiator_s.public(),
+                output.as_slice(),
+                &mut received_a,
+            )
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        responder
+            .reply_with_payload(&payload_b, &mut output)
+            .expect("responder sends message B");
+        let mut received_b = Vec::new();
+        initiator
+            .receive_with_payload(&initiator_s, output.as_slice(), &mut received_b)
+            .expect("initiator receives message B");
+
+        payload_a == received_a && payload_b == received_b
+    }
+
+    #[test]
+    fn unknown_initiator() {
+        let mut rng = rand::thread_rng();
+        let initiator_s = curve25519::SecretKey::new(&mut rng);
+        let responder_s = curve25519::SecretKey::new(&mut rng);
+        let other = curve25519::SecretKey::new(&mut rng);
+
+        let mut output = Vec::new();
+        KK::<curve25519::SecretKey, Blake2b, _, _>::new(&mut rng, &[])
+            .initiate(&initiator_s, responder_s.public_key(), &mut output)
+            .unwrap();
+
+        let responder = KK::<_, Blake2b, _, _>::new(&mut rng, &[]);
+        assert!(responder
+            .receive(&responder_s, other.public_key(), &output)
+            .is_err());
+    }
+
+    #[test]
+    fn smaller_than_ik() {
+        let mut rng = rand::thread_rng();
+        let initiator_s = curve25519::SecretKey::new(&mut rng);
+        let responder_s = curve25519::SecretKey::new(&mut rng);
+
+        let mut kk = Vec::new();
+        KK::<curve25519::SecretKey, Blake2b, _, _>::new(&mut rng, &[])
+            .initiate(&initiator_s, responder_s.public_key(), &mut kk)
+            .unwrap();
+        let mut ik = Vec::new();
+        IK::<curve25519::SecretKey, Blake2b, _, _>::new(&mut rng, &None, &[])
+            .initiate(&initiator_s, responder_s.public_key(), &mut ik)
+            .unwrap();
+
+        // the initiator's static key is not sent
+        assert_eq!(kk.len() + PublicKey::SIZE + TAG_LEN, ik.len());
+    }
+
+    macro_rules! mk_test {
+        ($name:ident, $sk:ty, $hash:ty) => {
+            #[quickcheck]
+            fn $name(
+                rng1: crate::Seed,
+                rng2: crate::Seed,
+                initiator_s: $sk,
+                responder_s: $sk,
+                messages_init_to_responder: Vec<Vec<u8>>,
+                messages_resp_to_initiator: Vec<Vec<u8>>,
+            ) -> bool {
+                let (initiator, responder) =
+                    establish_handshake::<$hash, _>(rng1, rng2, initiator_s, responder_s);
+
+                test_transport::<$hash, _>(
+                    initiator,
+                    responder,
+                    messages_init_to_responder,
+                    messages_resp_to_initiator,
+                )
+            }
+        };
+    }
+
+    mk_test!(curve25519_blake2b, curve25519::SecretKey, Blake2b);
+    mk_test!(curve25519_blake2s, curve25519::SecretKey, Blake2s);
+    mk_test!(ed25519_blake2b, ed25519::SecretKey, Blake2b);
+    mk_test!(ed25519_blake2s, ed25519::SecretKey, Blake2s);
+    mk_test!(
+        ed25519_extended_blake2b,
+        ed25519_extended::SecretKey,
+        Blake2b
+    );
+    mk_test!(
+        ed25519_extended_blake2s,
+        ed25519_extended::SecretKey,
+        Blake2s
+    );
+    mk_test!(ed25519_hd_blake2b, ed25519_hd::SecretKey, Blake2b);
+    mk_test!(ed25519_hd_blake2s, ed25519_hd::SecretKey, Blake2s);
+}
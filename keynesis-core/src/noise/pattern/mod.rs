@@ -5,9 +5,18 @@ Here are implemented a few of the handshakes available from the noise protocol.
 The naming convention of these patterns matches the one defined in the
 [Noise Specification].
 
-all of these handshakes expect the participants to authenticate. This means that
+all of these handshakes expect the participants to authenticate, except for
+the initiator of the [`NK`] handshake which stays anonymous. This means that
 We should always be able to authenticate messages between the participants.
 
+The [`NN`] handshake is the exception: no one is authenticated, it only
+protects from passive observers and ends with an [`Unauthenticated`](nn::Unauthenticated)
+session.
+
+The [`Resume`] handshake resumes a session established earlier with
+any of the other handshakes, using the [`ResumptionSecret`](crate::noise::ResumptionSecret)
+of that session instead of the static keys.
+
 Each of these handshakes comes with pros and cons. Before using any of these you
 should look at the [Noise Explorer] to understand the signification of the handshakes
 how you can leverage that.
@@ -17,8 +26,17 @@ how you can leverage that.
 */
 pub mod ik;
 pub mod ix;
+pub mod kk;
 pub mod n;
+pub mod nk;
+pub mod nn;
+pub mod resume;
 pub mod x;
+pub mod xk;
 pub mod xx;
+pub mod xxfallback;
 
-pub use self::{ik::IK, ix::IX, n::N, x::X, xx::XX};
+pub use self::{
+    ik::IK, ix::IX, kk::KK, n::N, nk::NK, nn::NN, resume::Resume, x::X, xk::XK, xx::XX,
+    xxfallback::XXfallback,
+};
@@ -3,8 +3,8 @@ use std::io::Write;
 use crate::{
     buffer::BufRead,
     hash::Hash,
-    key::{ed25519::PublicKey, Dh},
-    noise::{CipherState, HandshakeState, HandshakeStateError},
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName},
     seed::Seed,
 };
 use rand_core::{CryptoRng, RngCore};
@@ -12,29 +12,49 @@ use rand_core::{CryptoRng, RngCore};
 /// One-Way Handshake [**Noise N**]
 ///
 /// [**Noise N**]: https://noiseexplorer.com/patterns/K/
-pub struct N<DH, H, RNG>
+pub struct N<DH, H, RNG, C = ChaChaPoly>
 where
     H: Hash,
+    C: Cipher,
 {
-    inner: HandshakeState<RNG, DH, H>,
+    inner: HandshakeState<RNG, DH, H, C>,
 }
+impl<DH, H, RNG, C> N<DH, H, RNG, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
 impl<DH, H, RNG> N<DH, H, RNG>
 where
     DH: Dh,
     H: Hash,
 {
     pub fn new(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, psk, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> N<DH, H, RNG, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
         let pattern = if psk.is_some() { "Kpsk0" } else { "K" };
 
-        let protocol_name = format!(
-            "Noise_{pattern}_{dh}_{cipher}_{hash}",
-            pattern = pattern,
-            dh = DH::name(),
-            cipher = "ChaChaPoly",
-            hash = H::name(),
-        );
+        let protocol_name = ProtocolName::new::<DH, C, H>(pattern);
 
-        let mut inner = HandshakeState::new(rng, prologue, &protocol_name);
+        let mut inner = HandshakeState::new(rng, prologue, protocol_name);
 
         if let Some(psk) = psk {
             inner.psk(psk.as_ref());
@@ -44,11 +64,28 @@ where
     }
 }
 
-impl<DH, H, RNG> N<DH, H, RNG>
+impl<H, RNG, C> N<curve25519::SecretKey, H, RNG, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> N<DH, H, RNG, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     /// establish a one-way handshake with an already known `PublicIdentity`
     /// and send the given payload too.
@@ -78,10 +115,11 @@ where
     }
 }
 
-impl<DH, H, RNG> N<DH, H, RNG>
+impl<DH, H, RNG, C> N<DH, H, RNG, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     /// receive a one-way handshake with an unknown
     pub fn receive(self, s: &DH, input: &[u8]) -> Result<Box<[u8]>, HandshakeStateError> {
@@ -94,7 +132,7 @@ where
         let re = inner.read_e(&mut input)?;
         inner.dh_sx(s, &re);
 
-        let mut bytes = vec![0; input.remaining() - CipherState::TAG_LEN];
+        let mut bytes = Vec::with_capacity(input.remaining());
         inner.decrypt_and_hash(&mut input, &mut bytes)?;
 
         Ok(bytes.into_boxed_slice())
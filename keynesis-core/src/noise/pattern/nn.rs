@@ -0,0 +1,396 @@
+use crate::{
+    buffer::{BufRead, OutBuffer},
+    hash::Hash,
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{
+        ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName, TransportState,
+    },
+};
+use rand_core::{CryptoRng, RngCore};
+use std::io::Write;
+
+/// Interactive Handshake [**Noise NN**]
+///
+/// none of the peers has a static key: the messages are encrypted with
+/// the ephemeral keys only. This protects the messages from passive
+/// observers but **neither peer is authenticated**, anyone on the path
+/// can stand in the middle of the connection.
+///
+/// To make it explicit the handshake ends with an [`Unauthenticated`]
+/// session and not directly a [`TransportState`].
+///
+/// [**Noise NN**]: https://noiseexplorer.com/patterns/NN/
+#[allow(clippy::upper_case_acronyms)]
+pub struct NN<DH, H, RNG, S, C = ChaChaPoly>
+where
+    H: Hash,
+    C: Cipher,
+{
+    inner: HandshakeState<RNG, DH, H, C>,
+    state: S,
+}
+
+pub struct A;
+pub struct WaitB;
+pub struct SendB {
+    re: PublicKey,
+}
+
+/// the session established with an [`NN`] handshake
+///
+/// the remote peer is not authenticated: the messages are confidential
+/// against passive observers only. The [`noise_session`] may still be
+/// compared out of band (or signed by a key known by the other peer) to
+/// detect a man in the middle.
+///
+/// [`noise_session`]: Self::noise_session
+pub struct Unauthenticated<H: Hash, C = ChaChaPoly>(TransportState<H, C>);
+
+impl<H: Hash, C: Cipher> Unauthenticated<H, C> {
+    /// unique identifier of the noise session
+    pub fn noise_session(&self) -> &H::HASH {
+        self.0.noise_session()
+    }
+
+    /// use the session knowing the remote peer is not authenticated
+    pub fn accept_unauthenticated(self) -> TransportState<H, C> {
+        self.0
+    }
+}
+
+impl<DH, H, RNG, S, C> NN<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
+impl<DH, H, RNG> NN<DH, H, RNG, A>
+where
+    DH: Dh,
+    H: Hash,
+{
+    pub fn new(rng: RNG, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> NN<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, prologue: &[u8]) -> Self {
+        let protocol_name = ProtocolName::new::<DH, C, H>("NN");
+        Self {
+            inner: HandshakeState::new(rng, prologue, protocol_name),
+            state: A,
+        }
+    }
+}
+
+impl<H, RNG, C> NN<curve25519::SecretKey, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> NN<DH, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn initiate(
+        self,
+        output: impl Write,
+    ) -> Result<NN<DH, H, RNG, WaitB, C>, HandshakeStateError> {
+        self.initiate_with_payload(b"", output)
+    }
+
+    /// same as [`initiate`](Self::initiate) but send the given payload
+    /// too. The payload is sent in clear: there is no key yet.
+    pub fn initiate_with_payload(
+        self,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<NN<DH, H, RNG, WaitB, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A,
+        } = self;
+
+        inner.write_e(&mut output)?;
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        Ok(NN {
+            inner,
+            state: WaitB,
+        })
+    }
+}
+
+impl<DH, H, RNG, C> NN<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(self, input: &[u8]) -> Result<NN<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// initiator in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<NN<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A,
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        Ok(NN {
+            inner,
+            state: SendB { re },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> NN<DH, H, RNG, SendB, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn reply(self, output: impl Write) -> Result<Unauthenticated<H, C>, HandshakeStateError> {
+        self.reply_with_payload(b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted but not authenticated.
+    pub fn reply_with_payload(
+        self,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<Unauthenticated<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: SendB { re },
+        } = self;
+
+        inner.write_e(&mut output)?;
+        inner.dh_ex(&re);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        let (remote, local) = inner.symmetric_state().split();
+
+        Ok(Unauthenticated(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            None,
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        )))
+    }
+}
+
+impl<DH, H, RNG, C> NN<DH, H, RNG, WaitB, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(self, input: &[u8]) -> Result<Unauthenticated<H, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// responder in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<Unauthenticated<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitB,
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+        inner.dh_ex(&re);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        let (local, remote) = inner.symmetric_state().split();
+
+        Ok(Unauthenticated(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            None,
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::{curve25519, ed25519, ed25519_extended, ed25519_hd},
+        noise::transport_state::tests::test_transport,
+    };
+    use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
+
+    fn establish_handshake<H: Hash, K: Dh>(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+    ) -> (Unauthenticated<H>, Unauthenticated<H>) {
+        let initiator = NN::<K, H, _, _>::new(rng1.into_rand_chacha(), &[]);
+        let responder = NN::<K, H, _, _>::new(rng2.into_rand_chacha(), &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate(&mut output)
+            .expect("initiator sends message A");
+        let input = output;
+        let responder = responder
+            .receive(input.as_slice())
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = responder
+            .reply(&mut output)
+            .expect("responder sends message B");
+        let input = output;
+        let initiator = initiator
+            .receive(input.as_slice())
+            .expect("initiator receives message B");
+
+        (initiator, responder)
+    }
+
+    #[quickcheck]
+    fn payloads(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        payload_a: Vec<u8>,
+        payload_b: Vec<u8>,
+    ) -> bool {
+        let initiator =
+            NN::<curve25519::SecretKey, Blake2b, _, _>::new(rng1.into_rand_chacha(), &[]);
+        let responder =
+            NN::<curve25519::SecretKey, Blake2b, _, _>::new(rng2.into_rand_chacha(), &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate_with_payload(&payload_a, &mut output)
+            .expect("initiator sends message A");
+        let mut received_a = Vec::new();
+        let responder = responder
+            .receive_with_payload(output.as_slice(), &mut received_a)
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        responder
+            .reply_with_payload(&payload_b, &mut output)
+            .expect("responder sends message B");
+        let mut received_b = Vec::new();
+        initiator
+            .receive_with_payload(output.as_slice(), &mut received_b)
+            .expect("initiator receives message B");
+
+        payload_a == received_a && payload_b == received_b
+    }
+
+    #[quickcheck]
+    fn unauthenticated(rng1: crate::Seed, rng2: crate::Seed) -> bool {
+        let (initiator, responder) =
+            establish_handshake::<Blake2b, curve25519::SecretKey>(rng1, rng2);
+
+        if initiator.noise_session() != responder.noise_session() {
+            return false;
+        }
+
+        let initiator = initiator.accept_unauthenticated();
+        let responder = responder.accept_unauthenticated();
+
+        initiator.remote_public_identity().is_none()
+            && responder.remote_public_identity().is_none()
+            && initiator.export_secret(b"test", b"", 32)
+                == responder.export_secret(b"test", b"", 32)
+    }
+
+    macro_rules! mk_test {
+        ($name:ident, $sk:ty, $hash:ty) => {
+            #[quickcheck]
+            fn $name(
+                rng1: crate::Seed,
+                rng2: crate::Seed,
+                messages_init_to_responder: Vec<Vec<u8>>,
+                messages_resp_to_initiator: Vec<Vec<u8>>,
+            ) -> bool {
+                let (initiator, responder) = establish_handshake::<$hash, $sk>(rng1, rng2);
+
+                test_transport::<$hash, _>(
+                    initiator.accept_unauthenticated(),
+                    responder.accept_unauthenticated(),
+                    messages_init_to_responder,
+                    messages_resp_to_initiator,
+                )
+            }
+        };
+    }
+
+    mk_test!(curve25519_blake2b, curve25519::SecretKey, Blake2b);
+    mk_test!(curve25519_blake2s, curve25519::SecretKey, Blake2s);
+    mk_test!(ed25519_blake2b, ed25519::SecretKey, Blake2b);
+    mk_test!(ed25519_blake2s, ed25519::SecretKey, Blake2s);
+    mk_test!(
+        ed25519_extended_blake2b,
+        ed25519_extended::SecretKey,
+        Blake2b
+    );
+    mk_test!(
+        ed25519_extended_blake2s,
+        ed25519_extended::SecretKey,
+        Blake2s
+    );
+    mk_test!(ed25519_hd_blake2b, ed25519_hd::SecretKey, Blake2b);
+    mk_test!(ed25519_hd_blake2s, ed25519_hd::SecretKey, Blake2s);
+}
@@ -0,0 +1,469 @@
+use crate::{
+    buffer::{BufRead, OutBuffer},
+    hash::Hash,
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{
+        ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName, ResumptionSecret,
+        TransportState,
+    },
+};
+use rand_core::{CryptoRng, RngCore};
+use std::io::Write;
+
+/// Resumption Handshake [**Noise NNpsk0**]
+///
+/// resume a session established earlier without a full handshake: the
+/// pre-shared key is the [`ResumptionSecret`] of the previous session
+/// (see [`TransportState::resumption_secret`]) and no static key is
+/// used, the resumed session keeps the remote identity of the previous
+/// session. Only the peers knowing the secret can complete the handshake.
+///
+/// New ephemeral keys are exchanged so the resumed session has its own
+/// keys. The initiator may send data with its first message (0-RTT),
+/// this payload is encrypted with the secret only: it is not forward
+/// secret and it **may be replayed** by an attacker, the responder must
+/// not act on it if it cannot be replayed safely.
+///
+/// [**Noise NNpsk0**]: https://noiseexplorer.com/patterns/NNpsk0/
+pub struct Resume<DH, H, RNG, S, C = ChaChaPoly>
+where
+    H: Hash,
+    C: Cipher,
+{
+    inner: HandshakeState<RNG, DH, H, C>,
+    state: S,
+}
+
+pub struct A {
+    remote_id: Option<PublicKey>,
+}
+pub struct WaitB {
+    remote_id: Option<PublicKey>,
+}
+pub struct SendB {
+    re: PublicKey,
+    remote_id: Option<PublicKey>,
+}
+
+impl<DH, H, RNG, S, C> Resume<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
+impl<DH, H, RNG> Resume<DH, H, RNG, A>
+where
+    DH: Dh,
+    H: Hash,
+{
+    /// start the resumption of the session the `secret` comes from
+    pub fn new(rng: RNG, secret: &ResumptionSecret, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, secret, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> Resume<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, secret: &ResumptionSecret, prologue: &[u8]) -> Self {
+        let protocol_name = ProtocolName::new::<DH, C, H>("NNpsk0");
+        let mut inner = HandshakeState::new(rng, prologue, protocol_name);
+        inner.set_psk(&Some(secret.secret().clone()));
+        Self {
+            inner,
+            state: A {
+                remote_id: secret.remote_public_identity().copied(),
+            },
+        }
+    }
+}
+
+impl<H, RNG, C> Resume<curve25519::SecretKey, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> Resume<DH, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn initiate(
+        self,
+        output: impl Write,
+    ) -> Result<Resume<DH, H, RNG, WaitB, C>, HandshakeStateError> {
+        self.initiate_with_payload(b"", output)
+    }
+
+    /// same as [`initiate`](Self::initiate) but send the given payload
+    /// too (0-RTT). The payload is encrypted with the resumption secret
+    /// but it may be replayed, see [`Resume`].
+    pub fn initiate_with_payload(
+        self,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<Resume<DH, H, RNG, WaitB, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A { remote_id },
+        } = self;
+
+        inner.mix_psk();
+        inner.write_e(&mut output)?;
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        Ok(Resume {
+            inner,
+            state: WaitB { remote_id },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> Resume<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(
+        self,
+        input: &[u8],
+    ) -> Result<Resume<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// initiator in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<Resume<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A { remote_id },
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        inner.mix_psk();
+        let re = inner.read_e(&mut input)?;
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        Ok(Resume {
+            inner,
+            state: SendB { re, remote_id },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> Resume<DH, H, RNG, SendB, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn reply(self, output: impl Write) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.reply_with_payload(b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    pub fn reply_with_payload(
+        self,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: SendB { re, remote_id },
+        } = self;
+
+        inner.write_e(&mut output)?;
+        inner.dh_ex(&re);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        let (remote, local) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            remote_id,
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+impl<DH, H, RNG, C> Resume<DH, H, RNG, WaitB, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(self, input: &[u8]) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// responder in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitB { remote_id },
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+        inner.dh_ex(&re);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        let (local, remote) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            remote_id,
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::{curve25519, ed25519, ed25519_extended, ed25519_hd},
+        noise::{transport_state::tests::test_transport, IK},
+    };
+    use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
+
+    /// the session to resume, established with an [`IK`] handshake
+    fn previous_session<H: Hash, K: Dh>(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: K,
+        responder_s: K,
+    ) -> (TransportState<H>, TransportState<H>) {
+        let initiator = IK::<K, H, _, _>::new(rng1.into_rand_chacha(), &None, &[]);
+        let responder = IK::<K, H, _, _>::new(rng2.into_rand_chacha(), &None, &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate(&initiator_s, responder_s.public(), &mut output)
+            .expect("initiator sends message A");
+        let responder = responder
+            .receive(&responder_s, output.as_slice())
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = responder
+            .reply(&mut output)
+            .expect("responder sends message B");
+        let initiator = initiator
+            .receive(&initiator_s, output.as_slice())
+            .expect("initiator receives message B");
+
+        (initiator, responder)
+    }
+
+    fn resume<H: Hash, K: Dh>(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_secret: &ResumptionSecret,
+        responder_secret: &ResumptionSecret,
+    ) -> Result<(TransportState<H>, TransportState<H>), HandshakeStateError> {
+        let initiator = Resume::<K, H, _, _>::new(rng1.into_rand_chacha(), initiator_secret, &[]);
+        let responder = Resume::<K, H, _, _>::new(rng2.into_rand_chacha(), responder_secret, &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator.initiate(&mut output)?;
+        let responder = responder.receive(output.as_slice())?;
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = responder.reply(&mut output)?;
+        let initiator = initiator.receive(output.as_slice())?;
+
+        Ok((initiator, responder))
+    }
+
+    #[quickcheck]
+    fn same_secret(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+    ) -> bool {
+        let (initiator, responder) =
+            previous_session::<Blake2b, _>(rng1, rng2, initiator_s, responder_s);
+
+        initiator.resumption_secret().to_bytes()[..crate::Seed::SIZE]
+            == responder.resumption_secret().to_bytes()[..crate::Seed::SIZE]
+    }
+
+    #[quickcheck]
+    fn keep_identities(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+    ) -> bool {
+        let initiator_key = initiator_s.public_key();
+        let responder_key = responder_s.public_key();
+
+        let (initiator, responder) =
+            previous_session::<Blake2b, _>(rng1.clone(), rng2.clone(), initiator_s, responder_s);
+        let (resumed_initiator, resumed_responder) = resume::<Blake2b, ed25519::SecretKey>(
+            rng1,
+            rng2,
+            &initiator.resumption_secret(),
+            &responder.resumption_secret(),
+        )
+        .expect("resume the session");
+
+        resumed_initiator.remote_public_identity() == Some(&responder_key)
+            && resumed_responder.remote_public_identity() == Some(&initiator_key)
+            && resumed_initiator.noise_session() != initiator.noise_session()
+    }
+
+    #[quickcheck]
+    fn wrong_secret(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        other: crate::Seed,
+    ) -> bool {
+        let (initiator, _) =
+            previous_session::<Blake2b, _>(rng1.clone(), rng2.clone(), initiator_s, responder_s);
+        let wrong = ResumptionSecret::new(other, None);
+
+        matches!(
+            resume::<Blake2b, ed25519::SecretKey>(
+                rng1,
+                rng2,
+                &initiator.resumption_secret(),
+                &wrong
+            ),
+            Err(HandshakeStateError::Cipher(_))
+        )
+    }
+
+    #[quickcheck]
+    fn payloads(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        secret: crate::Seed,
+        payload_a: Vec<u8>,
+        payload_b: Vec<u8>,
+    ) -> bool {
+        let secret = ResumptionSecret::new(secret, None);
+        let initiator = Resume::<curve25519::SecretKey, Blake2b, _, _>::new(
+            rng1.into_rand_chacha(),
+            &secret,
+            &[],
+        );
+        let responder = Resume::<curve25519::SecretKey, Blake2b, _, _>::new(
+            rng2.into_rand_chacha(),
+            &secret,
+            &[],
+        );
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate_with_payload(&payload_a, &mut output)
+            .expect("initiator sends message A");
+        let mut received_a = Vec::new();
+        let responder = responder
+            .receive_with_payload(output.as_slice(), &mut received_a)
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        responder
+            .reply_with_payload(&payload_b, &mut output)
+            .expect("responder sends message B");
+        let mut received_b = Vec::new();
+        initiator
+            .receive_with_payload(output.as_slice(), &mut received_b)
+            .expect("initiator receives message B");
+
+        payload_a == received_a && payload_b == received_b
+    }
+
+    macro_rules! mk_test {
+        ($name:ident, $sk:ty, $hash:ty) => {
+            #[quickcheck]
+            fn $name(
+                rng1: crate::Seed,
+                rng2: crate::Seed,
+                secret: crate::Seed,
+                messages_init_to_responder: Vec<Vec<u8>>,
+                messages_resp_to_initiator: Vec<Vec<u8>>,
+            ) -> bool {
+                let secret = ResumptionSecret::new(secret, None);
+                let (initiator, responder) =
+                    resume::<$hash, $sk>(rng1, rng2, &secret, &secret).expect("resume the session");
+
+                test_transport::<$hash, _>(
+                    initiator,
+                    responder,
+                    messages_init_to_responder,
+                    messages_resp_to_initiator,
+                )
+            }
+        };
+    }
+
+    mk_test!(curve25519_blake2b, curve25519::SecretKey, Blake2b);
+    mk_test!(curve25519_blake2s, curve25519::SecretKey, Blake2s);
+    mk_test!(ed25519_blake2b, ed25519::SecretKey, Blake2b);
+    mk_test!(ed25519_blake2s, ed25519::SecretKey, Blake2s);
+    mk_test!(
+        ed25519_extended_blake2b,
+        ed25519_extended::SecretKey,
+        Blake2b
+    );
+    mk_test!(
+        ed25519_extended_blake2s,
+        ed25519_extended::SecretKey,
+        Blake2s
+    );
+    mk_test!(ed25519_hd_blake2b, ed25519_hd::SecretKey, Blake2b);
+    mk_test!(ed25519_hd_blake2s, ed25519_hd::SecretKey, Blake2s);
+}
@@ -3,45 +3,82 @@ use std::io::Write;
 use crate::{
     buffer::BufRead,
     hash::Hash,
-    key::{ed25519::PublicKey, Dh},
-    noise::{CipherState, HandshakeState, HandshakeStateError},
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName},
 };
 use rand_core::{CryptoRng, RngCore};
 
 /// One-Way Handshake [**Noise X**]
 ///
 /// [**Noise X**]: https://noiseexplorer.com/patterns/X/
-pub struct X<DH, H, RNG>
+pub struct X<DH, H, RNG, C = ChaChaPoly>
 where
     H: Hash,
+    C: Cipher,
 {
-    inner: HandshakeState<RNG, DH, H>,
+    inner: HandshakeState<RNG, DH, H, C>,
 }
+impl<DH, H, RNG, C> X<DH, H, RNG, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
 impl<DH, H, RNG> X<DH, H, RNG>
 where
     DH: Dh,
     H: Hash,
 {
     pub fn new(rng: RNG, prologue: &[u8]) -> Self {
-        let protocol_name = format!(
-            "Noise_{pattern}_{dh}_{cipher}_{hash}",
-            pattern = "X",
-            dh = DH::name(),
-            cipher = "ChaChaPoly",
-            hash = H::name(),
-        );
+        Self::with_cipher(rng, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> X<DH, H, RNG, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, prologue: &[u8]) -> Self {
+        let protocol_name = ProtocolName::new::<DH, C, H>("X");
 
         Self {
-            inner: HandshakeState::new(rng, prologue, &protocol_name),
+            inner: HandshakeState::new(rng, prologue, protocol_name),
         }
     }
 }
 
-impl<DH, H, RNG> X<DH, H, RNG>
+impl<H, RNG, C> X<curve25519::SecretKey, H, RNG, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> X<DH, H, RNG, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     /// establish a one-way handshake with an already known `PublicIdentity`
     /// and send the given payload too.
@@ -74,10 +111,11 @@ where
     }
 }
 
-impl<DH, H, RNG> X<DH, H, RNG>
+impl<DH, H, RNG, C> X<DH, H, RNG, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     /// receive a one-way handshake with an unknown
     pub fn receive(
@@ -96,7 +134,7 @@ where
         let rs = inner.read_s(&mut input)?;
         inner.dh_sx(s, &rs);
 
-        let mut bytes = vec![0; input.remaining() - CipherState::TAG_LEN];
+        let mut bytes = Vec::with_capacity(input.remaining());
         inner.decrypt_and_hash(&mut input, &mut bytes)?;
 
         Ok((rs, bytes.into_boxed_slice()))
@@ -0,0 +1,550 @@
+use crate::{
+    buffer::{BufRead, OutBuffer},
+    hash::Hash,
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{
+        ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName, TransportState,
+    },
+    seed::Seed,
+};
+use rand_core::{CryptoRng, RngCore};
+use std::io::Write;
+
+/// Interactive Handshake [**Noise XK**]
+///
+/// the initiator knows the responder's public key and sends its own
+/// static key in the last message, encrypted so only the responder
+/// learns it: a passive observer cannot identify the initiator.
+///
+/// [**Noise XK**]: https://noiseexplorer.com/patterns/XK/
+#[allow(clippy::upper_case_acronyms)]
+pub struct XK<DH, H, RNG, S, C = ChaChaPoly>
+where
+    H: Hash,
+    C: Cipher,
+{
+    inner: HandshakeState<RNG, DH, H, C>,
+    state: S,
+}
+
+pub struct A;
+pub struct WaitB {
+    rs: PublicKey,
+}
+pub struct SendB {
+    re: PublicKey,
+}
+pub struct WaitC;
+pub struct SendC {
+    re: PublicKey,
+    rs: PublicKey,
+}
+
+impl<DH, H, RNG, S, C> XK<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
+impl<DH, H, RNG> XK<DH, H, RNG, A>
+where
+    DH: Dh,
+    H: Hash,
+{
+    /// start the handshake, with the optional pre-shared key `psk` the
+    /// handshake is [**Noise XKpsk3**]
+    ///
+    /// both peers need to use the same pre-shared key.
+    ///
+    /// [**Noise XKpsk3**]: https://noiseexplorer.com/patterns/XKpsk3/
+    pub fn new(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, psk, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> XK<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
+        let pattern = if psk.is_some() { "XKpsk3" } else { "XK" };
+
+        let protocol_name = ProtocolName::new::<DH, C, H>(pattern);
+        let mut inner = HandshakeState::new(rng, prologue, protocol_name);
+        inner.set_psk(psk);
+
+        Self { inner, state: A }
+    }
+}
+
+impl<H, RNG, C> XK<curve25519::SecretKey, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> XK<DH, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn initiate(
+        self,
+        rs: PublicKey,
+        output: impl Write,
+    ) -> Result<XK<DH, H, RNG, WaitB, C>, HandshakeStateError> {
+        self.initiate_with_payload(rs, b"", output)
+    }
+
+    /// same as [`initiate`](Self::initiate) but send the given payload
+    /// too. The payload is encrypted but only the responder is
+    /// authenticated.
+    pub fn initiate_with_payload(
+        self,
+        rs: PublicKey,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<XK<DH, H, RNG, WaitB, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A,
+        } = self;
+
+        inner.mix_hash(&rs);
+
+        inner.write_e(&mut output)?;
+        inner.dh_ex(&rs);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        Ok(XK {
+            inner,
+            state: WaitB { rs },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> XK<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(
+        self,
+        s: &DH,
+        input: &[u8],
+    ) -> Result<XK<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        self.receive_with_payload(s, input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// initiator in `payload`
+    pub fn receive_with_payload(
+        self,
+        s: &DH,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<XK<DH, H, RNG, SendB, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A,
+        } = self;
+
+        inner.mix_hash(&s.public());
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+        inner.dh_sx(s, &re);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        Ok(XK {
+            inner,
+            state: SendB { re },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> XK<DH, H, RNG, SendB, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn reply(
+        self,
+        output: impl Write,
+    ) -> Result<XK<DH, H, RNG, WaitC, C>, HandshakeStateError> {
+        self.reply_with_payload(b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted and authenticated.
+    pub fn reply_with_payload(
+        self,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<XK<DH, H, RNG, WaitC, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: SendB { re },
+        } = self;
+
+        inner.write_e(&mut output)?;
+        inner.dh_ex(&re);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        Ok(XK {
+            inner,
+            state: WaitC,
+        })
+    }
+}
+
+impl<DH, H, RNG, C> XK<DH, H, RNG, WaitB, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn remote_public_identity(&self) -> &PublicKey {
+        &self.state.rs
+    }
+
+    pub fn receive(self, input: &[u8]) -> Result<XK<DH, H, RNG, SendC, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// responder in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<XK<DH, H, RNG, SendC, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitB { rs },
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+        inner.dh_ex(&re);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        Ok(XK {
+            inner,
+            state: SendC { re, rs },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> XK<DH, H, RNG, SendC, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn remote_public_identity(&self) -> &PublicKey {
+        &self.state.rs
+    }
+
+    pub fn reply(
+        self,
+        s: &DH,
+        output: impl Write,
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.reply_with_payload(s, b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted and authenticated.
+    pub fn reply_with_payload(
+        self,
+        s: &DH,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: SendC { re, rs },
+        } = self;
+
+        inner.write_s(&s.public(), &mut output)?;
+        inner.dh_sx(s, &re);
+        inner.mix_psk();
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        let (local, remote) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+impl<DH, H, RNG, C> XK<DH, H, RNG, WaitC, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(self, input: &[u8]) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// initiator in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitC,
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let rs = inner.read_s(&mut input)?;
+        inner.dh_ex(&rs);
+        inner.mix_psk();
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        let (remote, local) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::{curve25519, ed25519, ed25519_extended, ed25519_hd},
+        noise::transport_state::tests::test_transport,
+    };
+    use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
+
+    fn establish_handshake<H: Hash, K: Dh>(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: K,
+        responder_s: K,
+    ) -> (TransportState<H>, TransportState<H>) {
+        let initiator_key = initiator_s.public();
+        let responder_key = responder_s.public();
+
+        let initiator = XK::<K, H, _, _>::new(rng1.into_rand_chacha(), &None, &[]);
+        let responder = XK::<K, H, _, _>::new(rng2.into_rand_chacha(), &None, &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate(responder_key, &mut output)
+            .expect("initiator sends message A");
+        let responder = responder
+            .receive(&responder_s, output.as_slice())
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = responder
+            .reply(&mut output)
+            .expect("responder sends message B");
+        let initiator = initiator
+            .receive(output.as_slice())
+            .expect("initiator receives message B");
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .reply(&initiator_s, &mut output)
+            .expect("initiator sends message C");
+        let responder = responder
+            .receive(output.as_slice())
+            .expect("responder receives message C");
+
+        assert_eq!(Some(&initiator_key), responder.remote_public_identity());
+        assert_eq!(Some(&responder_key), initiator.remote_public_identity());
+
+        (initiator, responder)
+    }
+
+    #[quickcheck]
+    fn payloads(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        payloads: (Vec<u8>, Vec<u8>, Vec<u8>),
+    ) -> bool {
+        let (payload_a, payload_b, payload_c) = payloads;
+        let initiator = XK::<_, Blake2b, _, _>::new(rng1.into_rand_chacha(), &None, &[]);
+        let responder = XK::<_, Blake2b, _, _>::new(rng2.into_rand_chacha(), &None, &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate_with_payload(responder_s.public(), &payload_a, &mut output)
+            .expect("initiator sends message A");
+        let mut received_a = Vec::new();
+        let responder = responder
+            .receive_with_payload(&responder_s, output.as_slice(), &mut received_a)
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = responder
+            .reply_with_payload(&payload_b, &mut output)
+            .expect("responder sends message B");
+        let mut received_b = Vec::new();
+        let initiator = initiator
+            .receive_with_payload(output.as_slice(), &mut received_b)
+            .expect("initiator receives message B");
+
+        let mut output = Vec::with_capacity(1024);
+        initiator
+            .reply_with_payload(&initiator_s, &payload_c, &mut output)
+            .expect("initiator sends message C");
+        let mut received_c = Vec::new();
+        responder
+            .receive_with_payload(output.as_slice(), &mut received_c)
+            .expect("responder receives message C");
+
+        payload_a == received_a && payload_b == received_b && payload_c == received_c
+    }
+
+    #[quickcheck]
+    fn psk3(
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        psk: crate::Seed,
+        other: crate::Seed,
+    ) -> bool {
+        use rand::thread_rng;
+
+        let handshake = |initiator_psk: &Option<crate::Seed>,
+                         responder_psk: &Option<crate::Seed>| {
+            let initiator = XK::<_, Blake2b, _, _>::new(thread_rng(), initiator_psk, &[]);
+            let responder = XK::<_, Blake2b, _, _>::new(thread_rng(), responder_psk, &[]);
+
+            let mut a = Vec::new();
+            let initiator = initiator.initiate(responder_s.public(), &mut a)?;
+            let responder = responder.receive(&responder_s, &a)?;
+
+            let mut b = Vec::new();
+            let responder = responder.reply(&mut b)?;
+            let initiator = initiator.receive(&b)?;
+
+            let mut c = Vec::new();
+            let initiator = initiator.reply(&initiator_s, &mut c)?;
+            let responder = responder.receive(&c)?;
+
+            Ok::<_, HandshakeStateError>((initiator, responder))
+        };
+
+        let psk = Some(psk);
+        let established = match handshake(&psk, &psk) {
+            Ok((initiator, responder)) => initiator.noise_session() == responder.noise_session(),
+            Err(_) => false,
+        };
+
+        // the peers need to share the same pre-shared key
+        established && handshake(&psk, &Some(other)).is_err() && handshake(&psk, &None).is_err()
+    }
+
+    #[test]
+    fn wrong_responder() {
+        let mut rng = rand::thread_rng();
+        let responder_s = curve25519::SecretKey::new(&mut rng);
+        let other = curve25519::SecretKey::new(&mut rng);
+
+        let mut output = Vec::new();
+        XK::<curve25519::SecretKey, Blake2b, _, _>::new(&mut rng, &None, &[])
+            .initiate(other.public_key(), &mut output)
+            .unwrap();
+
+        let responder = XK::<_, Blake2b, _, _>::new(&mut rng, &None, &[]);
+        assert!(responder.receive(&responder_s, &output).is_err());
+    }
+
+    macro_rules! mk_test {
+        ($name:ident, $sk:ty, $hash:ty) => {
+            #[quickcheck]
+            fn $name(
+                rng1: crate::Seed,
+                rng2: crate::Seed,
+                initiator_s: $sk,
+                responder_s: $sk,
+                messages_init_to_responder: Vec<Vec<u8>>,
+                messages_resp_to_initiator: Vec<Vec<u8>>,
+            ) -> bool {
+                let (initiator, responder) =
+                    establish_handshake::<$hash, _>(rng1, rng2, initiator_s, responder_s);
+
+                test_transport::<$hash, _>(
+                    initiator,
+                    responder,
+                    messages_init_to_responder,
+                    messages_resp_to_initiator,
+                )
+            }
+        };
+    }
+
+    mk_test!(curve25519_blake2b, curve25519::SecretKey, Blake2b);
+    mk_test!(curve25519_blake2s, curve25519::SecretKey, Blake2s);
+    mk_test!(ed25519_blake2b, ed25519::SecretKey, Blake2b);
+    mk_test!(ed25519_blake2s, ed25519::SecretKey, Blake2s);
+    mk_test!(
+        ed25519_extended_blake2b,
+        ed25519_extended::SecretKey,
+        Blake2b
+    );
+    mk_test!(
+        ed25519_extended_blake2s,
+        ed25519_extended::SecretKey,
+        Blake2s
+    );
+    mk_test!(ed25519_hd_blake2b, ed25519_hd::SecretKey, Blake2b);
+    mk_test!(ed25519_hd_blake2s, ed25519_hd::SecretKey, Blake2s);
+}
@@ -1,10 +1,13 @@
 use std::io::Write;
 
 use crate::{
-    buffer::BufRead,
+    buffer::{BufRead, OutBuffer},
     hash::Hash,
-    key::{ed25519::PublicKey, Dh},
-    noise::{HandshakeState, HandshakeStateError, TransportState},
+    key::{curve25519, ed25519::PublicKey, Dh},
+    noise::{
+        ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName, TransportState,
+    },
+    seed::Seed,
 };
 use rand_core::{CryptoRng, RngCore};
 
@@ -12,11 +15,12 @@ use rand_core::{CryptoRng, RngCore};
 ///
 /// [**Noise XX**]: https://noiseexplorer.com/patterns/XX/
 #[allow(clippy::upper_case_acronyms)]
-pub struct XX<DH, H, RNG, S>
+pub struct XX<DH, H, RNG, S, C = ChaChaPoly>
 where
     H: Hash,
+    C: Cipher,
 {
-    inner: HandshakeState<RNG, DH, H>,
+    inner: HandshakeState<RNG, DH, H, C>,
     state: S,
 }
 
@@ -31,37 +35,81 @@ pub struct SendC {
     rs: PublicKey,
 }
 
+impl<DH, H, RNG, S, C> XX<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
 impl<DH, H, RNG> XX<DH, H, RNG, A>
 where
     DH: Dh,
     H: Hash,
 {
-    pub fn new(rng: RNG, prologue: &[u8]) -> Self {
-        let protocol_name = format!(
-            "Noise_{pattern}_{dh}_{cipher}_{hash}",
-            pattern = "XX",
-            dh = DH::name(),
-            cipher = "ChaChaPoly",
-            hash = H::name(),
-        );
+    /// start the handshake, with the optional pre-shared key `psk` the
+    /// handshake is [**Noise XXpsk3**]
+    ///
+    /// both peers need to use the same pre-shared key.
+    ///
+    /// [**Noise XXpsk3**]: https://noiseexplorer.com/patterns/XXpsk3/
+    pub fn new(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, psk, prologue)
+    }
+}
 
-        Self {
-            inner: HandshakeState::new(rng, prologue, &protocol_name),
-            state: A,
-        }
+impl<DH, H, RNG, C> XX<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, psk: &Option<Seed>, prologue: &[u8]) -> Self {
+        let pattern = if psk.is_some() { "XXpsk3" } else { "XX" };
+
+        let protocol_name = ProtocolName::new::<DH, C, H>(pattern);
+
+        let mut inner = HandshakeState::new(rng, prologue, protocol_name);
+        inner.set_psk(psk);
+
+        Self { inner, state: A }
     }
 }
 
-impl<DH, H, RNG> XX<DH, H, RNG, A>
+impl<H, RNG, C> XX<curve25519::SecretKey, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    H: Hash,
+    C: Cipher,
+{
+    /// send the ephemeral keys as Elligator2 representatives, making
+    /// the handshake messages indistinguishable from random bytes
+    ///
+    /// both peers need to enable it, see [`elligator`](crate::key::elligator)
+    pub fn with_elligator(mut self) -> Self {
+        self.inner.elligator();
+        self
+    }
+}
+
+impl<DH, H, RNG, C> XX<DH, H, RNG, A, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub fn initiate(
         self,
         mut output: impl Write,
-    ) -> Result<XX<DH, H, RNG, WaitB>, HandshakeStateError> {
+    ) -> Result<XX<DH, H, RNG, WaitB, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: A,
@@ -78,12 +126,13 @@ where
     }
 }
 
-impl<DH, H, RNG> XX<DH, H, RNG, A>
+impl<DH, H, RNG, C> XX<DH, H, RNG, A, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
-    pub fn receive(self, input: &[u8]) -> Result<XX<DH, H, RNG, SendB>, HandshakeStateError> {
+    pub fn receive(self, input: &[u8]) -> Result<XX<DH, H, RNG, SendB, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: A,
@@ -102,17 +151,29 @@ where
     }
 }
 
-impl<DH, H, RNG> XX<DH, H, RNG, SendB>
+impl<DH, H, RNG, C> XX<DH, H, RNG, SendB, C>
 where
     RNG: RngCore + CryptoRng,
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
     pub fn reply(
         self,
         s: &DH,
+        output: impl Write,
+    ) -> Result<XX<DH, H, RNG, WaitC, C>, HandshakeStateError> {
+        self.reply_with_payload(s, b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted and authenticated.
+    pub fn reply_with_payload(
+        self,
+        s: &DH,
+        payload: impl AsRef<[u8]>,
         mut output: impl Write,
-    ) -> Result<XX<DH, H, RNG, WaitC>, HandshakeStateError> {
+    ) -> Result<XX<DH, H, RNG, WaitC, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: SendB { re },
@@ -123,7 +184,7 @@ where
         inner.write_s(&s.public(), &mut output)?;
         inner.dh_sx(s, &re);
 
-        inner.encrypt_and_hash(&[], &mut output)?;
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
 
         Ok(XX {
             inner,
@@ -132,12 +193,23 @@ where
     }
 }
 
-impl<DH, H, RNG> XX<DH, H, RNG, WaitB>
+impl<DH, H, RNG, C> XX<DH, H, RNG, WaitB, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
-    pub fn receive(self, input: &[u8]) -> Result<XX<DH, H, RNG, SendC>, HandshakeStateError> {
+    pub fn receive(self, input: &[u8]) -> Result<XX<DH, H, RNG, SendC, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// responder in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<XX<DH, H, RNG, SendC, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: WaitB,
@@ -151,7 +223,7 @@ where
         inner.dh_ex(&rs);
 
         // decode the payload
-        inner.decrypt_and_hash(&mut input, &mut [])?;
+        inner.decrypt_and_hash(&mut input, payload)?;
 
         let state = SendC { rs, re };
 
@@ -159,16 +231,22 @@ where
     }
 }
 
-impl<DH, H, RNG> XX<DH, H, RNG, SendC>
+impl<DH, H, RNG, C> XX<DH, H, RNG, SendC, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
+    /// the responder's static key, to check before replying
+    pub fn remote_public_identity(&self) -> &PublicKey {
+        &self.state.rs
+    }
+
     pub fn reply(
         self,
         s: &DH,
         mut output: impl Write,
-    ) -> Result<TransportState<H>, HandshakeStateError> {
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: SendC { re, rs },
@@ -176,6 +254,7 @@ where
 
         inner.write_s(&s.public(), &mut output)?;
         inner.dh_sx(s, &re);
+        inner.mix_psk();
 
         // encode the payload: put the chain code so the peer can retrieve the public identity
         inner.encrypt_and_hash(&[], &mut output)?;
@@ -186,17 +265,20 @@ where
             inner.symmetric_state().get_handshake_hash().clone(),
             local,
             remote,
-            rs,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
         ))
     }
 }
 
-impl<DH, H, RNG> XX<DH, H, RNG, WaitC>
+impl<DH, H, RNG, C> XX<DH, H, RNG, WaitC, C>
 where
     DH: Dh,
     H: Hash,
+    C: Cipher,
 {
-    pub fn receive(self, input: &[u8]) -> Result<TransportState<H>, HandshakeStateError> {
+    pub fn receive(self, input: &[u8]) -> Result<TransportState<H, C>, HandshakeStateError> {
         let Self {
             mut inner,
             state: WaitC,
@@ -206,6 +288,7 @@ where
 
         let rs = inner.read_s(&mut input)?;
         inner.dh_ex(&rs);
+        inner.mix_psk();
 
         inner.decrypt_and_hash(&mut input, &mut [])?;
 
@@ -215,7 +298,9 @@ where
             inner.symmetric_state().get_handshake_hash().clone(),
             local,
             remote,
-            rs,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
         ))
     }
 }
@@ -241,8 +326,8 @@ mod tests {
         let mut rng1 = rng1.into_rand_chacha();
         let mut rng2 = rng2.into_rand_chacha();
 
-        let initiator = XX::new(&mut rng1, &[]);
-        let responder = XX::new(&mut rng2, &[]);
+        let initiator = XX::new(&mut rng1, &None, &[]);
+        let responder = XX::new(&mut rng2, &None, &[]);
 
         let mut output = Vec::with_capacity(1024);
         let initiator = initiator
@@ -271,8 +356,8 @@ mod tests {
             .receive(input.as_slice())
             .expect("responder receives message C");
 
-        assert_eq!(&initiator_key, responder.remote_public_identity());
-        assert_eq!(&responder_key, initiator.remote_public_identity());
+        assert_eq!(Some(&initiator_key), responder.remote_public_identity());
+        assert_eq!(Some(&responder_key), initiator.remote_public_identity());
 
         (initiator, responder)
     }
@@ -291,7 +376,7 @@ mod tests {
                 let (initiator, responder) =
                     establish_handshake(rng1, rng2, initiator_s, responder_s);
 
-                test_transport::<$hash>(
+                test_transport::<$hash, _>(
                     initiator,
                     responder,
                     messages_init_to_responder,
@@ -424,4 +509,122 @@ mod tests {
         ed25519_hd::SecretKey,
         Blake2s
     );
+
+    #[test]
+    fn elligator() {
+        use rand::thread_rng;
+
+        let initiator_s = curve25519::SecretKey::new(thread_rng());
+        let responder_s = curve25519::SecretKey::new(thread_rng());
+
+        let handshake = |initiator_elligator: bool| {
+            let mut initiator = XX::<_, Blake2s, _, _>::new(thread_rng(), &None, &[]);
+            if initiator_elligator {
+                initiator = initiator.with_elligator();
+            }
+            let responder = XX::<_, Blake2s, _, _>::new(thread_rng(), &None, &[]).with_elligator();
+
+            let mut a = Vec::new();
+            let initiator = initiator.initiate(&mut a).unwrap();
+            let responder = responder.receive(&a).unwrap();
+
+            let mut b = Vec::new();
+            let responder = responder.reply(&responder_s, &mut b).unwrap();
+            let initiator = initiator.receive(&b)?;
+
+            let mut c = Vec::new();
+            let initiator = initiator.reply(&initiator_s, &mut c).unwrap();
+            let responder = responder.receive(&c)?;
+
+            Ok::<_, HandshakeStateError>((initiator, responder))
+        };
+
+        let (initiator, responder) = handshake(true).unwrap();
+        assert_eq!(initiator.noise_session(), responder.noise_session());
+        assert_eq!(
+            responder.remote_public_identity(),
+            Some(&initiator_s.public_key())
+        );
+
+        // the peers need to agree on the encoding of the ephemeral keys
+        assert!(handshake(false).is_err());
+    }
+
+    #[quickcheck]
+    fn responder_payload(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        payload: Vec<u8>,
+    ) -> bool {
+        let initiator = XX::<_, Blake2b, _, _>::new(rng1.into_rand_chacha(), &None, &[]);
+        let responder = XX::<_, Blake2b, _, _>::new(rng2.into_rand_chacha(), &None, &[]);
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .initiate(&mut output)
+            .expect("initiator sends message A");
+        let responder = responder
+            .receive(output.as_slice())
+            .expect("responder receives message A");
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = responder
+            .reply_with_payload(&responder_s, &payload, &mut output)
+            .expect("responder sends message B");
+        let mut received = Vec::new();
+        let initiator = initiator
+            .receive_with_payload(output.as_slice(), &mut received)
+            .expect("initiator receives message B");
+
+        let mut output = Vec::with_capacity(1024);
+        initiator
+            .reply(&initiator_s, &mut output)
+            .expect("initiator sends message C");
+        responder
+            .receive(output.as_slice())
+            .expect("responder receives message C");
+
+        payload == received
+    }
+
+    #[quickcheck]
+    fn psk3(
+        initiator_s: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        psk: crate::Seed,
+        other: crate::Seed,
+    ) -> bool {
+        use rand::thread_rng;
+
+        let handshake = |initiator_psk: &Option<crate::Seed>,
+                         responder_psk: &Option<crate::Seed>| {
+            let initiator = XX::<_, Blake2b, _, _>::new(thread_rng(), initiator_psk, &[]);
+            let responder = XX::<_, Blake2b, _, _>::new(thread_rng(), responder_psk, &[]);
+
+            let mut a = Vec::new();
+            let initiator = initiator.initiate(&mut a)?;
+            let responder = responder.receive(&a)?;
+
+            let mut b = Vec::new();
+            let responder = responder.reply(&responder_s, &mut b)?;
+            let initiator = initiator.receive(&b)?;
+
+            let mut c = Vec::new();
+            let initiator = initiator.reply(&initiator_s, &mut c)?;
+            let responder = responder.receive(&c)?;
+
+            Ok::<_, HandshakeStateError>((initiator, responder))
+        };
+
+        let psk = Some(psk);
+        let established = match handshake(&psk, &psk) {
+            Ok((initiator, responder)) => initiator.noise_session() == responder.noise_session(),
+            Err(_) => false,
+        };
+
+        // the peers need to share the same pre-shared key
+        established && handshake(&psk, &Some(other)).is_err() && handshake(&psk, &None).is_err()
+    }
 }
@@ -0,0 +1,488 @@
+use crate::{
+    buffer::{BufRead, OutBuffer},
+    hash::Hash,
+    key::{ed25519::PublicKey, Dh},
+    noise::{
+        ChaChaPoly, Cipher, HandshakeState, HandshakeStateError, ProtocolName, TransportState,
+    },
+};
+use rand_core::{CryptoRng, RngCore};
+use std::io::Write;
+
+/// Interactive Handshake [**Noise XXfallback**], the second half of
+/// the [Noise Pipes]
+///
+/// The initiator of an [`IK`] handshake uses the static key it has
+/// cached for the responder. If the responder has rotated its static
+/// key it cannot decrypt the [`IK`] message. Instead of failing the
+/// connection, the responder falls back to this handshake reusing the
+/// ephemeral key of the initiator:
+///
+/// * the responder replies to the initial message of the [`IK`]
+///   handshake with [`reply`](XXfallback::reply), sending its new static
+///   key;
+/// * the initiator notices the fallback with
+///   [`IK::receive_with_fallback`] and finishes the handshake with
+///   [`reply`](XXfallback::reply), sending its static key.
+///
+/// The Elligator encoding of the ephemeral keys is not supported.
+///
+/// [**Noise XXfallback**]: https://noiseexplorer.com/patterns/XXfallback/
+/// [Noise Pipes]: http://noiseprotocol.org/noise.html#noise-pipes
+/// [`IK`]: crate::noise::IK
+/// [`IK::receive_with_fallback`]: crate::noise::IK::receive_with_fallback
+#[allow(clippy::upper_case_acronyms)]
+pub struct XXfallback<DH, H, RNG, S, C = ChaChaPoly>
+where
+    H: Hash,
+    C: Cipher,
+{
+    inner: HandshakeState<RNG, DH, H, C>,
+    state: S,
+}
+
+pub struct A;
+pub struct WaitB;
+pub struct SendC {
+    re: PublicKey,
+    rs: PublicKey,
+}
+pub struct WaitC;
+
+const PATTERN: &str = "XXfallback";
+
+impl<DH, H, RNG, S, C> XXfallback<DH, H, RNG, S, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the name of the Noise protocol, mixed in the handshake hash
+    pub fn protocol_name(&self) -> &ProtocolName {
+        self.inner.protocol_name()
+    }
+}
+
+impl<DH, H, RNG> XXfallback<DH, H, RNG, A>
+where
+    DH: Dh,
+    H: Hash,
+{
+    /// the responder's side of the fallback
+    pub fn new(rng: RNG, prologue: &[u8]) -> Self {
+        Self::with_cipher(rng, prologue)
+    }
+}
+
+impl<DH, H, RNG, C> XXfallback<DH, H, RNG, A, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// same as [`new`](Self::new) with the cipher `C` instead of
+    /// [`ChaChaPoly`]
+    pub fn with_cipher(rng: RNG, prologue: &[u8]) -> Self {
+        let protocol_name = ProtocolName::new::<DH, C, H>(PATTERN);
+        Self {
+            inner: HandshakeState::new(rng, prologue, protocol_name),
+            state: A,
+        }
+    }
+}
+
+impl<DH, H, RNG, C> XXfallback<DH, H, RNG, WaitB, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the initiator's side of the fallback, keeping the ephemeral key
+    /// sent in the initial message
+    pub(crate) fn fall_back(inner: HandshakeState<RNG, DH, H, C>, prologue: &[u8]) -> Self {
+        let protocol_name = ProtocolName::new::<DH, C, H>(PATTERN);
+        let mut inner = inner.fallback(prologue, protocol_name);
+        inner.mix_e();
+
+        Self {
+            inner,
+            state: WaitB,
+        }
+    }
+}
+
+impl<DH, H, RNG, C> XXfallback<DH, H, RNG, A, C>
+where
+    RNG: RngCore + CryptoRng,
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// reply to the `initial_message` of an [`IK`](crate::noise::IK)
+    /// handshake we could not decrypt, only the ephemeral key of the
+    /// initiator is read from the message
+    pub fn reply(
+        self,
+        s: &DH,
+        initial_message: &[u8],
+        output: impl Write,
+    ) -> Result<XXfallback<DH, H, RNG, WaitC, C>, HandshakeStateError> {
+        self.reply_with_payload(s, initial_message, b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted but the initiator is not authenticated
+    /// yet.
+    pub fn reply_with_payload(
+        self,
+        s: &DH,
+        initial_message: &[u8],
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<XXfallback<DH, H, RNG, WaitC, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: A,
+        } = self;
+
+        let re = inner.read_e(&mut BufRead::new(initial_message))?;
+
+        inner.write_e(&mut output)?;
+        inner.dh_ex(&re);
+        inner.write_s(&s.public(), &mut output)?;
+        inner.dh_sx(s, &re);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        Ok(XXfallback {
+            inner,
+            state: WaitC,
+        })
+    }
+}
+
+impl<DH, H, RNG, C> XXfallback<DH, H, RNG, WaitB, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(
+        self,
+        input: &[u8],
+    ) -> Result<XXfallback<DH, H, RNG, SendC, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// responder in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<XXfallback<DH, H, RNG, SendC, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitB,
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let re = inner.read_e(&mut input)?;
+        inner.dh_ex(&re);
+        let rs = inner.read_s(&mut input)?;
+        inner.dh_ex(&rs);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        Ok(XXfallback {
+            inner,
+            state: SendC { re, rs },
+        })
+    }
+}
+
+impl<DH, H, RNG, C> XXfallback<DH, H, RNG, SendC, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    /// the new static key of the responder, to check before replying
+    pub fn remote_public_identity(&self) -> &PublicKey {
+        &self.state.rs
+    }
+
+    pub fn reply(
+        self,
+        s: &DH,
+        output: impl Write,
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.reply_with_payload(s, b"", output)
+    }
+
+    /// same as [`reply`](Self::reply) but send the given payload too.
+    /// The payload is encrypted and authenticated.
+    pub fn reply_with_payload(
+        self,
+        s: &DH,
+        payload: impl AsRef<[u8]>,
+        mut output: impl Write,
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: SendC { re, rs },
+        } = self;
+
+        inner.write_s(&s.public(), &mut output)?;
+        inner.dh_sx(s, &re);
+
+        inner.encrypt_and_hash(payload.as_ref(), &mut output)?;
+
+        // the responder of the IK handshake is the initiator of the
+        // XXfallback handshake
+        let (remote, local) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+impl<DH, H, RNG, C> XXfallback<DH, H, RNG, WaitC, C>
+where
+    DH: Dh,
+    H: Hash,
+    C: Cipher,
+{
+    pub fn receive(self, input: &[u8]) -> Result<TransportState<H, C>, HandshakeStateError> {
+        self.receive_with_payload(input, &mut [])
+    }
+
+    /// same as [`receive`](Self::receive) but write the payload of the
+    /// initiator in `payload`
+    pub fn receive_with_payload(
+        self,
+        input: &[u8],
+        payload: &mut (impl OutBuffer + ?Sized),
+    ) -> Result<TransportState<H, C>, HandshakeStateError> {
+        let Self {
+            mut inner,
+            state: WaitC,
+        } = self;
+
+        let mut input = BufRead::new(input);
+
+        let rs = inner.read_s(&mut input)?;
+        inner.dh_ex(&rs);
+
+        inner.decrypt_and_hash(&mut input, payload)?;
+
+        let (local, remote) = inner.symmetric_state().split();
+
+        Ok(TransportState::new(
+            inner.symmetric_state().get_handshake_hash().clone(),
+            local,
+            remote,
+            Some(rs),
+            inner.symmetric_state().resumption_secret(),
+            inner.symmetric_state().exporter_secret(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::{curve25519, ed25519, ed25519_extended, ed25519_hd},
+        noise::{ik::Pipe, transport_state::tests::test_transport, IK},
+    };
+    use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
+
+    /// the initiator uses the `old` key of the responder, the responder
+    /// has rotated to `responder_s`
+    fn establish_handshake<H: Hash, K: Dh>(
+        rng1: crate::Seed,
+        rng2: crate::Seed,
+        initiator_s: K,
+        old: K,
+        responder_s: K,
+    ) -> (TransportState<H>, TransportState<H>) {
+        let initiator_key = initiator_s.public();
+        let responder_key = responder_s.public();
+
+        let mut rng2 = rng2.into_rand_chacha();
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = IK::<K, H, _, _>::new(rng1.into_rand_chacha(), &None, &[])
+            .initiate(&initiator_s, old.public(), &mut output)
+            .expect("initiator sends message A");
+        let initial_message = output;
+        assert!(IK::<K, H, _, _>::new(&mut rng2, &None, &[])
+            .receive(&responder_s, &initial_message)
+            .is_err());
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = XXfallback::<K, H, _, _>::new(&mut rng2, &[])
+            .reply(&responder_s, &initial_message, &mut output)
+            .expect("responder sends message B");
+        let input = output;
+        let initiator = match initiator
+            .receive_with_fallback(&initiator_s, &input, &mut [], &[])
+            .expect("initiator receives message B")
+        {
+            Pipe::Fallback(initiator) => initiator,
+            Pipe::Established(_) => panic!("the responder did not fall back"),
+        };
+        assert_eq!(&responder_key, initiator.remote_public_identity());
+
+        let mut output = Vec::with_capacity(1024);
+        let initiator = initiator
+            .reply(&initiator_s, &mut output)
+            .expect("initiator sends message C");
+        let input = output;
+        let responder = responder
+            .receive(&input)
+            .expect("responder receives message C");
+
+        assert_eq!(Some(&initiator_key), responder.remote_public_identity());
+        assert_eq!(Some(&responder_key), initiator.remote_public_identity());
+
+        (initiator, responder)
+    }
+
+    #[quickcheck]
+    fn payloads(
+        initiator_s: ed25519::SecretKey,
+        old: ed25519::SecretKey,
+        responder_s: ed25519::SecretKey,
+        payload_b: Vec<u8>,
+        payload_c: Vec<u8>,
+    ) -> bool {
+        let mut rng = rand::thread_rng();
+
+        let mut initial_message = Vec::with_capacity(1024);
+        let initiator = IK::<_, Blake2b, _, _>::new(&mut rng, &None, &[])
+            .initiate_with_payload(&initiator_s, old.public(), b"lost", &mut initial_message)
+            .expect("initiator sends message A");
+
+        let mut output = Vec::with_capacity(1024);
+        let responder = XXfallback::<_, Blake2b, _, _>::new(rand::thread_rng(), &[])
+            .reply_with_payload(&responder_s, &initial_message, &payload_b, &mut output)
+            .expect("responder sends message B");
+        let mut received_b = Vec::new();
+        let initiator =
+            match initiator.receive_with_fallback(&initiator_s, &output, &mut received_b, &[]) {
+                Ok(Pipe::Fallback(initiator)) => initiator,
+                _ => return false,
+            };
+
+        let mut output = Vec::with_capacity(1024);
+        initiator
+            .reply_with_payload(&initiator_s, &payload_c, &mut output)
+            .expect("initiator sends message C");
+        let mut received_c = Vec::new();
+        responder
+            .receive_with_payload(&output, &mut received_c)
+            .expect("responder receives message C");
+
+        payload_b == received_b && payload_c == received_c
+    }
+
+    #[test]
+    fn no_fallback() {
+        let mut rng = rand::thread_rng();
+        let initiator_s = curve25519::SecretKey::new(&mut rng);
+        let responder_s = curve25519::SecretKey::new(&mut rng);
+
+        let mut output = Vec::new();
+        let initiator = IK::<curve25519::SecretKey, Blake2b, _, _>::new(&mut rng, &None, &[])
+            .initiate(&initiator_s, responder_s.public_key(), &mut output)
+            .unwrap();
+
+        let mut reply = Vec::new();
+        let responder = IK::<_, Blake2b, _, _>::new(rand::thread_rng(), &None, &[])
+            .receive(&responder_s, &output)
+            .unwrap()
+            .reply(&mut reply)
+            .unwrap();
+
+        match initiator.receive_with_fallback(&initiator_s, &reply, &mut [], &[]) {
+            Ok(Pipe::Established(initiator)) => {
+                assert_eq!(initiator.noise_session(), responder.noise_session())
+            }
+            _ => panic!("expecting the IK handshake to succeed"),
+        }
+    }
+
+    #[test]
+    fn prologue() {
+        let mut rng = rand::thread_rng();
+        let initiator_s = curve25519::SecretKey::new(&mut rng);
+        let old = curve25519::SecretKey::new(&mut rng);
+        let responder_s = curve25519::SecretKey::new(&mut rng);
+
+        let mut initial_message = Vec::new();
+        let initiator = IK::<curve25519::SecretKey, Blake2b, _, _>::new(&mut rng, &None, &[])
+            .initiate(&initiator_s, old.public_key(), &mut initial_message)
+            .unwrap();
+
+        let mut output = Vec::new();
+        XXfallback::<_, Blake2b, _, _>::new(rand::thread_rng(), b"fallback")
+            .reply(&responder_s, &initial_message, &mut output)
+            .unwrap();
+
+        // both peers need to use the same prologue for the fallback
+        assert!(initiator
+            .receive_with_fallback(&initiator_s, &output, &mut [], b"other")
+            .is_err());
+    }
+
+    macro_rules! mk_test {
+        ($name:ident, $sk:ty, $hash:ty) => {
+            #[quickcheck]
+            fn $name(
+                rng1: crate::Seed,
+                rng2: crate::Seed,
+                initiator_s: $sk,
+                old: $sk,
+                responder_s: $sk,
+                messages_init_to_responder: Vec<Vec<u8>>,
+                messages_resp_to_initiator: Vec<Vec<u8>>,
+            ) -> bool {
+                let (initiator, responder) =
+                    establish_handshake::<$hash, _>(rng1, rng2, initiator_s, old, responder_s);
+
+                test_transport::<$hash, _>(
+                    initiator,
+                    responder,
+                    messages_init_to_responder,
+                    messages_resp_to_initiator,
+                )
+            }
+        };
+    }
+
+    mk_test!(curve25519_blake2b, curve25519::SecretKey, Blake2b);
+    mk_test!(curve25519_blake2s, curve25519::SecretKey, Blake2s);
+    mk_test!(ed25519_blake2b, ed25519::SecretKey, Blake2b);
+    mk_test!(ed25519_blake2s, ed25519::SecretKey, Blake2s);
+    mk_test!(
+        ed25519_extended_blake2b,
+        ed25519_extended::SecretKey,
+        Blake2b
+    );
+    mk_test!(
+        ed25519_extended_blake2s,
+        ed25519_extended::SecretKey,
+        Blake2s
+    );
+    mk_test!(ed25519_hd_blake2b, ed25519_hd::SecretKey, Blake2b);
+    mk_test!(ed25519_hd_blake2s, ed25519_hd::SecretKey, Blake2s);
+}
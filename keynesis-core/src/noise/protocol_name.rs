@@ -0,0 +1,235 @@
+use crate::{hash::Hash, key::Dh, noise::Cipher};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use thiserror::Error;
+
+const PREFIX: &str = "Noise";
+
+/// the name of a Noise protocol: `Noise_<pattern>_<dh>_<cipher>_<hash>`
+///
+/// The name is mixed in the handshake hash at the beginning of the
+/// handshake: both peers need to use the same protocol name. It can be
+/// parsed from the name received from a remote peer and checked against
+/// the type parameters of a handshake before starting it:
+///
+/// ```
+/// use keynesis_core::{
+///     hash::Blake2b,
+///     key::curve25519::SecretKey,
+///     noise::{AesGcm, ChaChaPoly, ProtocolName},
+/// };
+///
+/// let name: ProtocolName = "Noise_IK_25519_ChaChaPoly_BLAKE2b".parse().unwrap();
+/// assert_eq!(name.pattern(), "IK");
+///
+/// assert!(name.validate::<SecretKey, ChaChaPoly, Blake2b>().is_ok());
+/// assert!(name.validate::<SecretKey, AesGcm, Blake2b>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtocolName {
+    pattern: String,
+    dh: String,
+    cipher: String,
+    hash: String,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ProtocolNameError {
+    #[error("The protocol name does not start with `{PREFIX}_`")]
+    InvalidPrefix,
+
+    #[error("Expecting `{PREFIX}_<pattern>_<dh>_<cipher>_<hash>`")]
+    InvalidFormat,
+
+    #[error("Unexpected key exchange {found}, expecting {expected}")]
+    UnexpectedDh {
+        expected: &'static str,
+        found: String,
+    },
+
+    #[error("Unexpected cipher {found}, expecting {expected}")]
+    UnexpectedCipher {
+        expected: &'static str,
+        found: String,
+    },
+
+    #[error("Unexpected hash function {found}, expecting {expected}")]
+    UnexpectedHash {
+        expected: &'static str,
+        found: String,
+    },
+}
+
+impl ProtocolName {
+    /// the name of the handshake `pattern` (e.g. `IK` or `XXpsk3`) with
+    /// the given key exchange, cipher and hash function
+    pub fn new<DH, C, H>(pattern: &str) -> Self
+    where
+        DH: Dh,
+        C: Cipher,
+        H: Hash,
+    {
+        Self {
+            pattern: pattern.to_owned(),
+            dh: DH::name().to_owned(),
+            cipher: C::name().to_owned(),
+            hash: H::name().to_owned(),
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn dh(&self) -> &str {
+        &self.dh
+    }
+
+    pub fn cipher(&self) -> &str {
+        &self.cipher
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// check the key exchange, the cipher and the hash function of the
+    /// protocol are the given ones
+    ///
+    /// the pattern is not checked, compare it with [`pattern`](Self::pattern)
+    pub fn validate<DH, C, H>(&self) -> Result<(), ProtocolNameError>
+    where
+        DH: Dh,
+        C: Cipher,
+        H: Hash,
+    {
+        if self.dh != DH::name() {
+            Err(ProtocolNameError::UnexpectedDh {
+                expected: DH::name(),
+                found: self.dh.clone(),
+            })
+        } else if self.cipher != C::name() {
+            Err(ProtocolNameError::UnexpectedCipher {
+                expected: C::name(),
+                found: self.cipher.clone(),
+            })
+        } else if self.hash != H::name() {
+            Err(ProtocolNameError::UnexpectedHash {
+                expected: H::name(),
+                found: self.hash.clone(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/* Format ****************************************************************** */
+
+impl Display for ProtocolName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}_{}_{}",
+            PREFIX, self.pattern, self.dh, self.cipher, self.hash
+        )
+    }
+}
+
+impl FromStr for ProtocolName {
+    type Err = ProtocolNameError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('_');
+        if parts.next() != Some(PREFIX) {
+            return Err(ProtocolNameError::InvalidPrefix);
+        }
+
+        let mut next = || {
+            parts
+                .next()
+                .filter(|part| !part.is_empty())
+                .map(str::to_owned)
+                .ok_or(ProtocolNameError::InvalidFormat)
+        };
+        let name = Self {
+            pattern: next()?,
+            dh: next()?,
+            cipher: next()?,
+            hash: next()?,
+        };
+
+        if parts.next().is_some() {
+            Err(ProtocolNameError::InvalidFormat)
+        } else {
+            Ok(name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hash::{Blake2b, Sha256},
+        key::{curve25519, ed25519},
+        noise::{AesGcm, ChaChaPoly},
+    };
+
+    #[test]
+    fn display_parse() {
+        let name = ProtocolName::new::<curve25519::SecretKey, AesGcm, Sha256>("XXpsk3");
+        let s = name.to_string();
+        assert_eq!(s, "Noise_XXpsk3_25519_AESGCM_SHA256");
+
+        let decoded: ProtocolName = s.parse().unwrap();
+        assert_eq!(decoded, name);
+        assert_eq!(decoded.pattern(), "XXpsk3");
+        assert_eq!(decoded.dh(), "25519");
+        assert_eq!(decoded.cipher(), "AESGCM");
+        assert_eq!(decoded.hash(), "SHA256");
+    }
+
+    #[test]
+    fn invalid_names() {
+        assert!(matches!(
+            "Nois_IK_25519_ChaChaPoly_BLAKE2b".parse::<ProtocolName>(),
+            Err(ProtocolNameError::InvalidPrefix)
+        ));
+        assert!(matches!(
+            "Noise_IK_25519_ChaChaPoly".parse::<ProtocolName>(),
+            Err(ProtocolNameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            "Noise_IK__ChaChaPoly_BLAKE2b".parse::<ProtocolName>(),
+            Err(ProtocolNameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            "Noise_IK_25519_ChaChaPoly_BLAKE2b_".parse::<ProtocolName>(),
+            Err(ProtocolNameError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn validate() {
+        let name = ProtocolName::new::<ed25519::SecretKey, ChaChaPoly, Blake2b>("IK");
+
+        assert!(name
+            .validate::<ed25519::SecretKey, ChaChaPoly, Blake2b>()
+            .is_ok());
+        assert!(matches!(
+            name.validate::<curve25519::SecretKey, ChaChaPoly, Blake2b>(),
+            Err(ProtocolNameError::UnexpectedDh { .. })
+        ));
+        assert!(matches!(
+            name.validate::<ed25519::SecretKey, AesGcm, Blake2b>(),
+            Err(ProtocolNameError::UnexpectedCipher { .. })
+        ));
+        assert!(matches!(
+            name.validate::<ed25519::SecretKey, ChaChaPoly, Sha256>(),
+            Err(ProtocolNameError::UnexpectedHash { .. })
+        ));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{key::ed25519::PublicKey, seed::Seed};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+/// secret shared by the 2 peers of a [`TransportState`] to resume their
+/// session later with the [`Resume`] handshake
+///
+/// the secret is derived from the chaining key of the handshake, it is
+/// never sent on the wire. Knowing the secret is what authenticates the
+/// peers of the resumed session so it needs to be kept as private as a
+/// secret key (the servers may keep it sealed in a ticket they give to
+/// the client instead of storing it).
+///
+/// [`TransportState`]: crate::noise::TransportState
+/// [`Resume`]: crate::noise::Resume
+#[derive(Clone)]
+pub struct ResumptionSecret {
+    secret: Seed,
+    remote_id: Option<PublicKey>,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ResumptionSecretError {
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+}
+
+impl ResumptionSecret {
+    pub(crate) fn new(secret: Seed, remote_id: Option<PublicKey>) -> Self {
+        Self { secret, remote_id }
+    }
+
+    pub(crate) fn secret(&self) -> &Seed {
+        &self.secret
+    }
+
+    /// the public identity of the remote peer of the session this secret
+    /// comes from, the resumed session keeps it
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.remote_id.as_ref()
+    }
+
+    /// encode the secret, followed by the public identity of the remote
+    /// peer if any
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.secret.as_ref().to_vec();
+        if let Some(remote_id) = &self.remote_id {
+            bytes.extend_from_slice(remote_id.as_ref());
+        }
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for ResumptionSecret {
+    type Error = ResumptionSecretError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let remote_id = match bytes.len() {
+            Seed::SIZE => None,
+            len if len == Seed::SIZE + PublicKey::SIZE => Some(
+                PublicKey::try_from(&bytes[Seed::SIZE..])
+                    .map_err(|_| ResumptionSecretError::InvalidEncoding)?,
+            ),
+            _ => return Err(ResumptionSecretError::InvalidEncoding),
+        };
+        let secret = Seed::try_from(&bytes[..Seed::SIZE])
+            .map_err(|_| ResumptionSecretError::InvalidEncoding)?;
+
+        Ok(Self { secret, remote_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::ed25519::SecretKey;
+
+    #[quickcheck]
+    fn encoding(secret: Seed, remote: Option<SecretKey>) -> bool {
+        let secret = ResumptionSecret::new(secret, remote.map(|k| k.public_key()));
+
+        let decoded = ResumptionSecret::try_from(secret.to_bytes().as_slice())
+            .expect("valid encoded resumption secret");
+
+        decoded.to_bytes() == secret.to_bytes()
+    }
+}
@@ -1,20 +1,29 @@
 use crate::{
     hash::Hash,
-    noise::{CipherState, CipherStateError},
+    memsec::Scrubbed as _,
+    noise::{
+        cipher::{ChaChaPoly, Cipher, KEY_LEN},
+        CipherState, CipherStateError,
+    },
+    seed::Seed,
+    OutBuffer,
 };
 use std::fmt;
 
+const EXPORTER_CONTEXT: &[u8] = b"keynesis:exporter";
+
 #[derive(Clone)]
-pub struct SymmetricState<H: Hash> {
-    cipher_state: CipherState,
+pub struct SymmetricState<H: Hash, C = ChaChaPoly> {
+    cipher_state: CipherState<C>,
     ck: H::HASH,
     h: H::HASH,
     hasher: H,
 }
 
-impl<H> fmt::Debug for SymmetricState<H>
+impl<H, C> fmt::Debug for SymmetricState<H, C>
 where
     H: Hash,
+    C: Cipher,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct(&format!("SymmetricState<{}>", H::name()))
@@ -25,9 +34,22 @@ where
     }
 }
 
-impl<H> SymmetricState<H>
+/// the chaining key and the handshake hash are scrubbed (zeroed) before
+/// releasing the memory, the cipher key is scrubbed by the [`CipherState`]
+impl<H, C> Drop for SymmetricState<H, C>
+where
+    H: Hash,
+{
+    fn drop(&mut self) {
+        self.ck.as_mut().scrub();
+        self.h.as_mut().scrub();
+    }
+}
+
+impl<H, C> SymmetricState<H, C>
 where
     H: Hash,
+    C: Cipher,
 {
     pub fn has_key(&self) -> bool {
         self.cipher_state.has_key()
@@ -70,8 +92,8 @@ where
             &mut H::zero_hash(),
         );
 
-        let mut k = [0; CipherState::KEY_LEN];
-        k.copy_from_slice(&temp_k.as_ref()[..CipherState::KEY_LEN]);
+        let mut k = [0; KEY_LEN];
+        k.copy_from_slice(&temp_k.as_ref()[..KEY_LEN]);
         self.cipher_state = CipherState::initialize_key(k);
     }
 
@@ -100,8 +122,8 @@ where
 
         self.mix_hash(&temp_h);
 
-        let mut k = [0; CipherState::KEY_LEN];
-        k.copy_from_slice(&temp_k.as_ref()[..CipherState::KEY_LEN]);
+        let mut k = [0; KEY_LEN];
+        k.copy_from_slice(&temp_k.as_ref()[..KEY_LEN]);
         self.cipher_state = CipherState::initialize_key(k);
     }
 
@@ -112,8 +134,12 @@ where
     pub fn encrypt_and_hash(
         &mut self,
         plaintext: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<usize, CipherStateError> {
+        let len = self.cipher_state.encrypted_len(plaintext.as_ref().len());
+        let output = output
+            .prepare(len)
+            .ok_or(CipherStateError::NotEnoughOutput)?;
         let size = self
             .cipher_state
             .encrypt_with_ad(&self.h, plaintext, output)?;
@@ -124,7 +150,7 @@ where
     pub fn decrypt_and_hash(
         &mut self,
         cipher_text: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<(), CipherStateError> {
         self.cipher_state
             .decrypt_with_ad(&self.h, &cipher_text, output)?;
@@ -132,7 +158,7 @@ where
         Ok(())
     }
 
-    pub fn split(&mut self) -> (CipherState, CipherState) {
+    pub fn split(&mut self) -> (CipherState<C>, CipherState<C>) {
         let mut temp_k1 = H::zero_hash();
         let mut temp_k2 = H::zero_hash();
 
@@ -146,17 +172,109 @@ where
             &mut H::zero_hash(),
         );
 
-        let mut k1 = [0; CipherState::KEY_LEN];
-        k1.copy_from_slice(&temp_k1.as_ref()[..CipherState::KEY_LEN]);
+        let mut k1 = [0; KEY_LEN];
+        k1.copy_from_slice(&temp_k1.as_ref()[..KEY_LEN]);
 
-        let mut k2 = [0; CipherState::KEY_LEN];
-        k2.copy_from_slice(&temp_k2.as_ref()[..CipherState::KEY_LEN]);
+        let mut k2 = [0; KEY_LEN];
+        k2.copy_from_slice(&temp_k2.as_ref()[..KEY_LEN]);
+
+        temp_k1.as_mut().scrub();
+        temp_k2.as_mut().scrub();
 
         (
             CipherState::initialize_key(k1),
             CipherState::initialize_key(k2),
         )
     }
+
+    /// the secret to resume the session later, the third output of the
+    /// HKDF of [`split`](Self::split)
+    ///
+    /// it is derived from the chaining key so only the peers of the
+    /// handshake know it.
+    pub fn resumption_secret(&mut self) -> Seed {
+        let mut temp_k1 = H::zero_hash();
+        let mut temp_k2 = H::zero_hash();
+        let mut temp_k3 = H::zero_hash();
+
+        hkdf(
+            &mut self.hasher,
+            self.ck.as_ref(),
+            &[],
+            Output::Output3,
+            &mut temp_k1,
+            &mut temp_k2,
+            &mut temp_k3,
+        );
+
+        let mut secret = [0; Seed::SIZE];
+        secret.copy_from_slice(&temp_k3.as_ref()[..Seed::SIZE]);
+
+        temp_k1.as_mut().scrub();
+        temp_k2.as_mut().scrub();
+        temp_k3.as_mut().scrub();
+
+        Seed::from(secret)
+    }
+
+    /// the secret to export keying material from the session, see
+    /// [`TransportState::export_secret`](crate::noise::TransportState::export_secret)
+    ///
+    /// it is derived from the chaining key with its own input so it is
+    /// independent of the keys of the session and of the
+    /// [`resumption_secret`](Self::resumption_secret).
+    pub fn exporter_secret(&mut self) -> Seed {
+        let mut temp_k1 = H::zero_hash();
+        let mut temp_k2 = H::zero_hash();
+        let mut temp_k3 = H::zero_hash();
+
+        hkdf(
+            &mut self.hasher,
+            self.ck.as_ref(),
+            EXPORTER_CONTEXT,
+            Output::Output1,
+            &mut temp_k1,
+            &mut temp_k2,
+            &mut temp_k3,
+        );
+
+        let mut secret = [0; Seed::SIZE];
+        secret.copy_from_slice(&temp_k1.as_ref()[..Seed::SIZE]);
+
+        temp_k1.as_mut().scrub();
+
+        Seed::from(secret)
+    }
+}
+
+/// expand the pseudo random `key` into `output` (the HKDF-Expand of
+/// [RFC 5869](https://www.rfc-editor.org/rfc/rfc5869))
+///
+/// # Panics
+///
+/// if `output` is longer than 255 times the length of the hash
+pub(crate) fn hkdf_expand<H: Hash>(key: &[u8], info: &[u8], output: &mut [u8]) {
+    assert!(
+        output.len() <= 255 * H::HASH_LEN,
+        "cannot expand more than 255 blocks"
+    );
+
+    let mut hasher = H::hasher();
+    let mut block = H::zero_hash();
+    let mut data = Vec::with_capacity(H::HASH_LEN + info.len());
+
+    for (i, chunk) in output.chunks_mut(H::HASH_LEN).enumerate() {
+        data.extend_from_slice(info);
+        hmac(&mut hasher, key, &data, Some(&[i as u8 + 1]), &mut block);
+        chunk.copy_from_slice(&block.as_ref()[..chunk.len()]);
+
+        data.as_mut_slice().scrub();
+        data.clear();
+        data.extend_from_slice(block.as_ref());
+    }
+
+    data.as_mut_slice().scrub();
+    block.as_mut().scrub();
 }
 
 fn hmac<H: Hash>(hasher: &mut H, key: &[u8], data: &[u8], extra: Option<&[u8]>, out: &mut H::HASH) {
@@ -190,6 +308,10 @@ fn hmac<H: Hash>(hasher: &mut H, key: &[u8], data: &[u8], extra: Option<&[u8]>,
     hasher.input(&out_pad);
     hasher.input(&inner);
     hasher.result(out);
+
+    inner.as_mut().scrub();
+    inner_pad.as_mut().scrub();
+    out_pad.as_mut().scrub();
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -214,34 +336,32 @@ fn hkdf<H: Hash>(
     hmac(hasher, chaining_key, input_key_material, None, &mut tmp_key);
 
     hmac(hasher, tmp_key.as_ref(), &[1u8], None, output1);
-    if output == Output1 {
-        return;
+    if output != Output1 {
+        hmac(
+            hasher,
+            tmp_key.as_ref(),
+            output1.as_ref(),
+            Some(&[0x02]),
+            output2,
+        );
     }
-
-    hmac(
-        hasher,
-        tmp_key.as_ref(),
-        output1.as_ref(),
-        Some(&[0x02]),
-        output2,
-    );
-    if output == Output2 {
-        return;
+    if output == Output3 {
+        hmac(
+            hasher,
+            tmp_key.as_ref(),
+            output2.as_ref(),
+            Some(&[0x03]),
+            output3,
+        );
     }
 
-    hmac(
-        hasher,
-        tmp_key.as_ref(),
-        output2.as_ref(),
-        Some(&[0x03]),
-        output3,
-    );
-    debug_assert_eq!(output, Output3);
+    tmp_key.as_mut().scrub();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::noise::cipher::TAG_LEN;
     use cryptoxide::blake2s::Blake2s;
     use noiseexplorer_ik::state::SymmetricState as SymmetricStateRef;
 
@@ -327,8 +447,8 @@ mod tests {
         assert_eq!(ours.h, theirs.h.as_bytes(),);
         assert_eq!(ours.ck, theirs.ck.as_bytes(),);
         assert_eq!(
-            &our_output[..PLAINTEXT.len() + CipherState::TAG_LEN],
-            &their_output[..PLAINTEXT.len() + CipherState::TAG_LEN],
+            &our_output[..PLAINTEXT.len() + TAG_LEN],
+            &their_output[..PLAINTEXT.len() + TAG_LEN],
             "encrypted data should be the same"
         );
 
@@ -339,7 +459,7 @@ mod tests {
 
         let mut plaintext = [0; 10];
         decrypt_ours
-            .decrypt_and_hash(&our_output, &mut plaintext)
+            .decrypt_and_hash(our_output, &mut plaintext)
             .unwrap();
         assert_eq!(decrypt_ours.h, decrypt_theirs.h.as_bytes(),);
         assert_eq!(decrypt_ours.ck, decrypt_theirs.ck.as_bytes(),);
@@ -0,0 +1,315 @@
+use crate::{
+    hash::Hash,
+    key::{
+        ed25519::{PublicKey, Signature},
+        ed25519_extended::SecretKey,
+    },
+};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+const TRANSCRIPT_CONTEXT: &[u8] = b"keynesis:transcript";
+const SIGNATURE_CONTEXT: &[u8] = b"keynesis:transcript:summary";
+
+/// running hash chain over the digests of the messages of one direction
+/// of a session
+///
+/// each link of the chain is the hash of the previous link, the index
+/// of the message and the hash of the message (the plaintext). The
+/// messages are not kept: to prove a message has been sent its content
+/// and the other messages of the direction need to be revealed (see
+/// [`Transcript::replay`]).
+#[derive(Clone)]
+pub struct Transcript<H: Hash> {
+    chain: H::HASH,
+    count: u64,
+}
+
+/// the summary of the transcripts of the 2 directions of a session
+///
+/// the summary [`sign`](Self::sign)ed by each peer can be compared to
+/// resolve a dispute: the messages sent by one of the peers are the
+/// messages received by the other.
+#[derive(Clone)]
+pub struct TranscriptSummary<H: Hash> {
+    session: H::HASH,
+    sent: (u64, H::HASH),
+    received: (u64, H::HASH),
+}
+
+/// a [`TranscriptSummary`] signed by one of the peers
+#[derive(Clone)]
+pub struct SignedTranscript<H: Hash> {
+    summary: TranscriptSummary<H>,
+    signer: PublicKey,
+    signature: Signature,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum TranscriptError {
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+
+    #[error("Invalid signature of the transcript summary")]
+    InvalidSignature,
+}
+
+impl<H: Hash> Transcript<H> {
+    /// start the transcript of one direction of the session
+    pub(crate) fn new(session: &H::HASH) -> Self {
+        let mut hasher = H::hasher();
+        hasher.input(TRANSCRIPT_CONTEXT);
+        hasher.input(session);
+
+        let mut chain = H::zero_hash();
+        hasher.result(&mut chain);
+
+        Self { chain, count: 0 }
+    }
+
+    /// restore a transcript from its [`chain`](Self::chain) and
+    /// [`count`](Self::count)
+    pub(crate) fn from_parts(chain: H::HASH, count: u64) -> Self {
+        Self { chain, count }
+    }
+
+    /// recompute the transcript of the given messages, in order
+    pub fn replay<I>(session: &H::HASH, messages: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut transcript = Self::new(session);
+        for message in messages {
+            transcript.record(message.as_ref());
+        }
+        transcript
+    }
+
+    pub(crate) fn record(&mut self, message: &[u8]) {
+        let mut digest = H::zero_hash();
+        let mut hasher = H::hasher();
+        hasher.input(message);
+        hasher.result(&mut digest);
+
+        hasher.reset();
+        hasher.input(&self.chain);
+        hasher.input(self.count.to_be_bytes());
+        hasher.input(&digest);
+        hasher.result(&mut self.chain);
+
+        self.count += 1;
+    }
+
+    /// number of messages recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// the last link of the chain
+    pub fn chain(&self) -> &H::HASH {
+        &self.chain
+    }
+}
+
+impl<H: Hash> TranscriptSummary<H> {
+    /// size of the encoded summary
+    pub const SIZE: usize = 3 * H::HASH_LEN + 2 * 8;
+
+    /// combine the transcripts of the 2 directions, for example of the
+    /// 2 halves of a split [`TransportState`](super::TransportState)
+    pub fn new(session: &H::HASH, sent: &Transcript<H>, received: &Transcript<H>) -> Self {
+        Self {
+            session: session.clone(),
+            sent: (sent.count, sent.chain.clone()),
+            received: (received.count, received.chain.clone()),
+        }
+    }
+
+    pub fn session(&self) -> &H::HASH {
+        &self.session
+    }
+
+    pub fn count_sent(&self) -> u64 {
+        self.sent.0
+    }
+
+    pub fn sent(&self) -> &H::HASH {
+        &self.sent.1
+    }
+
+    pub fn count_received(&self) -> u64 {
+        self.received.0
+    }
+
+    pub fn received(&self) -> &H::HASH {
+        &self.received.1
+    }
+
+    /// check the messages sent by the peer are the messages received
+    /// by the owner of this summary, and the opposite
+    pub fn matches(&self, peer: &Self) -> bool {
+        self.session.as_ref() == peer.session.as_ref()
+            && self.sent.0 == peer.received.0
+            && self.sent.1.as_ref() == peer.received.1.as_ref()
+            && self.received.0 == peer.sent.0
+            && self.received.1.as_ref() == peer.sent.1.as_ref()
+    }
+
+    /// sign the summary with the static key used in the session
+    pub fn sign(self, key: &SecretKey) -> SignedTranscript<H> {
+        let signature = key.sign(self.signed_message());
+
+        SignedTranscript {
+            summary: self,
+            signer: key.public_key(),
+            signature,
+        }
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        let mut message = SIGNATURE_CONTEXT.to_vec();
+        message.extend_from_slice(&self.to_bytes());
+        message
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        bytes.extend_from_slice(self.session.as_ref());
+        bytes.extend_from_slice(&self.sent.0.to_be_bytes());
+        bytes.extend_from_slice(self.sent.1.as_ref());
+        bytes.extend_from_slice(&self.received.0.to_be_bytes());
+        bytes.extend_from_slice(self.received.1.as_ref());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        debug_assert_eq!(bytes.len(), Self::SIZE);
+
+        let hash = |bytes: &[u8]| {
+            let mut hash = H::zero_hash();
+            hash.as_mut().copy_from_slice(bytes);
+            hash
+        };
+        let count = |bytes: &[u8]| u64::from_be_bytes(<[u8; 8]>::try_from(bytes).unwrap());
+
+        let (session, bytes) = bytes.split_at(H::HASH_LEN);
+        let (sent_count, bytes) = bytes.split_at(8);
+        let (sent, bytes) = bytes.split_at(H::HASH_LEN);
+        let (received_count, received) = bytes.split_at(8);
+
+        Self {
+            session: hash(session),
+            sent: (count(sent_count), hash(sent)),
+            received: (count(received_count), hash(received)),
+        }
+    }
+}
+
+impl<H: Hash> SignedTranscript<H> {
+    /// size of the encoded signed summary
+    pub const SIZE: usize = TranscriptSummary::<H>::SIZE + PublicKey::SIZE + Signature::SIZE;
+
+    pub fn summary(&self) -> &TranscriptSummary<H> {
+        &self.summary
+    }
+
+    /// the public key of the peer that signed the summary
+    pub fn signer(&self) -> &PublicKey {
+        &self.signer
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    pub fn verify(&self) -> bool {
+        self.signer
+            .verify(self.summary.signed_message(), &self.signature)
+    }
+
+    /// encode the signed summary: the summary, the public key of the
+    /// signer and the signature
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.summary.to_bytes();
+        bytes.extend_from_slice(self.signer.as_ref());
+        bytes.extend_from_slice(self.signature.as_ref());
+        bytes
+    }
+}
+
+impl<'a, H: Hash> TryFrom<&'a [u8]> for SignedTranscript<H> {
+    type Error = TranscriptError;
+
+    /// decode the signed summary, the signature is verified
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != Self::SIZE {
+            return Err(TranscriptError::InvalidEncoding);
+        }
+
+        let (summary, bytes) = bytes.split_at(TranscriptSummary::<H>::SIZE);
+        let (signer, signature) = bytes.split_at(PublicKey::SIZE);
+
+        let signed = Self {
+            summary: TranscriptSummary::from_bytes(summary),
+            signer: PublicKey::try_from(signer).map_err(|_| TranscriptError::InvalidEncoding)?,
+            signature: Signature::try_from(signature)
+                .map_err(|_| TranscriptError::InvalidEncoding)?,
+        };
+
+        if signed.verify() {
+            Ok(signed)
+        } else {
+            Err(TranscriptError::InvalidSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::Blake2b;
+
+    #[test]
+    fn replay() {
+        let session = [1; 64];
+        let mut transcript = Transcript::<Blake2b>::new(&session);
+        transcript.record(b"hello");
+        transcript.record(b"world");
+
+        let replayed = Transcript::<Blake2b>::replay(&session, [b"hello", b"world"]);
+        assert_eq!(replayed.count(), 2);
+        assert_eq!(replayed.chain(), transcript.chain());
+
+        let reordered = Transcript::<Blake2b>::replay(&session, [b"world", b"hello"]);
+        assert_ne!(reordered.chain(), transcript.chain());
+
+        let other_session = Transcript::<Blake2b>::replay(&[2; 64], [b"hello", b"world"]);
+        assert_ne!(other_session.chain(), transcript.chain());
+    }
+
+    #[test]
+    fn signed_summary() {
+        let key = SecretKey::generate();
+        let session = [1; 64];
+        let sent = Transcript::<Blake2b>::replay(&session, [b"ping"]);
+        let received = Transcript::<Blake2b>::replay(&session, [b"pong", b"pong"]);
+
+        let signed = TranscriptSummary::new(&session, &sent, &received).sign(&key);
+        assert!(signed.verify());
+
+        let bytes = signed.to_bytes();
+        let decoded = SignedTranscript::<Blake2b>::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(decoded.signer(), &key.public_key());
+        assert_eq!(decoded.summary().count_received(), 2);
+        assert!(!decoded.summary().matches(signed.summary()));
+
+        let mut tampered = bytes;
+        tampered[64 + 7] ^= 1;
+        assert!(matches!(
+            SignedTranscript::<Blake2b>::try_from(tampered.as_slice()),
+            Err(TranscriptError::InvalidSignature)
+        ));
+    }
+}
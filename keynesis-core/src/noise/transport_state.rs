@@ -1,8 +1,133 @@
 use crate::{
+    buffer::BufRead,
     hash::Hash,
     key::ed25519::PublicKey,
-    noise::{CipherState, CipherStateError},
+    memsec::Scrubbed as _,
+    noise::{
+        cipher::{ChaChaPoly, Cipher, KEY_LEN, TAG_LEN},
+        cipher_state::Nonce,
+        symmetric_state::hkdf_expand,
+        CipherState, CipherStateError, DatagramReceiveHalf, DatagramSendHalf, ResumptionSecret,
+        Transcript, TranscriptSummary,
+    },
+    seed::Seed,
+    OutBuffer,
 };
+use rand_core::{CryptoRng, RngCore};
+use thiserror::Error;
+
+/// version of the snapshots of [`TransportState::suspend`]
+const SNAPSHOT_VERSION: u8 = 1;
+const SNAPSHOT_SALT_LEN: usize = 32;
+const SNAPSHOT_CONTEXT: &[u8] = b"keynesis:snapshot";
+
+/// maximum length of a noise message, see
+/// [`TransportState::send_chunked`]
+pub const MAX_MESSAGE_LEN: usize = 65535;
+
+/// maximum length of the payload of a chunk, one byte is used for the
+/// last chunk flag
+const MAX_CHUNK_LEN: usize = MAX_MESSAGE_LEN - TAG_LEN - 1;
+const CHUNK_MORE: u8 = 1;
+const CHUNK_LAST: u8 = 0;
+
+/// when the keys of the [`TransportState`] are rotated (see
+/// [`CipherState::rekey`])
+///
+/// rekeying after every message gives the strongest forward secrecy:
+/// a compromised key cannot decrypt the previous messages. High
+/// throughput streams can rekey less often.
+///
+/// Both peers need to use the same policy from the first message. Send
+/// the [`to_bytes`](Self::to_bytes) of the policy in an (authenticated)
+/// handshake payload and set it with
+/// [`TransportState::agree_rekey_policy`], which fails with a
+/// [`RekeyPolicyError::Mismatch`] if the peers disagree. Otherwise a peer
+/// using a different policy only fails to decrypt (invalid tag) the first
+/// message after the keys diverged. Time based rotation is not possible
+/// at this level since the receiver cannot know when the sender rotated,
+/// rotate the session with a new handshake instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RekeyPolicy {
+    /// rekey after every message
+    #[default]
+    EveryMessage,
+    /// rekey after the given number of messages
+    Messages(u64),
+    /// rekey once at least the given number of bytes (of plaintext)
+    /// have been sent or received since the last rekey
+    Bytes(u64),
+    /// never rekey, the keys of the handshake are used for the whole
+    /// session
+    Never,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum RekeyPolicyError {
+    #[error("Invalid rekey policy encoding")]
+    InvalidEncoding,
+
+    #[error("The remote peer rekeys {remote:?}, expected {local:?}")]
+    Mismatch {
+        local: RekeyPolicy,
+        remote: RekeyPolicy,
+    },
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Invalid snapshot, it was not suspended with this key, hash or cipher")]
+    InvalidTag,
+
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+}
+
+impl RekeyPolicy {
+    /// the encoded size of the [`RekeyPolicy`]
+    pub const SIZE: usize = 1 + 8;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let (policy, value) = match self {
+            Self::EveryMessage => (0, 0),
+            Self::Messages(n) => (1, n),
+            Self::Bytes(n) => (2, n),
+            Self::Never => (3, 0),
+        };
+
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = policy;
+        bytes[1..].copy_from_slice(&value.to_be_bytes());
+        bytes
+    }
+
+    /// `None` if the `bytes` are not a valid encoding
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Option<Self> {
+        let mut value = [0; 8];
+        value.copy_from_slice(&bytes[1..]);
+        match (bytes[0], u64::from_be_bytes(value)) {
+            (0, 0) => Some(Self::EveryMessage),
+            (1, n) => Some(Self::Messages(n)),
+            (2, n) => Some(Self::Bytes(n)),
+            (3, 0) => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+/// the [`RekeyPolicy`] of one direction and what has been sent or
+/// received since the last rekey
+#[derive(Debug, Clone, Default)]
+struct Rekey {
+    policy: RekeyPolicy,
+    messages: u64,
+    bytes: u64,
+}
 
 /// Noise transport session between 2 participant. Communication is
 /// Asymmetric. So it is possible to send messages independently from
@@ -11,75 +136,358 @@ use crate::{
 /// messages.
 ///
 /// All messages are authenticated and because we are rekeying after
-/// each messages we have strong forward secrecy.
-pub struct TransportState<H: Hash> {
+/// each messages we have strong forward secrecy. The rekeying can be
+/// made less frequent with a [`RekeyPolicy`].
+///
+/// The session can optionally keep a [`Transcript`] of the messages sent
+/// and received (see [`enable_transcript`](Self::enable_transcript)).
+/// It can be [`suspend`](Self::suspend)ed to survive a restart of the
+/// process.
+pub struct TransportState<H: Hash, C = ChaChaPoly> {
     handshake_hash: H::HASH,
-    local: CipherState,
-    remote: CipherState,
-    remote_id: PublicKey,
+    local: CipherState<C>,
+    local_rekey: Rekey,
+    remote: CipherState<C>,
+    remote_rekey: Rekey,
+    remote_id: Option<PublicKey>,
+    transcripts: Option<(Transcript<H>, Transcript<H>)>,
+    resumption: Seed,
+    exporter: Seed,
 }
 
-pub struct TransportSendHalf<H: Hash> {
+pub struct TransportSendHalf<H: Hash, C = ChaChaPoly> {
     handshake_hash: H::HASH,
-    local: CipherState,
-    remote_id: PublicKey,
+    local: CipherState<C>,
+    rekey: Rekey,
+    remote_id: Option<PublicKey>,
+    transcript: Option<Transcript<H>>,
 }
 
-pub struct TransportReceiveHalf<H: Hash> {
+pub struct TransportReceiveHalf<H: Hash, C = ChaChaPoly> {
     handshake_hash: H::HASH,
-    remote: CipherState,
-    remote_id: PublicKey,
+    remote: CipherState<C>,
+    rekey: Rekey,
+    remote_id: Option<PublicKey>,
+    transcript: Option<Transcript<H>>,
 }
 
-impl<H: Hash> TransportState<H> {
+impl<H: Hash, C: Cipher> TransportState<H, C> {
     pub(crate) fn new(
         handshake_hash: H::HASH,
-        local: CipherState,
-        remote: CipherState,
-        remote_id: PublicKey,
+        local: CipherState<C>,
+        remote: CipherState<C>,
+        remote_id: Option<PublicKey>,
+        resumption: Seed,
+        exporter: Seed,
     ) -> Self {
         TransportState {
             handshake_hash,
             local,
+            local_rekey: Rekey::default(),
             remote,
+            remote_rekey: Rekey::default(),
             remote_id,
+            transcripts: None,
+            resumption,
+            exporter,
         }
     }
 
+    /// set when the keys are rotated, see [`RekeyPolicy`]
+    ///
+    /// the remote peer needs to set the same policy before the first
+    /// message.
+    pub fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.local_rekey.policy = policy;
+        self.remote_rekey.policy = policy;
+    }
+
+    pub fn rekey_policy(&self) -> RekeyPolicy {
+        self.local_rekey.policy
+    }
+
+    /// set the `policy` after checking the remote peer uses the same one
+    ///
+    /// `remote` is the encoded policy (see [`RekeyPolicy::to_bytes`])
+    /// the remote peer sent in an authenticated payload of the handshake.
+    /// The policy is left unchanged on error.
+    pub fn agree_rekey_policy(
+        &mut self,
+        policy: RekeyPolicy,
+        remote: &[u8],
+    ) -> Result<(), RekeyPolicyError> {
+        let remote = <[u8; RekeyPolicy::SIZE]>::try_from(remote)
+            .ok()
+            .and_then(RekeyPolicy::from_bytes)
+            .ok_or(RekeyPolicyError::InvalidEncoding)?;
+        if remote != policy {
+            return Err(RekeyPolicyError::Mismatch {
+                local: policy,
+                remote,
+            });
+        }
+
+        self.set_rekey_policy(policy);
+        Ok(())
+    }
+
+    /// start recording the [`Transcript`]s of the messages sent and
+    /// received
+    ///
+    /// the remote peer needs to enable its transcripts before the first
+    /// message too, otherwise the summaries of the 2 peers will not
+    /// match. Does nothing if the transcripts are already enabled.
+    pub fn enable_transcript(&mut self) {
+        if self.transcripts.is_none() {
+            self.transcripts = Some((
+                Transcript::new(&self.handshake_hash),
+                Transcript::new(&self.handshake_hash),
+            ));
+        }
+    }
+
+    /// the summary of the transcripts, if they are enabled
+    pub fn transcript(&self) -> Option<TranscriptSummary<H>> {
+        self.transcripts
+            .as_ref()
+            .map(|(sent, received)| TranscriptSummary::new(&self.handshake_hash, sent, received))
+    }
+
     /// split the transport state into a sending half and receiving half
     ///
     /// this is to make it easier to handle bidirectional connections
     /// asynchronously.
-    pub fn split(self) -> (TransportSendHalf<H>, TransportReceiveHalf<H>) {
+    pub fn split(self) -> (TransportSendHalf<H, C>, TransportReceiveHalf<H, C>) {
         let Self {
             handshake_hash,
             local,
+            local_rekey,
             remote,
+            remote_rekey,
             remote_id,
+            transcripts,
+            resumption: _,
+            exporter: _,
         } = self;
+        let (sent, received) = transcripts.unzip();
         let send = TransportSendHalf {
             handshake_hash: handshake_hash.clone(),
             local,
+            rekey: local_rekey,
             remote_id,
+            transcript: sent,
         };
 
         let receive = TransportReceiveHalf {
             handshake_hash,
             remote,
+            rekey: remote_rekey,
             remote_id,
+            transcript: received,
         };
 
         (send, receive)
     }
 
+    /// switch to the datagram mode where every message carries its
+    /// nonce so the messages can be lost or received in any order (see
+    /// [`DatagramReceiveHalf`])
+    ///
+    /// both peers need to switch before sending their first message.
+    /// The [`RekeyPolicy`] and the transcripts do not apply to the
+    /// datagrams, the keys of the handshake are used for the whole
+    /// session.
+    pub fn into_datagram(self) -> (DatagramSendHalf<H, C>, DatagramReceiveHalf<H, C>) {
+        let Self {
+            handshake_hash,
+            local,
+            remote,
+            remote_id,
+            ..
+        } = self;
+
+        (
+            DatagramSendHalf::new(handshake_hash.clone(), local, remote_id),
+            DatagramReceiveHalf::new(handshake_hash, remote, remote_id),
+        )
+    }
+
     /// unique identifier of the noise session
     pub fn noise_session(&self) -> &H::HASH {
         &self.handshake_hash
     }
 
+    /// derive `len` bytes of keying material from the session for the
+    /// given `label` and `context` (like the exporters of
+    /// [RFC 5705](https://www.rfc-editor.org/rfc/rfc5705))
+    ///
+    /// both peers derive the same secret, bound to the handshake hash.
+    /// It is independent of the keys of the session: the higher layers
+    /// can key their own MACs or use it as a channel binding token.
+    /// Different labels give unrelated secrets.
+    ///
+    /// # Panics
+    ///
+    /// if `len` is longer than 255 times the length of the hash `H`
+    pub fn export_secret(&self, label: &[u8], context: &[u8], len: usize) -> Vec<u8> {
+        let mut info = Vec::with_capacity(H::HASH_LEN + label.len() + context.len() + 16);
+        info.extend_from_slice(self.handshake_hash.as_ref());
+        info.extend_from_slice(&(label.len() as u32).to_be_bytes());
+        info.extend_from_slice(label);
+        info.extend_from_slice(&(context.len() as u32).to_be_bytes());
+        info.extend_from_slice(context);
+        info.extend_from_slice(&(len as u32).to_be_bytes());
+
+        let mut output = vec![0; len];
+        hkdf_expand::<H>(self.exporter.as_ref(), &info, &mut output);
+        output
+    }
+
+    /// the secret to resume this session later without a full
+    /// handshake, see [`Resume`](crate::noise::Resume)
+    ///
+    /// both peers get the same secret, it does not change during the
+    /// session. Resuming the session gives a new secret.
+    pub fn resumption_secret(&self) -> ResumptionSecret {
+        ResumptionSecret::new(self.resumption.clone(), self.remote_id)
+    }
+
+    /// suspend the session: encrypt its state with the `key` so it can
+    /// be stored and [`resume`](Self::resume)d later, for example by a
+    /// new process
+    ///
+    /// the snapshot contains the keys and the nonces of the session, it
+    /// needs to be kept as private as the `key`. The session is consumed:
+    /// the snapshot must be resumed only once, resuming it twice would
+    /// reuse the nonces.
+    pub fn suspend<RNG>(self, mut rng: RNG, key: &[u8; KEY_LEN]) -> Vec<u8>
+    where
+        RNG: RngCore + CryptoRng,
+    {
+        let mut snapshot = vec![SNAPSHOT_VERSION; 1 + SNAPSHOT_SALT_LEN];
+        rng.fill_bytes(&mut snapshot[1..]);
+        let (header, salt) = snapshot.split_at(1);
+        let mut k = snapshot_key::<H, C>(key, header[0], salt);
+
+        let mut state = self.to_snapshot();
+        let tag = C::encrypt(&k, 0, &snapshot, &mut state);
+        snapshot.extend_from_slice(&state);
+        snapshot.extend_from_slice(&tag);
+
+        state.as_mut_slice().scrub();
+        k.scrub();
+        snapshot
+    }
+
+    /// resume a session [`suspend`](Self::suspend)ed with the same `key`
+    ///
+    /// the hash `H` and the cipher `C` need to be the ones of the
+    /// suspended session.
+    pub fn resume(key: &[u8; KEY_LEN], snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        const HEADER_LEN: usize = 1 + SNAPSHOT_SALT_LEN;
+
+        let version = *snapshot.first().ok_or(SnapshotError::InvalidEncoding)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        if snapshot.len() < HEADER_LEN + TAG_LEN {
+            return Err(SnapshotError::InvalidEncoding);
+        }
+
+        let (header, state) = snapshot.split_at(HEADER_LEN);
+        let (state, tag) = state.split_at(state.len() - TAG_LEN);
+        let mut tag_bytes = [0; TAG_LEN];
+        tag_bytes.copy_from_slice(tag);
+
+        let mut k = snapshot_key::<H, C>(key, version, &header[1..]);
+        let mut state = state.to_vec();
+        let valid = C::decrypt(&k, 0, header, &mut state, &tag_bytes);
+        k.scrub();
+        if !valid {
+            return Err(SnapshotError::InvalidTag);
+        }
+
+        let resumed = Self::from_snapshot(&state);
+        state.as_mut_slice().scrub();
+        resumed
+    }
+
+    fn to_snapshot(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(512);
+        state.extend_from_slice(self.handshake_hash.as_ref());
+        write_direction(&mut state, &self.local, &self.local_rekey);
+        write_direction(&mut state, &self.remote, &self.remote_rekey);
+        match &self.remote_id {
+            None => state.push(0),
+            Some(remote_id) => {
+                state.push(1);
+                state.extend_from_slice(remote_id.as_ref());
+            }
+        }
+        match &self.transcripts {
+            None => state.push(0),
+            Some((sent, received)) => {
+                state.push(1);
+                for transcript in [sent, received] {
+                    state.extend_from_slice(&transcript.count().to_be_bytes());
+                    state.extend_from_slice(transcript.chain().as_ref());
+                }
+            }
+        }
+        state.extend_from_slice(self.resumption.as_ref());
+        state.extend_from_slice(self.exporter.as_ref());
+        state
+    }
+
+    fn from_snapshot(state: &[u8]) -> Result<Self, SnapshotError> {
+        let mut state = SnapshotReader(BufRead::new(state));
+
+        let handshake_hash = state.hash::<H>()?;
+        let (local, local_rekey) = state.direction()?;
+        let (remote, remote_rekey) = state.direction()?;
+        let remote_id = match state.u8()? {
+            0 => None,
+            1 => Some(PublicKey::from(state.bytes::<{ PublicKey::SIZE }>()?)),
+            _ => return Err(SnapshotError::InvalidEncoding),
+        };
+        let transcripts = match state.u8()? {
+            0 => None,
+            1 => {
+                let sent_count = state.u64()?;
+                let sent = Transcript::from_parts(state.hash::<H>()?, sent_count);
+                let received_count = state.u64()?;
+                let received = Transcript::from_parts(state.hash::<H>()?, received_count);
+                Some((sent, received))
+            }
+            _ => return Err(SnapshotError::InvalidEncoding),
+        };
+        let resumption = Seed::from(state.bytes::<{ Seed::SIZE }>()?);
+        let exporter = Seed::from(state.bytes::<{ Seed::SIZE }>()?);
+
+        if state.0.remaining() != 0 {
+            return Err(SnapshotError::InvalidEncoding);
+        }
+
+        Ok(Self {
+            handshake_hash,
+            local,
+            local_rekey,
+            remote,
+            remote_rekey,
+            remote_id,
+            transcripts,
+            resumption,
+            exporter,
+        })
+    }
+
     /// get the remote's public identity
-    pub fn remote_public_identity(&self) -> &PublicKey {
-        &self.remote_id
+    ///
+    /// `None` if the remote peer did not authenticate itself (the
+    /// initiator of an [`NK`](crate::noise::NK) handshake or both peers
+    /// of an [`NN`](crate::noise::NN) handshake)
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.remote_id.as_ref()
     }
 
     /// get the number of message received from the remote peer
@@ -93,6 +501,15 @@ impl<H: Hash> TransportState<H> {
         self.remote.nonce().into_u64()
     }
 
+    /// get the number of messages that can still be received from the
+    /// remote peer before the nonce reaches its limit
+    ///
+    /// once it reaches 0 every new [`receive`](Self::receive) will fail,
+    /// a new session needs to be established before that happens.
+    pub fn remaining_receives(&self) -> u64 {
+        self.remote.remaining()
+    }
+
     /// get the number of message sent to the remote peer
     ///
     /// this function will be a little tainted by the handshake state
@@ -104,48 +521,143 @@ impl<H: Hash> TransportState<H> {
         self.local.nonce().into_u64()
     }
 
+    /// get the number of messages that can still be sent to the remote
+    /// peer before the nonce reaches its limit
+    ///
+    /// once it reaches 0 every new [`send`](Self::send) will fail, a new
+    /// session needs to be established before that happens.
+    pub fn remaining_sends(&self) -> u64 {
+        self.local.remaining()
+    }
+
     /// send message to the remote peer
     ///
-    /// The output must be at least 16 bytes longer than the input
+    /// The output must have room for at least 16 bytes more than the input
     /// this is in order to add the MAC, this will be use to authenticate
-    /// the message has not been tempered with.
+    /// the message has not been tempered with. See [`OutBuffer`] for
+    /// how the different output types are written.
     pub fn send(
         &mut self,
         input: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<(), CipherStateError> {
-        self.local.encrypt_with_ad(&[], input, output)?;
-        self.local.rekey();
+        let transcript = self.transcripts.as_mut().map(|(sent, _)| sent);
+        send(
+            &mut self.local,
+            &mut self.local_rekey,
+            transcript,
+            input.as_ref(),
+            output,
+        )
+    }
 
-        Ok(())
+    /// send a message of any size to the remote peer, split in as many
+    /// noise messages as needed
+    ///
+    /// every returned noise message is at most [`MAX_MESSAGE_LEN`] bytes
+    /// long, they need to be given in order to
+    /// [`receive_chunked`](Self::receive_chunked). The last byte of the
+    /// plaintext of every chunk tells if more chunks follow.
+    ///
+    /// Fails without sending anything if there are not enough
+    /// [`remaining_sends`](Self::remaining_sends) for all the chunks.
+    pub fn send_chunked(
+        &mut self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Vec<Vec<u8>>, CipherStateError> {
+        let transcript = self.transcripts.as_mut().map(|(sent, _)| sent);
+        send_chunked(
+            &mut self.local,
+            &mut self.local_rekey,
+            transcript,
+            input.as_ref(),
+        )
     }
 
     /// receive message from the remote peer
     ///
     /// The output can have 16 bytes less than the input. This is because
     /// the MAC is appended in the input message so we can verify the
-    /// message has not been tempered with.
+    /// message has not been tempered with. See [`OutBuffer`] for
+    /// how the different output types are written.
     pub fn receive(
         &mut self,
         input: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<(), CipherStateError> {
-        self.remote.decrypt_with_ad(&[], input, output)?;
-        self.remote.rekey();
+        let transcript = self.transcripts.as_mut().map(|(_, received)| received);
+        receive(
+            &mut self.remote,
+            &mut self.remote_rekey,
+            transcript,
+            input.as_ref(),
+            output,
+        )
+    }
 
-        Ok(())
+    /// same as [`send`](Self::send) but encrypt the message in place,
+    /// the 16 bytes of the MAC are appended to the `buffer`
+    pub fn send_in_place(&mut self, buffer: &mut Vec<u8>) -> Result<(), CipherStateError> {
+        let transcript = self.transcripts.as_mut().map(|(sent, _)| sent);
+        send_in_place(&mut self.local, &mut self.local_rekey, transcript, buffer)
+    }
+
+    /// same as [`receive`](Self::receive) but decrypt the message in
+    /// place, the 16 bytes of the MAC are removed from the `buffer`
+    ///
+    /// the `buffer` is left unchanged if the message is not valid.
+    pub fn receive_in_place(&mut self, buffer: &mut Vec<u8>) -> Result<(), CipherStateError> {
+        let transcript = self.transcripts.as_mut().map(|(_, received)| received);
+        receive_in_place(&mut self.remote, &mut self.remote_rekey, transcript, buffer)
+    }
+
+    /// receive a chunk of a message sent with
+    /// [`send_chunked`](Self::send_chunked)
+    ///
+    /// the plaintext of the chunk is appended to `output`. Returns `true`
+    /// once the last chunk of the message has been received, the
+    /// `output` then contains the whole message.
+    ///
+    /// Fails with [`CipherStateError::MessageTooLarge`] (before decrypting
+    /// the chunk) if the message would be larger than `max_message_size`
+    /// bytes, so the remote peer cannot make the `output` grow forever.
+    pub fn receive_chunked(
+        &mut self,
+        input: impl AsRef<[u8]>,
+        output: &mut Vec<u8>,
+        max_message_size: usize,
+    ) -> Result<bool, CipherStateError> {
+        let transcript = self.transcripts.as_mut().map(|(_, received)| received);
+        receive_chunked(
+            &mut self.remote,
+            &mut self.remote_rekey,
+            transcript,
+            input.as_ref(),
+            output,
+            max_message_size,
+        )
     }
 }
 
-impl<H: Hash> TransportSendHalf<H> {
+impl<H: Hash, C: Cipher> TransportSendHalf<H, C> {
     /// unique identifier of the noise session
     pub fn noise_session(&self) -> &H::HASH {
         &self.handshake_hash
     }
 
+    /// the transcript of the messages sent, if it was enabled before
+    /// the split (see [`TransportState::enable_transcript`])
+    pub fn transcript(&self) -> Option<&Transcript<H>> {
+        self.transcript.as_ref()
+    }
+
     /// get the remote's public identity
-    pub fn remote_public_identity(&self) -> &PublicKey {
-        &self.remote_id
+    ///
+    /// `None` if the remote peer did not authenticate itself (the
+    /// initiator of an [`NK`](crate::noise::NK) handshake or both peers
+    /// of an [`NN`](crate::noise::NN) handshake)
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.remote_id.as_ref()
     }
 
     /// get the number of message sent to the remote peer
@@ -159,32 +671,78 @@ impl<H: Hash> TransportSendHalf<H> {
         self.local.nonce().into_u64()
     }
 
+    /// get the number of messages that can still be sent to the remote
+    /// peer before the nonce reaches its limit
+    ///
+    /// once it reaches 0 every new [`send`](Self::send) will fail, a new
+    /// session needs to be established before that happens.
+    pub fn remaining_sends(&self) -> u64 {
+        self.local.remaining()
+    }
+
     /// send message to the remote peer
     ///
-    /// The output must be at least 16 bytes longer than the input
+    /// The output must have room for at least 16 bytes more than the input
     /// this is in order to add the MAC, this will be use to authenticate
-    /// the message has not been tempered with.
+    /// the message has not been tempered with. See [`OutBuffer`] for
+    /// how the different output types are written.
     pub fn send(
         &mut self,
         input: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<(), CipherStateError> {
-        self.local.encrypt_with_ad(&[], input, output)?;
-        self.local.rekey();
+        send(
+            &mut self.local,
+            &mut self.rekey,
+            self.transcript.as_mut(),
+            input.as_ref(),
+            output,
+        )
+    }
 
-        Ok(())
+    /// see [`TransportState::send_in_place`]
+    pub fn send_in_place(&mut self, buffer: &mut Vec<u8>) -> Result<(), CipherStateError> {
+        send_in_place(
+            &mut self.local,
+            &mut self.rekey,
+            self.transcript.as_mut(),
+            buffer,
+        )
+    }
+
+    /// see [`TransportState::send_chunked`]
+    pub fn send_chunked(
+        &mut self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Vec<Vec<u8>>, CipherStateError> {
+        send_chunked(
+            &mut self.local,
+            &mut self.rekey,
+            self.transcript.as_mut(),
+            input.as_ref(),
+        )
     }
 }
 
-impl<H: Hash> TransportReceiveHalf<H> {
+impl<H: Hash, C: Cipher> TransportReceiveHalf<H, C> {
     /// unique identifier of the noise session
     pub fn noise_session(&self) -> &H::HASH {
         &self.handshake_hash
     }
 
+    /// the transcript of the messages received, if it was enabled
+    /// before the split (see [`TransportState::enable_transcript`])
+    pub fn transcript(&self) -> Option<&Transcript<H>> {
+        self.transcript.as_ref()
+    }
+
     /// get the remote's public identity
-    pub fn remote_public_identity(&self) -> &PublicKey {
-        &self.remote_id
+    ///
+    /// `None` if the remote peer did not authenticate itself (the
+    /// initiator of an [`NK`](crate::noise::NK) handshake or both peers
+    /// of an [`NN`](crate::noise::NN) handshake)
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.remote_id.as_ref()
     }
 
     /// get the number of message received from the remote peer
@@ -198,63 +756,667 @@ impl<H: Hash> TransportReceiveHalf<H> {
         self.remote.nonce().into_u64()
     }
 
+    /// get the number of messages that can still be received from the
+    /// remote peer before the nonce reaches its limit
+    ///
+    /// once it reaches 0 every new [`receive`](Self::receive) will fail,
+    /// a new session needs to be established before that happens.
+    pub fn remaining_receives(&self) -> u64 {
+        self.remote.remaining()
+    }
+
     /// receive message from the remote peer
     ///
     /// The output can have 16 bytes less than the input. This is because
     /// the MAC is appended in the input message so we can verify the
-    /// message has not been tempered with.
+    /// message has not been tempered with. See [`OutBuffer`] for
+    /// how the different output types are written.
     pub fn receive(
         &mut self,
         input: impl AsRef<[u8]>,
-        output: &mut [u8],
+        output: &mut (impl OutBuffer + ?Sized),
     ) -> Result<(), CipherStateError> {
-        self.remote.decrypt_with_ad(&[], input, output)?;
-        self.remote.rekey();
+        receive(
+            &mut self.remote,
+            &mut self.rekey,
+            self.transcript.as_mut(),
+            input.as_ref(),
+            output,
+        )
+    }
 
-        Ok(())
+    /// see [`TransportState::receive_in_place`]
+    pub fn receive_in_place(&mut self, buffer: &mut Vec<u8>) -> Result<(), CipherStateError> {
+        receive_in_place(
+            &mut self.remote,
+            &mut self.rekey,
+            self.transcript.as_mut(),
+            buffer,
+        )
+    }
+
+    /// see [`TransportState::receive_chunked`]
+    pub fn receive_chunked(
+        &mut self,
+        input: impl AsRef<[u8]>,
+        output: &mut Vec<u8>,
+        max_message_size: usize,
+    ) -> Result<bool, CipherStateError> {
+        receive_chunked(
+            &mut self.remote,
+            &mut self.rekey,
+            self.transcript.as_mut(),
+            input.as_ref(),
+            output,
+            max_message_size,
+        )
+    }
+}
+
+impl Rekey {
+    /// rekey the `cipher` if the policy says so, after a message of
+    /// `len` bytes has been sent or received
+    fn after<C: Cipher>(&mut self, cipher: &mut CipherState<C>, len: usize) {
+        self.messages += 1;
+        self.bytes = self.bytes.saturating_add(len as u64);
+
+        let rekey = match self.policy {
+            RekeyPolicy::EveryMessage => true,
+            RekeyPolicy::Messages(n) => self.messages >= n,
+            RekeyPolicy::Bytes(n) => self.bytes >= n,
+            RekeyPolicy::Never => false,
+        };
+
+        if rekey {
+            cipher.rekey();
+            self.messages = 0;
+            self.bytes = 0;
+        }
+    }
+}
+
+/// derive the key encrypting a snapshot from the key given to
+/// [`TransportState::suspend`] and the salt of the snapshot
+fn snapshot_key<H: Hash, C: Cipher>(key: &[u8], version: u8, salt: &[u8]) -> [u8; KEY_LEN] {
+    let mut info = Vec::with_capacity(64 + salt.len());
+    info.extend_from_slice(SNAPSHOT_CONTEXT);
+    info.push(version);
+    info.extend_from_slice(H::name().as_bytes());
+    info.push(0);
+    info.extend_from_slice(C::name().as_bytes());
+    info.push(0);
+    info.extend_from_slice(salt);
+
+    let mut k = [0; KEY_LEN];
+    hkdf_expand::<H>(key, &info, &mut k);
+    k
+}
+
+fn write_direction<C: Cipher>(state: &mut Vec<u8>, cipher: &CipherState<C>, rekey: &Rekey) {
+    state.extend_from_slice(cipher.key());
+    state.extend_from_slice(&cipher.nonce().into_u64().to_be_bytes());
+    state.extend_from_slice(&rekey.policy.to_bytes());
+    state.extend_from_slice(&rekey.messages.to_be_bytes());
+    state.extend_from_slice(&rekey.bytes.to_be_bytes());
+}
+
+struct SnapshotReader<'a>(BufRead<'a>);
+
+impl SnapshotReader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        if self.0.remaining() < N {
+            return Err(SnapshotError::InvalidEncoding);
+        }
+        let mut bytes = [0; N];
+        self.0.read(&mut bytes);
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        self.bytes::<1>().map(|[byte]| byte)
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        self.bytes::<8>().map(u64::from_be_bytes)
+    }
+
+    fn hash<H: Hash>(&mut self) -> Result<H::HASH, SnapshotError> {
+        if self.0.remaining() < H::HASH_LEN {
+            return Err(SnapshotError::InvalidEncoding);
+        }
+        let mut hash = H::zero_hash();
+        self.0.read(hash.as_mut());
+        Ok(hash)
+    }
+
+    fn direction<C: Cipher>(&mut self) -> Result<(CipherState<C>, Rekey), SnapshotError> {
+        let k = self.bytes::<KEY_LEN>()?;
+        let n = self.u64()?;
+        let policy =
+            RekeyPolicy::from_bytes(self.bytes()?).ok_or(SnapshotError::InvalidEncoding)?;
+        let rekey = Rekey {
+            policy,
+            messages: self.u64()?,
+            bytes: self.u64()?,
+        };
+
+        Ok((CipherState::restore(k, Nonce::from_u64(n)), rekey))
+    }
+}
+
+fn send<H: Hash, C: Cipher>(
+    local: &mut CipherState<C>,
+    rekey: &mut Rekey,
+    transcript: Option<&mut Transcript<H>>,
+    input: &[u8],
+    output: &mut (impl OutBuffer + ?Sized),
+) -> Result<(), CipherStateError> {
+    local.encrypt_with_ad([], input, output)?;
+    rekey.after(local, input.len());
+
+    if let Some(transcript) = transcript {
+        transcript.record(input);
+    }
+
+    Ok(())
+}
+
+fn receive<H: Hash, C: Cipher>(
+    remote: &mut CipherState<C>,
+    rekey: &mut Rekey,
+    transcript: Option<&mut Transcript<H>>,
+    input: &[u8],
+    output: &mut (impl OutBuffer + ?Sized),
+) -> Result<(), CipherStateError> {
+    let len = input.len().saturating_sub(TAG_LEN);
+    let transcript = match transcript {
+        None => {
+            remote.decrypt_with_ad([], input, output)?;
+            rekey.after(remote, len);
+            return Ok(());
+        }
+        Some(transcript) => transcript,
+    };
+
+    // decrypt in place in the output so the plaintext can be recorded
+    // in the transcript
+    let plaintext = output
+        .prepare(len)
+        .ok_or(CipherStateError::NotEnoughOutput)?;
+    if let Err(error) = remote.decrypt_with_ad([], input, plaintext) {
+        output.discard(len);
+        return Err(error);
+    }
+    rekey.after(remote, len);
+    transcript.record(plaintext);
+
+    Ok(())
+}
+
+fn send_in_place<H: Hash, C: Cipher>(
+    local: &mut CipherState<C>,
+    rekey: &mut Rekey,
+    transcript: Option<&mut Transcript<H>>,
+    buffer: &mut Vec<u8>,
+) -> Result<(), CipherStateError> {
+    // the plaintext is recorded before it is encrypted, only the nonce
+    // can make the encryption fail
+    if let Some(transcript) = transcript {
+        if local.remaining() == 0 {
+            return Err(CipherStateError::NonceExhausted);
+        }
+        transcript.record(buffer);
+    }
+
+    let len = buffer.len();
+    local.encrypt_in_place([], buffer)?;
+    rekey.after(local, len);
+
+    Ok(())
+}
+
+fn receive_in_place<H: Hash, C: Cipher>(
+    remote: &mut CipherState<C>,
+    rekey: &mut Rekey,
+    transcript: Option<&mut Transcript<H>>,
+    buffer: &mut Vec<u8>,
+) -> Result<(), CipherStateError> {
+    remote.decrypt_in_place([], buffer)?;
+    rekey.after(remote, buffer.len());
+
+    if let Some(transcript) = transcript {
+        transcript.record(buffer);
+    }
+
+    Ok(())
+}
+
+fn send_chunked<H: Hash, C: Cipher>(
+    local: &mut CipherState<C>,
+    rekey: &mut Rekey,
+    mut transcript: Option<&mut Transcript<H>>,
+    input: &[u8],
+) -> Result<Vec<Vec<u8>>, CipherStateError> {
+    let count = std::cmp::max(1, input.len().div_ceil(MAX_CHUNK_LEN));
+    if local.remaining() < count as u64 {
+        return Err(CipherStateError::NonceExhausted);
+    }
+
+    let mut messages = Vec::with_capacity(count);
+    let mut chunk = Vec::with_capacity(MAX_CHUNK_LEN + 1);
+    for i in 0..count {
+        let start = i * MAX_CHUNK_LEN;
+        let end = std::cmp::min(input.len(), start + MAX_CHUNK_LEN);
+
+        chunk.clear();
+        chunk.extend_from_slice(&input[start..end]);
+        chunk.push(if i + 1 == count {
+            CHUNK_LAST
+        } else {
+            CHUNK_MORE
+        });
+
+        let mut message = Vec::with_capacity(chunk.len() + TAG_LEN);
+        send(
+            local,
+            rekey,
+            transcript.as_deref_mut(),
+            &chunk,
+            &mut message,
+        )?;
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+fn receive_chunked<H: Hash, C: Cipher>(
+    remote: &mut CipherState<C>,
+    rekey: &mut Rekey,
+    transcript: Option<&mut Transcript<H>>,
+    input: &[u8],
+    output: &mut Vec<u8>,
+    max_message_size: usize,
+) -> Result<bool, CipherStateError> {
+    let chunk_len = input.len().saturating_sub(TAG_LEN + 1);
+    if output.len().saturating_add(chunk_len) > max_message_size {
+        return Err(CipherStateError::MessageTooLarge);
+    }
+
+    let start = output.len();
+    receive(remote, rekey, transcript, input, output)?;
+
+    if output.len() == start {
+        return Err(CipherStateError::InvalidChunk);
+    }
+
+    match output.pop() {
+        Some(CHUNK_LAST) => Ok(true),
+        Some(CHUNK_MORE) => Ok(false),
+        _ => {
+            output.truncate(start);
+            Err(CipherStateError::InvalidChunk)
+        }
     }
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use crate::noise::cipher::KEY_LEN;
+    use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
 
-    pub fn test_transport<H: Hash>(
-        mut initiator: TransportState<H>,
-        mut responder: TransportState<H>,
+    pub fn test_transport<H: Hash, C: Cipher>(
+        mut initiator: TransportState<H, C>,
+        mut responder: TransportState<H, C>,
         messages_init_to_responder: Vec<Vec<u8>>,
         messages_resp_to_initiator: Vec<Vec<u8>>,
     ) -> bool {
         for message in messages_init_to_responder {
-            let mut output = vec![0; message.len() + CipherState::TAG_LEN];
+            let mut output = Vec::with_capacity(message.len() + TAG_LEN);
             initiator
                 .send(&message, &mut output)
                 .expect("send encrypted message");
 
             let input = output;
-            let mut output = vec![0; message.len()];
+            let mut output = Vec::with_capacity(message.len());
             responder
                 .receive(&input, &mut output)
                 .expect("receive message");
 
-            assert!(message == output, "decryption of the message failed")
+            assert!(message == output, "decryption of the message failed");
+            assert_eq!(
+                initiator.count_sent() + initiator.remaining_sends(),
+                u64::MAX
+            );
+            assert_eq!(
+                responder.count_received() + responder.remaining_receives(),
+                u64::MAX
+            );
         }
 
         for message in messages_resp_to_initiator {
-            let mut output = vec![0; message.len() + CipherState::TAG_LEN];
+            let mut output = Vec::with_capacity(message.len() + TAG_LEN);
             responder
                 .send(&message, &mut output)
                 .expect("send encrypted message");
 
             let input = output;
-            let mut output = vec![0; message.len()];
+            let mut output = Vec::with_capacity(message.len());
             initiator
                 .receive(&input, &mut output)
                 .expect("receive message");
 
-            assert!(message == output, "decryption of the message failed")
+            assert!(message == output, "decryption of the message failed");
+            assert_eq!(
+                responder.count_sent() + responder.remaining_sends(),
+                u64::MAX
+            );
+            assert_eq!(
+                initiator.count_received() + initiator.remaining_receives(),
+                u64::MAX
+            );
         }
 
         true
     }
+
+    fn transport_pair() -> (TransportState<Blake2b>, TransportState<Blake2b>) {
+        let initiator = TransportState::new(
+            Blake2b::zero_hash(),
+            CipherState::initialize_key([1; KEY_LEN]),
+            CipherState::initialize_key([2; KEY_LEN]),
+            None,
+            Seed::from([3; Seed::SIZE]),
+            Seed::from([4; Seed::SIZE]),
+        );
+        let responder = TransportState::new(
+            Blake2b::zero_hash(),
+            CipherState::initialize_key([2; KEY_LEN]),
+            CipherState::initialize_key([1; KEY_LEN]),
+            None,
+            Seed::from([3; Seed::SIZE]),
+            Seed::from([4; Seed::SIZE]),
+        );
+        (initiator, responder)
+    }
+
+    #[test]
+    fn rekey_policy() {
+        let policies = [
+            RekeyPolicy::EveryMessage,
+            RekeyPolicy::Messages(3),
+            RekeyPolicy::Bytes(100),
+            RekeyPolicy::Never,
+        ];
+
+        for policy in policies {
+            let (mut initiator, mut responder) = transport_pair();
+            initiator.set_rekey_policy(policy);
+            responder.set_rekey_policy(policy);
+            assert_eq!(initiator.rekey_policy(), policy);
+
+            let messages = (0..10).map(|len| vec![len as u8; len * 20]).collect();
+            assert!(test_transport(initiator, responder, messages, Vec::new()));
+        }
+    }
+
+    #[test]
+    fn rekey_policy_mismatch() {
+        let (mut initiator, mut responder) = transport_pair();
+        initiator.set_rekey_policy(RekeyPolicy::Messages(2));
+
+        for (i, message) in [b"first", b"other"].iter().enumerate() {
+            let mut output = Vec::new();
+            initiator.send(message, &mut output).unwrap();
+            let received = responder.receive(&output, &mut Vec::new());
+            if i == 0 {
+                assert!(received.is_ok());
+            } else {
+                assert!(matches!(received, Err(CipherStateError::InvalidTag)));
+            }
+        }
+    }
+
+    #[test]
+    fn agree_rekey_policy() {
+        let (mut initiator, mut responder) = transport_pair();
+        let policy = RekeyPolicy::Bytes(100);
+
+        // the encoded policies are exchanged in the handshake payloads
+        let remote = policy.to_bytes();
+        initiator.agree_rekey_policy(policy, &remote).unwrap();
+        assert!(matches!(
+            responder.agree_rekey_policy(RekeyPolicy::Messages(100), &remote),
+            Err(RekeyPolicyError::Mismatch {
+                local: RekeyPolicy::Messages(100),
+                remote: RekeyPolicy::Bytes(100),
+            })
+        ));
+        assert_eq!(responder.rekey_policy(), RekeyPolicy::EveryMessage);
+        assert!(matches!(
+            responder.agree_rekey_policy(policy, &remote[1..]),
+            Err(RekeyPolicyError::InvalidEncoding)
+        ));
+
+        responder.agree_rekey_policy(policy, &remote).unwrap();
+        let messages = (0..10).map(|len| vec![len as u8; len * 20]).collect();
+        assert!(test_transport(initiator, responder, messages, Vec::new()));
+    }
+
+    #[test]
+    fn encode_decode_rekey_policy() {
+        for policy in [
+            RekeyPolicy::EveryMessage,
+            RekeyPolicy::Messages(3),
+            RekeyPolicy::Bytes(u64::MAX),
+            RekeyPolicy::Never,
+        ] {
+            assert_eq!(RekeyPolicy::from_bytes(policy.to_bytes()), Some(policy));
+        }
+        assert_eq!(RekeyPolicy::from_bytes([4; RekeyPolicy::SIZE]), None);
+    }
+
+    #[test]
+    fn in_place() {
+        let (mut initiator, mut responder) = transport_pair();
+        initiator.enable_transcript();
+        responder.enable_transcript();
+
+        for message in [b"".as_ref(), b"message", &[0xAB; 1024]] {
+            let mut buffer = message.to_vec();
+            initiator.send_in_place(&mut buffer).unwrap();
+            assert_eq!(buffer.len(), message.len() + TAG_LEN);
+
+            let mut tempered = buffer.clone();
+            tempered[0] ^= 1;
+            assert!(responder.receive_in_place(&mut tempered).is_err());
+
+            responder.receive_in_place(&mut buffer).unwrap();
+            assert_eq!(buffer, message);
+        }
+
+        let summary = initiator.transcript().unwrap();
+        assert!(summary.matches(&responder.transcript().unwrap()));
+    }
+
+    #[test]
+    fn chunked() {
+        let (mut initiator, mut responder) = transport_pair();
+
+        for len in [
+            0,
+            1,
+            MAX_CHUNK_LEN,
+            MAX_CHUNK_LEN + 1,
+            3 * MAX_CHUNK_LEN + 7,
+        ] {
+            let message: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let chunks = initiator.send_chunked(&message).unwrap();
+            assert_eq!(chunks.len(), std::cmp::max(1, len.div_ceil(MAX_CHUNK_LEN)));
+
+            let mut output = Vec::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                assert!(chunk.len() <= MAX_MESSAGE_LEN);
+                let last = responder
+                    .receive_chunked(chunk, &mut output, usize::MAX)
+                    .unwrap();
+                assert_eq!(last, i + 1 == chunks.len());
+            }
+            assert_eq!(output, message);
+        }
+    }
+
+    #[test]
+    fn chunked_invalid() {
+        let (mut initiator, mut responder) = transport_pair();
+
+        // a message not sent with `send_chunked` has no chunk flag
+        let mut message = Vec::new();
+        initiator.send([], &mut message).unwrap();
+        assert!(matches!(
+            responder.receive_chunked(&message, &mut Vec::new(), usize::MAX),
+            Err(CipherStateError::InvalidChunk)
+        ));
+
+        let mut message = Vec::new();
+        initiator.send([0xFF], &mut message).unwrap();
+        let mut output = vec![42];
+        assert!(matches!(
+            responder.receive_chunked(&message, &mut output, usize::MAX),
+            Err(CipherStateError::InvalidChunk)
+        ));
+        assert_eq!(output, [42]);
+    }
+
+    #[test]
+    fn chunked_too_large() {
+        let (mut initiator, mut responder) = transport_pair();
+
+        let message = vec![42; 2 * MAX_CHUNK_LEN];
+        let chunks = initiator.send_chunked(&message).unwrap();
+
+        let mut output = Vec::new();
+        let max = MAX_CHUNK_LEN + 1;
+        assert!(!responder
+            .receive_chunked(&chunks[0], &mut output, max)
+            .unwrap());
+        assert!(matches!(
+            responder.receive_chunked(&chunks[1], &mut output, max),
+            Err(CipherStateError::MessageTooLarge)
+        ));
+        assert_eq!(output.len(), MAX_CHUNK_LEN);
+
+        // the message fitting exactly is accepted
+        let (mut initiator, mut responder) = transport_pair();
+        let chunks = initiator.send_chunked(&message).unwrap();
+        let mut output = Vec::new();
+        for chunk in &chunks {
+            responder
+                .receive_chunked(chunk, &mut output, message.len())
+                .unwrap();
+        }
+        assert_eq!(output, message);
+    }
+
+    #[test]
+    fn export_secret() {
+        let (initiator, responder) = transport_pair();
+
+        let secret = initiator.export_secret(b"label", b"context", 100);
+        assert_eq!(secret.len(), 100);
+        assert_eq!(secret, responder.export_secret(b"label", b"context", 100));
+
+        assert_ne!(secret, initiator.export_secret(b"other", b"context", 100));
+        assert_ne!(secret, initiator.export_secret(b"label", b"", 100));
+        assert_ne!(
+            secret[..32],
+            initiator.export_secret(b"label", b"context", 32)[..]
+        );
+        // the label and the context are not simply concatenated
+        assert_ne!(
+            initiator.export_secret(b"lab", b"elcontext", 32),
+            initiator.export_secret(b"label", b"context", 32)
+        );
+    }
+
+    #[quickcheck]
+    fn suspend_resume(
+        rng: crate::Seed,
+        key: crate::Seed,
+        messages_init_to_responder: Vec<Vec<u8>>,
+        messages_resp_to_initiator: Vec<Vec<u8>>,
+    ) -> bool {
+        let mut k = [0; KEY_LEN];
+        k.copy_from_slice(key.as_ref());
+
+        let (mut initiator, mut responder) = transport_pair();
+        initiator.set_rekey_policy(RekeyPolicy::Messages(3));
+        responder.set_rekey_policy(RekeyPolicy::Messages(3));
+        initiator.enable_transcript();
+        responder.enable_transcript();
+
+        let mut message = Vec::new();
+        initiator.send(b"before", &mut message).unwrap();
+        responder.receive(&message, &mut Vec::new()).unwrap();
+
+        let snapshot = initiator.suspend(rng.into_rand_chacha(), &k);
+        let initiator = TransportState::<Blake2b>::resume(&k, &snapshot).unwrap();
+
+        test_transport::<Blake2b, _>(
+            initiator,
+            responder,
+            messages_init_to_responder,
+            messages_resp_to_initiator,
+        )
+    }
+
+    #[test]
+    fn suspend_keeps_state() {
+        let (mut initiator, mut responder) = transport_pair();
+        initiator.enable_transcript();
+        responder.enable_transcript();
+
+        let mut message = Vec::new();
+        initiator.send(b"before", &mut message).unwrap();
+        responder.receive(&message, &mut Vec::new()).unwrap();
+
+        let count_sent = initiator.count_sent();
+        let transcript = initiator.transcript().unwrap().to_bytes();
+        let exported = initiator.export_secret(b"label", b"", 32);
+
+        let key = [42; KEY_LEN];
+        let snapshot = initiator.suspend(rand::thread_rng(), &key);
+        let initiator = TransportState::<Blake2b>::resume(&key, &snapshot).unwrap();
+
+        assert_eq!(initiator.count_sent(), count_sent);
+        assert_eq!(initiator.transcript().unwrap().to_bytes(), transcript);
+        assert_eq!(initiator.export_secret(b"label", b"", 32), exported);
+    }
+
+    #[test]
+    fn resume_invalid() {
+        let (initiator, _) = transport_pair();
+        let key = [42; KEY_LEN];
+        let mut snapshot = initiator.suspend(rand::thread_rng(), &key);
+
+        assert!(matches!(
+            TransportState::<Blake2b>::resume(&[43; KEY_LEN], &snapshot),
+            Err(SnapshotError::InvalidTag)
+        ));
+        assert!(matches!(
+            TransportState::<Blake2s>::resume(&key, &snapshot),
+            Err(SnapshotError::InvalidTag)
+        ));
+        assert!(matches!(
+            TransportState::<Blake2b>::resume(&key, &snapshot[..20]),
+            Err(SnapshotError::InvalidEncoding)
+        ));
+
+        snapshot[0] = SNAPSHOT_VERSION + 1;
+        assert!(matches!(
+            TransportState::<Blake2b>::resume(&key, &snapshot),
+            Err(SnapshotError::UnsupportedVersion(_))
+        ));
+    }
 }
@@ -0,0 +1,1014 @@
+/*!
+# OPAQUE: asymmetric password authenticated key exchange
+
+[OPAQUE] allows a server to authenticate its users with a password
+without ever seeing the password, not even during the registration.
+The server only stores a [`RegistrationRecord`] from which it cannot
+recover the password without an offline dictionary attack (and only
+after the server has been compromised).
+
+This follows the OPAQUE-3DH construction of [OPAQUE] with the
+ristretto255-SHA512 OPRF, HKDF-SHA512 and HMAC-SHA512. The optional key
+stretching function is one of the [`kdf`](crate::kdf) functions. The
+session key is a 32 bytes [`SharedSecret`].
+
+```
+use keynesis_core::opaque::{ClientLogin, ClientRegistration, Identities, ServerSetup};
+# use rand::thread_rng;
+
+let server = ServerSetup::new(thread_rng());
+
+// registration of the user "alice"
+let (registration, request) = ClientRegistration::start(thread_rng(), b"password");
+let response = server.registration_response(&request, b"alice").unwrap();
+let (record, export_key) = registration
+    .finish(thread_rng(), &response, Identities::default(), None)
+    .unwrap();
+// the server stores the `record` for "alice"
+
+// login
+let (login, ke1) = ClientLogin::start(thread_rng(), b"password");
+let (server_login, ke2) = server
+    .login(thread_rng(), Some(&record), b"alice", &ke1, Identities::default(), b"")
+    .unwrap();
+let client = login.finish(&ke2, Identities::default(), b"", None).unwrap();
+let session_key = server_login.finish(client.ke3()).unwrap();
+
+assert_eq!(client.session_key(), &session_key);
+assert!(client.export_key() == &export_key);
+```
+
+## Channel binding
+
+The `context` of the login is authenticated by both parties. When the
+login is performed over an established Noise session, the session's
+handshake hash (see [`TransportState::noise_session`]) should be used
+as context so the login is bound to this session. The [`ExportKey`]
+can be bound to the session too with [`ExportKey::bind`].
+
+[OPAQUE]: https://www.rfc-editor.org/rfc/rfc9807.html
+[`TransportState::noise_session`]: crate::noise::TransportState::noise_session
+*/
+
+mod oprf;
+
+use self::oprf::{ELEMENT_SIZE, HASH_SIZE};
+use crate::{
+    kdf::{Kdf, KdfError},
+    key::SharedSecret,
+    memsec::{self, Scrubbed as _},
+};
+use cryptoxide::{
+    digest::Digest as _,
+    hkdf::{hkdf_expand, hkdf_extract},
+    hmac::Hmac,
+    mac::Mac as _,
+    sha2::Sha512,
+};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_TABLE, ristretto::RistrettoPoint, scalar::Scalar,
+};
+use rand_core::{CryptoRng, RngCore};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+const NONCE_SIZE: usize = 32;
+const SEED_SIZE: usize = 32;
+const ENVELOPE_SIZE: usize = NONCE_SIZE + HASH_SIZE;
+const CREDENTIAL_RESPONSE_SIZE: usize = ELEMENT_SIZE + NONCE_SIZE + ELEMENT_SIZE + ENVELOPE_SIZE;
+
+const CHANNEL_BINDING: &[u8] = b"keynesis:opaque:channel-binding";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum OpaqueError {
+    #[error("Invalid size, expecting {expected} bytes")]
+    InvalidSize { expected: usize },
+
+    #[error("Invalid encoded point")]
+    InvalidPoint,
+
+    #[error("Invalid encoded scalar")]
+    InvalidScalar,
+
+    #[error("Cannot recover the credentials, the password may be wrong")]
+    EnvelopeRecovery,
+
+    #[error("The server's authentication failed")]
+    InvalidServerMac,
+
+    #[error("The client's authentication failed")]
+    InvalidClientMac,
+
+    #[error("Cannot stretch the password")]
+    Stretching(
+        #[source]
+        #[from]
+        KdfError,
+    ),
+}
+
+/// identities of the client and the server
+///
+/// if not set, the public keys are used as identities. These need to
+/// be the same during the registration and the logins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Identities<'a> {
+    pub client: Option<&'a [u8]>,
+    pub server: Option<&'a [u8]>,
+}
+
+/// the server's long term secrets: the seed of the per user OPRF keys
+/// and the private key of the server
+///
+/// The content is scrubbed (zeroed) when dropped.
+pub struct ServerSetup {
+    oprf_seed: [u8; HASH_SIZE],
+    private_key: Scalar,
+    public_key: RistrettoPoint,
+}
+
+/// the client side of the registration
+pub struct ClientRegistration {
+    password: Vec<u8>,
+    blind: Scalar,
+}
+
+/// the client side of the login
+pub struct ClientLogin {
+    password: Vec<u8>,
+    blind: Scalar,
+    keyshare: Scalar,
+    ke1: Ke1,
+}
+
+/// the server side of the login, waiting for the client's [`Ke3`]
+pub struct ServerLogin {
+    expected_client_mac: [u8; HASH_SIZE],
+    session_key: SharedSecret,
+}
+
+/// the result of a successful login on the client side
+pub struct ClientLoginFinish {
+    ke3: Ke3,
+    session_key: SharedSecret,
+    export_key: ExportKey,
+}
+
+/// a key only the client can compute, it is the same for every login
+/// of a given registration
+///
+/// It can be used to encrypt additional data stored on the server. The
+/// content is scrubbed (zeroed) when dropped.
+#[derive(Clone)]
+pub struct ExportKey([u8; HASH_SIZE]);
+
+macro_rules! message {
+    ($(#[$meta:meta])* $name:ident, $size:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name([u8; Self::SIZE]);
+
+        impl $name {
+            pub const SIZE: usize = $size;
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl<'a> TryFrom<&'a [u8]> for $name {
+            type Error = OpaqueError;
+
+            fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+                <[u8; Self::SIZE]>::try_from(value)
+                    .map(Self)
+                    .map_err(|_| OpaqueError::InvalidSize {
+                        expected: Self::SIZE,
+                    })
+            }
+        }
+    };
+}
+
+message!(
+    /// the client's blinded password, sent to the server to register
+    RegistrationRequest,
+    ELEMENT_SIZE
+);
+message!(
+    /// the server's response to the [`RegistrationRequest`]
+    RegistrationResponse,
+    ELEMENT_SIZE + ELEMENT_SIZE
+);
+message!(
+    /// the record the server needs to store to authenticate the client
+    RegistrationRecord,
+    ELEMENT_SIZE + HASH_SIZE + ENVELOPE_SIZE
+);
+message!(
+    /// the first message of the login, from the client
+    Ke1,
+    ELEMENT_SIZE + NONCE_SIZE + ELEMENT_SIZE
+);
+message!(
+    /// the second message of the login, from the server
+    Ke2,
+    CREDENTIAL_RESPONSE_SIZE + NONCE_SIZE + ELEMENT_SIZE + HASH_SIZE
+);
+message!(
+    /// the last message of the login, from the client
+    Ke3,
+    HASH_SIZE
+);
+
+/* Primitives ************************************************************** */
+
+fn hash(data: &[&[u8]]) -> [u8; HASH_SIZE] {
+    let mut hasher = Sha512::new();
+    for data in data {
+        hasher.input(data);
+    }
+    let mut output = [0; HASH_SIZE];
+    hasher.result(&mut output);
+    output
+}
+
+fn extract(ikm: &[&[u8]]) -> [u8; HASH_SIZE] {
+    let mut ikm = ikm.concat();
+    let mut prk = [0; HASH_SIZE];
+    hkdf_extract(Sha512::new(), &[], &ikm, &mut prk);
+    ikm.scrub();
+    prk
+}
+
+fn expand(prk: &[u8], info: &[&[u8]], output: &mut [u8]) {
+    hkdf_expand(Sha512::new(), prk, &info.concat(), output);
+}
+
+fn expand_label(secret: &[u8], label: &[u8], context: &[u8], output: &mut [u8]) {
+    expand(
+        secret,
+        &[
+            &(output.len() as u16).to_be_bytes(),
+            &[(7 + label.len()) as u8],
+            b"OPAQUE-",
+            label,
+            &[context.len() as u8],
+            context,
+        ],
+        output,
+    )
+}
+
+fn mac(key: &[u8], data: &[&[u8]]) -> [u8; HASH_SIZE] {
+    let mut mac = Hmac::new(Sha512::new(), key);
+    for data in data {
+        mac.input(data);
+    }
+    let mut output = [0; HASH_SIZE];
+    mac.raw_result(&mut output);
+    output
+}
+
+fn ct_eq(a: &[u8; HASH_SIZE], b: &[u8]) -> bool {
+    b.len() == HASH_SIZE && unsafe { memsec::memeq(a.as_ptr(), b.as_ptr(), HASH_SIZE) }
+}
+
+fn random<Rng, const N: usize>(rng: &mut Rng) -> [u8; N]
+where
+    Rng: RngCore + CryptoRng,
+{
+    let mut bytes = [0; N];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn with_length(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(2 + data.len());
+    bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/* Envelope **************************************************************** */
+
+fn randomized_password(
+    oprf_output: &[u8; HASH_SIZE],
+    ksf: Option<&Kdf>,
+) -> Result<[u8; HASH_SIZE], OpaqueError> {
+    let mut stretched = *oprf_output;
+    if let Some(ksf) = ksf {
+        ksf.derive(oprf_output, &[0; 16], &mut stretched)?;
+    }
+    let randomized_password = extract(&[oprf_output, &stretched]);
+    stretched.scrub();
+    Ok(randomized_password)
+}
+
+fn cleartext_credentials(
+    server_public_key: &[u8],
+    client_public_key: &[u8],
+    identities: Identities<'_>,
+) -> Vec<u8> {
+    [
+        server_public_key,
+        &with_length(identities.server.unwrap_or(server_public_key)),
+        &with_length(identities.client.unwrap_or(client_public_key)),
+    ]
+    .concat()
+}
+
+/// the keys derived from the randomized password and the envelope's nonce
+struct EnvelopeKeys {
+    auth_key: [u8; HASH_SIZE],
+    export_key: ExportKey,
+    private_key: Scalar,
+    public_key: RistrettoPoint,
+}
+
+impl EnvelopeKeys {
+    fn new(randomized_password: &[u8], nonce: &[u8]) -> Self {
+        let mut auth_key = [0; HASH_SIZE];
+        let mut export_key = [0; HASH_SIZE];
+        let mut seed = [0; SEED_SIZE];
+        expand(randomized_password, &[nonce, b"AuthKey"], &mut auth_key);
+        expand(randomized_password, &[nonce, b"ExportKey"], &mut export_key);
+        expand(randomized_password, &[nonce, b"PrivateKey"], &mut seed);
+        let (private_key, public_key) =
+            oprf::derive_key_pair(&seed, b"OPAQUE-DeriveDiffieHellmanKeyPair");
+        seed.scrub();
+
+        Self {
+            auth_key,
+            export_key: ExportKey(export_key),
+            private_key,
+            public_key,
+        }
+    }
+}
+
+impl Drop for EnvelopeKeys {
+    fn drop(&mut self) {
+        self.auth_key.scrub();
+        self.private_key = Scalar::zero();
+    }
+}
+
+fn masking_key(randomized_password: &[u8]) -> [u8; HASH_SIZE] {
+    let mut masking_key = [0; HASH_SIZE];
+    expand(randomized_password, &[b"MaskingKey"], &mut masking_key);
+    masking_key
+}
+
+fn credential_response_pad(
+    masking_key: &[u8],
+    masking_nonce: &[u8],
+) -> [u8; ELEMENT_SIZE + ENVELOPE_SIZE] {
+    let mut pad = [0; ELEMENT_SIZE + ENVELOPE_SIZE];
+    expand(
+        masking_key,
+        &[masking_nonce, b"CredentialResponsePad"],
+        &mut pad,
+    );
+    pad
+}
+
+/* 3DH ********************************************************************* */
+
+struct AkeKeys {
+    server_mac: [u8; HASH_SIZE],
+    client_mac: [u8; HASH_SIZE],
+    session_key: SharedSecret,
+}
+
+fn preamble(
+    context: &[u8],
+    client_identity: &[u8],
+    ke1: &Ke1,
+    server_identity: &[u8],
+    ke2_without_mac: &[u8],
+) -> Vec<u8> {
+    [
+        b"OPAQUEv1-".as_ref(),
+        &with_length(context),
+        &with_length(client_identity),
+        &ke1.0,
+        &with_length(server_identity),
+        ke2_without_mac,
+    ]
+    .concat()
+}
+
+fn derive_ake_keys(dh: [RistrettoPoint; 3], preamble: &[u8]) -> AkeKeys {
+    let ikm = dh
+        .iter()
+        .map(|p| p.compress().to_bytes())
+        .collect::<Vec<_>>();
+    let ikm = ikm.iter().map(|p| p.as_ref()).collect::<Vec<_>>();
+    let mut prk = extract(&ikm);
+    let preamble_hash = hash(&[preamble]);
+
+    let mut handshake_secret = [0; HASH_SIZE];
+    let mut session_key = [0; SharedSecret::SIZE];
+    expand_label(
+        &prk,
+        b"HandshakeSecret",
+        &preamble_hash,
+        &mut handshake_secret,
+    );
+    expand_label(&prk, b"SessionKey", &preamble_hash, &mut session_key);
+
+    let mut km2 = [0; HASH_SIZE];
+    let mut km3 = [0; HASH_SIZE];
+    expand_label(&handshake_secret, b"ServerMAC", b"", &mut km2);
+    expand_label(&handshake_secret, b"ClientMAC", b"", &mut km3);
+
+    let server_mac = mac(&km2, &[&preamble_hash]);
+    let client_mac = mac(&km3, &[&hash(&[preamble, &server_mac])]);
+
+    prk.scrub();
+    handshake_secret.scrub();
+    km2.scrub();
+    km3.scrub();
+
+    AkeKeys {
+        server_mac,
+        client_mac,
+        session_key: SharedSecret::new(session_key),
+    }
+}
+
+/* Server ****************************************************************** */
+
+impl ServerSetup {
+    pub const SIZE: usize = HASH_SIZE + ELEMENT_SIZE;
+
+    /// generate new random server secrets
+    pub fn new<Rng>(mut rng: Rng) -> Self
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let oprf_seed = random(&mut rng);
+        let private_key = oprf::random_scalar(&mut rng);
+        let public_key = &private_key * &RISTRETTO_BASEPOINT_TABLE;
+
+        Self {
+            oprf_seed,
+            private_key,
+            public_key,
+        }
+    }
+
+    /// the server's public key
+    pub fn public_key(&self) -> [u8; ELEMENT_SIZE] {
+        self.public_key.compress().to_bytes()
+    }
+
+    /// encode the server's secrets so they can be stored
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..HASH_SIZE].copy_from_slice(&self.oprf_seed);
+        bytes[HASH_SIZE..].copy_from_slice(self.private_key.as_bytes());
+        bytes
+    }
+
+    fn oprf_key(&self, credential_identifier: &[u8]) -> Scalar {
+        let mut seed = [0; SEED_SIZE];
+        expand(
+            &self.oprf_seed,
+            &[credential_identifier, b"OprfKey"],
+            &mut seed,
+        );
+        let (key, _) = oprf::derive_key_pair(&seed, b"OPAQUE-DeriveKeyPair");
+        seed.scrub();
+        key
+    }
+
+    /// respond to the registration request of the client identified
+    /// by the `credential_identifier` (the user name for example)
+    pub fn registration_response(
+        &self,
+        request: &RegistrationRequest,
+        credential_identifier: &[u8],
+    ) -> Result<RegistrationResponse, OpaqueError> {
+        let blinded = oprf::decode_element(&request.0)?;
+        let evaluated = oprf::blind_evaluate(&self.oprf_key(credential_identifier), &blinded);
+
+        let mut response = [0; RegistrationResponse::SIZE];
+        response[..ELEMENT_SIZE].copy_from_slice(evaluated.compress().as_bytes());
+        response[ELEMENT_SIZE..].copy_from_slice(&self.public_key());
+        Ok(RegistrationResponse(response))
+    }
+
+    /// respond to the login request of the client identified by the
+    /// `credential_identifier`
+    ///
+    /// If the client is not registered, use `None` for the `record`: the
+    /// response will be indistinguishable from the one of a registered
+    /// client so the login attempt cannot be used to know if the client
+    /// is registered. The login will fail at [`ServerLogin::finish`].
+    pub fn login<Rng>(
+        &self,
+        mut rng: Rng,
+        record: Option<&RegistrationRecord>,
+        credential_identifier: &[u8],
+        ke1: &Ke1,
+        identities: Identities<'_>,
+        context: &[u8],
+    ) -> Result<(ServerLogin, Ke2), OpaqueError>
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let blinded = oprf::decode_element(&ke1.0[..ELEMENT_SIZE])?;
+        let client_keyshare = oprf::decode_element(&ke1.0[ELEMENT_SIZE + NONCE_SIZE..])?;
+
+        let (client_public_key, masking_key, envelope) = match record {
+            Some(record) => (
+                oprf::decode_element(&record.0[..ELEMENT_SIZE])?,
+                <[u8; HASH_SIZE]>::try_from(&record.0[ELEMENT_SIZE..ELEMENT_SIZE + HASH_SIZE])
+                    .expect("the record has the expected size"),
+                &record.0[ELEMENT_SIZE + HASH_SIZE..],
+            ),
+            None => (
+                &oprf::random_scalar(&mut rng) * &RISTRETTO_BASEPOINT_TABLE,
+                random(&mut rng),
+                [0; ENVELOPE_SIZE].as_ref(),
+            ),
+        };
+
+        let evaluated = oprf::blind_evaluate(&self.oprf_key(credential_identifier), &blinded);
+        let masking_nonce: [u8; NONCE_SIZE] = random(&mut rng);
+        let pad = credential_response_pad(&masking_key, &masking_nonce);
+        let server_public_key = self.public_key();
+
+        let mut ke2 = [0; Ke2::SIZE];
+        let (credential_response, rest) = ke2.split_at_mut(CREDENTIAL_RESPONSE_SIZE);
+        credential_response[..ELEMENT_SIZE].copy_from_slice(evaluated.compress().as_bytes());
+        credential_response[ELEMENT_SIZE..ELEMENT_SIZE + NONCE_SIZE]
+            .copy_from_slice(&masking_nonce);
+        for (masked, (pad, clear)) in credential_response[ELEMENT_SIZE + NONCE_SIZE..]
+            .iter_mut()
+            .zip(pad.iter().zip(server_public_key.iter().chain(envelope)))
+        {
+            *masked = pad ^ clear;
+        }
+
+        let server_nonce: [u8; NONCE_SIZE] = random(&mut rng);
+        let keyshare = oprf::random_scalar(&mut rng);
+        rest[..NONCE_SIZE].copy_from_slice(&server_nonce);
+        rest[NONCE_SIZE..NONCE_SIZE + ELEMENT_SIZE].copy_from_slice(
+            (&keyshare * &RISTRETTO_BASEPOINT_TABLE)
+                .compress()
+                .as_bytes(),
+        );
+
+        let client_public_key_bytes = client_public_key.compress().to_bytes();
+        let preamble = preamble(
+            context,
+            identities.client.unwrap_or(&client_public_key_bytes),
+            ke1,
+            identities.server.unwrap_or(&server_public_key),
+            &ke2[..Ke2::SIZE - HASH_SIZE],
+        );
+        let keys = derive_ake_keys(
+            [
+                client_keyshare * keyshare,
+                client_keyshare * self.private_key,
+                client_public_key * keyshare,
+            ],
+            &preamble,
+        );
+        ke2[Ke2::SIZE - HASH_SIZE..].copy_from_slice(&keys.server_mac);
+
+        let login = ServerLogin {
+            expected_client_mac: keys.client_mac,
+            session_key: keys.session_key,
+        };
+
+        Ok((login, Ke2(ke2)))
+    }
+}
+
+impl ServerLogin {
+    /// verify the client's [`Ke3`] and release the session key
+    pub fn finish(self, ke3: &Ke3) -> Result<SharedSecret, OpaqueError> {
+        if ct_eq(&self.expected_client_mac, &ke3.0) {
+            Ok(self.session_key)
+        } else {
+            Err(OpaqueError::InvalidClientMac)
+        }
+    }
+}
+
+/* Client ****************************************************************** */
+
+impl ClientRegistration {
+    /// start the registration with the given password
+    pub fn start<Rng>(mut rng: Rng, password: &[u8]) -> (Self, RegistrationRequest)
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let (blind, blinded) = oprf::blind(&mut rng, password);
+        let registration = Self {
+            password: password.to_owned(),
+            blind,
+        };
+        let request = RegistrationRequest(blinded.compress().to_bytes());
+
+        (registration, request)
+    }
+
+    /// process the server's response and create the record to send
+    /// to the server
+    ///
+    /// the key stretching function `ksf` (if any) needs to be the same
+    /// for every login.
+    pub fn finish<Rng>(
+        self,
+        mut rng: Rng,
+        response: &RegistrationResponse,
+        identities: Identities<'_>,
+        ksf: Option<&Kdf>,
+    ) -> Result<(RegistrationRecord, ExportKey), OpaqueError>
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let evaluated = oprf::decode_element(&response.0[..ELEMENT_SIZE])?;
+        let server_public_key = &response.0[ELEMENT_SIZE..];
+        oprf::decode_element(server_public_key)?;
+
+        let mut oprf_output = oprf::finalize(&self.password, &self.blind, &evaluated);
+        let mut randomized_password = randomized_password(&oprf_output, ksf)?;
+        oprf_output.scrub();
+
+        let nonce: [u8; NONCE_SIZE] = random(&mut rng);
+        let keys = EnvelopeKeys::new(&randomized_password, &nonce);
+        let masking_key = masking_key(&randomized_password);
+        randomized_password.scrub();
+
+        let client_public_key = keys.public_key.compress().to_bytes();
+        let auth_tag = mac(
+            &keys.auth_key,
+            &[
+                &nonce,
+                &cleartext_credentials(server_public_key, &client_public_key, identities),
+            ],
+        );
+
+        let mut record = [0; RegistrationRecord::SIZE];
+        record[..ELEMENT_SIZE].copy_from_slice(&client_public_key);
+        record[ELEMENT_SIZE..ELEMENT_SIZE + HASH_SIZE].copy_from_slice(&masking_key);
+        record[ELEMENT_SIZE + HASH_SIZE..ELEMENT_SIZE + HASH_SIZE + NONCE_SIZE]
+            .copy_from_slice(&nonce);
+        record[ELEMENT_SIZE + HASH_SIZE + NONCE_SIZE..].copy_from_slice(&auth_tag);
+
+        Ok((RegistrationRecord(record), keys.export_key.clone()))
+    }
+}
+
+impl ClientLogin {
+    /// start the login with the given password
+    pub fn start<Rng>(mut rng: Rng, password: &[u8]) -> (Self, Ke1)
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let (blind, blinded) = oprf::blind(&mut rng, password);
+        let nonce: [u8; NONCE_SIZE] = random(&mut rng);
+        let keyshare = oprf::random_scalar(&mut rng);
+
+        let mut ke1 = [0; Ke1::SIZE];
+        ke1[..ELEMENT_SIZE].copy_from_slice(blinded.compress().as_bytes());
+        ke1[ELEMENT_SIZE..ELEMENT_SIZE + NONCE_SIZE].copy_from_slice(&nonce);
+        ke1[ELEMENT_SIZE + NONCE_SIZE..].copy_from_slice(
+            (&keyshare * &RISTRETTO_BASEPOINT_TABLE)
+                .compress()
+                .as_bytes(),
+        );
+        let ke1 = Ke1(ke1);
+
+        let login = Self {
+            password: password.to_owned(),
+            blind,
+            keyshare,
+            ke1: ke1.clone(),
+        };
+
+        (login, ke1)
+    }
+
+    /// process the server's [`Ke2`]
+    ///
+    /// the `identities`, the `context` and the key stretching function
+    /// `ksf` need to be the same as the ones used by the server and
+    /// during the registration.
+    pub fn finish(
+        self,
+        ke2: &Ke2,
+        identities: Identities<'_>,
+        context: &[u8],
+        ksf: Option<&Kdf>,
+    ) -> Result<ClientLoginFinish, OpaqueError> {
+        let evaluated = oprf::decode_element(&ke2.0[..ELEMENT_SIZE])?;
+        let masking_nonce = &ke2.0[ELEMENT_SIZE..ELEMENT_SIZE + NONCE_SIZE];
+        let masked_response = &ke2.0[ELEMENT_SIZE + NONCE_SIZE..CREDENTIAL_RESPONSE_SIZE];
+        let server_keyshare = oprf::decode_element(
+            &ke2.0[CREDENTIAL_RESPONSE_SIZE + NONCE_SIZE
+                ..CREDENTIAL_RESPONSE_SIZE + NONCE_SIZE + ELEMENT_SIZE],
+        )?;
+        let server_mac = &ke2.0[Ke2::SIZE - HASH_SIZE..];
+
+        let mut oprf_output = oprf::finalize(&self.password, &self.blind, &evaluated);
+        let mut randomized_password = randomized_password(&oprf_output, ksf)?;
+        oprf_output.scrub();
+
+        let mut masking_key = masking_key(&randomized_password);
+        let mut response = credential_response_pad(&masking_key, masking_nonce);
+        masking_key.scrub();
+        for (pad, masked) in response.iter_mut().zip(masked_response) {
+            *pad ^= masked;
+        }
+        let (server_public_key, envelope) = response.split_at(ELEMENT_SIZE);
+        let (nonce, auth_tag) = envelope.split_at(NONCE_SIZE);
+
+        let keys = EnvelopeKeys::new(&randomized_password, nonce);
+        randomized_password.scrub();
+        let client_public_key = keys.public_key.compress().to_bytes();
+        let expected_tag = mac(
+            &keys.auth_key,
+            &[
+                nonce,
+                &cleartext_credentials(server_public_key, &client_public_key, identities),
+            ],
+        );
+        if !ct_eq(&expected_tag, auth_tag) {
+            return Err(OpaqueError::EnvelopeRecovery);
+        }
+        let server_public_key_point = oprf::decode_element(server_public_key)?;
+
+        let preamble = preamble(
+            context,
+            identities.client.unwrap_or(&client_public_key),
+            &self.ke1,
+            identities.server.unwrap_or(server_public_key),
+            &ke2.0[..Ke2::SIZE - HASH_SIZE],
+        );
+        let ake = derive_ake_keys(
+            [
+                server_keyshare * self.keyshare,
+                server_public_key_point * self.keyshare,
+                server_keyshare * keys.private_key,
+            ],
+            &preamble,
+        );
+        if !ct_eq(&ake.server_mac, server_mac) {
+            return Err(OpaqueError::InvalidServerMac);
+        }
+
+        Ok(ClientLoginFinish {
+            ke3: Ke3(ake.client_mac),
+            session_key: ake.session_key,
+            export_key: keys.export_key.clone(),
+        })
+    }
+}
+
+impl ClientLoginFinish {
+    /// the message to send to the server to finish the login
+    pub fn ke3(&self) -> &Ke3 {
+        &self.ke3
+    }
+
+    pub fn session_key(&self) -> &SharedSecret {
+        &self.session_key
+    }
+
+    pub fn export_key(&self) -> &ExportKey {
+        &self.export_key
+    }
+}
+
+impl ExportKey {
+    pub const SIZE: usize = HASH_SIZE;
+
+    /// derive a key bound to the given channel (for example the Noise
+    /// handshake hash of the session the login was performed on)
+    pub fn bind(&self, channel_binding: &[u8]) -> SharedSecret {
+        let mut key = [0; SharedSecret::SIZE];
+        expand(&self.0, &[CHANNEL_BINDING, channel_binding], &mut key);
+        SharedSecret::new(key)
+    }
+}
+
+impl AsRef<[u8]> for ExportKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq for ExportKey {
+    fn eq(&self, other: &Self) -> bool {
+        ct_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ExportKey {}
+
+impl Drop for ExportKey {
+    fn drop(&mut self) {
+        self.0.scrub();
+    }
+}
+
+impl Drop for ServerSetup {
+    fn drop(&mut self) {
+        self.oprf_seed.scrub();
+        self.private_key = Scalar::zero();
+    }
+}
+
+impl Drop for ClientRegistration {
+    fn drop(&mut self) {
+        self.password.scrub();
+        self.blind = Scalar::zero();
+    }
+}
+
+impl Drop for ClientLogin {
+    fn drop(&mut self) {
+        self.password.scrub();
+        self.blind = Scalar::zero();
+        self.keyshare = Scalar::zero();
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for ServerSetup {
+    type Error = OpaqueError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        if value.len() != Self::SIZE {
+            return Err(OpaqueError::InvalidSize {
+                expected: Self::SIZE,
+            });
+        }
+        let oprf_seed = <[u8; HASH_SIZE]>::try_from(&value[..HASH_SIZE])
+            .expect("the size has already been checked");
+        let private_key = oprf::decode_scalar(&value[HASH_SIZE..])?;
+        let public_key = &private_key * &RISTRETTO_BASEPOINT_TABLE;
+
+        Ok(Self {
+            oprf_seed,
+            private_key,
+            public_key,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdf::ScryptParams;
+    use rand::thread_rng;
+
+    fn register(
+        server: &ServerSetup,
+        password: &[u8],
+        identities: Identities<'_>,
+        ksf: Option<&Kdf>,
+    ) -> (RegistrationRecord, ExportKey) {
+        let (registration, request) = ClientRegistration::start(thread_rng(), password);
+        let response = server.registration_response(&request, b"user").unwrap();
+        registration
+            .finish(thread_rng(), &response, identities, ksf)
+            .unwrap()
+    }
+
+    fn login(
+        server: &ServerSetup,
+        record: Option<&RegistrationRecord>,
+        password: &[u8],
+        ksf: Option<&Kdf>,
+    ) -> Result<(SharedSecret, ClientLoginFinish), OpaqueError> {
+        let (login, ke1) = ClientLogin::start(thread_rng(), password);
+        let (server_login, ke2) = server.login(
+            thread_rng(),
+            record,
+            b"user",
+            &ke1,
+            Identities::default(),
+            b"",
+        )?;
+        let client = login.finish(&ke2, Identities::default(), b"", ksf)?;
+        let session_key = server_login.finish(client.ke3())?;
+        Ok((session_key, client))
+    }
+
+    #[test]
+    fn register_and_login() {
+        let server = ServerSetup::new(thread_rng());
+        let ksf = Kdf::Scrypt(ScryptParams::new(4, 8, 1).unwrap());
+        let (record, export_key) =
+            register(&server, b"password", Identities::default(), Some(&ksf));
+
+        let (session_key, client) = login(&server, Some(&record), b"password", Some(&ksf)).unwrap();
+        assert_eq!(&session_key, client.session_key());
+        assert!(client.export_key() == &export_key);
+
+        let (other_session_key, _) =
+            login(&server, Some(&record), b"password", Some(&ksf)).unwrap();
+        assert_ne!(session_key, other_session_key);
+    }
+
+    #[test]
+    fn wrong_password() {
+        let server = ServerSetup::new(thread_rng());
+        let (record, _) = register(&server, b"password", Identities::default(), None);
+
+        assert!(matches!(
+            login(&server, Some(&record), b"passw0rd", None),
+            Err(OpaqueError::EnvelopeRecovery)
+        ));
+    }
+
+    #[test]
+    fn unknown_client() {
+        let server = ServerSetup::new(thread_rng());
+
+        assert!(matches!(
+            login(&server, None, b"password", None),
+            Err(OpaqueError::EnvelopeRecovery)
+        ));
+    }
+
+    #[test]
+    fn different_context() {
+        let server = ServerSetup::new(thread_rng());
+        let (record, _) = register(&server, b"password", Identities::default(), None);
+
+        let (login, ke1) = ClientLogin::start(thread_rng(), b"password");
+        let (_, ke2) = server
+            .login(
+                thread_rng(),
+                Some(&record),
+                b"user",
+                &ke1,
+                Identities::default(),
+                b"session 1",
+            )
+            .unwrap();
+        assert!(matches!(
+            login.finish(&ke2, Identities::default(), b"session 2", None),
+            Err(OpaqueError::InvalidServerMac)
+        ));
+    }
+
+    #[test]
+    fn identities() {
+        let server = ServerSetup::new(thread_rng());
+        let identities = Identities {
+            client: Some(b"alice"),
+            server: Some(b"server"),
+        };
+        let (record, _) = register(&server, b"password", identities, None);
+
+        let (client_login, ke1) = ClientLogin::start(thread_rng(), b"password");
+        let (server_login, ke2) = server
+            .login(thread_rng(), Some(&record), b"user", &ke1, identities, b"")
+            .unwrap();
+        let client = client_login.finish(&ke2, identities, b"", None).unwrap();
+        assert_eq!(
+            &server_login.finish(client.ke3()).unwrap(),
+            client.session_key()
+        );
+
+        // the identities are part of the envelope
+        assert!(matches!(
+            login(&server, Some(&record), b"password", None),
+            Err(OpaqueError::EnvelopeRecovery)
+        ));
+    }
+
+    #[test]
+    fn export_key_bind() {
+        let server = ServerSetup::new(thread_rng());
+        let (record, export_key) = register(&server, b"password", Identities::default(), None);
+        let (_, client) = login(&server, Some(&record), b"password", None).unwrap();
+
+        assert_eq!(
+            client.export_key().bind(b"session"),
+            export_key.bind(b"session")
+        );
+        assert_ne!(export_key.bind(b"session 1"), export_key.bind(b"session 2"));
+    }
+
+    #[test]
+    fn server_setup_encode_decode() {
+        let server = ServerSetup::new(thread_rng());
+        let (record, _) = register(&server, b"password", Identities::default(), None);
+
+        let decoded = ServerSetup::try_from(server.to_bytes().as_ref()).unwrap();
+        assert!(login(&decoded, Some(&record), b"password", None).is_ok());
+    }
+}
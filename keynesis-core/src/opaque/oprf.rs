@@ -0,0 +1,190 @@
+//! the OPRF of [RFC9497] (mode `0x00`, ristretto255-SHA512) used
+//! by OPAQUE
+//!
+//! [RFC9497]: https://www.rfc-editor.org/rfc/rfc9497.html
+
+use crate::opaque::OpaqueError;
+use cryptoxide::{digest::Digest as _, sha2::Sha512};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_TABLE,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::IsIdentity as _,
+};
+use rand_core::{CryptoRng, RngCore};
+
+const CONTEXT: &[u8] = b"OPRFV1-\x00-ristretto255-SHA512";
+
+/// size of the encoded elements and scalars
+pub(crate) const ELEMENT_SIZE: usize = 32;
+/// size of the output of the hash function
+pub(crate) const HASH_SIZE: usize = 64;
+
+/// `expand_message_xmd` of RFC9380 with SHA512
+fn expand_message_xmd(messages: &[&[u8]], dst: &[&[u8]], output: &mut [u8]) {
+    let dst_len: usize = dst.iter().map(|d| d.len()).sum();
+    debug_assert!(dst_len <= 255);
+    debug_assert!(output.len() <= 255 * HASH_SIZE && output.len() <= u16::MAX as usize);
+
+    let dst_prime = |hasher: &mut Sha512| {
+        for d in dst {
+            hasher.input(d);
+        }
+        hasher.input(&[dst_len as u8]);
+    };
+
+    let mut hasher = Sha512::new();
+    hasher.input(&[0; 128]);
+    for message in messages {
+        hasher.input(message);
+    }
+    hasher.input(&(output.len() as u16).to_be_bytes());
+    hasher.input(&[0]);
+    dst_prime(&mut hasher);
+    let mut b0 = [0; HASH_SIZE];
+    hasher.result(&mut b0);
+
+    let mut bi = [0; HASH_SIZE];
+    for (i, chunk) in output.chunks_mut(HASH_SIZE).enumerate() {
+        let mut hasher = Sha512::new();
+        if i == 0 {
+            hasher.input(&b0);
+        } else {
+            let mut xored = [0; HASH_SIZE];
+            for (x, (a, b)) in xored.iter_mut().zip(b0.iter().zip(bi.iter())) {
+                *x = a ^ b;
+            }
+            hasher.input(&xored);
+        }
+        hasher.input(&[(i + 1) as u8]);
+        dst_prime(&mut hasher);
+        hasher.result(&mut bi);
+        chunk.copy_from_slice(&bi[..chunk.len()]);
+    }
+}
+
+fn hash_to_group(input: &[u8]) -> RistrettoPoint {
+    let mut uniform = [0; 64];
+    expand_message_xmd(&[input], &[b"HashToGroup-", CONTEXT], &mut uniform);
+    RistrettoPoint::from_uniform_bytes(&uniform)
+}
+
+fn hash_to_scalar(input: &[&[u8]], dst: &[u8]) -> Scalar {
+    let mut uniform = [0; 64];
+    expand_message_xmd(input, &[dst, CONTEXT], &mut uniform);
+    Scalar::from_bytes_mod_order_wide(&uniform)
+}
+
+/// derive the key pair from the given `seed` and `info`
+pub(crate) fn derive_key_pair(seed: &[u8], info: &[u8]) -> (Scalar, RistrettoPoint) {
+    let info_len = (info.len() as u16).to_be_bytes();
+    for counter in 0..=u8::MAX {
+        let secret = hash_to_scalar(&[seed, &info_len, info, &[counter]], b"DeriveKeyPair");
+        if secret != Scalar::zero() {
+            return (secret, &secret * &RISTRETTO_BASEPOINT_TABLE);
+        }
+    }
+    unreachable!("deriving a key pair cannot fail 256 times in a row")
+}
+
+pub(crate) fn random_scalar<Rng>(rng: &mut Rng) -> Scalar
+where
+    Rng: RngCore + CryptoRng,
+{
+    let mut bytes = [0; 64];
+    rng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+pub(crate) fn decode_element(bytes: &[u8]) -> Result<RistrettoPoint, OpaqueError> {
+    let bytes = <[u8; ELEMENT_SIZE]>::try_from(bytes).map_err(|_| OpaqueError::InvalidPoint)?;
+    CompressedRistretto(bytes)
+        .decompress()
+        .filter(|point| !point.is_identity())
+        .ok_or(OpaqueError::InvalidPoint)
+}
+
+pub(crate) fn decode_scalar(bytes: &[u8]) -> Result<Scalar, OpaqueError> {
+    let bytes = <[u8; ELEMENT_SIZE]>::try_from(bytes).map_err(|_| OpaqueError::InvalidScalar)?;
+    Scalar::from_canonical_bytes(bytes)
+        .filter(|scalar| *scalar != Scalar::zero())
+        .ok_or(OpaqueError::InvalidScalar)
+}
+
+/// blind the `input`, returns the blind and the blinded element
+pub(crate) fn blind<Rng>(rng: &mut Rng, input: &[u8]) -> (Scalar, RistrettoPoint)
+where
+    Rng: RngCore + CryptoRng,
+{
+    let blind = random_scalar(rng);
+    (blind, hash_to_group(input) * blind)
+}
+
+/// evaluate the blinded element with the server's key
+pub(crate) fn blind_evaluate(key: &Scalar, blinded: &RistrettoPoint) -> RistrettoPoint {
+    blinded * key
+}
+
+/// unblind the evaluated element and compute the output of the OPRF
+pub(crate) fn finalize(
+    input: &[u8],
+    blind: &Scalar,
+    evaluated: &RistrettoPoint,
+) -> [u8; HASH_SIZE] {
+    let unblinded = (evaluated * blind.invert()).compress();
+
+    let mut hasher = Sha512::new();
+    hasher.input(&(input.len() as u16).to_be_bytes());
+    hasher.input(input);
+    hasher.input(&(ELEMENT_SIZE as u16).to_be_bytes());
+    hasher.input(unblinded.as_bytes());
+    hasher.input(b"Finalize");
+
+    let mut output = [0; HASH_SIZE];
+    hasher.result(&mut output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn rfc9380_expand_message_xmd_sha512() {
+        let mut output = [0; 0x20];
+        expand_message_xmd(
+            &[b""],
+            &[b"QUUX-V01-CS02-with-expander-SHA512-256"],
+            &mut output,
+        );
+
+        assert_eq!(
+            hex::encode(output),
+            "6b9a7312411d92f921c6f68ca0b6380730a1a4d982c507211a90964c394179ba"
+        );
+    }
+
+    #[test]
+    fn rfc9497_derive_key_pair() {
+        let seed = [0xa3; 32];
+        let (secret, _) = derive_key_pair(&seed, b"test key");
+
+        assert_eq!(
+            hex::encode(secret.as_bytes()),
+            "5ebcea5ee37023ccb9fc2d2019f9d7737be85591ae8652ffa9ef0f4d37063b0e"
+        );
+    }
+
+    #[quickcheck]
+    fn evaluate_unblinded(input: Vec<u8>) -> bool {
+        let key = random_scalar(&mut thread_rng());
+        let (blind, blinded) = blind(&mut thread_rng(), &input);
+        let evaluated = blind_evaluate(&key, &blinded);
+
+        let (other_blind, other_blinded) = super::blind(&mut thread_rng(), &input);
+        let other_evaluated = blind_evaluate(&key, &other_blinded);
+
+        finalize(&input, &blind, &evaluated) == finalize(&input, &other_blind, &other_evaluated)
+    }
+}
@@ -0,0 +1,398 @@
+/*!
+# Password authenticated key exchange
+
+[SPAKE2] over edwards25519: two parties sharing a low entropy password
+(a pairing code for example) establish a [`SharedSecret`] without having
+to know any keys in advance. An attacker in the middle can only test one
+password guess per exchange.
+
+The hash, the KDF and the MAC of the [SPAKE2] suite are SHA512,
+HKDF-SHA512 and HMAC-SHA512 so the shared secret is 32 bytes long.
+
+```
+use keynesis_core::pake::{Role, Spake2};
+# use rand::thread_rng;
+
+let (alice, alice_message) = Spake2::start(thread_rng(), Role::A, b"1234", b"alice", b"bob");
+let (bob, bob_message) = Spake2::start(thread_rng(), Role::B, b"1234", b"alice", b"bob");
+
+let alice = alice.finish(&bob_message).unwrap();
+let bob = bob.finish(&alice_message).unwrap();
+
+// the confirmations are exchanged so both parties know they have the
+// same shared secret
+let alice_confirmation = *alice.confirmation();
+let alice_secret = alice.verify(bob.confirmation()).unwrap();
+let bob_secret = bob.verify(&alice_confirmation).unwrap();
+
+assert_eq!(alice_secret, bob_secret);
+```
+
+The shared secret can then be used as the pre shared key of a Noise
+handshake (see [`Seed`](crate::Seed)'s `From<&SharedSecret>`).
+
+The password is hashed with SHA512 before being mapped to a scalar. If
+the password is a long lived, low entropy secret, it should be stretched
+first with one of the [`kdf`](crate::kdf) functions.
+
+[SPAKE2]: https://www.rfc-editor.org/rfc/rfc9382.html
+*/
+
+use crate::{
+    canonical::{self, Canonical, CanonicalError},
+    key::SharedSecret,
+    memsec,
+    memsec::Scrubbed as _,
+};
+use cryptoxide::{
+    digest::Digest as _,
+    hkdf::{hkdf_expand, hkdf_extract},
+    hmac::Hmac,
+    mac::Mac as _,
+    sha2::Sha512,
+};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_TABLE,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::IsIdentity as _,
+};
+use rand_core::{CryptoRng, RngCore};
+use std::convert::TryFrom;
+use thiserror::Error;
+
+/// the `M` point of RFC9382 for edwards25519
+const M: [u8; 32] = [
+    0xd0, 0x48, 0x03, 0x2c, 0x6e, 0xa0, 0xb6, 0xd6, 0x97, 0xdd, 0xc2, 0xe8, 0x6b, 0xda, 0x85, 0xa3,
+    0x3a, 0xda, 0xc9, 0x20, 0xf1, 0xbf, 0x18, 0xe1, 0xb0, 0xc6, 0xd1, 0x66, 0xa5, 0xce, 0xcd, 0xaf,
+];
+
+/// the `N` point of RFC9382 for edwards25519
+const N: [u8; 32] = [
+    0xd3, 0xbf, 0xb5, 0x18, 0xf4, 0x4f, 0x34, 0x30, 0xf2, 0x9d, 0x0c, 0x92, 0xaf, 0x50, 0x38, 0x65,
+    0xa1, 0xed, 0x32, 0x81, 0xdc, 0x69, 0xb3, 0x5d, 0xd8, 0x68, 0xba, 0x85, 0xf8, 0x86, 0xc4, 0xab,
+];
+
+const CONFIRMATION_KEYS: &[u8] = b"ConfirmationKeys";
+
+/// the side of the exchange
+///
+/// the two parties need to agree in advance on who is `A` and who is
+/// `B` (the one initiating the pairing is usually `A`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    A,
+    B,
+}
+
+/// the first step of the SPAKE2 exchange
+///
+/// created with [`Spake2::start`] alongside the [`Message`] to send to
+/// the other party.
+pub struct Spake2 {
+    role: Role,
+    x: Scalar,
+    w: Scalar,
+    message: Message,
+    id_a: Vec<u8>,
+    id_b: Vec<u8>,
+}
+
+/// the message exchanged by the two parties
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Message([u8; Self::SIZE]);
+
+/// the second step of the SPAKE2 exchange
+///
+/// the [`confirmation`](Confirming::confirmation) needs to be sent to the
+/// other party and the other party's confirmation needs to be verified
+/// to access the [`SharedSecret`].
+pub struct Confirming {
+    shared_secret: SharedSecret,
+    confirmation: [u8; Self::CONFIRMATION_SIZE],
+    expected: [u8; Self::CONFIRMATION_SIZE],
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PakeError {
+    #[error("Invalid size, expecting {}", Message::SIZE)]
+    InvalidSize,
+
+    #[error("The message is not a valid point")]
+    InvalidPoint,
+
+    #[error("The confirmation does not match, the password may be different")]
+    InvalidConfirmation,
+}
+
+impl Message {
+    pub const SIZE: usize = 32;
+}
+
+impl Spake2 {
+    /// start the exchange with the given password and the identities
+    /// of the two parties (`id_a` and `id_b` can be empty)
+    pub fn start<Rng>(
+        mut rng: Rng,
+        role: Role,
+        password: &[u8],
+        id_a: &[u8],
+        id_b: &[u8],
+    ) -> (Self, Message)
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let mut bytes = [0; 64];
+        rng.fill_bytes(&mut bytes);
+        let x = Scalar::from_bytes_mod_order_wide(&bytes);
+
+        let mut hasher = Sha512::new();
+        hasher.input(password);
+        hasher.result(&mut bytes);
+        let w = Scalar::from_bytes_mod_order_wide(&bytes);
+        bytes.scrub();
+
+        let blinding = match role {
+            Role::A => point(&M),
+            Role::B => point(&N),
+        };
+        let public = &x * &ED25519_BASEPOINT_TABLE + w * blinding;
+        let message = Message(public.compress().to_bytes());
+
+        let spake2 = Self {
+            role,
+            x,
+            w,
+            message,
+            id_a: id_a.to_owned(),
+            id_b: id_b.to_owned(),
+        };
+
+        (spake2, message)
+    }
+
+    /// process the other party's message
+    pub fn finish(self, peer: &Message) -> Result<Confirming, PakeError> {
+        let received = CompressedEdwardsY(peer.0)
+            .decompress()
+            .ok_or(PakeError::InvalidPoint)?;
+
+        let blinding = match self.role {
+            Role::A => point(&N),
+            Role::B => point(&M),
+        };
+        let k = ((received - self.w * blinding) * self.x).mul_by_cofactor();
+        if k.is_identity() {
+            return Err(PakeError::InvalidPoint);
+        }
+
+        let (p_a, p_b) = match self.role {
+            Role::A => (&self.message, peer),
+            Role::B => (peer, &self.message),
+        };
+
+        let mut transcript = Sha512::new();
+        for entry in [
+            self.id_a.as_slice(),
+            self.id_b.as_slice(),
+            &p_a.0,
+            &p_b.0,
+            k.compress().as_bytes(),
+            self.w.as_bytes(),
+        ] {
+            transcript.input(&(entry.len() as u64).to_le_bytes());
+            transcript.input(entry);
+        }
+        let mut tt = [0; 64];
+        transcript.result(&mut tt);
+        let (ke, ka) = tt.split_at(32);
+
+        let mut prk = [0; 64];
+        let mut kc = [0; 64];
+        hkdf_extract(Sha512::new(), &[], ka, &mut prk);
+        hkdf_expand(Sha512::new(), &prk, CONFIRMATION_KEYS, &mut kc);
+        let (kc_a, kc_b) = kc.split_at(32);
+
+        let mut c_a = [0; Confirming::CONFIRMATION_SIZE];
+        let mut c_b = [0; Confirming::CONFIRMATION_SIZE];
+        let mut mac = Hmac::new(Sha512::new(), kc_a);
+        mac.input(&tt);
+        mac.raw_result(&mut c_a);
+        let mut mac = Hmac::new(Sha512::new(), kc_b);
+        mac.input(&tt);
+        mac.raw_result(&mut c_b);
+
+        let mut shared_secret = [0; SharedSecret::SIZE];
+        shared_secret.copy_from_slice(ke);
+        tt.scrub();
+        prk.scrub();
+        kc.scrub();
+
+        let (confirmation, expected) = match self.role {
+            Role::A => (c_a, c_b),
+            Role::B => (c_b, c_a),
+        };
+
+        Ok(Confirming {
+            shared_secret: SharedSecret::new(shared_secret),
+            confirmation,
+            expected,
+        })
+    }
+}
+
+impl Confirming {
+    pub const CONFIRMATION_SIZE: usize = 64;
+
+    /// the confirmation to send to the other party
+    pub fn confirmation(&self) -> &[u8; Self::CONFIRMATION_SIZE] {
+        &self.confirmation
+    }
+
+    /// check the other party's confirmation and release the [`SharedSecret`]
+    ///
+    /// the comparison is done in constant time.
+    pub fn verify(self, confirmation: &[u8]) -> Result<SharedSecret, PakeError> {
+        if confirmation.len() != Self::CONFIRMATION_SIZE
+            || !unsafe {
+                memsec::memeq(
+                    self.expected.as_ptr(),
+                    confirmation.as_ptr(),
+                    Self::CONFIRMATION_SIZE,
+                )
+            }
+        {
+            return Err(PakeError::InvalidConfirmation);
+        }
+
+        Ok(self.shared_secret)
+    }
+}
+
+impl Drop for Spake2 {
+    fn drop(&mut self) {
+        self.x = Scalar::zero();
+        self.w = Scalar::zero();
+    }
+}
+
+fn point(bytes: &[u8; 32]) -> EdwardsPoint {
+    CompressedEdwardsY(*bytes)
+        .decompress()
+        .expect("the constant is a valid point")
+}
+
+/* Conversion ************************************************************** */
+
+impl AsRef<[u8]> for Message {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Message> for [u8; Message::SIZE] {
+    fn from(message: Message) -> Self {
+        message.0
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Message {
+    type Error = PakeError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let bytes = <[u8; Self::SIZE]>::try_from(value).map_err(|_| PakeError::InvalidSize)?;
+        Ok(Self(bytes))
+    }
+}
+
+/* Canonical *************************************************************** */
+
+impl Canonical for Message {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    /// the message needs to be the canonical encoding of a point
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        canonical::check_point(bytes)?;
+        Self::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn exchange(
+        password_a: &[u8],
+        password_b: &[u8],
+    ) -> Result<(SharedSecret, SharedSecret), PakeError> {
+        let (a, message_a) = Spake2::start(thread_rng(), Role::A, password_a, b"a", b"b");
+        let (b, message_b) = Spake2::start(thread_rng(), Role::B, password_b, b"a", b"b");
+
+        let a = a.finish(&message_b)?;
+        let b = b.finish(&message_a)?;
+        let confirmation_a = *a.confirmation();
+
+        Ok((a.verify(b.confirmation())?, b.verify(&confirmation_a)?))
+    }
+
+    #[quickcheck]
+    fn same_password(password: Vec<u8>) -> bool {
+        let (a, b) = exchange(&password, &password).unwrap();
+        a == b
+    }
+
+    #[test]
+    fn different_password() {
+        assert!(matches!(
+            exchange(b"1234", b"1235"),
+            Err(PakeError::InvalidConfirmation)
+        ));
+    }
+
+    #[test]
+    fn same_role() {
+        let (a, _) = Spake2::start(thread_rng(), Role::A, b"1234", b"a", b"b");
+        let (_, message) = Spake2::start(thread_rng(), Role::A, b"1234", b"a", b"b");
+        let a = a.finish(&message).unwrap();
+
+        let (b, _) = Spake2::start(thread_rng(), Role::A, b"1234", b"a", b"b");
+        let b = b.finish(&message).unwrap();
+
+        assert!(a.verify(b.confirmation()).is_err());
+    }
+
+    #[test]
+    fn invalid_point() {
+        let (a, _) = Spake2::start(thread_rng(), Role::A, b"1234", b"a", b"b");
+
+        // cancelling the password's blinding yields the identity
+        let message = Message((a.w * point(&N)).compress().to_bytes());
+        assert!(matches!(a.finish(&message), Err(PakeError::InvalidPoint)));
+
+        let (a, _) = Spake2::start(thread_rng(), Role::A, b"1234", b"a", b"b");
+        let invalid = (0..=u8::MAX)
+            .map(|y| [y; Message::SIZE])
+            .find(|bytes| CompressedEdwardsY(*bytes).decompress().is_none())
+            .unwrap();
+        assert!(matches!(
+            a.finish(&Message(invalid)),
+            Err(PakeError::InvalidPoint)
+        ));
+    }
+
+    #[test]
+    fn canonical_encoding() {
+        let (_, message) = Spake2::start(thread_rng(), Role::A, b"1234", b"a", b"b");
+        let decoded = Message::from_bytes_strict(&message.to_canonical_bytes()).unwrap();
+        assert_eq!(decoded, message);
+
+        assert_eq!(
+            Message::from_bytes_strict(&crate::canonical::tests::NON_CANONICAL_POINT),
+            Err(CanonicalError::NonCanonical)
+        );
+    }
+}
@@ -0,0 +1,1065 @@
+/*!
+# Asynchronous key agreement with prekeys
+
+[X3DH] like key agreement: the responder publishes in advance a
+[`PreKeyBundle`] (its identity, a signed prekey and optionally a one time
+prekey). The initiator can then establish a [`SharedSecret`] with the
+responder while the responder is offline. The initiator sends an
+[`InitialMessage`] along its first encrypted message so the responder can
+compute the same [`SharedSecret`] once back online.
+
+```
+use keynesis_core::{key::ed25519_extended::SecretKey, prekey::{self, OneTimePreKey, SignedPreKey}};
+# use rand::thread_rng;
+
+let alice = SecretKey::new(thread_rng());
+let bob = SecretKey::new(thread_rng());
+
+// bob prepares and publishes its bundle
+let signed_prekey = SignedPreKey::new(thread_rng(), 1, &bob);
+let one_time_prekey = OneTimePreKey::new(thread_rng(), 1);
+let bundle = signed_prekey.bundle(&bob.public_key(), Some(&one_time_prekey));
+
+// alice retrieves the bundle and initiate the agreement
+let (alice_agreement, message) = prekey::initiate(thread_rng(), &alice, &bundle).unwrap();
+
+// bob receives the initial message
+let bob_agreement =
+    prekey::respond(&bob, &signed_prekey, Some(&one_time_prekey), &message).unwrap();
+
+assert_eq!(alice_agreement.shared_secret(), bob_agreement.shared_secret());
+```
+
+[X3DH]: https://signal.org/docs/specifications/x3dh/
+*/
+
+use crate::{
+    canonical::{Canonical, CanonicalError},
+    key::{
+        ed25519::{PublicKey, Signature},
+        ed25519_extended::SecretKey,
+        SharedSecret,
+    },
+    memsec::Scrubbed as _,
+};
+use cryptoxide::{
+    hkdf::{hkdf_expand, hkdf_extract},
+    sha2::Sha512,
+};
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::TryFrom,
+};
+use thiserror::Error;
+
+const SIGNATURE_CONTEXT: &[u8] = b"keynesis:prekey:signed";
+const KDF_INFO: &[u8] = b"keynesis:prekey:x3dh";
+
+/// identifier of a prekey, chosen by the owner of the prekey
+pub type PreKeyId = u32;
+
+/// the medium term prekey of the responder, signed with its identity key
+#[derive(Clone)]
+pub struct SignedPreKey {
+    id: PreKeyId,
+    key: SecretKey,
+    signature: Signature,
+}
+
+/// a prekey that is meant to be used only once
+#[derive(Clone)]
+pub struct OneTimePreKey {
+    id: PreKeyId,
+    key: SecretKey,
+}
+
+/// the public keys the responder publishes so initiators can
+/// start a session while the responder is offline
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PreKeyBundle {
+    identity: PublicKey,
+    signed_prekey_id: PreKeyId,
+    signed_prekey: PublicKey,
+    signature: Signature,
+    one_time_prekey: Option<(PreKeyId, PublicKey)>,
+}
+
+/// message the initiator sends to the responder so the responder can
+/// compute the same [`Agreement`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InitialMessage {
+    identity: PublicKey,
+    ephemeral: PublicKey,
+    signed_prekey_id: PreKeyId,
+    one_time_prekey_id: Option<PreKeyId>,
+}
+
+/// result of the key agreement
+///
+/// the [`associated_data`](Agreement::associated_data) binds the two
+/// identities and should be authenticated with the first messages
+/// encrypted with the [`shared_secret`](Agreement::shared_secret).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Agreement {
+    shared_secret: SharedSecret,
+    associated_data: [u8; Agreement::ASSOCIATED_DATA_SIZE],
+}
+
+/// the public prekeys a responder publishes to a directory
+///
+/// The directory hands out a [`PreKeyBundle`] for every request, consuming
+/// one of the one time prekeys each time. Once the one time prekeys are
+/// exhausted the bundles only contain the signed prekey.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreKeyUpload {
+    identity: PublicKey,
+    signed_prekey_id: PreKeyId,
+    signed_prekey: PublicKey,
+    signature: Signature,
+    one_time_prekeys: VecDeque<(PreKeyId, PublicKey)>,
+}
+
+/// the responder's private prekeys
+///
+/// keeps track of the current signed prekey (and the previous one so
+/// messages initiated before a rotation can still be answered) and
+/// of the one time prekeys that have not been used yet.
+pub struct PreKeyStore {
+    signed_prekey: SignedPreKey,
+    previous_signed_prekey: Option<SignedPreKey>,
+    signed_prekey_published: bool,
+    one_time_prekeys: BTreeMap<PreKeyId, OneTimePreKey>,
+    unpublished: Vec<PreKeyId>,
+    next_id: PreKeyId,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PreKeyError {
+    #[error("The signature of the signed prekey is not valid")]
+    InvalidSignature,
+
+    #[error("The message was made for the signed prekey {expected}, not {received}")]
+    UnexpectedSignedPreKey {
+        expected: PreKeyId,
+        received: PreKeyId,
+    },
+
+    #[error("The one time prekey of the message does not match the provided one")]
+    UnexpectedOneTimePreKey,
+
+    #[error("The one time prekey {0} is unknown or has already been used")]
+    UnknownOneTimePreKey(PreKeyId),
+
+    #[error("The prekeys belong to a different identity")]
+    UnexpectedIdentity,
+
+    #[error("Too many one time prekeys")]
+    TooManyOneTimePreKeys,
+
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+}
+
+/* Prekeys ***************************************************************** */
+
+impl SignedPreKey {
+    /// generate a new signed prekey with the given `id`, signed with
+    /// the `identity` key
+    pub fn new<Rng>(rng: Rng, id: PreKeyId, identity: &SecretKey) -> Self
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let key = SecretKey::new(rng);
+        let signature = identity.sign(signed_prekey_message(id, &key.public_key()));
+
+        Self { id, key, signature }
+    }
+
+    pub fn id(&self) -> PreKeyId {
+        self.id
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.key.public_key()
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// create the bundle to publish, with the optional one time prekey
+    pub fn bundle(
+        &self,
+        identity: &PublicKey,
+        one_time_prekey: Option<&OneTimePreKey>,
+    ) -> PreKeyBundle {
+        PreKeyBundle {
+            identity: *identity,
+            signed_prekey_id: self.id,
+            signed_prekey: self.public_key(),
+            signature: self.signature,
+            one_time_prekey: one_time_prekey.map(|k| (k.id, k.public_key())),
+        }
+    }
+}
+
+impl OneTimePreKey {
+    pub fn new<Rng>(rng: Rng, id: PreKeyId) -> Self
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        Self {
+            id,
+            key: SecretKey::new(rng),
+        }
+    }
+
+    pub fn id(&self) -> PreKeyId {
+        self.id
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.key.public_key()
+    }
+}
+
+fn signed_prekey_message(id: PreKeyId, key: &PublicKey) -> Vec<u8> {
+    let mut message = Vec::with_capacity(SIGNATURE_CONTEXT.len() + 4 + PublicKey::SIZE);
+    message.extend_from_slice(SIGNATURE_CONTEXT);
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(key.as_ref());
+    message
+}
+
+/* Bundle ****************************************************************** */
+
+impl PreKeyBundle {
+    /// size of the encoded bundle without the one time prekey
+    pub const MIN_SIZE: usize = PublicKey::SIZE + 4 + PublicKey::SIZE + Signature::SIZE + 1;
+    /// size of the encoded bundle with a one time prekey
+    pub const MAX_SIZE: usize = Self::MIN_SIZE + 4 + PublicKey::SIZE;
+
+    pub fn identity(&self) -> &PublicKey {
+        &self.identity
+    }
+
+    pub fn signed_prekey_id(&self) -> PreKeyId {
+        self.signed_prekey_id
+    }
+
+    pub fn signed_prekey(&self) -> &PublicKey {
+        &self.signed_prekey
+    }
+
+    pub fn one_time_prekey(&self) -> Option<(PreKeyId, &PublicKey)> {
+        self.one_time_prekey.as_ref().map(|(id, key)| (*id, key))
+    }
+
+    /// check the signed prekey has been signed by the identity
+    pub fn verify(&self) -> bool {
+        self.identity.verify(
+            signed_prekey_message(self.signed_prekey_id, &self.signed_prekey),
+            &self.signature,
+        )
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE);
+        bytes.extend_from_slice(self.identity.as_ref());
+        bytes.extend_from_slice(&self.signed_prekey_id.to_be_bytes());
+        bytes.extend_from_slice(self.signed_prekey.as_ref());
+        bytes.extend_from_slice(self.signature.as_ref());
+        match &self.one_time_prekey {
+            None => bytes.push(0),
+            Some((id, key)) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&id.to_be_bytes());
+                bytes.extend_from_slice(key.as_ref());
+            }
+        }
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PreKeyBundle {
+    type Error = PreKeyError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = Reader(bytes);
+
+        let identity = reader.public_key()?;
+        let signed_prekey_id = reader.id()?;
+        let signed_prekey = reader.public_key()?;
+        let signature = Signature::from(reader.array::<{ Signature::SIZE }>()?);
+        let one_time_prekey = match reader.array::<1>()? {
+            [0] => None,
+            [1] => Some((reader.id()?, reader.public_key()?)),
+            _ => return Err(PreKeyError::InvalidEncoding),
+        };
+        reader.finish()?;
+
+        Ok(Self {
+            identity,
+            signed_prekey_id,
+            signed_prekey,
+            signature,
+            one_time_prekey,
+        })
+    }
+}
+
+impl Canonical for PreKeyBundle {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    /// all the keys and the signature need to be canonical
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        let bundle = Self::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)?;
+
+        check_key(&bundle.identity)?;
+        check_key(&bundle.signed_prekey)?;
+        check_signature(&bundle.signature)?;
+        if let Some((_, key)) = &bundle.one_time_prekey {
+            check_key(key)?;
+        }
+
+        Ok(bundle)
+    }
+}
+
+/* Initial message ********************************************************* */
+
+impl InitialMessage {
+    /// size of the encoded message without the one time prekey
+    pub const MIN_SIZE: usize = PublicKey::SIZE + PublicKey::SIZE + 4 + 1;
+    /// size of the encoded message with a one time prekey
+    pub const MAX_SIZE: usize = Self::MIN_SIZE + 4;
+
+    pub fn identity(&self) -> &PublicKey {
+        &self.identity
+    }
+
+    pub fn signed_prekey_id(&self) -> PreKeyId {
+        self.signed_prekey_id
+    }
+
+    pub fn one_time_prekey_id(&self) -> Option<PreKeyId> {
+        self.one_time_prekey_id
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::MAX_SIZE);
+        bytes.extend_from_slice(self.identity.as_ref());
+        bytes.extend_from_slice(self.ephemeral.as_ref());
+        bytes.extend_from_slice(&self.signed_prekey_id.to_be_bytes());
+        match self.one_time_prekey_id {
+            None => bytes.push(0),
+            Some(id) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&id.to_be_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for InitialMessage {
+    type Error = PreKeyError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = Reader(bytes);
+
+        let identity = reader.public_key()?;
+        let ephemeral = reader.public_key()?;
+        let signed_prekey_id = reader.id()?;
+        let one_time_prekey_id = match reader.array::<1>()? {
+            [0] => None,
+            [1] => Some(reader.id()?),
+            _ => return Err(PreKeyError::InvalidEncoding),
+        };
+        reader.finish()?;
+
+        Ok(Self {
+            identity,
+            ephemeral,
+            signed_prekey_id,
+            one_time_prekey_id,
+        })
+    }
+}
+
+impl Canonical for InitialMessage {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    /// both the identity and the ephemeral keys need to be canonical
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        let message = Self::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)?;
+
+        check_key(&message.identity)?;
+        check_key(&message.ephemeral)?;
+
+        Ok(message)
+    }
+}
+
+fn check_key(key: &PublicKey) -> Result<(), CanonicalError> {
+    PublicKey::from_bytes_strict(key.as_ref()).map(|_| ())
+}
+
+fn check_signature(signature: &Signature) -> Result<(), CanonicalError> {
+    Signature::from_bytes_strict(signature.as_ref()).map(|_| ())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PreKeyError> {
+        if self.0.len() < N {
+            return Err(PreKeyError::InvalidEncoding);
+        }
+        let (bytes, remaining) = self.0.split_at(N);
+        self.0 = remaining;
+
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        Ok(array)
+    }
+
+    fn id(&mut self) -> Result<PreKeyId, PreKeyError> {
+        self.array().map(PreKeyId::from_be_bytes)
+    }
+
+    fn public_key(&mut self) -> Result<PublicKey, PreKeyError> {
+        self.array::<{ PublicKey::SIZE }>().map(PublicKey::from)
+    }
+
+    fn finish(self) -> Result<(), PreKeyError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(PreKeyError::InvalidEncoding)
+        }
+    }
+}
+
+/* Upload ****************************************************************** */
+
+impl PreKeyUpload {
+    /// size of the encoded upload without any one time prekeys
+    pub const MIN_SIZE: usize = PublicKey::SIZE + 4 + PublicKey::SIZE + Signature::SIZE + 2;
+    /// size of each of the encoded one time prekeys
+    pub const ONE_TIME_PREKEY_SIZE: usize = 4 + PublicKey::SIZE;
+    /// maximum number of one time prekeys in a single upload
+    pub const MAX_ONE_TIME_PREKEYS: usize = u16::MAX as usize;
+
+    pub fn identity(&self) -> &PublicKey {
+        &self.identity
+    }
+
+    pub fn signed_prekey_id(&self) -> PreKeyId {
+        self.signed_prekey_id
+    }
+
+    pub fn signed_prekey(&self) -> &PublicKey {
+        &self.signed_prekey
+    }
+
+    pub fn one_time_prekeys(&self) -> impl Iterator<Item = (PreKeyId, &PublicKey)> {
+        self.one_time_prekeys.iter().map(|(id, key)| (*id, key))
+    }
+
+    /// number of one time prekeys left
+    pub fn remaining_one_time_prekeys(&self) -> usize {
+        self.one_time_prekeys.len()
+    }
+
+    /// check the signed prekey has been signed by the identity
+    pub fn verify(&self) -> bool {
+        self.identity.verify(
+            signed_prekey_message(self.signed_prekey_id, &self.signed_prekey),
+            &self.signature,
+        )
+    }
+
+    /// retrieve the next bundle to hand out to an initiator
+    ///
+    /// the oldest one time prekey is removed from the upload and
+    /// added to the bundle.
+    pub fn take_bundle(&mut self) -> PreKeyBundle {
+        PreKeyBundle {
+            identity: self.identity,
+            signed_prekey_id: self.signed_prekey_id,
+            signed_prekey: self.signed_prekey,
+            signature: self.signature,
+            one_time_prekey: self.one_time_prekeys.pop_front(),
+        }
+    }
+
+    /// update with a newer upload of the same identity
+    ///
+    /// the signed prekey is replaced and the new one time prekeys are
+    /// appended (the ones we already know of are ignored).
+    pub fn merge(&mut self, newer: Self) -> Result<(), PreKeyError> {
+        if self.identity != newer.identity {
+            return Err(PreKeyError::UnexpectedIdentity);
+        }
+        if self.one_time_prekeys.len() + newer.one_time_prekeys.len() > Self::MAX_ONE_TIME_PREKEYS {
+            return Err(PreKeyError::TooManyOneTimePreKeys);
+        }
+
+        self.signed_prekey_id = newer.signed_prekey_id;
+        self.signed_prekey = newer.signed_prekey;
+        self.signature = newer.signature;
+        for (id, key) in newer.one_time_prekeys {
+            if !self.one_time_prekeys.iter().any(|(known, _)| *known == id) {
+                self.one_time_prekeys.push_back((id, key));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            Self::MIN_SIZE + self.one_time_prekeys.len() * Self::ONE_TIME_PREKEY_SIZE,
+        );
+        bytes.extend_from_slice(self.identity.as_ref());
+        bytes.extend_from_slice(&self.signed_prekey_id.to_be_bytes());
+        bytes.extend_from_slice(self.signed_prekey.as_ref());
+        bytes.extend_from_slice(self.signature.as_ref());
+        bytes.extend_from_slice(&(self.one_time_prekeys.len() as u16).to_be_bytes());
+        for (id, key) in &self.one_time_prekeys {
+            bytes.extend_from_slice(&id.to_be_bytes());
+            bytes.extend_from_slice(key.as_ref());
+        }
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PreKeyUpload {
+    type Error = PreKeyError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = Reader(bytes);
+
+        let identity = reader.public_key()?;
+        let signed_prekey_id = reader.id()?;
+        let signed_prekey = reader.public_key()?;
+        let signature = Signature::from(reader.array::<{ Signature::SIZE }>()?);
+        let count = u16::from_be_bytes(reader.array()?) as usize;
+        let one_time_prekeys = (0..count)
+            .map(|_| Ok((reader.id()?, reader.public_key()?)))
+            .collect::<Result<_, PreKeyError>>()?;
+        reader.finish()?;
+
+        Ok(Self {
+            identity,
+            signed_prekey_id,
+            signed_prekey,
+            signature,
+            one_time_prekeys,
+        })
+    }
+}
+
+impl Canonical for PreKeyUpload {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    /// all the keys and the signature need to be canonical
+    fn from_bytes_strict(bytes: &[u8]) -> Result<Self, CanonicalError> {
+        let upload = Self::try_from(bytes).map_err(|_| CanonicalError::InvalidEncoding)?;
+
+        check_key(&upload.identity)?;
+        check_key(&upload.signed_prekey)?;
+        check_signature(&upload.signature)?;
+        for (_, key) in &upload.one_time_prekeys {
+            check_key(key)?;
+        }
+
+        Ok(upload)
+    }
+}
+
+/* Store ******************************************************************* */
+
+impl PreKeyStore {
+    /// create a new store with a freshly generated signed prekey
+    /// and no one time prekeys
+    pub fn new<Rng>(rng: Rng, identity: &SecretKey) -> Self
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        Self {
+            signed_prekey: SignedPreKey::new(rng, 0, identity),
+            previous_signed_prekey: None,
+            signed_prekey_published: false,
+            one_time_prekeys: BTreeMap::new(),
+            unpublished: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    fn next_id(&mut self) -> PreKeyId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+
+    pub fn signed_prekey(&self) -> &SignedPreKey {
+        &self.signed_prekey
+    }
+
+    /// number of one time prekeys that have not been used yet
+    /// (published or not)
+    pub fn remaining_one_time_prekeys(&self) -> usize {
+        self.one_time_prekeys.len()
+    }
+
+    /// replace the signed prekey with a new one
+    ///
+    /// the current signed prekey is kept so the messages that
+    /// were initiated with it can still be answered until the
+    /// next rotation.
+    pub fn rotate_signed_prekey<Rng>(&mut self, rng: Rng, identity: &SecretKey)
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let id = self.next_id();
+        let signed_prekey = SignedPreKey::new(rng, id, identity);
+        self.previous_signed_prekey =
+            Some(std::mem::replace(&mut self.signed_prekey, signed_prekey));
+        self.signed_prekey_published = false;
+    }
+
+    /// generate `count` new one time prekeys
+    pub fn generate_one_time_prekeys<Rng>(&mut self, mut rng: Rng, count: usize)
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        for _ in 0..count {
+            let id = self.next_id();
+            self.one_time_prekeys
+                .insert(id, OneTimePreKey::new(&mut rng, id));
+            self.unpublished.push(id);
+        }
+    }
+
+    /// `true` if the signed prekey or some one time prekeys have not
+    /// been published yet
+    pub fn needs_upload(&self) -> bool {
+        !self.signed_prekey_published || !self.unpublished.is_empty()
+    }
+
+    /// create the upload for the directory
+    ///
+    /// the upload contains the signed prekey and at most `max` of the
+    /// one time prekeys that were not yet published. These are marked
+    /// as published.
+    pub fn upload(&mut self, identity: &PublicKey, max: usize) -> PreKeyUpload {
+        let max = max
+            .min(self.unpublished.len())
+            .min(PreKeyUpload::MAX_ONE_TIME_PREKEYS);
+        let one_time_prekeys = self
+            .unpublished
+            .drain(..max)
+            .filter_map(|id| self.one_time_prekeys.get(&id))
+            .map(|k| (k.id, k.public_key()))
+            .collect();
+
+        self.signed_prekey_published = true;
+
+        PreKeyUpload {
+            identity: *identity,
+            signed_prekey_id: self.signed_prekey.id,
+            signed_prekey: self.signed_prekey.public_key(),
+            signature: self.signed_prekey.signature,
+            one_time_prekeys,
+        }
+    }
+
+    /// compute the key agreement from the initiator's [`InitialMessage`]
+    ///
+    /// the one time prekey used by the initiator is removed from the
+    /// store so it cannot be used again.
+    pub fn respond(
+        &mut self,
+        identity: &SecretKey,
+        message: &InitialMessage,
+    ) -> Result<Agreement, PreKeyError> {
+        let signed_prekey = std::iter::once(&self.signed_prekey)
+            .chain(self.previous_signed_prekey.as_ref())
+            .find(|k| k.id == message.signed_prekey_id)
+            .ok_or(PreKeyError::UnexpectedSignedPreKey {
+                expected: self.signed_prekey.id,
+                received: message.signed_prekey_id,
+            })?;
+
+        let one_time_prekey = if let Some(id) = message.one_time_prekey_id {
+            let one_time_prekey = self
+                .one_time_prekeys
+                .remove(&id)
+                .ok_or(PreKeyError::UnknownOneTimePreKey(id))?;
+            self.unpublished.retain(|unpublished| *unpublished != id);
+            Some(one_time_prekey)
+        } else {
+            None
+        };
+
+        respond(identity, signed_prekey, one_time_prekey.as_ref(), message)
+    }
+}
+
+/* Agreement *************************************************************** */
+
+impl Agreement {
+    pub const ASSOCIATED_DATA_SIZE: usize = PublicKey::SIZE * 2;
+
+    fn new(
+        initiator: &PublicKey,
+        responder: &PublicKey,
+        secrets: [Option<SharedSecret>; 4],
+    ) -> Self {
+        // as per the X3DH specification, the input key material is
+        // prefixed with 32 0xFF bytes for domain separation
+        let mut ikm = vec![0xFF; 32];
+        for secret in secrets.iter().flatten() {
+            ikm.extend_from_slice(secret.as_ref());
+        }
+
+        let mut prk = [0; 64];
+        let mut okm = [0; SharedSecret::SIZE];
+        hkdf_extract(Sha512::new(), &[0; 64], &ikm, &mut prk);
+        hkdf_expand(Sha512::new(), &prk, KDF_INFO, &mut okm);
+        ikm.scrub();
+        prk.scrub();
+
+        let mut associated_data = [0; Self::ASSOCIATED_DATA_SIZE];
+        associated_data[..PublicKey::SIZE].copy_from_slice(initiator.as_ref());
+        associated_data[PublicKey::SIZE..].copy_from_slice(responder.as_ref());
+
+        Self {
+            shared_secret: SharedSecret::new(okm),
+            associated_data,
+        }
+    }
+
+    pub fn shared_secret(&self) -> &SharedSecret {
+        &self.shared_secret
+    }
+
+    /// the initiator's identity followed by the responder's identity
+    pub fn associated_data(&self) -> &[u8; Self::ASSOCIATED_DATA_SIZE] {
+        &self.associated_data
+    }
+
+    pub fn into_shared_secret(self) -> SharedSecret {
+        self.shared_secret
+    }
+}
+
+/// initiate the key agreement with the owner of the `bundle`
+///
+/// This will fail if the signature of the bundle is not valid.
+/// The returned [`InitialMessage`] needs to be sent to the responder.
+pub fn initiate<Rng>(
+    rng: Rng,
+    identity: &SecretKey,
+    bundle: &PreKeyBundle,
+) -> Result<(Agreement, InitialMessage), PreKeyError>
+where
+    Rng: RngCore + CryptoRng,
+{
+    if !bundle.verify() {
+        return Err(PreKeyError::InvalidSignature);
+    }
+
+    let ephemeral = SecretKey::new(rng);
+
+    let agreement = Agreement::new(
+        &identity.public_key(),
+        &bundle.identity,
+        [
+            Some(identity.exchange(&bundle.signed_prekey)),
+            Some(ephemeral.exchange(&bundle.identity)),
+            Some(ephemeral.exchange(&bundle.signed_prekey)),
+            bundle
+                .one_time_prekey
+                .as_ref()
+                .map(|(_, key)| ephemeral.exchange(key)),
+        ],
+    );
+
+    let message = InitialMessage {
+        identity: identity.public_key(),
+        ephemeral: ephemeral.public_key(),
+        signed_prekey_id: bundle.signed_prekey_id,
+        one_time_prekey_id: bundle.one_time_prekey.as_ref().map(|(id, _)| *id),
+    };
+
+    Ok((agreement, message))
+}
+
+/// compute the key agreement from the initiator's [`InitialMessage`]
+///
+/// the prekeys need to be the ones referred by the message. The one
+/// time prekey should be deleted once used.
+pub fn respond(
+    identity: &SecretKey,
+    signed_prekey: &SignedPreKey,
+    one_time_prekey: Option<&OneTimePreKey>,
+    message: &InitialMessage,
+) -> Result<Agreement, PreKeyError> {
+    if signed_prekey.id != message.signed_prekey_id {
+        return Err(PreKeyError::UnexpectedSignedPreKey {
+            expected: signed_prekey.id,
+            received: message.signed_prekey_id,
+        });
+    }
+    if one_time_prekey.map(|k| k.id) != message.one_time_prekey_id {
+        return Err(PreKeyError::UnexpectedOneTimePreKey);
+    }
+
+    Ok(Agreement::new(
+        &message.identity,
+        &identity.public_key(),
+        [
+            Some(signed_prekey.key.exchange(&message.identity)),
+            Some(identity.exchange(&message.ephemeral)),
+            Some(signed_prekey.key.exchange(&message.ephemeral)),
+            one_time_prekey.map(|k| k.key.exchange(&message.ephemeral)),
+        ],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen};
+    use rand::thread_rng;
+
+    impl Arbitrary for PreKeyBundle {
+        fn arbitrary(g: &mut Gen) -> Self {
+            Self {
+                identity: PublicKey::arbitrary(g),
+                signed_prekey_id: PreKeyId::arbitrary(g),
+                signed_prekey: PublicKey::arbitrary(g),
+                signature: Signature::arbitrary(g),
+                one_time_prekey: Option::<(PreKeyId, PublicKey)>::arbitrary(g),
+            }
+        }
+    }
+
+    impl Arbitrary for InitialMessage {
+        fn arbitrary(g: &mut Gen) -> Self {
+            Self {
+                identity: PublicKey::arbitrary(g),
+                ephemeral: PublicKey::arbitrary(g),
+                signed_prekey_id: PreKeyId::arbitrary(g),
+                one_time_prekey_id: Option::<PreKeyId>::arbitrary(g),
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn agreement(alice: SecretKey, bob: SecretKey, with_one_time_prekey: bool) -> bool {
+        let signed_prekey = SignedPreKey::new(thread_rng(), 1, &bob);
+        let one_time_prekey = OneTimePreKey::new(thread_rng(), 2);
+        let one_time_prekey = Some(&one_time_prekey).filter(|_| with_one_time_prekey);
+        let bundle = signed_prekey.bundle(&bob.public_key(), one_time_prekey);
+
+        let (alice_agreement, message) = initiate(thread_rng(), &alice, &bundle).unwrap();
+        let bob_agreement = respond(&bob, &signed_prekey, one_time_prekey, &message).unwrap();
+
+        alice_agreement == bob_agreement
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let bob = SecretKey::new(thread_rng());
+        let mallory = SecretKey::new(thread_rng());
+        let signed_prekey = SignedPreKey::new(thread_rng(), 1, &mallory);
+        let bundle = signed_prekey.bundle(&bob.public_key(), None);
+
+        assert!(matches!(
+            initiate(thread_rng(), &mallory, &bundle),
+            Err(PreKeyError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn unexpected_prekeys() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let signed_prekey = SignedPreKey::new(thread_rng(), 1, &bob);
+        let other_signed_prekey = SignedPreKey::new(thread_rng(), 2, &bob);
+        let one_time_prekey = OneTimePreKey::new(thread_rng(), 1);
+        let bundle = signed_prekey.bundle(&bob.public_key(), Some(&one_time_prekey));
+
+        let (_, message) = initiate(thread_rng(), &alice, &bundle).unwrap();
+
+        assert!(matches!(
+            respond(&bob, &other_signed_prekey, Some(&one_time_prekey), &message),
+            Err(PreKeyError::UnexpectedSignedPreKey {
+                expected: 2,
+                received: 1
+            })
+        ));
+        assert!(matches!(
+            respond(&bob, &signed_prekey, None, &message),
+            Err(PreKeyError::UnexpectedOneTimePreKey)
+        ));
+    }
+
+    #[quickcheck]
+    fn bundle_encode_decode(bundle: PreKeyBundle) -> bool {
+        let bytes = bundle.to_bytes();
+        PreKeyBundle::try_from(bytes.as_slice()).unwrap() == bundle
+    }
+
+    #[quickcheck]
+    fn initial_message_encode_decode(message: InitialMessage) -> bool {
+        let bytes = message.to_bytes();
+        InitialMessage::try_from(bytes.as_slice()).unwrap() == message
+    }
+
+    #[quickcheck]
+    fn bundle_decode_trailing_bytes(bundle: PreKeyBundle) -> bool {
+        let mut bytes = bundle.to_bytes();
+        bytes.push(0);
+        PreKeyBundle::try_from(bytes.as_slice()).is_err()
+    }
+
+    #[test]
+    fn canonical_encoding() {
+        use crate::canonical::tests::NON_CANONICAL_POINT;
+
+        let bob = SecretKey::new(thread_rng());
+        let mut store = PreKeyStore::new(thread_rng(), &bob);
+        store.generate_one_time_prekeys(thread_rng(), 2);
+        let mut upload = store.upload(&bob.public_key(), 2);
+        let bundle = upload.take_bundle();
+
+        let decoded = PreKeyUpload::from_bytes_strict(&upload.to_canonical_bytes()).unwrap();
+        assert_eq!(decoded, upload);
+        let decoded = PreKeyBundle::from_bytes_strict(&bundle.to_canonical_bytes()).unwrap();
+        assert_eq!(decoded, bundle);
+
+        let (_, message) = initiate(thread_rng(), &bob, &bundle).unwrap();
+        let decoded = InitialMessage::from_bytes_strict(&message.to_canonical_bytes()).unwrap();
+        assert_eq!(decoded, message);
+
+        // the lenient decoding accepts the non canonical identity
+        let mut bytes = bundle.to_bytes();
+        bytes[..PublicKey::SIZE].copy_from_slice(&NON_CANONICAL_POINT);
+        assert!(PreKeyBundle::try_from(bytes.as_slice()).is_ok());
+        assert_eq!(
+            PreKeyBundle::from_bytes_strict(&bytes),
+            Err(CanonicalError::NonCanonical)
+        );
+
+        let mut bytes = message.to_bytes();
+        bytes[PublicKey::SIZE..2 * PublicKey::SIZE].copy_from_slice(&NON_CANONICAL_POINT);
+        assert_eq!(
+            InitialMessage::from_bytes_strict(&bytes),
+            Err(CanonicalError::NonCanonical)
+        );
+    }
+
+    impl Arbitrary for PreKeyUpload {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let count = usize::arbitrary(g) % 8;
+            Self {
+                identity: PublicKey::arbitrary(g),
+                signed_prekey_id: PreKeyId::arbitrary(g),
+                signed_prekey: PublicKey::arbitrary(g),
+                signature: Signature::arbitrary(g),
+                one_time_prekeys: (0..count)
+                    .map(|_| (PreKeyId::arbitrary(g), PublicKey::arbitrary(g)))
+                    .collect(),
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn upload_encode_decode(upload: PreKeyUpload) -> bool {
+        let bytes = upload.to_bytes();
+        PreKeyUpload::try_from(bytes.as_slice()).unwrap() == upload
+    }
+
+    #[test]
+    fn store_exhaustion() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let mut store = PreKeyStore::new(thread_rng(), &bob);
+        store.generate_one_time_prekeys(thread_rng(), 2);
+        assert!(store.needs_upload());
+
+        let mut upload = store.upload(&bob.public_key(), usize::MAX);
+        assert!(upload.verify());
+        assert!(!store.needs_upload());
+        assert_eq!(upload.remaining_one_time_prekeys(), 2);
+
+        for remaining in (0..2).rev() {
+            let bundle = upload.take_bundle();
+            assert!(bundle.one_time_prekey().is_some());
+            assert_eq!(upload.remaining_one_time_prekeys(), remaining);
+
+            let (agreement, message) = initiate(thread_rng(), &alice, &bundle).unwrap();
+            assert_eq!(store.respond(&bob, &message).unwrap(), agreement);
+            assert_eq!(store.remaining_one_time_prekeys(), remaining);
+
+            // the one time prekey cannot be used twice
+            assert!(matches!(
+                store.respond(&bob, &message),
+                Err(PreKeyError::UnknownOneTimePreKey(_))
+            ));
+        }
+
+        // once exhausted the bundle only contains the signed prekey
+        let bundle = upload.take_bundle();
+        assert!(bundle.one_time_prekey().is_none());
+        let (agreement, message) = initiate(thread_rng(), &alice, &bundle).unwrap();
+        assert_eq!(store.respond(&bob, &message).unwrap(), agreement);
+    }
+
+    #[test]
+    fn store_rotation() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let mut store = PreKeyStore::new(thread_rng(), &bob);
+        let mut upload = store.upload(&bob.public_key(), usize::MAX);
+        let (agreement, message) = initiate(thread_rng(), &alice, &upload.take_bundle()).unwrap();
+
+        store.rotate_signed_prekey(thread_rng(), &bob);
+        assert!(store.needs_upload());
+        store.generate_one_time_prekeys(thread_rng(), 1);
+        upload
+            .merge(store.upload(&bob.public_key(), usize::MAX))
+            .unwrap();
+        assert!(upload.verify());
+        assert_eq!(upload.signed_prekey_id(), store.signed_prekey().id());
+
+        // the previous signed prekey is still valid
+        assert_eq!(store.respond(&bob, &message).unwrap(), agreement);
+
+        store.rotate_signed_prekey(thread_rng(), &bob);
+        assert!(matches!(
+            store.respond(&bob, &message),
+            Err(PreKeyError::UnexpectedSignedPreKey { .. })
+        ));
+
+        let mallory = SecretKey::new(thread_rng());
+        let other = PreKeyStore::new(thread_rng(), &mallory).upload(&mallory.public_key(), 0);
+        assert!(matches!(
+            upload.merge(other),
+            Err(PreKeyError::UnexpectedIdentity)
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+/*!
+# Epoch key schedule
+
+Deterministically derive a key per epoch (a day, a week...) from an HD
+root key. The [`KeySchedule`] keeps the keys of the current epoch and
+of the `retention` previous epochs (to decrypt what has been encrypted
+for them) and drops the older ones as the time passes.
+
+The peers only need the HD public key of the root to compute the
+public key of any epoch (see [`KeySchedule::public_key`]).
+
+```
+use keynesis_core::{
+    key::ed25519_hd::SecretKey,
+    schedule::{KeySchedule, Period},
+};
+use std::time::{Duration, SystemTime};
+
+let root = SecretKey::generate();
+let now = SystemTime::now();
+let mut schedule = KeySchedule::new(&root, Period::daily(), 7, now);
+
+let epoch = schedule.current_epoch();
+let public_key = KeySchedule::public_key(&root.public_key(), epoch).unwrap();
+assert_eq!(schedule.current().public_key(), public_key);
+
+// a week later the key is still available, not after
+schedule.advance(now + Duration::from_secs(7 * 24 * 3600));
+assert!(schedule.get(epoch).is_some());
+schedule.advance(now + Duration::from_secs(8 * 24 * 3600));
+assert!(schedule.get(epoch).is_none());
+```
+
+The schedule does not keep the root itself but the intermediate key
+all the epoch keys are derived from: anyone holding the schedule can
+derive the keys of the future epochs. The pruning only bounds the
+exposure of the past epochs if the schedule is the only copy of the
+keys in memory (the root being stored encrypted elsewhere).
+*/
+
+use crate::key::ed25519_hd::{DerivationError, PublicKey, SecretKey};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// the path of the intermediate key all the epoch keys derive from
+const SCHEDULE_PATH: &[u8] = b"keynesis:schedule";
+
+/// number of the epoch since the origin of the [`Period`]
+pub type Epoch = u64;
+
+/// the duration of the epochs and the time the first epoch starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Period {
+    origin: SystemTime,
+    duration: Duration,
+}
+
+/// the keys of the current epoch and of the previous epochs still
+/// retained
+pub struct KeySchedule {
+    root: SecretKey,
+    period: Period,
+    retention: u64,
+    current: Epoch,
+    keys: BTreeMap<Epoch, SecretKey>,
+}
+
+impl Period {
+    /// epochs of a day, starting at midnight UTC
+    pub fn daily() -> Self {
+        Self::new(UNIX_EPOCH, Duration::from_secs(24 * 3600))
+    }
+
+    /// epochs of a week, starting on Monday at midnight UTC
+    pub fn weekly() -> Self {
+        // the 1st of January 1970 is a Thursday
+        let monday = UNIX_EPOCH + Duration::from_secs(4 * 24 * 3600);
+        Self::new(monday, Duration::from_secs(7 * 24 * 3600))
+    }
+
+    /// epochs of the given `duration`, the epoch `0` starts at `origin`
+    ///
+    /// # panics
+    ///
+    /// the duration needs to be at least a second
+    pub fn new(origin: SystemTime, duration: Duration) -> Self {
+        assert!(
+            duration.as_secs() > 0,
+            "the epochs need to last at least a second"
+        );
+        Self { origin, duration }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// the epoch of the given time, the times before the origin are
+    /// in the epoch `0`
+    pub fn epoch(&self, time: SystemTime) -> Epoch {
+        let elapsed = time.duration_since(self.origin).unwrap_or_default();
+        elapsed.as_secs() / self.duration.as_secs()
+    }
+
+    /// the time the epoch starts
+    pub fn start(&self, epoch: Epoch) -> SystemTime {
+        self.origin + Duration::from_secs(self.duration.as_secs().saturating_mul(epoch))
+    }
+}
+
+impl KeySchedule {
+    /// create the schedule of the `root` key, keeping the keys of the
+    /// `retention` epochs before the current one
+    pub fn new(root: &SecretKey, period: Period, retention: u64, now: SystemTime) -> Self {
+        let mut schedule = Self {
+            root: root.derive(SCHEDULE_PATH),
+            period,
+            retention,
+            current: period.epoch(now),
+            keys: BTreeMap::new(),
+        };
+        schedule.fill();
+        schedule
+    }
+
+    /// the public key of the given epoch, for the peers of the owner of
+    /// the `root`
+    pub fn public_key(root: &PublicKey, epoch: Epoch) -> Result<PublicKey, DerivationError> {
+        root.derive(SCHEDULE_PATH)?.derive(epoch.to_be_bytes())
+    }
+
+    pub fn period(&self) -> &Period {
+        &self.period
+    }
+
+    pub fn current_epoch(&self) -> Epoch {
+        self.current
+    }
+
+    /// the key of the current epoch
+    pub fn current(&self) -> &SecretKey {
+        // the current epoch is always in the retained keys
+        &self.keys[&self.current]
+    }
+
+    /// the key of the given epoch, if it is not expired and not in
+    /// the future
+    pub fn get(&self, epoch: Epoch) -> Option<&SecretKey> {
+        self.keys.get(&epoch)
+    }
+
+    /// move to the epoch of `now`, deriving the new keys and dropping
+    /// the expired ones
+    ///
+    /// the clock going backward does not bring back the expired keys,
+    /// the schedule stays in the latest epoch it has seen.
+    pub fn advance(&mut self, now: SystemTime) {
+        let epoch = self.period.epoch(now);
+        if epoch <= self.current {
+            return;
+        }
+
+        self.current = epoch;
+        self.fill();
+    }
+
+    fn oldest(&self) -> Epoch {
+        self.current.saturating_sub(self.retention)
+    }
+
+    /// derive the missing keys of the retained epochs and drop the
+    /// others
+    fn fill(&mut self) {
+        let oldest = self.oldest();
+        self.keys = self.keys.split_off(&oldest);
+
+        for epoch in oldest..=self.current {
+            if !self.keys.contains_key(&epoch) {
+                let key = self.root.derive(epoch.to_be_bytes());
+                self.keys.insert(epoch, key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 3600;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn periods() {
+        assert_eq!(Period::daily().epoch(at(DAY - 1)), 0);
+        assert_eq!(Period::daily().epoch(at(DAY)), 1);
+        assert_eq!(Period::daily().start(3), at(3 * DAY));
+
+        // Monday the 5th of January 1970
+        assert_eq!(Period::weekly().epoch(at(4 * DAY - 1)), 0);
+        assert_eq!(Period::weekly().epoch(at(4 * DAY)), 0);
+        assert_eq!(Period::weekly().epoch(at(11 * DAY)), 1);
+        assert_eq!(Period::weekly().start(1), at(11 * DAY));
+    }
+
+    #[test]
+    fn deterministic() {
+        let root = SecretKey::generate();
+        let schedule1 = KeySchedule::new(&root, Period::daily(), 2, at(10 * DAY));
+        let schedule2 = KeySchedule::new(&root, Period::daily(), 0, at(8 * DAY));
+
+        assert_eq!(schedule1.get(8), schedule2.get(8));
+        assert_ne!(schedule1.get(8), schedule1.get(9));
+        assert_eq!(
+            schedule1.current().public_key(),
+            KeySchedule::public_key(&root.public_key(), 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn pruning() {
+        let root = SecretKey::generate();
+        let mut schedule = KeySchedule::new(&root, Period::daily(), 2, at(10 * DAY));
+        let key = schedule.current().clone();
+
+        assert!(schedule.get(7).is_none());
+        assert!(schedule.get(8).is_some());
+        assert!(schedule.get(11).is_none());
+
+        schedule.advance(at(12 * DAY));
+        assert_eq!(schedule.current_epoch(), 12);
+        assert_eq!(schedule.get(10), Some(&key));
+        assert!(schedule.get(9).is_none());
+        assert_eq!(schedule.keys.len(), 3);
+
+        // the clock going back does not restore the expired keys
+        schedule.advance(at(8 * DAY));
+        assert_eq!(schedule.current_epoch(), 12);
+        assert!(schedule.get(9).is_none());
+
+        schedule.advance(at(100 * DAY));
+        assert!(schedule.get(10).is_none());
+        assert_eq!(schedule.keys.len(), 3);
+    }
+}
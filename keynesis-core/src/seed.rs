@@ -1,18 +1,81 @@
-use crate::memsec::Scrubbed as _;
-use cryptoxide::{hmac::Hmac, pbkdf2::pbkdf2, sha2::Sha512};
+use crate::{
+    bech32::{self, Bech32Error},
+    key::SharedSecret,
+    memsec::Scrubbed as _,
+};
+use cryptoxide::{blake2b::Blake2b, digest::Digest as _, hmac::Hmac, pbkdf2::pbkdf2, sha2::Sha512};
 use rand_chacha::ChaChaRng;
 use rand_core::{CryptoRng, RngCore, SeedableRng};
 use std::{
+    convert::TryFrom,
     fmt::{self, Debug, Display, Formatter},
     str::FromStr,
 };
+use subtle::{Choice, ConstantTimeEq};
+use thiserror::Error;
+
+/// domain separation of [`Seed::from_phrase`]
+const PHRASE_CONTEXT: &[u8] = b"keynesis:seed:phrase";
+
+/// domain separation of the ChaCha seed of the extended seeds
+const EXTENDED_CONTEXT: &[u8] = b"keynesis:seed:extended";
 
+/// Seed of entropy to deterministically generate keys from
+///
+/// The seed is either [`Seed::SIZE`] or [`Seed::EXTENDED_SIZE`] bytes
+/// long (like the seeds of BIP39). All the secret key types provide a
+/// `from_seed` constructor that expands the `Seed` with
+/// [`Seed::into_rand_chacha`]. The same seed always yields the same key.
+///
+/// The content of the seed is scrubbed (zeroed) when the seed is dropped.
 #[derive(Clone)]
-pub struct Seed([u8; Self::SIZE]);
+pub struct Seed {
+    bytes: [u8; Self::EXTENDED_SIZE],
+    len: usize,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SeedError {
+    #[error("Invalid size, expecting {} or {}", Seed::SIZE, Seed::EXTENDED_SIZE)]
+    InvalidSize,
+
+    #[error("Invalid bech32 string")]
+    InvalidBech32(
+        #[from]
+        #[source]
+        Bech32Error,
+    ),
+}
 
 impl Seed {
     pub const SIZE: usize = 32;
 
+    /// size of the extended seeds, see [`generate_extended`](Self::generate_extended)
+    pub const EXTENDED_SIZE: usize = 64;
+
+    fn new(bytes: &[u8]) -> Result<Self, SeedError> {
+        if bytes.len() != Self::SIZE && bytes.len() != Self::EXTENDED_SIZE {
+            return Err(SeedError::InvalidSize);
+        }
+
+        let mut seed = Self {
+            bytes: [0; Self::EXTENDED_SIZE],
+            len: bytes.len(),
+        };
+        seed.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(seed)
+    }
+
+    fn from_array(bytes: [u8; Self::SIZE]) -> Self {
+        let mut seed = Self {
+            bytes: [0; Self::EXTENDED_SIZE],
+            len: Self::SIZE,
+        };
+        seed.bytes[..Self::SIZE].copy_from_slice(&bytes);
+        seed
+    }
+
     /// Generate a random see with the given Cryptographically secure
     /// Random Number Generator (RNG).
     ///
@@ -22,9 +85,28 @@ impl Seed {
     where
         RNG: RngCore + CryptoRng,
     {
-        let mut bytes = [0; Self::SIZE];
-        rng.fill_bytes(&mut bytes);
-        Self(bytes)
+        let mut seed = Self::from_array([0; Self::SIZE]);
+        rng.fill_bytes(&mut seed.bytes[..Self::SIZE]);
+        seed
+    }
+
+    /// same as [`generate`](Self::generate) but the seed is
+    /// [`EXTENDED_SIZE`](Self::EXTENDED_SIZE) bytes long
+    pub fn generate_extended<RNG>(rng: &mut RNG) -> Self
+    where
+        RNG: RngCore + CryptoRng,
+    {
+        let mut seed = Self {
+            bytes: [0; Self::EXTENDED_SIZE],
+            len: Self::EXTENDED_SIZE,
+        };
+        rng.fill_bytes(&mut seed.bytes);
+        seed
+    }
+
+    /// `true` if the seed is [`EXTENDED_SIZE`](Self::EXTENDED_SIZE) bytes long
+    pub fn is_extended(&self) -> bool {
+        self.len == Self::EXTENDED_SIZE
     }
 
     /// it is possible to derive the Seed from a given key
@@ -52,7 +134,26 @@ impl Seed {
 
         pbkdf2(&mut mac, key.as_ref(), iteration, &mut bytes);
 
-        Self(bytes)
+        Self::from_array(bytes)
+    }
+
+    /// hash an arbitrary byte string in a seed
+    ///
+    /// this is for reproducible test fixtures or for entropy from an
+    /// external source that is not already 32 bytes long. Unlike
+    /// [`derive_from_key`](Self::derive_from_key) this is a single
+    /// fast hash, **it does not protect a low entropy phrase** (a
+    /// password for example) against brute force attacks.
+    pub fn from_phrase<P>(phrase: P) -> Self
+    where
+        P: AsRef<[u8]>,
+    {
+        let mut bytes = [0; Self::SIZE];
+        let mut hasher = Blake2b::new(Self::SIZE);
+        hasher.input(PHRASE_CONTEXT);
+        hasher.input(phrase.as_ref());
+        hasher.result(&mut bytes);
+        Self::from_array(bytes)
     }
 
     /// use this to seed a ChaCha RNG
@@ -60,47 +161,131 @@ impl Seed {
     /// then you can use the RNG to create new private key. This is an
     /// handy way to derive a private key from a key and a password
     /// (or an HSM and a password?)
+    ///
+    /// the extended seeds are [compacted](Self::compact) to the size of
+    /// the ChaCha seed first.
     pub fn into_rand_chacha(self) -> ChaChaRng {
-        ChaChaRng::from_seed(self.0)
+        let compact = self.compact();
+        let mut bytes = [0; Self::SIZE];
+        bytes.copy_from_slice(compact.as_ref());
+
+        let rng = ChaChaRng::from_seed(bytes);
+        bytes.scrub();
+        rng
+    }
+
+    /// the [`SIZE`](Self::SIZE) bytes long version of the seed: the seed
+    /// itself or the hash of the extended seed
+    ///
+    /// this is what is used where a 32 bytes key is needed (the pre
+    /// shared key of a Noise handshake for example).
+    pub fn compact(&self) -> Self {
+        if !self.is_extended() {
+            return self.clone();
+        }
+
+        let mut seed = Self::from_array([0; Self::SIZE]);
+        let mut hasher = Blake2b::new(Self::SIZE);
+        hasher.input(EXTENDED_CONTEXT);
+        hasher.input(self.as_ref());
+        hasher.result(&mut seed.bytes[..Self::SIZE]);
+        seed
+    }
+
+    /// encode the seed in a bech32 string with the given human readable part
+    ///
+    /// # Security Consideration
+    ///
+    /// the returned string contains the seed in clear. Anyone with access to
+    /// it can regenerate the keys derived from this seed.
+    pub fn to_bech32_str(&self, hrp: &str) -> String {
+        bech32::encode(hrp, self.as_ref())
+    }
+
+    /// decode a seed from a bech32 string, the human readable part needs to
+    /// be the given `hrp`.
+    pub fn from_bech32_str(hrp: &str, s: &str) -> Result<Self, SeedError> {
+        let mut bytes = bech32::decode_with_hrp(hrp, s)?;
+        let seed = Self::try_from(bytes.as_slice());
+        bytes.scrub();
+        seed
+    }
+}
+
+impl ConstantTimeEq for Seed {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.len.ct_eq(&other.len) & self.bytes[..].ct_eq(&other.bytes[..])
     }
 }
 
 impl Drop for Seed {
     fn drop(&mut self) {
-        self.0.scrub()
+        self.bytes.scrub()
     }
 }
 
 impl Display for Seed {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Display::fmt(&hex::encode(&self.0), f)
+        Display::fmt(&hex::encode(self.as_ref()), f)
     }
 }
 
 impl Debug for Seed {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_tuple("Seed").field(&hex::encode(&self.0)).finish()
+        f.debug_tuple("Seed")
+            .field(&hex::encode(self.as_ref()))
+            .finish()
     }
 }
 
 impl AsRef<[u8]> for Seed {
     fn as_ref(&self) -> &[u8] {
-        &self.0
+        &self.bytes[..self.len]
     }
 }
 
 impl FromStr for Seed {
     type Err = hex::FromHexError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut bytes = [0; Self::SIZE];
-        hex::decode_to_slice(s, &mut bytes)?;
-        Ok(Self(bytes))
+        let mut seed = Self {
+            bytes: [0; Self::EXTENDED_SIZE],
+            len: s.len() / 2,
+        };
+        if seed.len != Self::SIZE && seed.len != Self::EXTENDED_SIZE {
+            return Err(hex::FromHexError::InvalidStringLength);
+        }
+        hex::decode_to_slice(s, &mut seed.bytes[..seed.len])?;
+        Ok(seed)
     }
 }
 
 impl From<[u8; Self::SIZE]> for Seed {
     fn from(seed: [u8; Self::SIZE]) -> Self {
-        Self(seed)
+        Self::from_array(seed)
+    }
+}
+
+impl From<[u8; Self::EXTENDED_SIZE]> for Seed {
+    fn from(bytes: [u8; Self::EXTENDED_SIZE]) -> Self {
+        Self {
+            bytes,
+            len: Self::EXTENDED_SIZE,
+        }
+    }
+}
+
+/// use the shared secret as a seed, for example as the pre shared key
+/// of a Noise handshake
+impl<'a> From<&'a SharedSecret> for Seed {
+    fn from(shared_secret: &'a SharedSecret) -> Self {
+        Self::new(shared_secret.as_ref()).expect("the shared secret is 32 bytes long")
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Seed {
+    type Error = SeedError;
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::new(value)
     }
 }
 
@@ -112,10 +297,101 @@ mod tests {
     impl Arbitrary for Seed {
         fn arbitrary(g: &mut Gen) -> Self {
             let mut bytes = [0; Self::SIZE];
-            bytes.iter_mut().for_each(|byte| {
-                *byte = u8::arbitrary(g);
-            });
-            Self(bytes)
+            bytes.iter_mut().for_each(|byte| *byte = u8::arbitrary(g));
+            Self::from(bytes)
+        }
+    }
+
+    /// either a [`Seed`] or an extended one
+    #[derive(Clone, Debug)]
+    struct AnySeed(Seed);
+
+    impl Arbitrary for AnySeed {
+        fn arbitrary(g: &mut Gen) -> Self {
+            if bool::arbitrary(g) {
+                return Self(Seed::arbitrary(g));
+            }
+            let mut bytes = [0; Seed::EXTENDED_SIZE];
+            bytes.iter_mut().for_each(|byte| *byte = u8::arbitrary(g));
+            Self(Seed::from(bytes))
         }
     }
+
+    #[quickcheck]
+    fn bech32_encode_decode(AnySeed(seed): AnySeed) -> bool {
+        let s = seed.to_bech32_str("seed");
+        let decoded = Seed::from_bech32_str("seed", &s).unwrap();
+
+        decoded.as_ref() == seed.as_ref()
+    }
+
+    #[quickcheck]
+    fn hex_encode_decode(AnySeed(seed): AnySeed) -> bool {
+        let decoded: Seed = seed.to_string().parse().unwrap();
+
+        decoded.as_ref() == seed.as_ref() && bool::from(decoded.ct_eq(&seed))
+    }
+
+    #[quickcheck]
+    fn try_from_bytes(AnySeed(seed): AnySeed) -> bool {
+        let decoded = Seed::try_from(seed.as_ref()).unwrap();
+
+        decoded.as_ref() == seed.as_ref() && decoded.is_extended() == seed.is_extended()
+    }
+
+    #[test]
+    fn extended_seed() {
+        let mut rng = rand::thread_rng();
+        let seed = Seed::generate_extended(&mut rng);
+        assert!(seed.is_extended());
+        assert_eq!(seed.as_ref().len(), Seed::EXTENDED_SIZE);
+        assert!(!Seed::generate(&mut rng).is_extended());
+
+        // the same extended seed always yields the same keys
+        let mut a = seed.clone().into_rand_chacha();
+        let mut b = seed.into_rand_chacha();
+        assert_eq!(a.next_u64(), b.next_u64());
+
+        // the 32 first bytes are not enough to get the same keys
+        let bytes = [0x42; Seed::EXTENDED_SIZE];
+        let mut short = [0; Seed::SIZE];
+        short.copy_from_slice(&bytes[..Seed::SIZE]);
+        let mut a = Seed::from(bytes).into_rand_chacha();
+        let mut b = Seed::from(short).into_rand_chacha();
+        assert_ne!(a.next_u64(), b.next_u64());
+
+        // 32 bytes seeds yield the same keys as before
+        let mut a = Seed::from(short).into_rand_chacha();
+        let mut b = ChaChaRng::from_seed(short);
+        assert_eq!(a.next_u64(), b.next_u64());
+    }
+
+    #[quickcheck]
+    fn bech32_unexpected_hrp(seed: Seed) -> bool {
+        let s = seed.to_bech32_str("seed");
+
+        matches!(
+            Seed::from_bech32_str("sk", &s),
+            Err(SeedError::InvalidBech32(Bech32Error::UnexpectedHrp { .. }))
+        )
+    }
+
+    #[quickcheck]
+    fn from_phrase_is_deterministic(phrase: Vec<u8>) -> bool {
+        let mut other = phrase.clone();
+        other.push(0);
+
+        Seed::from_phrase(&phrase).as_ref() == Seed::from_phrase(&phrase).as_ref()
+            && Seed::from_phrase(&phrase).as_ref() != Seed::from_phrase(&other).as_ref()
+    }
+
+    #[quickcheck]
+    fn try_from_incorrect_size(bytes: Vec<u8>) -> bool {
+        bytes.len() == Seed::SIZE
+            || bytes.len() == Seed::EXTENDED_SIZE
+            || matches!(
+                Seed::try_from(bytes.as_slice()),
+                Err(SeedError::InvalidSize)
+            )
+    }
 }
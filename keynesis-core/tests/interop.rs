@@ -0,0 +1,222 @@
+//! drive the keynesis handshakes against snow, in both directions
+//!
+//! run with `cargo test --features interop`
+#![cfg(feature = "interop")]
+
+use cryptoxide::{blake2b::Blake2b, blake2s::Blake2s};
+use keynesis_core::{
+    hash::{Hash, Sha256, Sha512},
+    key::{curve25519::SecretKey, ed25519::PublicKey},
+    noise::{
+        interop::{Handshake, Pattern},
+        AesGcm, ChaChaPoly, Cipher, TransportState,
+    },
+};
+use rand::thread_rng;
+use snow::Builder;
+use std::convert::TryFrom;
+
+const PROLOGUE: &[u8] = b"keynesis interop";
+
+fn params<C: Cipher>(pattern: Pattern, hash: &str) -> snow::params::NoiseParams {
+    let pattern = match pattern {
+        Pattern::IK => "IK",
+        Pattern::XX => "XX",
+    };
+    format!("Noise_{}_25519_{}_{}", pattern, C::name(), hash)
+        .parse()
+        .unwrap()
+}
+
+/// the handshake payloads, the XX pattern of keynesis does not have any
+fn payload(pattern: Pattern, payload: &'static [u8]) -> &'static [u8] {
+    match pattern {
+        Pattern::IK => payload,
+        Pattern::XX => &[],
+    }
+}
+
+/// exchange transport messages, rekeying snow's cipher states after
+/// every message like keynesis does
+fn transport<H: Hash, C: Cipher>(
+    mut keynesis: TransportState<H, C>,
+    mut snow: snow::TransportState,
+) {
+    let mut buffer = [0; 1024];
+    let mut plaintext = [0; 1024];
+
+    for message in [&b"hello"[..], b"", b"world"] {
+        let mut output = Vec::new();
+        keynesis.send(message, &mut output).unwrap();
+        let len = snow.read_message(&output, &mut plaintext).unwrap();
+        snow.rekey_incoming();
+        assert_eq!(&plaintext[..len], message);
+
+        let len = snow.write_message(message, &mut buffer).unwrap();
+        snow.rekey_outgoing();
+        let mut received = Vec::new();
+        keynesis.receive(&buffer[..len], &mut received).unwrap();
+        assert_eq!(received, message);
+    }
+}
+
+fn keynesis_initiator<H: Hash, C: Cipher>(pattern: Pattern, hash: &str) {
+    let builder = Builder::new(params::<C>(pattern, hash));
+    let responder_keys = builder.generate_keypair().unwrap();
+    let mut responder = builder
+        .local_private_key(&responder_keys.private)
+        .prologue(PROLOGUE)
+        .build_responder()
+        .unwrap();
+
+    let s = SecretKey::new(thread_rng());
+    let rs = PublicKey::try_from(responder_keys.public.as_slice()).unwrap();
+    let mut initiator =
+        Handshake::<_, H, _, C>::initiator(pattern, thread_rng(), PROLOGUE, s.clone(), Some(rs));
+
+    let mut buffer = [0; 1024];
+    let mut payload_buffer = [0; 1024];
+
+    // -> e, (s...)
+    let mut message = Vec::new();
+    initiator
+        .write_message(payload(pattern, b"initiator"), &mut message)
+        .unwrap();
+    let len = responder
+        .read_message(&message, &mut payload_buffer)
+        .unwrap();
+    assert_eq!(&payload_buffer[..len], payload(pattern, b"initiator"));
+
+    // <- e, ...
+    let len = responder
+        .write_message(payload(pattern, b"responder"), &mut buffer)
+        .unwrap();
+    let mut received = Vec::new();
+    initiator
+        .read_message(&buffer[..len], &mut received)
+        .unwrap();
+    assert_eq!(received, payload(pattern, b"responder"));
+
+    if pattern == Pattern::XX {
+        // -> s, se
+        let mut message = Vec::new();
+        initiator.write_message(&[], &mut message).unwrap();
+        responder
+            .read_message(&message, &mut payload_buffer)
+            .unwrap();
+    }
+
+    assert!(initiator.is_finished() && responder.is_handshake_finished());
+    assert_eq!(
+        responder.get_remote_static().unwrap(),
+        s.public_key().as_ref()
+    );
+
+    let initiator = initiator.into_transport().unwrap();
+    assert_eq!(
+        initiator.noise_session().as_ref(),
+        responder.get_handshake_hash()
+    );
+    transport(initiator, responder.into_transport_mode().unwrap());
+}
+
+fn snow_initiator<H: Hash, C: Cipher>(pattern: Pattern, hash: &str) {
+    let s = SecretKey::new(thread_rng());
+    let mut responder =
+        Handshake::<_, H, _, C>::responder(pattern, thread_rng(), PROLOGUE, s.clone());
+
+    let builder = Builder::new(params::<C>(pattern, hash));
+    let initiator_keys = builder.generate_keypair().unwrap();
+    let builder = builder
+        .local_private_key(&initiator_keys.private)
+        .prologue(PROLOGUE);
+    let rs = s.public_key();
+    let builder = match pattern {
+        Pattern::IK => builder.remote_public_key(rs.as_ref()),
+        Pattern::XX => builder,
+    };
+    let mut initiator = builder.build_initiator().unwrap();
+
+    let mut buffer = [0; 1024];
+    let mut payload_buffer = [0; 1024];
+
+    // -> e, (s...)
+    let len = initiator
+        .write_message(payload(pattern, b"initiator"), &mut buffer)
+        .unwrap();
+    let mut received = Vec::new();
+    responder
+        .read_message(&buffer[..len], &mut received)
+        .unwrap();
+    assert_eq!(received, payload(pattern, b"initiator"));
+
+    // <- e, ...
+    let mut message = Vec::new();
+    responder
+        .write_message(payload(pattern, b"responder"), &mut message)
+        .unwrap();
+    let len = initiator
+        .read_message(&message, &mut payload_buffer)
+        .unwrap();
+    assert_eq!(&payload_buffer[..len], payload(pattern, b"responder"));
+
+    if pattern == Pattern::XX {
+        // -> s, se
+        let len = initiator.write_message(&[], &mut buffer).unwrap();
+        responder.read_message(&buffer[..len], &mut []).unwrap();
+    }
+
+    assert!(responder.is_finished() && initiator.is_handshake_finished());
+    assert_eq!(initiator.get_remote_static().unwrap(), rs.as_ref());
+
+    let responder = responder.into_transport().unwrap();
+    assert_eq!(
+        responder.remote_public_identity().unwrap().as_ref(),
+        initiator_keys.public.as_slice()
+    );
+    assert_eq!(
+        responder.noise_session().as_ref(),
+        initiator.get_handshake_hash()
+    );
+    transport(responder, initiator.into_transport_mode().unwrap());
+}
+
+#[test]
+fn ik_keynesis_initiator() {
+    keynesis_initiator::<Blake2s, ChaChaPoly>(Pattern::IK, "BLAKE2s");
+    keynesis_initiator::<Blake2b, ChaChaPoly>(Pattern::IK, "BLAKE2b");
+    keynesis_initiator::<Sha256, ChaChaPoly>(Pattern::IK, "SHA256");
+    keynesis_initiator::<Sha512, ChaChaPoly>(Pattern::IK, "SHA512");
+}
+
+#[test]
+fn ik_snow_initiator() {
+    snow_initiator::<Blake2s, ChaChaPoly>(Pattern::IK, "BLAKE2s");
+    snow_initiator::<Blake2b, ChaChaPoly>(Pattern::IK, "BLAKE2b");
+    snow_initiator::<Sha256, ChaChaPoly>(Pattern::IK, "SHA256");
+    snow_initiator::<Sha512, ChaChaPoly>(Pattern::IK, "SHA512");
+}
+
+#[test]
+fn xx_keynesis_initiator() {
+    keynesis_initiator::<Blake2s, ChaChaPoly>(Pattern::XX, "BLAKE2s");
+    keynesis_initiator::<Blake2b, ChaChaPoly>(Pattern::XX, "BLAKE2b");
+    keynesis_initiator::<Sha256, ChaChaPoly>(Pattern::XX, "SHA256");
+    keynesis_initiator::<Sha512, ChaChaPoly>(Pattern::XX, "SHA512");
+}
+
+#[test]
+fn xx_snow_initiator() {
+    snow_initiator::<Blake2s, ChaChaPoly>(Pattern::XX, "BLAKE2s");
+    snow_initiator::<Blake2b, ChaChaPoly>(Pattern::XX, "BLAKE2b");
+    snow_initiator::<Sha256, ChaChaPoly>(Pattern::XX, "SHA256");
+    snow_initiator::<Sha512, ChaChaPoly>(Pattern::XX, "SHA512");
+}
+
+#[test]
+fn aes_gcm() {
+    keynesis_initiator::<Blake2s, AesGcm>(Pattern::IK, "BLAKE2s");
+    keynesis_initiator::<Sha256, AesGcm>(Pattern::XX, "SHA256");
+    snow_initiator::<Blake2b, AesGcm>(Pattern::IK, "BLAKE2b");
+    snow_initiator::<Sha512, AesGcm>(Pattern::XX, "SHA512");
+}
@@ -24,8 +24,8 @@ fn ik(b: &mut Bencher) {
     let (mut first_msg, mut second_msg) = (Vec::with_capacity(1024), Vec::with_capacity(1024));
 
     b.iter(|| {
-        let initiator = IK::<_, Blake2s, _, _>::new(&mut rng1, &[]);
-        let responder = IK::<_, Blake2s, _, _>::new(&mut rng2, &[]);
+        let initiator = IK::<_, Blake2s, _, _>::new(&mut rng1, &None, &[]);
+        let responder = IK::<_, Blake2s, _, _>::new(&mut rng2, &None, &[]);
 
         // -> e, es, s, ss
         let initiator = initiator
@@ -17,7 +17,7 @@ fn ref_talk_to_keynesis() {
     let responder_key = SecretKey::new(&mut rng);
     let responder_public = responder_key.public().to_string().parse().unwrap();
 
-    let responder = IK::<_, Blake2s, _, _>::new(rng, &[]);
+    let responder = IK::<_, Blake2s, _, _>::new(rng, &None, &[]);
 
     let mut initiator = noise::noisesession::NoiseSession::init_session(
         true,
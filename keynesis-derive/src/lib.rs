@@ -0,0 +1,397 @@
+/*!
+# Key hierarchy
+
+`#[derive(KeyHierarchy)]` turns an enum (or a struct) listing the purposes
+of the keys of an application into typed derivation methods. This removes
+the derivation paths spread as strings in the code of the application: a
+typo in a path is a different key, a typo in a method is a compile error.
+
+```
+use keynesis_core::key::ed25519_hd::SecretKey;
+use keynesis_derive::KeyHierarchy;
+# use rand::thread_rng;
+
+#[derive(KeyHierarchy)]
+#[key_hierarchy(prefix = "chat")]
+pub enum Purpose {
+    /// the key of the user's passport, path `chat/identity`
+    Identity,
+    /// path `chat/device` and the index of the device
+    Device(u32),
+    /// path `chat/room` and the identifier of the room
+    #[key_hierarchy(path = "room")]
+    ChatRoom { id: u64 },
+}
+
+let root = SecretKey::new(thread_rng());
+
+// methods of the generated `PurposeHierarchy` trait
+let identity = root.identity_key();
+let device = root.device_key(1);
+let room = root.chat_room_key(42);
+
+assert_eq!(device.public_key(), Purpose::Device(1).derive(&root).public_key());
+assert_eq!(
+    room.public_key(),
+    Purpose::ChatRoom { id: 42 }
+        .derive_public(&root.public_key())
+        .unwrap()
+);
+```
+
+## Generated items
+
+For a type `Purpose` the derive generates:
+
+* `Purpose::path`: the derivation path of the purpose;
+* `Purpose::derive` and `Purpose::derive_public`: the derivation of the
+  secret and the public keys of the purpose from the root keys;
+* the `PurposeHierarchy` trait, implemented for the `ed25519_hd::SecretKey`,
+  with a `<purpose>_key` method for every purpose.
+
+## Derivation paths
+
+The path is the name of the purpose in snake case (or the `path` of the
+`#[key_hierarchy(path = "...")]` attribute), prefixed with the `prefix` of
+the type's attribute followed by a `/`. It is terminated with a `0` byte
+and followed by the big endian encoding of the fields, which need to be
+unsigned integers. Changing any of these changes the keys: the paths
+are part of the application's format.
+
+By default the generated code refers to the `keynesis_core` crate, use
+`#[key_hierarchy(crate = "keynesis")]` to use another path.
+*/
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Ident, LitByteStr, LitStr,
+    Path, Result, Type,
+};
+
+#[proc_macro_derive(KeyHierarchy, attributes(key_hierarchy))]
+pub fn derive_key_hierarchy(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// the options of the `#[key_hierarchy(...)]` attribute of the type
+struct Options {
+    prefix: Option<String>,
+    krate: Path,
+}
+
+/// one of the purposes of the hierarchy
+struct Purpose {
+    /// the variant, `None` for a struct
+    variant: Option<Ident>,
+    /// the name of the variant or of the struct
+    name: Ident,
+    /// the derivation path, without the fields
+    path: String,
+    fields: Vec<(Ident, Type)>,
+    /// the fields are named (`{ a, b }`) and not a tuple (`(a, b)`)
+    named: bool,
+    span: Span,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "KeyHierarchy cannot be derived for generic types",
+        ));
+    }
+
+    let options = Options::parse(&input.attrs)?;
+
+    let purposes = match &input.data {
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                Purpose::new(
+                    &options,
+                    Some(&variant.ident),
+                    &variant.ident,
+                    &variant.attrs,
+                    &variant.fields,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?,
+        Data::Struct(data) => vec![Purpose::new(
+            &options,
+            None,
+            &input.ident,
+            &input.attrs,
+            &data.fields,
+        )?],
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "KeyHierarchy cannot be derived for unions",
+            ))
+        }
+    };
+
+    if purposes.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "KeyHierarchy needs at least one purpose",
+        ));
+    }
+
+    for (index, purpose) in purposes.iter().enumerate() {
+        if purposes[..index].iter().any(|p| p.path == purpose.path) {
+            return Err(Error::new(
+                purpose.span,
+                format!("the derivation path {:?} is already used", purpose.path),
+            ));
+        }
+    }
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let krate = &options.krate;
+    let hierarchy = format_ident!("{}Hierarchy", name);
+
+    let paths = purposes.iter().map(|purpose| {
+        let pattern = purpose.constructor(quote!(Self));
+        let path = LitByteStr::new(purpose.path.as_bytes(), purpose.span);
+        let fields = purpose.fields.iter().map(|(ident, _)| ident);
+
+        quote! {
+            #pattern => {
+                path.extend_from_slice(#path);
+                path.push(0);
+                #( path.extend_from_slice(&#fields.to_be_bytes()); )*
+            }
+        }
+    });
+
+    let methods = purposes.iter().map(|purpose| {
+        let method = purpose.method();
+        let args = purpose.fields.iter().map(|(ident, ty)| quote!(#ident: #ty));
+        let doc = format!("derive the key of the `{}` derivation path", purpose.path);
+
+        quote! {
+            #[doc = #doc]
+            fn #method(&self, #(#args),*) -> Self;
+        }
+    });
+
+    let implementations = purposes.iter().map(|purpose| {
+        let method = purpose.method();
+        let args = purpose.fields.iter().map(|(ident, ty)| quote!(#ident: #ty));
+        let constructor = purpose.constructor(quote!(#name));
+
+        quote! {
+            fn #method(&self, #(#args),*) -> Self {
+                (#constructor).derive(self)
+            }
+        }
+    });
+
+    let hierarchy_doc = format!("typed derivation methods of the [`{}`] keys", name);
+
+    Ok(quote! {
+        impl #name {
+            /// the derivation path of the purpose
+            #vis fn path(&self) -> ::std::vec::Vec<u8> {
+                let mut path = ::std::vec::Vec::new();
+                match self {
+                    #( #paths )*
+                }
+                path
+            }
+
+            /// derive the secret key of the purpose from the root key
+            #vis fn derive(
+                &self,
+                root: &#krate::key::ed25519_hd::SecretKey,
+            ) -> #krate::key::ed25519_hd::SecretKey {
+                root.derive(self.path())
+            }
+
+            /// derive the public key of the purpose from the root public key
+            #vis fn derive_public(
+                &self,
+                root: &#krate::key::ed25519_hd::PublicKey,
+            ) -> ::std::result::Result<
+                #krate::key::ed25519_hd::PublicKey,
+                #krate::key::ed25519_hd::DerivationError,
+            > {
+                root.derive(self.path())
+            }
+        }
+
+        #[doc = #hierarchy_doc]
+        #vis trait #hierarchy {
+            #( #methods )*
+        }
+
+        impl #hierarchy for #krate::key::ed25519_hd::SecretKey {
+            #( #implementations )*
+        }
+    })
+}
+
+impl Options {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut prefix = None;
+        let mut krate = syn::parse_quote!(::keynesis_core);
+
+        for attr in attrs.iter().filter(|a| a.path().is_ident("key_hierarchy")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("prefix") {
+                    let value: LitStr = meta.value()?.parse()?;
+                    prefix = Some(check_path(&value)?);
+                    Ok(())
+                } else if meta.path.is_ident("crate") {
+                    let value: LitStr = meta.value()?.parse()?;
+                    krate = value.parse()?;
+                    Ok(())
+                } else {
+                    Err(meta.error("expecting `prefix` or `crate`"))
+                }
+            })?;
+        }
+
+        Ok(Self { prefix, krate })
+    }
+}
+
+impl Purpose {
+    fn new(
+        options: &Options,
+        variant: Option<&Ident>,
+        ident: &Ident,
+        attrs: &[Attribute],
+        fields: &Fields,
+    ) -> Result<Self> {
+        let mut path = None;
+
+        // the attributes of a struct are the options of the hierarchy
+        if variant.is_some() {
+            for attr in attrs.iter().filter(|a| a.path().is_ident("key_hierarchy")) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("path") {
+                        let value: LitStr = meta.value()?.parse()?;
+                        path = Some(check_path(&value)?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("expecting `path`"))
+                    }
+                })?;
+            }
+        }
+
+        let path = path.unwrap_or_else(|| snake_case(&ident.to_string()));
+        let path = match &options.prefix {
+            None => path,
+            Some(prefix) => format!("{}/{}", prefix, path),
+        };
+
+        let named = matches!(fields, Fields::Named(_));
+        let fields = fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                let ident = field
+                    .ident
+                    .clone()
+                    .unwrap_or_else(|| format_ident!("arg{}", index));
+                (ident, field.ty.clone())
+            })
+            .collect();
+
+        Ok(Self {
+            variant: variant.cloned(),
+            name: ident.clone(),
+            path,
+            fields,
+            named,
+            span: ident.span(),
+        })
+    }
+
+    /// name of the method of the hierarchy trait
+    fn method(&self) -> Ident {
+        format_ident!(
+            "{}_key",
+            snake_case(&self.name.to_string()),
+            span = self.span
+        )
+    }
+
+    /// expression (or pattern) building the purpose from its fields
+    fn constructor(&self, ty: TokenStream2) -> TokenStream2 {
+        let path = match &self.variant {
+            None => ty,
+            Some(variant) => quote!(#ty::#variant),
+        };
+        let fields = self.fields.iter().map(|(ident, _)| ident);
+
+        if self.fields.is_empty() {
+            path
+        } else if self.named {
+            quote!(#path { #(#fields),* })
+        } else {
+            quote!(#path ( #(#fields),* ))
+        }
+    }
+}
+
+/// the paths are written as strings in the code so the `0` byte
+/// terminating them cannot be part of them
+fn check_path(value: &LitStr) -> Result<String> {
+    let path = value.value();
+
+    if path.is_empty() || path.contains('\0') {
+        Err(Error::new_spanned(
+            value,
+            "the path cannot be empty or contain a NUL character",
+        ))
+    } else {
+        Ok(path)
+    }
+}
+
+/// `ChatRoom` to `chat_room`
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+
+    for (index, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && index > 0 {
+            let previous = chars[index - 1];
+            let next_is_lower = chars.get(index + 1).is_some_and(|c| c.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_is_lower)
+            {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case_names() {
+        assert_eq!(snake_case("Identity"), "identity");
+        assert_eq!(snake_case("ChatRoom"), "chat_room");
+        assert_eq!(snake_case("HTTPServer"), "http_server");
+        assert_eq!(snake_case("Device2Fa"), "device2_fa");
+    }
+}
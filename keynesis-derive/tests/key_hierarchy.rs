@@ -0,0 +1,66 @@
+use keynesis_core::key::ed25519_hd::SecretKey;
+use keynesis_derive::KeyHierarchy;
+use rand::thread_rng;
+
+#[derive(KeyHierarchy)]
+#[key_hierarchy(prefix = "app")]
+enum Purpose {
+    Identity,
+    Device(u32),
+    #[key_hierarchy(path = "room")]
+    ChatRoom {
+        id: u64,
+        epoch: u8,
+    },
+}
+
+#[derive(KeyHierarchy)]
+struct Backup(u16);
+
+#[derive(KeyHierarchy)]
+#[key_hierarchy(crate = "keynesis")]
+struct HttpServer;
+
+#[test]
+fn paths() {
+    assert_eq!(Purpose::Identity.path(), b"app/identity\0");
+    assert_eq!(Purpose::Device(1).path(), b"app/device\0\0\0\0\x01");
+    assert_eq!(
+        Purpose::ChatRoom { id: 2, epoch: 3 }.path(),
+        b"app/room\0\0\0\0\0\0\0\0\x02\x03"
+    );
+    assert_eq!(Backup(4).path(), b"backup\0\0\x04");
+    assert_eq!(HttpServer.path(), b"http_server\0");
+}
+
+#[test]
+fn typed_derivation() {
+    let root = SecretKey::new(thread_rng());
+
+    assert_eq!(
+        root.identity_key().public_key(),
+        root.derive(b"app/identity\0").public_key()
+    );
+    assert_eq!(
+        root.device_key(1).public_key(),
+        Purpose::Device(1).derive(&root).public_key()
+    );
+    assert_ne!(
+        root.device_key(1).public_key(),
+        root.device_key(2).public_key()
+    );
+    assert_eq!(
+        root.chat_room_key(2, 3).public_key(),
+        Purpose::ChatRoom { id: 2, epoch: 3 }
+            .derive_public(&root.public_key())
+            .unwrap()
+    );
+    assert_eq!(
+        root.backup_key(4).public_key(),
+        Backup(4).derive(&root).public_key()
+    );
+    assert_eq!(
+        root.http_server_key().public_key(),
+        HttpServer.derive(&root).public_key()
+    );
+}
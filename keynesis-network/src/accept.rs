@@ -1,6 +1,11 @@
 use crate::{
-    codec::handshake::{HandshakeInitialize, HandshakeResponse},
-    Handle,
+    codec::handshake::{
+        self, FallbackExtensions, FallbackFinalize, FallbackResponse, HandshakeInitialize,
+        HandshakeResponse, Initiation, NkInitialize, NkResponse, XkFinalize, XkResponse,
+        XxFinalize, XxInitialize, XxResponse,
+    },
+    config::{with_timeout, ConnectConfig},
+    Extensions, Handle, Version, VersionRange,
 };
 use anyhow::{bail, Context as _, Result};
 use keynesis_core::{
@@ -9,10 +14,11 @@ use keynesis_core::{
         ed25519::{self, PublicKey},
         Dh,
     },
-    noise::{ik::A, IK},
+    noise::{xk, HandshakeStateError, XXfallback, IK, NK, XK, XX},
 };
 use rand_core::{CryptoRng, RngCore};
-use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use std::marker::PhantomData;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt as _};
 
 /// accept incoming handshake
 ///
@@ -22,10 +28,45 @@ use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
 ///
 /// This object offers the necessary tooling to identify the initiator so it is
 /// possible to deny the connection early enough (see [Accepting::accept])
+///
+/// The initiator either knows our public key and opens a [Noise **IK**]
+/// handshake ([`Handle::open`]) or it does not and opens an identity
+/// hiding [Noise **XX**] handshake ([`Handle::open_xx`]), the pattern is
+/// told apart from the size of the first message. The [Noise **XX**]
+/// handshakes send our public key to anyone, they are rejected unless
+/// [`allow_xx`](Accepting::allow_xx) is set.
+///
+/// If the [Noise **IK**] initial message cannot be decrypted (the initiator
+/// uses one of our previous keys for example), we may fall back to a
+/// [Noise **XXfallback**] handshake sending our current public key: the
+/// initiators opening with [`Handle::open_with_fallback`] recover from it.
+/// Like for the [Noise **XX**] handshakes, this is rejected unless
+/// [`allow_fallback`](Accepting::allow_fallback) is set. Our [`Extensions`]
+/// are only sent once the initiator is authenticated.
+///
+/// An initiator knowing our public key may also hide its identity from
+/// passive observers with a [Noise **XK**] handshake ([`Handle::open_xk`]).
+///
+/// The initiator may also stay anonymous with a [Noise **NK**] handshake
+/// ([`Handle::open_nk`]), these connections are rejected unless
+/// [`allow_anonymous`](Accepting::allow_anonymous) is set. The first
+/// messages of the [Noise **XK**] and [Noise **NK**] handshakes have the
+/// same size, they are told apart by decrypting the message.
+///
+/// [Noise **IK**]: https://noiseexplorer.com/patterns/IK/
+/// [Noise **XX**]: https://noiseexplorer.com/patterns/XX/
+/// [Noise **XXfallback**]: https://noiseexplorer.com/patterns/XXfallback/
+/// [Noise **XK**]: https://noiseexplorer.com/patterns/XK/
+/// [Noise **NK**]: https://noiseexplorer.com/patterns/NK/
 pub struct Accepting<I, O, RNG, K = ed25519::SecretKey> {
     reader: I,
     writer: O,
-    state: IK<K, Blake2b, RNG, A>,
+    rng: RNG,
+    anonymous: bool,
+    xx: bool,
+    fallback: bool,
+    config: ConnectConfig,
+    _key: PhantomData<K>,
 }
 
 impl<I, O, K, RNG> Accepting<I, O, RNG, K>
@@ -37,9 +78,56 @@ where
         Self {
             reader,
             writer,
-            state: IK::new(rng, &[]),
+            rng,
+            anonymous: false,
+            xx: false,
+            fallback: false,
+            config: ConnectConfig::default(),
+            _key: PhantomData,
         }
     }
+
+    /// accept the initiators that do not authenticate themselves
+    ///
+    /// the `check_id` function is not called for these connections and
+    /// the [`Handle::remote_public_identity`] is `None`.
+    pub fn allow_anonymous(mut self) -> Self {
+        self.anonymous = true;
+        self
+    }
+
+    /// accept the [Noise **XX**] handshakes of the initiators that do not
+    /// know our public key
+    ///
+    /// our public key is sent to whoever opens the connection, `check_id`
+    /// only verifies the initiator after that. This defeats hiding the
+    /// listener (see [`Obfs4`](crate::Obfs4)).
+    ///
+    /// [Noise **XX**]: https://noiseexplorer.com/patterns/XX/
+    pub fn allow_xx(mut self) -> Self {
+        self.xx = true;
+        self
+    }
+
+    /// reply to the [Noise **IK**] initial messages we cannot decrypt with
+    /// a [Noise **XXfallback**] handshake sending our current public key
+    ///
+    /// like for [`allow_xx`](Self::allow_xx), our public key is sent
+    /// before the initiator is authenticated.
+    ///
+    /// [Noise **IK**]: https://noiseexplorer.com/patterns/IK/
+    /// [Noise **XXfallback**]: https://noiseexplorer.com/patterns/XXfallback/
+    pub fn allow_fallback(mut self) -> Self {
+        self.fallback = true;
+        self
+    }
+
+    /// bound the handshake and the accepted connection by the limits of
+    /// the [`ConnectConfig`]
+    pub fn with_config(mut self, config: ConnectConfig) -> Self {
+        self.config = config;
+        self
+    }
 }
 
 impl<I, O, K, RNG> Accepting<I, O, RNG, K>
@@ -64,50 +152,373 @@ where
     /// noise handshake.
     ///
     pub async fn accept<F>(self, k: &K, check_id: F) -> Result<Handle<I, O>>
+    where
+        F: Fn(&PublicKey) -> bool,
+    {
+        self.accept_with_extensions(k, &Extensions::new(), check_id)
+            .await
+    }
+
+    /// same as [`accept`](Self::accept) but reply the given [`Extensions`]
+    /// to the initiator
+    ///
+    /// the initiator's extensions are available with
+    /// [`Handle::remote_extensions`]. The [Noise **XX**] handshakes do
+    /// not exchange any extensions.
+    ///
+    /// [Noise **XX**]: https://noiseexplorer.com/patterns/XX/
+    pub async fn accept_with_extensions<F>(
+        self,
+        k: &K,
+        extensions: &Extensions,
+        check_id: F,
+    ) -> Result<Handle<I, O>>
+    where
+        F: Fn(&PublicKey) -> bool,
+    {
+        let config = self.config;
+        let handle = with_timeout(
+            config.handshake_timeout,
+            self.handshake(k, extensions, check_id),
+        )
+        .await?;
+
+        Ok(handle.with_config(&config))
+    }
+
+    async fn handshake<F>(self, k: &K, extensions: &Extensions, check_id: F) -> Result<Handle<I, O>>
     where
         F: Fn(&PublicKey) -> bool,
     {
         let Self {
             mut reader,
-            mut writer,
-            state,
+            writer,
+            rng,
+            anonymous,
+            xx,
+            fallback,
+            config,
+            _key,
         } = self;
 
-        let mut bytes = [0; HandshakeInitialize::SIZE];
-
-        reader
-            .read_exact(&mut bytes)
+        let message = Initiation::read(&mut reader)
             .await
-            .context("Cannot receive the Noise IK initiate Handshake")?;
+            .context("Cannot receive the Noise initiate Handshake")?;
 
-        let message = HandshakeInitialize::from_bytes(bytes);
-
-        if !message.version().is_supported() {
-            bail!("Unsupported version {:?}", message.version());
+        match message {
+            Initiation::IK(message) => {
+                let extensions = config.extensions(extensions)?;
+                accept_ik(
+                    reader,
+                    writer,
+                    rng,
+                    k,
+                    &extensions,
+                    &config,
+                    fallback,
+                    check_id,
+                    message,
+                )
+                .await
+            }
+            Initiation::XX(_) if !xx => bail!("Rejecting Noise XX handshake"),
+            Initiation::XX(message) => {
+                config.check_no_extensions("XX")?;
+                accept_xx(reader, writer, rng, k, check_id, message).await
+            }
+            Initiation::NkOrXk(message) => {
+                config.check_no_extensions("XK or NK")?;
+                let version = message.negotiate()?;
+                let versions = message.versions();
+                let mut rng = rng;
+                match XK::<K, Blake2b, _, _>::new(&mut rng, &None, &handshake::prologue(&versions))
+                    .receive(k, message.message())
+                {
+                    Ok(state) => {
+                        accept_xk(reader, writer, check_id, versions, version, state).await
+                    }
+                    Err(_) if anonymous => accept_nk(reader, writer, rng, k, message).await,
+                    Err(_) => bail!("Rejecting anonymous connection"),
+                }
+            }
         }
+    }
+}
 
-        let state = state
-            .receive(k, message.message())
-            .context("Noise IK Handshake Initiate failed")?;
+#[allow(clippy::too_many_arguments)]
+async fn accept_ik<I, O, RNG, K, F>(
+    reader: I,
+    mut writer: O,
+    rng: RNG,
+    k: &K,
+    extensions: &Extensions,
+    config: &ConnectConfig,
+    fallback: bool,
+    check_id: F,
+    message: HandshakeInitialize,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+    F: Fn(&PublicKey) -> bool,
+{
+    let version = message.negotiate()?;
+    let versions = message.versions();
+    let mut rng = rng;
+    let state = IK::<K, Blake2b, _, _>::new(&mut rng, &None, &handshake::prologue(&versions));
 
-        if !check_id(state.remote_public_identity()) {
-            bail!(
-                "Rejecting connection with {}",
-                state.remote_public_identity()
+    let mut payload = Vec::with_capacity(message.message().len());
+    let state = match state.receive_with_policy(k, message.message(), &mut payload, &check_id) {
+        Err(HandshakeStateError::RejectedIdentity(id)) => bail!("Rejecting connection with {}", id),
+        Err(HandshakeStateError::Cipher(_)) if !fallback => {
+            bail!("Cannot decrypt the Noise IK initial Handshake")
+        }
+        Err(HandshakeStateError::Cipher(_)) => {
+            // the initiator may be using one of our previous keys
+            return accept_fallback(
+                reader, writer, rng, k, extensions, config, check_id, message,
             )
+            .await;
         }
+        result => result.context("Noise IK Handshake Initiate failed")?,
+    };
+    let remote_extensions =
+        Extensions::from_bytes(&payload).context("Invalid handshake extensions")?;
 
-        let mut message = HandshakeResponse::DEFAULT;
+    let mut message = Vec::with_capacity(HandshakeResponse::MAX_MESSAGE_SIZE);
 
-        let state = state
-            .reply(&mut message.message_mut())
-            .context("Cannot prep the Noise's Handshake Response message")?;
+    let mut state = state
+        .reply_with_payload(
+            handshake::reply_payload(&versions, &extensions.to_bytes()),
+            &mut message,
+        )
+        .context("Cannot prep the Noise's Handshake Response message")?;
+    config.agree_rekey_policy(&mut state, &remote_extensions)?;
 
-        writer
-            .write_all(message.as_ref())
-            .await
-            .context("Cannot send the Noise IK response Handshake")?;
+    writer
+        .write_all(&HandshakeResponse::new(version, message).to_bytes())
+        .await
+        .context("Cannot send the Noise IK response Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise IK response Handshake")?;
+
+    Ok(Handle::new(reader, writer, state, remote_extensions).with_version(version))
+}
+
+/// reply to an [Noise **IK**] initial message we could not decrypt with
+/// a [Noise **XXfallback**] handshake, see [Noise Pipes]
+///
+/// [Noise **IK**]: https://noiseexplorer.com/patterns/IK/
+/// [Noise **XXfallback**]: https://noiseexplorer.com/patterns/XXfallback/
+/// [Noise Pipes]: http://noiseprotocol.org/noise.html#noise-pipes
+#[allow(clippy::too_many_arguments)]
+async fn accept_fallback<I, O, RNG, K, F>(
+    mut reader: I,
+    mut writer: O,
+    rng: RNG,
+    k: &K,
+    extensions: &Extensions,
+    config: &ConnectConfig,
+    check_id: F,
+    message: HandshakeInitialize,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+    F: Fn(&PublicKey) -> bool,
+{
+    let version = message.negotiate()?;
+    let versions = message.versions();
+    let mut reply = Vec::with_capacity(FallbackResponse::MAX_MESSAGE_SIZE);
+    let state = XXfallback::<K, Blake2b, RNG, _>::new(rng, &handshake::prologue(&versions))
+        .reply_with_payload(
+            k,
+            message.message(),
+            handshake::reply_payload(&versions, &[]),
+            &mut reply,
+        )
+        .context("Cannot prep the Noise's XXfallback Handshake Response message")?;
+
+    writer
+        .write_all(&FallbackResponse::new(version, reply).to_bytes())
+        .await
+        .context("Cannot send the Noise XXfallback response Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XXfallback response Handshake")?;
+
+    let message = FallbackFinalize::read(&mut reader)
+        .await
+        .context("Cannot receive the Noise XXfallback final Handshake")?;
+    let mut payload = Vec::with_capacity(message.message().len());
+    let mut state = state
+        .receive_with_payload(message.message(), &mut payload)
+        .context("Noise XXfallback Handshake final message failed")?;
+    let remote_extensions =
+        Extensions::from_bytes(&payload).context("Invalid handshake extensions")?;
+
+    let id = state
+        .remote_public_identity()
+        .context("Noise XXfallback Handshake did not authenticate the initiator")?;
+    if !check_id(id) {
+        bail!("Rejecting connection with {}", id)
+    }
+
+    // the initiator is authenticated, it can have our extensions
+    let mut message = Vec::with_capacity(FallbackExtensions::MAX_MESSAGE_SIZE);
+    state
+        .send(extensions.to_bytes(), &mut message)
+        .context("Cannot encrypt the Noise XXfallback extensions")?;
+    writer
+        .write_all(&FallbackExtensions::new(version, message).to_bytes())
+        .await
+        .context("Cannot send the Noise XXfallback extensions")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XXfallback extensions")?;
+    config.agree_rekey_policy(&mut state, &remote_extensions)?;
+
+    Ok(Handle::new(reader, writer, state, remote_extensions).with_version(version))
+}
+
+async fn accept_xx<I, O, RNG, K, F>(
+    mut reader: I,
+    mut writer: O,
+    rng: RNG,
+    k: &K,
+    check_id: F,
+    message: XxInitialize,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+    F: Fn(&PublicKey) -> bool,
+{
+    let version = message.negotiate()?;
+    let versions = message.versions();
+    let state = XX::<K, Blake2b, RNG, _>::new(rng, &None, &handshake::prologue(&versions))
+        .receive(message.message())
+        .context("Noise XX Handshake Initiate failed")?;
+
+    let mut message = Vec::with_capacity(XxResponse::MIN_MESSAGE_SIZE);
+    let state = state
+        .reply_with_payload(k, handshake::reply_payload(&versions, &[]), &mut message)
+        .context("Cannot prep the Noise's XX Handshake Response message")?;
+
+    writer
+        .write_all(&XxResponse::new(version, message).to_bytes())
+        .await
+        .context("Cannot send the Noise XX response Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XX response Handshake")?;
+
+    let message = XxFinalize::read(&mut reader)
+        .await
+        .context("Cannot receive the Noise XX final Handshake")?;
+    let state = state
+        .receive(message.message())
+        .context("Noise XX Handshake final message failed")?;
+
+    let id = state
+        .remote_public_identity()
+        .context("Noise XX Handshake did not authenticate the initiator")?;
+    if !check_id(id) {
+        bail!("Rejecting connection with {}", id)
+    }
+
+    Ok(Handle::new(reader, writer, state, Extensions::new()).with_version(version))
+}
 
-        Ok(Handle::new(reader, writer, state))
+async fn accept_xk<I, O, RNG, K, F>(
+    mut reader: I,
+    mut writer: O,
+    check_id: F,
+    versions: VersionRange,
+    version: Version,
+    state: XK<K, Blake2b, RNG, xk::SendB>,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+    F: Fn(&PublicKey) -> bool,
+{
+    let mut message = Vec::with_capacity(XkResponse::MIN_MESSAGE_SIZE);
+    let state = state
+        .reply_with_payload(handshake::reply_payload(&versions, &[]), &mut message)
+        .context("Cannot prep the Noise's XK Handshake Response message")?;
+
+    writer
+        .write_all(&XkResponse::new(version, message).to_bytes())
+        .await
+        .context("Cannot send the Noise XK response Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XK response Handshake")?;
+
+    let message = XkFinalize::read(&mut reader)
+        .await
+        .context("Cannot receive the Noise XK final Handshake")?;
+    let state = state
+        .receive(message.message())
+        .context("Noise XK Handshake final message failed")?;
+
+    let id = state
+        .remote_public_identity()
+        .context("Noise XK Handshake did not authenticate the initiator")?;
+    if !check_id(id) {
+        bail!("Rejecting connection with {}", id)
     }
+
+    Ok(Handle::new(reader, writer, state, Extensions::new()).with_version(version))
+}
+
+async fn accept_nk<I, O, RNG, K>(
+    reader: I,
+    mut writer: O,
+    rng: RNG,
+    k: &K,
+    message: NkInitialize,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+{
+    let version = message.negotiate()?;
+    let versions = message.versions();
+    let state = NK::<K, Blake2b, RNG, _>::new(rng, &handshake::prologue(&versions))
+        .receive(k, message.message())
+        .context("Noise NK Handshake Initiate failed")?;
+
+    let mut message = Vec::with_capacity(NkResponse::MIN_MESSAGE_SIZE);
+    let state = state
+        .reply_with_payload(handshake::reply_payload(&versions, &[]), &mut message)
+        .context("Cannot prep the Noise's NK Handshake Response message")?;
+
+    writer
+        .write_all(&NkResponse::new(version, message).to_bytes())
+        .await
+        .context("Cannot send the Noise NK response Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise NK response Handshake")?;
+
+    Ok(Handle::new(reader, writer, state, Extensions::new()).with_version(version))
 }
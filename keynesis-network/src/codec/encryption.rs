@@ -8,9 +8,41 @@ use keynesis_core::{
 use std::io;
 use tokio_util::codec::{Decoder, Encoder};
 
-const MIN_FRAME_LENGTH: usize = 16; // the length and the 16 bytes of mac
+const MIN_FRAME_LENGTH: usize = CONTENT_TYPE_LENGTH + 16; // the content type and the 16 bytes of mac
 pub const MAX_FRAME_LENGTH: usize = u16::MAX as usize - HEAD_LENGTH;
 const HEAD_LENGTH: usize = std::mem::size_of::<u16>();
+const CONTENT_TYPE_LENGTH: usize = std::mem::size_of::<u8>();
+/// maximum length of the payload of a frame
+pub const MAX_PAYLOAD_LENGTH: usize = MAX_FRAME_LENGTH - 16 - CONTENT_TYPE_LENGTH;
+
+/// type of the content of a frame
+///
+/// this is the first byte of the encrypted data of the frame so the
+/// application data can be multiplexed with the control messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// application data
+    Data = 0,
+    /// initial message of a new handshake performed within the session
+    RehandshakeInitialize = 1,
+    /// response to the [`ContentType::RehandshakeInitialize`]
+    RehandshakeResponse = 2,
+    /// sent on an idle connection so the remote peer (and the NATs on the
+    /// way) know it is still alive, without payload
+    KeepAlive = 3,
+    /// the sender will not send anything more, without payload
+    ///
+    /// the connection is closed gracefully once both peers sent it, the
+    /// end of the underlying stream without it is a truncation.
+    Close = 4,
+}
+
+/// a decoded frame
+#[derive(Debug)]
+pub struct Frame {
+    pub content_type: ContentType,
+    pub payload: BytesMut,
+}
 
 /**
 # Decoder for encrypted connections
@@ -68,13 +100,26 @@ impl NoiseEncryptedEncoder {
         &self.session
     }
 
+    /// replace the transport state, the following frames will be
+    /// encrypted with the new session's keys
+    pub fn rekey(&mut self, noise: TransportSendHalf<Blake2b>) {
+        *self = Self::new(noise);
+    }
+
     /// retrieve the remote's public key. When performing the handshake
     /// the two peers are going to securely share their public keys in
     /// order to authenticate to each others.
     ///
-    pub fn remote_public_identity(&self) -> &PublicKey {
+    /// `None` if the remote peer connected anonymously.
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
         self.noise.remote_public_identity()
     }
+
+    /// number of frames that can still be encoded before the session
+    /// reaches its nonce limit and a new session is required
+    pub fn remaining_sends(&self) -> u64 {
+        self.noise.remaining_sends()
+    }
 }
 
 impl NoiseEncryptedDecoder {
@@ -93,14 +138,27 @@ impl NoiseEncryptedDecoder {
         &self.session
     }
 
+    /// replace the transport state, the following frames will be
+    /// decrypted with the new session's keys
+    pub fn rekey(&mut self, noise: TransportReceiveHalf<Blake2b>) {
+        *self = Self::new(noise);
+    }
+
     /// retrieve the remote's public key. When performing the handshake
     /// the two peers are going to securely share their public keys in
     /// order to authenticate to each others.
     ///
-    pub fn remote_public_identity(&self) -> &PublicKey {
+    /// `None` if the remote peer connected anonymously.
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
         self.noise.remote_public_identity()
     }
 
+    /// number of frames that can still be decoded before the session
+    /// reaches its nonce limit and a new session is required
+    pub fn remaining_receives(&self) -> u64 {
+        self.noise.remaining_receives()
+    }
+
     fn decode_head(&mut self, src: &mut BytesMut) -> io::Result<Option<usize>> {
         if src.len() < HEAD_LENGTH {
             return Ok(None);
@@ -127,24 +185,41 @@ impl NoiseEncryptedDecoder {
         Ok(Some(n))
     }
 
-    fn decode_data(&mut self, n: usize, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+    fn decode_data(&mut self, n: usize, src: &mut BytesMut) -> io::Result<Option<Frame>> {
         if src.len() < n {
             return Ok(None);
         }
 
         let bytes = src.split_to(n);
-        let mut output = vec![0; n.wrapping_sub(16)];
+        let mut output = BytesMut::with_capacity(n.saturating_sub(16));
 
         if let Err(error) = self.noise.receive(bytes.as_ref(), &mut output) {
-            Err(io::Error::new(io::ErrorKind::InvalidData, error))
-        } else {
-            Ok(Some(BytesMut::from(output.as_slice())))
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
         }
+
+        let content_type = match output.get_u8() {
+            0 => ContentType::Data,
+            1 => ContentType::RehandshakeInitialize,
+            2 => ContentType::RehandshakeResponse,
+            3 => ContentType::KeepAlive,
+            4 => ContentType::Close,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unknown content type",
+                ))
+            }
+        };
+
+        Ok(Some(Frame {
+            content_type,
+            payload: output,
+        }))
     }
 }
 
 impl Decoder for NoiseEncryptedDecoder {
-    type Item = BytesMut;
+    type Item = Frame;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
@@ -170,30 +245,52 @@ impl Decoder for NoiseEncryptedDecoder {
     }
 }
 
-impl Encoder<Bytes> for NoiseEncryptedEncoder {
-    type Error = io::Error;
-    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let n = item.len();
-
-        if n > (MAX_FRAME_LENGTH - 16) {
+impl NoiseEncryptedEncoder {
+    fn encode_frame(
+        &mut self,
+        content_type: ContentType,
+        payload: &[u8],
+        dst: &mut BytesMut,
+    ) -> io::Result<()> {
+        let n = payload.len();
+
+        if n > MAX_PAYLOAD_LENGTH {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "frame is too long",
             ));
         }
 
-        let n = n.wrapping_add(16);
+        let mut plaintext = BytesMut::with_capacity(CONTENT_TYPE_LENGTH + n);
+        plaintext.put_u8(content_type as u8);
+        plaintext.extend_from_slice(payload);
+
+        let n = plaintext.len().wrapping_add(16);
 
         dst.reserve(HEAD_LENGTH + n);
 
+        let start = dst.len();
         dst.put_u16(n as u16);
 
-        let mut output = vec![0; n];
-        if let Err(error) = self.noise.send(item.as_ref(), &mut output) {
+        if let Err(error) = self.noise.send(plaintext.as_ref(), dst) {
+            dst.truncate(start);
             Err(io::Error::new(io::ErrorKind::InvalidInput, error))
         } else {
-            dst.extend_from_slice(output.as_ref());
             Ok(())
         }
     }
 }
+
+impl Encoder<Bytes> for NoiseEncryptedEncoder {
+    type Error = io::Error;
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_frame(ContentType::Data, item.as_ref(), dst)
+    }
+}
+
+impl Encoder<Frame> for NoiseEncryptedEncoder {
+    type Error = io::Error;
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_frame(item.content_type, item.payload.as_ref(), dst)
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{ensure, Context as _, Result};
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+
+const HEADER_SIZE: usize = std::mem::size_of::<u32>();
+
+/// length prefixed message
+///
+/// composed of the length of the message (4 bytes, big endian) and the
+/// message itself. It is written to the encrypted byte stream of a
+/// [`Handle`] so the length is encrypted too and the message can span
+/// over multiple noise frames.
+///
+/// [`Handle`]: crate::Handle
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub struct FramedMessage {
+    message: Bytes,
+}
+
+impl FramedMessage {
+    /// maximum size of a message, larger messages are rejected before
+    /// allocating the memory to receive them
+    pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+    pub fn new(message: Bytes) -> Result<Self> {
+        Self::check_len(message.len())?;
+        Ok(Self { message })
+    }
+
+    pub fn message(&self) -> &Bytes {
+        &self.message
+    }
+
+    pub fn into_message(self) -> Bytes {
+        self.message
+    }
+
+    fn check_len(len: usize) -> Result<()> {
+        Self::check_len_limit(len, Self::MAX_MESSAGE_SIZE)
+    }
+
+    fn check_len_limit(len: usize, max_message_size: usize) -> Result<()> {
+        ensure!(
+            len <= max_message_size,
+            "Invalid framed message length ({} bytes)",
+            len
+        );
+        Ok(())
+    }
+
+    /// write the length prefix and the message to the given stream
+    pub async fn write<O>(&self, writer: &mut O) -> Result<()>
+    where
+        O: AsyncWrite + Unpin,
+    {
+        writer
+            .write_all(&(self.message.len() as u32).to_be_bytes())
+            .await
+            .context("Cannot write the framed message header")?;
+        writer
+            .write_all(&self.message)
+            .await
+            .context("Cannot write the framed message")?;
+        writer
+            .flush()
+            .await
+            .context("Cannot flush the framed message")
+    }
+
+    /// read the next message from the given stream
+    ///
+    /// returns `None` if the stream is closed before a new message
+    pub async fn read<I>(reader: &mut I) -> Result<Option<Self>>
+    where
+        I: AsyncRead + Unpin,
+    {
+        Self::read_limited(reader, Self::MAX_MESSAGE_SIZE).await
+    }
+
+    /// same as [`read`](Self::read) but reject the messages larger than
+    /// `max_message_size`
+    pub(crate) async fn read_limited<I>(
+        reader: &mut I,
+        max_message_size: usize,
+    ) -> Result<Option<Self>>
+    where
+        I: AsyncRead + Unpin,
+    {
+        let mut header = [0; HEADER_SIZE];
+        let read = reader
+            .read(&mut header)
+            .await
+            .context("Cannot read the framed message header")?;
+        if read == 0 {
+            return Ok(None);
+        }
+        reader
+            .read_exact(&mut header[read..])
+            .await
+            .context("Cannot read the framed message header")?;
+
+        let len = u32::from_be_bytes(header) as usize;
+        Self::check_len_limit(len, max_message_size)?;
+
+        let mut message = BytesMut::zeroed(len);
+        reader
+            .read_exact(&mut message)
+            .await
+            .context("Cannot read the framed message")?;
+
+        Ok(Some(Self {
+            message: message.freeze(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn write_read() {
+        let message = FramedMessage::new(Bytes::from_static(b"framed message")).unwrap();
+        let mut bytes = Vec::new();
+        block_on(message.write(&mut bytes)).unwrap();
+
+        assert_eq!(bytes.len(), HEADER_SIZE + message.message().len());
+
+        let mut reader = bytes.as_slice();
+        let decoded = block_on(FramedMessage::read(&mut reader)).unwrap();
+        assert_eq!(decoded, Some(message));
+        assert_eq!(block_on(FramedMessage::read(&mut reader)).unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_length() {
+        let bytes = ((FramedMessage::MAX_MESSAGE_SIZE + 1) as u32).to_be_bytes();
+        assert!(block_on(FramedMessage::read(&mut bytes.as_ref())).is_err());
+
+        // truncated header and message
+        assert!(block_on(FramedMessage::read(&mut [0, 0].as_ref())).is_err());
+        assert!(block_on(FramedMessage::read(&mut [0, 0, 0, 2, 1].as_ref())).is_err());
+    }
+}
@@ -1,81 +1,680 @@
-use crate::Version;
+use crate::{Extensions, Version, VersionRange};
+use anyhow::{bail, ensure, Context as _, Result};
 use keynesis_core::key::ed25519;
+use tokio::io::{AsyncRead, AsyncReadExt as _};
+
+const HEADER_SIZE: usize = Version::SIZE + std::mem::size_of::<u16>();
+const INITIATE_HEADER_SIZE: usize = VersionRange::SIZE + std::mem::size_of::<u16>();
+
+/// handshake message
+///
+/// composed of the [`Version`], the length of the noise handshake message
+/// (2 bytes, big endian) and the noise handshake message itself. The
+/// [`Extensions`] are in the payload of the noise message so the size
+/// of the message is at least `MIN` bytes.
+///
+/// The [`Version`] of the header is not authenticated: the replies of
+/// the responder carry its [`VersionRange`] in their payload (see
+/// [`reply_payload`]) and the initiator checks the [`Version`] against
+/// it with [`check_version`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Hash)]
+pub struct HandshakeMessage<const MIN: usize> {
+    version: Version,
+    message: Vec<u8>,
+}
+
+/// first handshake message of the initiator
+///
+/// same as the [`HandshakeMessage`] but starts with the [`VersionRange`]
+/// supported by the initiator instead of a [`Version`]: the maximal
+/// version first, then the minimal one. The responder picks the version
+/// of the session and replies with it. The range is the prologue of the
+/// noise handshake (see [`prologue`]).
+///
+/// The [`Version::V2`] peers only advertise their version, so the
+/// messages starting with a version older than [`Version::V3`] are
+/// [`HandshakeMessage`]s without prologue (and the replies do not carry
+/// the responder's [`VersionRange`], see [`reply_payload`]).
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub struct InitiateMessage<const MIN: usize> {
+    versions: VersionRange,
+    message: Vec<u8>,
+}
 
 /// initial handshake message
 ///
-/// composed of the [`Version`] and the noise initiator handshake [`IK`]
+/// composed of the [`VersionRange`] and the noise initiator handshake [`IK`]
 ///
 /// [`IK`]: keynesis::noise::IK
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
-pub struct HandshakeInitialize([u8; Self::SIZE]);
+pub type HandshakeInitialize =
+    InitiateMessage<{ ed25519::PublicKey::SIZE + (ed25519::PublicKey::SIZE + 16) + 16 }>;
 
 /// handshake reply
 ///
 /// composed of the [`Version`] and the noise response handshake [`IK`]
+/// with the responder's [`VersionRange`] (from [`Version::V3`]) and
+/// [`Extensions`] in its payload
+///
+/// [`IK`]: keynesis::noise::IK
+pub type HandshakeResponse = HandshakeMessage<{ ed25519::PublicKey::SIZE + 16 }>;
+
+/// reply of the responder of a [Noise Pipes] handshake that could not
+/// decrypt the [`HandshakeInitialize`], the first message of the
+/// [`XXfallback`] handshake
+///
+/// composed of the [`Version`] and the responder's ephemeral key, its new
+/// static key and its [`VersionRange`]. The initiator is not authenticated
+/// yet so the responder's [`Extensions`] come later, in the
+/// [`FallbackExtensions`].
+///
+/// [Noise Pipes]: http://noiseprotocol.org/noise.html#noise-pipes
+/// [`XXfallback`]: keynesis::noise::XXfallback
+pub type FallbackResponse =
+    HandshakeMessage<{ ed25519::PublicKey::SIZE + (ed25519::PublicKey::SIZE + 16) + 16 }>;
+
+/// last message of the [`XXfallback`] handshake, from the initiator
+///
+/// composed of the [`Version`], the initiator's static key and the
+/// [`Extensions`] (sent again as the responder could not decrypt the
+/// [`HandshakeInitialize`]).
+///
+/// [`XXfallback`]: keynesis::noise::XXfallback
+pub type FallbackFinalize = HandshakeMessage<{ (ed25519::PublicKey::SIZE + 16) + 16 }>;
+
+/// the [`Extensions`] of the responder of the [`XXfallback`] handshake,
+/// sent once the initiator is authenticated
+///
+/// composed of the [`Version`] and the [`Extensions`] encrypted with the
+/// established session (the first message of the responder).
+///
+/// [`XXfallback`]: keynesis::noise::XXfallback
+pub type FallbackExtensions = HandshakeMessage<16>;
+
+/// initial handshake message of the identity hiding connections
+///
+/// composed of the [`VersionRange`] and the first message of the noise
+/// handshake [`XX`] (the initiator's ephemeral key). The [`XX`] messages
+/// do not carry [`Extensions`].
+///
+/// [`XX`]: keynesis::noise::XX
+pub type XxInitialize = InitiateMessage<{ ed25519::PublicKey::SIZE }>;
+
+/// second message of the [`XX`] handshake, from the responder, with
+/// the responder's [`VersionRange`] in its payload
+///
+/// [`XX`]: keynesis::noise::XX
+pub type XxResponse =
+    HandshakeMessage<{ ed25519::PublicKey::SIZE + (ed25519::PublicKey::SIZE + 16) + 16 }>;
+
+/// last message of the [`XX`] handshake, from the initiator
+///
+/// [`XX`]: keynesis::noise::XX
+pub type XxFinalize = HandshakeMessage<{ (ed25519::PublicKey::SIZE + 16) + 16 }>;
+
+/// initial handshake message of the anonymous connections
+///
+/// composed of the [`VersionRange`] and the first message of the noise
+/// handshake [`NK`] (the initiator's ephemeral key and an empty
+/// payload). The [`NK`] messages do not carry [`Extensions`].
+///
+/// [`NK`]: keynesis::noise::NK
+pub type NkInitialize = InitiateMessage<{ ed25519::PublicKey::SIZE + 16 }>;
+
+/// initial handshake message of the initiator identity hiding
+/// connections to a known responder
+///
+/// composed of the [`VersionRange`] and the first message of the noise
+/// handshake [`XK`] (the initiator's ephemeral key and an empty
+/// payload). It has the same size as the [`NkInitialize`]: the
+/// responder tells them apart by decrypting the message. The [`XK`]
+/// messages do not carry [`Extensions`].
+///
+/// [`XK`]: keynesis::noise::XK
+pub type XkInitialize = NkInitialize;
+
+/// second message of the [`XK`] handshake, from the responder, with
+/// the responder's [`VersionRange`] in its payload
+///
+/// [`XK`]: keynesis::noise::XK
+pub type XkResponse = HandshakeMessage<{ ed25519::PublicKey::SIZE + 16 }>;
+
+/// last message of the [`XK`] handshake, from the initiator
+///
+/// [`XK`]: keynesis::noise::XK
+pub type XkFinalize = HandshakeMessage<{ (ed25519::PublicKey::SIZE + 16) + 16 }>;
+
+/// second message of the [`NK`] handshake, from the responder, with
+/// the responder's [`VersionRange`] in its payload
+///
+/// [`NK`]: keynesis::noise::NK
+pub type NkResponse = HandshakeMessage<{ ed25519::PublicKey::SIZE + 16 }>;
+
+/// the first message of the initiator, either an [`IK`], an [`XX`],
+/// an [`NK`] or an [`XK`] handshake
+///
+/// the patterns are told apart by the size of the message: the
+/// [`XxInitialize`] and the [`NkInitialize`] have a fixed size smaller
+/// than any [`HandshakeInitialize`]. The [`NK`] and [`XK`] messages
+/// have the same size and are only told apart by the noise handshake.
 ///
 /// [`IK`]: keynesis::noise::IK
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
-pub struct HandshakeResponse([u8; Self::SIZE]);
+/// [`XX`]: keynesis::noise::XX
+/// [`NK`]: keynesis::noise::NK
+/// [`XK`]: keynesis::noise::XK
+#[derive(Debug)]
+pub enum Initiation {
+    IK(HandshakeInitialize),
+    XX(XxInitialize),
+    NkOrXk(NkInitialize),
+}
+
+impl Initiation {
+    /// read the first message of the initiator from the given stream
+    pub async fn read<I>(reader: &mut I) -> Result<Self>
+    where
+        I: AsyncRead + Unpin,
+    {
+        let mut header = [0; INITIATE_HEADER_SIZE];
+        reader
+            .read_exact(&mut header[..1])
+            .await
+            .context("Cannot read the handshake header")?;
+        let header_size = initiate_header_size(header[0]);
+        let header = &mut header[..header_size];
+        reader
+            .read_exact(&mut header[1..])
+            .await
+            .context("Cannot read the handshake header")?;
 
-impl HandshakeInitialize {
-    pub const SIZE: usize =
-        Version::SIZE + ed25519::PublicKey::SIZE + (ed25519::PublicKey::SIZE + 16) + 16;
-    pub const DEFAULT: Self = Self::new(Version::CURRENT);
+        let (versions, len) = decode_initiate_header(header)?;
+        if len != XxInitialize::MIN_MESSAGE_SIZE && len != NkInitialize::MIN_MESSAGE_SIZE {
+            HandshakeInitialize::check_len(len)?;
+        }
 
-    const fn new(version: Version) -> Self {
-        Self::from_bytes([version.to_u8(); Self::SIZE])
+        let mut message = vec![0; len];
+        reader
+            .read_exact(&mut message)
+            .await
+            .context("Cannot read the handshake message")?;
+
+        if len == XxInitialize::MIN_MESSAGE_SIZE {
+            Ok(Self::XX(XxInitialize { versions, message }))
+        } else if len == NkInitialize::MIN_MESSAGE_SIZE {
+            Ok(Self::NkOrXk(NkInitialize { versions, message }))
+        } else {
+            Ok(Self::IK(HandshakeInitialize { versions, message }))
+        }
     }
+}
 
-    pub const fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
-        Self(bytes)
+/// read the reply to a [`HandshakeInitialize`] from the given stream
+///
+/// the reply is either a [`HandshakeResponse`] or a [`FallbackResponse`].
+/// They cannot be told apart from their size, only by decrypting the
+/// message (see [`IK::receive_with_fallback`]).
+///
+/// [`IK::receive_with_fallback`]: keynesis::noise::IK::receive_with_fallback
+pub async fn read_response<I>(reader: &mut I) -> Result<(Version, Vec<u8>)>
+where
+    I: AsyncRead + Unpin,
+{
+    let mut header = [0; HEADER_SIZE];
+    reader
+        .read_exact(&mut header)
+        .await
+        .context("Cannot read the handshake header")?;
+
+    let (version, len) = decode_header(header)?;
+    if HandshakeResponse::check_len(len).is_err() {
+        FallbackResponse::check_len(len)?;
     }
 
-    pub fn version(&self) -> Version {
-        Version::from_u8(self.0[0])
+    let mut message = vec![0; len];
+    reader
+        .read_exact(&mut message)
+        .await
+        .context("Cannot read the handshake message")?;
+
+    Ok((version, message))
+}
+
+/// `true` if the `versions` are advertised with the handshake of the
+/// [`Version::V2`] peers, see [`InitiateMessage`]
+fn is_legacy(versions: &VersionRange) -> bool {
+    versions.max() < Version::V3
+}
+
+/// the prologue of the noise handshakes of the initiator advertising
+/// the `versions`: the encoded [`VersionRange`], there is none in the
+/// handshakes of the [`Version::V2`] peers
+pub fn prologue(versions: &VersionRange) -> Vec<u8> {
+    if is_legacy(versions) {
+        Vec::new()
+    } else {
+        versions.to_bytes().to_vec()
     }
+}
 
-    pub fn message(&self) -> &[u8] {
-        &self.0[1..]
+/// the versions advertised in the re-handshakes of a session of the
+/// given `version`: a [`Version::V2`] peer only knows its version
+pub fn session_versions(version: Version) -> VersionRange {
+    if version < Version::V3 {
+        VersionRange::new(version, version).expect("a single version")
+    } else {
+        VersionRange::SUPPORTED
     }
+}
+
+/// check the `versions` the initiator is about to advertise are ones we
+/// support
+pub fn check_advertised(versions: &VersionRange) -> Result<()> {
+    ensure!(
+        versions.min().is_supported() && versions.max().is_supported(),
+        "Cannot advertise the unsupported versions {}",
+        versions
+    );
+    Ok(())
+}
+
+/// the payload of the replies of the responder to the initiator that
+/// advertised the `versions`: the [`VersionRange`] it supports followed
+/// by its `extensions` (if any)
+///
+/// unlike the [`Version`] in the header of the reply, the payload is
+/// authenticated by the noise handshake. The replies to the
+/// [`Version::V2`] peers only carry the `extensions`.
+pub fn reply_payload(versions: &VersionRange, extensions: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(VersionRange::SIZE + extensions.len());
+    if !is_legacy(versions) {
+        payload.extend_from_slice(&VersionRange::SUPPORTED.to_bytes());
+    }
+    payload.extend_from_slice(extensions);
+    payload
+}
 
-    pub fn message_mut(&mut self) -> &mut [u8] {
-        &mut self.0[1..]
+/// check the `version` in the header of the reply of the responder is
+/// the one negotiated from the advertised `versions` and the
+/// [`VersionRange`] of its authenticated `payload` (see [`reply_payload`])
+///
+/// returns the rest of the payload: the responder's extensions.
+pub fn check_version<'a>(
+    versions: &VersionRange,
+    version: Version,
+    payload: &'a [u8],
+) -> Result<&'a [u8]> {
+    if is_legacy(versions) {
+        ensure!(
+            version == versions.max(),
+            "The responder replied with version {} but only version {} was advertised",
+            version,
+            versions.max()
+        );
+        return Ok(payload);
     }
+
+    check_negotiated(versions, version, payload)
 }
 
-impl HandshakeResponse {
-    pub const SIZE: usize = Version::SIZE + ed25519::PublicKey::SIZE + 16;
-    pub const DEFAULT: Self = Self::new(Version::CURRENT);
+fn check_negotiated<'a>(
+    supported: &VersionRange,
+    version: Version,
+    payload: &'a [u8],
+) -> Result<&'a [u8]> {
+    ensure!(
+        payload.len() >= VersionRange::SIZE,
+        "Missing the versions of the responder"
+    );
+    let (versions, extensions) = payload.split_at(VersionRange::SIZE);
+    let versions = VersionRange::from_bytes([versions[0], versions[1]]).with_context(|| {
+        format!(
+            "Invalid version range of the responder {}..={}",
+            versions[0], versions[1]
+        )
+    })?;
+
+    let negotiated = supported.negotiate(&versions).with_context(|| {
+        format!(
+            "No version in common with the responder (supports {})",
+            versions
+        )
+    })?;
+    ensure!(
+        version == negotiated,
+        "The responder replied with version {} but version {} was negotiated",
+        version,
+        negotiated
+    );
+
+    Ok(extensions)
+}
 
-    const fn new(version: Version) -> Self {
-        Self::from_bytes([version.to_u8(); Self::SIZE])
+/// decode the version and the length of the noise message
+fn decode_header(header: [u8; HEADER_SIZE]) -> Result<(Version, usize)> {
+    let version = Version::from_u8(header[0]);
+    if !version.is_supported() {
+        bail!("Unsupported version {:?}", version);
     }
 
-    pub const fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
-        Self(bytes)
+    let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+    Ok((version, len))
+}
+
+/// the size of the header of the [`InitiateMessage`] starting with the
+/// byte `first`
+fn initiate_header_size(first: u8) -> usize {
+    if Version::from_u8(first) < Version::V3 {
+        HEADER_SIZE
+    } else {
+        INITIATE_HEADER_SIZE
     }
+}
 
+/// decode the versions advertised by the initiator and the length of
+/// the noise message, see [`initiate_header_size`]
+///
+/// the versions are not checked here, the responder negotiates them
+/// with its own (see [`VersionRange::negotiate`])
+fn decode_initiate_header(header: &[u8]) -> Result<(VersionRange, usize)> {
+    if header.len() == HEADER_SIZE {
+        let (version, len) = decode_header([header[0], header[1], header[2]])?;
+        let versions = VersionRange::new(version, version).expect("a single version");
+        return Ok((versions, len));
+    }
+
+    let versions = VersionRange::from_bytes([header[1], header[0]])
+        .with_context(|| format!("Invalid version range {}..={}", header[1], header[0]))?;
+
+    let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+    Ok((versions, len))
+}
+
+fn check_len(len: usize, min: usize, max: usize) -> Result<()> {
+    ensure!(
+        (min..=max).contains(&len),
+        "Invalid handshake message length ({} bytes)",
+        len
+    );
+    Ok(())
+}
+
+impl<const MIN: usize> HandshakeMessage<MIN> {
+    pub const MIN_MESSAGE_SIZE: usize = MIN;
+    pub const MAX_MESSAGE_SIZE: usize = MIN + Extensions::MAX_SIZE;
+
+    /// the handshake message of a session of the given `version`
+    pub fn new(version: Version, message: Vec<u8>) -> Self {
+        debug_assert!(Self::MIN_MESSAGE_SIZE <= message.len());
+        debug_assert!(message.len() <= Self::MAX_MESSAGE_SIZE);
+
+        Self { version, message }
+    }
+
+    /// the version of the session, picked by the responder
     pub fn version(&self) -> Version {
-        Version::from_u8(self.0[0])
+        self.version
     }
 
     pub fn message(&self) -> &[u8] {
-        &self.0[1..]
+        &self.message
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.message.len());
+        bytes.push(self.version.to_u8());
+        bytes.extend_from_slice(&(self.message.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.message);
+        bytes
+    }
+
+    /// decode the version and the length of the noise message
+    fn decode_header(header: [u8; HEADER_SIZE]) -> Result<(Version, usize)> {
+        let (version, len) = decode_header(header)?;
+        Self::check_len(len)?;
+        Ok((version, len))
+    }
+
+    fn check_len(len: usize) -> Result<()> {
+        check_len(len, Self::MIN_MESSAGE_SIZE, Self::MAX_MESSAGE_SIZE)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(bytes.len() >= HEADER_SIZE, "Invalid handshake message");
+        let (header, message) = bytes.split_at(HEADER_SIZE);
+
+        let (version, len) = Self::decode_header([header[0], header[1], header[2]])?;
+        ensure!(message.len() == len, "Invalid handshake message length");
+
+        Ok(Self {
+            version,
+            message: message.to_vec(),
+        })
     }
 
-    pub fn message_mut(&mut self) -> &mut [u8] {
-        &mut self.0[1..]
+    /// read the handshake message from the given stream
+    pub async fn read<I>(reader: &mut I) -> Result<Self>
+    where
+        I: AsyncRead + Unpin,
+    {
+        let mut header = [0; HEADER_SIZE];
+        reader
+            .read_exact(&mut header)
+            .await
+            .context("Cannot read the handshake header")?;
+
+        let (version, len) = Self::decode_header(header)?;
+
+        let mut message = vec![0; len];
+        reader
+            .read_exact(&mut message)
+            .await
+            .context("Cannot read the handshake message")?;
+
+        Ok(Self { version, message })
     }
 }
 
-impl AsRef<[u8]> for HandshakeInitialize {
-    fn as_ref(&self) -> &[u8] {
-        self.0.as_ref()
+impl<const MIN: usize> InitiateMessage<MIN> {
+    pub const MIN_MESSAGE_SIZE: usize = MIN;
+    pub const MAX_MESSAGE_SIZE: usize = MIN + Extensions::MAX_SIZE;
+
+    /// the handshake message advertising the given `versions`
+    pub fn with_versions(versions: VersionRange, message: Vec<u8>) -> Self {
+        debug_assert!(Self::MIN_MESSAGE_SIZE <= message.len());
+        debug_assert!(message.len() <= Self::MAX_MESSAGE_SIZE);
+
+        Self { versions, message }
+    }
+
+    /// the versions supported by the initiator
+    pub fn versions(&self) -> VersionRange {
+        self.versions
+    }
+
+    /// pick the version of the session: the latest version supported by
+    /// both the initiator and us
+    pub fn negotiate(&self) -> Result<Version> {
+        VersionRange::SUPPORTED
+            .negotiate(&self.versions)
+            .with_context(|| {
+                format!(
+                    "No version in common with the initiator (supports {})",
+                    self.versions
+                )
+            })
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(INITIATE_HEADER_SIZE + self.message.len());
+        bytes.push(self.versions.max().to_u8());
+        if !is_legacy(&self.versions) {
+            bytes.push(self.versions.min().to_u8());
+        }
+        bytes.extend_from_slice(&(self.message.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.message);
+        bytes
+    }
+
+    fn check_len(len: usize) -> Result<()> {
+        check_len(len, Self::MIN_MESSAGE_SIZE, Self::MAX_MESSAGE_SIZE)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let header_size = bytes.first().copied().map_or(0, initiate_header_size);
+        ensure!(
+            header_size > 0 && bytes.len() >= header_size,
+            "Invalid handshake message"
+        );
+        let (header, message) = bytes.split_at(header_size);
+
+        let (versions, len) = decode_initiate_header(header)?;
+        Self::check_len(len)?;
+        ensure!(message.len() == len, "Invalid handshake message length");
+
+        Ok(Self {
+            versions,
+            message: message.to_vec(),
+        })
     }
 }
 
-impl AsRef<[u8]> for HandshakeResponse {
-    fn as_ref(&self) -> &[u8] {
-        self.0.as_ref()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let message = HandshakeResponse::new(
+            Version::CURRENT,
+            vec![1; HandshakeResponse::MIN_MESSAGE_SIZE + 10],
+        );
+        let decoded = HandshakeResponse::from_bytes(&message.to_bytes()).unwrap();
+
+        assert_eq!(message, decoded);
+    }
+
+    #[test]
+    fn invalid_length() {
+        let message = HandshakeInitialize::with_versions(
+            VersionRange::SUPPORTED,
+            vec![1; HandshakeInitialize::MIN_MESSAGE_SIZE],
+        );
+        let bytes = message.to_bytes();
+
+        assert!(HandshakeInitialize::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut bytes = bytes;
+        bytes[2..4]
+            .copy_from_slice(&(HandshakeInitialize::MAX_MESSAGE_SIZE as u16 + 1).to_be_bytes());
+        assert!(HandshakeInitialize::from_bytes(&bytes).is_err());
+    }
+
+    fn range(min: u8, max: u8) -> VersionRange {
+        VersionRange::from_bytes([min, max]).unwrap()
+    }
+
+    #[test]
+    fn check_reply_version() {
+        let supported = VersionRange::SUPPORTED;
+        let payload = reply_payload(&supported, b"extensions");
+        assert_eq!(
+            check_version(&supported, Version::CURRENT, &payload).unwrap(),
+            b"extensions"
+        );
+
+        assert!(check_version(&supported, Version::CURRENT, &[]).is_err());
+        assert!(check_version(&supported, Version::CURRENT, &[3, 2]).is_err());
+    }
+
+    #[test]
+    fn check_legacy_reply_version() {
+        let v2 = range(2, 2);
+        let payload = reply_payload(&v2, b"extensions");
+        assert_eq!(payload, b"extensions");
+        assert_eq!(
+            check_version(&v2, Version::V2, &payload).unwrap(),
+            b"extensions"
+        );
+
+        assert!(check_version(&v2, Version::V3, &payload).is_err());
+    }
+
+    #[test]
+    fn check_negotiated_version() {
+        let v1_v3 = range(1, 3);
+
+        // the responder supports newer versions than us
+        assert!(check_negotiated(&v1_v3, Version::V3, &[2, 4]).is_ok());
+        // the responder only supports older versions
+        assert!(check_negotiated(&v1_v3, Version::V2, &[1, 2]).is_ok());
+        // no version in common
+        assert!(check_negotiated(&range(2, 3), Version::V1, &[1, 1]).is_err());
+    }
+
+    #[test]
+    fn responder_negotiates() {
+        let message = HandshakeInitialize::with_versions(
+            VersionRange::SUPPORTED,
+            vec![1; HandshakeInitialize::MIN_MESSAGE_SIZE],
+        );
+        let mut bytes = message.to_bytes();
+
+        // the initiator supports newer versions than us
+        bytes[..2].copy_from_slice(&[4, 2]);
+        let message = HandshakeInitialize::from_bytes(&bytes).unwrap();
+        assert_eq!(message.negotiate().unwrap(), Version::CURRENT);
+
+        // the initiator only supports older versions
+        bytes[0] = Version::V1.to_u8();
+        assert!(HandshakeInitialize::from_bytes(&bytes).is_err());
+    }
+
+    /// the [`Version::V2`] peers advertise their version alone and
+    /// negotiate it with the peers supporting newer versions
+    #[test]
+    fn legacy_initiate() {
+        let message = HandshakeInitialize::with_versions(
+            range(2, 2),
+            vec![1; HandshakeInitialize::MIN_MESSAGE_SIZE],
+        );
+        let bytes = message.to_bytes();
+        assert_eq!(bytes[0], Version::V2.to_u8());
+        assert_eq!(
+            u16::from_be_bytes([bytes[1], bytes[2]]) as usize,
+            HandshakeInitialize::MIN_MESSAGE_SIZE
+        );
+        assert!(prologue(&message.versions()).is_empty());
+
+        let decoded = HandshakeInitialize::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, message);
+        assert_eq!(decoded.negotiate().unwrap(), Version::V2);
+    }
+
+    /// a man in the middle rewriting the version of the header to
+    /// downgrade the session is detected
+    #[test]
+    fn tampered_version() {
+        let v1_v3 = range(1, 3);
+
+        assert!(check_negotiated(&v1_v3, Version::V2, &[1, 3]).is_err());
+        assert!(check_negotiated(&v1_v3, Version::V1, &[1, 3]).is_err());
+        assert!(check_negotiated(&v1_v3, Version::V3, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn invalid_version_range() {
+        let message = HandshakeInitialize::with_versions(
+            VersionRange::SUPPORTED,
+            vec![1; HandshakeInitialize::MIN_MESSAGE_SIZE],
+        );
+        let decoded = HandshakeInitialize::from_bytes(&message.to_bytes()).unwrap();
+        assert_eq!(decoded.versions(), VersionRange::SUPPORTED);
+
+        let mut bytes = message.to_bytes();
+        bytes[1] = Version::MAX.to_u8() + 1;
+        assert!(HandshakeInitialize::from_bytes(&bytes).is_err());
     }
 }
@@ -6,6 +6,7 @@ to be encrypted.
 */
 
 pub(crate) mod encryption;
+pub(crate) mod frame;
 pub(crate) mod handshake;
 
 pub use self::encryption::{NoiseEncryptedDecoder, NoiseEncryptedEncoder};
@@ -0,0 +1,140 @@
+use crate::{Extensions, FramedMessage, VersionRange};
+use anyhow::{ensure, Context as _, Result};
+use keynesis_core::{
+    hash::Blake2b,
+    noise::{RekeyPolicy, TransportState},
+};
+use std::{future::Future, time::Duration};
+use tokio::time::Instant;
+
+/// limits of a connection, so a stalled or malicious peer cannot hold
+/// the connecting (or accepting) task forever
+///
+/// the configuration is given to [`Handle::open_with_config`] (or the
+/// other `*_with_config` opening functions, one per handshake pattern)
+/// or to [`Accepting::with_config`]. The timeouts require the tokio
+/// runtime's timer, there is none by default.
+///
+/// [`Handle::open_with_config`]: crate::Handle::open_with_config
+/// [`Accepting::with_config`]: crate::Accepting::with_config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectConfig {
+    /// maximum time to complete the handshake
+    pub handshake_timeout: Option<Duration>,
+    /// maximum time without receiving anything once the session is
+    /// established, see [`Handle::with_idle_timeout`]
+    ///
+    /// [`Handle::with_idle_timeout`]: crate::Handle::with_idle_timeout
+    pub read_timeout: Option<Duration>,
+    /// maximum time the underlying stream can take to accept the data
+    /// written once the session is established
+    pub write_timeout: Option<Duration>,
+    /// maximum size of the messages sent and received with
+    /// [`Handle::send_frame`] and [`Handle::recv_frame`], it cannot be
+    /// larger than [`FramedMessage::MAX_MESSAGE_SIZE`]
+    ///
+    /// [`Handle::send_frame`]: crate::Handle::send_frame
+    /// [`Handle::recv_frame`]: crate::Handle::recv_frame
+    pub max_message_size: usize,
+    /// the versions of the protocol advertised when opening the
+    /// connection, the responder picks the latest one it supports
+    ///
+    /// [`VersionRange::SUPPORTED`] by default, connecting to a
+    /// [`Version::V2`](crate::Version::V2) peer requires advertising
+    /// that version alone.
+    pub versions: VersionRange,
+    /// the [`RekeyPolicy`] of the established connection, sent to the
+    /// remote peer in the [`Extensions::REKEY_POLICY`] extension
+    ///
+    /// both peers need to use the same policy, the handshake fails with
+    /// a [`RekeyPolicyError`] otherwise. Only the handshakes exchanging
+    /// [`Extensions`] can agree on it. `None` by default: no extension
+    /// is sent and the keys are rotated after every message.
+    ///
+    /// [`RekeyPolicyError`]: keynesis_core::noise::RekeyPolicyError
+    pub rekey_policy: Option<RekeyPolicy>,
+}
+
+impl ConnectConfig {
+    /// the time by which the handshake starting now must be completed
+    pub(crate) fn handshake_deadline(&self) -> Option<Instant> {
+        self.handshake_timeout
+            .map(|handshake_timeout| Instant::now() + handshake_timeout)
+    }
+
+    /// the `extensions` to send during the handshake, with our rekey
+    /// policy if any
+    pub(crate) fn extensions(&self, extensions: &Extensions) -> Result<Extensions> {
+        let mut extensions = extensions.clone();
+        if let Some(policy) = self.rekey_policy {
+            extensions.insert(Extensions::REKEY_POLICY, policy.to_bytes().to_vec())?;
+        }
+        Ok(extensions)
+    }
+
+    /// set our rekey policy (if any) to the `state` once checked the
+    /// remote peer sent the same one in its `extensions`
+    pub(crate) fn agree_rekey_policy(
+        &self,
+        state: &mut TransportState<Blake2b>,
+        extensions: &Extensions,
+    ) -> Result<()> {
+        if let Some(policy) = self.rekey_policy {
+            let remote = extensions.get(Extensions::REKEY_POLICY).unwrap_or_default();
+            state
+                .agree_rekey_policy(policy, remote)
+                .context("Cannot agree on the rekey policy with the remote peer")?;
+        }
+        Ok(())
+    }
+
+    /// fails if the handshake `pattern`, which does not exchange any
+    /// [`Extensions`], would have to agree on a rekey policy
+    pub(crate) fn check_no_extensions(&self, pattern: &str) -> Result<()> {
+        ensure!(
+            self.rekey_policy.is_none(),
+            "The Noise {} handshake cannot agree on the rekey policy",
+            pattern
+        );
+        Ok(())
+    }
+}
+
+impl Default for ConnectConfig {
+    fn default() -> Self {
+        Self {
+            handshake_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            max_message_size: FramedMessage::MAX_MESSAGE_SIZE,
+            versions: VersionRange::SUPPORTED,
+            rekey_policy: None,
+        }
+    }
+}
+
+/// run the `future`, failing if it does not complete within the `timeout`
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        None => future.await,
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .with_context(|| format!("Timed out after {:?}", timeout))?,
+    }
+}
+
+/// run the `future`, failing if it does not complete by the `deadline`
+pub(crate) async fn with_deadline<T>(
+    deadline: Option<Instant>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match deadline {
+        None => future.await,
+        Some(deadline) => tokio::time::timeout_at(deadline, future)
+            .await
+            .context("Handshake timed out")?,
+    }
+}
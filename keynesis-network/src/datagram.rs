@@ -0,0 +1,620 @@
+/*!
+Secure datagram channel on top of UDP
+
+The peers perform a [Noise **IK**] handshake over UDP: the initiator
+retransmits its first message until the responder replies (see
+[`Retransmission`]) and the responder replies again to the retransmitted
+messages until it receives the first message of the session. The
+replies that cannot be authenticated (forged or corrupted datagrams)
+are ignored by the initiator.
+
+The first message carries the time it was created: the responder
+ignores the messages older than [`MAX_HANDSHAKE_AGE`] so a replayed
+message cannot connect it to another address. A message can still be
+replayed within [`MAX_HANDSHAKE_AGE`] by a peer in the middle seeing it,
+the session cannot be used without the initiator's keys but the
+responder is connected to the address of the first one it receives.
+
+Then the messages are sent with the datagram transport of the session
+(see [`TransportState::into_datagram`]): every datagram carries its
+nonce so it can be lost, duplicated or reordered by the network and
+the replay window of the receiver accepts every message only once. The
+keys are not rotated after every message, the channel needs to be
+renewed with a new handshake before the nonces run out.
+
+A [`DatagramChannel`] is bound to one remote peer, the [`UdpSocket`] is
+connected to its address.
+
+[Noise **IK**]: https://noiseexplorer.com/patterns/IK/
+*/
+
+use crate::SessionId;
+use anyhow::{bail, Context as _, Result};
+use keynesis_core::{
+    hash::Blake2b,
+    key::{ed25519::PublicKey, Dh},
+    noise::{CipherStateError, DatagramReceiveHalf, DatagramSendHalf, TransportState, IK},
+    Seed,
+};
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    convert::TryInto as _,
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{net::UdpSocket, time::Instant};
+
+const HANDSHAKE_INITIALIZE: u8 = 0;
+const HANDSHAKE_RESPONSE: u8 = 1;
+const DATA: u8 = 2;
+
+/// the handshakes of the datagram channels are not interchangeable with
+/// the handshakes of the stream connections
+const PROLOGUE: &[u8] = b"keynesis:datagram";
+
+/// largest datagram that can be received
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+/// the responder ignores the first messages of the handshake created
+/// longer than this ago (or this far in the future, the clocks of the
+/// peers are not exactly in sync)
+///
+/// the initiator retransmits the same message so the [`Retransmission`]
+/// needs to be shorter.
+pub const MAX_HANDSHAKE_AGE: Duration = Duration::from_secs(30);
+
+/// size of the payload of the first message: the time it was created
+/// in milliseconds since the UNIX epoch (8 bytes, big endian)
+const TIMESTAMP_SIZE: usize = std::mem::size_of::<u64>();
+
+/// how the initiator retransmits the first message of the handshake
+#[derive(Debug, Clone, Copy)]
+pub struct Retransmission {
+    /// time to wait for the response before retransmitting
+    pub interval: Duration,
+    /// number of times the message is sent before giving up
+    pub attempts: u32,
+}
+
+/// encrypted and authenticated datagram channel with a remote peer
+pub struct DatagramChannel {
+    socket: UdpSocket,
+    peer_addr: SocketAddr,
+    session_id: SessionId,
+    send: DatagramSendHalf<Blake2b>,
+    receive: DatagramReceiveHalf<Blake2b>,
+    /// the responder keeps the initial message and its response until
+    /// the first message of the session is received, to reply to the
+    /// retransmissions of the initiator
+    handshake: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Default for Retransmission {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(500),
+            attempts: 10,
+        }
+    }
+}
+
+impl DatagramChannel {
+    /// perform the handshake with the peer at `peer_addr`, expecting it
+    /// to identify with the [`PublicKey`] `rs`
+    ///
+    /// the `socket` is connected to the `peer_addr`. The replies that
+    /// cannot be authenticated are ignored, the function keeps waiting
+    /// for a valid one until the `retransmission` attempts are exhausted.
+    #[tracing::instrument(skip(rng, k, socket), level = "debug")]
+    pub async fn connect<RNG, K>(
+        mut rng: RNG,
+        k: &K,
+        socket: UdpSocket,
+        peer_addr: SocketAddr,
+        rs: PublicKey,
+        retransmission: Retransmission,
+    ) -> Result<Self>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh + Clone,
+    {
+        socket
+            .connect(peer_addr)
+            .await
+            .with_context(|| format!("Cannot connect the socket to {}", peer_addr))?;
+
+        // the state is cloned to try every reply, the rng needs to be
+        // cloned with it
+        let rng = Seed::generate(&mut rng).into_rand_chacha();
+        let mut message = vec![HANDSHAKE_INITIALIZE];
+        let state = IK::<K, Blake2b, _, _>::new(rng, &None, PROLOGUE)
+            .initiate_with_payload(k, rs, timestamp(SystemTime::now()), &mut message)
+            .context("Cannot initiate Noise IK handshake")?;
+
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        for attempt in 0..retransmission.attempts {
+            socket
+                .send(&message)
+                .await
+                .context("Cannot send the Noise IK initial Handshake")?;
+
+            let deadline = Instant::now() + retransmission.interval;
+            while let Ok(len) = tokio::time::timeout_at(deadline, socket.recv(&mut buffer)).await {
+                let len = match len {
+                    Ok(len) => len,
+                    Err(error) => {
+                        // the responder may not be listening yet (ICMP port
+                        // unreachable), wait for the next attempt
+                        tracing::debug!(reason = %error, "cannot receive the response");
+                        tokio::time::sleep_until(deadline).await;
+                        break;
+                    }
+                };
+                let response = match buffer[..len].split_first() {
+                    Some((&HANDSHAKE_RESPONSE, response)) => response,
+                    _ => continue,
+                };
+
+                match state.clone().receive(k, response) {
+                    Ok(state) => return Ok(Self::new(socket, peer_addr, state, None)),
+                    Err(error) => {
+                        tracing::debug!(reason = %error, "ignoring the invalid response");
+                    }
+                }
+            }
+
+            tracing::debug!(attempt, "no response, retransmitting the handshake");
+        }
+
+        bail!(
+            "No response from {} after {} attempts",
+            peer_addr,
+            retransmission.attempts
+        )
+    }
+
+    /// wait for the handshake of a remote peer on the `socket`
+    ///
+    /// `check_id` verifies the public key of the initiator. The invalid,
+    /// rejected or stale (see [`MAX_HANDSHAKE_AGE`]) handshakes are
+    /// ignored and the function keeps waiting, the `socket` is connected
+    /// to the first accepted peer.
+    #[tracing::instrument(skip(rng, k, socket, check_id), level = "debug")]
+    pub async fn accept<RNG, K, F>(
+        mut rng: RNG,
+        k: &K,
+        socket: UdpSocket,
+        check_id: F,
+    ) -> Result<Self>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+        F: Fn(&PublicKey) -> bool,
+    {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, peer_addr) = socket
+                .recv_from(&mut buffer)
+                .await
+                .context("Cannot receive the Noise IK initial Handshake")?;
+            let initial = &buffer[..len];
+            if initial.first() != Some(&HANDSHAKE_INITIALIZE) {
+                continue;
+            }
+
+            let mut payload = Vec::with_capacity(TIMESTAMP_SIZE);
+            let state = match IK::<K, Blake2b, _, _>::new(&mut rng, &None, PROLOGUE)
+                .receive_with_policy(k, &initial[1..], &mut payload, &check_id)
+            {
+                Ok(state) => state,
+                Err(error) => {
+                    tracing::debug!(%peer_addr, reason = %error, "ignoring the handshake");
+                    continue;
+                }
+            };
+            if !is_fresh(&payload, SystemTime::now()) {
+                tracing::debug!(%peer_addr, "ignoring the stale or replayed handshake");
+                continue;
+            }
+
+            let mut reply = vec![HANDSHAKE_RESPONSE];
+            let state = state
+                .reply(&mut reply)
+                .context("Cannot reply to the Noise IK Handshake")?;
+
+            socket
+                .connect(peer_addr)
+                .await
+                .with_context(|| format!("Cannot connect the socket to {}", peer_addr))?;
+            socket
+                .send(&reply)
+                .await
+                .context("Cannot send the Noise IK response Handshake")?;
+
+            let handshake = Some((initial.to_vec(), reply));
+            return Ok(Self::new(socket, peer_addr, state, handshake));
+        }
+    }
+
+    fn new(
+        socket: UdpSocket,
+        peer_addr: SocketAddr,
+        state: TransportState<Blake2b>,
+        handshake: Option<(Vec<u8>, Vec<u8>)>,
+    ) -> Self {
+        let session_id = SessionId::new(*state.noise_session());
+        let (send, receive) = state.into_datagram();
+
+        Self {
+            socket,
+            peer_addr,
+            session_id,
+            send,
+            receive,
+            handshake,
+        }
+    }
+
+    /// retrieve the public identity of the peer
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.receive.remote_public_identity()
+    }
+
+    /// the address of the remote peer
+    pub fn remote_address(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// retrieve the unique identifier of the established session
+    pub fn session_id(&self) -> &SessionId {
+        &self.session_id
+    }
+
+    /// number of messages that can still be sent on this channel
+    pub fn remaining_sends(&self) -> u64 {
+        self.send.remaining_sends()
+    }
+
+    /// encrypt the `message` and send it in one datagram
+    ///
+    /// the message may be lost, nothing is retransmitted.
+    pub async fn send(&mut self, message: impl AsRef<[u8]>) -> Result<()> {
+        let mut datagram = vec![DATA];
+        self.send
+            .send(message, &mut datagram)
+            .context("Cannot encrypt the message")?;
+
+        self.socket
+            .send(&datagram)
+            .await
+            .context("Cannot send the datagram")?;
+        Ok(())
+    }
+
+    /// receive the next message of the remote peer
+    ///
+    /// the datagrams that cannot be authenticated or that have already
+    /// been received are dropped.
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let len = self
+                .socket
+                .recv(&mut buffer)
+                .await
+                .context("Cannot receive the datagram")?;
+            let datagram = &buffer[..len];
+
+            match datagram.split_first() {
+                Some((&DATA, datagram)) => {
+                    let mut message = Vec::with_capacity(datagram.len());
+                    match self.receive.receive(datagram, &mut message) {
+                        Ok(()) => {
+                            // the initiator has the session, it won't
+                            // retransmit its handshake anymore
+                            self.handshake = None;
+                            return Ok(message);
+                        }
+                        Err(CipherStateError::NonceExhausted) => {
+                            bail!("The session is exhausted, a new handshake is needed")
+                        }
+                        Err(error) => {
+                            tracing::debug!(reason = %error, "dropping invalid datagram");
+                        }
+                    }
+                }
+                Some((&HANDSHAKE_INITIALIZE, _)) => {
+                    if let Some((initial, reply)) = &self.handshake {
+                        if initial == datagram {
+                            self.socket
+                                .send(reply)
+                                .await
+                                .context("Cannot send the Noise IK response Handshake")?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// the payload of the first message of the handshake
+fn timestamp(now: SystemTime) -> [u8; TIMESTAMP_SIZE] {
+    let millis = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    millis.to_be_bytes()
+}
+
+/// check the first message of the handshake was created within
+/// [`MAX_HANDSHAKE_AGE`] of `now`
+fn is_fresh(payload: &[u8], now: SystemTime) -> bool {
+    let millis = match payload.try_into() {
+        Ok(millis) => u64::from_be_bytes(millis),
+        Err(_) => return false,
+    };
+    let created = UNIX_EPOCH + Duration::from_millis(millis);
+
+    match now.duration_since(created) {
+        Ok(age) => age <= MAX_HANDSHAKE_AGE,
+        Err(error) => error.duration() <= MAX_HANDSHAKE_AGE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    async fn channels(alice: &SecretKey, bob: &SecretKey) -> (DatagramChannel, DatagramChannel) {
+        let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b_addr = b.local_addr().unwrap();
+
+        let (a, b) = futures::join!(
+            DatagramChannel::connect(
+                thread_rng(),
+                alice,
+                a,
+                b_addr,
+                bob.public_key(),
+                Retransmission::default()
+            ),
+            DatagramChannel::accept(thread_rng(), bob, b, |_| true),
+        );
+        (a.unwrap(), b.unwrap())
+    }
+
+    fn initial(alice: &SecretKey, bob: &SecretKey, now: SystemTime) -> Vec<u8> {
+        let mut initial = vec![HANDSHAKE_INITIALIZE];
+        IK::<SecretKey, Blake2b, _, _>::new(thread_rng(), &None, PROLOGUE)
+            .initiate_with_payload(alice, bob.public_key(), timestamp(now), &mut initial)
+            .unwrap();
+        initial
+    }
+
+    #[test]
+    fn send_recv() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+
+        runtime().block_on(async {
+            let (mut a, mut b) = channels(&alice, &bob).await;
+            assert_eq!(a.session_id(), b.session_id());
+            assert_eq!(a.remote_public_identity(), Some(&bob.public_key()));
+            assert_eq!(b.remote_public_identity(), Some(&alice.public_key()));
+
+            a.send(b"hello").await.unwrap();
+            assert_eq!(b.recv().await.unwrap(), b"hello");
+            b.send(b"world").await.unwrap();
+            assert_eq!(a.recv().await.unwrap(), b"world");
+        });
+    }
+
+    #[test]
+    fn retransmitted_handshake() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+
+        runtime().block_on(async {
+            let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let a_addr = a.local_addr().unwrap();
+            let b_addr = b.local_addr().unwrap();
+
+            // the initiator sends its first message but loses the response
+            let initial = initial(&alice, &bob, SystemTime::now());
+            a.send_to(&initial, b_addr).await.unwrap();
+            let mut b = DatagramChannel::accept(thread_rng(), &bob, b, |_| true)
+                .await
+                .unwrap();
+            let mut response = vec![0; MAX_DATAGRAM_SIZE];
+            let len = a.recv(&mut response).await.unwrap();
+            response.truncate(len);
+
+            // the retransmission gets the same response
+            a.send_to(&initial, b_addr).await.unwrap();
+            let (retransmitted, _) = futures::join!(
+                async {
+                    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+                    let len = a.recv(&mut buffer).await.unwrap();
+                    buffer.truncate(len);
+                    buffer
+                },
+                tokio::time::timeout(Duration::from_millis(100), b.recv()),
+            );
+            assert_eq!(retransmitted, response);
+            assert_eq!(b.remote_address(), a_addr);
+        });
+    }
+
+    #[test]
+    fn forged_response() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let retransmission = Retransmission {
+            interval: Duration::from_millis(100),
+            attempts: 10,
+        };
+
+        runtime().block_on(async {
+            let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let b_addr = b.local_addr().unwrap();
+
+            let (a, b) = futures::join!(
+                DatagramChannel::connect(
+                    thread_rng(),
+                    &alice,
+                    a,
+                    b_addr,
+                    bob.public_key(),
+                    retransmission
+                ),
+                async {
+                    // reply garbage to the first message, the initiator
+                    // keeps waiting and retransmits
+                    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+                    let (_, a_addr) = b.recv_from(&mut buffer).await.unwrap();
+                    let mut forged = vec![HANDSHAKE_RESPONSE];
+                    forged.extend_from_slice(&[0x42; 48]);
+                    b.send_to(&forged, a_addr).await.unwrap();
+
+                    DatagramChannel::accept(thread_rng(), &bob, b, |_| true).await
+                },
+            );
+            let (a, b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.session_id(), b.session_id());
+        });
+    }
+
+    #[test]
+    fn responder_not_listening_yet() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let retransmission = Retransmission {
+            interval: Duration::from_millis(50),
+            attempts: 20,
+        };
+
+        runtime().block_on(async {
+            let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let b_addr = UdpSocket::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap();
+
+            // the first messages are refused, nothing is bound to `b_addr`
+            let (a, b) = futures::join!(
+                DatagramChannel::connect(
+                    thread_rng(),
+                    &alice,
+                    a,
+                    b_addr,
+                    bob.public_key(),
+                    retransmission
+                ),
+                async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    let b = UdpSocket::bind(b_addr).await.unwrap();
+                    let accept = DatagramChannel::accept(thread_rng(), &bob, b, |_| true);
+                    tokio::time::timeout(Duration::from_secs(1), accept).await?
+                },
+            );
+            let (a, b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.session_id(), b.session_id());
+        });
+    }
+
+    #[test]
+    fn no_responder() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let retransmission = Retransmission {
+            interval: Duration::from_millis(20),
+            attempts: 3,
+        };
+
+        runtime().block_on(async {
+            let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let b_addr = UdpSocket::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap();
+
+            let start = Instant::now();
+            let error = DatagramChannel::connect(
+                thread_rng(),
+                &alice,
+                a,
+                b_addr,
+                bob.public_key(),
+                retransmission,
+            )
+            .await
+            .err()
+            .unwrap();
+            assert!(error.to_string().starts_with("No response"));
+            assert!(start.elapsed() >= 3 * retransmission.interval);
+        });
+    }
+
+    #[test]
+    fn replayed_handshake() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+
+        runtime().block_on(async {
+            let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let eve = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let a_addr = a.local_addr().unwrap();
+            let b_addr = b.local_addr().unwrap();
+
+            // an old message of alice replayed from another address
+            let old = SystemTime::now() - 2 * MAX_HANDSHAKE_AGE;
+            eve.send_to(&initial(&alice, &bob, old), b_addr)
+                .await
+                .unwrap();
+
+            let (a, b) = futures::join!(
+                DatagramChannel::connect(
+                    thread_rng(),
+                    &alice,
+                    a,
+                    b_addr,
+                    bob.public_key(),
+                    Retransmission::default()
+                ),
+                DatagramChannel::accept(thread_rng(), &bob, b, |_| true),
+            );
+            let (a, b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.session_id(), b.session_id());
+            assert_eq!(b.remote_address(), a_addr);
+        });
+    }
+
+    #[test]
+    fn fresh_timestamp() {
+        let now = SystemTime::now();
+
+        assert!(is_fresh(&timestamp(now), now));
+        assert!(is_fresh(&timestamp(now - MAX_HANDSHAKE_AGE / 2), now));
+        assert!(is_fresh(&timestamp(now + MAX_HANDSHAKE_AGE / 2), now));
+        assert!(!is_fresh(&timestamp(now - 2 * MAX_HANDSHAKE_AGE), now));
+        assert!(!is_fresh(&timestamp(now + 2 * MAX_HANDSHAKE_AGE), now));
+        assert!(!is_fresh(&[], now));
+    }
+}
@@ -0,0 +1,398 @@
+/*!
+Resolution of the peers' public keys from the DNS
+
+A peer publishes its public key in a TXT record under the `_keynesis`
+label of its domain name:
+
+```text
+_keynesis.example.com. 3600 IN TXT "keynesis=<hex encoded public key>"
+```
+
+The [`Resolver`] queries the record and returns the [`PublicKey`] to
+expect from the peer when connecting to it:
+
+```no_run
+# async fn example() -> anyhow::Result<()> {
+use keynesis_core::key::ed25519::SecretKey;
+use keynesis_network::{dns::Resolver, net::Connection};
+use rand::thread_rng;
+# let k = SecretKey::new(thread_rng());
+
+let resolver = Resolver::new("127.0.0.1:53".parse()?).require_dnssec();
+let rs = resolver.resolve(thread_rng(), "example.com").await?;
+
+let connection = Connection::connect(thread_rng(), &k, "example.com:9800", rs).await?;
+# Ok(()) }
+```
+
+## DNSSEC
+
+The [`Resolver`] does not validate the DNSSEC signatures itself: with
+[`require_dnssec`](Resolver::require_dnssec) it asks the DNS server to
+validate the answer and rejects the answers the server did not
+authenticate (the `AD` flag). The server then needs to be a trusted
+validating resolver reached over a trusted path (usually running on the
+same host).
+
+Without DNSSEC, an attacker able to tamper with the DNS answers chooses
+the key the connection will authenticate.
+*/
+
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use keynesis_core::key::ed25519::PublicKey;
+use rand_core::RngCore;
+use std::{net::SocketAddr, str::FromStr as _, time::Duration};
+use tokio::net::UdpSocket;
+
+/// the label the TXT records are published under
+pub const LABEL: &str = "_keynesis";
+
+/// the prefix of the TXT records
+const RECORD_PREFIX: &str = "keynesis=";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+const TYPE_TXT: u16 = 16;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+
+const FLAG_QR: u16 = 0x8000;
+const FLAG_TC: u16 = 0x0200;
+const FLAG_RD: u16 = 0x0100;
+const FLAG_AD: u16 = 0x0020;
+const RCODE_MASK: u16 = 0x000F;
+/// the `DO` bit of the EDNS(0) `OPT` record
+const EDNS_DO: u32 = 0x0000_8000;
+
+/// resolve the peers' public keys with a DNS server
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    server: SocketAddr,
+    require_dnssec: bool,
+    timeout: Duration,
+}
+
+impl Resolver {
+    /// resolve the keys with the given DNS server
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            require_dnssec: false,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// only accept the answers authenticated by the DNS server, see the
+    /// [module documentation](self)
+    pub fn require_dnssec(mut self) -> Self {
+        self.require_dnssec = true;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// resolve the public key published by `domain`, the `rng` is used
+    /// for the id of the query
+    ///
+    /// fails if the domain publishes no key or different keys.
+    #[tracing::instrument(skip(self, rng), fields(server = %self.server), level = "debug")]
+    pub async fn resolve<RNG>(&self, mut rng: RNG, domain: &str) -> Result<PublicKey>
+    where
+        RNG: RngCore,
+    {
+        let name = format!("{}.{}", LABEL, domain.trim_end_matches('.'));
+        let id = rng.next_u32() as u16;
+        let query = query(id, &name, self.require_dnssec)?;
+
+        let bind: SocketAddr = if self.server.is_ipv4() {
+            "0.0.0.0:0".parse()?
+        } else {
+            "[::]:0".parse()?
+        };
+        let socket = UdpSocket::bind(bind)
+            .await
+            .context("Cannot bind the DNS socket")?;
+        socket
+            .connect(self.server)
+            .await
+            .with_context(|| format!("Cannot connect to the DNS server {}", self.server))?;
+        socket
+            .send(&query)
+            .await
+            .context("Cannot send the DNS query")?;
+
+        let mut response = vec![0; MAX_MESSAGE_SIZE];
+        let response = loop {
+            let len = tokio::time::timeout(self.timeout, socket.recv(&mut response))
+                .await
+                .with_context(|| format!("No DNS response for {}", name))?
+                .context("Cannot receive the DNS response")?;
+            // ignore the responses to other queries
+            if len >= 2 && u16::from_be_bytes([response[0], response[1]]) == id {
+                break &response[..len];
+            }
+        };
+
+        let records = parse_response(id, response, self.require_dnssec)
+            .with_context(|| format!("Invalid DNS response for {}", name))?;
+        key_from_records(&records).with_context(|| format!("No valid key for {}", domain))
+    }
+}
+
+/// a TXT query for `name`, with the `DO` bit set if `dnssec`
+fn query(id: u16, name: &str, dnssec: bool) -> Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(512);
+    let flags = if dnssec { FLAG_RD | FLAG_AD } else { FLAG_RD };
+
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&flags.to_be_bytes());
+    query.extend_from_slice(&1u16.to_be_bytes()); // questions
+    query.extend_from_slice(&0u16.to_be_bytes()); // answers
+    query.extend_from_slice(&0u16.to_be_bytes()); // authorities
+    query.extend_from_slice(&(dnssec as u16).to_be_bytes()); // additionals
+
+    for label in name.split('.') {
+        ensure!(
+            !label.is_empty() && label.len() < 64,
+            "Invalid domain name {}",
+            name
+        );
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_TXT.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    if dnssec {
+        // EDNS(0) OPT record: root name, type, payload size, extended
+        // rcode and flags (with DO), no options
+        query.push(0);
+        query.extend_from_slice(&TYPE_OPT.to_be_bytes());
+        query.extend_from_slice(&(MAX_MESSAGE_SIZE as u16).to_be_bytes());
+        query.extend_from_slice(&EDNS_DO.to_be_bytes());
+        query.extend_from_slice(&0u16.to_be_bytes());
+    }
+
+    Ok(query)
+}
+
+/// the TXT records of the answer of the response, each record being the
+/// concatenation of its strings
+fn parse_response(id: u16, response: &[u8], require_dnssec: bool) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader {
+        bytes: response,
+        position: 0,
+    };
+
+    ensure!(reader.u16()? == id, "Unexpected response id");
+    let flags = reader.u16()?;
+    ensure!(flags & FLAG_QR != 0, "Not a response");
+    ensure!(flags & FLAG_TC == 0, "Truncated response");
+    ensure!(
+        flags & RCODE_MASK == 0,
+        "DNS error (rcode {})",
+        flags & RCODE_MASK
+    );
+    ensure!(
+        !require_dnssec || flags & FLAG_AD != 0,
+        "The response is not authenticated with DNSSEC"
+    );
+
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    reader.u16()?; // authorities
+    reader.u16()?; // additionals
+
+    for _ in 0..questions {
+        reader.skip_name()?;
+        reader.take(4)?; // type and class
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        reader.skip_name()?;
+        let rtype = reader.u16()?;
+        let class = reader.u16()?;
+        reader.take(4)?; // ttl
+        let len = reader.u16()? as usize;
+        let mut rdata = reader.take(len)?;
+
+        // the answers may contain the CNAMEs and the signatures
+        if rtype != TYPE_TXT || class != CLASS_IN {
+            continue;
+        }
+        let mut record = Vec::with_capacity(len);
+        while let Some((&len, rest)) = rdata.split_first() {
+            ensure!(rest.len() >= len as usize, "Invalid TXT record");
+            let (string, rest) = rest.split_at(len as usize);
+            record.extend_from_slice(string);
+            rdata = rest;
+        }
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// the key of the `keynesis=` records, the other records are ignored
+fn key_from_records(records: &[Vec<u8>]) -> Result<PublicKey> {
+    let mut key = None;
+
+    for record in records {
+        let record = match std::str::from_utf8(record) {
+            Ok(record) => record.trim(),
+            Err(_) => continue,
+        };
+        let hex = match record.strip_prefix(RECORD_PREFIX) {
+            Some(hex) => hex,
+            None => continue,
+        };
+        let record_key = PublicKey::from_str(hex)
+            .map_err(|error| anyhow!("Invalid public key in TXT record: {}", error))?;
+
+        match key {
+            Some(key) if key != record_key => bail!("The domain publishes different keys"),
+            _ => key = Some(record_key),
+        }
+    }
+
+    key.ok_or_else(|| anyhow!("No {} record", RECORD_PREFIX))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("Unexpected end of the DNS message"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// skip a name, a sequence of labels ending with an empty label or
+    /// with a compression pointer
+    fn skip_name(&mut self) -> Result<()> {
+        loop {
+            let len = self.take(1)?[0];
+            match len {
+                0 => return Ok(()),
+                len if len & 0xC0 == 0xC0 => {
+                    self.take(1)?;
+                    return Ok(());
+                }
+                len if len & 0xC0 == 0 => {
+                    self.take(len as usize)?;
+                }
+                _ => bail!("Invalid label in the DNS message"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+
+    /// a response to the `query` with the given TXT records
+    fn response(query: &[u8], flags: u16, records: &[&[u8]]) -> Vec<u8> {
+        let mut response = query[..2].to_vec();
+        response.extend_from_slice(&(FLAG_QR | flags).to_be_bytes());
+        response.extend_from_slice(&1u16.to_be_bytes());
+        response.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        response.extend_from_slice(&[0, 0, 0, 0]);
+        // the question, without the OPT record
+        let question_end = 12 + query[12..].iter().position(|b| *b == 0).unwrap() + 1 + 4;
+        response.extend_from_slice(&query[12..question_end]);
+
+        for record in records {
+            response.extend_from_slice(&[0xC0, 12]); // pointer to the question
+            response.extend_from_slice(&TYPE_TXT.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&3600u32.to_be_bytes());
+            // split the record in strings of 64 bytes
+            let strings = record.chunks(64).collect::<Vec<_>>();
+            let len = record.len() + strings.len();
+            response.extend_from_slice(&(len as u16).to_be_bytes());
+            for string in strings {
+                response.push(string.len() as u8);
+                response.extend_from_slice(string);
+            }
+        }
+        response
+    }
+
+    #[test]
+    fn records() {
+        let key = SecretKey::new(thread_rng()).public_key();
+        let record = format!("{}{}", RECORD_PREFIX, key);
+        let query = query(42, "_keynesis.example.com", true).unwrap();
+
+        let records = parse_response(
+            42,
+            &response(&query, FLAG_AD, &[b"v=spf1 -all", record.as_bytes()]),
+            true,
+        )
+        .unwrap();
+        assert_eq!(key_from_records(&records).unwrap(), key);
+
+        // not authenticated
+        let unauthenticated = response(&query, 0, &[record.as_bytes()]);
+        assert!(parse_response(42, &unauthenticated, true).is_err());
+        assert!(parse_response(42, &unauthenticated, false).is_ok());
+
+        // different keys
+        let other = format!(
+            "{}{}",
+            RECORD_PREFIX,
+            SecretKey::new(thread_rng()).public_key()
+        );
+        let records = [record.into_bytes(), other.into_bytes()];
+        assert!(key_from_records(&records).is_err());
+        assert!(key_from_records(&[]).is_err());
+    }
+
+    #[test]
+    fn resolve() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let key = SecretKey::new(thread_rng()).public_key();
+
+        runtime.block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let resolver = Resolver::new(server.local_addr().unwrap()).require_dnssec();
+
+            let serve = async {
+                let mut query = [0; 512];
+                let (len, peer) = server.recv_from(&mut query).await.unwrap();
+                let record = format!("{}{}", RECORD_PREFIX, key);
+                let response = response(&query[..len], FLAG_AD, &[record.as_bytes()]);
+                server.send_to(&response, peer).await.unwrap();
+            };
+
+            let (resolved, ()) =
+                futures::join!(resolver.resolve(thread_rng(), "example.com."), serve);
+            assert_eq!(resolved.unwrap(), key);
+        });
+    }
+}
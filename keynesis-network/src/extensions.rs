@@ -0,0 +1,176 @@
+use anyhow::{bail, ensure, Result};
+use std::collections::BTreeMap;
+
+/// identifier of an extension of the handshake
+pub type ExtensionType = u16;
+
+/// extensions exchanged during the handshake
+///
+/// The extensions are encoded as a sequence of TLV entries (the 2 bytes
+/// [`ExtensionType`], the 2 bytes length of the value and the value) in
+/// the payload of the Noise handshake messages so they are encrypted
+/// and authenticated.
+///
+/// A peer ignores the extensions it does not know about. This is what
+/// allows adding new capabilities to the handshake without having to
+/// change the wire format.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Extensions(BTreeMap<ExtensionType, Vec<u8>>);
+
+impl Extensions {
+    /// maximum size of the encoded extensions
+    pub const MAX_SIZE: usize = 4096;
+
+    /// the [`RekeyPolicy`] of the peer, see [`ConnectConfig::rekey_policy`]
+    ///
+    /// [`RekeyPolicy`]: keynesis_core::noise::RekeyPolicy
+    /// [`ConnectConfig::rekey_policy`]: crate::ConnectConfig::rekey_policy
+    pub const REKEY_POLICY: ExtensionType = 0x0100;
+
+    const HEADER_SIZE: usize = 2 * std::mem::size_of::<u16>();
+
+    /// empty set of extensions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// set the value of the extension, returns the previous value if any
+    ///
+    /// # Errors
+    ///
+    /// fails if the encoded extensions would be larger than [`Extensions::MAX_SIZE`]
+    pub fn insert(
+        &mut self,
+        extension_type: ExtensionType,
+        value: impl Into<Vec<u8>>,
+    ) -> Result<Option<Vec<u8>>> {
+        let value = value.into();
+        let previous = self
+            .0
+            .get(&extension_type)
+            .map(|v| v.len() + Self::HEADER_SIZE);
+        let size = self.encoded_len() - previous.unwrap_or_default() + Self::HEADER_SIZE;
+
+        ensure!(
+            size + value.len() <= Self::MAX_SIZE,
+            "Extensions cannot be larger than {} bytes",
+            Self::MAX_SIZE
+        );
+
+        Ok(self.0.insert(extension_type, value))
+    }
+
+    /// get the value of the given extension
+    pub fn get(&self, extension_type: ExtensionType) -> Option<&[u8]> {
+        self.0.get(&extension_type).map(Vec::as_slice)
+    }
+
+    /// remove the given extension, returns its value if it was set
+    pub fn remove(&mut self, extension_type: ExtensionType) -> Option<Vec<u8>> {
+        self.0.remove(&extension_type)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// iterate through the extensions, ordered by [`ExtensionType`]
+    pub fn iter(&self) -> impl Iterator<Item = (ExtensionType, &[u8])> {
+        self.0.iter().map(|(t, v)| (*t, v.as_slice()))
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.0.values().map(|v| v.len() + Self::HEADER_SIZE).sum()
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+
+        for (extension_type, value) in self.0.iter() {
+            bytes.extend_from_slice(&extension_type.to_be_bytes());
+            bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(value);
+        }
+
+        bytes
+    }
+
+    pub(crate) fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() <= Self::MAX_SIZE,
+            "Extensions cannot be larger than {} bytes",
+            Self::MAX_SIZE
+        );
+
+        let mut extensions = BTreeMap::new();
+        let mut last = None;
+
+        while !bytes.is_empty() {
+            ensure!(bytes.len() >= Self::HEADER_SIZE, "Invalid extension header");
+            let extension_type = u16::from_be_bytes([bytes[0], bytes[1]]);
+            let len = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+            bytes = &bytes[Self::HEADER_SIZE..];
+
+            ensure!(bytes.len() >= len, "Invalid extension length");
+            let (value, remaining) = bytes.split_at(len);
+            bytes = remaining;
+
+            // only one encoding is valid: the extensions ordered by type
+            if last >= Some(extension_type) {
+                bail!("Extension {} is duplicated or out of order", extension_type)
+            }
+            last = Some(extension_type);
+            extensions.insert(extension_type, value.to_vec());
+        }
+
+        Ok(Self(extensions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let mut extensions = Extensions::new();
+        extensions.insert(1, b"one".as_ref()).unwrap();
+        extensions.insert(0xFFFF, Vec::new()).unwrap();
+        extensions.insert(42, vec![0; 1024]).unwrap();
+
+        let decoded = Extensions::from_bytes(&extensions.to_bytes()).unwrap();
+
+        assert_eq!(decoded, extensions);
+        assert_eq!(decoded.get(1), Some(b"one".as_ref()));
+        assert_eq!(decoded.get(2), None);
+    }
+
+    #[test]
+    fn max_size() {
+        let mut extensions = Extensions::new();
+        extensions
+            .insert(1, vec![0; Extensions::MAX_SIZE - Extensions::HEADER_SIZE])
+            .unwrap();
+        assert!(extensions.insert(2, Vec::new()).is_err());
+
+        // replacing the value does not count the previous one
+        extensions.insert(1, Vec::new()).unwrap();
+        extensions.insert(2, Vec::new()).unwrap();
+        assert_eq!(extensions.to_bytes().len(), 2 * Extensions::HEADER_SIZE);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Extensions::from_bytes(&[0, 1, 0]).is_err());
+        assert!(Extensions::from_bytes(&[0, 1, 0, 2, 0]).is_err());
+        assert!(Extensions::from_bytes(&[0, 1, 0, 0, 0, 1, 0, 0]).is_err());
+        assert!(Extensions::from_bytes(&[]).unwrap().is_empty());
+
+        // not ordered by extension type
+        assert!(Extensions::from_bytes(&[0, 2, 0, 0, 0, 1, 0, 0]).is_err());
+    }
+}
@@ -1,33 +1,63 @@
 use crate::{
-    codec::{NoiseEncryptedDecoder, NoiseEncryptedEncoder},
-    opening::Opening,
-    Accepting, SessionId,
+    codec::{
+        encryption::{ContentType, Frame, MAX_PAYLOAD_LENGTH},
+        frame::FramedMessage,
+        handshake::{self, HandshakeInitialize, HandshakeResponse},
+        NoiseEncryptedDecoder, NoiseEncryptedEncoder,
+    },
+    config::with_timeout,
+    opening::{self, Opening},
+    Accepting, ConnectConfig, Extensions, SessionId, Version, VersionRange,
 };
-use anyhow::{Context as _, Result};
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
 use bytes::{Bytes, BytesMut};
 use futures::{prelude::*, stream::FusedStream as _};
 use keynesis_core::{
     hash::Blake2b,
     key::{ed25519::PublicKey, Dh},
-    noise::{TransportReceiveHalf, TransportSendHalf, TransportState},
+    noise::{ik::WaitB, RekeyPolicy, TransportReceiveHalf, TransportSendHalf, TransportState, IK},
+    Seed,
 };
 use rand_core::{CryptoRng, RngCore};
 use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
     pin::Pin,
     task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
 };
-use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::{FramedRead, FramedWrite};
 
+/// default number of messages left on a session under which it is
+/// rotated (see [`Handle::with_rotation`] and [`Handle::rotate_if_needed`])
+pub const DEFAULT_MIN_REMAINING_MESSAGES: u64 = 1 << 32;
+
 /// bidirectional handle of an encrypted connection
 ///
 /// The [`Handle`] is composed of 2 halves that can be split for more convenient
 /// management of the ins and outs of the connections.
 ///
 /// see [`Handle::split`] for more information
+///
+/// The messages are read and written as a [`Stream`] and a [`Sink`] of
+/// frames, or as a byte stream with the [`AsyncRead`] and [`AsyncWrite`]
+/// implementations so the handle (or its halves) can be used with any
+/// tokio based code.
 pub struct Handle<I, O> {
     stream: HandleReadHalf<I>,
     sink: HandleWriteHalf<O>,
+    /// rotates the session without the application, see
+    /// [`Handle::with_rotation`]
+    rotation: Option<Box<dyn Rotate>>,
+    /// the re-handshake started by the rotation, waiting for the
+    /// remote peer's response
+    rotating: Option<Rotating>,
 }
 
 /// the reading half of the encrypted connection
@@ -36,6 +66,21 @@ pub struct Handle<I, O> {
 pub struct HandleReadHalf<I> {
     none: bool,
     stream: FramedRead<I, NoiseEncryptedDecoder>,
+    /// data received while waiting for the re-handshake's messages
+    pending: VecDeque<BytesMut>,
+    /// re-handshake the remote peer requested
+    rehandshake: Option<HandshakeInitialize>,
+    /// extensions the remote peer sent during the handshake
+    extensions: Extensions,
+    /// time the last frame has been received
+    last_received: Instant,
+    idle_timeout: Option<Duration>,
+    idle: Option<Pin<Box<Sleep>>>,
+    /// the remote peer sent its close frame
+    closed: bool,
+    max_message_size: usize,
+    /// the version negotiated during the handshake
+    version: Version,
 }
 
 /// the writing half of the encrypted connection
@@ -43,22 +88,162 @@ pub struct HandleReadHalf<I> {
 /// see [`Handle::split`] for more information
 pub struct HandleWriteHalf<O> {
     sink: FramedWrite<O, NoiseEncryptedEncoder>,
+    /// time the current session has been established
+    established: SystemTime,
+    max_session_age: Option<Duration>,
+    min_remaining_messages: u64,
+    /// time the last frame has been sent
+    last_sent: Instant,
+    keep_alive: Option<Duration>,
+    keep_alive_timer: Option<Pin<Box<Sleep>>>,
+    /// a frame sent without the application (a keep-alive or a
+    /// re-handshake response) is not flushed yet
+    flush: bool,
+    /// the close frame has been sent, nothing more can be sent
+    close_sent: bool,
+    max_message_size: usize,
+    write_timeout: Option<Duration>,
+    /// started when the underlying stream stops accepting the data
+    stalled: Option<Pin<Box<Sleep>>>,
+    /// the policy agreed during the handshake, kept by the re-handshakes
+    rekey_policy: RekeyPolicy,
+}
+
+/// error returned by the reading half of the connection when the remote
+/// peer requested a re-handshake
+///
+/// the application is expected to call [`Handle::accept_rehandshake`],
+/// unless the [`Handle`] rotates the sessions itself (see
+/// [`Handle::with_rotation`]).
+/// The data sent by the remote peer after the request will be encrypted
+/// with the new session's keys so nothing more can be read until then.
+///
+/// ```no_run
+/// # use keynesis_network::{Handle, RehandshakeRequested};
+/// # use keynesis_core::key::ed25519::SecretKey;
+/// # use futures::prelude::*;
+/// # use tokio::io::{AsyncRead, AsyncWrite};
+/// # async fn read<I, O>(handle: &mut Handle<I, O>, k: &SecretKey) -> anyhow::Result<()>
+/// # where I: AsyncRead + Unpin, O: AsyncWrite + Unpin {
+/// if let Some(Err(error)) = handle.next().await {
+///     if error.is::<RehandshakeRequested>() {
+///         handle.accept_rehandshake(rand::thread_rng(), k, |_| true).await?;
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RehandshakeRequested;
+
+/// error returned by the reading half of the connection when nothing
+/// has been received from the remote peer for longer than the idle
+/// timeout (see [`Handle::with_idle_timeout`])
+///
+/// the remote peer is considered dead, the application is expected to
+/// drop the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleTimeout;
+
+/// error returned by the reading half of the connection when the
+/// underlying stream ends without the close frame of the remote peer
+///
+/// unlike the end of the underlying stream, the close frame is
+/// authenticated: without it the connection may have been cut by an
+/// attacker, so the data received so far may not be everything the
+/// remote peer sent (see [`Handle::shutdown`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionTruncated;
+
+/// performs the re-handshakes of the automatic rotation, see
+/// [`Handle::with_rotation`]
+///
+/// the type of the static key does not appear in the type of the
+/// [`Handle`]
+trait Rotate: Send {
+    /// the request of a new session for the session `session_id`
+    fn initiate(&mut self, session_id: &SessionId, version: Version) -> Result<(Rotating, Frame)>;
+
+    /// answer the re-handshake request of the remote peer
+    fn accept(
+        &mut self,
+        session_id: &SessionId,
+        version: Version,
+        message: HandshakeInitialize,
+    ) -> Result<(TransportState<Blake2b>, Frame)>;
+}
+
+/// the re-handshake request waiting for the remote peer's response
+trait Initiated: Send {
+    fn receive(
+        self: Box<Self>,
+        version: Version,
+        response: &[u8],
+    ) -> Result<TransportState<Blake2b>>;
+}
+
+struct Rotating {
+    /// the request sent to the remote peer, see [`Handle::poll_control`]
+    request: BytesMut,
+    state: Box<dyn Initiated>,
+}
+
+struct Rotation<K, RNG> {
+    k: K,
+    rs: PublicKey,
+    rng: RNG,
+}
+
+struct RotationRequest<K, RNG> {
+    k: K,
+    versions: VersionRange,
+    state: IK<K, Blake2b, RNG, WaitB>,
 }
 
 impl<I> HandleReadHalf<I>
 where
     I: AsyncRead,
 {
-    fn new(stream: I, state: TransportReceiveHalf<Blake2b>) -> Self {
+    fn new(stream: I, state: TransportReceiveHalf<Blake2b>, extensions: Extensions) -> Self {
         let stream = FramedRead::new(stream, NoiseEncryptedDecoder::new(state));
         let none = false;
 
-        Self { stream, none }
+        Self {
+            stream,
+            none,
+            pending: VecDeque::new(),
+            rehandshake: None,
+            extensions,
+            last_received: Instant::now(),
+            idle_timeout: None,
+            idle: None,
+            closed: false,
+            max_message_size: FramedMessage::MAX_MESSAGE_SIZE,
+            version: Version::CURRENT,
+        }
+    }
+
+    /// the [`Version`] of the protocol picked by the responder of the
+    /// handshake
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// the [`Extensions`] the remote peer sent during the handshake
+    pub fn remote_extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// returns true if the remote peer requested a re-handshake
+    ///
+    /// see [`RehandshakeRequested`]
+    pub fn rehandshake_requested(&self) -> bool {
+        self.rehandshake.is_some()
     }
 
     /// retrieve the public identity of the peer
     ///
-    pub fn remote_public_identity(&self) -> &PublicKey {
+    /// `None` if the peer connected anonymously (see [`Handle::open_nk`])
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
         self.stream.decoder().remote_public_identity()
     }
 
@@ -69,21 +254,251 @@ where
     pub fn session_id(&self) -> &SessionId {
         self.stream.decoder().session_id()
     }
+
+    /// number of messages that can still be received on this session
+    ///
+    /// the application should schedule opening a new session before
+    /// this reaches 0.
+    pub fn remaining_receives(&self) -> u64 {
+        self.stream.decoder().remaining_receives()
+    }
+
+    /// `true` if the remote peer closed the connection gracefully (see
+    /// [`Handle::shutdown`])
+    pub fn remote_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// maximum size of the messages received with
+    /// [`recv_frame`](Self::recv_frame)
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size.min(FramedMessage::MAX_MESSAGE_SIZE);
+    }
+
+    /// time the last frame (including the keep-alives) has been received
+    pub fn last_received(&self) -> Instant {
+        self.last_received
+    }
+
+    /// the maximum time without receiving anything from the remote peer,
+    /// see [`IdleTimeout`]
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// `false` if nothing has been received from the remote peer for
+    /// longer than the idle timeout
+    ///
+    /// always `true` if there is no idle timeout.
+    pub fn is_alive(&self) -> bool {
+        match self.idle_timeout {
+            Some(idle_timeout) => self.last_received.elapsed() < idle_timeout,
+            None => true,
+        }
+    }
+
+    /// wait for the idle timeout to expire
+    ///
+    /// this requires the tokio runtime's timer
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let idle_timeout = if let Some(idle_timeout) = self.idle_timeout {
+            idle_timeout
+        } else {
+            return Poll::Pending;
+        };
+
+        let deadline = tokio::time::Instant::from_std(self.last_received + idle_timeout);
+        let idle = self
+            .idle
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+        if idle.deadline() != deadline {
+            idle.as_mut().reset(deadline);
+        }
+
+        idle.as_mut().poll(cx)
+    }
+}
+
+impl<I> HandleReadHalf<I>
+where
+    I: AsyncRead + Unpin,
+{
+    /// wait for the next control frame, the data frames received in the
+    /// meantime are kept so they can be read later
+    async fn next_control(&mut self) -> Result<Frame> {
+        while let Some(frame) = self.stream.next().await {
+            let frame = frame.context("Invalid frame received from peer")?;
+            self.last_received = Instant::now();
+
+            match frame.content_type {
+                ContentType::Data => self.pending.push_back(frame.payload),
+                ContentType::KeepAlive => (),
+                _ => return Ok(frame),
+            }
+        }
+
+        bail!("Connection closed during the re-handshake")
+    }
+
+    /// wait for the close frame of the remote peer, the data frames
+    /// received in the meantime are kept so they can be read later
+    pub(crate) async fn wait_close(&mut self) -> Result<()> {
+        while !self.closed && !self.none {
+            let frame = match self.stream.next().await {
+                Some(frame) => frame.context("Invalid frame received from peer")?,
+                None => {
+                    self.none = true;
+                    break;
+                }
+            };
+            self.last_received = Instant::now();
+
+            match frame.content_type {
+                ContentType::Data => self.pending.push_back(frame.payload),
+                ContentType::KeepAlive => (),
+                ContentType::Close => {
+                    self.closed = true;
+                    self.none = true;
+                }
+                ContentType::RehandshakeInitialize | ContentType::RehandshakeResponse => {
+                    bail!("Unexpected re-handshake from peer while closing the connection")
+                }
+            }
+        }
+
+        if self.closed {
+            Ok(())
+        } else {
+            Err(anyhow!(ConnectionTruncated))
+        }
+    }
+
+    /// receive the next length prefixed message (see [`FramedMessage`])
+    ///
+    /// returns `None` if the connection is closed before a new message
+    pub async fn recv_frame(&mut self) -> Result<Option<Bytes>> {
+        let max_message_size = self.max_message_size;
+        let message = FramedMessage::read_limited(self, max_message_size).await?;
+        Ok(message.map(FramedMessage::into_message))
+    }
+
+    /// take the re-handshake request of the remote peer, waiting for it
+    /// if it has not been received yet
+    async fn rehandshake_request(&mut self) -> Result<HandshakeInitialize> {
+        if let Some(message) = self.rehandshake.take() {
+            return Ok(message);
+        }
+
+        let frame = self.next_control().await?;
+        match frame.content_type {
+            ContentType::RehandshakeInitialize => decode_initialize(&frame.payload),
+            _ => bail!("Expecting a re-handshake request from the remote peer"),
+        }
+    }
+
+    /// poll the next frame of the remote peer, the keep-alives are
+    /// skipped
+    fn poll_frame(&mut self, cx: &mut Context<'_>) -> Poll<Result<Frame>> {
+        loop {
+            let frame = match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Pending => {
+                    futures::ready!(self.poll_idle(cx));
+                    return Poll::Ready(Err(anyhow!(IdleTimeout)));
+                }
+                Poll::Ready(None) => {
+                    self.none = true;
+                    return Poll::Ready(Err(anyhow!(ConnectionTruncated)));
+                }
+                Poll::Ready(Some(Err(error))) => {
+                    return Poll::Ready(Err(error).context("Invalid frame received from peer"))
+                }
+                Poll::Ready(Some(Ok(frame))) => frame,
+            };
+            self.last_received = Instant::now();
+
+            match frame.content_type {
+                ContentType::KeepAlive => continue,
+                ContentType::Close => {
+                    self.closed = true;
+                    self.none = true;
+                }
+                _ => (),
+            }
+
+            return Poll::Ready(Ok(frame));
+        }
+    }
 }
 
 impl<O> HandleWriteHalf<O>
 where
     O: AsyncWrite,
 {
-    fn new(stream: O, state: TransportSendHalf<Blake2b>) -> Self {
+    fn new(stream: O, state: TransportSendHalf<Blake2b>, rekey_policy: RekeyPolicy) -> Self {
         let sink = FramedWrite::new(stream, NoiseEncryptedEncoder::new(state));
 
-        Self { sink }
+        Self {
+            sink,
+            established: SystemTime::now(),
+            max_session_age: None,
+            min_remaining_messages: DEFAULT_MIN_REMAINING_MESSAGES,
+            last_sent: Instant::now(),
+            keep_alive: None,
+            keep_alive_timer: None,
+            flush: false,
+            close_sent: false,
+            max_message_size: FramedMessage::MAX_MESSAGE_SIZE,
+            write_timeout: None,
+            stalled: None,
+            rekey_policy,
+        }
+    }
+
+    /// time the current session has been established, by the initial
+    /// handshake or by the last re-handshake
+    pub fn session_established(&self) -> SystemTime {
+        self.established
+    }
+
+    /// the maximum age of a session, see [`Handle::rotate_if_needed`]
+    pub fn max_session_age(&self) -> Option<Duration> {
+        self.max_session_age
+    }
+
+    pub fn set_max_session_age(&mut self, max_session_age: Option<Duration>) {
+        self.max_session_age = max_session_age;
+    }
+
+    /// the number of messages left under which the session is rotated,
+    /// see [`Handle::rotate_if_needed`]
+    pub fn min_remaining_messages(&self) -> u64 {
+        self.min_remaining_messages
+    }
+
+    pub fn set_min_remaining_messages(&mut self, min_remaining_messages: u64) {
+        self.min_remaining_messages = min_remaining_messages;
+    }
+
+    /// `true` if the session is older than the maximum session age
+    pub fn session_expired(&self, now: SystemTime) -> bool {
+        match (self.max_session_age, now.duration_since(self.established)) {
+            (Some(max_session_age), Ok(age)) => age >= max_session_age,
+            _ => false,
+        }
     }
 
     /// retrieve the public identity of the peer
     ///
-    pub fn remote_public_identity(&self) -> &PublicKey {
+    /// `None` if the peer connected anonymously (see [`Handle::open_nk`])
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
         self.sink.encoder().remote_public_identity()
     }
 
@@ -94,6 +509,228 @@ where
     pub fn session_id(&self) -> &SessionId {
         self.sink.encoder().session_id()
     }
+
+    /// number of messages that can still be sent on this session
+    ///
+    /// the application should schedule opening a new session before
+    /// this reaches 0.
+    pub fn remaining_sends(&self) -> u64 {
+        self.sink.encoder().remaining_sends()
+    }
+
+    /// maximum size of the messages sent with
+    /// [`send_frame`](Self::send_frame)
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size.min(FramedMessage::MAX_MESSAGE_SIZE);
+    }
+
+    /// maximum time the underlying stream can take to accept the data
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    pub fn set_write_timeout(&mut self, write_timeout: Option<Duration>) {
+        self.write_timeout = write_timeout;
+    }
+
+    /// time the last frame has been sent
+    pub fn last_sent(&self) -> Instant {
+        self.last_sent
+    }
+
+    /// the time without sending anything after which a keep-alive is
+    /// sent, see [`HandleWriteHalf::keep_alive`]
+    pub fn keep_alive_interval(&self) -> Option<Duration> {
+        self.keep_alive
+    }
+
+    pub fn set_keep_alive_interval(&mut self, keep_alive: Option<Duration>) {
+        self.keep_alive = keep_alive;
+    }
+
+    /// `true` if nothing has been sent for longer than the keep-alive
+    /// interval
+    pub fn keep_alive_needed(&self) -> bool {
+        match self.keep_alive {
+            Some(keep_alive) => self.last_sent.elapsed() >= keep_alive,
+            None => false,
+        }
+    }
+}
+
+impl<O> HandleWriteHalf<O>
+where
+    O: AsyncWrite + Unpin,
+{
+    /// send a length prefixed message (see [`FramedMessage`])
+    ///
+    /// unlike the messages of the [`Sink`] the message can be larger than
+    /// [`MAX_PAYLOAD_LENGTH`] (up to [`FramedMessage::MAX_MESSAGE_SIZE`]),
+    /// the remote peer receives it with [`HandleReadHalf::recv_frame`].
+    pub async fn send_frame(&mut self, message: impl Into<Bytes>) -> Result<()> {
+        self.framed_message(message)?.write(self).await
+    }
+
+    fn framed_message(&self, message: impl Into<Bytes>) -> Result<FramedMessage> {
+        let message = message.into();
+        ensure!(
+            message.len() <= self.max_message_size,
+            "Invalid framed message length ({} bytes)",
+            message.len()
+        );
+        FramedMessage::new(message)
+    }
+
+    /// send a keep-alive if nothing has been sent for longer than the
+    /// keep-alive interval, returns `true` if the keep-alive has been sent
+    ///
+    /// the keep-alives are also sent when flushing and, with the
+    /// [`Handle`], while waiting for the data of the remote peer. The
+    /// application calls this if the half is neither flushed nor read
+    /// from regularly. The keep-alives are not returned by the remote's
+    /// reading half, they only refresh its
+    /// [idle timeout](HandleReadHalf::idle_timeout).
+    pub async fn keep_alive(&mut self) -> Result<bool> {
+        if self.close_sent || !self.keep_alive_needed() {
+            return Ok(false);
+        }
+
+        self.sink
+            .send(Frame {
+                content_type: ContentType::KeepAlive,
+                payload: BytesMut::new(),
+            })
+            .await
+            .context("Cannot send the keep-alive")?;
+        self.last_sent = Instant::now();
+
+        Ok(true)
+    }
+
+    /// fail the pending operation once the underlying stream has not
+    /// accepted the data for longer than the write timeout
+    fn poll_timeout<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        if poll.is_ready() {
+            self.stalled = None;
+            return poll;
+        }
+
+        let write_timeout = if let Some(write_timeout) = self.write_timeout {
+            write_timeout
+        } else {
+            return Poll::Pending;
+        };
+
+        let stalled = self
+            .stalled
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(write_timeout)));
+        futures::ready!(stalled.as_mut().poll(cx));
+        self.stalled = None;
+
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "write timed out",
+        )))
+    }
+
+    fn poll_sink_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Sink::<Bytes>::poll_ready(Pin::new(&mut self.sink), cx);
+        self.poll_timeout(cx, poll)
+    }
+
+    fn poll_sink_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Sink::<Bytes>::poll_flush(Pin::new(&mut self.sink), cx);
+        let poll = self.poll_timeout(cx, poll);
+        if let Poll::Ready(Ok(())) = poll {
+            self.flush = false;
+        }
+        poll
+    }
+
+    /// flush the frames sent without the application, without waiting
+    /// for the underlying stream
+    fn poll_flush_control(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        if self.flush {
+            if let Poll::Ready(result) = self.poll_sink_flush(cx) {
+                result.context("Cannot flush the connection")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// send a keep-alive if nothing has been sent for longer than the
+    /// keep-alive interval, see [`keep_alive`](Self::keep_alive)
+    fn poll_send_keep_alive(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if !self.close_sent && self.keep_alive_needed() {
+            futures::ready!(self.poll_sink_ready(cx)).context("Cannot send the keep-alive")?;
+            Pin::new(&mut self.sink)
+                .start_send(Frame {
+                    content_type: ContentType::KeepAlive,
+                    payload: BytesMut::new(),
+                })
+                .context("Cannot send the keep-alive")?;
+            self.last_sent = Instant::now();
+            self.flush = true;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// send the keep-alives while the application waits for the data of
+    /// the remote peer, this only returns the errors
+    ///
+    /// this requires the tokio runtime's timer
+    fn poll_keep_alive(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        loop {
+            futures::ready!(self.poll_send_keep_alive(cx))?;
+            self.poll_flush_control(cx)?;
+
+            let keep_alive = match self.keep_alive {
+                Some(keep_alive) if !self.close_sent && !keep_alive.is_zero() => keep_alive,
+                _ => return Poll::Pending,
+            };
+
+            let deadline = tokio::time::Instant::from_std(self.last_sent + keep_alive);
+            let timer = self
+                .keep_alive_timer
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            if timer.deadline() != deadline {
+                timer.as_mut().reset(deadline);
+            }
+
+            futures::ready!(timer.as_mut().poll(cx));
+        }
+    }
+
+    fn poll_sink_close(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Sink::<Bytes>::poll_close(Pin::new(&mut self.sink), cx);
+        self.poll_timeout(cx, poll)
+    }
+
+    /// send the close frame, once
+    fn poll_send_close(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if !self.close_sent {
+            futures::ready!(self.poll_sink_ready(cx)).context("Cannot send the close frame")?;
+            Pin::new(&mut self.sink)
+                .start_send(Frame {
+                    content_type: ContentType::Close,
+                    payload: BytesMut::new(),
+                })
+                .context("Cannot send the close frame")?;
+            self.close_sent = true;
+        }
+
+        Poll::Ready(Ok(()))
+    }
 }
 
 impl<I, O> Handle<I, O>
@@ -101,13 +738,28 @@ where
     I: AsyncRead + Unpin,
     O: AsyncWrite + Unpin,
 {
-    pub(crate) fn new(stream: I, sink: O, state: TransportState<Blake2b>) -> Self {
+    pub(crate) fn new(
+        stream: I,
+        sink: O,
+        state: TransportState<Blake2b>,
+        extensions: Extensions,
+    ) -> Self {
+        let rekey_policy = state.rekey_policy();
         let (tsh, trh) = state.split();
 
-        let stream = HandleReadHalf::new(stream, trh);
-        let sink = HandleWriteHalf::new(sink, tsh);
+        let stream = HandleReadHalf::new(stream, trh, extensions);
+        let sink = HandleWriteHalf::new(sink, tsh, rekey_policy);
 
-        Self { stream, sink }
+        Self::from_halves(stream, sink)
+    }
+
+    fn from_halves(stream: HandleReadHalf<I>, sink: HandleWriteHalf<O>) -> Self {
+        Self {
+            stream,
+            sink,
+            rotation: None,
+            rotating: None,
+        }
     }
 
     /// split the handle into 2 parts into 2 separate half
@@ -116,10 +768,26 @@ where
     /// because the connection is bidirectional/duplex so it is more convenient to handle
     /// the protocol if the 2 halves are split. However, if you are only using the
     /// synchronous you can keep the [`Handle`] as it is.
+    ///
+    /// The halves do not rotate the session (see [`Handle::with_rotation`]),
+    /// the application rotates it with [`rotate_if_needed`](Self::rotate_if_needed).
     pub fn split(self) -> (HandleReadHalf<I>, HandleWriteHalf<O>) {
         (self.stream, self.sink)
     }
 
+    /// put back together the 2 halves of [`Handle::split`]
+    ///
+    /// # Errors
+    ///
+    /// the function fails if the halves are not from the same session
+    pub fn unsplit(stream: HandleReadHalf<I>, sink: HandleWriteHalf<O>) -> Result<Self> {
+        if stream.session_id() != sink.session_id() {
+            bail!("Cannot put together halves of different sessions")
+        }
+
+        Ok(Self::from_halves(stream, sink))
+    }
+
     /// prepare accepting the new request from the given stream
     ///
     pub fn accept<K, RNG>(rng: RNG, reader: I, writer: O) -> Accepting<I, O, RNG, K>
@@ -145,148 +813,2524 @@ where
         K: Dh,
         RNG: RngCore + CryptoRng,
     {
-        let opening = Opening::new(rng, k, rs, reader, writer).await?;
-        opening.wait(k).await
+        Self::open_with_extensions(rng, k, rs, &Extensions::new(), reader, writer).await
     }
 
-    /// retrieve the public identity of the peer
+    /// same as [`open`](Self::open) but send the given [`Extensions`] to
+    /// the remote peer
     ///
-    #[allow(dead_code)]
-    pub fn remote_public_identity(&self) -> &PublicKey {
-        self.stream.remote_public_identity()
+    /// the remote peer's extensions are available with
+    /// [`remote_extensions`](Self::remote_extensions).
+    pub async fn open_with_extensions<K, RNG>(
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        extensions: &Extensions,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        Self::open_with_config(
+            rng,
+            k,
+            rs,
+            extensions,
+            &ConnectConfig::default(),
+            reader,
+            writer,
+        )
+        .await
     }
 
-    /// retrieve the unique identifier of the established session
-    ///
-    /// this is derived from the NOISE handshake and is the same
-    /// on both sides of the stream (here and for the remote).
-    pub fn session_id(&self) -> &SessionId {
-        self.stream.session_id()
+    /// same as [`open_with_extensions`](Self::open_with_extensions) but
+    /// the handshake and the established connection are bounded by the
+    /// limits of the [`ConnectConfig`]
+    pub async fn open_with_config<K, RNG>(
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        extensions: &Extensions,
+        config: &ConnectConfig,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        let opening = Opening::new(rng, k, rs, extensions, config, reader, writer).await?;
+        opening.wait(k).await
     }
-}
 
-impl<I, O> Stream for Handle<I, O>
-where
-    I: AsyncRead + Unpin,
-    O: Unpin,
-{
-    type Item = Result<BytesMut>;
+    /// same as [`open_with_extensions`](Self::open_with_extensions) but
+    /// recover if the remote peer has rotated its static key since we
+    /// learned `rs`
+    ///
+    /// This is the [Noise Pipes] protocol: the remote peer that cannot
+    /// decrypt our initial message falls back to a [Noise **XXfallback**]
+    /// handshake and sends its new public identity. `check_id` verifies
+    /// it before we authenticate ourself, the new identity is then
+    /// available with [`remote_public_identity`](Self::remote_public_identity).
+    ///
+    /// The remote peer accepts the connection as usual (see [`Handle::accept`]).
+    ///
+    /// [Noise Pipes]: http://noiseprotocol.org/noise.html#noise-pipes
+    /// [Noise **XXfallback**]: https://noiseexplorer.com/patterns/XXfallback/
+    pub async fn open_with_fallback<K, RNG, F>(
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        extensions: &Extensions,
+        check_id: F,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+        F: Fn(&PublicKey) -> bool,
+    {
+        Self::open_with_fallback_and_config(
+            rng,
+            k,
+            rs,
+            extensions,
+            &ConnectConfig::default(),
+            check_id,
+            reader,
+            writer,
+        )
+        .await
+    }
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let handle = self.get_mut();
-        let stream = Pin::new(&mut handle.stream);
-        stream.poll_next(cx)
+    /// same as [`open_with_fallback`](Self::open_with_fallback) but the
+    /// handshake and the established connection are bounded by the
+    /// limits of the [`ConnectConfig`]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn open_with_fallback_and_config<K, RNG, F>(
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        extensions: &Extensions,
+        config: &ConnectConfig,
+        check_id: F,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+        F: Fn(&PublicKey) -> bool,
+    {
+        let opening = Opening::new(rng, k, rs, extensions, config, reader, writer).await?;
+        opening.wait_with_fallback(k, extensions, check_id).await
     }
-}
 
-impl<I> Stream for HandleReadHalf<I>
-where
-    I: AsyncRead + Unpin,
-{
-    type Item = Result<BytesMut>;
+    /// open a new stream with a remote peer whose public identity is not
+    /// known in advance
+    ///
+    /// This is a [Noise **XX**] handshake: the remote peer sends its public
+    /// identity during the handshake and `check_id` verifies it before
+    /// we send ours. Our identity is encrypted so only the remote peer
+    /// learns it. No [`Extensions`] are exchanged.
+    ///
+    /// The remote peer accepts the connection as usual (see [`Handle::accept`]).
+    ///
+    /// [Noise **XX**]: https://noiseexplorer.com/patterns/XX/
+    pub async fn open_xx<K, RNG, F>(
+        rng: RNG,
+        k: &K,
+        check_id: F,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+        F: Fn(&PublicKey) -> bool,
+    {
+        Self::open_xx_with_config(rng, k, &ConnectConfig::default(), check_id, reader, writer).await
+    }
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if self.is_terminated() {
-            return Poll::Ready(None);
-        }
+    /// same as [`open_xx`](Self::open_xx) but the handshake and the
+    /// established connection are bounded by the limits of the
+    /// [`ConnectConfig`]
+    pub async fn open_xx_with_config<K, RNG, F>(
+        rng: RNG,
+        k: &K,
+        config: &ConnectConfig,
+        check_id: F,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+        F: Fn(&PublicKey) -> bool,
+    {
+        config.check_no_extensions("XX")?;
+        let handle = with_timeout(
+            config.handshake_timeout,
+            opening::open_xx(rng, k, config.versions, check_id, reader, writer),
+        )
+        .await?;
 
-        let handle = self.get_mut();
-        let stream = Pin::new(&mut handle.stream);
+        Ok(handle.with_config(config))
+    }
 
-        match futures::ready!(stream.poll_next(cx)) {
-            None => {
-                handle.none = true;
-                Poll::Ready(None)
-            }
-            Some(result) => Poll::Ready(Some(result.context("Invalid frame received from peer"))),
-        }
+    /// open a new stream with the remote peer expecting the remote's
+    /// public identity `rs`, hiding our public identity from passive
+    /// observers
+    ///
+    /// This is a [Noise **XK**] handshake: our public identity is only
+    /// sent in the last message, once the remote peer is authenticated,
+    /// and it is encrypted so only the remote peer learns it. No
+    /// [`Extensions`] are exchanged.
+    ///
+    /// The remote peer accepts the connection as usual (see [`Handle::accept`]).
+    ///
+    /// [Noise **XK**]: https://noiseexplorer.com/patterns/XK/
+    pub async fn open_xk<K, RNG>(
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        Self::open_xk_with_config(rng, k, rs, &ConnectConfig::default(), reader, writer).await
     }
-}
 
-impl<I> stream::FusedStream for HandleReadHalf<I>
-where
+    /// same as [`open_xk`](Self::open_xk) but the handshake and the
+    /// established connection are bounded by the limits of the
+    /// [`ConnectConfig`]
+    pub async fn open_xk_with_config<K, RNG>(
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        config: &ConnectConfig,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        config.check_no_extensions("XK")?;
+        let handle = with_timeout(
+            config.handshake_timeout,
+            opening::open_xk(rng, k, rs, config.versions, reader, writer),
+        )
+        .await?;
+
+        Ok(handle.with_config(config))
+    }
+
+    /// open a new stream with the remote peer expecting the remote's
+    /// public identity `rs`, without authenticating ourself
+    ///
+    /// This is a [Noise **NK**] handshake: only the ephemeral key of type
+    /// `K` is sent, the remote peer does not learn any public identity
+    /// (its [`remote_public_identity`](Self::remote_public_identity) is
+    /// `None`). No [`Extensions`] are exchanged.
+    ///
+    /// The remote peer needs to allow the anonymous connections (see
+    /// [`Accepting::allow_anonymous`]).
+    ///
+    /// [Noise **NK**]: https://noiseexplorer.com/patterns/NK/
+    pub async fn open_nk<K, RNG>(rng: RNG, rs: PublicKey, reader: I, writer: O) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        Self::open_nk_with_config::<K, _>(rng, rs, &ConnectConfig::default(), reader, writer).await
+    }
+
+    /// same as [`open_nk`](Self::open_nk) but the handshake and the
+    /// established connection are bounded by the limits of the
+    /// [`ConnectConfig`]
+    pub async fn open_nk_with_config<K, RNG>(
+        rng: RNG,
+        rs: PublicKey,
+        config: &ConnectConfig,
+        reader: I,
+        writer: O,
+    ) -> Result<Self>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        config.check_no_extensions("NK")?;
+        let handle = with_timeout(
+            config.handshake_timeout,
+            opening::open_nk::<_, _, _, K>(rng, rs, config.versions, reader, writer),
+        )
+        .await?;
+
+        Ok(handle.with_config(config))
+    }
+
+    /// retrieve the public identity of the peer
+    ///
+    /// `None` if the peer connected anonymously (see [`Handle::open_nk`])
+    #[allow(dead_code)]
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
+        self.stream.remote_public_identity()
+    }
+
+    /// retrieve the unique identifier of the established session
+    ///
+    /// this is derived from the NOISE handshake and is the same
+    /// on both sides of the stream (here and for the remote).
+    pub fn session_id(&self) -> &SessionId {
+        self.stream.session_id()
+    }
+
+    /// number of messages that can still be sent on this session
+    pub fn remaining_sends(&self) -> u64 {
+        self.sink.remaining_sends()
+    }
+
+    /// number of messages that can still be received on this session
+    pub fn remaining_receives(&self) -> u64 {
+        self.stream.remaining_receives()
+    }
+
+    /// the [`Extensions`] the remote peer sent during the handshake
+    pub fn remote_extensions(&self) -> &Extensions {
+        self.stream.remote_extensions()
+    }
+
+    /// the [`Version`] of the protocol negotiated during the handshake
+    pub fn version(&self) -> Version {
+        self.stream.version()
+    }
+
+    pub(crate) fn with_version(mut self, version: Version) -> Self {
+        self.stream.version = version;
+        self
+    }
+
+    /// see [`HandleWriteHalf::send_frame`]
+    pub async fn send_frame(&mut self, message: impl Into<Bytes>) -> Result<()> {
+        self.sink.framed_message(message)?.write(self).await
+    }
+
+    /// see [`HandleReadHalf::recv_frame`]
+    pub async fn recv_frame(&mut self) -> Result<Option<Bytes>> {
+        let max_message_size = self.stream.max_message_size;
+        let message = FramedMessage::read_limited(self, max_message_size).await?;
+        Ok(message.map(FramedMessage::into_message))
+    }
+
+    /// rotate the session without the application
+    ///
+    /// The handle keeps our static key `k` and the remote's public
+    /// identity `rs` to perform the [re-handshakes](Self::rehandshake)
+    /// itself: a new session is requested before sending once the
+    /// session is older than the maximum session age (see
+    /// [`with_max_session_age`](Self::with_max_session_age)) or once it
+    /// is [exhausted](Self::session_exhausted), and the
+    /// re-handshakes requested by the remote peer are answered while
+    /// reading instead of returning [`RehandshakeRequested`]. Only the
+    /// session's keys change, the remote peer's re-handshakes with another
+    /// identity than `rs` are rejected.
+    ///
+    /// `rng` seeds the generation of the ephemeral keys of the
+    /// re-handshakes.
+    pub fn with_rotation<K, RNG>(mut self, mut rng: RNG, k: &K, rs: PublicKey) -> Self
+    where
+        K: Dh + Clone + Send + 'static,
+        RNG: RngCore + CryptoRng,
+    {
+        self.rotation = Some(Box::new(Rotation {
+            k: k.clone(),
+            rs,
+            rng: Seed::generate(&mut rng).into_rand_chacha(),
+        }));
+        self
+    }
+
+    /// rotate the session once it is older than `max_session_age`, see
+    /// [`with_rotation`](Self::with_rotation) and
+    /// [`rotate_if_needed`](Self::rotate_if_needed)
+    pub fn with_max_session_age(mut self, max_session_age: Duration) -> Self {
+        self.sink.set_max_session_age(Some(max_session_age));
+        self
+    }
+
+    /// time the current session has been established
+    pub fn session_established(&self) -> SystemTime {
+        self.sink.session_established()
+    }
+
+    /// apply the read and write timeouts and the maximum message size of
+    /// the [`ConnectConfig`] to the established connection
+    ///
+    /// the connections opened with [`open_with_config`](Self::open_with_config)
+    /// or accepted with [`Accepting::with_config`] are already configured.
+    pub fn with_config(mut self, config: &ConnectConfig) -> Self {
+        self.stream.set_idle_timeout(config.read_timeout);
+        self.stream.set_max_message_size(config.max_message_size);
+        self.sink.set_write_timeout(config.write_timeout);
+        self.sink.set_max_message_size(config.max_message_size);
+        self
+    }
+
+    /// send a keep-alive after `keep_alive` without sending anything, see
+    /// [`keep_alive`](Self::keep_alive)
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.sink.set_keep_alive_interval(Some(keep_alive));
+        self
+    }
+
+    /// consider the remote peer dead after `idle_timeout` without
+    /// receiving anything from it, see [`IdleTimeout`]
+    ///
+    /// the remote peer is expected to send keep-alives more often than
+    /// this (see [`with_keep_alive`](Self::with_keep_alive)).
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.stream.set_idle_timeout(Some(idle_timeout));
+        self
+    }
+
+    /// `false` if nothing has been received from the remote peer for
+    /// longer than the idle timeout, see [`IdleTimeout`]
+    pub fn is_alive(&self) -> bool {
+        self.stream.is_alive()
+    }
+
+    /// see [`HandleWriteHalf::keep_alive`]
+    pub async fn keep_alive(&mut self) -> Result<bool> {
+        self.sink.keep_alive().await
+    }
+
+    /// close the connection gracefully
+    ///
+    /// the close frame is sent and the writing side of the underlying
+    /// stream is shut down (this is also what [`SinkExt::close`] and
+    /// [`AsyncWriteExt::shutdown`] do), then the function waits for the
+    /// close frame of the remote peer. The data received in the meantime
+    /// remain available to read.
+    ///
+    /// The remote peer's reading half returns `None` once it receives
+    /// the close frame, it is then expected to close its side too. If the
+    /// underlying stream ends without the close frame the reading half
+    /// returns [`ConnectionTruncated`] instead.
+    ///
+    /// [`AsyncWriteExt::shutdown`]: tokio::io::AsyncWriteExt::shutdown
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.sink
+            .close()
+            .await
+            .context("Cannot close the connection")?;
+        self.stream.wait_close().await
+    }
+
+    /// `true` if the remote peer closed the connection gracefully
+    pub fn remote_closed(&self) -> bool {
+        self.stream.remote_closed()
+    }
+
+    /// `true` if the session is older than the maximum session age
+    pub fn session_expired(&self, now: SystemTime) -> bool {
+        self.sink.session_expired(now)
+    }
+
+    /// rotate the session once fewer than `min_remaining_messages` can
+    /// be sent or received on it (the default is
+    /// [`DEFAULT_MIN_REMAINING_MESSAGES`]), see
+    /// [`with_rotation`](Self::with_rotation) and
+    /// [`rotate_if_needed`](Self::rotate_if_needed)
+    pub fn with_min_remaining_messages(mut self, min_remaining_messages: u64) -> Self {
+        self.sink.set_min_remaining_messages(min_remaining_messages);
+        self
+    }
+
+    /// `true` if the nonces of the session are close to their limit:
+    /// fewer than the minimum remaining messages can be sent or received
+    pub fn session_exhausted(&self) -> bool {
+        session_exhausted(&self.stream, &self.sink)
+    }
+
+    /// perform a [re-handshake](Self::rehandshake) if the session is
+    /// older than the maximum session age (see
+    /// [`with_max_session_age`](Self::with_max_session_age)) or if it
+    /// is [exhausted](Self::session_exhausted), returns `true` if the
+    /// session has been rotated
+    ///
+    /// Rotating the session before its nonces reach their limit avoids
+    /// the [`NonceExhausted`](keynesis_core::noise::CipherStateError::NonceExhausted) errors that would end the
+    /// connection.
+    ///
+    /// the application calls this regularly (for example before sending
+    /// a message or with a timer) so long lived connections comply with
+    /// the key rotation policies, unless the handle rotates the session
+    /// itself (see [`with_rotation`](Self::with_rotation)). The remote peer
+    /// accepts the re-handshake as usual (see [`RehandshakeRequested`]).
+    pub async fn rotate_if_needed<K, RNG>(
+        &mut self,
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        now: SystemTime,
+    ) -> Result<bool>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        rotate_if_needed(&mut self.stream, &mut self.sink, rng, k, rs, now).await
+    }
+
+    /// perform a new handshake with the remote peer within the current
+    /// session and switch to the new session
+    ///
+    /// This is a new [Noise **IK**] handshake: the static key `k` and the
+    /// remote's expected identity `rs` can be different from the ones of
+    /// the current session. This allows rotating the identity keys without
+    /// dropping the connection. The handshake messages are encrypted with
+    /// the current session and the new handshake is bound to it (the
+    /// current [`SessionId`] is the prologue).
+    ///
+    /// The remote peer is expected to call [`Handle::accept_rehandshake`].
+    /// The data received before the handshake is completed remain
+    /// available to read.
+    ///
+    /// No [`Extensions`] are exchanged, the ones of the initial handshake
+    /// still apply.
+    ///
+    /// [Noise **IK**]: https://noiseexplorer.com/patterns/IK/
+    pub async fn rehandshake<K, RNG>(&mut self, rng: RNG, k: &K, rs: PublicKey) -> Result<()>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+    {
+        if self.rotating.is_some() {
+            bail!("The session is already being rotated")
+        }
+
+        rehandshake(&mut self.stream, &mut self.sink, rng, k, rs).await
+    }
+
+    /// accept the re-handshake requested by the remote peer (see
+    /// [`Handle::rehandshake`]) and switch to the new session
+    ///
+    /// If the request has not been received yet (see [`RehandshakeRequested`])
+    /// the function waits for it. Like for [`Accepting::accept`], `check_id`
+    /// verifies the remote's new public key.
+    pub async fn accept_rehandshake<K, RNG, F>(
+        &mut self,
+        rng: RNG,
+        k: &K,
+        check_id: F,
+    ) -> Result<()>
+    where
+        K: Dh,
+        RNG: RngCore + CryptoRng,
+        F: Fn(&PublicKey) -> bool,
+    {
+        accept_rehandshake(&mut self.stream, &mut self.sink, rng, k, check_id).await
+    }
+
+    /// answer the re-handshake requested by the remote peer with the
+    /// automatic rotation, then flush the response without waiting for it
+    fn poll_answer(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let (Some(rotation), true) = (&mut self.rotation, self.stream.rehandshake_requested()) {
+            if self.sink.close_sent {
+                return Poll::Ready(Err(anyhow!(
+                    "Cannot answer the re-handshake after closing the connection"
+                )));
+            }
+
+            futures::ready!(self.sink.poll_sink_ready(cx))
+                .context("Cannot send the Noise IK response re-handshake")?;
+            let message = self
+                .stream
+                .rehandshake
+                .take()
+                .expect("re-handshake requested");
+            let (state, response) = rotation
+                .accept(self.stream.session_id(), self.stream.version(), message)
+                .context("Cannot rotate the session")?;
+            Pin::new(&mut self.sink.sink)
+                .start_send(response)
+                .context("Cannot send the Noise IK response re-handshake")?;
+
+            switch(&mut self.stream, &mut self.sink, state);
+            self.sink.flush = true;
+        }
+
+        self.sink.poll_flush_control(cx)?;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// poll the next frame of the remote peer with the automatic
+    /// rotation: the re-handshake frames are processed here and `None`
+    /// is returned, the data and close frames are returned
+    ///
+    /// If both peers request a new session at the same time, the request
+    /// with the smallest encoding is answered and the other one is dropped
+    /// so they both end up with the same session.
+    fn poll_control(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Frame>>> {
+        let frame = futures::ready!(self.stream.poll_frame(cx))?;
+
+        match frame.content_type {
+            ContentType::Data | ContentType::Close => return Poll::Ready(Ok(Some(frame))),
+            ContentType::KeepAlive => unreachable!("the keep-alives are skipped by poll_frame"),
+            ContentType::RehandshakeInitialize => {
+                let message = decode_initialize(&frame.payload)?;
+                match &self.rotating {
+                    Some(rotating) if rotating.request < frame.payload => (),
+                    _ => {
+                        self.rotating = None;
+                        self.stream.rehandshake = Some(message);
+                    }
+                }
+            }
+            ContentType::RehandshakeResponse => {
+                let rotating = if let Some(rotating) = self.rotating.take() {
+                    rotating
+                } else {
+                    return Poll::Ready(Err(anyhow!("Unexpected re-handshake response from peer")));
+                };
+                let state = rotating
+                    .state
+                    .receive(self.stream.version(), &frame.payload)
+                    .context("Cannot rotate the session")?;
+
+                switch(&mut self.stream, &mut self.sink, state);
+            }
+        }
+
+        Poll::Ready(Ok(None))
+    }
+
+    /// the [`Stream`] of the data with the automatic rotation
+    fn poll_rotation_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<BytesMut>>> {
+        loop {
+            if let Some(data) = self.stream.pending.pop_front() {
+                return Poll::Ready(Some(Ok(data)));
+            }
+
+            if self.stream.is_terminated() {
+                return Poll::Ready(None);
+            }
+
+            if let Err(error) = futures::ready!(self.poll_answer(cx)) {
+                return Poll::Ready(Some(Err(error)));
+            }
+
+            match futures::ready!(self.poll_control(cx)) {
+                Ok(None) => (),
+                Ok(Some(frame)) if frame.content_type == ContentType::Data => {
+                    return Poll::Ready(Some(Ok(frame.payload)))
+                }
+                Ok(Some(_)) => return Poll::Ready(None),
+                Err(error) => return Poll::Ready(Some(Err(error))),
+            }
+        }
+    }
+
+    /// request a new session with the automatic rotation once the current
+    /// one is expired or exhausted, and wait for the remote peer's response: nothing
+    /// can be sent until then
+    ///
+    /// the data received in the meantime are kept so they can be read later
+    fn poll_rotate(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.rotation.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+
+        futures::ready!(self.poll_answer(cx))?;
+
+        if self.rotating.is_none()
+            && !self.sink.close_sent
+            && (self.sink.session_expired(SystemTime::now())
+                || session_exhausted(&self.stream, &self.sink))
+        {
+            futures::ready!(self.sink.poll_sink_ready(cx))
+                .context("Cannot send the Noise IK initial re-handshake")?;
+            let rotation = self.rotation.as_mut().expect("automatic rotation");
+            let (rotating, request) = rotation
+                .initiate(self.stream.session_id(), self.stream.version())
+                .context("Cannot rotate the session")?;
+            Pin::new(&mut self.sink.sink)
+                .start_send(request)
+                .context("Cannot send the Noise IK initial re-handshake")?;
+            self.rotating = Some(rotating);
+        }
+
+        while self.rotating.is_some() {
+            if let Poll::Ready(result) = self.sink.poll_sink_flush(cx) {
+                result.context("Cannot send the Noise IK initial re-handshake")?;
+            }
+
+            match futures::ready!(self.poll_control(cx))? {
+                Some(frame) if frame.content_type == ContentType::Data => {
+                    self.stream.pending.push_back(frame.payload)
+                }
+                Some(_) => {
+                    return Poll::Ready(Err(anyhow!(
+                        "The remote peer closed the connection during the re-handshake"
+                    )))
+                }
+                None => futures::ready!(self.poll_answer(cx))?,
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub(crate) async fn rehandshake<I, O, K, RNG>(
+    stream: &mut HandleReadHalf<I>,
+    sink: &mut HandleWriteHalf<O>,
+    rng: RNG,
+    k: &K,
+    rs: PublicKey,
+) -> Result<()>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: RngCore + CryptoRng,
+{
+    if stream.rehandshake_requested() {
+        bail!("The remote peer already requested a re-handshake")
+    }
+
+    let versions = handshake::session_versions(stream.version());
+    let (state, request) = rehandshake_request(rng, k, rs, stream.session_id(), versions)?;
+
+    sink.sink
+        .send(request)
+        .await
+        .context("Cannot send the Noise IK initial re-handshake")?;
+
+    let frame = stream.next_control().await?;
+    match frame.content_type {
+        ContentType::RehandshakeResponse => (),
+        ContentType::RehandshakeInitialize => {
+            bail!("The remote peer requested a re-handshake at the same time")
+        }
+        ContentType::Close => {
+            bail!("The remote peer closed the connection during the re-handshake")
+        }
+        ContentType::Data | ContentType::KeepAlive => {
+            unreachable!("data and keep-alive frames are handled by the read half")
+        }
+    }
+
+    let state = rehandshake_complete(state, k, versions, stream.version(), &frame.payload)?;
+
+    switch(stream, sink, state);
+    Ok(())
+}
+
+/// the request of a new session, the handshake is bound to the current
+/// session `session_id`
+fn rehandshake_request<K, RNG>(
+    rng: RNG,
+    k: &K,
+    rs: PublicKey,
+    session_id: &SessionId,
+    versions: VersionRange,
+) -> Result<(IK<K, Blake2b, RNG, WaitB>, Frame)>
+where
+    K: Dh,
+    RNG: RngCore + CryptoRng,
+{
+    let mut message = Vec::with_capacity(HandshakeInitialize::MIN_MESSAGE_SIZE);
+    let state = IK::new(rng, &None, session_id.as_ref())
+        .initiate(k, rs, &mut message)
+        .context("Cannot initiate Noise IK re-handshake")?;
+
+    let request = Frame {
+        content_type: ContentType::RehandshakeInitialize,
+        payload: BytesMut::from(
+            HandshakeInitialize::with_versions(versions, message)
+                .to_bytes()
+                .as_slice(),
+        ),
+    };
+
+    Ok((state, request))
+}
+
+/// the new session from the remote peer's response to our request, the
+/// version of the session cannot change
+fn rehandshake_complete<K, RNG>(
+    state: IK<K, Blake2b, RNG, WaitB>,
+    k: &K,
+    versions: VersionRange,
+    version: Version,
+    response: &[u8],
+) -> Result<TransportState<Blake2b>>
+where
+    K: Dh,
+{
+    let message =
+        HandshakeResponse::from_bytes(response).context("Invalid re-handshake response")?;
+
+    let mut payload = Vec::with_capacity(message.message().len());
+    let state = state
+        .receive_with_payload(k, message.message(), &mut payload)
+        .context("Noise IK re-handshake response failed")?;
+    handshake::check_version(&versions, message.version(), &payload)?;
+    ensure!(
+        message.version() == version,
+        "The remote peer changed the version of the session to {}",
+        message.version()
+    );
+
+    Ok(state)
+}
+
+pub(crate) fn session_exhausted<I, O>(stream: &HandleReadHalf<I>, sink: &HandleWriteHalf<O>) -> bool
+where
+    I: AsyncRead,
+    O: AsyncWrite,
+{
+    let remaining = std::cmp::min(stream.remaining_receives(), sink.remaining_sends());
+    remaining < sink.min_remaining_messages
+}
+
+pub(crate) async fn rotate_if_needed<I, O, K, RNG>(
+    stream: &mut HandleReadHalf<I>,
+    sink: &mut HandleWriteHalf<O>,
+    rng: RNG,
+    k: &K,
+    rs: PublicKey,
+    now: SystemTime,
+) -> Result<bool>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: RngCore + CryptoRng,
+{
+    if !sink.session_expired(now) && !session_exhausted(stream, sink) {
+        return Ok(false);
+    }
+
+    rehandshake(stream, sink, rng, k, rs)
+        .await
+        .context("Cannot rotate the expired session")?;
+    Ok(true)
+}
+
+pub(crate) async fn accept_rehandshake<I, O, K, RNG, F>(
+    stream: &mut HandleReadHalf<I>,
+    sink: &mut HandleWriteHalf<O>,
+    rng: RNG,
+    k: &K,
+    check_id: F,
+) -> Result<()>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: RngCore + CryptoRng,
+    F: Fn(&PublicKey) -> bool,
+{
+    let message = stream.rehandshake_request().await?;
+    let (state, response) = rehandshake_response(
+        rng,
+        k,
+        check_id,
+        stream.session_id(),
+        stream.version(),
+        message,
+    )?;
+
+    sink.sink
+        .send(response)
+        .await
+        .context("Cannot send the Noise IK response re-handshake")?;
+
+    switch(stream, sink, state);
+    Ok(())
+}
+
+/// the response to the remote peer's request of a new session, the
+/// new session is used once the response is sent
+fn rehandshake_response<K, RNG, F>(
+    rng: RNG,
+    k: &K,
+    check_id: F,
+    session_id: &SessionId,
+    version: Version,
+    message: HandshakeInitialize,
+) -> Result<(TransportState<Blake2b>, Frame)>
+where
+    K: Dh,
+    RNG: RngCore + CryptoRng,
+    F: Fn(&PublicKey) -> bool,
+{
+    let versions = message.versions();
+
+    let mut payload = Vec::with_capacity(message.message().len());
+    let state = IK::new(rng, &None, session_id.as_ref())
+        .receive_with_payload(k, message.message(), &mut payload)
+        .context("Noise IK re-handshake initiate failed")?;
+
+    if !check_id(state.remote_public_identity()) {
+        bail!(
+            "Rejecting re-handshake with {}",
+            state.remote_public_identity()
+        )
+    }
+
+    let mut message = Vec::with_capacity(HandshakeResponse::MIN_MESSAGE_SIZE);
+    let state = state
+        .reply_with_payload(handshake::reply_payload(&versions, &[]), &mut message)
+        .context("Cannot prep the Noise's re-handshake Response message")?;
+
+    let response = Frame {
+        content_type: ContentType::RehandshakeResponse,
+        payload: BytesMut::from(
+            HandshakeResponse::new(version, message)
+                .to_bytes()
+                .as_slice(),
+        ),
+    };
+
+    Ok((state, response))
+}
+
+fn switch<I, O>(
+    stream: &mut HandleReadHalf<I>,
+    sink: &mut HandleWriteHalf<O>,
+    mut state: TransportState<Blake2b>,
+) {
+    state.set_rekey_policy(sink.rekey_policy);
+    let (tsh, trh) = state.split();
+
+    sink.sink.encoder_mut().rekey(tsh);
+    sink.established = SystemTime::now();
+    stream.stream.decoder_mut().rekey(trh);
+}
+
+fn decode_initialize(payload: &[u8]) -> Result<HandshakeInitialize> {
+    HandshakeInitialize::from_bytes(payload).context("Invalid re-handshake request")
+}
+
+impl<K, RNG> Rotate for Rotation<K, RNG>
+where
+    K: Dh + Clone + Send + 'static,
+    RNG: RngCore + CryptoRng + Send,
+{
+    fn initiate(&mut self, session_id: &SessionId, version: Version) -> Result<(Rotating, Frame)> {
+        let versions = handshake::session_versions(version);
+        let rng = Seed::generate(&mut self.rng).into_rand_chacha();
+        let (state, request) = rehandshake_request(rng, &self.k, self.rs, session_id, versions)?;
+
+        let rotating = Rotating {
+            request: request.payload.clone(),
+            state: Box::new(RotationRequest {
+                k: self.k.clone(),
+                versions,
+                state,
+            }),
+        };
+
+        Ok((rotating, request))
+    }
+
+    fn accept(
+        &mut self,
+        session_id: &SessionId,
+        version: Version,
+        message: HandshakeInitialize,
+    ) -> Result<(TransportState<Blake2b>, Frame)> {
+        let rng = Seed::generate(&mut self.rng).into_rand_chacha();
+        let rs = self.rs;
+        rehandshake_response(rng, &self.k, |id| *id == rs, session_id, version, message)
+    }
+}
+
+impl<K, RNG> Initiated for RotationRequest<K, RNG>
+where
+    K: Dh + Send,
+    RNG: Send,
+{
+    fn receive(
+        self: Box<Self>,
+        version: Version,
+        response: &[u8],
+    ) -> Result<TransportState<Blake2b>> {
+        let Self { k, versions, state } = *self;
+        rehandshake_complete(state, &k, versions, version, response)
+    }
+}
+
+impl<I, O> Stream for Handle<I, O>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    type Item = Result<BytesMut>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let handle = self.get_mut();
+
+        let next = if handle.rotation.is_none() {
+            Pin::new(&mut handle.stream).poll_next(cx)
+        } else {
+            handle.poll_rotation_next(cx)
+        };
+
+        if next.is_pending() {
+            if let Err(error) = futures::ready!(handle.sink.poll_keep_alive(cx)) {
+                return Poll::Ready(Some(Err(error)));
+            }
+        }
+
+        next
+    }
+}
+
+impl<I> Stream for HandleReadHalf<I>
+where
+    I: AsyncRead + Unpin,
+{
+    type Item = Result<BytesMut>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let handle = self.get_mut();
+
+        if let Some(data) = handle.pending.pop_front() {
+            return Poll::Ready(Some(Ok(data)));
+        }
+
+        if handle.is_terminated() {
+            return Poll::Ready(None);
+        }
+
+        if handle.rehandshake_requested() {
+            return Poll::Ready(Some(Err(anyhow!(RehandshakeRequested))));
+        }
+
+        let frame = match futures::ready!(handle.poll_frame(cx)) {
+            Ok(frame) => frame,
+            Err(error) => return Poll::Ready(Some(Err(error))),
+        };
+
+        match frame.content_type {
+            ContentType::Data => Poll::Ready(Some(Ok(frame.payload))),
+            ContentType::Close => Poll::Ready(None),
+            ContentType::RehandshakeInitialize => {
+                handle.rehandshake = match decode_initialize(&frame.payload) {
+                    Ok(message) => Some(message),
+                    Err(error) => return Poll::Ready(Some(Err(error))),
+                };
+                Poll::Ready(Some(Err(anyhow!(RehandshakeRequested))))
+            }
+            ContentType::RehandshakeResponse => Poll::Ready(Some(Err(anyhow!(
+                "Unexpected re-handshake response from peer"
+            )))),
+            ContentType::KeepAlive => unreachable!("the keep-alives are skipped by poll_frame"),
+        }
+    }
+}
+
+impl<I> stream::FusedStream for HandleReadHalf<I>
+where
     I: AsyncRead + Unpin,
 {
     fn is_terminated(&self) -> bool {
-        self.none
+        self.none && self.pending.is_empty()
+    }
+}
+
+impl<I, O> stream::FusedStream for Handle<I, O>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+impl<I, O> Sink<Bytes> for Handle<I, O>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    type Error = anyhow::Error;
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let handle = self.get_mut();
+        Pin::new(&mut handle.sink).start_send(item)
+    }
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let handle = self.get_mut();
+        futures::ready!(handle.poll_rotate(cx))?;
+        Pin::new(&mut handle.sink).poll_ready(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let handle = self.get_mut();
+        Pin::new(&mut handle.sink).poll_close(cx)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let handle = self.get_mut();
+        Sink::poll_flush(Pin::new(&mut handle.sink), cx)
+    }
+}
+
+impl<O> Sink<Bytes> for HandleWriteHalf<O>
+where
+    O: AsyncWrite + Unpin,
+{
+    type Error = anyhow::Error;
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let handle = self.get_mut();
+
+        if handle.close_sent {
+            bail!("Cannot send data after closing the connection")
+        }
+
+        Pin::new(&mut handle.sink)
+            .start_send(item)
+            .context("Cannot send the encrypted data to the handle")?;
+        handle.last_sent = Instant::now();
+
+        Ok(())
+    }
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let handle = self.get_mut();
+        match handle.poll_sink_ready(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(result) => Poll::Ready(result.context("Cannot poll_ready the handle")),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let handle = self.get_mut();
+        futures::ready!(handle.poll_send_close(cx))?;
+        match handle.poll_sink_close(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(result) => Poll::Ready(result.context("Cannot poll_close the handle")),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let handle = self.get_mut();
+        futures::ready!(handle.poll_send_keep_alive(cx))?;
+        match handle.poll_sink_flush(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(result) => Poll::Ready(result.context("Cannot poll_flush the handle")),
+        }
     }
 }
 
-impl<I, O> stream::FusedStream for Handle<I, O>
+/* AsyncRead/AsyncWrite **************************************************** */
+
+/// read the decrypted data as a byte stream, the frames boundaries are not
+/// preserved
+///
+/// the errors of the [`Stream`] (including [`RehandshakeRequested`]) are
+/// returned as [`io::Error`] wrapping the original error.
+impl<I> AsyncRead for HandleReadHalf<I>
 where
     I: AsyncRead + Unpin,
-    O: Unpin,
 {
-    fn is_terminated(&self) -> bool {
-        self.stream.is_terminated()
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let handle = self.get_mut();
+        let data = futures::ready!(Pin::new(&mut *handle).poll_next(cx));
+        Poll::Ready(read_data(data, &mut handle.pending, buf))
     }
 }
 
-impl<I, O> Sink<Bytes> for Handle<I, O>
+impl<I, O> AsyncRead for Handle<I, O>
 where
-    I: Unpin,
+    I: AsyncRead + Unpin,
     O: AsyncWrite + Unpin,
 {
-    type Error = anyhow::Error;
-
-    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
         let handle = self.get_mut();
-        Pin::new(&mut handle.sink).start_send(item)
+        let data = futures::ready!(Pin::new(&mut *handle).poll_next(cx));
+        Poll::Ready(read_data(data, &mut handle.stream.pending, buf))
     }
+}
 
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+/// copy the data of the [`Stream`] in `buf`
+fn read_data(
+    data: Option<Result<BytesMut>>,
+    pending: &mut VecDeque<BytesMut>,
+    buf: &mut ReadBuf<'_>,
+) -> io::Result<()> {
+    let mut data = match data {
+        None => return Ok(()),
+        Some(Err(error)) => return Err(io::Error::other(error)),
+        Some(Ok(data)) => data,
+    };
+
+    let n = std::cmp::min(buf.remaining(), data.len());
+    buf.put_slice(&data.split_to(n));
+
+    // keep what did not fit for the next read
+    if !data.is_empty() {
+        pending.push_front(data);
+    }
+
+    Ok(())
+}
+
+/// write the data as a byte stream, every write is encrypted in its own
+/// frame (of at most [`MAX_PAYLOAD_LENGTH`] bytes)
+impl<O> AsyncWrite for HandleWriteHalf<O>
+where
+    O: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
         let handle = self.get_mut();
-        Pin::new(&mut handle.sink).poll_ready(cx)
+
+        if handle.close_sent {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        futures::ready!(handle.poll_sink_ready(cx))?;
+
+        let n = std::cmp::min(buf.len(), MAX_PAYLOAD_LENGTH);
+        Pin::new(&mut handle.sink).start_send(Bytes::copy_from_slice(&buf[..n]))?;
+        handle.last_sent = Instant::now();
+
+        Poll::Ready(Ok(n))
     }
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let handle = self.get_mut();
-        Pin::new(&mut handle.sink).poll_close(cx)
+        futures::ready!(handle.poll_send_keep_alive(cx)).map_err(io::Error::other)?;
+        handle.poll_sink_flush(cx)
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let handle = self.get_mut();
-        Pin::new(&mut handle.sink).poll_flush(cx)
+        futures::ready!(handle.poll_send_close(cx)).map_err(io::Error::other)?;
+        handle.poll_sink_close(cx)
     }
 }
 
-impl<O> Sink<Bytes> for HandleWriteHalf<O>
+impl<I, O> AsyncWrite for Handle<I, O>
 where
+    I: AsyncRead + Unpin,
     O: AsyncWrite + Unpin,
 {
-    type Error = anyhow::Error;
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let handle = self.get_mut();
+        futures::ready!(handle.poll_rotate(cx)).map_err(io::Error::other)?;
+        Pin::new(&mut handle.sink).poll_write(cx, buf)
+    }
 
-    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let handle = self.get_mut();
+        AsyncWrite::poll_flush(Pin::new(&mut handle.sink), cx)
+    }
 
-        Pin::new(&mut handle.sink)
-            .start_send(item)
-            .context("Cannot send the encrypted data to the handle")?;
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let handle = self.get_mut();
+        Pin::new(&mut handle.sink).poll_shutdown(cx)
+    }
+}
 
-        Ok(())
+/* Format ****************************************************************** */
+
+impl Display for RehandshakeRequested {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("The remote peer requested a re-handshake")
     }
+}
 
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        let handle = self.get_mut();
-        match Pin::new(&mut handle.sink).poll_ready(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(result) => Poll::Ready(result.context("Cannot poll_ready the handle")),
-        }
+impl Error for RehandshakeRequested {}
+
+impl Display for IdleTimeout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Nothing received from the remote peer for too long")
     }
+}
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        let handle = self.get_mut();
-        match Pin::new(&mut handle.sink).poll_close(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(result) => Poll::Ready(result.context("Cannot poll_close the handle")),
-        }
+impl Error for IdleTimeout {}
+
+impl Display for ConnectionTruncated {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("The connection ended without the remote peer closing it")
     }
+}
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        let handle = self.get_mut();
-        match Pin::new(&mut handle.sink).poll_flush(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(result) => Poll::Ready(result.context("Cannot poll_flush the handle")),
+impl Error for ConnectionTruncated {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VersionRange;
+    use futures::executor::block_on;
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+    use tokio::io::{duplex, DuplexStream, ReadHalf, WriteHalf};
+
+    type TestHandle = Handle<ReadHalf<DuplexStream>, WriteHalf<DuplexStream>>;
+
+    fn connect(alice: &SecretKey, bob: &SecretKey) -> (TestHandle, TestHandle) {
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open(thread_rng(), alice, bob.public_key(), a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept(bob, |_| true),
+            );
+            (a.unwrap(), b.unwrap())
+        })
+    }
+
+    #[test]
+    fn extensions() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let mut alice_extensions = Extensions::new();
+        alice_extensions.insert(1, b"alpn".as_ref()).unwrap();
+        alice_extensions
+            .insert(0xABCD, b"unknown".as_ref())
+            .unwrap();
+        let mut bob_extensions = Extensions::new();
+        bob_extensions.insert(2, vec![0; 1024]).unwrap();
+
+        let (a, b) = block_on(async {
+            futures::join!(
+                Handle::open_with_extensions(
+                    thread_rng(),
+                    &alice,
+                    bob.public_key(),
+                    &alice_extensions,
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept_with_extensions(
+                    &bob,
+                    &bob_extensions,
+                    |_| true
+                ),
+            )
+        });
+        let (a, b) = (a.unwrap(), b.unwrap());
+
+        assert_eq!(a.remote_extensions(), &bob_extensions);
+        assert_eq!(b.remote_extensions(), &alice_extensions);
+    }
+
+    #[test]
+    fn rehandshake() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let alice_new = SecretKey::new(thread_rng());
+        let (mut a, mut b) = connect(&alice, &bob);
+        let session_id = *a.session_id();
+
+        block_on(async {
+            a.send(Bytes::from_static(b"before")).await.unwrap();
+            b.send(Bytes::from_static(b"before")).await.unwrap();
+
+            let (ra, rb) = futures::join!(
+                a.rehandshake(thread_rng(), &alice_new, bob.public_key()),
+                b.accept_rehandshake(thread_rng(), &bob, |id| *id == alice_new.public_key()),
+            );
+            ra.unwrap();
+            rb.unwrap();
+
+            a.send(Bytes::from_static(b"after")).await.unwrap();
+            b.send(Bytes::from_static(b"after")).await.unwrap();
+
+            for handle in [&mut a, &mut b] {
+                assert_eq!(handle.next().await.unwrap().unwrap().as_ref(), b"before");
+                assert_eq!(handle.next().await.unwrap().unwrap().as_ref(), b"after");
+            }
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_ne!(a.session_id(), &session_id);
+        assert_eq!(b.remote_public_identity(), Some(&alice_new.public_key()));
+    }
+
+    #[test]
+    fn rehandshake_requested() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (mut a, mut b) = connect(&alice, &bob);
+
+        block_on(async {
+            let (ra, rb) = futures::join!(
+                a.rehandshake(thread_rng(), &alice, bob.public_key()),
+                async {
+                    let error = b.next().await.unwrap().unwrap_err();
+                    assert!(error.is::<RehandshakeRequested>());
+                    assert!(b.stream.rehandshake_requested());
+
+                    b.accept_rehandshake(thread_rng(), &bob, |_| true).await
+                },
+            );
+            ra.unwrap();
+            rb.unwrap();
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+    }
+
+    #[test]
+    fn rehandshake_rejected() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (mut a, b) = connect(&alice, &bob);
+
+        block_on(async {
+            let (ra, rb) = futures::join!(
+                a.rehandshake(thread_rng(), &alice, bob.public_key()),
+                async move {
+                    let mut b = b;
+                    b.accept_rehandshake(thread_rng(), &bob, |_| false).await
+                    // the connection is dropped here
+                },
+            );
+            assert!(ra.is_err());
+            assert!(rb.is_err());
+        });
+    }
+
+    #[test]
+    fn rotate_if_needed() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, mut b) = connect(&alice, &bob);
+        let mut a = a.with_max_session_age(Duration::from_secs(3600));
+        let session_id = *a.session_id();
+        let established = a.session_established();
+
+        block_on(async {
+            let now = established + Duration::from_secs(60);
+            assert!(!a.session_expired(now));
+            let rotated = a
+                .rotate_if_needed(thread_rng(), &alice, bob.public_key(), now)
+                .await
+                .unwrap();
+            assert!(!rotated);
+            assert_eq!(a.session_id(), &session_id);
+
+            let now = established + Duration::from_secs(3600);
+            assert!(a.session_expired(now));
+            let (ra, rb) = futures::join!(
+                a.rotate_if_needed(thread_rng(), &alice, bob.public_key(), now),
+                b.accept_rehandshake(thread_rng(), &bob, |_| true),
+            );
+            assert!(ra.unwrap());
+            rb.unwrap();
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_ne!(a.session_id(), &session_id);
+        assert!(a.session_established() >= established);
+        // b has no maximum age
+        assert!(!b.session_expired(established + Duration::from_secs(3600 * 24)));
+    }
+
+    #[test]
+    fn rotate_exhausted() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, mut b) = connect(&alice, &bob);
+        let mut a = a.with_min_remaining_messages(u64::MAX - 1);
+        let session_id = *a.session_id();
+        let now = a.session_established();
+
+        block_on(async {
+            assert!(!a.session_exhausted());
+            let rotated = a
+                .rotate_if_needed(thread_rng(), &alice, bob.public_key(), now)
+                .await
+                .unwrap();
+            assert!(!rotated);
+
+            a.send_frame(&b"hello"[..]).await.unwrap();
+            assert!(a.session_exhausted());
+            let (ra, rb) = futures::join!(
+                a.rotate_if_needed(thread_rng(), &alice, bob.public_key(), now),
+                b.accept_rehandshake(thread_rng(), &bob, |_| true),
+            );
+            assert!(ra.unwrap());
+            rb.unwrap();
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_ne!(a.session_id(), &session_id);
+        assert!(!b.session_exhausted());
+    }
+
+    #[test]
+    fn rotation() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+        let mut a = a
+            .with_rotation(thread_rng(), &alice, bob.public_key())
+            .with_max_session_age(Duration::from_secs(3600));
+        let mut b = b.with_rotation(thread_rng(), &bob, alice.public_key());
+        let session_id = *a.session_id();
+
+        block_on(async {
+            a.send(Bytes::from_static(b"before")).await.unwrap();
+            assert_eq!(a.session_id(), &session_id);
+
+            a.sink.established -= Duration::from_secs(3600);
+            let (ra, ()) = futures::join!(a.send(Bytes::from_static(b"after")), async {
+                assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"before");
+                assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"after");
+            });
+            ra.unwrap();
+
+            b.send(Bytes::from_static(b"reply")).await.unwrap();
+            assert_eq!(a.next().await.unwrap().unwrap().as_ref(), b"reply");
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_ne!(a.session_id(), &session_id);
+        assert!(!a.session_expired(SystemTime::now()));
+    }
+
+    #[test]
+    fn rotation_exhausted() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+        let session_id = *a.session_id();
+        let mut a = a
+            .with_rotation(thread_rng(), &alice, bob.public_key())
+            .with_min_remaining_messages(u64::MAX - 3);
+        let mut b = b.with_rotation(thread_rng(), &bob, alice.public_key());
+
+        block_on(async {
+            let (ra, ()) = futures::join!(
+                async {
+                    for i in 0..10u8 {
+                        a.send(Bytes::from(vec![i])).await?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                },
+                async {
+                    for i in 0..10u8 {
+                        assert_eq!(b.next().await.unwrap().unwrap().as_ref(), &[i]);
+                    }
+                }
+            );
+            ra.unwrap();
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_ne!(a.session_id(), &session_id);
+        assert!(!a.session_exhausted());
+    }
+
+    #[test]
+    fn rotation_accepted() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, mut b) = connect(&alice, &bob);
+        let mut a = a
+            .with_rotation(thread_rng(), &alice, bob.public_key())
+            .with_max_session_age(Duration::from_secs(3600));
+        a.sink.established -= Duration::from_secs(3600);
+
+        // the remote peer does not rotate the sessions itself
+        let received = block_on(async {
+            let (ra, rb) = futures::join!(a.send(Bytes::from_static(b"hello")), async {
+                let error = b.next().await.unwrap().unwrap_err();
+                assert!(error.is::<RehandshakeRequested>());
+                b.accept_rehandshake(thread_rng(), &bob, |_| true)
+                    .await
+                    .unwrap();
+                b.next().await.unwrap().unwrap()
+            });
+            ra.unwrap();
+            rb
+        });
+
+        assert_eq!(received.as_ref(), b"hello");
+        assert_eq!(a.session_id(), b.session_id());
+    }
+
+    #[test]
+    fn rotation_simultaneous() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+        let session_id = *a.session_id();
+        let mut a = a
+            .with_rotation(thread_rng(), &alice, bob.public_key())
+            .with_max_session_age(Duration::from_secs(3600));
+        let mut b = b
+            .with_rotation(thread_rng(), &bob, alice.public_key())
+            .with_max_session_age(Duration::from_secs(3600));
+        a.sink.established -= Duration::from_secs(3600);
+        b.sink.established -= Duration::from_secs(3600);
+
+        block_on(async {
+            let (ra, rb) = futures::join!(
+                a.send(Bytes::from_static(b"alice")),
+                b.send(Bytes::from_static(b"bob")),
+            );
+            ra.unwrap();
+            rb.unwrap();
+
+            assert_eq!(a.next().await.unwrap().unwrap().as_ref(), b"bob");
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"alice");
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_ne!(a.session_id(), &session_id);
+    }
+
+    #[test]
+    fn rotation_rejected() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let mallory = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+        let mut a = a
+            .with_rotation(thread_rng(), &alice, bob.public_key())
+            .with_max_session_age(Duration::from_secs(3600));
+        a.sink.established -= Duration::from_secs(3600);
+        // the identity of the remote peer cannot change
+        let mut b = b.with_rotation(thread_rng(), &bob, mallory.public_key());
+
+        block_on(async {
+            let (ra, rb) = futures::join!(a.send(Bytes::from_static(b"hello")), async move {
+                b.next().await.unwrap()
+                // the connection is dropped here
+            });
+            assert!(ra.is_err());
+            assert!(rb.is_err());
+        });
+    }
+
+    #[test]
+    fn keep_alive() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+        let mut a = a.with_keep_alive(Duration::from_secs(3600));
+        let mut b = b.with_idle_timeout(Duration::from_millis(100));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            assert!(!a.keep_alive().await.unwrap());
+            a.sink.set_keep_alive_interval(Some(Duration::ZERO));
+            assert!(a.keep_alive().await.unwrap());
+
+            // the keep-alive is not returned to the application
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+            assert!(b.is_alive());
+
+            let error = b.next().await.unwrap().unwrap_err();
+            assert!(error.is::<IdleTimeout>());
+            assert!(!b.is_alive());
+
+            // anything received makes the peer alive again
+            a.keep_alive().await.unwrap();
+            a.send(Bytes::from_static(b"world")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"world");
+            assert!(b.is_alive());
+        });
+    }
+
+    #[test]
+    fn keep_alive_idle() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+        let mut a = a
+            .with_keep_alive(Duration::from_millis(20))
+            .with_idle_timeout(Duration::from_millis(100));
+        let mut b = b
+            .with_keep_alive(Duration::from_millis(20))
+            .with_idle_timeout(Duration::from_millis(100));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            // both peers only wait for the data of the other one
+            let idle = Duration::from_millis(300);
+            let (ra, rb) = futures::join!(
+                tokio::time::timeout(idle, a.next()),
+                tokio::time::timeout(idle, b.next()),
+            );
+            assert!(ra.is_err());
+            assert!(rb.is_err());
+            assert!(a.is_alive());
+            assert!(b.is_alive());
+
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+        });
+    }
+
+    #[test]
+    fn keep_alive_flush() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+        let mut a = a.with_keep_alive(Duration::from_millis(20));
+        let mut b = b.with_idle_timeout(Duration::from_millis(100));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let ((), rb) = futures::join!(
+                async {
+                    // the application only flushes the connection
+                    for _ in 0..10 {
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                        a.flush().await.unwrap();
+                    }
+                },
+                tokio::time::timeout(Duration::from_millis(300), b.next()),
+            );
+            assert!(rb.is_err());
+            assert!(b.is_alive());
+        });
+    }
+
+    #[test]
+    fn xx() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let (mut a, mut b) = block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_xx(
+                    thread_rng(),
+                    &alice,
+                    |id| *id == bob.public_key(),
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .allow_xx()
+                    .accept(&bob, |id| *id == alice.public_key()),
+            );
+            (a.unwrap(), b.unwrap())
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_eq!(a.remote_public_identity(), Some(&bob.public_key()));
+        assert_eq!(b.remote_public_identity(), Some(&alice.public_key()));
+        assert!(a.remote_extensions().is_empty());
+
+        block_on(async {
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+
+            // the sessions can be rotated with the IK re-handshakes
+            let (ra, rb) = futures::join!(
+                a.rehandshake(thread_rng(), &alice, bob.public_key()),
+                b.accept_rehandshake(thread_rng(), &bob, |_| true),
+            );
+            ra.unwrap();
+            rb.unwrap();
+        });
+        assert_eq!(a.session_id(), b.session_id());
+    }
+
+    #[test]
+    fn xx_rejected() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_xx(thread_rng(), &alice, |_| false, a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .allow_xx()
+                    .accept(&bob, |_| true),
+            );
+            assert!(a.is_err());
+            assert!(b.is_err());
+        });
+    }
+
+    /// the responder does not send its public key to the unknown initiators
+    #[test]
+    fn xx_not_allowed() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_xx(thread_rng(), &alice, |_| true, a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept(&bob, |_| true),
+            );
+            assert!(a.is_err());
+            assert!(b.is_err());
+        });
+    }
+
+    #[test]
+    fn fallback() {
+        let alice = SecretKey::new(thread_rng());
+        let old = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let mut extensions = Extensions::new();
+        extensions.insert(1, b"alice".to_vec()).unwrap();
+        let mut bob_extensions = Extensions::new();
+        bob_extensions.insert(2, b"bob".to_vec()).unwrap();
+
+        let (mut a, mut b) = block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_with_fallback(
+                    thread_rng(),
+                    &alice,
+                    old.public_key(),
+                    &extensions,
+                    |id| *id == bob.public_key(),
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .allow_fallback()
+                    .accept_with_extensions(&bob, &bob_extensions, |id| {
+                        *id == alice.public_key()
+                    }),
+            );
+            (a.unwrap(), b.unwrap())
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_eq!(a.remote_public_identity(), Some(&bob.public_key()));
+        assert_eq!(b.remote_public_identity(), Some(&alice.public_key()));
+        assert_eq!(b.remote_extensions().get(1), Some(b"alice".as_ref()));
+        assert_eq!(a.remote_extensions(), &bob_extensions);
+
+        block_on(async {
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+        });
+    }
+
+    #[test]
+    fn fallback_rejected() {
+        let alice = SecretKey::new(thread_rng());
+        let old = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let extensions = Extensions::new();
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_with_fallback(
+                    thread_rng(),
+                    &alice,
+                    old.public_key(),
+                    &extensions,
+                    |_| false,
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .allow_fallback()
+                    .accept(&bob, |_| true),
+            );
+            assert!(a.is_err());
+            assert!(b.is_err());
+        });
+    }
+
+    /// the responder does not send its new public key unless allowed,
+    /// nor its extensions to an initiator it rejects
+    #[test]
+    fn fallback_not_allowed() {
+        let alice = SecretKey::new(thread_rng());
+        let old = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+
+        let mut bob_extensions = Extensions::new();
+        bob_extensions.insert(2, b"bob".to_vec()).unwrap();
+        let extensions = Extensions::new();
+
+        for allow_fallback in [false, true] {
+            let (a, b) = duplex(1024);
+            let (a_reader, a_writer) = tokio::io::split(a);
+            let (b_reader, b_writer) = tokio::io::split(b);
+            let mut accepting = Handle::accept(thread_rng(), b_reader, b_writer);
+            if allow_fallback {
+                accepting = accepting.allow_fallback();
+            }
+
+            block_on(async {
+                let (a, b) = futures::join!(
+                    Handle::open_with_fallback(
+                        thread_rng(),
+                        &alice,
+                        old.public_key(),
+                        &extensions,
+                        |_| true,
+                        a_reader,
+                        a_writer
+                    ),
+                    accepting.accept_with_extensions(&bob, &bob_extensions, |_| false),
+                );
+                assert!(a.is_err());
+                assert!(b.is_err());
+            });
         }
     }
+
+    #[test]
+    fn no_fallback() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let extensions = Extensions::new();
+        let (a, b) = block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_with_fallback(
+                    thread_rng(),
+                    &alice,
+                    bob.public_key(),
+                    &extensions,
+                    |_| false,
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept(&bob, |_| true),
+            );
+            (a.unwrap(), b.unwrap())
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+    }
+
+    #[test]
+    fn xk() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let (mut a, mut b) = block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_xk(thread_rng(), &alice, bob.public_key(), a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .accept(&bob, |id| *id == alice.public_key()),
+            );
+            (a.unwrap(), b.unwrap())
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_eq!(a.remote_public_identity(), Some(&bob.public_key()));
+        assert_eq!(b.remote_public_identity(), Some(&alice.public_key()));
+
+        block_on(async {
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+        });
+    }
+
+    #[test]
+    fn xk_rejected() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let bob_id = bob.public_key();
+        block_on(async {
+            let (a, b) = futures::join!(
+                async move {
+                    Handle::open_xk(thread_rng(), &alice, bob_id, a_reader, a_writer)
+                        .await?
+                        .next()
+                        .await
+                        .transpose()
+                },
+                Handle::accept(thread_rng(), b_reader, b_writer).accept(&bob, |_| false),
+            );
+            // the initiator sends the last message of the handshake, it only
+            // sees the connection closed by the responder
+            assert!(!matches!(a, Ok(Some(_))));
+            assert!(b.is_err());
+        });
+    }
+
+    #[test]
+    fn nk() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        let (mut a, mut b) = block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_nk::<SecretKey, _>(thread_rng(), bob.public_key(), a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .allow_anonymous()
+                    .accept(&bob, |_| false),
+            );
+            (a.unwrap(), b.unwrap())
+        });
+
+        assert_eq!(a.session_id(), b.session_id());
+        assert_eq!(a.remote_public_identity(), Some(&bob.public_key()));
+        assert_eq!(b.remote_public_identity(), None);
+
+        block_on(async {
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+
+            // the anonymous peer may authenticate later with a re-handshake
+            let (ra, rb) = futures::join!(
+                a.rehandshake(thread_rng(), &alice, bob.public_key()),
+                b.accept_rehandshake(thread_rng(), &bob, |_| true),
+            );
+            ra.unwrap();
+            rb.unwrap();
+        });
+        assert_eq!(b.remote_public_identity(), Some(&alice.public_key()));
+    }
+
+    #[test]
+    fn nk_rejected() {
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_nk::<SecretKey, _>(thread_rng(), bob.public_key(), a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept(&bob, |_| true),
+            );
+            assert!(a.is_err());
+            assert!(b.is_err());
+        });
+    }
+
+    #[test]
+    fn async_io() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt};
+
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (mut a, mut b) = connect(&alice, &bob);
+        let data: Vec<u8> = (0..MAX_PAYLOAD_LENGTH + 1024).map(|i| i as u8).collect();
+
+        block_on(async {
+            let write = async {
+                a.write_all(&data).await.unwrap();
+                a.send(Bytes::from_static(b"frame")).await.unwrap();
+                AsyncWriteExt::shutdown(&mut a).await.unwrap();
+            };
+            let read = async {
+                let mut received = vec![0; data.len() + 5];
+                for chunk in received.chunks_mut(1000) {
+                    b.read_exact(chunk).await.unwrap();
+                }
+                assert_eq!(&received[..data.len()], data.as_slice());
+                assert_eq!(&received[data.len()..], b"frame");
+
+                assert_eq!(b.read(&mut [0; 8]).await.unwrap(), 0);
+            };
+            futures::join!(write, read);
+        });
+    }
+
+    #[test]
+    fn shutdown() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (mut a, mut b) = connect(&alice, &bob);
+
+        block_on(async {
+            let close = async {
+                a.send(Bytes::from_static(b"hello")).await.unwrap();
+                a.shutdown().await.unwrap();
+                assert!(a.send(Bytes::from_static(b"world")).await.is_err());
+            };
+            let receive = async {
+                b.send(Bytes::from_static(b"world")).await.unwrap();
+                assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+                assert!(b.next().await.is_none());
+                assert!(b.remote_closed());
+                b.shutdown().await.unwrap();
+            };
+            futures::join!(close, receive);
+
+            // received while closing
+            assert!(a.remote_closed());
+            assert_eq!(a.next().await.unwrap().unwrap().as_ref(), b"world");
+            assert!(a.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn truncated() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (mut a, mut b) = connect(&alice, &bob);
+
+        block_on(async {
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            drop(a);
+
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+            let error = b.next().await.unwrap().unwrap_err();
+            assert!(error.is::<ConnectionTruncated>());
+            assert!(!b.remote_closed());
+            assert!(b.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn connect_config() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+        let config = ConnectConfig {
+            handshake_timeout: Some(Duration::from_secs(5)),
+            read_timeout: Some(Duration::from_millis(100)),
+            write_timeout: Some(Duration::from_millis(100)),
+            max_message_size: 16,
+            ..ConnectConfig::default()
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let extensions = Extensions::new();
+            let (a, b) = futures::join!(
+                Handle::open_with_config(
+                    thread_rng(),
+                    &alice,
+                    bob.public_key(),
+                    &extensions,
+                    &config,
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .with_config(config)
+                    .accept(&bob, |_| true),
+            );
+            let (mut a, mut b) = (a.unwrap(), b.unwrap());
+
+            assert!(a.send_frame(vec![0; 17]).await.is_err());
+            a.send_frame(vec![0; 16]).await.unwrap();
+            assert_eq!(b.recv_frame().await.unwrap().unwrap().len(), 16);
+
+            // nothing is received
+            let error = a.next().await.unwrap().unwrap_err();
+            assert!(error.is::<IdleTimeout>());
+
+            // the remote peer does not read
+            let error = loop {
+                if let Err(error) = a.send(Bytes::from(vec![0; 512])).await {
+                    break error;
+                }
+            };
+            let error = error.downcast::<io::Error>().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        });
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let config = ConnectConfig {
+            handshake_timeout: Some(Duration::from_millis(100)),
+            ..ConnectConfig::default()
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            // the responder does not reply
+            let (a, _b) = duplex(1024);
+            let (a_reader, a_writer) = tokio::io::split(a);
+            let opened = Handle::open_with_config(
+                thread_rng(),
+                &alice,
+                bob.public_key(),
+                &Extensions::new(),
+                &config,
+                a_reader,
+                a_writer,
+            )
+            .await;
+            assert!(opened.is_err());
+
+            let (a, _b) = duplex(1024);
+            let (a_reader, a_writer) = tokio::io::split(a);
+            let opened = Handle::open_with_fallback_and_config(
+                thread_rng(),
+                &alice,
+                bob.public_key(),
+                &Extensions::new(),
+                &config,
+                |_| true,
+                a_reader,
+                a_writer,
+            )
+            .await;
+            assert!(opened.is_err());
+
+            let (a, _b) = duplex(1024);
+            let (a_reader, a_writer) = tokio::io::split(a);
+            let opened = Handle::open_xx_with_config(
+                thread_rng(),
+                &alice,
+                &config,
+                |_| true,
+                a_reader,
+                a_writer,
+            )
+            .await;
+            assert!(opened.is_err());
+
+            let (a, _b) = duplex(1024);
+            let (a_reader, a_writer) = tokio::io::split(a);
+            let opened = Handle::open_xk_with_config(
+                thread_rng(),
+                &alice,
+                bob.public_key(),
+                &config,
+                a_reader,
+                a_writer,
+            )
+            .await;
+            assert!(opened.is_err());
+
+            let (a, _b) = duplex(1024);
+            let (a_reader, a_writer) = tokio::io::split(a);
+            let opened = Handle::open_nk_with_config::<SecretKey, _>(
+                thread_rng(),
+                bob.public_key(),
+                &config,
+                a_reader,
+                a_writer,
+            )
+            .await;
+            assert!(opened.is_err());
+
+            // the initiator does not send anything
+            let (b, _a) = duplex(1024);
+            let (b_reader, b_writer) = tokio::io::split(b);
+            let accepted = Handle::accept(thread_rng(), b_reader, b_writer)
+                .with_config(config)
+                .accept(&bob, |_| true)
+                .await;
+            assert!(accepted.is_err());
+        });
+    }
+
+    #[test]
+    fn version() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect(&alice, &bob);
+
+        assert_eq!(a.version(), Version::CURRENT);
+        assert_eq!(b.version(), Version::CURRENT);
+    }
+
+    fn connect_with_policies(
+        alice: &SecretKey,
+        bob: &SecretKey,
+        alice_policy: Option<RekeyPolicy>,
+        bob_policy: Option<RekeyPolicy>,
+    ) -> (Result<TestHandle>, Result<TestHandle>) {
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+        let alice_config = ConnectConfig {
+            rekey_policy: alice_policy,
+            ..ConnectConfig::default()
+        };
+        let bob_config = ConnectConfig {
+            rekey_policy: bob_policy,
+            ..ConnectConfig::default()
+        };
+        let extensions = Extensions::new();
+
+        block_on(async {
+            futures::join!(
+                Handle::open_with_config(
+                    thread_rng(),
+                    alice,
+                    bob.public_key(),
+                    &extensions,
+                    &alice_config,
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer)
+                    .with_config(bob_config)
+                    .accept(bob, |_| true),
+            )
+        })
+    }
+
+    #[test]
+    fn rekey_policy() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let policy = Some(RekeyPolicy::Messages(3));
+        let (a, b) = connect_with_policies(&alice, &bob, policy, policy);
+        let (mut a, mut b) = (a.unwrap(), b.unwrap());
+
+        assert_eq!(
+            a.remote_extensions().get(Extensions::REKEY_POLICY),
+            Some(RekeyPolicy::Messages(3).to_bytes().as_ref())
+        );
+
+        block_on(async {
+            for _ in 0..2 {
+                for i in 0..5u8 {
+                    a.send(Bytes::from(vec![i])).await.unwrap();
+                    assert_eq!(b.next().await.unwrap().unwrap().as_ref(), &[i]);
+                    b.send(Bytes::from(vec![i])).await.unwrap();
+                    assert_eq!(a.next().await.unwrap().unwrap().as_ref(), &[i]);
+                }
+
+                // the new sessions keep the policy
+                let (ra, rb) = futures::join!(
+                    a.rehandshake(thread_rng(), &alice, bob.public_key()),
+                    b.accept_rehandshake(thread_rng(), &bob, |_| true),
+                );
+                ra.unwrap();
+                rb.unwrap();
+            }
+        });
+    }
+
+    /// the peers using different rekey policies cannot connect
+    #[test]
+    fn rekey_policy_mismatch() {
+        use keynesis_core::noise::RekeyPolicyError;
+
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = connect_with_policies(
+            &alice,
+            &bob,
+            Some(RekeyPolicy::Messages(3)),
+            Some(RekeyPolicy::Never),
+        );
+
+        assert!(a.is_err());
+        assert!(matches!(
+            b.err().unwrap().downcast_ref::<RekeyPolicyError>(),
+            Some(RekeyPolicyError::Mismatch {
+                local: RekeyPolicy::Never,
+                remote: RekeyPolicy::Messages(3),
+            })
+        ));
+
+        // the remote peer does not send its policy
+        let (a, b) = connect_with_policies(&alice, &bob, Some(RekeyPolicy::Never), None);
+        assert!(b.is_ok());
+        assert!(a
+            .err()
+            .unwrap()
+            .downcast_ref::<RekeyPolicyError>()
+            .is_some());
+    }
+
+    /// a peer advertising an older version than ours still connects
+    /// and re-handshakes with it
+    #[test]
+    fn older_version() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+        let config = ConnectConfig {
+            versions: VersionRange::new(Version::V2, Version::V2).unwrap(),
+            ..ConnectConfig::default()
+        };
+
+        let mut alice_extensions = Extensions::new();
+        alice_extensions.insert(1, b"alpn".as_ref()).unwrap();
+        let mut bob_extensions = Extensions::new();
+        bob_extensions.insert(2, b"bob".as_ref()).unwrap();
+
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open_with_config(
+                    thread_rng(),
+                    &alice,
+                    bob.public_key(),
+                    &alice_extensions,
+                    &config,
+                    a_reader,
+                    a_writer
+                ),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept_with_extensions(
+                    &bob,
+                    &bob_extensions,
+                    |_| true
+                ),
+            );
+            let (mut a, mut b) = (a.unwrap(), b.unwrap());
+
+            assert_eq!(a.version(), Version::V2);
+            assert_eq!(b.version(), Version::V2);
+            assert_eq!(a.remote_extensions(), &bob_extensions);
+            assert_eq!(b.remote_extensions(), &alice_extensions);
+
+            let (ra, rb) = futures::join!(
+                a.rehandshake(thread_rng(), &alice, bob.public_key()),
+                b.accept_rehandshake(thread_rng(), &bob, |_| true),
+            );
+            ra.unwrap();
+            rb.unwrap();
+
+            a.send(Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+            assert_eq!(b.version(), Version::V2);
+        });
+    }
+
+    #[test]
+    fn tampered_versions() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a_writer, mut relay_reader) = duplex(8192);
+        let (mut relay_writer, b_reader) = duplex(8192);
+        let (b_writer, a_reader) = duplex(8192);
+
+        block_on(async {
+            // a man in the middle removes the older versions advertised
+            // by the initiator, the responder still finds a version in common
+            let relay = async move {
+                let mut header = [0; 4];
+                relay_reader.read_exact(&mut header).await.unwrap();
+                let mut message = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize];
+                relay_reader.read_exact(&mut message).await.unwrap();
+
+                header[1] = Version::V3.to_u8();
+                relay_writer.write_all(&header).await.unwrap();
+                relay_writer.write_all(&message).await.unwrap();
+            };
+
+            let (a, b, ()) = futures::join!(
+                Handle::open(thread_rng(), &alice, bob.public_key(), a_reader, a_writer),
+                Handle::accept::<SecretKey, _>(thread_rng(), b_reader, b_writer)
+                    .accept(&bob, |_| true),
+                relay,
+            );
+            assert!(a.is_err());
+            assert!(b.is_err());
+        });
+    }
+
+    #[test]
+    fn frames() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (mut a, mut b) = connect(&alice, &bob);
+        let large = Bytes::from(vec![0xAB; MAX_PAYLOAD_LENGTH * 3]);
+
+        block_on(async {
+            let send = async {
+                a.send_frame(Bytes::from_static(b"small")).await.unwrap();
+                a.send_frame(large.clone()).await.unwrap();
+                a.send_frame(Bytes::new()).await.unwrap();
+                a.close().await.unwrap();
+            };
+            let receive = async {
+                assert_eq!(b.recv_frame().await.unwrap().unwrap().as_ref(), b"small");
+                assert_eq!(b.recv_frame().await.unwrap().unwrap(), large);
+                assert!(b.recv_frame().await.unwrap().unwrap().is_empty());
+                assert!(b.recv_frame().await.unwrap().is_none());
+            };
+            futures::join!(send, receive);
+
+            let too_large = vec![0; FramedMessage::MAX_MESSAGE_SIZE + 1];
+            assert!(b.send_frame(too_large).await.is_err());
+        });
+    }
 }
@@ -11,10 +11,10 @@ The protocol is rather simple:
    After each messages the key is being rotated (see Noise's transport state _rekey_
    function).
 3. Only 2 types of messages are allowed to transport between the nodes:
-   a. [`Gossip`] which are information about other peers in the network and their
-      subscriptions (see [`poldercast`])
-   b. [`Topic`] based message: messages that are associated with a 32bytes topic
-      code.
+   * [`Gossip`] which are information about other peers in the network and their
+     subscriptions (see [`poldercast`])
+   * [`Topic`] based message: messages that are associated with a 32bytes topic
+     code.
 
 This crates only implements the network part of ASMTP.
 
@@ -34,10 +34,32 @@ ASMTP will use the [`Topic`] to relay messages to the appropriate peers.
 
 mod accept;
 mod codec;
+mod config;
+pub mod datagram;
+pub mod dns;
+mod extensions;
 mod handle;
+pub mod mux;
 pub mod net;
+pub mod obfuscation;
 mod opening;
+pub mod prekey;
 mod session_id;
+pub mod ticket;
+#[cfg(unix)]
+pub mod unix;
 mod version;
+pub mod websocket;
 
-pub use self::{accept::Accepting, handle::Handle, session_id::SessionId, version::Version};
+pub use self::{
+    accept::Accepting,
+    codec::frame::FramedMessage,
+    config::ConnectConfig,
+    extensions::{ExtensionType, Extensions},
+    handle::{
+        ConnectionTruncated, Handle, IdleTimeout, RehandshakeRequested,
+        DEFAULT_MIN_REMAINING_MESSAGES,
+    },
+    session_id::SessionId,
+    version::{Version, VersionRange},
+};
@@ -0,0 +1,1035 @@
+/*!
+# Stream multiplexing
+
+Run many logical byte streams over 1 encrypted [`Handle`] instead of
+opening a new connection (and performing a new handshake) for every
+channel of the application.
+
+* the [`Mux`] owns the [`Handle`] and is the [`Future`] that drives the
+  connection: it needs to be polled (spawned) for the streams to make
+  progress. It completes once the connection is closed;
+* the [`MuxControl`] opens new streams and accepts the streams opened by
+  the remote peer;
+* the [`MuxStream`] is a logical stream, it implements [`AsyncRead`] and
+  [`AsyncWrite`].
+
+While it drives the connection, the [`Mux`] sends the keep-alives of the
+[`Handle`] (see [`Handle::with_keep_alive`]). With [`Mux::with_rotation`]
+the sessions are rotated without interrupting the streams.
+
+```no_run
+# async fn run<I, O>(handle: keynesis_network::Handle<I, O>) -> anyhow::Result<()>
+# where I: tokio::io::AsyncRead + Unpin + Send + 'static,
+#       O: tokio::io::AsyncWrite + Unpin + Send + 'static {
+use keynesis_network::mux::Mux;
+use tokio::io::AsyncWriteExt as _;
+
+let mux = Mux::new(handle);
+let control = mux.control();
+tokio::spawn(mux);
+
+let mut stream = control.open()?;
+stream.write_all(b"hello").await?;
+stream.shutdown().await?;
+
+while let Some(stream) = control.accept().await {
+    // ...
+}
+# Ok(())
+# }
+```
+
+## Protocol
+
+Every frame of the multiplexer is sent in 1 data frame of the [`Handle`]:
+
+```text
++------+-----------+---------+
+| kind | stream id | payload |
++------+-----------+---------+
+   1B       4B
+```
+
+The high bit of the `kind` is set on the frames sent by the peer that
+opened the stream, so the 2 peers can allocate the stream ids
+independently.
+
+* `Open` (0): open a new stream;
+* `Data` (1): the payload is the data of the stream;
+* `Window` (2): the payload is the big endian `u32` number of bytes the
+  sender of the frame is ready to receive more;
+* `Close` (3): the sender of the frame will not write on the stream
+  anymore;
+* `Reset` (4): the stream is aborted.
+
+Each direction of a stream starts with a window of [`INITIAL_WINDOW`]
+bytes. The writer stops once the window is used and waits for the reader
+to consume the data (and to send a `Window` frame).
+
+[`Handle`]: crate::Handle
+*/
+
+use crate::{codec::encryption::MAX_PAYLOAD_LENGTH, Handle};
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use bytes::{Buf as _, BufMut as _, Bytes, BytesMut};
+use futures::{future, ready, Sink, Stream};
+use keynesis_core::key::{ed25519::PublicKey, Dh};
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// number of bytes each side of a stream can send before waiting for the
+/// remote peer to consume them
+pub const INITIAL_WINDOW: u32 = 256 * 1024;
+
+/// default maximum number of streams opened by the remote peer at the
+/// same time, see [`Mux::with_max_streams`]
+pub const DEFAULT_MAX_STREAMS: usize = 256;
+
+/// maximum number of streams opened by the remote peer waiting to be
+/// reset, the remote peer opening more streams than that while the resets
+/// are not sent yet fails the connection
+const MAX_REFUSED_STREAMS: usize = 256;
+
+const HEADER_LENGTH: usize = 1 + 4;
+const MAX_DATA_LENGTH: usize = MAX_PAYLOAD_LENGTH - HEADER_LENGTH;
+const OPENER: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Open = 0,
+    Data = 1,
+    Window = 2,
+    Close = 3,
+    Reset = 4,
+}
+
+struct Frame {
+    kind: Kind,
+    /// `true` if the sender of the frame opened the stream
+    opener: bool,
+    id: u32,
+    payload: Bytes,
+}
+
+/// the streams are identified by their id and by which peer opened it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    id: u32,
+    local: bool,
+}
+
+/// drive the multiplexed connection
+///
+/// see the [module documentation](self)
+pub struct Mux<I, O> {
+    handle: Handle<I, O>,
+    shared: Arc<Mutex<Shared>>,
+    sink_closed: bool,
+}
+
+/// open and accept the streams of a [`Mux`]
+#[derive(Clone)]
+pub struct MuxControl {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// a logical stream of a [`Mux`]
+///
+/// dropping the stream before both sides have closed it resets it.
+pub struct MuxStream {
+    key: Key,
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    streams: HashMap<Key, State>,
+    /// the streams opened by the remote peer not accepted yet
+    incoming: VecDeque<u32>,
+    /// the streams opened by the remote peer beyond the `max_streams` to
+    /// reset, at most [`MAX_REFUSED_STREAMS`]
+    refused: VecDeque<u32>,
+    /// the frames to be sent by the [`Mux`]
+    outgoing: VecDeque<Frame>,
+    next_id: u32,
+    max_streams: usize,
+    /// no new stream can be opened, the connection is closing
+    closing: bool,
+    /// the connection is closed
+    closed: bool,
+    driver: Option<Waker>,
+    accept: Option<Waker>,
+}
+
+struct State {
+    /// data received not read yet
+    received: BytesMut,
+    /// number of bytes the remote peer can still send
+    recv_window: u32,
+    /// number of bytes read since the last `Window` frame
+    consumed: u32,
+    /// number of bytes that can still be sent
+    send_window: u32,
+    local_closed: bool,
+    remote_closed: bool,
+    reset: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl Kind {
+    fn from_u8(kind: u8) -> Result<Self> {
+        match kind {
+            0 => Ok(Self::Open),
+            1 => Ok(Self::Data),
+            2 => Ok(Self::Window),
+            3 => Ok(Self::Close),
+            4 => Ok(Self::Reset),
+            kind => bail!("Unknown multiplexed frame kind ({})", kind),
+        }
+    }
+}
+
+impl Frame {
+    fn new(kind: Kind, key: Key, payload: Bytes) -> Self {
+        Self {
+            kind,
+            opener: key.local,
+            id: key.id,
+            payload,
+        }
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        let mut bytes = BytesMut::with_capacity(HEADER_LENGTH + self.payload.len());
+        let opener = if self.opener { OPENER } else { 0 };
+        bytes.put_u8(self.kind as u8 | opener);
+        bytes.put_u32(self.id);
+        bytes.put_slice(&self.payload);
+        bytes.freeze()
+    }
+
+    fn from_bytes(mut bytes: BytesMut) -> Result<Self> {
+        ensure!(
+            bytes.len() >= HEADER_LENGTH,
+            "Multiplexed frame is too short"
+        );
+
+        let kind = bytes.get_u8();
+        let id = bytes.get_u32();
+
+        Ok(Self {
+            kind: Kind::from_u8(kind & !OPENER)?,
+            opener: kind & OPENER == OPENER,
+            id,
+            payload: bytes.freeze(),
+        })
+    }
+
+    /// the key of the stream, from the point of view of the receiver
+    fn key(&self) -> Key {
+        Key {
+            id: self.id,
+            local: !self.opener,
+        }
+    }
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            received: BytesMut::new(),
+            recv_window: INITIAL_WINDOW,
+            consumed: 0,
+            send_window: INITIAL_WINDOW,
+            local_closed: false,
+            remote_closed: false,
+            reset: false,
+            reader: None,
+            writer: None,
+        }
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.writer.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Shared {
+    fn new() -> Self {
+        Self {
+            streams: HashMap::new(),
+            incoming: VecDeque::new(),
+            refused: VecDeque::new(),
+            outgoing: VecDeque::new(),
+            next_id: 0,
+            max_streams: DEFAULT_MAX_STREAMS,
+            closing: false,
+            closed: false,
+            driver: None,
+            accept: None,
+        }
+    }
+
+    fn send(&mut self, frame: Frame) {
+        self.outgoing.push_back(frame);
+        self.wake_driver();
+    }
+
+    /// the next frame to send, the refused streams are reset first
+    fn next_outgoing(&mut self) -> Option<Frame> {
+        if let Some(id) = self.refused.pop_front() {
+            let key = Key { id, local: false };
+            return Some(Frame::new(Kind::Reset, key, Bytes::new()));
+        }
+        self.outgoing.pop_front()
+    }
+
+    fn wake_driver(&mut self) {
+        if let Some(waker) = self.driver.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_accept(&mut self) {
+        if let Some(waker) = self.accept.take() {
+            waker.wake();
+        }
+    }
+
+    /// the connection is gone, wake up everyone waiting on it
+    fn terminate(&mut self) {
+        self.closing = true;
+        self.closed = true;
+        self.refused.clear();
+        self.outgoing.clear();
+        self.wake_accept();
+        for state in self.streams.values_mut() {
+            state.wake();
+        }
+    }
+
+    fn receive(&mut self, frame: Frame) -> Result<()> {
+        let key = frame.key();
+
+        if frame.kind == Kind::Open {
+            ensure!(frame.opener, "Stream opened by the wrong peer");
+            ensure!(
+                !self.streams.contains_key(&key),
+                "Stream {} opened twice",
+                key.id
+            );
+
+            let remote_streams = self.streams.keys().filter(|key| !key.local).count();
+            if self.closing || remote_streams >= self.max_streams {
+                ensure!(
+                    self.refused.len() < MAX_REFUSED_STREAMS,
+                    "Remote peer opened too many streams"
+                );
+                self.refused.push_back(key.id);
+                self.wake_driver();
+            } else {
+                self.streams.insert(key, State::new());
+                self.incoming.push_back(key.id);
+                self.wake_accept();
+            }
+            return Ok(());
+        }
+
+        // the stream might have been dropped already, the frames the
+        // remote peer sent in the meantime are ignored
+        let state = if let Some(state) = self.streams.get_mut(&key) {
+            state
+        } else {
+            return Ok(());
+        };
+
+        match frame.kind {
+            Kind::Open => unreachable!("the open frames are handled above"),
+            Kind::Data => {
+                ensure!(!state.remote_closed, "Data received on a closed stream");
+                let len = frame.payload.len() as u32;
+                state.recv_window = state
+                    .recv_window
+                    .checked_sub(len)
+                    .ok_or_else(|| anyhow!("Remote peer exceeded the stream's window"))?;
+                state.received.extend_from_slice(&frame.payload);
+            }
+            Kind::Window => {
+                ensure!(frame.payload.len() == 4, "Invalid window update");
+                let increment = (&frame.payload[..]).get_u32();
+                state.send_window = state
+                    .send_window
+                    .checked_add(increment)
+                    .ok_or_else(|| anyhow!("Remote peer overflowed the stream's window"))?;
+            }
+            Kind::Close => state.remote_closed = true,
+            Kind::Reset => state.reset = true,
+        }
+        state.wake();
+
+        Ok(())
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/* Mux ********************************************************************* */
+
+impl<I, O> Mux<I, O>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    pub fn new(handle: Handle<I, O>) -> Self {
+        Self {
+            handle,
+            shared: Arc::new(Mutex::new(Shared::new())),
+            sink_closed: false,
+        }
+    }
+
+    /// set the maximum number of streams the remote peer can have opened
+    /// at the same time (see [`DEFAULT_MAX_STREAMS`])
+    ///
+    /// the streams opened beyond this limit are reset.
+    pub fn with_max_streams(self, max_streams: usize) -> Self {
+        lock(&self.shared).max_streams = max_streams;
+        self
+    }
+
+    /// rotate the sessions of the connection, see [`Handle::with_rotation`]
+    ///
+    /// the re-handshakes requested by the remote peer are answered
+    /// while the [`Mux`] drives the connection. Without the rotation, the
+    /// first re-handshake requested by the remote peer fails the
+    /// connection.
+    pub fn with_rotation<K, RNG>(mut self, rng: RNG, k: &K, rs: PublicKey) -> Self
+    where
+        K: Dh + Clone + Send + 'static,
+        RNG: RngCore + CryptoRng,
+    {
+        self.handle = self.handle.with_rotation(rng, k, rs);
+        self
+    }
+
+    /// the [`Handle`] of the multiplexed connection
+    pub fn handle(&self) -> &Handle<I, O> {
+        &self.handle
+    }
+
+    /// the [`MuxControl`] to open and accept the streams
+    pub fn control(&self) -> MuxControl {
+        MuxControl {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// send the pending frames to the remote peer
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.sink_closed {
+            let mut shared = lock(&self.shared);
+            shared.refused.clear();
+            shared.outgoing.clear();
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            ready!(Pin::new(&mut self.handle).poll_ready(cx))?;
+
+            let frame = lock(&self.shared).next_outgoing();
+            match frame {
+                Some(frame) => Pin::new(&mut self.handle).start_send(frame.to_bytes())?,
+                None => break,
+            }
+        }
+
+        ready!(Sink::poll_flush(Pin::new(&mut self.handle), cx))?;
+
+        if lock(&self.shared).closing && !self.sink_closed {
+            ready!(Pin::new(&mut self.handle).poll_close(cx))?;
+            self.sink_closed = true;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_run(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        loop {
+            lock(&self.shared).driver = Some(cx.waker().clone());
+
+            if let Poll::Ready(Err(error)) = self.poll_send(cx) {
+                return Poll::Ready(Err(error));
+            }
+
+            match ready!(Pin::new(&mut self.handle).poll_next(cx)) {
+                Some(message) => {
+                    let message = message.context("Cannot receive the multiplexed frames")?;
+                    let frame = Frame::from_bytes(message)?;
+                    lock(&self.shared).receive(frame)?;
+                }
+                None => {
+                    lock(&self.shared).terminate();
+                    // the remote peer is gone, failing to close our side
+                    // of the connection is not relevant anymore
+                    if !self.sink_closed {
+                        let _ = ready!(Pin::new(&mut self.handle).poll_close(cx));
+                        self.sink_closed = true;
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+impl<I, O> Future for Mux<I, O>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let result = ready!(this.poll_run(cx));
+
+        lock(&this.shared).terminate();
+        Poll::Ready(result)
+    }
+}
+
+/* Control ***************************************************************** */
+
+impl MuxControl {
+    /// open a new stream
+    ///
+    /// the remote peer receives it with [`MuxControl::accept`]
+    pub fn open(&self) -> Result<MuxStream> {
+        let mut shared = lock(&self.shared);
+        ensure!(!shared.closing, "The multiplexed connection is closed");
+
+        let id = shared.next_id;
+        shared.next_id = id
+            .checked_add(1)
+            .context("No more stream id available on the connection")?;
+
+        let key = Key { id, local: true };
+        shared.streams.insert(key, State::new());
+        shared.send(Frame::new(Kind::Open, key, Bytes::new()));
+
+        Ok(MuxStream {
+            key,
+            shared: Arc::clone(&self.shared),
+        })
+    }
+
+    /// wait for the next stream opened by the remote peer
+    ///
+    /// returns `None` once the connection is closed
+    pub async fn accept(&self) -> Option<MuxStream> {
+        future::poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Option<MuxStream>> {
+        let mut shared = lock(&self.shared);
+
+        if let Some(id) = shared.incoming.pop_front() {
+            return Poll::Ready(Some(MuxStream {
+                key: Key { id, local: false },
+                shared: Arc::clone(&self.shared),
+            }));
+        }
+
+        if shared.closed {
+            return Poll::Ready(None);
+        }
+
+        shared.accept = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// close the connection
+    ///
+    /// no new stream can be opened or accepted, the streams opened by
+    /// the remote peer and not accepted yet are reset. The [`Mux`] sends
+    /// the pending frames, closes the writing side of the [`Handle`] and
+    /// completes once the remote peer closes the connection too.
+    pub fn close(&self) {
+        let mut shared = lock(&self.shared);
+        shared.closing = true;
+        while let Some(id) = shared.incoming.pop_front() {
+            let key = Key { id, local: false };
+            shared.streams.remove(&key);
+            shared.send(Frame::new(Kind::Reset, key, Bytes::new()));
+        }
+        shared.wake_accept();
+        shared.wake_driver();
+    }
+}
+
+impl Stream for MuxControl {
+    type Item = MuxStream;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_accept(cx)
+    }
+}
+
+/* Stream ****************************************************************** */
+
+impl MuxStream {
+    /// the identifier of the stream, it is unique among the streams
+    /// opened by the same peer
+    pub fn id(&self) -> u32 {
+        self.key.id
+    }
+
+    /// `true` if the stream has been opened by the local peer
+    pub fn is_local(&self) -> bool {
+        self.key.local
+    }
+}
+
+impl AsyncRead for MuxStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut shared = lock(&this.shared);
+        let closed = shared.closed;
+        let state = shared
+            .streams
+            .get_mut(&this.key)
+            .expect("the stream's state lives as long as the stream");
+
+        if !state.received.is_empty() {
+            let len = state.received.len().min(buf.remaining());
+            buf.put_slice(&state.received.split_to(len));
+
+            state.consumed += len as u32;
+            if state.consumed >= INITIAL_WINDOW / 2 && !state.remote_closed {
+                let increment = std::mem::replace(&mut state.consumed, 0);
+                state.recv_window += increment;
+                let payload = Bytes::copy_from_slice(&increment.to_be_bytes());
+                shared.send(Frame::new(Kind::Window, this.key, payload));
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        if state.reset {
+            Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        } else if state.remote_closed {
+            Poll::Ready(Ok(()))
+        } else if closed {
+            Poll::Ready(Err(io::ErrorKind::ConnectionAborted.into()))
+        } else {
+            state.reader = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let mut shared = lock(&this.shared);
+        let closing = shared.closing;
+        let state = shared
+            .streams
+            .get_mut(&this.key)
+            .expect("the stream's state lives as long as the stream");
+
+        if state.reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        if state.local_closed || closing {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if state.send_window == 0 {
+            state.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = buf
+            .len()
+            .min(MAX_DATA_LENGTH)
+            .min(state.send_window as usize);
+        state.send_window -= len as u32;
+
+        let payload = Bytes::copy_from_slice(&buf[..len]);
+        shared.send(Frame::new(Kind::Data, this.key, payload));
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // the frames are sent by the `Mux`
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut shared = lock(&this.shared);
+        let state = shared
+            .streams
+            .get_mut(&this.key)
+            .expect("the stream's state lives as long as the stream");
+
+        if !state.local_closed && !state.reset {
+            state.local_closed = true;
+            shared.send(Frame::new(Kind::Close, this.key, Bytes::new()));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        let mut shared = lock(&self.shared);
+
+        if let Some(state) = shared.streams.remove(&self.key) {
+            let done = state.reset || (state.local_closed && state.remote_closed);
+            if !done && !shared.closed {
+                shared.send(Frame::new(Kind::Reset, self.key, Bytes::new()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+    use std::time::Duration;
+    use tokio::io::{duplex, AsyncReadExt as _, AsyncWriteExt as _, DuplexStream};
+
+    type TestMux = Mux<tokio::io::ReadHalf<DuplexStream>, tokio::io::WriteHalf<DuplexStream>>;
+
+    type TestHandle = Handle<tokio::io::ReadHalf<DuplexStream>, tokio::io::WriteHalf<DuplexStream>>;
+
+    fn connect() -> (TestMux, TestMux) {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = handles(&alice, &bob);
+        (Mux::new(a), Mux::new(b))
+    }
+
+    fn handles(alice: &SecretKey, bob: &SecretKey) -> (TestHandle, TestHandle) {
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open(thread_rng(), alice, bob.public_key(), a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept(bob, |_| true),
+            );
+            (a.unwrap(), b.unwrap())
+        })
+    }
+
+    #[test]
+    fn open_accept() {
+        let (a, b) = connect();
+        let (a_control, b_control) = (a.control(), b.control());
+
+        let application = async {
+            // both peers open their first stream with the same id
+            let mut a_stream = a_control.open().unwrap();
+            let mut b_stream = b_control.open().unwrap();
+            assert_eq!(a_stream.id(), b_stream.id());
+
+            a_stream.write_all(b"hello").await.unwrap();
+            a_stream.shutdown().await.unwrap();
+            b_stream.write_all(b"world").await.unwrap();
+            b_stream.shutdown().await.unwrap();
+
+            let mut accepted = b_control.accept().await.unwrap();
+            assert!(!accepted.is_local());
+            let mut message = Vec::new();
+            accepted.read_to_end(&mut message).await.unwrap();
+            assert_eq!(message, b"hello");
+            accepted.write_all(b"bye").await.unwrap();
+            accepted.shutdown().await.unwrap();
+
+            let mut message = Vec::new();
+            a_stream.read_to_end(&mut message).await.unwrap();
+            assert_eq!(message, b"bye");
+
+            let mut accepted = a_control.accept().await.unwrap();
+            let mut message = Vec::new();
+            accepted.read_to_end(&mut message).await.unwrap();
+            assert_eq!(message, b"world");
+
+            a_control.close();
+            assert!(a_control.open().is_err());
+        };
+
+        block_on(async {
+            let (a, b, ()) = futures::join!(a, b, application);
+            a.unwrap();
+            b.unwrap();
+        });
+        assert!(block_on(b_control.accept()).is_none());
+    }
+
+    #[test]
+    fn flow_control() {
+        let (a, b) = connect();
+        let (a_control, b_control) = (a.control(), b.control());
+        let data = vec![42; 4 * INITIAL_WINDOW as usize];
+
+        let application = async {
+            let mut sender = a_control.open().unwrap();
+            let send = async {
+                sender.write_all(&data).await.unwrap();
+                sender.shutdown().await.unwrap();
+            };
+            let receive = async {
+                let mut receiver = b_control.accept().await.unwrap();
+                let mut received = Vec::new();
+                receiver.read_to_end(&mut received).await.unwrap();
+                received
+            };
+            let ((), received) = futures::join!(send, receive);
+            assert!(received == data);
+
+            a_control.close();
+        };
+
+        block_on(async {
+            let (a, b, ()) = futures::join!(a, b, application);
+            a.unwrap();
+            b.unwrap();
+        });
+    }
+
+    #[test]
+    fn rotation() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = handles(&alice, &bob);
+        let session_id = *a.session_id();
+        // rotate the sessions every few frames, in both directions
+        let mut a = Mux::new(a.with_min_remaining_messages(u64::MAX - 4)).with_rotation(
+            thread_rng(),
+            &alice,
+            bob.public_key(),
+        );
+        let mut b = Mux::new(b.with_min_remaining_messages(u64::MAX - 4)).with_rotation(
+            thread_rng(),
+            &bob,
+            alice.public_key(),
+        );
+        let (a_control, b_control) = (a.control(), b.control());
+        let data = vec![42; 2 * INITIAL_WINDOW as usize];
+
+        let application = async {
+            let mut sender = a_control.open().unwrap();
+            let send = async {
+                sender.write_all(&data).await.unwrap();
+                sender.shutdown().await.unwrap();
+            };
+            let receive = async {
+                let mut receiver = b_control.accept().await.unwrap();
+                let mut received = Vec::new();
+                receiver.read_to_end(&mut received).await.unwrap();
+                received
+            };
+            let ((), received) = futures::join!(send, receive);
+            assert!(received == data);
+
+            a_control.close();
+        };
+
+        block_on(async {
+            let (ra, rb, ()) = futures::join!(&mut a, &mut b, application);
+            ra.unwrap();
+            rb.unwrap();
+        });
+
+        assert_eq!(a.handle().session_id(), b.handle().session_id());
+        assert_ne!(a.handle().session_id(), &session_id);
+    }
+
+    #[test]
+    fn keep_alive() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let (a, b) = handles(&alice, &bob);
+        let keep_alive = Duration::from_millis(20);
+        let idle_timeout = Duration::from_millis(100);
+        let a = Mux::new(
+            a.with_keep_alive(keep_alive)
+                .with_idle_timeout(idle_timeout),
+        );
+        let b = Mux::new(
+            b.with_keep_alive(keep_alive)
+                .with_idle_timeout(idle_timeout),
+        );
+        let (a_control, b_control) = (a.control(), b.control());
+
+        let application = async {
+            let mut stream = a_control.open().unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            let mut accepted = b_control.accept().await.unwrap();
+
+            // the connection stays idle for longer than the idle timeout
+            tokio::time::sleep(3 * idle_timeout).await;
+
+            stream.write_all(b" world").await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut message = Vec::new();
+            accepted.read_to_end(&mut message).await.unwrap();
+            assert_eq!(message, b"hello world");
+
+            a_control.close();
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (a, b, ()) = futures::join!(a, b, application);
+            a.unwrap();
+            b.unwrap();
+        });
+    }
+
+    #[test]
+    fn reset() {
+        let (a, b) = connect();
+        let (a_control, b_control) = (a.control(), b.control());
+
+        let application = async {
+            let mut stream = a_control.open().unwrap();
+            stream.write_all(b"hello").await.unwrap();
+
+            let mut accepted = b_control.accept().await.unwrap();
+            drop(stream);
+
+            let mut message = Vec::new();
+            let error = accepted.read_to_end(&mut message).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+            assert_eq!(message, b"hello");
+
+            a_control.close();
+        };
+
+        block_on(async {
+            let (a, b, ()) = futures::join!(a, b, application);
+            a.unwrap();
+            b.unwrap();
+        });
+    }
+
+    #[test]
+    fn max_streams() {
+        let (a, b) = connect();
+        let b = b.with_max_streams(1);
+        let (a_control, b_control) = (a.control(), b.control());
+
+        let application = async {
+            let _first = a_control.open().unwrap();
+            let mut second = a_control.open().unwrap();
+
+            let error = second.read_u8().await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+            assert_eq!(b_control.accept().await.unwrap().id(), 0);
+
+            a_control.close();
+        };
+
+        block_on(async {
+            let (a, b, ()) = futures::join!(a, b, application);
+            a.unwrap();
+            b.unwrap();
+        });
+    }
+
+    #[test]
+    fn close_resets_pending_streams() {
+        let (a, b) = connect();
+        let (a_control, b_control) = (a.control(), b.control());
+        let b_shared = Arc::clone(&b.shared);
+
+        let application = async {
+            let mut stream = a_control.open().unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            stream.flush().await.unwrap();
+
+            // wait for the stream to be received, not accepted
+            while lock(&b_shared).incoming.is_empty() {
+                tokio::task::yield_now().await;
+            }
+            b_control.close();
+            assert!(lock(&b_shared).streams.is_empty());
+
+            let error = stream.read_u8().await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+
+            a_control.close();
+        };
+
+        block_on(async {
+            let (a, b, ()) = futures::join!(a, b, application);
+            a.unwrap();
+            b.unwrap();
+        });
+    }
+
+    #[test]
+    fn open_flood() {
+        let mut shared = Shared::new();
+        shared.max_streams = 0;
+
+        let open = |id| Frame {
+            kind: Kind::Open,
+            opener: true,
+            id,
+            payload: Bytes::new(),
+        };
+
+        for id in 0..MAX_REFUSED_STREAMS as u32 {
+            shared.receive(open(id)).unwrap();
+        }
+        assert!(shared.receive(open(MAX_REFUSED_STREAMS as u32)).is_err());
+        assert!(shared.outgoing.is_empty());
+
+        // the resets are sent, the remote peer can open streams again
+        while shared.next_outgoing().is_some() {}
+        shared
+            .receive(open(MAX_REFUSED_STREAMS as u32 + 1))
+            .unwrap();
+    }
+}
@@ -9,7 +9,9 @@ and simple to use network implementation
 use crate::SessionId;
 use crate::{
     accept,
-    handle::{Handle, HandleReadHalf, HandleWriteHalf},
+    config::with_timeout,
+    handle::{self, Handle, HandleReadHalf, HandleWriteHalf},
+    ConnectConfig, Extensions,
 };
 use anyhow::{bail, Context as _, Result};
 use bytes::Bytes;
@@ -24,6 +26,7 @@ use std::{
     net::SocketAddr,
     pin::Pin,
     task::{Context, Poll},
+    time::{Duration, SystemTime},
 };
 use tokio::net::{
     lookup_host,
@@ -40,6 +43,7 @@ use tokio::net::{
 ///
 pub struct Listener {
     listener: TcpListener,
+    config: ConnectConfig,
 }
 
 /// A bidirectional, encrypted and authenticated connection with a peer
@@ -89,7 +93,36 @@ impl Listener {
             .await
             .with_context(|| format!("Cannot listen to {}", addr))?;
 
-        Ok(Self { listener })
+        Ok(Self {
+            listener,
+            config: ConnectConfig::default(),
+        })
+    }
+
+    /// give up the handshake of the inbound connections that take longer
+    /// than `timeout`, so slow or idle peers do not hold the resources
+    /// of the server
+    ///
+    /// there is no timeout by default.
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.config.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// bound the handshake and the inbound connections by the limits of
+    /// the [`ConnectConfig`]
+    ///
+    /// see [`accept::Accepting::with_config`]
+    pub fn with_config(mut self, config: ConnectConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// the local address the listener is bound to
+    pub fn local_address(&self) -> Result<SocketAddr> {
+        self.listener
+            .local_addr()
+            .context("Cannot get the local address of the listener")
     }
 
     /// start accepting a new incoming connection
@@ -111,7 +144,7 @@ impl Listener {
 
         let (reader, writer) = stream.into_split();
 
-        let handle = Handle::accept(rng, reader, writer);
+        let handle = Handle::accept(rng, reader, writer).with_config(self.config);
 
         Ok(Accepting { handle, peer_addr })
     }
@@ -138,6 +171,39 @@ where
         self.peer_addr
     }
 
+    /// accept the peers that do not authenticate themselves
+    ///
+    /// see [`accept::Accepting::allow_anonymous`]
+    pub fn allow_anonymous(self) -> Self {
+        let Self { handle, peer_addr } = self;
+        Self {
+            handle: handle.allow_anonymous(),
+            peer_addr,
+        }
+    }
+
+    /// accept the peers that do not know our public key
+    ///
+    /// see [`accept::Accepting::allow_xx`]
+    pub fn allow_xx(self) -> Self {
+        let Self { handle, peer_addr } = self;
+        Self {
+            handle: handle.allow_xx(),
+            peer_addr,
+        }
+    }
+
+    /// send our current public key to the peers using a previous one
+    ///
+    /// see [`accept::Accepting::allow_fallback`]
+    pub fn allow_fallback(self) -> Self {
+        let Self { handle, peer_addr } = self;
+        Self {
+            handle: handle.allow_fallback(),
+            peer_addr,
+        }
+    }
+
     /// perform the handshake check with the inbound peer
     ///
     /// except to receive the first message of the [Noise **IK**] handshake.
@@ -161,7 +227,7 @@ where
 
         tracing::debug!(
             session_id = %handle.session_id(),
-            id = %handle.remote_public_identity(),
+            id = handle.remote_public_identity().map(tracing::field::display),
             "handshake succeed",
         );
 
@@ -176,7 +242,8 @@ where
 impl ConnectionReader {
     /// retrieve the public identity of the peer
     ///
-    pub fn remote_public_identity(&self) -> &PublicKey {
+    /// `None` if the peer connected anonymously
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
         self.reader.remote_public_identity()
     }
 
@@ -203,7 +270,8 @@ impl ConnectionReader {
 impl ConnectionWriter {
     /// retrieve the public identity of the peer
     ///
-    pub fn remote_public_identity(&self) -> &PublicKey {
+    /// `None` if the peer connected anonymously
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
         self.writer.remote_public_identity()
     }
 
@@ -231,7 +299,8 @@ impl ConnectionWriter {
 impl Connection {
     /// retrieve the public identity of the peer
     ///
-    pub fn remote_public_identity(&self) -> &PublicKey {
+    /// `None` if the peer connected anonymously
+    pub fn remote_public_identity(&self) -> Option<&PublicKey> {
         self.writer.remote_public_identity()
     }
 
@@ -255,6 +324,193 @@ impl Connection {
         self.writer.session_id()
     }
 
+    /// perform a new handshake with the remote peer and switch to the
+    /// new session without dropping the connection
+    ///
+    /// see [`Handle::rehandshake`]
+    #[tracing::instrument(skip(self, rng, k), fields(peer_addr = %self.remote_address()), level = "debug")]
+    pub async fn rehandshake<RNG, K>(&mut self, rng: RNG, k: &K, rs: PublicKey) -> Result<()>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+    {
+        handle::rehandshake(&mut self.reader.reader, &mut self.writer.writer, rng, k, rs)
+            .await
+            .with_context(|| format!("Failed to re-handshake with {}", self.remote_address()))?;
+
+        tracing::debug!(
+            session_id = %self.session_id(),
+            id = self.remote_public_identity().map(tracing::field::display),
+            "re-handshake succeed",
+        );
+
+        Ok(())
+    }
+
+    /// rotate the session once it is older than `max_session_age`
+    ///
+    /// see [`Handle::with_max_session_age`]
+    pub fn with_max_session_age(mut self, max_session_age: Duration) -> Self {
+        self.writer
+            .writer
+            .set_max_session_age(Some(max_session_age));
+        self
+    }
+
+    /// `true` if the session is older than the maximum session age
+    pub fn session_expired(&self, now: SystemTime) -> bool {
+        self.writer.writer.session_expired(now)
+    }
+
+    /// rotate the session once fewer than `min_remaining_messages` can
+    /// be sent or received on it
+    ///
+    /// see [`Handle::with_min_remaining_messages`]
+    pub fn with_min_remaining_messages(mut self, min_remaining_messages: u64) -> Self {
+        self.writer
+            .writer
+            .set_min_remaining_messages(min_remaining_messages);
+        self
+    }
+
+    /// `true` if the nonces of the session are close to their limit
+    ///
+    /// see [`Handle::session_exhausted`]
+    pub fn session_exhausted(&self) -> bool {
+        handle::session_exhausted(&self.reader.reader, &self.writer.writer)
+    }
+
+    /// send a keep-alive after `keep_alive` without sending anything
+    ///
+    /// see [`Handle::with_keep_alive`]
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.writer.writer.set_keep_alive_interval(Some(keep_alive));
+        self
+    }
+
+    /// consider the remote peer dead after `idle_timeout` without
+    /// receiving anything from it
+    ///
+    /// see [`Handle::with_idle_timeout`]
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.reader.reader.set_idle_timeout(Some(idle_timeout));
+        self
+    }
+
+    /// `false` if nothing has been received from the remote peer for
+    /// longer than the idle timeout
+    pub fn is_alive(&self) -> bool {
+        self.reader.reader.is_alive()
+    }
+
+    /// send a keep-alive if nothing has been sent for longer than the
+    /// keep-alive interval
+    ///
+    /// see [`Handle::keep_alive`]
+    pub async fn keep_alive(&mut self) -> Result<bool> {
+        self.writer
+            .writer
+            .keep_alive()
+            .await
+            .with_context(|| format!("Cannot keep alive {}", self.writer.peer_addr))
+    }
+
+    /// close the connection gracefully and wait for the remote peer to
+    /// close it too
+    ///
+    /// see [`Handle::shutdown`]
+    pub async fn shutdown(&mut self) -> Result<()> {
+        let peer_addr = self.writer.peer_addr;
+
+        self.writer
+            .close()
+            .await
+            .with_context(|| format!("Cannot close the connection with {}", peer_addr))?;
+        self.reader
+            .reader
+            .wait_close()
+            .await
+            .with_context(|| format!("Connection with {} not closed gracefully", peer_addr))
+    }
+
+    /// perform a re-handshake if the session is older than the maximum
+    /// session age or if it is exhausted, returns `true` if the session
+    /// has been rotated
+    ///
+    /// see [`Handle::rotate_if_needed`]
+    #[tracing::instrument(skip(self, rng, k), fields(peer_addr = %self.remote_address()), level = "debug")]
+    pub async fn rotate_if_needed<RNG, K>(
+        &mut self,
+        rng: RNG,
+        k: &K,
+        rs: PublicKey,
+        now: SystemTime,
+    ) -> Result<bool>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+    {
+        let rotated = handle::rotate_if_needed(
+            &mut self.reader.reader,
+            &mut self.writer.writer,
+            rng,
+            k,
+            rs,
+            now,
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to rotate the session with {}",
+                self.remote_address()
+            )
+        })?;
+
+        if rotated {
+            tracing::debug!(
+                session_id = %self.session_id(),
+                id = self.remote_public_identity().map(tracing::field::display),
+                "session rotated",
+            );
+        }
+
+        Ok(rotated)
+    }
+
+    /// accept the re-handshake requested by the remote peer
+    ///
+    /// see [`Handle::accept_rehandshake`]
+    #[tracing::instrument(skip(self, rng, k, check_id), fields(peer_addr = %self.remote_address()), level = "debug")]
+    pub async fn accept_rehandshake<RNG, K, F>(
+        &mut self,
+        rng: RNG,
+        k: &K,
+        check_id: F,
+    ) -> Result<()>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+        F: Fn(&PublicKey) -> bool,
+    {
+        handle::accept_rehandshake(
+            &mut self.reader.reader,
+            &mut self.writer.writer,
+            rng,
+            k,
+            check_id,
+        )
+        .await
+        .with_context(|| format!("Failed to re-handshake with {}", self.remote_address()))?;
+
+        tracing::debug!(
+            session_id = %self.session_id(),
+            id = self.remote_public_identity().map(tracing::field::display),
+            "re-handshake succeed",
+        );
+
+        Ok(())
+    }
+
     /// connect to the given socket address, expecting the remote to identify
     /// with the [`PublicKey`] `rs`.
     ///
@@ -285,7 +541,164 @@ impl Connection {
 
         tracing::debug!(
             session_id = %handle.session_id(),
-            id = %handle.remote_public_identity(),
+            id = handle.remote_public_identity().map(tracing::field::display),
+            "handshake succeed",
+        );
+
+        let (reader, writer) = handle.split();
+
+        let reader = ConnectionReader { reader, peer_addr };
+        let writer = ConnectionWriter { writer, peer_addr };
+        Ok(Self { reader, writer })
+    }
+
+    /// same as [`connect_to`](Self::connect_to) but recover if the remote
+    /// has rotated its static key since we learned `rs`, `check_id`
+    /// verifies the new public identity
+    ///
+    /// see [`Handle::open_with_fallback`]
+    #[tracing::instrument(skip(k, rng, check_id), level = "info")]
+    pub async fn connect_to_with_fallback<RNG, K, F>(
+        rng: RNG,
+        k: &K,
+        peer_addr: SocketAddr,
+        rs: PublicKey,
+        check_id: F,
+    ) -> Result<Self>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+        F: Fn(&PublicKey) -> bool,
+    {
+        let stream = TcpStream::connect(peer_addr)
+            .await
+            .with_context(|| format!("Cannot connect to peer {}", peer_addr))?;
+
+        let (reader, writer) = stream.into_split();
+
+        let handle =
+            Handle::open_with_fallback(rng, k, rs, &Extensions::new(), check_id, reader, writer)
+                .await
+                .with_context(|| format!("Failed to handshake with peer {}", peer_addr))?;
+
+        tracing::debug!(
+            session_id = %handle.session_id(),
+            id = handle.remote_public_identity().map(tracing::field::display),
+            "handshake succeed",
+        );
+
+        let (reader, writer) = handle.split();
+
+        let reader = ConnectionReader { reader, peer_addr };
+        let writer = ConnectionWriter { writer, peer_addr };
+        Ok(Self { reader, writer })
+    }
+
+    /// connect to the given socket address without knowing the remote's
+    /// public identity in advance, `check_id` verifies it
+    ///
+    /// see [`Handle::open_xx`]
+    #[tracing::instrument(skip(k, rng, check_id), level = "info")]
+    pub async fn connect_to_xx<RNG, K, F>(
+        rng: RNG,
+        k: &K,
+        peer_addr: SocketAddr,
+        check_id: F,
+    ) -> Result<Self>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+        F: Fn(&PublicKey) -> bool,
+    {
+        let stream = TcpStream::connect(peer_addr)
+            .await
+            .with_context(|| format!("Cannot connect to peer {}", peer_addr))?;
+
+        let (reader, writer) = stream.into_split();
+
+        let handle = Handle::open_xx(rng, k, check_id, reader, writer)
+            .await
+            .with_context(|| format!("Failed to handshake with peer {}", peer_addr))?;
+
+        tracing::debug!(
+            session_id = %handle.session_id(),
+            id = handle.remote_public_identity().map(tracing::field::display),
+            "handshake succeed",
+        );
+
+        let (reader, writer) = handle.split();
+
+        let reader = ConnectionReader { reader, peer_addr };
+        let writer = ConnectionWriter { writer, peer_addr };
+        Ok(Self { reader, writer })
+    }
+
+    /// connect to the given socket address, expecting the remote to identify
+    /// with the [`PublicKey`] `rs`, our public identity is hidden from
+    /// passive observers
+    ///
+    /// see [`Handle::open_xk`]
+    #[tracing::instrument(skip(k, rng), level = "info")]
+    pub async fn connect_to_xk<RNG, K>(
+        rng: RNG,
+        k: &K,
+        peer_addr: SocketAddr,
+        rs: PublicKey,
+    ) -> Result<Self>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+    {
+        let stream = TcpStream::connect(peer_addr)
+            .await
+            .with_context(|| format!("Cannot connect to peer {}", peer_addr))?;
+
+        let (reader, writer) = stream.into_split();
+
+        let handle = Handle::open_xk(rng, k, rs, reader, writer)
+            .await
+            .with_context(|| format!("Failed to handshake with peer {}", peer_addr))?;
+
+        tracing::debug!(
+            session_id = %handle.session_id(),
+            id = handle.remote_public_identity().map(tracing::field::display),
+            "handshake succeed",
+        );
+
+        let (reader, writer) = handle.split();
+
+        let reader = ConnectionReader { reader, peer_addr };
+        let writer = ConnectionWriter { writer, peer_addr };
+        Ok(Self { reader, writer })
+    }
+
+    /// connect to the given socket address, expecting the remote to identify
+    /// with the [`PublicKey`] `rs`, without authenticating ourself
+    ///
+    /// see [`Handle::open_nk`]
+    #[tracing::instrument(skip(rng), level = "info")]
+    pub async fn connect_to_nk<RNG, K>(
+        rng: RNG,
+        peer_addr: SocketAddr,
+        rs: PublicKey,
+    ) -> Result<Self>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+    {
+        let stream = TcpStream::connect(peer_addr)
+            .await
+            .with_context(|| format!("Cannot connect to peer {}", peer_addr))?;
+
+        let (reader, writer) = stream.into_split();
+
+        let handle = Handle::open_nk::<K, _>(rng, rs, reader, writer)
+            .await
+            .with_context(|| format!("Failed to handshake with peer {}", peer_addr))?;
+
+        tracing::debug!(
+            session_id = %handle.session_id(),
+            id = handle.remote_public_identity().map(tracing::field::display),
             "handshake succeed",
         );
 
@@ -302,11 +715,40 @@ impl Connection {
     /// have been tried and failed.
     ///
     #[tracing::instrument(skip(k, rng), level = "info")]
-    pub async fn connect<RNG, K, A>(
+    pub async fn connect<RNG, K, A>(rng: RNG, k: &K, peer_addr: A, rs: PublicKey) -> Result<Self>
+    where
+        RNG: RngCore + CryptoRng,
+        A: ToSocketAddrs + Display + fmt::Debug,
+        K: Dh,
+    {
+        Self::connect_any(rng, k, peer_addr, rs, None).await
+    }
+
+    /// same as [`connect`](Self::connect) but give up an attempt (the
+    /// connection and the handshake) that takes longer than `timeout`
+    /// and try the next address
+    #[tracing::instrument(skip(k, rng), level = "info")]
+    pub async fn connect_with_timeout<RNG, K, A>(
+        rng: RNG,
+        k: &K,
+        peer_addr: A,
+        rs: PublicKey,
+        timeout: Duration,
+    ) -> Result<Self>
+    where
+        RNG: RngCore + CryptoRng,
+        A: ToSocketAddrs + Display + fmt::Debug,
+        K: Dh,
+    {
+        Self::connect_any(rng, k, peer_addr, rs, Some(timeout)).await
+    }
+
+    async fn connect_any<RNG, K, A>(
         mut rng: RNG,
         k: &K,
         peer_addr: A,
         rs: PublicKey,
+        timeout: Option<Duration>,
     ) -> Result<Self>
     where
         RNG: RngCore + CryptoRng,
@@ -318,7 +760,7 @@ impl Connection {
             .context("Cannot connect to remote peer address")?;
 
         for socket_addr in peer_addrs {
-            match Self::connect_to(&mut rng, k, socket_addr, rs).await {
+            match with_timeout(timeout, Self::connect_to(&mut rng, k, socket_addr, rs)).await {
                 Ok(connection) => return Ok(connection),
                 Err(error) => {
                     tracing::info!(reason = ?error, "Failed to connect to {} with {}", peer_addr, socket_addr);
@@ -441,7 +883,71 @@ impl fmt::Debug for Connection {
         f.debug_struct("Connection")
             .field("remote_address", &self.remote_address())
             .field("session", &self.session_id())
-            .field("id", self.remote_public_identity())
+            .field("id", &self.remote_public_identity())
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn connect_with_timeout() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+
+        runtime().block_on(async {
+            let listener = Listener::new("127.0.0.1:0")
+                .await
+                .unwrap()
+                .with_handshake_timeout(Duration::from_secs(5));
+            let addr = listener.local_address().unwrap();
+
+            let accept = async {
+                let accepting = listener.accept::<_, SecretKey>(thread_rng()).await?;
+                accepting.handshake(&bob, |_| true).await
+            };
+            let connect = Connection::connect_with_timeout(
+                thread_rng(),
+                &alice,
+                addr.to_string(),
+                bob.public_key(),
+                Duration::from_secs(5),
+            );
+
+            let (a, b) = futures::join!(connect, accept);
+            let (a, b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.session_id(), b.session_id());
+            assert_eq!(b.remote_public_identity(), Some(&alice.public_key()));
+        });
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let bob = SecretKey::new(thread_rng());
+
+        runtime().block_on(async {
+            let listener = Listener::new("127.0.0.1:0")
+                .await
+                .unwrap()
+                .with_handshake_timeout(Duration::from_millis(50));
+            let addr = listener.local_address().unwrap();
+
+            // the peer connects but never sends its handshake
+            let _idle = TcpStream::connect(addr).await.unwrap();
+            let accepting = listener.accept::<_, SecretKey>(thread_rng()).await.unwrap();
+
+            assert!(accepting.handshake(&bob, |_| true).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,488 @@
+/*!
+# Traffic obfuscation
+
+The bytes of an ASMTP connection have a recognisable shape: the
+handshake messages have known sizes and start with the [`Version`],
+the encrypted messages are prefixed with their length. In censored
+networks this is enough for a deep packet inspection to fingerprint and
+block the connections.
+
+An [`Obfuscation`] transforms the bytes written and read by the
+[`Handle`] on their way to and from the network. It is applied outside
+of the Noise layer: the [`ObfuscatedReader`] and [`ObfuscatedWriter`]
+wrap the reader and the writer given to the [`Handle`].
+
+```no_run
+# async fn example() -> anyhow::Result<()> {
+use keynesis_core::{key::ed25519::SecretKey, Seed};
+use keynesis_network::{
+    obfuscation::{Obfs4, ObfuscatedReader, ObfuscatedWriter},
+    Handle,
+};
+use rand::thread_rng;
+use tokio::net::TcpStream;
+# let k = SecretKey::new(thread_rng());
+# let rs = k.public_key();
+# let secret = Seed::generate(&mut thread_rng());
+
+let (reader, writer) = TcpStream::connect("127.0.0.1:9800").await?.into_split();
+let reader = ObfuscatedReader::new(reader, Obfs4::new(thread_rng(), &secret));
+let writer = ObfuscatedWriter::new(writer, Obfs4::new(thread_rng(), &secret));
+
+let handle = Handle::open(thread_rng(), &k, rs, reader, writer).await?;
+# Ok(()) }
+```
+
+The obfuscation is not a security layer: the Noise handshake and the
+transport already authenticate and encrypt the messages.
+
+[`Version`]: crate::Version
+[`Handle`]: crate::Handle
+*/
+
+use bytes::{Buf as _, BufMut as _, BytesMut};
+use cryptoxide::chacha20::ChaCha20;
+use futures::ready;
+use keynesis_core::Seed;
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    future::Future as _,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+/// transformation of the bytes of one direction of a connection
+///
+/// an [`ObfuscatedWriter`] calls [`obfuscate`](Self::obfuscate) and
+/// [`delay`](Self::delay), an [`ObfuscatedReader`] calls
+/// [`deobfuscate`](Self::deobfuscate). The same type is used on both
+/// sides of the connection.
+pub trait Obfuscation {
+    /// obfuscate the `data` written by the handle, the bytes to send on
+    /// the wire are appended in `output`
+    fn obfuscate(&mut self, data: &[u8], output: &mut BytesMut);
+
+    /// time to wait before sending the bytes of the last call to
+    /// [`obfuscate`](Self::obfuscate)
+    fn delay(&mut self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// recover the data from the bytes received in `input` and append it
+    /// in `output`
+    ///
+    /// the processed bytes are consumed from `input`, the incomplete
+    /// frames are left in `input` for the next call.
+    fn deobfuscate(&mut self, input: &mut BytesMut, output: &mut BytesMut) -> io::Result<()>;
+}
+
+/// an obfs4-like [`Obfuscation`]
+///
+/// * the stream starts with a random nonce and everything after it is
+///   XORed with the XChaCha20 keystream of the shared secret: the peers
+///   need to agree on the secret beforehand (like the obfs4 bridge
+///   lines);
+/// * the data is sent in frames of random sizes: each frame is padded
+///   with a random number of bytes (up to [`with_padding`](Self::with_padding));
+/// * each frame is sent after a random delay (up to
+///   [`with_jitter`](Self::with_jitter)), there is no delay by default.
+///
+/// All the bytes on the wire are indistinguishable from random bytes.
+pub struct Obfs4<RNG> {
+    rng: RNG,
+    secret: Seed,
+    max_padding: usize,
+    max_jitter: Duration,
+
+    sender: Option<ChaCha20>,
+    receiver: Option<ChaCha20>,
+    /// length of the frame being received, once its header is decoded
+    receiving: Option<usize>,
+}
+
+/// the reader half, deobfuscating the bytes received from `R`
+pub struct ObfuscatedReader<R, O> {
+    reader: R,
+    obfuscation: O,
+    input: BytesMut,
+    output: BytesMut,
+}
+
+/// the writer half, obfuscating the bytes written in `W`
+pub struct ObfuscatedWriter<W, O> {
+    writer: W,
+    obfuscation: O,
+    output: BytesMut,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+const NONCE_SIZE: usize = 24;
+const LENGTH_SIZE: usize = 2;
+/// maximum data size in one frame
+const MAX_DATA_SIZE: usize = 16 * 1024;
+/// maximum size of a frame, without its length
+const MAX_FRAME_SIZE: usize = u16::MAX as usize;
+const DEFAULT_MAX_PADDING: usize = 512;
+const READ_BUFFER_SIZE: usize = 4 * 1024;
+
+/* Obfs4 ******************************************************************* */
+
+impl<RNG> Obfs4<RNG>
+where
+    RNG: RngCore + CryptoRng,
+{
+    pub fn new(rng: RNG, secret: &Seed) -> Self {
+        Self {
+            rng,
+            secret: secret.compact(),
+            max_padding: DEFAULT_MAX_PADDING,
+            max_jitter: Duration::ZERO,
+            sender: None,
+            receiver: None,
+            receiving: None,
+        }
+    }
+
+    /// set the maximum number of padding bytes of a frame
+    ///
+    /// # panics
+    ///
+    /// if the padding does not fit in a frame with the maximum data size
+    /// (16KiB)
+    pub fn with_padding(mut self, max_padding: usize) -> Self {
+        assert!(
+            LENGTH_SIZE + MAX_DATA_SIZE + max_padding <= MAX_FRAME_SIZE,
+            "the padding does not fit in a frame"
+        );
+        self.max_padding = max_padding;
+        self
+    }
+
+    /// set the maximum delay before sending a frame
+    ///
+    /// the delays need a tokio runtime with the time driver enabled.
+    pub fn with_jitter(mut self, max_jitter: Duration) -> Self {
+        self.max_jitter = max_jitter;
+        self
+    }
+
+    fn random_below(&mut self, max: u64) -> u64 {
+        if max == 0 {
+            0
+        } else {
+            self.rng.next_u64() % max.saturating_add(1)
+        }
+    }
+}
+
+impl<RNG> Obfuscation for Obfs4<RNG>
+where
+    RNG: RngCore + CryptoRng,
+{
+    fn obfuscate(&mut self, data: &[u8], output: &mut BytesMut) {
+        if self.sender.is_none() {
+            let mut nonce = [0; NONCE_SIZE];
+            self.rng.fill_bytes(&mut nonce);
+            output.put_slice(&nonce);
+            self.sender = Some(ChaCha20::new_xchacha20(self.secret.as_ref(), &nonce));
+        }
+
+        for data in data.chunks(MAX_DATA_SIZE) {
+            let padding = self.random_below(self.max_padding as u64) as usize;
+            let frame_size = LENGTH_SIZE + data.len() + padding;
+
+            let start = output.len();
+            output.put_u16(frame_size as u16);
+            output.put_u16(data.len() as u16);
+            output.put_slice(data);
+            let padding_start = output.len();
+            output.resize(padding_start + padding, 0);
+            self.rng.fill_bytes(&mut output[padding_start..]);
+
+            if let Some(sender) = self.sender.as_mut() {
+                sender.process_mut(&mut output[start..]);
+            }
+        }
+    }
+
+    fn delay(&mut self) -> Duration {
+        let max = self.max_jitter.as_micros().min(u64::MAX as u128) as u64;
+        Duration::from_micros(self.random_below(max))
+    }
+
+    fn deobfuscate(&mut self, input: &mut BytesMut, output: &mut BytesMut) -> io::Result<()> {
+        let receiver = match self.receiver.as_mut() {
+            Some(receiver) => receiver,
+            None if input.len() < NONCE_SIZE => return Ok(()),
+            None => {
+                let nonce = input.split_to(NONCE_SIZE);
+                self.receiver
+                    .insert(ChaCha20::new_xchacha20(self.secret.as_ref(), &nonce))
+            }
+        };
+
+        loop {
+            match self.receiving {
+                None if input.len() >= LENGTH_SIZE => {
+                    let mut length = input.split_to(LENGTH_SIZE);
+                    receiver.process_mut(&mut length);
+                    let length = length.get_u16() as usize;
+                    if length < LENGTH_SIZE {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid obfuscated frame length",
+                        ));
+                    }
+                    self.receiving = Some(length);
+                }
+                Some(length) if input.len() >= length => {
+                    let mut frame = input.split_to(length);
+                    receiver.process_mut(&mut frame);
+                    let data_size = frame.get_u16() as usize;
+                    if data_size > frame.len() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid obfuscated frame data size",
+                        ));
+                    }
+                    output.put_slice(&frame[..data_size]);
+                    self.receiving = None;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+/* Reader ****************************************************************** */
+
+impl<R, O> ObfuscatedReader<R, O>
+where
+    R: AsyncRead + Unpin,
+    O: Obfuscation + Unpin,
+{
+    pub fn new(reader: R, obfuscation: O) -> Self {
+        Self {
+            reader,
+            obfuscation,
+            input: BytesMut::new(),
+            output: BytesMut::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R, O> AsyncRead for ObfuscatedReader<R, O>
+where
+    R: AsyncRead + Unpin,
+    O: Obfuscation + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.output.is_empty() {
+            let mut bytes = [0; READ_BUFFER_SIZE];
+            let mut read = ReadBuf::new(&mut bytes);
+            ready!(Pin::new(&mut this.reader).poll_read(cx, &mut read))?;
+            if read.filled().is_empty() {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+
+            this.input.put_slice(read.filled());
+            this.obfuscation
+                .deobfuscate(&mut this.input, &mut this.output)?;
+        }
+
+        let len = this.output.len().min(buf.remaining());
+        buf.put_slice(&this.output.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/* Writer ****************************************************************** */
+
+impl<W, O> ObfuscatedWriter<W, O>
+where
+    W: AsyncWrite + Unpin,
+    O: Obfuscation + Unpin,
+{
+    pub fn new(writer: W, obfuscation: O) -> Self {
+        Self {
+            writer,
+            obfuscation,
+            output: BytesMut::new(),
+            delay: None,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// wait for the delay and write the pending obfuscated bytes
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(delay) = self.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+
+        while !self.output.is_empty() {
+            let written = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.output))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.output.advance(written);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W, O> AsyncWrite for ObfuscatedWriter<W, O>
+where
+    W: AsyncWrite + Unpin,
+    O: Obfuscation + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+
+        this.obfuscation.obfuscate(buf, &mut this.output);
+        let delay = this.obfuscation.delay();
+        if !delay.is_zero() {
+            this.delay = Some(Box::pin(tokio::time::sleep(delay)));
+        }
+
+        // the bytes are accepted, sending them is only attempted here:
+        // they are sent on the next write or flush otherwise
+        if let Poll::Ready(Err(error)) = this.poll_send(cx) {
+            return Poll::Ready(Err(error));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Handle;
+    use futures::{executor::block_on, SinkExt as _, StreamExt as _};
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+    use tokio::io::duplex;
+
+    #[test]
+    fn frames() {
+        let secret = Seed::generate(&mut thread_rng());
+        let mut sender = Obfs4::new(thread_rng(), &secret).with_padding(64);
+        let mut receiver = Obfs4::new(thread_rng(), &secret);
+
+        let mut wire = BytesMut::new();
+        sender.obfuscate(b"hello", &mut wire);
+        sender.obfuscate(&[], &mut wire);
+        sender.obfuscate(&[7; MAX_DATA_SIZE + 1], &mut wire);
+        assert!(!wire.windows(5).any(|w| w == b"hello"));
+
+        // received one byte at the time
+        let mut input = BytesMut::new();
+        let mut output = BytesMut::new();
+        for byte in wire {
+            input.put_u8(byte);
+            receiver.deobfuscate(&mut input, &mut output).unwrap();
+        }
+        assert!(input.is_empty());
+        assert_eq!(&output[..5], b"hello");
+        assert_eq!(&output[5..], [7; MAX_DATA_SIZE + 1].as_ref());
+
+        // another secret does not decode the frames
+        let mut other = Obfs4::new(thread_rng(), &Seed::generate(&mut thread_rng()));
+        let mut wire = BytesMut::new();
+        Obfs4::new(thread_rng(), &secret).obfuscate(b"hello", &mut wire);
+        let mut output = BytesMut::new();
+        let _ = other.deobfuscate(&mut wire, &mut output);
+        assert_ne!(output.as_ref(), b"hello");
+    }
+
+    #[test]
+    fn handle() {
+        let secret = Seed::generate(&mut thread_rng());
+        let obfs4 = || Obfs4::new(thread_rng(), &secret);
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+
+        let (a, b) = duplex(1024);
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+        let a_reader = ObfuscatedReader::new(a_reader, obfs4());
+        let a_writer = ObfuscatedWriter::new(a_writer, obfs4());
+        let b_reader = ObfuscatedReader::new(b_reader, obfs4());
+        let b_writer = ObfuscatedWriter::new(b_writer, obfs4());
+
+        block_on(async {
+            let (a, b) = futures::join!(
+                Handle::open(thread_rng(), &alice, bob.public_key(), a_reader, a_writer),
+                Handle::accept(thread_rng(), b_reader, b_writer).accept(&bob, |_| true),
+            );
+            let (mut a, mut b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.session_id(), b.session_id());
+
+            a.send(bytes::Bytes::from_static(b"hello")).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap().as_ref(), b"hello");
+        });
+    }
+
+    #[test]
+    fn jitter() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let secret = Seed::generate(&mut thread_rng());
+        let obfs4 = Obfs4::new(thread_rng(), &secret).with_jitter(Duration::from_millis(5));
+
+        let (a, b) = duplex(1024);
+        let mut writer = ObfuscatedWriter::new(a, obfs4);
+        let mut reader = ObfuscatedReader::new(b, Obfs4::new(thread_rng(), &secret));
+
+        runtime.block_on(async {
+            use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+            writer.write_all(b"hello").await.unwrap();
+            writer.flush().await.unwrap();
+
+            let mut received = [0; 5];
+            reader.read_exact(&mut received).await.unwrap();
+            assert_eq!(&received, b"hello");
+        });
+    }
+}
@@ -1,6 +1,11 @@
 use crate::{
-    codec::handshake::{HandshakeInitialize, HandshakeResponse},
-    Handle,
+    codec::handshake::{
+        self, FallbackExtensions, FallbackFinalize, HandshakeInitialize, HandshakeResponse,
+        NkInitialize, NkResponse, XkFinalize, XkInitialize, XkResponse, XxFinalize, XxInitialize,
+        XxResponse,
+    },
+    config::{with_deadline, ConnectConfig},
+    Extensions, Handle, VersionRange,
 };
 use anyhow::{bail, Context as _, Result};
 use keynesis_core::{
@@ -9,15 +14,24 @@ use keynesis_core::{
         ed25519::{self, PublicKey},
         Dh,
     },
-    noise::{ik::WaitB, IK},
+    noise::{
+        ik::{Pipe, WaitB},
+        IK, NK, XK, XX,
+    },
 };
 use rand_core::{CryptoRng, RngCore};
-use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt as _},
+    time::Instant,
+};
 
 pub struct Opening<I, O, RNG, K = ed25519::SecretKey> {
     reader: I,
     writer: O,
     state: IK<K, Blake2b, RNG, WaitB>,
+    config: ConnectConfig,
+    /// the time by which the handshake must be completed
+    deadline: Option<Instant>,
 }
 
 impl<I, O, RNG, K> Opening<I, O, RNG, K>
@@ -30,25 +44,40 @@ where
         rng: RNG,
         k: &K,
         rs: PublicKey,
+        extensions: &Extensions,
+        config: &ConnectConfig,
         reader: I,
         mut writer: O,
     ) -> Result<Self> {
-        let mut message = HandshakeInitialize::DEFAULT;
-        let ik = IK::new(rng, &[]);
+        let deadline = config.handshake_deadline();
+        handshake::check_advertised(&config.versions)?;
+        let extensions = config.extensions(extensions)?;
+
+        let mut message = Vec::with_capacity(HandshakeInitialize::MAX_MESSAGE_SIZE);
+        let ik = IK::new(rng, &None, &handshake::prologue(&config.versions));
 
         let state = ik
-            .initiate(k, rs, message.message_mut())
+            .initiate_with_payload(k, rs, extensions.to_bytes(), &mut message)
             .context("Cannot initiate Noise IK handshake")?;
 
-        writer
-            .write_all(message.as_ref())
-            .await
-            .context("Cannot send the Noise IK initial Handshake")?;
+        with_deadline(deadline, async {
+            writer
+                .write_all(&HandshakeInitialize::with_versions(config.versions, message).to_bytes())
+                .await
+                .context("Cannot send the Noise IK initial Handshake")?;
+            writer
+                .flush()
+                .await
+                .context("Cannot flush the Noise IK initial Handshake")
+        })
+        .await?;
 
         Ok(Self {
             reader,
             writer,
             state,
+            config: *config,
+            deadline,
         })
     }
 }
@@ -65,25 +94,282 @@ where
             mut reader,
             writer,
             state,
+            config,
+            deadline,
         } = self;
 
-        let mut bytes = [0; HandshakeResponse::SIZE];
-
-        reader
-            .read_exact(&mut bytes)
+        let message = with_deadline(deadline, HandshakeResponse::read(&mut reader))
             .await
             .context("Cannot receive the Noise IK response Handshake")?;
 
-        let message = HandshakeResponse::from_bytes(bytes);
+        let mut payload = Vec::with_capacity(message.message().len());
+        let mut state = state
+            .receive_with_payload(k, message.message(), &mut payload)
+            .context("Noise IK Handshake response failed")?;
+        let extensions = handshake::check_version(&config.versions, message.version(), &payload)?;
+        let extensions =
+            Extensions::from_bytes(extensions).context("Invalid handshake extensions")?;
+        config.agree_rekey_policy(&mut state, &extensions)?;
+
+        Ok(Handle::new(reader, writer, state, extensions)
+            .with_version(message.version())
+            .with_config(&config))
+    }
 
-        if !message.version().is_supported() {
-            bail!("Unsupported version {:?}", message.version());
-        }
+    /// same as [`wait`](Self::wait) but the responder may fall back to
+    /// the [Noise **XXfallback**] handshake if it has rotated its static
+    /// key, the new key is verified with `check_id`
+    ///
+    /// our `extensions` are sent again in the last message of the
+    /// fallback handshake.
+    ///
+    /// [Noise **XXfallback**]: https://noiseexplorer.com/patterns/XXfallback/
+    pub(crate) async fn wait_with_fallback<F>(
+        self,
+        k: &K,
+        extensions: &Extensions,
+        check_id: F,
+    ) -> Result<Handle<I, O>>
+    where
+        F: Fn(&PublicKey) -> bool,
+    {
+        let Self {
+            mut reader,
+            mut writer,
+            state,
+            config,
+            deadline,
+        } = self;
 
-        let state = state
-            .receive(k, message.message())
+        let (version, message) = with_deadline(deadline, handshake::read_response(&mut reader))
+            .await
+            .context("Cannot receive the Noise IK response Handshake")?;
+
+        let mut payload = Vec::with_capacity(message.len());
+        let pipe = state
+            .receive_with_fallback(
+                k,
+                &message,
+                &mut payload,
+                &handshake::prologue(&config.versions),
+            )
             .context("Noise IK Handshake response failed")?;
+        let remote_extensions = handshake::check_version(&config.versions, version, &payload)?;
+
+        let (mut state, extensions) = match pipe {
+            Pipe::Established(state) => (state, remote_extensions.to_vec()),
+            Pipe::Fallback(state) => {
+                let id = state.remote_public_identity();
+                if !check_id(id) {
+                    bail!("Rejecting the new identity {} of the remote peer", id)
+                }
+
+                let mut message = Vec::with_capacity(FallbackFinalize::MAX_MESSAGE_SIZE);
+                let mut state = state
+                    .reply_with_payload(k, config.extensions(extensions)?.to_bytes(), &mut message)
+                    .context("Cannot prep the Noise's XXfallback final Handshake message")?;
 
-        Ok(Handle::new(reader, writer, state))
+                // the responder sends its extensions once we are authenticated
+                let message = with_deadline(deadline, async {
+                    writer
+                        .write_all(&FallbackFinalize::new(version, message).to_bytes())
+                        .await
+                        .context("Cannot send the Noise XXfallback final Handshake")?;
+                    writer
+                        .flush()
+                        .await
+                        .context("Cannot flush the Noise XXfallback final Handshake")?;
+                    FallbackExtensions::read(&mut reader)
+                        .await
+                        .context("Cannot receive the Noise XXfallback extensions")
+                })
+                .await?;
+
+                let mut extensions = Vec::with_capacity(message.message().len());
+                state
+                    .receive(message.message(), &mut extensions)
+                    .context("Invalid Noise XXfallback extensions")?;
+                (state, extensions)
+            }
+        };
+        let extensions =
+            Extensions::from_bytes(&extensions).context("Invalid handshake extensions")?;
+        config.agree_rekey_policy(&mut state, &extensions)?;
+
+        Ok(Handle::new(reader, writer, state, extensions)
+            .with_version(version)
+            .with_config(&config))
     }
 }
+
+/// open a [Noise **XX**] handshake, the responder's static key is
+/// verified with `check_id`
+///
+/// [Noise **XX**]: https://noiseexplorer.com/patterns/XX/
+pub(crate) async fn open_xx<I, O, RNG, K, F>(
+    rng: RNG,
+    k: &K,
+    versions: VersionRange,
+    check_id: F,
+    mut reader: I,
+    mut writer: O,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+    F: Fn(&PublicKey) -> bool,
+{
+    handshake::check_advertised(&versions)?;
+    let mut message = Vec::with_capacity(XxInitialize::MIN_MESSAGE_SIZE);
+    let state = XX::<K, Blake2b, RNG, _>::new(rng, &None, &handshake::prologue(&versions))
+        .initiate(&mut message)
+        .context("Cannot initiate Noise XX handshake")?;
+
+    writer
+        .write_all(&XxInitialize::with_versions(versions, message).to_bytes())
+        .await
+        .context("Cannot send the Noise XX initial Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XX initial Handshake")?;
+
+    let message = XxResponse::read(&mut reader)
+        .await
+        .context("Cannot receive the Noise XX response Handshake")?;
+    let version = message.version();
+    let mut payload = Vec::with_capacity(message.message().len());
+    let state = state
+        .receive_with_payload(message.message(), &mut payload)
+        .context("Noise XX Handshake response failed")?;
+    handshake::check_version(&versions, version, &payload)?;
+
+    if !check_id(state.remote_public_identity()) {
+        bail!(
+            "Rejecting connection with {}",
+            state.remote_public_identity()
+        )
+    }
+
+    let mut message = Vec::with_capacity(XxFinalize::MIN_MESSAGE_SIZE);
+    let state = state
+        .reply(k, &mut message)
+        .context("Cannot prep the Noise's XX final Handshake message")?;
+
+    writer
+        .write_all(&XxFinalize::new(version, message).to_bytes())
+        .await
+        .context("Cannot send the Noise XX final Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XX final Handshake")?;
+
+    Ok(Handle::new(reader, writer, state, Extensions::new()).with_version(version))
+}
+
+/// open a [Noise **NK**] handshake with the responder `rs`, we do not
+/// authenticate
+///
+/// [Noise **NK**]: https://noiseexplorer.com/patterns/NK/
+pub(crate) async fn open_nk<I, O, RNG, K>(
+    rng: RNG,
+    rs: PublicKey,
+    versions: VersionRange,
+    mut reader: I,
+    mut writer: O,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+{
+    handshake::check_advertised(&versions)?;
+    let mut message = Vec::with_capacity(NkInitialize::MIN_MESSAGE_SIZE);
+    let state = NK::<K, Blake2b, RNG, _>::new(rng, &handshake::prologue(&versions))
+        .initiate(rs, &mut message)
+        .context("Cannot initiate Noise NK handshake")?;
+
+    writer
+        .write_all(&NkInitialize::with_versions(versions, message).to_bytes())
+        .await
+        .context("Cannot send the Noise NK initial Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise NK initial Handshake")?;
+
+    let message = NkResponse::read(&mut reader)
+        .await
+        .context("Cannot receive the Noise NK response Handshake")?;
+    let mut payload = Vec::with_capacity(message.message().len());
+    let state = state
+        .receive_with_payload(message.message(), &mut payload)
+        .context("Noise NK Handshake response failed")?;
+    handshake::check_version(&versions, message.version(), &payload)?;
+
+    Ok(Handle::new(reader, writer, state, Extensions::new()).with_version(message.version()))
+}
+
+/// open a [Noise **XK**] handshake with the responder `rs`, our static
+/// key is only sent once the responder is authenticated
+///
+/// [Noise **XK**]: https://noiseexplorer.com/patterns/XK/
+pub(crate) async fn open_xk<I, O, RNG, K>(
+    rng: RNG,
+    k: &K,
+    rs: PublicKey,
+    versions: VersionRange,
+    mut reader: I,
+    mut writer: O,
+) -> Result<Handle<I, O>>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    K: Dh,
+    RNG: CryptoRng + RngCore,
+{
+    handshake::check_advertised(&versions)?;
+    let mut message = Vec::with_capacity(XkInitialize::MIN_MESSAGE_SIZE);
+    let state = XK::<K, Blake2b, RNG, _>::new(rng, &None, &handshake::prologue(&versions))
+        .initiate(rs, &mut message)
+        .context("Cannot initiate Noise XK handshake")?;
+
+    writer
+        .write_all(&XkInitialize::with_versions(versions, message).to_bytes())
+        .await
+        .context("Cannot send the Noise XK initial Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XK initial Handshake")?;
+
+    let message = XkResponse::read(&mut reader)
+        .await
+        .context("Cannot receive the Noise XK response Handshake")?;
+    let version = message.version();
+    let mut payload = Vec::with_capacity(message.message().len());
+    let state = state
+        .receive_with_payload(message.message(), &mut payload)
+        .context("Noise XK Handshake response failed")?;
+    handshake::check_version(&versions, version, &payload)?;
+
+    let mut message = Vec::with_capacity(XkFinalize::MIN_MESSAGE_SIZE);
+    let state = state
+        .reply(k, &mut message)
+        .context("Cannot prep the Noise's XK final Handshake message")?;
+
+    writer
+        .write_all(&XkFinalize::new(version, message).to_bytes())
+        .await
+        .context("Cannot send the Noise XK final Handshake")?;
+    writer
+        .flush()
+        .await
+        .context("Cannot flush the Noise XK final Handshake")?;
+
+    Ok(Handle::new(reader, writer, state, Extensions::new()).with_version(version))
+}
@@ -0,0 +1,383 @@
+/*!
+# Prekey directory protocol
+
+small request/response protocol to publish and fetch [`PreKeyBundle`]s
+on top of an established encrypted connection (see [`net::Connection`]).
+
+* the responder publishes its [`PreKeyUpload`] with [`publish`]. The
+  directory accepts the upload only if it is signed by the identity
+  that authenticated the connection;
+* the initiator retrieves the bundle of the responder with [`fetch`]
+  (each fetch consumes one of the one time prekeys);
+* the responder can check how many one time prekeys are left on the
+  directory with [`remaining_one_time_prekeys`] and publish new ones
+  before they are exhausted.
+
+The directory side is implemented by [`PreKeyDirectory::serve`].
+
+[`net::Connection`]: crate::net::Connection
+*/
+
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use bytes::Bytes;
+use futures::prelude::*;
+use keynesis_core::{
+    key::ed25519::PublicKey,
+    prekey::{PreKeyBundle, PreKeyUpload},
+};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+};
+
+/// maximum number of one time prekeys to publish at once so that
+/// the upload fits in one frame
+pub const MAX_ONE_TIME_PREKEYS_PER_UPLOAD: usize =
+    (crate::codec::encryption::MAX_PAYLOAD_LENGTH - 1 - PreKeyUpload::MIN_SIZE)
+        / PreKeyUpload::ONE_TIME_PREKEY_SIZE;
+
+/// shared directory of the published prekeys
+///
+/// cloning the directory is cheap and all the clones share the same
+/// prekeys, so a clone can be given to every accepted connection.
+#[derive(Clone, Default)]
+pub struct PreKeyDirectory {
+    uploads: Arc<Mutex<HashMap<PublicKey, PreKeyUpload>>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Request {
+    Publish(PreKeyUpload),
+    Fetch(PublicKey),
+    Remaining,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Response {
+    Published,
+    Bundle(Option<PreKeyBundle>),
+    Remaining(u32),
+    Refused,
+}
+
+impl Request {
+    const PUBLISH: u8 = 1;
+    const FETCH: u8 = 2;
+    const REMAINING: u8 = 3;
+
+    fn to_bytes(&self) -> Bytes {
+        let mut bytes = Vec::new();
+        match self {
+            Self::Publish(upload) => {
+                bytes.push(Self::PUBLISH);
+                bytes.extend_from_slice(&upload.to_bytes());
+            }
+            Self::Fetch(identity) => {
+                bytes.push(Self::FETCH);
+                bytes.extend_from_slice(identity.as_ref());
+            }
+            Self::Remaining => bytes.push(Self::REMAINING),
+        }
+        Bytes::from(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&Self::PUBLISH, upload)) => Ok(Self::Publish(
+                PreKeyUpload::try_from(upload).context("Invalid prekey upload")?,
+            )),
+            Some((&Self::FETCH, identity)) => Ok(Self::Fetch(
+                PublicKey::try_from(identity).context("Invalid identity")?,
+            )),
+            Some((&Self::REMAINING, [])) => Ok(Self::Remaining),
+            _ => bail!("Invalid prekey request"),
+        }
+    }
+}
+
+impl Response {
+    const PUBLISHED: u8 = 1;
+    const BUNDLE: u8 = 2;
+    const NO_BUNDLE: u8 = 3;
+    const REMAINING: u8 = 4;
+    const REFUSED: u8 = 5;
+
+    fn to_bytes(&self) -> Bytes {
+        let mut bytes = Vec::new();
+        match self {
+            Self::Published => bytes.push(Self::PUBLISHED),
+            Self::Bundle(Some(bundle)) => {
+                bytes.push(Self::BUNDLE);
+                bytes.extend_from_slice(&bundle.to_bytes());
+            }
+            Self::Bundle(None) => bytes.push(Self::NO_BUNDLE),
+            Self::Remaining(remaining) => {
+                bytes.push(Self::REMAINING);
+                bytes.extend_from_slice(&remaining.to_be_bytes());
+            }
+            Self::Refused => bytes.push(Self::REFUSED),
+        }
+        Bytes::from(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&Self::PUBLISHED, [])) => Ok(Self::Published),
+            Some((&Self::BUNDLE, bundle)) => Ok(Self::Bundle(Some(
+                PreKeyBundle::try_from(bundle).context("Invalid prekey bundle")?,
+            ))),
+            Some((&Self::NO_BUNDLE, [])) => Ok(Self::Bundle(None)),
+            Some((&Self::REMAINING, remaining)) => {
+                let remaining = <[u8; 4]>::try_from(remaining)
+                    .map_err(|_| anyhow!("Invalid remaining one time prekeys"))?;
+                Ok(Self::Remaining(u32::from_be_bytes(remaining)))
+            }
+            Some((&Self::REFUSED, [])) => Ok(Self::Refused),
+            _ => bail!("Invalid prekey response"),
+        }
+    }
+}
+
+async fn request<C, B>(connection: &mut C, request: Request) -> Result<Response>
+where
+    C: Sink<Bytes, Error = anyhow::Error> + Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    connection
+        .send(request.to_bytes())
+        .await
+        .context("Cannot send the prekey request")?;
+
+    let response = connection
+        .next()
+        .await
+        .ok_or_else(|| anyhow!("Connection closed before receiving the prekey response"))?
+        .context("Cannot receive the prekey response")?;
+
+    Response::from_bytes(response.as_ref())
+}
+
+/// publish the `upload` to the directory at the other end of the `connection`
+///
+/// the connection needs to be authenticated with the identity of the
+/// upload or the directory will refuse it. The upload cannot contain
+/// more than [`MAX_ONE_TIME_PREKEYS_PER_UPLOAD`] one time prekeys.
+pub async fn publish<C, B>(connection: &mut C, upload: PreKeyUpload) -> Result<()>
+where
+    C: Sink<Bytes, Error = anyhow::Error> + Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    ensure!(
+        upload.remaining_one_time_prekeys() <= MAX_ONE_TIME_PREKEYS_PER_UPLOAD,
+        "Cannot publish more than {} one time prekeys at once",
+        MAX_ONE_TIME_PREKEYS_PER_UPLOAD
+    );
+
+    match request(connection, Request::Publish(upload)).await? {
+        Response::Published => Ok(()),
+        Response::Refused => bail!("The directory refused the prekeys"),
+        response => bail!("Unexpected response from the directory: {:?}", response),
+    }
+}
+
+/// fetch the bundle of `identity` from the directory
+///
+/// returns `None` if the directory does not have any prekeys for this
+/// identity. The bundle's signature is verified.
+pub async fn fetch<C, B>(connection: &mut C, identity: &PublicKey) -> Result<Option<PreKeyBundle>>
+where
+    C: Sink<Bytes, Error = anyhow::Error> + Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    match request(connection, Request::Fetch(*identity)).await? {
+        Response::Bundle(None) => Ok(None),
+        Response::Bundle(Some(bundle)) => {
+            ensure!(
+                bundle.identity() == identity,
+                "The directory returned the bundle of {} instead of {}",
+                bundle.identity(),
+                identity,
+            );
+            ensure!(bundle.verify(), "The prekey bundle is not properly signed");
+            Ok(Some(bundle))
+        }
+        response => bail!("Unexpected response from the directory: {:?}", response),
+    }
+}
+
+/// number of our own one time prekeys that the directory can still hand out
+pub async fn remaining_one_time_prekeys<C, B>(connection: &mut C) -> Result<usize>
+where
+    C: Sink<Bytes, Error = anyhow::Error> + Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    match request(connection, Request::Remaining).await? {
+        Response::Remaining(remaining) => Ok(remaining as usize),
+        response => bail!("Unexpected response from the directory: {:?}", response),
+    }
+}
+
+impl PreKeyDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add the `upload` to the directory
+    ///
+    /// the upload is refused if the signed prekey is not signed by the
+    /// identity.
+    pub fn publish(&self, upload: PreKeyUpload) -> Result<()> {
+        ensure!(upload.verify(), "The prekey upload is not properly signed");
+
+        let mut uploads = self.uploads.lock().expect("the directory lock is poisoned");
+        match uploads.get_mut(upload.identity()) {
+            Some(known) => known.merge(upload)?,
+            None => {
+                uploads.insert(*upload.identity(), upload);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// take the next bundle of the given `identity`
+    pub fn fetch(&self, identity: &PublicKey) -> Option<PreKeyBundle> {
+        self.uploads
+            .lock()
+            .expect("the directory lock is poisoned")
+            .get_mut(identity)
+            .map(PreKeyUpload::take_bundle)
+    }
+
+    /// number of one time prekeys left for the given `identity`
+    pub fn remaining_one_time_prekeys(&self, identity: &PublicKey) -> usize {
+        self.uploads
+            .lock()
+            .expect("the directory lock is poisoned")
+            .get(identity)
+            .map(PreKeyUpload::remaining_one_time_prekeys)
+            .unwrap_or_default()
+    }
+
+    /// answer the prekey requests received on the `connection` until
+    /// it is closed
+    ///
+    /// `remote` is the authenticated identity of the peer of the
+    /// connection, it is the only identity the peer can publish
+    /// prekeys for.
+    #[tracing::instrument(skip(self, connection), level = "debug")]
+    pub async fn serve<C, B>(&self, connection: &mut C, remote: PublicKey) -> Result<()>
+    where
+        C: Sink<Bytes, Error = anyhow::Error> + Stream<Item = Result<B>> + Unpin,
+        B: AsRef<[u8]>,
+    {
+        while let Some(request) = connection.next().await {
+            let request = request.context("Cannot receive the prekey request")?;
+
+            let response = match Request::from_bytes(request.as_ref())? {
+                Request::Publish(upload) if upload.identity() != &remote => {
+                    tracing::debug!("refusing prekeys of another identity");
+                    Response::Refused
+                }
+                Request::Publish(upload) => match self.publish(upload) {
+                    Ok(()) => Response::Published,
+                    Err(error) => {
+                        tracing::debug!(reason = ?error, "refusing prekeys");
+                        Response::Refused
+                    }
+                },
+                Request::Fetch(identity) => Response::Bundle(self.fetch(&identity)),
+                Request::Remaining => {
+                    let remaining = self.remaining_one_time_prekeys(&remote);
+                    Response::Remaining(remaining as u32)
+                }
+            };
+
+            connection
+                .send(response.to_bytes())
+                .await
+                .context("Cannot send the prekey response")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keynesis_core::{key::ed25519_extended::SecretKey, prekey::PreKeyStore};
+    use rand::thread_rng;
+
+    #[test]
+    fn encode_decode() {
+        let bob = SecretKey::new(thread_rng());
+        let mut store = PreKeyStore::new(thread_rng(), &bob);
+        store.generate_one_time_prekeys(thread_rng(), 3);
+        let mut upload = store.upload(&bob.public_key(), usize::MAX);
+        let bundle = upload.take_bundle();
+
+        let requests = [
+            Request::Publish(upload),
+            Request::Fetch(bob.public_key()),
+            Request::Remaining,
+        ];
+        for request in requests {
+            assert_eq!(Request::from_bytes(&request.to_bytes()).unwrap(), request);
+        }
+
+        let responses = [
+            Response::Published,
+            Response::Bundle(Some(bundle)),
+            Response::Bundle(None),
+            Response::Remaining(42),
+            Response::Refused,
+        ];
+        for response in responses {
+            assert_eq!(
+                Response::from_bytes(&response.to_bytes()).unwrap(),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn max_upload_fits_in_a_frame() {
+        let bob = SecretKey::new(thread_rng());
+        let mut store = PreKeyStore::new(thread_rng(), &bob);
+        store.generate_one_time_prekeys(thread_rng(), MAX_ONE_TIME_PREKEYS_PER_UPLOAD + 1);
+        let upload = store.upload(&bob.public_key(), MAX_ONE_TIME_PREKEYS_PER_UPLOAD);
+
+        let bytes = Request::Publish(upload).to_bytes();
+        assert!(bytes.len() <= crate::codec::encryption::MAX_PAYLOAD_LENGTH);
+        assert!(store.needs_upload());
+    }
+
+    #[test]
+    fn directory() {
+        let bob = SecretKey::new(thread_rng());
+        let mallory = SecretKey::new(thread_rng());
+        let directory = PreKeyDirectory::new();
+        let mut store = PreKeyStore::new(thread_rng(), &bob);
+        store.generate_one_time_prekeys(thread_rng(), 1);
+
+        assert!(directory.fetch(&bob.public_key()).is_none());
+
+        // the upload needs to be signed by the identity
+        let forged = PreKeyStore::new(thread_rng(), &mallory).upload(&bob.public_key(), 0);
+        assert!(directory.publish(forged).is_err());
+
+        directory
+            .publish(store.upload(&bob.public_key(), usize::MAX))
+            .unwrap();
+        assert_eq!(directory.remaining_one_time_prekeys(&bob.public_key()), 1);
+
+        let bundle = directory.fetch(&bob.public_key()).unwrap();
+        assert!(bundle.one_time_prekey().is_some());
+        assert_eq!(directory.remaining_one_time_prekeys(&bob.public_key()), 0);
+
+        let bundle = directory.fetch(&bob.public_key()).unwrap();
+        assert!(bundle.one_time_prekey().is_none());
+    }
+}
@@ -0,0 +1,407 @@
+/*!
+Management of the keys used by a server to encrypt the resumption tickets
+
+The server encrypts the resumption state in a ticket it gives to the
+client so it does not have to keep the state itself. The [`TicketKeys`]
+rotate the encryption key every `rotation_period` and keep accepting the
+tickets encrypted with the previous key for a `grace_period`. Once the
+grace period is over, the tickets of the previous key are rejected, this
+bounds the lifetime of a compromised key.
+
+A server fleet shares the same keys so a client can resume its session
+with any of the servers: one of them generates the keys and the others
+[`install`](TicketKeys::install) them (see [`TicketKey::to_bytes`]).
+The keys need to be shared over a secure channel.
+
+```
+use keynesis_network::ticket::TicketKeys;
+use std::time::{Duration, SystemTime};
+# use rand::thread_rng;
+
+let now = SystemTime::now();
+let mut keys = TicketKeys::new(
+    thread_rng(),
+    Duration::from_secs(12 * 3600),
+    Duration::from_secs(3600),
+    now,
+);
+
+let ticket = keys.seal(thread_rng(), b"resumption state");
+
+// the key is rotated, the ticket is still valid for the grace period
+keys.rotate_if_needed(thread_rng(), now + Duration::from_secs(12 * 3600));
+assert!(keys.open(now + Duration::from_secs(12 * 3600), &ticket).is_ok());
+assert!(keys.open(now + Duration::from_secs(13 * 3600), &ticket).is_err());
+```
+*/
+
+use anyhow::{bail, ensure, Result};
+use cryptoxide::chacha20poly1305::ChaCha20Poly1305;
+use keynesis_core::memsec::Scrubbed as _;
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// identifier of a [`TicketKey`], it prefixes the tickets so the server
+/// knows which key to use to open them
+pub type TicketKeyId = [u8; 8];
+
+/// key used to encrypt the resumption tickets
+///
+/// the key is zeroed when dropped.
+#[derive(Clone)]
+pub struct TicketKey {
+    id: TicketKeyId,
+    created: SystemTime,
+    key: [u8; Self::KEY_SIZE],
+}
+
+/// the current and previous [`TicketKey`]
+pub struct TicketKeys {
+    current: TicketKey,
+    /// the previous key and the time it has been replaced
+    previous: Option<(TicketKey, SystemTime)>,
+    rotation_period: Duration,
+    grace_period: Duration,
+}
+
+impl TicketKey {
+    const KEY_SIZE: usize = 32;
+    /// size of the encoded key (see [`TicketKey::to_bytes`])
+    pub const SIZE: usize = 8 + 8 + Self::KEY_SIZE;
+
+    const NONCE_SIZE: usize = 12;
+    const TAG_SIZE: usize = 16;
+
+    /// generate a new key, `created` is used to schedule its rotation
+    pub fn generate<Rng>(mut rng: Rng, created: SystemTime) -> Self
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let mut id = [0; 8];
+        let mut key = [0; Self::KEY_SIZE];
+        rng.fill_bytes(&mut id);
+        rng.fill_bytes(&mut key);
+
+        Self {
+            id,
+            created: to_seconds(created),
+            key,
+        }
+    }
+
+    pub fn id(&self) -> &TicketKeyId {
+        &self.id
+    }
+
+    /// time the key has been created
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    /// encode the key so it can be shared with the other servers
+    ///
+    /// the encoding contains the secret key, it needs to be shared over
+    /// a secure channel.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let created = self
+            .created
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.id);
+        bytes[8..16].copy_from_slice(&created.to_be_bytes());
+        bytes[16..].copy_from_slice(&self.key);
+        bytes
+    }
+
+    fn seal<Rng>(&self, mut rng: Rng, plaintext: &[u8]) -> Vec<u8>
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        let mut nonce = [0; Self::NONCE_SIZE];
+        rng.fill_bytes(&mut nonce);
+
+        let start = self.id.len() + Self::NONCE_SIZE;
+        let mut ticket = vec![0; start + plaintext.len() + Self::TAG_SIZE];
+        ticket[..self.id.len()].copy_from_slice(&self.id);
+        ticket[self.id.len()..start].copy_from_slice(&nonce);
+
+        let (ciphertext, tag) = ticket[start..].split_at_mut(plaintext.len());
+        ChaCha20Poly1305::new(&self.key, &nonce, &self.id).encrypt(plaintext, ciphertext, tag);
+
+        ticket
+    }
+
+    fn open(&self, ticket: &[u8]) -> Result<Vec<u8>> {
+        let start = self.id.len() + Self::NONCE_SIZE;
+        let (nonce, ciphertext) = ticket[self.id.len()..].split_at(Self::NONCE_SIZE);
+        let (ciphertext, tag) = ciphertext.split_at(ticket.len() - start - Self::TAG_SIZE);
+
+        let mut plaintext = vec![0; ciphertext.len()];
+        if !ChaCha20Poly1305::new(&self.key, nonce, &self.id).decrypt(
+            ciphertext,
+            &mut plaintext,
+            tag,
+        ) {
+            bail!("Invalid ticket")
+        }
+
+        Ok(plaintext)
+    }
+}
+
+impl TicketKeys {
+    /// minimal size of a ticket (the ticket of an empty state)
+    pub const MIN_TICKET_SIZE: usize = 8 + TicketKey::NONCE_SIZE + TicketKey::TAG_SIZE;
+
+    /// create the server's key management with a newly generated key
+    pub fn new<Rng>(
+        rng: Rng,
+        rotation_period: Duration,
+        grace_period: Duration,
+        now: SystemTime,
+    ) -> Self
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        Self::with_key(TicketKey::generate(rng, now), rotation_period, grace_period)
+    }
+
+    /// create the server's key management with the given key, for example
+    /// the key generated by another server of the fleet
+    pub fn with_key(current: TicketKey, rotation_period: Duration, grace_period: Duration) -> Self {
+        Self {
+            current,
+            previous: None,
+            rotation_period,
+            grace_period,
+        }
+    }
+
+    /// the key used to encrypt the new tickets
+    pub fn current(&self) -> &TicketKey {
+        &self.current
+    }
+
+    /// the previous key, if the tickets it encrypted are still accepted
+    pub fn previous(&self, now: SystemTime) -> Option<&TicketKey> {
+        self.previous
+            .as_ref()
+            .filter(|(_, retired)| self.in_grace_period(*retired, now))
+            .map(|(key, _)| key)
+    }
+
+    fn in_grace_period(&self, retired: SystemTime, now: SystemTime) -> bool {
+        now.duration_since(retired)
+            .map(|elapsed| elapsed < self.grace_period)
+            .unwrap_or(true)
+    }
+
+    /// time the current key needs to be rotated
+    pub fn next_rotation(&self) -> SystemTime {
+        self.current.created + self.rotation_period
+    }
+
+    /// generate a new key if the current key is older than the
+    /// rotation period, returns the new key if the keys have been rotated
+    ///
+    /// the new key needs to be [installed](TicketKeys::install) on the
+    /// other servers of the fleet.
+    pub fn rotate_if_needed<Rng>(&mut self, rng: Rng, now: SystemTime) -> Option<&TicketKey>
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        if now < self.next_rotation() {
+            return None;
+        }
+
+        self.rotate(TicketKey::generate(rng, now), now);
+        Some(&self.current)
+    }
+
+    /// install the key shared by another server of the fleet as the
+    /// current key
+    ///
+    /// # Errors
+    ///
+    /// fails if the key is older than the current key, this prevents
+    /// installing the keys out of order.
+    pub fn install(&mut self, key: TicketKey, now: SystemTime) -> Result<()> {
+        if key.id == self.current.id {
+            return Ok(());
+        }
+        ensure!(
+            key.created >= self.current.created,
+            "Cannot install a key older than the current one"
+        );
+
+        self.rotate(key, now);
+        Ok(())
+    }
+
+    fn rotate(&mut self, key: TicketKey, now: SystemTime) {
+        let previous = std::mem::replace(&mut self.current, key);
+        self.previous = Some((previous, now));
+    }
+
+    /// encrypt the resumption `state` with the current key
+    pub fn seal<Rng>(&self, rng: Rng, state: &[u8]) -> Vec<u8>
+    where
+        Rng: RngCore + CryptoRng,
+    {
+        self.current.seal(rng, state)
+    }
+
+    /// decrypt the ticket, the ticket needs to be encrypted with the current
+    /// key or with the previous key if it is still in its grace period
+    pub fn open(&self, now: SystemTime, ticket: &[u8]) -> Result<Vec<u8>> {
+        ensure!(ticket.len() >= Self::MIN_TICKET_SIZE, "Invalid ticket");
+
+        let id = &ticket[..8];
+        if id == self.current.id {
+            self.current.open(ticket)
+        } else if let Some(previous) = self.previous(now).filter(|key| key.id == id) {
+            previous.open(ticket)
+        } else {
+            bail!("Ticket encrypted with an unknown or expired key")
+        }
+    }
+}
+
+/// the keys are shared with their creation time in seconds
+fn to_seconds(time: SystemTime) -> SystemTime {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+impl Drop for TicketKey {
+    fn drop(&mut self) {
+        self.key.scrub();
+    }
+}
+
+/* Format ****************************************************************** */
+
+impl fmt::Debug for TicketKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TicketKey")
+            .field("id", &hex::encode(self.id))
+            .field("created", &self.created)
+            .finish_non_exhaustive()
+    }
+}
+
+/* Conversion ************************************************************** */
+
+impl<'a> TryFrom<&'a [u8]> for TicketKey {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        ensure!(bytes.len() == Self::SIZE, "Invalid ticket key size");
+
+        let mut id = [0; 8];
+        let mut created = [0; 8];
+        let mut key = [0; Self::KEY_SIZE];
+        id.copy_from_slice(&bytes[..8]);
+        created.copy_from_slice(&bytes[8..16]);
+        key.copy_from_slice(&bytes[16..]);
+
+        Ok(Self {
+            id,
+            created: UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(created)),
+            key,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn keys(now: SystemTime) -> TicketKeys {
+        TicketKeys::new(thread_rng(), 12 * HOUR, HOUR, now)
+    }
+
+    #[test]
+    fn seal_open() {
+        let now = SystemTime::now();
+        let keys = keys(now);
+
+        let ticket = keys.seal(thread_rng(), b"state");
+        assert_eq!(keys.open(now, &ticket).unwrap(), b"state");
+
+        let ticket = keys.seal(thread_rng(), b"");
+        assert_eq!(ticket.len(), TicketKeys::MIN_TICKET_SIZE);
+        assert!(keys.open(now, &ticket).unwrap().is_empty());
+    }
+
+    #[test]
+    fn tampered() {
+        let now = SystemTime::now();
+        let keys = keys(now);
+
+        let mut ticket = keys.seal(thread_rng(), b"state");
+        let last = ticket.len() - 1;
+        ticket[last] ^= 1;
+        assert!(keys.open(now, &ticket).is_err());
+        assert!(keys.open(now, &ticket[..10]).is_err());
+    }
+
+    #[test]
+    fn rotation() {
+        let now = SystemTime::now();
+        let mut keys = keys(now);
+        let ticket = keys.seal(thread_rng(), b"state");
+
+        assert!(keys.rotate_if_needed(thread_rng(), now + HOUR).is_none());
+
+        let rotated = now + 12 * HOUR;
+        assert!(keys.rotate_if_needed(thread_rng(), rotated).is_some());
+        assert!(keys.open(rotated, &ticket).is_ok());
+        assert!(keys.open(rotated + HOUR / 2, &ticket).is_ok());
+        assert!(keys.open(rotated + HOUR, &ticket).is_err());
+
+        let ticket = keys.seal(thread_rng(), b"state");
+        assert!(keys.open(rotated + HOUR, &ticket).is_ok());
+    }
+
+    #[test]
+    fn fleet() {
+        let now = SystemTime::now();
+        let mut leader = keys(now);
+        let mut follower = TicketKeys::with_key(
+            TicketKey::try_from(leader.current().to_bytes().as_ref()).unwrap(),
+            12 * HOUR,
+            HOUR,
+        );
+
+        let ticket = leader.seal(thread_rng(), b"state");
+        assert_eq!(follower.open(now, &ticket).unwrap(), b"state");
+
+        let rotated = now + 12 * HOUR;
+        let key = leader.rotate_if_needed(thread_rng(), rotated).unwrap();
+        let shared = TicketKey::try_from(key.to_bytes().as_ref()).unwrap();
+        follower.install(shared.clone(), rotated).unwrap();
+        // installing the same key twice is fine
+        follower.install(shared, rotated).unwrap();
+
+        let new_ticket = follower.seal(thread_rng(), b"new state");
+        assert_eq!(leader.open(rotated, &new_ticket).unwrap(), b"new state");
+        assert!(follower.open(rotated, &ticket).is_ok());
+
+        let old = TicketKey::generate(thread_rng(), now);
+        assert!(follower.install(old, rotated).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+/*!
+Helpers of the ASMTP protocol on top of Unix domain sockets
+
+The [`Handle`] works over any [`AsyncRead`](tokio::io::AsyncRead) and
+[`AsyncWrite`](tokio::io::AsyncWrite). This module only sets up the
+[`UnixStream`] so co-located services can talk to each other with the
+same handshakes as over TCP: the peers are still authenticated with
+their keys, the permissions of the socket file are not enough.
+
+```no_run
+# async fn run() -> anyhow::Result<()> {
+use keynesis_core::key::ed25519::SecretKey;
+use keynesis_network::unix;
+# use rand::thread_rng;
+# let server_key = SecretKey::new(thread_rng());
+# let client_key = SecretKey::new(thread_rng());
+
+// the server
+let listener = unix::Listener::bind("/run/service.sock")?;
+let accepting = listener.accept::<_, SecretKey>(thread_rng()).await?;
+let handle = accepting
+    .accept(&server_key, |id| id == &client_key.public_key())
+    .await?;
+
+// the client
+let handle = unix::connect(
+    thread_rng(),
+    &client_key,
+    "/run/service.sock",
+    server_key.public_key(),
+)
+.await?;
+# Ok(())
+# }
+```
+*/
+
+use crate::{Accepting, Handle};
+use anyhow::{Context as _, Result};
+use keynesis_core::key::{ed25519::PublicKey, Dh};
+use rand_core::{CryptoRng, RngCore};
+use std::path::Path;
+use tokio::net::{
+    unix::{OwnedReadHalf, OwnedWriteHalf},
+    UnixListener, UnixStream,
+};
+
+/// the [`Handle`] of a connection over a Unix domain socket
+pub type UnixHandle = Handle<OwnedReadHalf, OwnedWriteHalf>;
+
+/// listen to the inbound connections on a Unix domain socket
+pub struct Listener {
+    listener: UnixListener,
+}
+
+impl Listener {
+    /// listen to the incoming connections on the socket at `path`
+    ///
+    /// the socket file is created, it must not exist already.
+    pub fn bind(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Cannot listen to {}", path.display()))?;
+
+        Ok(Self { listener })
+    }
+
+    /// wait for a new inbound connection
+    ///
+    /// the handshake is not performed yet, see [`Accepting::accept`].
+    pub async fn accept<RNG, K>(
+        &self,
+        rng: RNG,
+    ) -> Result<Accepting<OwnedReadHalf, OwnedWriteHalf, RNG, K>>
+    where
+        RNG: CryptoRng + RngCore,
+        K: Dh,
+    {
+        let (stream, _) = self
+            .listener
+            .accept()
+            .await
+            .context("Cannot accept new peer from the listener")?;
+
+        let (reader, writer) = stream.into_split();
+
+        Ok(Handle::accept(rng, reader, writer))
+    }
+}
+
+/// connect to the socket at `path`, expecting the remote to identify
+/// with the [`PublicKey`] `rs`
+///
+/// see [`Handle::open`]
+pub async fn connect<RNG, K>(
+    rng: RNG,
+    k: &K,
+    path: impl AsRef<Path>,
+    rs: PublicKey,
+) -> Result<UnixHandle>
+where
+    RNG: CryptoRng + RngCore,
+    K: Dh,
+{
+    let path = path.as_ref();
+    let stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("Cannot connect to {}", path.display()))?;
+
+    let (reader, writer) = stream.into_split();
+
+    Handle::open(rng, k, rs, reader, writer)
+        .await
+        .with_context(|| format!("Failed to handshake with {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+
+    #[test]
+    fn connect_accept() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+        let path =
+            std::env::temp_dir().join(format!("keynesis-{:x}.sock", thread_rng().next_u64()));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let listener = Listener::bind(&path).unwrap();
+
+            let accept = async {
+                let accepting = listener.accept::<_, SecretKey>(thread_rng()).await?;
+                accepting.accept(&bob, |_| true).await
+            };
+            let connect = connect(thread_rng(), &alice, &path, bob.public_key());
+
+            let (a, b) = futures::join!(connect, accept);
+            let (mut a, mut b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.session_id(), b.session_id());
+            assert_eq!(b.remote_public_identity(), Some(&alice.public_key()));
+
+            a.send_frame(&b"hello"[..]).await.unwrap();
+            assert_eq!(b.recv_frame().await.unwrap().unwrap(), &b"hello"[..]);
+        });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
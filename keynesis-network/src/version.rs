@@ -12,9 +12,9 @@ use std::{
 ///
 ///
 /// Versions will be listed here overtime. However when performing the
-/// handshake, this function will use [`Version::CURRENT`] and will check
-/// how the remote's version match the [`Version::MIN`] and [`Version::MAX`].
-/// See [`is_supported`].
+/// handshake, the initiator advertises the [`VersionRange::SUPPORTED`]
+/// (from [`Version::MIN`] to [`Version::MAX`]) and the responder picks the
+/// latest version both support. See [`is_supported`].
 ///
 /// [`is_supported`]: Version::is_supported
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
@@ -32,13 +32,34 @@ impl Version {
     /// version 1:
     ///
     /// Support syncing passports between the nodes
+    ///
+    /// not supported anymore: the encrypted frames do not have a content type
     pub const V1: Self = Self(0x01);
 
+    /// version 2:
+    ///
+    /// the encrypted frames start with their content type so the
+    /// peers can re-handshake within an established session
+    /// and the handshake messages carry [`Extensions`](crate::Extensions)
+    ///
+    /// the initiator only sends its version, the responders supporting
+    /// newer versions still accept it (see [`VersionRange`])
+    pub const V2: Self = Self(0x02);
+
+    /// version 3:
+    ///
+    /// the initiator advertises the [`VersionRange`] it supports, the
+    /// responder picks the version of the session and the advertised
+    /// range is the prologue of the Noise handshake. The responder's
+    /// range is in the payload of its reply so the initiator can check
+    /// the picked version
+    pub const V3: Self = Self(0x03);
+
     /// get the minimal supported version supported by this implementation
-    pub const MIN: Self = Self::V1;
+    pub const MIN: Self = Self::V2;
 
     /// get the current version implemented by this implementation
-    pub const CURRENT: Self = Self::V1;
+    pub const CURRENT: Self = Self::V3;
 
     /// get the maximal supported version supported by this implementation
     pub const MAX: Self = Self::CURRENT;
@@ -71,6 +92,98 @@ impl Version {
     }
 }
 
+/// range of the [`Version`]s supported by a peer
+///
+/// the initiator of a handshake advertises its range, the responder
+/// picks the version of the session with [`VersionRange::negotiate`].
+/// The advertised range is mixed in the prologue of the Noise handshake
+/// so a man in the middle removing the latest versions from the range
+/// (to downgrade the session to an older version) breaks the handshake.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct VersionRange {
+    min: Version,
+    max: Version,
+}
+
+impl VersionRange {
+    /// the encoded size of the [`VersionRange`]
+    ///
+    /// ```
+    /// # use keynesis_network::VersionRange;
+    /// assert_eq!(VersionRange::SIZE, 2)
+    /// ```
+    pub const SIZE: usize = 2 * Version::SIZE;
+
+    /// the versions supported by this implementation, from
+    /// [`Version::MIN`] to [`Version::MAX`]
+    pub const SUPPORTED: Self = Self {
+        min: Version::MIN,
+        max: Version::MAX,
+    };
+
+    /// `None` if `min` is greater than `max`
+    pub fn new(min: Version, max: Version) -> Option<Self> {
+        if min <= max {
+            Some(Self { min, max })
+        } else {
+            None
+        }
+    }
+
+    pub fn min(&self) -> Version {
+        self.min
+    }
+
+    pub fn max(&self) -> Version {
+        self.max
+    }
+
+    pub fn contains(&self, version: Version) -> bool {
+        self.min <= version && version <= self.max
+    }
+
+    /// the latest version of both ranges, `None` if they have no
+    /// version in common
+    ///
+    /// ```
+    /// # use keynesis_network::{Version, VersionRange};
+    /// let v1_v2 = VersionRange::new(Version::V1, Version::V2).unwrap();
+    /// let v2_v3 = VersionRange::new(Version::V2, Version::V3).unwrap();
+    /// let v3 = VersionRange::new(Version::V3, Version::V3).unwrap();
+    ///
+    /// assert_eq!(v1_v2.negotiate(&v2_v3), Some(Version::V2));
+    /// assert_eq!(v1_v2.negotiate(&v3), None);
+    /// ```
+    pub fn negotiate(&self, other: &Self) -> Option<Version> {
+        let version = std::cmp::min(self.max, other.max);
+        if self.contains(version) && other.contains(version) {
+            Some(version)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) const fn to_bytes(self) -> [u8; Self::SIZE] {
+        [self.min.to_u8(), self.max.to_u8()]
+    }
+
+    pub(crate) fn from_bytes(bytes: [u8; Self::SIZE]) -> Option<Self> {
+        Self::new(Version::from_u8(bytes[0]), Version::from_u8(bytes[1]))
+    }
+}
+
+impl Default for VersionRange {
+    fn default() -> Self {
+        Self::SUPPORTED
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..={}", self.min, self.max)
+    }
+}
+
 impl Default for Version {
     fn default() -> Self {
         Self::CURRENT
@@ -128,4 +241,31 @@ mod tests {
 
         assert_eq!(version, Version::CURRENT)
     }
+
+    #[test]
+    fn negotiate_latest_common_version() {
+        let v1_v3 = VersionRange::new(Version::V1, Version::V3).unwrap();
+        let v2 = VersionRange::new(Version::V2, Version::V2).unwrap();
+
+        assert_eq!(v1_v3.negotiate(&v2), Some(Version::V2));
+        assert_eq!(v2.negotiate(&v1_v3), Some(Version::V2));
+        assert_eq!(v1_v3.negotiate(&v1_v3), Some(Version::V3));
+        assert_eq!(v2.negotiate(&VersionRange::SUPPORTED), Some(Version::V2));
+        assert_eq!(v1_v3.negotiate(&VersionRange::SUPPORTED), Some(Version::V3));
+        assert_eq!(
+            VersionRange::new(Version::V1, Version::V1)
+                .unwrap()
+                .negotiate(&VersionRange::SUPPORTED),
+            None
+        );
+        assert!(VersionRange::new(Version::V3, Version::V2).is_none());
+    }
+
+    #[test]
+    fn encode_decode_range() {
+        let range = VersionRange::SUPPORTED;
+
+        assert_eq!(VersionRange::from_bytes(range.to_bytes()), Some(range));
+        assert_eq!(VersionRange::from_bytes([3, 2]), None);
+    }
 }
@@ -0,0 +1,233 @@
+/*!
+# Message based transports (WebSocket)
+
+The [`Handle`] reads and writes a byte stream. Some transports only carry
+whole messages: a WebSocket through HTTP proxies and load balancers, or
+the WebSocket of a browser. The [`MessageReader`] and [`MessageWriter`]
+adapt the [`Stream`] and the [`Sink`] of the binary messages of such a
+transport into the [`AsyncRead`] and [`AsyncWrite`] of the [`Handle`].
+
+The bytes written between 2 flushes are sent in 1 message. The
+[`Handle`] flushes after every handshake message and every frame so each
+binary message carries 1 Noise message.
+
+With `tokio-tungstenite` the binary messages are taken from the
+WebSocket stream (the other messages are control messages of the
+WebSocket protocol):
+
+```ignore
+use futures::{future, SinkExt as _, StreamExt as _, TryStreamExt as _};
+use keynesis_network::{websocket::{MessageReader, MessageWriter}, Handle};
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+let (sink, stream) = websocket.split();
+let reader = MessageReader::new(stream.try_filter_map(|message| {
+    future::ok(match message {
+        Message::Binary(bytes) => Some(bytes::Bytes::from(bytes)),
+        _ => None,
+    })
+}));
+let writer = MessageWriter::new(sink.with(|bytes: bytes::Bytes| {
+    future::ok::<_, Error>(Message::Binary(bytes.to_vec()))
+}));
+
+let handle = Handle::open(thread_rng(), &k, rs, reader, writer).await?;
+```
+
+[`Handle`]: crate::Handle
+*/
+
+use bytes::{Bytes, BytesMut};
+use futures::{ready, Sink, Stream};
+use std::{
+    error::Error,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// [`AsyncRead`] over a [`Stream`] of binary messages
+pub struct MessageReader<S> {
+    stream: S,
+    /// the bytes of the last message not read yet
+    message: Bytes,
+}
+
+/// [`AsyncWrite`] over a [`Sink`] of binary messages
+pub struct MessageWriter<S> {
+    sink: S,
+    /// the bytes written since the last flush
+    message: BytesMut,
+}
+
+fn other<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::other(error)
+}
+
+/* Reader ****************************************************************** */
+
+impl<S> MessageReader<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            message: Bytes::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, E> AsyncRead for MessageReader<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.message.is_empty() {
+            match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(message) => this.message = message.map_err(other)?,
+                // end of stream
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = this.message.len().min(buf.remaining());
+        buf.put_slice(&this.message.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/* Writer ****************************************************************** */
+
+impl<S> MessageWriter<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            message: BytesMut::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S> MessageWriter<S>
+where
+    S: Sink<Bytes> + Unpin,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    /// send the bytes written since the last flush as 1 message
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.message.is_empty() {
+            ready!(Pin::new(&mut self.sink).poll_ready(cx)).map_err(other)?;
+            let message = self.message.split().freeze();
+            Pin::new(&mut self.sink)
+                .start_send(message)
+                .map_err(other)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for MessageWriter<S>
+where
+    S: Sink<Bytes> + Unpin,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.message.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.sink).poll_flush(cx).map_err(other)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.sink).poll_close(cx).map_err(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Handle;
+    use futures::{channel::mpsc, executor::block_on, StreamExt as _};
+    use keynesis_core::key::ed25519::SecretKey;
+    use rand::thread_rng;
+    use tokio::io::AsyncWriteExt as _;
+
+    type Messages = futures::stream::Map<mpsc::Receiver<Bytes>, fn(Bytes) -> io::Result<Bytes>>;
+
+    fn channel() -> (MessageWriter<mpsc::Sender<Bytes>>, MessageReader<Messages>) {
+        let (sender, receiver) = mpsc::channel(16);
+        let receiver: Messages = receiver.map(Ok);
+        (MessageWriter::new(sender), MessageReader::new(receiver))
+    }
+
+    #[test]
+    fn one_message_per_flush() {
+        let (sender, receiver) = mpsc::channel(16);
+        let mut writer = MessageWriter::new(sender);
+
+        block_on(async {
+            writer.write_all(b"hello ").await.unwrap();
+            writer.write_all(b"world").await.unwrap();
+            writer.flush().await.unwrap();
+            writer.write_all(b"!").await.unwrap();
+            writer.shutdown().await.unwrap();
+
+            let messages: Vec<Bytes> = receiver.collect().await;
+            assert_eq!(messages, [&b"hello world"[..], &b"!"[..]]);
+        });
+    }
+
+    #[test]
+    fn handshake() {
+        let alice = SecretKey::new(thread_rng());
+        let bob = SecretKey::new(thread_rng());
+
+        let (a_writer, b_reader) = channel();
+        let (b_writer, a_reader) = channel();
+
+        block_on(async {
+            let accept = async {
+                Handle::accept::<SecretKey, _>(thread_rng(), b_reader, b_writer)
+                    .accept(&bob, |_| true)
+                    .await
+            };
+            let open = Handle::open(thread_rng(), &alice, bob.public_key(), a_reader, a_writer);
+
+            let (a, b) = futures::join!(open, accept);
+            let (mut a, mut b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a.session_id(), b.session_id());
+
+            a.send_frame(&b"hello"[..]).await.unwrap();
+            assert_eq!(b.recv_frame().await.unwrap().unwrap(), &b"hello"[..]);
+            b.send_frame(&b"world"[..]).await.unwrap();
+            assert_eq!(a.recv_frame().await.unwrap().unwrap(), &b"world"[..]);
+        });
+    }
+}